@@ -0,0 +1,36 @@
+//! Benchmarks of the serialization of `PROPFIND` responses
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dandidav::bench::ZarrFolderListing;
+
+/// Benchmark the building & serialization of the `multistatus` documents for
+/// depth-1 `allprop` listings of Zarr folders of various sizes
+fn bench_zarr_folder(c: &mut Criterion) {
+    let mut group = c.benchmark_group("zarr-folder");
+    for entries in [10, 1000, 10_000] {
+        let listing = ZarrFolderListing::new(entries);
+        let response = listing.responses();
+        let size = response.to_xml().len();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("find", entries), &listing, |b, listing| {
+            b.iter(|| black_box(listing).responses());
+        });
+        group.bench_with_input(
+            BenchmarkId::new("to_xml", entries),
+            &response,
+            |b, response| {
+                b.iter(|| black_box(response).to_xml());
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("total", entries),
+            &listing,
+            |b, listing| {
+                b.iter(|| black_box(listing).responses().to_xml());
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_zarr_folder);
+criterion_main!(benches);
@@ -0,0 +1,175 @@
+use soupy::{Node, Queryable, Soup};
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Check an HTML document for common accessibility problems, returning a list
+/// of all problems found.  An empty list means the document passed.
+///
+/// The checks performed are:
+///
+/// - The `<html>` element must have a non-empty `lang` attribute.
+/// - The `<title>` element must be present and non-empty.
+/// - Every table must have a `<caption>` or an `aria-label`.
+/// - Every `<th>` must have a `scope` attribute of `col` or `row`.
+/// - Every link must have an `href` and accessible text (text content
+///   containing a letter or digit, an image with `alt` text, or an
+///   `aria-label`).
+/// - Every in-page link (`href="#..."`) must point to an element that exists.
+/// - `id` attributes must be unique.
+/// - Every table cell whose content is only text made up of symbols (e.g., a
+///   dash standing in for a missing value) must have an `aria-label`.
+/// - Every `<img>` must have an `alt` attribute.
+pub fn check_accessibility(html: &str) -> Result<Vec<A11yViolation>, A11yError> {
+    let soup = Soup::html_strict(html).map_err(|e| A11yError(e.to_string()))?;
+    let mut violations = Vec::new();
+    match soup.tag("html").first() {
+        Some(tag) if tag.get("lang").is_some_and(|lang| !lang.trim().is_empty()) => (),
+        _ => violations.push(A11yViolation::NoLang),
+    }
+    if soup
+        .tag("title")
+        .first()
+        .map_or(true, |tag| tag.all_text().trim().is_empty())
+    {
+        violations.push(A11yViolation::NoTitle);
+    }
+    for table in soup.tag("table").all() {
+        if table.get("aria-label").is_none() && table.query().tag("caption").first().is_none() {
+            violations.push(A11yViolation::UnlabelledTable);
+        }
+    }
+    for th in soup.tag("th").all() {
+        if !th
+            .get("scope")
+            .is_some_and(|scope| scope == "col" || scope == "row")
+        {
+            violations.push(A11yViolation::NoScope(th.all_text()));
+        }
+    }
+    let mut ids = HashSet::new();
+    for tag in soup.attr_name("id").all() {
+        if let Some(id) = tag.get("id") {
+            if !ids.insert(id.to_string()) {
+                violations.push(A11yViolation::DuplicateId(id.to_string()));
+            }
+        }
+    }
+    for a in soup.tag("a").all() {
+        let Some(href) = a.get("href") else {
+            violations.push(A11yViolation::NoHref(a.all_text()));
+            continue;
+        };
+        let has_alt = a
+            .query()
+            .tag("img")
+            .all()
+            .any(|img| img.get("alt").is_some_and(|alt| has_words(alt)));
+        if a.get("aria-label").is_none() && !has_alt && !has_words(&a.all_text()) {
+            violations.push(A11yViolation::NoLinkText(href.to_string()));
+        }
+        if let Some(target) = href.strip_prefix('#') {
+            if !ids.contains(target) {
+                violations.push(A11yViolation::BrokenSkipLink(href.to_string()));
+            }
+        }
+    }
+    for td in soup.tag("td").all() {
+        // Only cells containing nothing but text are checked; anything more
+        // elaborate (like a link) is checked on its own.
+        if td.children().iter().any(|child| child.name().is_some()) {
+            continue;
+        }
+        let text = td.all_text();
+        if !text.trim().is_empty() && !has_words(&text) && td.get("aria-label").is_none() {
+            violations.push(A11yViolation::UnlabelledSymbolCell(text));
+        }
+    }
+    for img in soup.tag("img").all() {
+        if img.get("alt").is_none() {
+            violations.push(A11yViolation::NoAlt(
+                img.get("src").map(ToString::to_string).unwrap_or_default(),
+            ));
+        }
+    }
+    Ok(violations)
+}
+
+/// Returns true if `s` contains any letters or digits
+fn has_words(s: &str) -> bool {
+    s.chars().any(char::is_alphanumeric)
+}
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum A11yViolation {
+    #[error("<html> element missing lang attribute")]
+    NoLang,
+    #[error("document has no title")]
+    NoTitle,
+    #[error("table has neither a <caption> nor an aria-label")]
+    UnlabelledTable,
+    #[error("<th> element {0:?} missing scope attribute")]
+    NoScope(String),
+    #[error("id {0:?} used more than once")]
+    DuplicateId(String),
+    #[error("<a> element {0:?} missing href attribute")]
+    NoHref(String),
+    #[error("link to {0:?} has no accessible text")]
+    NoLinkText(String),
+    #[error("in-page link {0:?} does not point to any element")]
+    BrokenSkipLink(String),
+    #[error("table cell containing only {0:?} missing aria-label")]
+    UnlabelledSymbolCell(String),
+    #[error("<img> element with src {0:?} missing alt attribute")]
+    NoAlt(String),
+}
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+#[error("failed to parse HTML: {0}")]
+pub struct A11yError(String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn violations() {
+        let html = concat!(
+            "<!DOCTYPE html>\n",
+            "<html><head><title></title></head><body>",
+            "<a href=\"#nowhere\">Skip</a>",
+            "<a href=\"/\"><img src=\"home.png\"/></a>",
+            "<table id=\"t\"><tr><th>Name</th></tr><tr><td>&#x2014;</td></tr></table>",
+            "<p id=\"t\"></p>",
+            "</body></html>",
+        );
+        pretty_assertions::assert_eq!(
+            check_accessibility(html).unwrap(),
+            vec![
+                A11yViolation::NoLang,
+                A11yViolation::NoTitle,
+                A11yViolation::UnlabelledTable,
+                A11yViolation::NoScope("Name".into()),
+                A11yViolation::DuplicateId("t".into()),
+                A11yViolation::BrokenSkipLink("#nowhere".into()),
+                A11yViolation::NoLinkText("/".into()),
+                A11yViolation::UnlabelledSymbolCell("\u{2014}".into()),
+                A11yViolation::NoAlt("home.png".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_violations() {
+        let html = concat!(
+            "<!DOCTYPE html>\n",
+            "<html lang=\"en\"><head><title>Test</title></head><body>",
+            "<a href=\"#t\">Skip</a>",
+            "<a href=\"/\"><img src=\"home.png\" alt=\"Home\"/></a>",
+            "<table id=\"t\"><caption>Things</caption>",
+            "<tr><th scope=\"col\">Name</th></tr>",
+            "<tr><td aria-label=\"None\">&#x2014;</td></tr></table>",
+            "</body></html>",
+        );
+        assert_eq!(check_accessibility(html).unwrap(), Vec::new());
+    }
+}
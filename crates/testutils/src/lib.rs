@@ -1,6 +1,8 @@
+mod a11y;
 mod mockarchive;
 mod parsehtml;
 mod parsexml;
+pub use crate::a11y::*;
 pub use crate::mockarchive::*;
 pub use crate::parsehtml::*;
 pub use crate::parsexml::*;
@@ -70,7 +70,7 @@ impl Respond for StubResponder {
             if st.params == params {
                 let mut response = st.response;
                 self.template_next(&mut response);
-                return ResponseTemplate::new(200).set_body_json(response);
+                return ResponseTemplate::new(st.status).set_body_json(response);
             }
         }
         ResponseTemplate::new(404)
@@ -80,9 +80,15 @@ impl Respond for StubResponder {
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 struct Stub {
     params: HashMap<String, String>,
+    #[serde(default = "default_status")]
+    status: u16,
     response: serde_json::Value,
 }
 
+fn default_status() -> u16 {
+    200
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,109 @@
+//! Access control for, and responses from, the administrative endpoints
+//!
+//! The endpoints under `/.admin/` report on the server's internal state (its
+//! configuration, memory usage, recent downloads, cache contents, etc.), none
+//! of which is meant for the general public.  They are therefore only served
+//! when an admin token is configured via `--admin-token-file`, and requests
+//! for them must supply the token as a bearer token in an `Authorization`
+//! header.  This is enforced for all such endpoints at once by the
+//! [`require_admin_token()`] middleware.
+use crate::consts::{ADMIN_PREFIX, JSON_CONTENT_TYPE};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{
+        header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE},
+        response::Response,
+        StatusCode,
+    },
+    middleware::Next,
+    response::IntoResponse,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Middleware that only lets requests for administrative endpoints through if
+/// they supply `token` as a bearer token.  If no token is configured, the
+/// administrative endpoints are treated as nonexistent.
+pub(crate) async fn require_admin_token(
+    State(token): State<Option<Arc<str>>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    if !request.uri().path().starts_with(ADMIN_PREFIX) {
+        return next.run(request).await;
+    }
+    match token {
+        Some(token) if bearer_token_matches(request.headers(), &token) => next.run(request).await,
+        Some(_) => (
+            StatusCode::UNAUTHORIZED,
+            [(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"))],
+            "401 Unauthorized\n",
+        )
+            .into_response(),
+        None => (StatusCode::NOT_FOUND, "404 Not Found\n").into_response(),
+    }
+}
+
+/// Serialize `value` as the body of a JSON response, responding with a 500
+/// if serialization fails
+pub(crate) fn json_response<T: Serialize>(value: &T) -> Response<Body> {
+    // Note: This response should not have WebDAV headers (DAV, Allow)
+    match serde_json::to_string(value) {
+        Ok(body) => ([(CONTENT_TYPE, JSON_CONTENT_TYPE)], body).into_response(),
+        Err(e) => {
+            tracing::error!(
+                error = %e,
+                r#type = std::any::type_name::<T>(),
+                "Failed to serialize JSON response"
+            );
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Returns true if `headers` contain an `Authorization` header with the bearer
+/// token `token`.  The comparison takes the same time regardless of where
+/// the supplied token differs from `token`.
+fn bearer_token_matches(headers: &HeaderMap, token: &str) -> bool {
+    let Some(supplied) = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| {
+            let (scheme, credentials) = v.split_once(' ')?;
+            scheme
+                .eq_ignore_ascii_case("bearer")
+                .then(|| credentials.trim())
+        })
+    else {
+        return false;
+    };
+    supplied.len() == token.len()
+        && supplied
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Some("Bearer hunter2"), true)]
+    #[case(Some("bearer hunter2"), true)]
+    #[case(Some("Bearer hunter3"), false)]
+    #[case(Some("Bearer hunter"), false)]
+    #[case(Some("Basic aHVudGVyMg=="), false)]
+    #[case(Some("hunter2"), false)]
+    #[case(None, false)]
+    fn test_bearer_token_matches(#[case] auth: Option<&str>, #[case] ok: bool) {
+        let mut headers = HeaderMap::new();
+        if let Some(auth) = auth {
+            headers.insert(AUTHORIZATION, HeaderValue::from_str(auth).unwrap());
+        }
+        assert_eq!(bearer_token_matches(&headers, "hunter2"), ok);
+    }
+}
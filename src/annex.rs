@@ -0,0 +1,121 @@
+//! Constructing git-annex keys for served files
+//!
+//! A git-annex key identifies a file's content by a *backend* (the hashing
+//! scheme), the file's size, and the file's digest under that backend, in the
+//! form `{backend}-s{size}--{digest}`.  See
+//! <https://git-annex.branchable.com/internals/key_format/> for details.
+use std::fmt;
+
+/// A git-annex key for a file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct AnnexKey {
+    /// The key's backend
+    backend: AnnexBackend,
+
+    /// The size of the file in bytes
+    size: i64,
+
+    /// The file's digest, as a lowercase hexadecimal string
+    digest: String,
+}
+
+impl AnnexKey {
+    /// Construct a `SHA256` key for a file of the given size with the given
+    /// SHA-256 digest.  Returns `None` if `digest` is not a valid SHA-256
+    /// digest.
+    pub(crate) fn sha256(size: i64, digest: &str) -> Option<AnnexKey> {
+        AnnexKey::new(AnnexBackend::Sha256, size, digest)
+    }
+
+    /// Construct an `MD5` key for a file of the given size from the file's S3
+    /// ETag.  Returns `None` if the ETag is not a plain MD5 digest (as is the
+    /// case for objects uploaded in multiple parts).
+    pub(crate) fn md5_from_etag(size: i64, etag: &str) -> Option<AnnexKey> {
+        AnnexKey::new(AnnexBackend::Md5, size, etag.trim_matches('"'))
+    }
+
+    fn new(backend: AnnexBackend, size: i64, digest: &str) -> Option<AnnexKey> {
+        (size >= 0
+            && digest.len() == backend.digest_len()
+            && digest.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| AnnexKey {
+            backend,
+            size,
+            digest: digest.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl fmt::Display for AnnexKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-s{}--{}", self.backend, self.size, self.digest)
+    }
+}
+
+/// The git-annex backends for which `dandidav` can produce keys
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum AnnexBackend {
+    Md5,
+    Sha256,
+}
+
+impl AnnexBackend {
+    /// Return the length of a hexadecimal digest for the backend
+    fn digest_len(self) -> usize {
+        match self {
+            AnnexBackend::Md5 => 32,
+            AnnexBackend::Sha256 => 64,
+        }
+    }
+}
+
+impl fmt::Display for AnnexBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnnexBackend::Md5 => write!(f, "MD5"),
+            AnnexBackend::Sha256 => write!(f, "SHA256"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn sha256_key() {
+        let key = AnnexKey::sha256(
+            18792,
+            "F59C1E1A3B6B3B1A4F3A3D5C7E1B0B6B9C9A1F2D0E3C4B5A69788796A5B4C3D2",
+        )
+        .unwrap();
+        assert_eq!(
+            key.to_string(),
+            "SHA256-s18792--f59c1e1a3b6b3b1a4f3a3d5c7e1b0b6b9c9a1f2d0e3c4b5a69788796a5b4c3d2"
+        );
+    }
+
+    #[test]
+    fn md5_key() {
+        let key = AnnexKey::md5_from_etag(42, "\"6ec084ca9d3be17ec194a8f700d65344\"").unwrap();
+        assert_eq!(key.to_string(), "MD5-s42--6ec084ca9d3be17ec194a8f700d65344");
+    }
+
+    #[rstest]
+    #[case("6ec084ca9d3be17ec194a8f700d65344-1")]
+    #[case("6ec084ca9d3be17ec194a8f700d6534")]
+    #[case("6ec084ca9d3be17ec194a8f700d6534g")]
+    #[case("")]
+    fn bad_md5_etag(#[case] etag: &str) {
+        assert_eq!(AnnexKey::md5_from_etag(42, etag), None);
+    }
+
+    #[test]
+    fn negative_size() {
+        assert_eq!(
+            AnnexKey::md5_from_etag(-1, "6ec084ca9d3be17ec194a8f700d65344"),
+            None
+        );
+    }
+}
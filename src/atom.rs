@@ -0,0 +1,158 @@
+//! Rendering of Atom feeds (RFC 4287)
+use crate::dav::sanitize_xml_text;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use xml::writer::{events::XmlEvent, EmitterConfig, Error as WriteError, EventWriter};
+
+/// The XML namespace of Atom documents
+static ATOM_NS: &str = "http://www.w3.org/2005/Atom";
+
+/// An Atom feed document
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct AtomFeed {
+    /// A permanent, universally unique identifier for the feed (an IRI)
+    pub(crate) id: String,
+
+    /// The human-readable title of the feed
+    pub(crate) title: String,
+
+    /// The name of the feed's author
+    pub(crate) author: String,
+
+    /// The most recent time at which the feed changed in a significant way
+    pub(crate) updated: OffsetDateTime,
+
+    /// The entries in the feed
+    pub(crate) entries: Vec<AtomEntry>,
+}
+
+/// An entry in an [`AtomFeed`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct AtomEntry {
+    /// A permanent, universally unique identifier for the entry (an IRI)
+    pub(crate) id: String,
+
+    /// The human-readable title of the entry
+    pub(crate) title: String,
+
+    /// The most recent time at which the entry changed in a significant way
+    pub(crate) updated: OffsetDateTime,
+
+    /// A URL or absolute path at which the entry's subject can be found
+    pub(crate) link: String,
+
+    /// A short plain-text summary of the entry, if any
+    pub(crate) summary: Option<String>,
+}
+
+impl AtomFeed {
+    /// Render the feed as an XML document
+    pub(crate) fn to_xml(&self) -> String {
+        let mut writer = EmitterConfig::new()
+            .perform_indent(true)
+            .write_document_declaration(true)
+            .create_writer(Vec::new());
+        self.write(&mut writer)
+            .expect("writing a well-formed Atom feed to memory should not fail");
+        String::from_utf8(writer.into_inner()).expect("XML writer should emit valid UTF-8")
+    }
+
+    /// Write the feed's XML events to `writer`
+    fn write(&self, writer: &mut EventWriter<Vec<u8>>) -> Result<(), WriteError> {
+        writer.write(XmlEvent::start_element("feed").default_ns(ATOM_NS))?;
+        text_tag(writer, "id", &self.id)?;
+        text_tag(writer, "title", &self.title)?;
+        text_tag(writer, "updated", &format_timestamp(self.updated))?;
+        writer.write(XmlEvent::start_element("author"))?;
+        text_tag(writer, "name", &self.author)?;
+        writer.write(XmlEvent::end_element())?;
+        for entry in &self.entries {
+            writer.write(XmlEvent::start_element("entry"))?;
+            text_tag(writer, "id", &entry.id)?;
+            text_tag(writer, "title", &entry.title)?;
+            text_tag(writer, "updated", &format_timestamp(entry.updated))?;
+            writer.write(XmlEvent::start_element("link").attr("href", &entry.link))?;
+            writer.write(XmlEvent::end_element())?;
+            if let Some(summary) = &entry.summary {
+                text_tag(writer, "summary", summary)?;
+            }
+            writer.write(XmlEvent::end_element())?;
+        }
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// Write an element named `name` containing only the text `text`, replacing
+/// any characters in the text that are not allowed in XML documents
+fn text_tag(writer: &mut EventWriter<Vec<u8>>, name: &str, text: &str) -> Result<(), WriteError> {
+    writer.write(XmlEvent::start_element(name))?;
+    writer.write(XmlEvent::characters(&sanitize_xml_text(text)))?;
+    writer.write(XmlEvent::end_element())?;
+    Ok(())
+}
+
+/// Format a timestamp as an RFC 3339 date-time, as required by Atom
+fn format_timestamp(ts: OffsetDateTime) -> String {
+    ts.format(&Rfc3339)
+        .expect("formatting an OffsetDateTime as RFC 3339 should not fail")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+    use time::macros::datetime;
+
+    #[test]
+    fn render_feed() {
+        let feed = AtomFeed {
+            id: "https://api.example.com/feed".into(),
+            title: "Example <feed>".into(),
+            author: "Example Author".into(),
+            updated: datetime!(2024-05-18 17:13:27.131814 UTC),
+            entries: vec![
+                AtomEntry {
+                    id: "https://api.example.com/item/1/".into(),
+                    title: "Item & one".into(),
+                    updated: datetime!(2024-05-18 17:13:27.131814 UTC),
+                    link: "/items/1/".into(),
+                    summary: Some("First\u{1} item".into()),
+                },
+                AtomEntry {
+                    id: "https://api.example.com/item/2/".into(),
+                    title: "Item two".into(),
+                    updated: datetime!(2023-06-29 19:55:35 UTC),
+                    link: "/items/2/".into(),
+                    summary: None,
+                },
+            ],
+        };
+        assert_eq!(
+            feed.to_xml(),
+            indoc! {r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+              <id>https://api.example.com/feed</id>
+              <title>Example &lt;feed&gt;</title>
+              <updated>2024-05-18T17:13:27.131814Z</updated>
+              <author>
+                <name>Example Author</name>
+              </author>
+              <entry>
+                <id>https://api.example.com/item/1/</id>
+                <title>Item &amp; one</title>
+                <updated>2024-05-18T17:13:27.131814Z</updated>
+                <link href="/items/1/" />
+                <summary>First� item</summary>
+              </entry>
+              <entry>
+                <id>https://api.example.com/item/2/</id>
+                <title>Item two</title>
+                <updated>2023-06-29T19:55:35Z</updated>
+                <link href="/items/2/" />
+              </entry>
+            </feed>"#}
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Recording of recently-served download redirects
+use crate::consts::AUDIT_LOG_QUEUE_SIZE;
+use crate::openapi::ApiSchema;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use time::OffsetDateTime;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// A bounded in-memory record of download redirects served by `dandidav`,
+/// optionally also appended to a file as JSON Lines
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    /// The most recent records, oldest first
+    records: Mutex<VecDeque<DownloadRecord>>,
+
+    /// The maximum number of records to keep in memory
+    capacity: usize,
+
+    /// A channel for sending serialized records to the thread that appends
+    /// them to the audit log file, if any.  Writing happens on a dedicated
+    /// thread so that request handlers never block on file I/O.
+    file: Option<mpsc::Sender<String>>,
+}
+
+impl AuditLog {
+    /// Construct a new `AuditLog` that keeps up to `capacity` records in
+    /// memory and, if `path` is given, appends records to the file at that
+    /// path
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file at `path` cannot be opened for appending
+    /// or if the thread for writing to it cannot be spawned
+    pub(crate) fn new(capacity: usize, path: Option<&Path>) -> std::io::Result<AuditLog> {
+        let file = match path {
+            Some(p) => {
+                let fp = OpenOptions::new().create(true).append(true).open(p)?;
+                let (sender, receiver) = mpsc::channel(AUDIT_LOG_QUEUE_SIZE);
+                std::thread::Builder::new()
+                    .name(String::from("audit-log-writer"))
+                    .spawn(move || write_lines(fp, receiver))?;
+                Some(sender)
+            }
+            None => None,
+        };
+        Ok(AuditLog {
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            file,
+        })
+    }
+
+    /// Record a download redirect, evicting the oldest in-memory record if
+    /// the log is at capacity
+    pub(crate) fn record(&self, rec: DownloadRecord) {
+        if let Some(ref file) = self.file {
+            match serde_json::to_string(&rec) {
+                Ok(line) => match file.try_send(line) {
+                    Ok(()) => (),
+                    Err(TrySendError::Full(_)) => tracing::warn!(
+                        "Audit log file writer is falling behind; record not written to file"
+                    ),
+                    Err(TrySendError::Closed(_)) => tracing::warn!(
+                        "Audit log file writer has stopped; record not written to file"
+                    ),
+                },
+                Err(e) => tracing::warn!(error = %e, "Failed to serialize audit log record"),
+            }
+        }
+        if self.capacity > 0 {
+            let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+            if records.len() >= self.capacity {
+                records.pop_front();
+            }
+            records.push_back(rec);
+        }
+    }
+
+    /// Return the in-memory records, most recent first
+    pub(crate) fn recent(&self) -> Vec<DownloadRecord> {
+        let records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        records.iter().rev().cloned().collect()
+    }
+}
+
+/// Append each line received on `receiver` to `fp` until all senders are
+/// dropped
+fn write_lines(mut fp: File, mut receiver: mpsc::Receiver<String>) {
+    while let Some(line) = receiver.blocking_recv() {
+        if let Err(e) = writeln!(fp, "{line}") {
+            tracing::warn!(error = %e, "Failed to write to audit log file");
+        }
+    }
+}
+
+/// A record of a single download redirect
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct DownloadRecord {
+    /// The time at which the redirect was served
+    #[serde(with = "time::serde::rfc3339")]
+    pub(crate) timestamp: OffsetDateTime,
+
+    /// The request path for the downloaded resource
+    pub(crate) path: String,
+
+    /// The kind of client that made the request
+    pub(crate) client_class: ClientClass,
+}
+
+impl ApiSchema for DownloadRecord {
+    fn schema() -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "description": "A download redirect served by dandidav",
+            "properties": {
+                "timestamp": {"type": "string", "format": "date-time"},
+                "path": {"type": "string"},
+                "client_class": {
+                    "type": "string",
+                    "enum": ["webdav", "browser", "tool", "other"],
+                },
+            },
+            "required": ["timestamp", "path", "client_class"],
+        })
+    }
+}
+
+/// A rough classification of HTTP clients based on their `User-Agent` headers
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ClientClass {
+    /// A dedicated WebDAV client or sync tool
+    #[serde(rename = "webdav")]
+    WebDav,
+
+    /// A web browser
+    Browser,
+
+    /// A general-purpose command-line HTTP client or library
+    Tool,
+
+    /// Anything else, including requests without a `User-Agent`
+    Other,
+}
+
+impl ClientClass {
+    /// Classify a client by its `User-Agent` header value
+    pub(crate) fn from_user_agent(user_agent: Option<&str>) -> ClientClass {
+        let Some(ua) = user_agent.map(str::to_ascii_lowercase) else {
+            return ClientClass::Other;
+        };
+        if [
+            "rclone",
+            "davfs2",
+            "webdavfs",
+            "microsoft-webdav",
+            "cadaver",
+            "gvfs",
+            "cyberduck",
+            "webdav",
+        ]
+        .iter()
+        .any(|s| ua.contains(s))
+        {
+            ClientClass::WebDav
+        } else if ua.starts_with("mozilla/") {
+            ClientClass::Browser
+        } else if [
+            "curl/",
+            "wget/",
+            "python-requests/",
+            "aiohttp/",
+            "datalad",
+            "git-annex",
+        ]
+        .iter()
+        .any(|s| ua.contains(s))
+        {
+            ClientClass::Tool
+        } else {
+            ClientClass::Other
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(None, ClientClass::Other)]
+    #[case(Some("rclone/v1.69.0"), ClientClass::WebDav)]
+    #[case(Some("davfs2/1.7.0 neon/0.33.0"), ClientClass::WebDav)]
+    #[case(
+        Some("Mozilla/5.0 (X11; Linux x86_64; rv:133.0) Gecko/20100101 Firefox/133.0"),
+        ClientClass::Browser
+    )]
+    #[case(Some("curl/8.11.1"), ClientClass::Tool)]
+    #[case(Some("python-requests/2.32.3"), ClientClass::Tool)]
+    #[case(Some("foobar/1.0"), ClientClass::Other)]
+    fn classify(#[case] ua: Option<&str>, #[case] class: ClientClass) {
+        assert_eq!(ClientClass::from_user_agent(ua), class);
+    }
+
+    fn record(path: &str) -> DownloadRecord {
+        DownloadRecord {
+            timestamp: OffsetDateTime::UNIX_EPOCH,
+            path: path.into(),
+            client_class: ClientClass::Other,
+        }
+    }
+
+    #[test]
+    fn bounded() {
+        let log = AuditLog::new(2, None).unwrap();
+        log.record(record("/a"));
+        log.record(record("/b"));
+        log.record(record("/c"));
+        assert_eq!(log.recent(), vec![record("/c"), record("/b")]);
+    }
+
+    #[test]
+    fn write_to_file() {
+        let path = std::env::temp_dir().join(format!("dandidav-audit-{}", uuid::Uuid::new_v4()));
+        let log = AuditLog::new(0, Some(&path)).unwrap();
+        log.record(record("/a"));
+        log.record(record("/b"));
+        assert_eq!(log.recent(), Vec::new());
+        let mut lines = Vec::new();
+        for _ in 0..100 {
+            lines = std::fs::read_to_string(&path)
+                .unwrap()
+                .lines()
+                .map(|ln| serde_json::from_str::<serde_json::Value>(ln).unwrap())
+                .collect::<Vec<_>>();
+            if lines.len() >= 2 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["path"], "/a");
+        assert_eq!(lines[1]["path"], "/b");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,47 @@
+//! Running CPU-heavy work off of the async worker threads
+//!
+//! Rendering the response for a large collection (whether as HTML or as a
+//! PROPFIND `multistatus` document) or converting a large metadata document to
+//! YAML can take long enough to stall every other request scheduled on the
+//! same worker thread, which is especially noticeable when there are only one
+//! or two worker threads.  Such work is therefore run on Tokio's blocking
+//! thread pool instead.
+
+/// Run the synchronous function `f` on Tokio's blocking thread pool and return
+/// its result
+///
+/// # Panics
+///
+/// If `f` panics, the panic is resumed in the calling task.  Panics if the
+/// blocking task is cancelled, which only happens when the runtime is shutting
+/// down.
+pub(crate) async fn run_blocking<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(r) => r,
+        Err(e) => match e.try_into_panic() {
+            Ok(payload) => std::panic::resume_unwind(payload),
+            Err(e) => panic!("blocking task did not complete: {e}"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn returns_result() {
+        let v = run_blocking(|| (1..=10).sum::<u32>()).await;
+        assert_eq!(v, 55);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "boom")]
+    async fn resumes_panic() {
+        run_blocking(|| panic!("boom")).await;
+    }
+}
@@ -0,0 +1,114 @@
+//! Limiting the number of upstream requests made on behalf of a single
+//! incoming request
+//!
+//! Some requests — most notably deep `PROPFIND` listings — can cause
+//! `dandidav` to make a large number of requests to the Archive and other
+//! upstream servers.  To keep a single client from using `dandidav` to flood
+//! the Archive, each incoming request can be given a budget of upstream
+//! requests; once the budget is spent, further upstream requests fail with
+//! [`BudgetExceeded`], and the incoming request is answered with a 503.
+//!
+//! The budget is tracked in a task-local variable, so only upstream requests
+//! made from the task handling the incoming request (and not from background
+//! tasks spawned by it, such as metadata prefetching) are counted.
+use std::cell::Cell;
+use std::future::Future;
+use thiserror::Error;
+
+tokio::task_local! {
+    /// The budget for the incoming request being handled by the current task
+    static BUDGET: RequestBudget;
+}
+
+/// A count of the upstream requests made on behalf of an incoming request
+#[derive(Debug)]
+struct RequestBudget {
+    /// The maximum number of upstream requests permitted
+    limit: usize,
+
+    /// The number of upstream requests made or attempted so far
+    used: Cell<usize>,
+}
+
+impl RequestBudget {
+    /// Returns true if more upstream requests were attempted than permitted
+    fn exceeded(&self) -> bool {
+        self.used.get() > self.limit
+    }
+}
+
+/// Run `fut` with a budget of `limit` upstream requests.  Returns the output
+/// of `fut` along with whether the budget was exceeded.
+pub(crate) async fn with_budget<F: Future>(limit: usize, fut: F) -> (F::Output, bool) {
+    let budget = RequestBudget {
+        limit,
+        used: Cell::new(0),
+    };
+    BUDGET
+        .scope(budget, async move {
+            let output = fut.await;
+            (output, BUDGET.with(RequestBudget::exceeded))
+        })
+        .await
+}
+
+/// Record that an upstream request is about to be made.  If the current task
+/// has a budget and it has already been spent, an error is returned, and the
+/// request should not be made.  If the current task does not have a budget,
+/// this always succeeds.
+pub(crate) fn charge() -> Result<(), BudgetExceeded> {
+    BUDGET
+        .try_with(|budget| {
+            budget.used.set(budget.used.get().saturating_add(1));
+            if budget.exceeded() {
+                Err(BudgetExceeded {
+                    limit: budget.limit,
+                })
+            } else {
+                Ok(())
+            }
+        })
+        .unwrap_or(Ok(()))
+}
+
+/// Error returned by [`charge()`] when the current task's budget of upstream
+/// requests has been spent
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("incoming request required more than {limit} requests to upstream servers")]
+pub(crate) struct BudgetExceeded {
+    /// The number of upstream requests permitted per incoming request
+    pub(crate) limit: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn within_budget() {
+        let (r, exceeded) = with_budget(2, async { (charge(), charge()) }).await;
+        assert_eq!(r, (Ok(()), Ok(())));
+        assert!(!exceeded);
+    }
+
+    #[tokio::test]
+    async fn over_budget() {
+        let (r, exceeded) = with_budget(1, async { (charge(), charge(), charge()) }).await;
+        assert_eq!(
+            r,
+            (
+                Ok(()),
+                Err(BudgetExceeded { limit: 1 }),
+                Err(BudgetExceeded { limit: 1 })
+            )
+        );
+        assert!(exceeded);
+    }
+
+    #[test]
+    fn no_budget() {
+        for _ in 0..10 {
+            assert_eq!(charge(), Ok(()));
+        }
+    }
+}
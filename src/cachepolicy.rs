@@ -0,0 +1,98 @@
+//! Central policy for the `Cache-Control` headers of responses
+//!
+//! Responses to administrative endpoints (under `/.admin/`) and to requests
+//! that carry credentials may contain details that must not be shared with
+//! other clients, and so they are always marked `no-store, private`,
+//! overriding whatever caching directives the handler produced.  All other
+//! responses keep their directives, except that any `max-age` or `s-maxage`
+//! longer than [`CACHE_MAX_AGE_LIMIT`] is clamped to it, as the data behind
+//! them may change on the Archive at any time.
+use crate::consts::{ADMIN_PREFIX, CACHE_MAX_AGE_LIMIT};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{
+        header::{AUTHORIZATION, CACHE_CONTROL},
+        HeaderValue,
+    },
+    middleware::Next,
+    response::Response,
+};
+
+/// The `Cache-Control` value for responses that must not be cached
+static NO_STORE: &str = "no-store, private";
+
+/// Middleware for applying the caching policy to all responses
+pub(crate) async fn apply_cache_policy(request: Request, next: Next) -> Response<Body> {
+    let private = request.uri().path().starts_with(ADMIN_PREFIX)
+        || request.headers().contains_key(AUTHORIZATION);
+    let mut resp = next.run(request).await;
+    if private {
+        resp.headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static(NO_STORE));
+    } else if let Some(value) = resp
+        .headers()
+        .get(CACHE_CONTROL)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| clamp_max_age(v, CACHE_MAX_AGE_LIMIT))
+    {
+        resp.headers_mut().insert(CACHE_CONTROL, value);
+    }
+    resp
+}
+
+/// If the `Cache-Control` value `value` contains a `max-age` or `s-maxage`
+/// directive greater than `limit` seconds, return the value with all such
+/// directives lowered to `limit`; otherwise, return `None`
+fn clamp_max_age(value: &str, limit: u64) -> Option<HeaderValue> {
+    let mut clamped = false;
+    let directives = value
+        .split(',')
+        .map(|d| {
+            let d = d.trim();
+            if let Some((name, arg)) = d.split_once('=') {
+                let name = name.trim();
+                if (name.eq_ignore_ascii_case("max-age") || name.eq_ignore_ascii_case("s-maxage"))
+                    && arg
+                        .trim()
+                        .trim_matches('"')
+                        .parse::<u64>()
+                        .is_ok_and(|n| n > limit)
+                {
+                    clamped = true;
+                    return format!("{name}={limit}");
+                }
+            }
+            d.to_owned()
+        })
+        .collect::<Vec<_>>();
+    if clamped {
+        HeaderValue::from_str(&directives.join(", ")).ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("max-age=60", None)]
+    #[case("max-age=300", None)]
+    #[case("max-age=86400", Some("max-age=300"))]
+    #[case("public, max-age=86400", Some("public, max-age=300"))]
+    #[case("max-age=\"86400\", immutable", Some("max-age=300, immutable"))]
+    #[case("S-MaxAge=3600,max-age=30", Some("S-MaxAge=300, max-age=30"))]
+    #[case("no-cache", None)]
+    #[case("max-age=forever", None)]
+    fn test_clamp_max_age(#[case] value: &str, #[case] clamped: Option<&str>) {
+        assert_eq!(
+            clamp_max_age(value, 300)
+                .as_ref()
+                .map(|v| v.to_str().unwrap()),
+            clamped
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Reporting of Dandisets & versions modified since a given time
+use crate::atom::{AtomEntry, AtomFeed};
+use crate::consts::{ATOM_CONTENT_TYPE, JSON_CONTENT_TYPE};
+use crate::dandi::{DandiClient, DandiError, DandisetId, VersionId};
+use crate::httputil::HttpUrl;
+use crate::openapi::ApiSchema;
+use axum::{
+    body::Body,
+    extract::FromRequestParts,
+    http::{header::CONTENT_TYPE, request::Parts, response::Response, StatusCode},
+    response::IntoResponse,
+};
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use url::form_urlencoded;
+
+/// The point in time after which changes are requested, as given by the
+/// `since` query parameter in RFC 3339 format
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Since(pub(crate) OffsetDateTime);
+
+impl Since {
+    /// Parse the value of the `since` query parameter from the query string
+    /// `query`
+    fn from_query(query: Option<&str>) -> Option<Since> {
+        let value = form_urlencoded::parse(query?.as_bytes())
+            .find(|(k, _)| k == "since")
+            .map(|(_, v)| v.into_owned())?;
+        OffsetDateTime::parse(value.trim(), &Rfc3339)
+            .ok()
+            .map(Since)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Since {
+    type Rejection = Response<Body>;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Since::from_query(parts.uri.query()).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                "Missing or invalid \"since\" parameter; must be an RFC 3339 timestamp\n",
+            )
+                .into_response()
+        })
+    }
+}
+
+/// A Dandiset or Dandiset version that was modified after a requested point
+/// in time
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct ChangeRecord {
+    /// The ID of the modified Dandiset
+    pub(crate) dandiset_id: DandisetId,
+
+    /// The ID of the modified version, or `None` if the record describes a
+    /// change to the Dandiset as a whole
+    pub(crate) version: Option<VersionId>,
+
+    /// The `modified` timestamp of the Dandiset or version
+    #[serde(with = "time::serde::rfc3339")]
+    pub(crate) modified: OffsetDateTime,
+
+    /// The path at which `dandidav` serves the Dandiset or version
+    pub(crate) path: String,
+
+    /// The Archive API URL for the Dandiset or version
+    pub(crate) api_url: String,
+}
+
+impl ChangeRecord {
+    /// Return a short human-readable description of the record
+    fn title(&self) -> String {
+        match &self.version {
+            Some(v) => format!("Dandiset {} version {}", self.dandiset_id, v),
+            None => format!("Dandiset {}", self.dandiset_id),
+        }
+    }
+}
+
+impl ApiSchema for ChangeRecord {
+    fn schema() -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "description": "A Dandiset or Dandiset version modified after the requested time",
+            "properties": {
+                "dandiset_id": {"type": "string"},
+                "version": {"type": ["string", "null"]},
+                "modified": {"type": "string", "format": "date-time"},
+                "path": {"type": "string"},
+                "api_url": {"type": "string", "format": "uri"},
+            },
+            "required": ["dandiset_id", "version", "modified", "path", "api_url"],
+        })
+    }
+}
+
+/// Return records for all Dandisets and Dandiset versions on the Archive
+/// instance whose `modified` timestamps are after `since`, newest first
+///
+/// The versions of a Dandiset are only fetched individually if its most
+/// recent published version was modified after `since`.
+pub(crate) async fn get_changes(
+    dandi: &DandiClient,
+    since: OffsetDateTime,
+) -> Result<Vec<ChangeRecord>, DandiError> {
+    let mut records = Vec::new();
+    let mut dandisets = std::pin::pin!(dandi.get_all_dandisets());
+    while let Some(ds) = dandisets.try_next().await? {
+        if ds.modified > since {
+            records.push(ChangeRecord {
+                dandiset_id: ds.identifier.clone(),
+                version: None,
+                modified: ds.modified,
+                path: format!("/dandisets/{}/", ds.identifier),
+                api_url: dandi.dandiset_url(&ds.identifier).to_string(),
+            });
+        }
+        if ds.draft_version.modified > since {
+            records.push(ChangeRecord {
+                dandiset_id: ds.identifier.clone(),
+                version: Some(VersionId::Draft),
+                modified: ds.draft_version.modified,
+                path: format!("/dandisets/{}/draft/", ds.identifier),
+                api_url: ds.draft_version.metadata_url.to_string(),
+            });
+        }
+        if ds
+            .most_recent_published_version
+            .as_ref()
+            .is_some_and(|v| v.modified > since)
+        {
+            let endpoint = dandi.dandiset(ds.identifier.clone());
+            let mut versions = std::pin::pin!(endpoint.get_all_versions());
+            while let Some(v) = versions.try_next().await? {
+                if let VersionId::Published(ref pvid) = v.version {
+                    if v.modified > since {
+                        records.push(ChangeRecord {
+                            dandiset_id: ds.identifier.clone(),
+                            path: format!("/dandisets/{}/releases/{}/", ds.identifier, pvid),
+                            version: Some(v.version),
+                            modified: v.modified,
+                            api_url: v.metadata_url.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    records.sort_by_key(|r| std::cmp::Reverse(r.modified));
+    Ok(records)
+}
+
+/// Return a response listing the changes on `dandi`'s Archive instance after
+/// `since` as JSON
+pub(crate) async fn json_changes(dandi: &DandiClient, since: OffsetDateTime) -> Response<Body> {
+    // Note: This response should not have WebDAV headers (DAV, Allow)
+    match get_changes(dandi, since).await {
+        Ok(records) => match serde_json::to_string(&records) {
+            Ok(body) => ([(CONTENT_TYPE, JSON_CONTENT_TYPE)], body).into_response(),
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to serialize change records");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        },
+        Err(e) => error_response(e),
+    }
+}
+
+/// Return a response listing the changes on `dandi`'s Archive instance after
+/// `since` as an Atom feed
+pub(crate) async fn atom_changes(dandi: &DandiClient, since: OffsetDateTime) -> Response<Body> {
+    // Note: This response should not have WebDAV headers (DAV, Allow)
+    match get_changes(dandi, since).await {
+        Ok(records) => {
+            let body = changes_feed(&records, since, dandi.api_url()).to_xml();
+            ([(CONTENT_TYPE, ATOM_CONTENT_TYPE)], body).into_response()
+        }
+        Err(e) => error_response(e),
+    }
+}
+
+/// Convert an error encountered while querying the Archive into a response
+fn error_response(e: DandiError) -> Response<Body> {
+    let class = e.class();
+    let e = anyhow::Error::from(e);
+    tracing::info!(error = ?e, status = class.to_status().as_u16(), "Failed to fetch changes");
+    (class.to_status(), format!("{e:?}")).into_response()
+}
+
+/// Construct an Atom feed of the change records `records` for changes made
+/// after `since` on the Archive instance with API URL `api_url`
+fn changes_feed(records: &[ChangeRecord], since: OffsetDateTime, api_url: &HttpUrl) -> AtomFeed {
+    let since_str = since
+        .format(&Rfc3339)
+        .expect("formatting an OffsetDateTime as RFC 3339 should not fail");
+    AtomFeed {
+        id: format!("{api_url}#changes-since-{since_str}"),
+        title: format!("DANDI Archive changes since {since_str}"),
+        author: String::from("DANDI Archive"),
+        updated: records.first().map_or(since, |r| r.modified),
+        entries: records
+            .iter()
+            .map(|r| AtomEntry {
+                id: r.api_url.clone(),
+                title: r.title(),
+                updated: r.modified,
+                link: r.path.clone(),
+                summary: None,
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use time::macros::datetime;
+
+    #[rstest]
+    #[case(Some("since=2024-01-01T00:00:00Z"), Some(datetime!(2024-01-01 00:00:00 UTC)))]
+    #[case(
+        Some("foo=bar&since=2024-01-01T12:34:56.5-05:00"),
+        Some(datetime!(2024-01-01 12:34:56.5 -05:00))
+    )]
+    #[case(Some("since=2024-01-01T00%3A00%3A00%2B00%3A00"), Some(datetime!(2024-01-01 00:00:00 UTC)))]
+    #[case(Some("since=2024-01-01"), None)]
+    #[case(Some("since="), None)]
+    #[case(Some("until=2024-01-01T00:00:00Z"), None)]
+    #[case(None, None)]
+    fn test_since_from_query(#[case] query: Option<&str>, #[case] since: Option<OffsetDateTime>) {
+        assert_eq!(Since::from_query(query), since.map(Since));
+    }
+}
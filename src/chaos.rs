@@ -0,0 +1,124 @@
+//! Injection of upstream delays & failures for resilience testing
+//!
+//! When `dandidav` is run with the hidden `--chaos` option, every request made
+//! by its HTTP clients (those behind `DandiClient`, `ZarrManClient`, and the
+//! other users of [`Client`](crate::httputil::Client)) is first delayed by a
+//! random amount of time up to a configured maximum, and a configured
+//! fraction of requests are then answered with a synthetic 503 response
+//! instead of being sent.  Injection takes place beneath the clients' retry
+//! middleware, so injected failures are retried just like real ones, allowing
+//! the handling of a flaky upstream to be exercised without a flaky network.
+//!
+//! This is a development aid and should never be enabled in production.
+use crate::logsample::SampleRate;
+use axum::http::{self, StatusCode};
+use reqwest::{Request, Response, ResponseBuilderExt};
+use reqwest_middleware::{Middleware, Next};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// The body of synthetic responses to requests chosen to fail
+static INJECTED_FAILURE_BODY: &str = "dandidav: injected failure\n";
+
+/// The failure injector installed for the process, if any
+static INSTALLED: OnceLock<ChaosMonkey> = OnceLock::new();
+
+/// HTTP client middleware that delays requests and makes some of them fail
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct ChaosMonkey {
+    /// The fraction of requests to fail
+    error_rate: SampleRate,
+
+    /// The maximum amount of time to delay each request by
+    max_delay: Duration,
+}
+
+impl ChaosMonkey {
+    pub(crate) fn new(error_rate: SampleRate, max_delay: Duration) -> ChaosMonkey {
+        ChaosMonkey {
+            error_rate,
+            max_delay,
+        }
+    }
+
+    /// Make `self` the failure injector for all HTTP clients constructed from
+    /// now on.  Only the first call in a process has any effect.
+    pub(crate) fn install(self) {
+        if INSTALLED.set(self).is_ok() {
+            tracing::warn!(
+                error_rate = %self.error_rate,
+                max_delay_ms = self.max_delay.as_millis(),
+                "Chaos mode enabled; upstream requests will be delayed and failed at random"
+            );
+        }
+    }
+
+    /// Return the failure injector installed for the process, if any
+    pub(crate) fn installed() -> Option<ChaosMonkey> {
+        INSTALLED.get().copied()
+    }
+
+    /// Choose a random amount of time to delay a request by
+    fn delay(&self) -> Duration {
+        self.max_delay.mul_f64(fastrand::f64())
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for ChaosMonkey {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut http::Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let delay = self.delay();
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        if self.error_rate.sample_random() {
+            tracing::debug!(
+                method = %req.method(),
+                url = %req.url(),
+                "Injecting failure into outgoing request"
+            );
+            let resp = http::Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .url(req.url().clone())
+                .body(INJECTED_FAILURE_BODY)
+                .expect("building a synthetic response should not fail");
+            return Ok(Response::from(resp));
+        }
+        next.run(req, extensions).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    #[rstest]
+    #[case("0", StatusCode::OK, 1)]
+    #[case("1", StatusCode::SERVICE_UNAVAILABLE, 0)]
+    #[tokio::test]
+    async fn inject_failures(
+        #[case] error_rate: &str,
+        #[case] status: StatusCode,
+        #[case] sent: usize,
+    ) {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+            .mount(&server)
+            .await;
+        let monkey = ChaosMonkey::new(error_rate.parse().unwrap(), Duration::from_millis(10));
+        let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(monkey)
+            .build();
+        let r = client.get(server.uri()).send().await.unwrap();
+        assert_eq!(r.status(), status);
+        assert_eq!(server.received_requests().await.unwrap().len(), sent);
+    }
+}
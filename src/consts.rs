@@ -19,27 +19,155 @@ pub(crate) static SERVER_VALUE: &str =
 /// The default value of the `--api-url` command-line option
 pub(crate) static DEFAULT_API_URL: &str = "https://api.dandiarchive.org/api";
 
-/// File extensions (case sensitive) for Zarrs, including the leading periods
-pub(crate) static ZARR_EXTENSIONS: [&str; 2] = [".zarr", ".ngff"];
+/// File extensions (case sensitive) for Zarrs, excluding the leading periods
+pub(crate) static ZARR_EXTENSIONS: [&str; 2] = ["zarr", "ngff"];
+
+/// The region used for buckets on a custom S3 endpoint when no region is
+/// specified
+pub(crate) static DEFAULT_S3_REGION: &str = "us-east-1";
 
 /// The maximum number of S3 clients cached at once by `DandiClient`
 pub(crate) const S3CLIENT_CACHE_SIZE: u64 = 8;
 
+/// The maximum number of per-user `DandiClient`s kept at once when
+/// forwarding clients' credentials to the Archive.  Each such client has its
+/// own caches of Archive data.
+pub(crate) const USER_CLIENT_CACHE_SIZE: u64 = 32;
+
+/// Discard a per-user `DandiClient` (and its caches) once no requests with
+/// its credentials have been received for this long
+pub(crate) const USER_CLIENT_IDLE_TTL: Duration = Duration::from_secs(900);
+
+/// When `--api-cache-compress` is given, Archive API response bodies at least
+/// this many bytes in size are stored gzip-compressed in the API cache.
+/// Smaller bodies gain too little from compression to be worth the CPU time.
+pub(crate) const API_CACHE_COMPRESS_MIN_SIZE: usize = 4096;
+
+/// The maximum number of asset folder sizes cached at once by `DandiClient`
+pub(crate) const FOLDER_SIZE_CACHE_SIZE: u64 = 10_000;
+
+/// Expire cached asset folder sizes this long after they were inserted.
+///
+/// Folder sizes in draft versions can change at any time, so they are not kept
+/// for long.
+pub(crate) const FOLDER_SIZE_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// The maximum total size in bytes of serialized `dandiset.yaml` documents
+/// cached at once by `DandiClient`
+pub(crate) const VERSION_METADATA_CACHE_BYTES: u64 = 16_000_000;
+
+/// Expire cached `dandiset.yaml` documents this long after they were
+/// inserted.
+///
+/// The cache exists so that the size reported for a `dandiset.yaml` by
+/// `PROPFIND` matches the body of a subsequent `GET`; draft metadata can
+/// change at any time, so the documents are not kept for long.
+pub(crate) const VERSION_METADATA_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Cached values that are accessed after this fraction of their time-to-live
+/// has elapsed are refreshed in the background (minus a random jitter of up to
+/// [`REFRESH_AHEAD_JITTER`] times the time-to-live)
+pub(crate) const REFRESH_AHEAD_START: f64 = 0.75;
+
+/// The maximum fraction of a cached value's time-to-live by which its
+/// background refresh is randomly moved earlier, so that values cached at the
+/// same time are not all refreshed at once
+pub(crate) const REFRESH_AHEAD_JITTER: f64 = 0.15;
+
+/// Cached values that refresh ahead of expiry continue to be served for this
+/// long past their time-to-live while a single background task fetches a
+/// replacement, so that an expiring value does not cause every concurrent
+/// request for it to go to the Archive at once
+pub(crate) const CACHE_STALE_WINDOW: Duration = Duration::from_secs(60);
+
+/// The maximum number of prefetched asset records cached at once by
+/// `DandiClient`
+pub(crate) const PREFETCH_ASSET_CACHE_SIZE: u64 = 10_000;
+
+/// Expire prefetched asset records this long after they were inserted, and
+/// don't prefetch the same folder listing again within this interval.
+///
+/// Assets in draft versions can change at any time, so they are not kept for
+/// long.
+pub(crate) const PREFETCH_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// The maximum number of sibling prefetches that may run at once.  Requests
+/// that would start a prefetch while this many are already running do not
+/// prefetch anything.
+pub(crate) const PREFETCH_CONCURRENCY: usize = 4;
+
+/// The maximum number of entries of a folder listing to fetch asset records
+/// for when prefetching
+pub(crate) const PREFETCH_MAX_ENTRIES: usize = 100;
+
+/// The maximum number of entries in each of `DandiClient`'s Zarr readahead
+/// caches
+pub(crate) const READAHEAD_CACHE_SIZE: u64 = 10_000;
+
+/// Expire Zarr chunk details fetched ahead of time (and records of the most
+/// recently accessed chunks) this long after they were inserted
+pub(crate) const READAHEAD_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// The maximum number of Zarr readahead fetches that may run at once
+pub(crate) const READAHEAD_CONCURRENCY: usize = 4;
+
+/// The maximum number of `paths` query parameters permitted in a single
+/// request for an export manifest of a Dandiset version
+pub(crate) const EXPORT_MAX_PATHS: usize = 100;
+
+/// The maximum number of files listed in an export manifest of a Dandiset
+/// version; any further files are omitted, and the manifest is marked as
+/// truncated
+pub(crate) const EXPORT_MAX_FILES: usize = 10_000;
+
+/// The maximum number of paths in a request for an export manifest that may
+/// be looked up at once
+pub(crate) const EXPORT_CONCURRENCY: usize = 8;
+
 /// The "Content-Type" value for HTML responses to `GET` requests for
 /// collections
 pub(crate) static HTML_CONTENT_TYPE: &str = "text/html; charset=utf-8";
 
+/// The default "Content-Security-Policy" value for HTML responses.  The web
+/// view's only subresources are the stylesheet & download script served by
+/// `dandidav` itself, and its only form (for downloading selected resources)
+/// is submitted back to `dandidav`, either directly or by the script.
+pub(crate) static DEFAULT_HTML_CSP: &str = "default-src 'none'; style-src 'self'; script-src 'self'; connect-src 'self'; base-uri 'none'; form-action 'self'; frame-ancestors 'none'";
+
+/// The default "X-Content-Type-Options" value for HTML responses
+pub(crate) static DEFAULT_HTML_CONTENT_TYPE_OPTIONS: &str = "nosniff";
+
+/// The default "X-Frame-Options" value for HTML responses
+pub(crate) static DEFAULT_HTML_FRAME_OPTIONS: &str = "DENY";
+
+/// The default "Referrer-Policy" value for HTML responses
+pub(crate) static DEFAULT_HTML_REFERRER_POLICY: &str = "no-referrer";
+
 /// The "Content-Type" value for the stylesheet
 pub(crate) static CSS_CONTENT_TYPE: &str = "text/css; charset=utf-8";
 
+/// The "Content-Type" value for the download script for HTML views
+pub(crate) static JS_CONTENT_TYPE: &str = "text/javascript; charset=utf-8";
+
 /// The "Content-Type" value (reported in both `GET` and `PROPFIND` responses)
 /// for virtual `dandiset.yaml` files
 pub(crate) static YAML_CONTENT_TYPE: &str = "text/yaml; charset=utf-8";
 
+/// The size of the chunks in which the bodies of `dandiset.yaml` responses
+/// are streamed
+pub(crate) const YAML_BODY_CHUNK_SIZE: usize = 64 * 1024;
+
 /// The "Content-Type" value given in `PROPFIND` responses for blob assets with
 /// no `encodingFormat` set
 pub(crate) static DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
 
+/// The "Content-Type" value given in `PROPFIND` responses for Zarr assets
+/// served as single files redirecting to their Zarr manifests
+pub(crate) static JSON_CONTENT_TYPE: &str = "application/json";
+
+/// The "Content-Type" value for Atom feeds
+pub(crate) static ATOM_CONTENT_TYPE: &str = "application/atom+xml";
+
 /// The "Content-Type" value for `PROPFIND` XML responses
 ///
 /// Quoth §8.2 of RFC 4918:
@@ -48,14 +176,50 @@ pub(crate) static DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
 /// > SHOULD be application/xml. … Use of text/xml is deprecated.
 pub(crate) static DAV_XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
 
+/// The name of the request header with which clients can select how Zarr
+/// assets are presented for a single request
+pub(crate) static ZARR_MODE_HEADER: &str = "X-Dandidav-Zarr";
+
 /// The XML namespace for standard WebDAV elements
 pub(crate) static DAV_XMLNS: &str = "DAV:";
 
+/// The XML namespace for `dandidav`-specific WebDAV properties
+pub(crate) static DANDIDAV_XMLNS: &str = "https://github.com/dandi/dandidav/ns";
+
 /// The display format for timestamps shown in collections' HTML views (after
 /// converting to UTC)
 pub(crate) static HTML_TIMESTAMP_FORMAT: &[FormatItem<'_>] =
     format_description!("[year]-[month]-[day] [hour]:[minute]:[second]Z");
 
+/// HTML views of collections with more than this many entries are sent to the
+/// client in pieces as they are rendered rather than rendered in full first
+pub(crate) const HTML_STREAM_THRESHOLD: usize = 1000;
+
+/// When streaming an HTML view of a collection, render this many table rows
+/// at a time
+pub(crate) const HTML_STREAM_BATCH_SIZE: usize = 500;
+
+/// When streaming an HTML view of a collection, pause rendering once this many
+/// rendered pieces are waiting to be sent to the client
+pub(crate) const HTML_STREAM_BUFFER: usize = 4;
+
+/// The maximum total size in bytes of the `PROPFIND` responses for the root
+/// and index collections cached at once when `--propfind-cache-ttl` is given
+pub(crate) const PROPFIND_CACHE_BYTES: u64 = 16_000_000;
+
+/// The number of seconds for which locks granted by `LOCK` requests are held
+/// before expiring unless refreshed
+pub(crate) const LOCK_TIMEOUT_SECS: u64 = 3600;
+
+/// The maximum number of unexpired locks held at once.  `LOCK` requests made
+/// while this many locks are held are answered with 503 responses.
+pub(crate) const MAX_LOCKS: usize = 10_000;
+
+/// When streaming a `PROPFIND` response with infinite depth, pause walking the
+/// tree once this many serialized listings are waiting to be sent to the
+/// client
+pub(crate) const PROPFIND_STREAM_BUFFER: usize = 4;
+
 /// If a client makes a request for a resource with one of these names as a
 /// component (case insensitive), assume it doesn't exist without bothering to
 /// check the backend.
@@ -63,9 +227,90 @@ pub(crate) static HTML_TIMESTAMP_FORMAT: &[FormatItem<'_>] =
 /// This list must be kept in sorted order; this is enforced by a test below.
 pub(crate) static FAST_NOT_EXIST: &[&str] = &[".bzr", ".git", ".nols", ".svn"];
 
+/// The name of the virtual directory beneath each Dandiset version path under
+/// which assets are served by asset ID.  It shadows any real folder of the
+/// same name at the top of a version.
+pub(crate) static BY_ASSET_ID_DIR: &str = ".by-asset-id";
+
+/// The name of the virtual directory beneath `/zarrs/` under which Zarr
+/// entries are served by the digests of their S3 objects
+pub(crate) static BY_CHECKSUM_DIR: &str = ".by-checksum";
+
+/// How long presigned download URLs for objects in private S3 buckets remain
+/// valid
+pub(crate) const PRESIGNED_URL_TTL: Duration = Duration::from_secs(3600);
+
+/// The largest Zarr entry, in bytes, that is stored in the local object cache
+/// when `--proxy-cache-dir` is given.  Larger entries are always redirected
+/// to (or, for ranged requests, proxied from) S3.
+pub(crate) const PROXY_CACHE_MAX_OBJECT_SIZE: u64 = 1 << 20;
+
+/// The largest Zarr entry, in bytes, that is stored in the local object cache
+/// once its path has become hot (see [`HOT_OBJECT_PROMOTION_THRESHOLD`])
+pub(crate) const PROXY_CACHE_MAX_HOT_OBJECT_SIZE: u64 = 16 << 20;
+
+/// The number of requests for a downloadable path after which the path is
+/// considered hot and its object is promoted into the local object cache
+pub(crate) const HOT_OBJECT_PROMOTION_THRESHOLD: u64 = 10;
+
+/// The maximum number of audit log records that may be waiting to be written
+/// to the audit log file before further records are dropped from the file
+pub(crate) const AUDIT_LOG_QUEUE_SIZE: usize = 1024;
+
+/// The maximum number of paths listed at `/.admin/hot-objects`
+pub(crate) const HOT_OBJECT_REPORT_SIZE: usize = 100;
+
+/// The maximum number of version publications listed in the feed at
+/// `/feed.xml`
+pub(crate) const PUBLICATION_FEED_SIZE: usize = 50;
+
+/// How long a rendered `/feed.xml` document is reused before it is
+/// regenerated
+pub(crate) const PUBLICATION_FEED_TTL: Duration = Duration::from_secs(300);
+
+/// The maximum number of concurrent requests made to the Archive for version
+/// details when generating `/feed.xml`
+pub(crate) const PUBLICATION_FEED_CONCURRENCY: usize = 8;
+
+/// The name of the header in webhook notifications that contains the
+/// HMAC-SHA256 signature of the notification body
+pub(crate) static WEBHOOK_SIGNATURE_HEADER: &str = "X-Dandidav-Signature";
+
+/// The path prefix of administrative endpoints
+pub(crate) static ADMIN_PREFIX: &str = "/.admin/";
+
+/// The path at which a snapshot of the caches is served to standby instances
+/// when `--admin-token-file` is given
+pub(crate) static CACHE_SNAPSHOT_PATH: &str = "/.admin/cache-snapshot";
+
+/// How long a request under a mount with a concurrency limit may wait for
+/// the mount to have capacity before being answered with a 503
+pub(crate) const MOUNT_QUEUE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Timeout for a standby instance's request for a cache snapshot from its
+/// primary, which can be much larger than other responses
+pub(crate) const CACHE_SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(300);
+
 /// Interval between periodic logging of the Zarr manifest cache's contents
 pub(crate) const ZARR_MANIFEST_CACHE_DUMP_PERIOD: Duration = Duration::from_secs(3600);
 
+/// The earliest version of the DANDI metadata schema that `dandidav` has been
+/// tested against, as a (major, minor, patch) triple
+pub(crate) const MIN_TESTED_SCHEMA_VERSION: (u32, u32, u32) = (0, 6, 0);
+
+/// The latest version of the DANDI metadata schema that `dandidav` has been
+/// tested against, as a (major, minor, patch) triple.  When the Archive
+/// reports a newer schema version, a warning is shown in HTML views.
+pub(crate) const MAX_TESTED_SCHEMA_VERSION: (u32, u32, u32) = (0, 6, 10);
+
+/// Interval between checks of the versions of the Archive's API server &
+/// metadata schema
+pub(crate) const UPSTREAM_CHECK_PERIOD: Duration = Duration::from_secs(3600);
+
+/// The longest `max-age` (in seconds) that responses may declare in their
+/// `Cache-Control` headers; longer values are clamped to this
+pub(crate) const CACHE_MAX_AGE_LIMIT: u64 = 300;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,197 @@
+//! Restricting the Dandiset metadata fields that are exposed
+//!
+//! Some deployments do not want to surface all of a Dandiset's metadata (such
+//! as contributors' e-mail addresses) in the `dandiset.yaml` files they serve.
+//! A [`MetadataAllowlist`] lists the metadata fields that may be shown; the
+//! values of all other fields are replaced with [`REDACTED_MARKER`] so that it
+//! is clear to readers that something was removed.
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The value that replaces the values of metadata fields that are not in the
+/// allowlist
+pub(crate) static REDACTED_MARKER: &str = "[redacted]";
+
+/// A set of metadata fields that may be exposed, given as dotted paths
+/// through the metadata's objects (e.g., `contributor.name`).  Arrays are
+/// transparent to paths, so `contributor.name` refers to the `name` field of
+/// every object in the `contributor` array.
+///
+/// A path that is allowed exposes everything beneath it, and the fields
+/// leading up to an allowed path are retained (with only their allowed
+/// subfields exposed).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct MetadataAllowlist(AllowTree);
+
+impl MetadataAllowlist {
+    /// Replace the values of all fields in `data` that are not in the
+    /// allowlist with [`REDACTED_MARKER`]
+    pub(crate) fn apply(&self, data: &mut Value) {
+        self.0.apply(data);
+    }
+}
+
+impl FromStr for MetadataAllowlist {
+    type Err = ParseMetadataAllowlistError;
+
+    /// Parse a comma-separated list of dotted field paths.  Whitespace around
+    /// paths is ignored.
+    fn from_str(s: &str) -> Result<MetadataAllowlist, ParseMetadataAllowlistError> {
+        let mut allowlist = MetadataAllowlist::default();
+        for field in s.split(',').map(str::trim) {
+            if field.is_empty() {
+                return Err(ParseMetadataAllowlistError::EmptyField);
+            }
+            let mut node = &mut allowlist.0;
+            for key in field.split('.') {
+                if key.is_empty() {
+                    return Err(ParseMetadataAllowlistError::EmptyKey(field.to_owned()));
+                }
+                node = node.children.entry(key.to_owned()).or_default();
+            }
+            node.all = true;
+        }
+        Ok(allowlist)
+    }
+}
+
+/// A node in the tree of allowed field paths
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct AllowTree {
+    /// Whether the value at this node is allowed in its entirety
+    all: bool,
+
+    /// The allowed fields of the value at this node, if it is not allowed in
+    /// its entirety
+    children: BTreeMap<String, AllowTree>,
+}
+
+impl AllowTree {
+    /// Redact the parts of `value` that are not allowed by this node
+    fn apply(&self, value: &mut Value) {
+        if self.all {
+            return;
+        }
+        match value {
+            Value::Object(map) => {
+                for (key, v) in map.iter_mut() {
+                    match self.children.get(key) {
+                        Some(child) => child.apply(v),
+                        None => *v = Value::from(REDACTED_MARKER),
+                    }
+                }
+            }
+            Value::Array(items) => {
+                for v in items {
+                    self.apply(v);
+                }
+            }
+            // Only subfields of this value are allowed, but it doesn't have
+            // any fields, so redact it wholesale.
+            v => *v = Value::from(REDACTED_MARKER),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub(crate) enum ParseMetadataAllowlistError {
+    #[error("metadata allowlist contains an empty field path")]
+    EmptyField,
+    #[error("metadata field path {0:?} contains an empty key")]
+    EmptyKey(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn sample() -> Value {
+        json!({
+            "id": "DANDI:000001/draft",
+            "name": "Brainscan of a Unicorn",
+            "contributor": [
+                {
+                    "name": "Doe, Jane",
+                    "email": "jane@example.com",
+                    "roleName": ["dcite:Author"],
+                },
+                {
+                    "name": "Example Lab",
+                    "email": "lab@example.com",
+                },
+            ],
+            "assetsSummary": {
+                "numberOfBytes": 42,
+                "species": [{"name": "Unicorn"}],
+            },
+        })
+    }
+
+    #[test]
+    fn apply() {
+        let allowlist = "id, name,contributor.name,contributor.roleName,assetsSummary.species"
+            .parse::<MetadataAllowlist>()
+            .unwrap();
+        let mut data = sample();
+        allowlist.apply(&mut data);
+        assert_eq!(
+            data,
+            json!({
+                "id": "DANDI:000001/draft",
+                "name": "Brainscan of a Unicorn",
+                "contributor": [
+                    {
+                        "name": "Doe, Jane",
+                        "email": "[redacted]",
+                        "roleName": ["dcite:Author"],
+                    },
+                    {
+                        "name": "Example Lab",
+                        "email": "[redacted]",
+                    },
+                ],
+                "assetsSummary": {
+                    "numberOfBytes": "[redacted]",
+                    "species": [{"name": "Unicorn"}],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn apply_subfield_of_scalar() {
+        let allowlist = "id.foo".parse::<MetadataAllowlist>().unwrap();
+        let mut data = json!({"id": "DANDI:000001/draft", "name": "Unicorn"});
+        allowlist.apply(&mut data);
+        assert_eq!(data, json!({"id": "[redacted]", "name": "[redacted]"}));
+    }
+
+    #[test]
+    fn overlapping_paths() {
+        let allowlist = "contributor.name,contributor"
+            .parse::<MetadataAllowlist>()
+            .unwrap();
+        let mut data = sample();
+        allowlist.apply(&mut data);
+        assert_eq!(data["contributor"], sample()["contributor"]);
+        assert_eq!(data["name"], "[redacted]");
+    }
+
+    #[rstest]
+    #[case("", ParseMetadataAllowlistError::EmptyField)]
+    #[case("name,,id", ParseMetadataAllowlistError::EmptyField)]
+    #[case("name,", ParseMetadataAllowlistError::EmptyField)]
+    #[case(
+        "contributor..name",
+        ParseMetadataAllowlistError::EmptyKey("contributor..name".into())
+    )]
+    #[case(".name", ParseMetadataAllowlistError::EmptyKey(".name".into()))]
+    fn parse_err(#[case] s: &str, #[case] err: ParseMetadataAllowlistError) {
+        assert_eq!(s.parse::<MetadataAllowlist>(), Err(err));
+    }
+}
@@ -0,0 +1,296 @@
+use crate::blocking::run_blocking;
+use crate::consts::API_CACHE_COMPRESS_MIN_SIZE;
+use crate::httputil::{Client, HttpError, HttpUrl};
+use bytes::Bytes;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use moka::future::{Cache, CacheBuilder};
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::io::{Read, Write};
+use std::ops::Add;
+use std::time::Duration;
+
+/// A cache of the raw bodies of successful `GET` responses from the Archive
+/// API, keyed by request URL (including query parameters, so that each page
+/// of a paginated listing is cached separately).
+///
+/// The cache's capacity is measured in bytes, with each entry weighed by the
+/// size of its URL & stored body, so that a few large responses (such as pages
+/// of big asset listings) cannot crowd out many small, frequently-used ones.
+/// Entries are evicted once they are older than the configured TTL or, when
+/// the cache is full, in least-recently-used order.  Error responses are not
+/// cached.
+///
+/// If compression is enabled, large bodies are stored gzip-compressed and are
+/// only decompressed when they are read back out of the cache.  Compression &
+/// decompression are run on the blocking thread pool.
+#[derive(Clone, Debug)]
+pub(super) struct ApiCache {
+    responses: Cache<HttpUrl, CachedBody>,
+    compress: bool,
+}
+
+impl ApiCache {
+    /// Construct a new `ApiCache` holding at most `max_bytes` bytes of
+    /// responses for at most `ttl` each.  If `compress` is true, bodies of at
+    /// least [`API_CACHE_COMPRESS_MIN_SIZE`] bytes are stored compressed.
+    pub(super) fn new(max_bytes: u64, ttl: Duration, compress: bool) -> ApiCache {
+        ApiCache {
+            responses: CacheBuilder::new(max_bytes)
+                .name("api-responses")
+                .weigher(|url: &HttpUrl, body: &CachedBody| {
+                    u32::try_from(url.as_str().len().saturating_add(body.data.len()))
+                        .unwrap_or(u32::MAX)
+                })
+                .time_to_live(ttl)
+                .build(),
+            compress,
+        }
+    }
+
+    /// Construct a new, empty `ApiCache` with the same TTL & compression
+    /// setting as `self` and a `1/shares` share of its capacity
+    pub(super) fn empty_share(&self, shares: u64) -> ApiCache {
+        let policy = self.responses.policy();
+        ApiCache::new(
+            policy.max_capacity().unwrap_or_default() / shares.max(1),
+            policy.time_to_live().unwrap_or_default(),
+            self.compress,
+        )
+    }
+
+    /// Return the approximate number of cached responses
+    pub(super) fn cache_len(&self) -> u64 {
+        self.responses.entry_count()
+    }
+
+    /// Return the amount of memory used by the cached response bodies
+    pub(super) fn usage(&self) -> ApiCacheUsage {
+        self.responses
+            .iter()
+            .map(|(_, body)| ApiCacheUsage {
+                stored_size: as_u64(body.data.len()),
+                uncompressed_size: as_u64(body.raw_len),
+            })
+            .fold(ApiCacheUsage::default(), Add::add)
+    }
+
+    /// Discard all cached responses
+    pub(super) fn clear(&self) {
+        self.responses.invalidate_all();
+    }
+
+    /// Deserialize the JSON body of the response to a `GET` request to `url`,
+    /// using a cached body if there is one and otherwise performing the
+    /// request with `client` and caching the body
+    ///
+    /// As with [`Client::get_json()`], the returned future does not borrow
+    /// `self` or `client`.
+    pub(super) fn get_json<T: DeserializeOwned>(
+        &self,
+        client: &Client,
+        url: HttpUrl,
+    ) -> impl Future<Output = Result<T, HttpError>> {
+        let cache = self.clone();
+        let client = client.clone();
+        async move {
+            let decoded = match cache.responses.get(&url).await {
+                Some(entry) if entry.compressed => Some(run_blocking(move || entry.decode()).await),
+                Some(entry) => Some(entry.decode()),
+                None => None,
+            };
+            let cached = match decoded {
+                Some(Ok(body)) => Some(body),
+                Some(Err(e)) => {
+                    tracing::warn!(
+                        url = %url,
+                        error = ?e,
+                        "Failed to decompress cached API response; refetching",
+                    );
+                    None
+                }
+                None => None,
+            };
+            let body = if let Some(body) = cached {
+                body
+            } else {
+                let body = client.get_bytes(url.clone()).await?;
+                let entry = if cache.compress && body.len() >= API_CACHE_COMPRESS_MIN_SIZE {
+                    let body = body.clone();
+                    run_blocking(move || CachedBody::compressed(body)).await
+                } else {
+                    CachedBody::plain(body.clone())
+                };
+                cache.responses.insert(url.clone(), entry).await;
+                body
+            };
+            serde_json::from_slice(&body).map_err(move |source| HttpError::Parse { url, source })
+        }
+    }
+}
+
+/// A response body as stored in an [`ApiCache`]
+#[derive(Clone, Debug)]
+struct CachedBody {
+    /// The body, gzip-compressed if `compressed` is true
+    data: Bytes,
+
+    /// The size of the body before compression
+    raw_len: usize,
+
+    /// Whether `data` is gzip-compressed
+    compressed: bool,
+}
+
+impl CachedBody {
+    /// Store `body` as-is
+    fn plain(body: Bytes) -> CachedBody {
+        CachedBody {
+            raw_len: body.len(),
+            data: body,
+            compressed: false,
+        }
+    }
+
+    /// Store `body` gzip-compressed if it is at least
+    /// [`API_CACHE_COMPRESS_MIN_SIZE`] bytes long and compression makes it
+    /// smaller; otherwise, store it as-is
+    fn compressed(body: Bytes) -> CachedBody {
+        if body.len() < API_CACHE_COMPRESS_MIN_SIZE {
+            return CachedBody::plain(body);
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        match encoder.write_all(&body).and_then(|()| encoder.finish()) {
+            Ok(data) if data.len() < body.len() => CachedBody {
+                data: Bytes::from(data),
+                raw_len: body.len(),
+                compressed: true,
+            },
+            _ => CachedBody::plain(body),
+        }
+    }
+
+    /// Return the original body, decompressing it if necessary
+    fn decode(&self) -> std::io::Result<Bytes> {
+        if self.compressed {
+            let mut buf = Vec::with_capacity(self.raw_len);
+            GzDecoder::new(&self.data[..]).read_to_end(&mut buf)?;
+            Ok(Bytes::from(buf))
+        } else {
+            Ok(self.data.clone())
+        }
+    }
+}
+
+/// The amount of memory used by the response bodies in one or more
+/// `ApiCache`s
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct ApiCacheUsage {
+    /// The total size in bytes of the bodies as stored, i.e., after any
+    /// compression
+    pub(crate) stored_size: u64,
+
+    /// The total size in bytes that the bodies would take up if stored
+    /// uncompressed
+    pub(crate) uncompressed_size: u64,
+}
+
+impl Add for ApiCacheUsage {
+    type Output = ApiCacheUsage;
+
+    fn add(self, other: ApiCacheUsage) -> ApiCacheUsage {
+        ApiCacheUsage {
+            stored_size: self.stored_size.saturating_add(other.stored_size),
+            uncompressed_size: self
+                .uncompressed_size
+                .saturating_add(other.uncompressed_size),
+        }
+    }
+}
+
+/// Convert a `usize` body size to a `u64`
+fn as_u64(n: usize) -> u64 {
+    u64::try_from(n).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn large_responses_do_not_evict_small_ones() {
+        let cache = ApiCache::new(1000, Duration::from_secs(60), false);
+        let small = (0..10)
+            .map(|i| {
+                format!("https://api.example.com/api/dandisets/{i:06}/")
+                    .parse::<HttpUrl>()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        for url in &small {
+            cache
+                .responses
+                .insert(url.clone(), CachedBody::plain(Bytes::from_static(b"{}")))
+                .await;
+        }
+        let big = "https://api.example.com/api/dandisets/000001/versions/draft/assets/"
+            .parse::<HttpUrl>()
+            .unwrap();
+        cache
+            .responses
+            .insert(
+                big.clone(),
+                CachedBody::plain(Bytes::from(vec![b' '; 2000])),
+            )
+            .await;
+        cache.responses.run_pending_tasks().await;
+        assert!(!cache.responses.contains_key(&big));
+        for url in &small {
+            assert!(cache.responses.contains_key(url));
+        }
+        assert_eq!(cache.cache_len(), 10);
+    }
+
+    #[test]
+    fn compressed_roundtrip() {
+        let body = Bytes::from(
+            serde_json::to_vec(&serde_json::json!({
+                "results": vec!["sub-RAT123/sub-RAT123.nwb"; 500],
+            }))
+            .unwrap(),
+        );
+        let entry = CachedBody::compressed(body.clone());
+        assert!(entry.compressed);
+        assert!(entry.data.len() < body.len());
+        assert_eq!(entry.raw_len, body.len());
+        assert_eq!(entry.decode().unwrap(), body);
+    }
+
+    #[test]
+    fn small_bodies_not_compressed() {
+        let body = Bytes::from_static(b"{\"count\": 0, \"results\": []}");
+        let entry = CachedBody::compressed(body.clone());
+        assert!(!entry.compressed);
+        assert_eq!(entry.decode().unwrap(), body);
+    }
+
+    #[tokio::test]
+    async fn usage() {
+        let cache = ApiCache::new(1_000_000, Duration::from_secs(60), true);
+        let body = Bytes::from(vec![b' '; 10_000]);
+        let small = Bytes::from_static(b"{}");
+        for (path, body) in [("big", body), ("small", small)] {
+            let url = format!("https://api.example.com/api/{path}/")
+                .parse::<HttpUrl>()
+                .unwrap();
+            cache
+                .responses
+                .insert(url, CachedBody::compressed(body))
+                .await;
+        }
+        cache.responses.run_pending_tasks().await;
+        let usage = cache.usage();
+        assert_eq!(usage.uncompressed_size, 10_002);
+        assert!(usage.stored_size < 1000);
+    }
+}
@@ -1,24 +1,43 @@
 //! The implementation of the data source for the `/dandisets/` hierarchy
+mod allowlist;
+mod apicache;
 mod dandiset_id;
+mod prefetch;
+mod readahead;
 mod streams;
 mod types;
 mod version_id;
+pub(crate) use self::allowlist::*;
+use self::apicache::ApiCache;
+pub(crate) use self::apicache::ApiCacheUsage;
 pub(crate) use self::dandiset_id::*;
+use self::prefetch::*;
+use self::readahead::Readahead;
 use self::streams::Paginate;
 pub(crate) use self::types::*;
 pub(crate) use self::version_id::*;
-use crate::consts::S3CLIENT_CACHE_SIZE;
+use crate::blocking::run_blocking;
+use crate::consts::{
+    FOLDER_SIZE_CACHE_SIZE, FOLDER_SIZE_CACHE_TTL, PREFETCH_MAX_ENTRIES, PRESIGNED_URL_TTL,
+    S3CLIENT_CACHE_SIZE, VERSION_METADATA_CACHE_BYTES, VERSION_METADATA_CACHE_TTL,
+};
 use crate::dav::ErrorClass;
 use crate::httputil::{BuildClientError, Client, HttpError, HttpUrl};
 use crate::paths::{ParsePureDirPathError, PureDirPath, PurePath};
+use crate::recorder::FixtureRecorder;
+use crate::refresh::{cache_lifetime, Fetched, Refreshing};
 use crate::s3::{
-    BucketSpec, GetBucketRegionError, PrefixedS3Client, S3Client, S3Error, S3Location,
+    BucketSpec, GetBucketRegionError, PrefixedS3Client, S3Client, S3Entry, S3Error, S3Location,
+    S3Settings,
 };
-use futures_util::{Stream, TryStreamExt};
+use futures_util::{Stream, StreamExt, TryStreamExt};
 use moka::future::{Cache, CacheBuilder};
-use serde::de::DeserializeOwned;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use smartstring::alias::CompactString;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 
 /// A client for fetching data about Dandisets, their versions, and their
@@ -28,6 +47,12 @@ pub(crate) struct DandiClient {
     /// The HTTP client used for making requests to the Archive instance's API
     inner: Client,
 
+    /// The HTTP client used for resolving the download URLs of embargoed
+    /// assets into the presigned URLs that they redirect to.  This is only
+    /// present when an API token was supplied, and, like `inner`, it sends
+    /// the token with each request.
+    download_resolver: Option<Client>,
+
     /// The base API URL of the Archive instance
     api_url: HttpUrl,
 
@@ -42,14 +67,68 @@ pub(crate) struct DandiClient {
     /// cache them.
     s3clients: Cache<BucketSpec, Arc<S3Client>>,
 
+    /// A cache of the aggregate sizes of asset folders, as reported by the
+    /// Archive when listing the folders' parents.
+    ///
+    /// This allows the size of a folder to be reported when the folder itself
+    /// is requested without its children, as the Archive provides no
+    /// efficient way to query a single folder's size directly.
+    folder_sizes: Cache<FolderSizeKey, i64>,
+
+    /// A cache of serialized `dandiset.yaml` documents for Dandiset versions.
+    ///
+    /// Documents are cached whenever version information is fetched, so that
+    /// the size reported for a `dandiset.yaml` file (computed from the
+    /// serialized document) is exactly the length of the body served by a
+    /// following `GET` request for it.  Documents that are accessed when close
+    /// to expiry are refreshed in the background.
+    version_metadata: Cache<(DandisetId, VersionId), Fetched<VersionMetadata>>,
+
+    /// The keys of `version_metadata` currently being refreshed in the
+    /// background
+    refreshing_metadata: Refreshing<(DandisetId, VersionId)>,
+
+    /// A cache of raw responses from the Archive API.  `None` means that
+    /// responses are not cached.
+    api_cache: Option<ApiCache>,
+
     /// The page size to use when making paginated requests to the DANDI
     /// Archive API.  `None` means to not specify a page size.
     page_size: Option<usize>,
+
+    /// State for prefetching the metadata of sibling assets when an asset is
+    /// accessed.  `None` means that prefetching is disabled.
+    prefetcher: Option<Arc<Prefetcher>>,
+
+    /// State for fetching details on Zarr chunks ahead of sequential access.
+    /// `None` means that readahead is disabled.
+    readahead: Option<Arc<Readahead>>,
+
+    /// The Dandiset metadata fields to expose in version metadata.  `None`
+    /// means that all fields are exposed.
+    metadata_allowlist: Option<Arc<MetadataAllowlist>>,
+
+    /// The names of S3 buckets that are not publicly readable.  Objects in
+    /// these buckets are accessed with credentials from the environment, and
+    /// downloads from them are redirected to presigned URLs.
+    private_buckets: Arc<HashSet<CompactString>>,
+
+    /// Overrides for the S3 endpoint & region used when accessing buckets
+    /// directly
+    s3settings: Arc<S3Settings>,
 }
 
 impl DandiClient {
     /// Construct a new `DandiClient` for the Archive instance with the given
-    /// base API URL
+    /// base API URL.  If `prefetch_siblings` is true, accessing an asset will
+    /// cause the metadata of the other assets in the same folder to be
+    /// fetched & cached in the background.  If `zarr_readahead` is nonzero,
+    /// sequential access to the chunks in a Zarr folder will cause the
+    /// details of the next `zarr_readahead` chunks to be fetched & cached in
+    /// the background.  If `authorization` is given, it is sent as the value
+    /// of the `Authorization` header of all requests to the Archive, allowing
+    /// access to embargoed Dandisets.  If `recorder` is given, responses from
+    /// the Archive are recorded by it.
     ///
     /// # Errors
     ///
@@ -57,19 +136,218 @@ impl DandiClient {
     pub(crate) fn new(
         api_url: HttpUrl,
         page_size: Option<usize>,
+        prefetch_siblings: bool,
+        zarr_readahead: usize,
+        authorization: Option<HeaderValue>,
+        recorder: Option<Arc<FixtureRecorder>>,
     ) -> Result<Self, BuildClientError> {
-        let inner = Client::new()?;
+        let mut headers = HeaderMap::new();
+        if let Some(mut auth) = authorization {
+            auth.set_sensitive(true);
+            headers.insert(AUTHORIZATION, auth);
+        }
+        let download_resolver = if headers.is_empty() {
+            None
+        } else {
+            Some(
+                Client::builder()
+                    .default_headers(headers.clone())
+                    .follow_redirects(false)
+                    .build()?,
+            )
+        };
+        let inner = Client::builder()
+            .default_headers(headers)
+            .recorder(recorder)
+            .build()?;
         let s3clients = CacheBuilder::new(S3CLIENT_CACHE_SIZE)
             .name("s3clients")
             .build();
+        let folder_sizes = CacheBuilder::new(FOLDER_SIZE_CACHE_SIZE)
+            .name("folder-sizes")
+            .time_to_live(FOLDER_SIZE_CACHE_TTL)
+            .build();
+        let version_metadata = CacheBuilder::new(VERSION_METADATA_CACHE_BYTES)
+            .name("version-metadata")
+            .weigher(|_, md: &Fetched<VersionMetadata>| {
+                u32::try_from(md.value.len()).unwrap_or(u32::MAX)
+            })
+            .time_to_live(cache_lifetime(VERSION_METADATA_CACHE_TTL))
+            .build();
         Ok(DandiClient {
             inner,
+            download_resolver,
             api_url,
             s3clients,
+            folder_sizes,
+            version_metadata,
+            refreshing_metadata: Refreshing::new(),
+            api_cache: None,
             page_size,
+            prefetcher: prefetch_siblings.then(|| Arc::new(Prefetcher::new())),
+            readahead: (zarr_readahead > 0).then(|| Arc::new(Readahead::new(zarr_readahead))),
+            metadata_allowlist: None,
+            private_buckets: Arc::new(HashSet::new()),
+            s3settings: Arc::new(S3Settings::default()),
         })
     }
 
+    /// Return a client for the same Archive instance with the same settings
+    /// as `self`, except that `authorization` is sent as the value of the
+    /// `Authorization` header of all requests to the Archive.
+    ///
+    /// The new client starts out with empty caches of Archive data that are
+    /// not shared with `self`, so that data only visible with the given
+    /// credentials is never served to anyone else.  Cached S3 clients are
+    /// shared, as they hold no Archive data.  Responses are not recorded.
+    ///
+    /// The new client's API response cache, if any, is given a `1/shares`
+    /// share of the capacity of `self`'s, so that the caches of up to `shares`
+    /// such clients together take up no more memory than `self`'s.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if construction of the inner `reqwest::Client` fails
+    pub(crate) fn with_authorization(
+        &self,
+        authorization: HeaderValue,
+        shares: u64,
+    ) -> Result<DandiClient, BuildClientError> {
+        let mut client = DandiClient::new(
+            self.api_url.clone(),
+            self.page_size,
+            self.prefetcher.is_some(),
+            self.readahead.as_ref().map_or(0, |r| r.count()),
+            Some(authorization),
+            None,
+        )?;
+        client.s3clients = self.s3clients.clone();
+        client.api_cache = self.api_cache.as_ref().map(|c| c.empty_share(shares));
+        client.metadata_allowlist = self.metadata_allowlist.clone();
+        client.private_buckets = Arc::clone(&self.private_buckets);
+        client.s3settings = Arc::clone(&self.s3settings);
+        Ok(client)
+    }
+
+    /// Cache up to `max_bytes` bytes of responses from the Archive API for up
+    /// to `ttl` each, so that repeated requests for the same data are served
+    /// from memory.  A `max_bytes` of zero disables caching.  If `compress` is
+    /// true, large responses are stored gzip-compressed.
+    pub(crate) fn with_api_cache(
+        mut self,
+        max_bytes: u64,
+        ttl: Duration,
+        compress: bool,
+    ) -> DandiClient {
+        self.api_cache = (max_bytes > 0).then(|| ApiCache::new(max_bytes, ttl, compress));
+        self
+    }
+
+    /// Treat the given S3 buckets as private, accessing them with credentials
+    /// from the environment
+    pub(crate) fn with_private_buckets<I>(mut self, buckets: I) -> DandiClient
+    where
+        I: IntoIterator<Item = CompactString>,
+    {
+        self.private_buckets = Arc::new(buckets.into_iter().collect());
+        self
+    }
+
+    /// Access S3 buckets using the endpoint & region overrides in `settings`
+    pub(crate) fn with_s3_settings(mut self, settings: S3Settings) -> DandiClient {
+        self.s3settings = Arc::new(settings);
+        self
+    }
+
+    /// Redact all fields of version metadata that are not in `allowlist` (if
+    /// given)
+    pub(crate) fn with_metadata_allowlist(
+        mut self,
+        allowlist: Option<MetadataAllowlist>,
+    ) -> DandiClient {
+        self.metadata_allowlist = allowlist.map(Arc::new);
+        self
+    }
+
+    /// Return the approximate total number of entries in the client's caches
+    /// of Archive data
+    pub(crate) fn cache_len(&self) -> u64 {
+        self.folder_sizes.entry_count()
+            + self.version_metadata.entry_count()
+            + self.prefetcher.as_ref().map_or(0, |p| p.cache_len())
+            + self.readahead.as_ref().map_or(0, |r| r.cache_len())
+            + self.api_cache.as_ref().map_or(0, ApiCache::cache_len)
+    }
+
+    /// Return the amount of memory used by the client's cache of Archive API
+    /// responses
+    pub(crate) fn api_cache_usage(&self) -> ApiCacheUsage {
+        self.api_cache
+            .as_ref()
+            .map(ApiCache::usage)
+            .unwrap_or_default()
+    }
+
+    /// Discard all cached Archive data.  Cached S3 clients are kept, as they
+    /// are small and expensive to reconstruct.
+    pub(crate) fn clear_caches(&self) {
+        self.folder_sizes.invalidate_all();
+        self.version_metadata.invalidate_all();
+        if let Some(ref p) = self.prefetcher {
+            p.clear();
+        }
+        if let Some(ref r) = self.readahead {
+            r.clear();
+        }
+        if let Some(ref c) = self.api_cache {
+            c.clear();
+        }
+    }
+
+    /// Return the cached `dandiset.yaml` documents for all Dandiset versions
+    pub(crate) fn snapshot_version_metadata(&self) -> Vec<VersionMetadataSnapshot> {
+        self.version_metadata
+            .iter()
+            .map(|(key, md)| VersionMetadataSnapshot {
+                dandiset_id: key.0.clone(),
+                version_id: key.1.clone(),
+                document: String::from_utf8_lossy(&md.value.0).into_owned(),
+            })
+            .collect()
+    }
+
+    /// Insert the documents in `snapshot` into the cache of version metadata,
+    /// skipping any versions that are already cached, and return the number
+    /// of documents inserted.  The documents are treated as freshly fetched.
+    pub(crate) async fn restore_version_metadata(
+        &self,
+        snapshot: Vec<VersionMetadataSnapshot>,
+    ) -> usize {
+        let mut inserted = 0;
+        for vms in snapshot {
+            let key = (vms.dandiset_id, vms.version_id);
+            if !self.version_metadata.contains_key(&key) {
+                let md = VersionMetadata(vms.document.into());
+                self.version_metadata
+                    .insert(key, Fetched::new(md, VERSION_METADATA_CACHE_TTL))
+                    .await;
+                inserted += 1;
+            }
+        }
+        inserted
+    }
+
+    /// Return the base API URL of the Archive instance
+    pub(crate) fn api_url(&self) -> &HttpUrl {
+        &self.api_url
+    }
+
+    /// Fetch information about the Archive instance, including the versions
+    /// of its API server & metadata schema
+    pub(crate) async fn get_info(&self) -> Result<ArchiveInfo, DandiError> {
+        self.get(self.get_url(["info"])).await
+    }
+
     /// Return the URL formed by appending the given path segments and a
     /// trailing slash to the path of the API base URL
     fn get_url<I>(&self, segments: I) -> HttpUrl
@@ -82,10 +360,15 @@ impl DandiClient {
         url
     }
 
-    /// Perform a `GET` request to the given URL and return the deserialized
-    /// JSON response body
+    /// Perform a `GET` request to the given URL (or look up its response in
+    /// the API cache, if enabled) and return the deserialized JSON response
+    /// body
     async fn get<T: DeserializeOwned>(&self, url: HttpUrl) -> Result<T, DandiError> {
-        self.inner.get_json(url).await.map_err(Into::into)
+        match self.api_cache {
+            Some(ref cache) => cache.get_json(&self.inner, url).await,
+            None => self.inner.get_json(url).await,
+        }
+        .map_err(Into::into)
     }
 
     /// Return a [`futures_util::Stream`] that makes paginated `GET` requests
@@ -104,7 +387,7 @@ impl DandiClient {
     /// question, one is constructed & cached.
     ///
     /// Specifically, the first `contentUrl` of the Zarr that can be parsed by
-    /// [`S3Location::parse_url()`] into a bucket, optional region, and key
+    /// [`S3Settings::parse_url()`] into a bucket, optional region, and key
     /// prefix is used to construct the `PrefixedS3Client` (with a trailing
     /// slash appended to the key prefix if one isn't already present), with
     /// the assumption that the Zarr's entries are laid out under the given key
@@ -117,7 +400,7 @@ impl DandiClient {
         let Some(S3Location {
             bucket_spec,
             mut key,
-        }) = zarr.s3location()
+        }) = zarr.s3location(&self.s3settings)
         else {
             return Err(DandiError::ZarrToS3Error {
                 asset_id: zarr.asset_id.clone(),
@@ -131,17 +414,7 @@ impl DandiClient {
             asset_id: zarr.asset_id.clone(),
             source: ZarrToS3Error::BadS3Key(source),
         })?;
-        match self
-            .s3clients
-            .try_get_with_by_ref(
-                &bucket_spec,
-                // Box the future passed to moka in order to minimize the size
-                // of the moka future (cf.
-                // <https://github.com/moka-rs/moka/issues/212>):
-                Box::pin(async { bucket_spec.clone().into_s3client().await.map(Arc::new) }),
-            )
-            .await
-        {
+        match self.get_s3client(&bucket_spec).await {
             Ok(client) => Ok(client.with_prefix(prefix)),
             Err(source) => Err(DandiError::ZarrToS3Error {
                 asset_id: zarr.asset_id.clone(),
@@ -153,6 +426,66 @@ impl DandiClient {
         }
     }
 
+    /// Return the [`S3Client`] for the given bucket, constructing & caching
+    /// one if necessary.  Clients for private buckets use credentials from
+    /// the environment.
+    async fn get_s3client(
+        &self,
+        bucket_spec: &BucketSpec,
+    ) -> Result<Arc<S3Client>, Arc<GetBucketRegionError>> {
+        let authenticated = self.private_buckets.contains(&bucket_spec.bucket);
+        self.s3clients
+            .try_get_with_by_ref(
+                bucket_spec,
+                // Box the future passed to moka in order to minimize the size
+                // of the moka future (cf.
+                // <https://github.com/moka-rs/moka/issues/212>):
+                Box::pin(async {
+                    bucket_spec
+                        .clone()
+                        .into_s3client(authenticated, &self.s3settings)
+                        .await
+                        .map(Arc::new)
+                }),
+            )
+            .await
+    }
+
+    /// If `url` is the URL of an object in one of the private S3 buckets,
+    /// return the object's location
+    pub(crate) fn private_location(&self, url: &HttpUrl) -> Option<S3Location> {
+        if self.private_buckets.is_empty() {
+            return None;
+        }
+        self.s3settings
+            .parse_url(url.as_url())
+            .ok()
+            .filter(|loc| self.private_buckets.contains(&loc.bucket_spec.bucket))
+    }
+
+    /// Return a presigned URL for downloading the object at the given
+    /// location in a private S3 bucket
+    pub(crate) async fn presign_download(&self, loc: &S3Location) -> Result<HttpUrl, DandiError> {
+        let client = self
+            .get_s3client(&loc.bucket_spec)
+            .await
+            .map_err(|source| DandiError::LocateBucket {
+                bucket: loc.bucket_spec.bucket.clone(),
+                source,
+            })?;
+        Ok(client.presign_get(&loc.key, PRESIGNED_URL_TTL).await?)
+    }
+
+    /// Return the URL that the Archive download URL `url` of an embargoed
+    /// asset redirects authenticated requests to.  If no API token was
+    /// supplied, `url` is returned unchanged.
+    pub(crate) async fn resolve_download(&self, url: &HttpUrl) -> Result<HttpUrl, DandiError> {
+        match self.download_resolver {
+            Some(ref client) => Ok(client.get_redirect(url.clone()).await?),
+            None => Ok(url.clone()),
+        }
+    }
+
     /// Return a [`futures_util::Stream`] that yields a `Dandiset` for each
     /// Dandiset on the Archive instance
     pub(crate) fn get_all_dandisets(
@@ -168,6 +501,11 @@ impl DandiClient {
         DandisetEndpoint::new(self, dandiset_id)
     }
 
+    /// Return the API URL for the given Dandiset
+    pub(crate) fn dandiset_url(&self, dandiset_id: &DandisetId) -> HttpUrl {
+        self.get_url(["dandisets", dandiset_id.as_ref()])
+    }
+
     /// Return the URL for the metadata for the given version of the given
     /// Dandiset
     fn version_metadata_url(&self, dandiset_id: &DandisetId, version_id: &VersionId) -> HttpUrl {
@@ -180,6 +518,19 @@ impl DandiClient {
     }
 }
 
+/// A cached `dandiset.yaml` document for a Dandiset version, as transferred
+/// between instances when warming caches
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct VersionMetadataSnapshot {
+    dandiset_id: DandisetId,
+    version_id: VersionId,
+    document: String,
+}
+
+/// A key in [`DandiClient`]'s cache of asset folder sizes: a Dandiset ID, a
+/// version ID, and the path to a folder within that version
+type FolderSizeKey = (DandisetId, VersionId, PureDirPath);
+
 /// An object for making requests relating to a specific Dandiset
 #[derive(Clone, Debug)]
 pub(crate) struct DandisetEndpoint<'a> {
@@ -208,10 +559,7 @@ impl<'a> DandisetEndpoint<'a> {
     /// Retrieve information about the Dandiset
     pub(crate) async fn get(&self) -> Result<Dandiset, DandiError> {
         self.client
-            .get::<RawDandiset>(
-                self.client
-                    .get_url(["dandisets", self.dandiset_id.as_ref()]),
-            )
+            .get::<RawDandiset>(self.client.dandiset_url(&self.dandiset_id))
             .await
             .map(|ds| ds.with_metadata_urls(self.client))
     }
@@ -259,9 +607,16 @@ impl<'a> VersionEndpoint<'a> {
         }
     }
 
-    /// Retrieve information about the version
+    /// Return the ID of the version this instance operates on
+    pub(crate) fn version_id(&self) -> &VersionId {
+        &self.version_id
+    }
+
+    /// Retrieve information about the version.  The version's serialized
+    /// metadata is cached for use by [`VersionEndpoint::get_metadata()`].
     pub(crate) async fn get(&self) -> Result<VersionInfo, DandiError> {
-        self.client
+        let info = self
+            .client
             .get::<RawVersionInfo>(self.client.get_url([
                 "dandisets",
                 self.dandiset_id.as_ref(),
@@ -270,24 +625,111 @@ impl<'a> VersionEndpoint<'a> {
                 "info",
             ]))
             .await
-            .map(|v| v.with_metadata_url(self.metadata_url()))
+            .map(|v| {
+                v.with_metadata_url(
+                    self.metadata_url(),
+                    self.client.metadata_allowlist.as_deref(),
+                )
+            })?;
+        self.client
+            .version_metadata
+            .insert(
+                self.metadata_key(),
+                Fetched::new(info.metadata.clone(), VERSION_METADATA_CACHE_TTL),
+            )
+            .await;
+        Ok(info)
     }
 
-    /// Retrieve the version's metadata as serialized YAML
+    /// Retrieve the version's metadata as serialized YAML, using the cached
+    /// serialization if there is one.  If the cached serialization is close to
+    /// expiry or is stale, it is refreshed in the background.  Concurrent
+    /// requests for uncached metadata share a single fetch.
     pub(crate) async fn get_metadata(&self) -> Result<VersionMetadata, DandiError> {
-        self.client
-            .get::<VersionMetadata>(self.metadata_url())
+        let key = self.metadata_key();
+        let fetch = async {
+            self.fetch_metadata()
+                .await
+                .map(|md| Fetched::new(md, VERSION_METADATA_CACHE_TTL))
+        };
+        let md = self
+            .client
+            .version_metadata
+            .try_get_with_by_ref(&key, fetch)
             .await
-            .map_err(Into::into)
+            .map_err(DandiError::Shared)?;
+        if md.needs_refresh() {
+            if md.is_stale() {
+                tracing::debug!(dandiset_id = %key.0, version_id = %key.1, "Serving stale version metadata while refreshing");
+            }
+            self.refresh_metadata();
+        }
+        Ok(md.value)
+    }
+
+    /// Fetch the version's metadata from the Archive and serialize it as YAML
+    async fn fetch_metadata(&self) -> Result<VersionMetadata, DandiError> {
+        let mut data = self
+            .client
+            .get::<serde_json::Value>(self.metadata_url())
+            .await?;
+        let allowlist = self.client.metadata_allowlist.clone();
+        Ok(run_blocking(move || {
+            if let Some(allowlist) = allowlist {
+                allowlist.apply(&mut data);
+            }
+            VersionMetadata::from_json(data)
+        })
+        .await)
+    }
+
+    /// Spawn a background task for fetching the version's metadata and
+    /// replacing the cached serialization with it.  Nothing is done if the
+    /// metadata is already being refreshed.
+    fn refresh_metadata(&self) {
+        let key = self.metadata_key();
+        let Some(guard) = self.client.refreshing_metadata.start(key.clone()) else {
+            return;
+        };
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            let (dandiset_id, version_id) = key.clone();
+            let endpoint = client.dandiset(dandiset_id).version(version_id);
+            match endpoint.fetch_metadata().await {
+                Ok(md) => {
+                    client
+                        .version_metadata
+                        .insert(key, Fetched::new(md, VERSION_METADATA_CACHE_TTL))
+                        .await;
+                }
+                Err(e) => {
+                    tracing::debug!(error = ?e, dandiset_id = %key.0, version_id = %key.1, "Failed to refresh version metadata");
+                }
+            }
+        });
+    }
+
+    /// Return the key for the version in [`DandiClient`]'s cache of
+    /// serialized metadata
+    fn metadata_key(&self) -> (DandisetId, VersionId) {
+        (self.dandiset_id.clone(), self.version_id.clone())
     }
 
     /// Get details on the resource at the given `path` in the version's file
     /// hierarchy, treating Zarrs as directories of their entries
     ///
     /// Although `path` is a `PurePath`, the resulting resource may be a
-    /// collection.
+    /// collection.  If the resource is a folder whose size is not cached, the
+    /// size is looked up in the listing of the folder's parent.
     pub(crate) async fn get_resource(&self, path: &PurePath) -> Result<DandiResource, DandiError> {
-        self.get_resource_with_s3(path).await.map(Into::into)
+        match self.get_resource_with_s3(path).await? {
+            DandiResourceWithS3::Folder(mut folder) if folder.size.is_none() => {
+                folder.size = self.lookup_folder_size(&folder.path).await?;
+                Ok(DandiResource::Folder(folder))
+            }
+            r => Ok(r.into()),
+        }
     }
 
     /// Get details on the resource at the given `path` in the version's file
@@ -301,24 +743,7 @@ impl<'a> VersionEndpoint<'a> {
         path: &PurePath,
     ) -> Result<DandiResourceWithChildren, DandiError> {
         match self.get_resource_with_s3(path).await? {
-            DandiResourceWithS3::Folder(folder) => {
-                let mut children = Vec::new();
-                let mut stream = self.get_folder_entries(&folder);
-                while let Some(child) = stream.try_next().await? {
-                    let child = match child {
-                        FolderEntry::Folder(subf) => DandiResource::Folder(subf),
-                        FolderEntry::Asset { id, path } => match self.get_asset_by_id(&id).await {
-                            Ok(asset) => DandiResource::Asset(asset),
-                            Err(DandiError::Http(HttpError::NotFound { .. })) => {
-                                return Err(DandiError::DisappearingAsset { asset_id: id, path })
-                            }
-                            Err(e) => return Err(e),
-                        },
-                    };
-                    children.push(child);
-                }
-                Ok(DandiResourceWithChildren::Folder { folder, children })
-            }
+            DandiResourceWithS3::Folder(folder) => self.get_folder_with_children(folder).await,
             DandiResourceWithS3::Asset(Asset::Blob(r)) => Ok(DandiResourceWithChildren::Blob(r)),
             DandiResourceWithS3::Asset(Asset::Zarr(zarr)) => {
                 let s3 = self.client.get_s3client_for_zarr(&zarr).await?;
@@ -341,6 +766,71 @@ impl<'a> VersionEndpoint<'a> {
         }
     }
 
+    /// Get details on the resource at the given `path` in the version's file
+    /// hierarchy, treating Zarrs as non-collections.  Paths beneath Zarr
+    /// assets are not found.
+    pub(crate) async fn get_opaque_resource(
+        &self,
+        path: &PurePath,
+    ) -> Result<DandiResource, DandiError> {
+        self.get_item_path(path).await.map(Into::into)
+    }
+
+    /// Get details on the resource at the given `path` in the version's file
+    /// hierarchy (treating Zarrs as non-collections) along with its immediate
+    /// child resources (if any).  Zarr assets are returned without any
+    /// children, and paths beneath Zarr assets are not found.
+    pub(crate) async fn get_opaque_resource_with_children(
+        &self,
+        path: &PurePath,
+    ) -> Result<DandiResourceWithChildren, DandiError> {
+        match self.get_item_path(path).await? {
+            AtAssetPath::Folder(folder) => self.get_folder_with_children(folder).await,
+            AtAssetPath::Asset(Asset::Blob(r)) => Ok(DandiResourceWithChildren::Blob(r)),
+            AtAssetPath::Asset(Asset::Zarr(zarr)) => Ok(DandiResourceWithChildren::Zarr {
+                zarr,
+                children: Vec::new(),
+            }),
+        }
+    }
+
+    /// Fetch the immediate child resources of the given asset folder,
+    /// recording the folder's total size along the way
+    async fn get_folder_with_children(
+        &self,
+        mut folder: AssetFolder,
+    ) -> Result<DandiResourceWithChildren, DandiError> {
+        let mut children = Vec::new();
+        let mut size = 0i64;
+        let mut complete = true;
+        let mut stream = self.get_folder_entries(&folder);
+        while let Some(child) = stream.try_next().await? {
+            let child = match child {
+                FolderEntry::Folder(subf) => {
+                    self.remember_folder_size(&subf).await;
+                    size = size.saturating_add(subf.size.unwrap_or_default());
+                    DandiResource::Folder(subf)
+                }
+                FolderEntry::Asset { id, path } => match self.get_listed_asset(id, path).await {
+                    Ok(asset) => {
+                        size = size.saturating_add(asset.size());
+                        DandiResource::Asset(asset)
+                    }
+                    Err(e) => {
+                        complete = false;
+                        DandiResource::Unavailable(e)
+                    }
+                },
+            };
+            children.push(child);
+        }
+        if complete {
+            folder.size = Some(size);
+            self.remember_folder_size(&folder).await;
+        }
+        Ok(DandiResourceWithChildren::Folder { folder, children })
+    }
+
     /// Return a [`futures_util::Stream`] that yields the resources at the root
     /// of the version's file hierarchy
     pub(crate) fn get_root_children(
@@ -349,14 +839,14 @@ impl<'a> VersionEndpoint<'a> {
         self.get_entries_under_path(None)
             .and_then(move |entry| async move {
                 match entry {
-                    FolderEntry::Folder(subf) => Ok(DandiResource::Folder(subf)),
-                    FolderEntry::Asset { id, path } => match self.get_asset_by_id(&id).await {
-                        Ok(asset) => Ok(DandiResource::Asset(asset)),
-                        Err(DandiError::Http(HttpError::NotFound { .. })) => {
-                            Err(DandiError::DisappearingAsset { asset_id: id, path })
-                        }
-                        Err(e) => Err(e),
-                    },
+                    FolderEntry::Folder(subf) => {
+                        self.remember_folder_size(&subf).await;
+                        Ok(DandiResource::Folder(subf))
+                    }
+                    FolderEntry::Asset { id, path } => Ok(self
+                        .get_listed_asset(id, path)
+                        .await
+                        .map_or_else(DandiResource::Unavailable, DandiResource::Asset)),
                 }
             })
     }
@@ -393,8 +883,28 @@ impl<'a> VersionEndpoint<'a> {
                 }
                 AtAssetPath::Asset(Asset::Zarr(zarr)) => {
                     let s3 = self.client.get_s3client_for_zarr(&zarr).await?;
-                    return match s3.get_path(&entry_path).await? {
-                        Some(entry) => Ok(zarr.make_resource(entry).with_s3(s3)),
+                    let entry = match self.client.readahead {
+                        Some(ref ra) => match ra.get_entry(&zarr.asset_id, &entry_path).await {
+                            Some(entry) => Some(entry),
+                            None => s3.get_path(&entry_path).await?,
+                        },
+                        None => s3.get_path(&entry_path).await?,
+                    };
+                    return match entry {
+                        Some(S3Entry::Folder(folder)) => Ok(DandiResourceWithS3::ZarrFolder {
+                            folder: zarr.make_folder(folder),
+                            s3,
+                        }),
+                        Some(S3Entry::Object(obj)) => {
+                            if let Some(ref ra) = self.client.readahead {
+                                ra.observe(&zarr.asset_id, &entry_path, &s3).await;
+                            }
+                            Ok(DandiResourceWithS3::ZarrEntry(zarr.make_entry(obj)))
+                        }
+                        Some(S3Entry::BadObject(_)) => Err(DandiError::BadZarrEntry {
+                            zarr_path,
+                            entry_path,
+                        }),
                         None => Err(DandiError::ZarrEntryNotFound {
                             zarr_path,
                             entry_path,
@@ -403,7 +913,36 @@ impl<'a> VersionEndpoint<'a> {
                 }
             }
         }
-        self.get_path(path).await.map(Into::into)
+        self.get_item_path(path).await.map(Into::into)
+    }
+
+    /// If `path` is the path of a Zarr asset in this version or of a resource
+    /// within one, return the Zarr asset along with the path within it (if
+    /// any).  Returns `None` if `path` is not in a Zarr.
+    ///
+    /// Zarr assets are located using the same algorithm as
+    /// [`VersionEndpoint::get_resource_with_s3()`], but the Zarr itself is not
+    /// queried.
+    pub(crate) async fn locate_zarr(
+        &self,
+        path: &PurePath,
+    ) -> Result<Option<(ZarrAsset, Option<PurePath>)>, DandiError> {
+        for (zarr_path, entry_path) in path.split_zarr_candidates() {
+            match self.get_path(&zarr_path).await? {
+                AtAssetPath::Folder(_) => continue,
+                AtAssetPath::Asset(Asset::Blob(_)) => {
+                    return Err(DandiError::PathUnderBlob {
+                        path: path.clone(),
+                        blob_path: zarr_path,
+                    })
+                }
+                AtAssetPath::Asset(Asset::Zarr(zarr)) => return Ok(Some((zarr, Some(entry_path)))),
+            }
+        }
+        match self.get_path(path).await? {
+            AtAssetPath::Asset(Asset::Zarr(zarr)) => Ok(Some((zarr, None))),
+            _ => Ok(None),
+        }
     }
 
     /// Return the URL for the version's metadata
@@ -414,7 +953,7 @@ impl<'a> VersionEndpoint<'a> {
 
     /// Retrieve information on the asset in this version with the given asset
     /// ID
-    async fn get_asset_by_id(&self, id: &str) -> Result<Asset, DandiError> {
+    pub(crate) async fn get_asset_by_id(&self, id: &str) -> Result<Asset, DandiError> {
         self.client
             .get::<RawAsset>(self.client.get_url([
                 "dandisets",
@@ -430,6 +969,20 @@ impl<'a> VersionEndpoint<'a> {
             .map_err(Into::into)
     }
 
+    /// Return a [`futures_util::Stream`] that yields a [`ListedAsset`] for
+    /// every asset in the version, in order of path
+    pub(crate) fn get_listed_assets(&self) -> impl Stream<Item = Result<ListedAsset, DandiError>> {
+        let mut url = self.client.get_url([
+            "dandisets",
+            self.dandiset_id.as_ref(),
+            "versions",
+            self.version_id.as_ref(),
+            "assets",
+        ]);
+        url.append_query_param("order", "path");
+        self.client.paginate(url)
+    }
+
     /// Return the URL for the metadata of the asset in this version with the
     /// given asset ID
     fn asset_metadata_url(&self, asset_id: &str) -> HttpUrl {
@@ -451,6 +1004,9 @@ impl<'a> VersionEndpoint<'a> {
     /// match is found, that asset is returned.  If an asset is found whose
     /// path is a descendant of `path`, then `path` is a folder.
     async fn get_path(&self, path: &PurePath) -> Result<AtAssetPath, DandiError> {
+        if let Some(asset) = self.prefetched_asset(path).await {
+            return Ok(AtAssetPath::Asset(asset));
+        }
         let mut url = self.client.get_url([
             "dandisets",
             self.dandiset_id.as_ref(),
@@ -467,7 +1023,11 @@ impl<'a> VersionEndpoint<'a> {
             if &asset.path == path {
                 return Ok(AtAssetPath::Asset(asset.try_into_asset(self)?));
             } else if asset.path.is_strictly_under(&dirpath) {
-                return Ok(AtAssetPath::Folder(AssetFolder { path: dirpath }));
+                let size = self.cached_folder_size(&dirpath).await;
+                return Ok(AtAssetPath::Folder(AssetFolder {
+                    path: dirpath,
+                    size,
+                }));
             } else if asset.path.as_ref() > dirpath.as_ref() {
                 break;
             }
@@ -475,6 +1035,169 @@ impl<'a> VersionEndpoint<'a> {
         Err(DandiError::PathNotFound { path: path.clone() })
     }
 
+    /// Return the key under which the size of the folder at `path` in this
+    /// version is stored in the client's folder size cache
+    fn folder_size_key(&self, path: &PureDirPath) -> FolderSizeKey {
+        (
+            self.dandiset_id.clone(),
+            self.version_id.clone(),
+            path.clone(),
+        )
+    }
+
+    /// Store the size of `folder` (if known) in the client's folder size cache
+    async fn remember_folder_size(&self, folder: &AssetFolder) {
+        if let Some(size) = folder.size {
+            self.client
+                .folder_sizes
+                .insert(self.folder_size_key(&folder.path), size)
+                .await;
+        }
+    }
+
+    /// Determine the size of the folder at `path` from the listing of its
+    /// parent folder (or of the root of the file hierarchy), caching the sizes
+    /// of all of the folder's sibling folders along the way
+    async fn lookup_folder_size(&self, path: &PureDirPath) -> Result<Option<i64>, DandiError> {
+        let parent = path.parent();
+        let mut size = None;
+        let mut stream = self.get_entries_under_path(parent.as_ref());
+        while let Some(entry) = stream.try_next().await? {
+            if let FolderEntry::Folder(subf) = entry {
+                self.remember_folder_size(&subf).await;
+                if &subf.path == path {
+                    size = subf.size;
+                }
+            }
+        }
+        Ok(size)
+    }
+
+    /// Retrieve the size of the folder at `path` from the client's folder size
+    /// cache, if present
+    async fn cached_folder_size(&self, path: &PureDirPath) -> Option<i64> {
+        self.client
+            .folder_sizes
+            .get(&self.folder_size_key(path))
+            .await
+    }
+
+    /// Like [`VersionEndpoint::get_path()`], but if the resource found is an
+    /// asset and prefetching is enabled, the metadata of the asset's siblings
+    /// is also fetched in the background
+    async fn get_item_path(&self, path: &PurePath) -> Result<AtAssetPath, DandiError> {
+        let r = self.get_path(path).await?;
+        if matches!(r, AtAssetPath::Asset(_)) {
+            self.prefetch_siblings(path);
+        }
+        Ok(r)
+    }
+
+    /// Retrieve information on the asset with ID `id` that was returned at
+    /// `path` in a folder listing, using a prefetched record if available.
+    ///
+    /// If the asset's information cannot be retrieved, the error is logged,
+    /// and an `UnavailableResource` is returned for the asset so that the
+    /// rest of the listing can still be served.
+    async fn get_listed_asset(
+        &self,
+        id: String,
+        path: PurePath,
+    ) -> Result<Asset, UnavailableResource> {
+        if let Some(asset) = self.prefetched_asset(&path).await {
+            if asset.asset_id() == id {
+                return Ok(asset);
+            }
+        }
+        let e = match self.get_asset_by_id(&id).await {
+            Ok(asset) => return Ok(asset),
+            Err(DandiError::Http(HttpError::NotFound { .. })) => DandiError::DisappearingAsset {
+                asset_id: id,
+                path: path.clone(),
+            },
+            Err(e) => e,
+        };
+        let class = e.class();
+        tracing::warn!(
+            error = ?anyhow::Error::from(e),
+            "Failed to retrieve details of asset in folder listing",
+        );
+        Err(UnavailableResource { path, class })
+    }
+
+    /// Return the key under which the asset at `path` in this version is
+    /// stored in the prefetcher's asset cache
+    fn asset_path_key(&self, path: &PurePath) -> AssetPathKey {
+        (
+            self.dandiset_id.clone(),
+            self.version_id.clone(),
+            path.clone(),
+        )
+    }
+
+    /// Retrieve the prefetched record for the asset at `path`, if prefetching
+    /// is enabled and the asset has been prefetched
+    async fn prefetched_asset(&self, path: &PurePath) -> Option<Asset> {
+        let prefetcher = self.client.prefetcher.as_ref()?;
+        prefetcher.get_asset(&self.asset_path_key(path)).await
+    }
+
+    /// If prefetching is enabled, spawn a background task for fetching the
+    /// listing of the folder containing `path` along with the records for the
+    /// assets therein.  Nothing is done if the folder was already prefetched
+    /// recently or if too many prefetches are already running.
+    fn prefetch_siblings(&self, path: &PurePath) {
+        let Some(prefetcher) = self.client.prefetcher.as_ref() else {
+            return;
+        };
+        let Some(permit) = prefetcher.try_start() else {
+            tracing::debug!(%path, "Too many prefetches running; not prefetching siblings");
+            return;
+        };
+        let client = self.client.clone();
+        let dandiset_id = self.dandiset_id.clone();
+        let version_id = self.version_id.clone();
+        let parent = path.parent();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let endpoint = client.dandiset(dandiset_id).version(version_id);
+            if let Err(e) = endpoint.prefetch_listing(parent.as_ref()).await {
+                tracing::debug!(error = ?e, folder = ?parent, "Failed to prefetch folder listing");
+            }
+        });
+    }
+
+    /// Fetch the listing of the folder at `path` (or of the root of the file
+    /// hierarchy if `path` is `None`) and cache the sizes of its subfolders
+    /// and the records of (up to [`PREFETCH_MAX_ENTRIES`] of) its assets
+    async fn prefetch_listing(&self, path: Option<&PureDirPath>) -> Result<(), DandiError> {
+        let Some(prefetcher) = self.client.prefetcher.as_ref() else {
+            return Ok(());
+        };
+        let key = (
+            self.dandiset_id.clone(),
+            self.version_id.clone(),
+            path.cloned(),
+        );
+        if !prefetcher.claim_listing(key).await {
+            return Ok(());
+        }
+        let mut stream = self.get_entries_under_path(path).take(PREFETCH_MAX_ENTRIES);
+        while let Some(entry) = stream.try_next().await? {
+            match entry {
+                FolderEntry::Folder(subf) => self.remember_folder_size(&subf).await,
+                FolderEntry::Asset { id, path } => {
+                    let key = self.asset_path_key(&path);
+                    if prefetcher.get_asset(&key).await.is_none() {
+                        let asset = self.get_asset_by_id(&id).await?;
+                        prefetcher.put_asset(key, asset).await;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Return a [`futures_util::Stream`] that yields a [`FolderEntry`] object
     /// for each immediate child resource (both assets and folders) of the
     /// folder at `path` in the version's file hierarchy, treating Zarrs as
@@ -517,6 +1240,11 @@ pub(crate) enum DandiError {
         zarr_path: PurePath,
         entry_path: PurePath,
     },
+    #[error("S3 details for entry {entry_path:?} in Zarr {zarr_path:?} are invalid")]
+    BadZarrEntry {
+        zarr_path: PurePath,
+        entry_path: PurePath,
+    },
     #[error("folder listing included asset ID {asset_id} at path {path:?}, but request to asset returned 404")]
     DisappearingAsset { asset_id: String, path: PurePath },
     #[error("failed to acquire S3 client for Zarr with asset ID {asset_id}")]
@@ -528,6 +1256,13 @@ pub(crate) enum DandiError {
     AssetType(#[from] AssetTypeError),
     #[error(transparent)]
     S3(#[from] S3Error),
+    #[error("failed to determine region for S3 bucket {bucket:?}")]
+    LocateBucket {
+        bucket: CompactString,
+        source: Arc<GetBucketRegionError>,
+    },
+    #[error(transparent)]
+    Shared(Arc<DandiError>),
 }
 
 impl DandiError {
@@ -538,10 +1273,14 @@ impl DandiError {
             DandiError::PathNotFound { .. }
             | DandiError::PathUnderBlob { .. }
             | DandiError::ZarrEntryNotFound { .. } => ErrorClass::NotFound,
-            DandiError::DisappearingAsset { .. } => ErrorClass::BadGateway,
+            DandiError::BadZarrEntry { .. } | DandiError::DisappearingAsset { .. } => {
+                ErrorClass::BadGateway
+            }
             DandiError::ZarrToS3Error { source, .. } => source.class(),
             DandiError::AssetType(_) => ErrorClass::BadGateway,
             DandiError::S3(source) => source.class(),
+            DandiError::LocateBucket { source, .. } => source.class(),
+            DandiError::Shared(source) => source.class(),
         }
     }
 }
@@ -580,3 +1319,144 @@ impl ZarrToS3Error {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    fn count_info_requests(requests: &[wiremock::Request]) -> usize {
+        requests
+            .iter()
+            .filter(|r| r.url.path().ends_with("/info/"))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn metadata_cached_from_info() {
+        let mock_archive = testutils::make_mock_archive(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/testdata/stubs"
+        ))
+        .await;
+        let api_url = format!("{}/api", mock_archive.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let client = DandiClient::new(api_url, Some(25), false, 0, None, None).unwrap();
+        let endpoint = client
+            .dandiset("000001".parse::<DandisetId>().unwrap())
+            .version(VersionId::Draft);
+        let info = endpoint.get().await.unwrap();
+        let md = endpoint.get_metadata().await.unwrap();
+        assert_eq!(md, info.metadata);
+        let requests = mock_archive.received_requests().await.unwrap();
+        assert!(!requests
+            .iter()
+            .any(|r| r.url.path() == "/api/dandisets/000001/versions/draft/"));
+    }
+
+    #[tokio::test]
+    async fn prefetch_listing() {
+        let mock_archive = testutils::make_mock_archive(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/testdata/stubs"
+        ))
+        .await;
+        let api_url = format!("{}/api", mock_archive.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let client = DandiClient::new(api_url, Some(25), true, 0, None, None).unwrap();
+        let endpoint = client
+            .dandiset("000002".parse::<DandisetId>().unwrap())
+            .version(VersionId::Draft);
+        let folder = "fRLy/".parse::<PureDirPath>().unwrap();
+        endpoint.prefetch_listing(Some(&folder)).await.unwrap();
+        let requests = mock_archive.received_requests().await.unwrap();
+        let prefetched = count_info_requests(&requests);
+        assert!(prefetched > 0);
+
+        let zarr = endpoint
+            .prefetched_asset(&"fRLy/zfa6zGT.zarr".parse::<PurePath>().unwrap())
+            .await;
+        assert_matches!(zarr, Some(Asset::Zarr(_)));
+
+        // Listing the folder now should not require fetching any asset
+        // records:
+        let r = endpoint
+            .get_folder_with_children(AssetFolder {
+                path: folder.clone(),
+                size: None,
+            })
+            .await
+            .unwrap();
+        assert_matches!(r, DandiResourceWithChildren::Folder { .. });
+        let requests = mock_archive.received_requests().await.unwrap();
+        assert_eq!(count_info_requests(&requests), prefetched);
+
+        // Prefetching the same folder again does nothing:
+        endpoint.prefetch_listing(Some(&folder)).await.unwrap();
+        let requests = mock_archive.received_requests().await.unwrap();
+        assert_eq!(count_info_requests(&requests), prefetched);
+    }
+
+    #[test]
+    fn private_location() {
+        let client = DandiClient::new(
+            "https://api.example.com/api".parse().unwrap(),
+            None,
+            false,
+            0,
+            None,
+            None,
+        )
+        .unwrap()
+        .with_private_buckets(["embargoed".into()]);
+        let url = "https://embargoed.s3.amazonaws.com/blobs/abc/def/abcdef"
+            .parse::<HttpUrl>()
+            .unwrap();
+        let loc = client.private_location(&url).unwrap();
+        assert_eq!(loc.bucket_spec.bucket, "embargoed");
+        assert_eq!(loc.key, "blobs/abc/def/abcdef");
+        let url = "https://dandiarchive.s3.amazonaws.com/blobs/abc/def/abcdef"
+            .parse::<HttpUrl>()
+            .unwrap();
+        assert_eq!(client.private_location(&url), None);
+    }
+
+    #[tokio::test]
+    async fn resolve_embargoed_download() {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let presigned =
+            "https://embargoed.s3.amazonaws.com/blobs/abc/def/abcdef?X-Amz-Signature=1234";
+        let mock_archive = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/assets/0123/download/"))
+            .and(header("Authorization", "token hunter2"))
+            .respond_with(ResponseTemplate::new(302).insert_header("Location", presigned))
+            .mount(&mock_archive)
+            .await;
+        let api_url = format!("{}/api", mock_archive.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let url = format!("{}/api/assets/0123/download/", mock_archive.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let anonymous = DandiClient::new(api_url.clone(), None, false, 0, None, None).unwrap();
+        assert_eq!(anonymous.resolve_download(&url).await.unwrap(), url);
+        let client = DandiClient::new(
+            api_url,
+            None,
+            false,
+            0,
+            Some(HeaderValue::from_static("token hunter2")),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            client.resolve_download(&url).await.unwrap().as_str(),
+            presigned
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use super::{Asset, DandisetId, VersionId};
+use crate::consts::{PREFETCH_ASSET_CACHE_SIZE, PREFETCH_CACHE_TTL, PREFETCH_CONCURRENCY};
+use crate::paths::{PureDirPath, PurePath};
+use moka::future::{Cache, CacheBuilder};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// A key identifying an asset within a Dandiset version by its path
+pub(super) type AssetPathKey = (DandisetId, VersionId, PurePath);
+
+/// A key identifying a folder within a Dandiset version by its path; `None`
+/// denotes the root of the version's file hierarchy
+pub(super) type ListingKey = (DandisetId, VersionId, Option<PureDirPath>);
+
+/// State for speculatively fetching the metadata of the siblings of assets
+/// that clients access, on the assumption that clients (especially mounted
+/// filesystems) will soon access those siblings as well
+#[derive(Debug)]
+pub(super) struct Prefetcher {
+    /// Limits the number of prefetches that may run at once
+    permits: Arc<Semaphore>,
+
+    /// The folder listings that have been prefetched recently, so that the
+    /// same folder is not prefetched repeatedly
+    listed: Cache<ListingKey, ()>,
+
+    /// Prefetched asset records
+    assets: Cache<AssetPathKey, Asset>,
+}
+
+impl Prefetcher {
+    pub(super) fn new() -> Prefetcher {
+        Prefetcher {
+            permits: Arc::new(Semaphore::new(PREFETCH_CONCURRENCY)),
+            listed: CacheBuilder::new(PREFETCH_ASSET_CACHE_SIZE)
+                .name("prefetched-listings")
+                .time_to_live(PREFETCH_CACHE_TTL)
+                .build(),
+            assets: CacheBuilder::new(PREFETCH_ASSET_CACHE_SIZE)
+                .name("prefetched-assets")
+                .time_to_live(PREFETCH_CACHE_TTL)
+                .build(),
+        }
+    }
+
+    /// Reserve a slot for running a prefetch.  Returns `None` if the maximum
+    /// number of prefetches are already running.
+    pub(super) fn try_start(&self) -> Option<OwnedSemaphorePermit> {
+        Arc::clone(&self.permits).try_acquire_owned().ok()
+    }
+
+    /// Mark the folder listing identified by `key` as prefetched.  Returns
+    /// `false` if it was already prefetched recently, in which case it should
+    /// not be prefetched again.
+    pub(super) async fn claim_listing(&self, key: ListingKey) -> bool {
+        self.listed.entry(key).or_insert(()).await.is_fresh()
+    }
+
+    /// Retrieve a prefetched asset record
+    pub(super) async fn get_asset(&self, key: &AssetPathKey) -> Option<Asset> {
+        self.assets.get(key).await
+    }
+
+    /// Store a prefetched asset record
+    pub(super) async fn put_asset(&self, key: AssetPathKey, asset: Asset) {
+        self.assets.insert(key, asset).await;
+    }
+
+    /// Return the approximate number of entries in the prefetch caches
+    pub(super) fn cache_len(&self) -> u64 {
+        self.listed.entry_count() + self.assets.entry_count()
+    }
+
+    /// Discard all prefetched data
+    pub(super) fn clear(&self) {
+        self.listed.invalidate_all();
+        self.assets.invalidate_all();
+    }
+}
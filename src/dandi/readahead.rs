@@ -0,0 +1,214 @@
+use crate::consts::{READAHEAD_CACHE_SIZE, READAHEAD_CACHE_TTL, READAHEAD_CONCURRENCY};
+use crate::paths::{PureDirPath, PurePath};
+use crate::s3::{PrefixedS3Client, S3Entry, S3Object};
+use itertools::Itertools;
+use moka::future::{Cache, CacheBuilder};
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// A key identifying an entry within a Zarr: the Zarr's asset ID and the
+/// entry's path within the Zarr
+type ZarrEntryKey = (String, PurePath);
+
+/// A key identifying a folder within a Zarr: the Zarr's asset ID and the
+/// folder's path within the Zarr, with `None` denoting the root of the Zarr
+type ZarrFolderKey = (String, Option<PureDirPath>);
+
+/// State for detecting sequential access to the chunks in a Zarr folder and
+/// fetching the S3 details of the following chunks ahead of time
+#[derive(Debug)]
+pub(super) struct Readahead {
+    /// The number of chunks past the most recently accessed one to fetch
+    count: usize,
+
+    /// Limits the number of readahead fetches that may run at once
+    permits: Arc<Semaphore>,
+
+    /// The index of the most recently accessed chunk in each Zarr folder
+    last_access: Cache<ZarrFolderKey, ChunkIndex>,
+
+    /// Details on chunks fetched ahead of time
+    entries: Cache<ZarrEntryKey, S3Object>,
+}
+
+impl Readahead {
+    /// Construct a new `Readahead` that fetches details on the `count` chunks
+    /// following each sequentially-accessed chunk
+    pub(super) fn new(count: usize) -> Readahead {
+        Readahead {
+            count,
+            permits: Arc::new(Semaphore::new(READAHEAD_CONCURRENCY)),
+            last_access: CacheBuilder::new(READAHEAD_CACHE_SIZE)
+                .name("zarr-readahead-access")
+                .time_to_live(READAHEAD_CACHE_TTL)
+                .build(),
+            entries: CacheBuilder::new(READAHEAD_CACHE_SIZE)
+                .name("zarr-readahead-entries")
+                .time_to_live(READAHEAD_CACHE_TTL)
+                .build(),
+        }
+    }
+
+    /// Return the number of chunks fetched ahead of each sequentially-accessed
+    /// chunk
+    pub(super) fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Return the approximate number of entries in the readahead caches
+    pub(super) fn cache_len(&self) -> u64 {
+        self.last_access.entry_count() + self.entries.entry_count()
+    }
+
+    /// Discard all details fetched ahead of time
+    pub(super) fn clear(&self) {
+        self.last_access.invalidate_all();
+        self.entries.invalidate_all();
+    }
+
+    /// Retrieve the details on the entry at `path` in the Zarr with asset ID
+    /// `zarr_id` if they were fetched ahead of time
+    pub(super) async fn get_entry(&self, zarr_id: &str, path: &PurePath) -> Option<S3Entry> {
+        self.entries
+            .get(&(zarr_id.to_owned(), path.clone()))
+            .await
+            .map(S3Entry::Object)
+    }
+
+    /// Record that the chunk at `path` in the Zarr with asset ID `zarr_id`
+    /// was accessed.  If the chunk immediately preceding it in the same
+    /// folder was the last one accessed there, spawn a background task for
+    /// fetching the details of the following chunks via `s3`.
+    pub(super) async fn observe(
+        self: &Arc<Self>,
+        zarr_id: &str,
+        path: &PurePath,
+        s3: &PrefixedS3Client,
+    ) {
+        let Some(index) = ChunkIndex::parse(path.name_str()) else {
+            return;
+        };
+        let folder_key = (zarr_id.to_owned(), path.parent());
+        let previous = self.last_access.get(&folder_key).await;
+        self.last_access.insert(folder_key, index.clone()).await;
+        if !previous.is_some_and(|prev| prev.successor() == index) {
+            return;
+        }
+        let Ok(permit) = Arc::clone(&self.permits).try_acquire_owned() else {
+            tracing::debug!(%path, "Too many Zarr readaheads running; not reading ahead");
+            return;
+        };
+        let this = Arc::clone(self);
+        let zarr_id = zarr_id.to_owned();
+        let path = path.clone();
+        let s3 = s3.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            this.read_ahead(zarr_id, path, index, s3).await;
+        });
+    }
+
+    /// Fetch & cache the details of the chunks following the chunk at `path`
+    /// (whose index is `index`), stopping at the first chunk that does not
+    /// exist
+    async fn read_ahead(
+        &self,
+        zarr_id: String,
+        path: PurePath,
+        mut index: ChunkIndex,
+        s3: PrefixedS3Client,
+    ) {
+        let parent = path.parent();
+        for _ in 0..self.count {
+            index = index.successor();
+            let name = index
+                .to_string()
+                .parse::<PurePath>()
+                .expect("chunk index should be a valid path");
+            let next_path = match parent {
+                Some(ref p) => p.join(&name),
+                None => name,
+            };
+            let key = (zarr_id.clone(), next_path);
+            if self.entries.contains_key(&key) {
+                continue;
+            }
+            match s3.get_path(&key.1).await {
+                Ok(Some(S3Entry::Object(obj))) => self.entries.insert(key, obj).await,
+                Ok(_) => break,
+                Err(e) => {
+                    tracing::debug!(error = ?e, zarr_id, path = %key.1, "Failed to read ahead Zarr chunk");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// The index of a Zarr chunk as given by its filename: a sequence of
+/// nonnegative integers separated by periods (e.g., `0.1.2`).  Chunks stored
+/// in nested directories have one-element indices.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ChunkIndex(Vec<u64>);
+
+impl ChunkIndex {
+    /// Parse a chunk filename into a `ChunkIndex`.  Returns `None` if the
+    /// name is not of the expected form.
+    fn parse(name: &str) -> Option<ChunkIndex> {
+        name.split('.')
+            .map(|s| {
+                if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+                    s.parse::<u64>().ok()
+                } else {
+                    None
+                }
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(ChunkIndex)
+    }
+
+    /// Return the index of the next chunk along the last dimension
+    fn successor(&self) -> ChunkIndex {
+        let mut indices = self.0.clone();
+        if let Some(last) = indices.last_mut() {
+            *last = last.saturating_add(1);
+        }
+        ChunkIndex(indices)
+    }
+}
+
+impl fmt::Display for ChunkIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.iter().format("."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("0", Some(vec![0]))]
+    #[case("0.0.1", Some(vec![0, 0, 1]))]
+    #[case("12.3", Some(vec![12, 3]))]
+    #[case(".zarray", None)]
+    #[case("0..1", None)]
+    #[case("0.1.", None)]
+    #[case("c", None)]
+    #[case("-1", None)]
+    #[case("+1", None)]
+    fn parse_chunk_index(#[case] name: &str, #[case] index: Option<Vec<u64>>) {
+        assert_eq!(ChunkIndex::parse(name), index.map(ChunkIndex));
+    }
+
+    #[rstest]
+    #[case("0", "1")]
+    #[case("0.0.9", "0.0.10")]
+    #[case("3.1.41", "3.1.42")]
+    fn chunk_successor(#[case] name: &str, #[case] next: &str) {
+        let index = ChunkIndex::parse(name).unwrap();
+        assert_eq!(index.successor().to_string(), next);
+    }
+}
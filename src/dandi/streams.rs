@@ -1,4 +1,4 @@
-use super::{DandiClient, DandiError};
+use super::{ApiCache, DandiClient, DandiError};
 use crate::httputil::{Client, HttpError, HttpUrl};
 use futures_util::{future::BoxFuture, FutureExt, Stream};
 use pin_project::pin_project;
@@ -12,6 +12,7 @@ use std::task::{ready, Context, Poll};
 #[must_use = "streams do nothing unless polled"]
 pub(super) struct Paginate<T> {
     client: Client,
+    cache: Option<ApiCache>,
     state: PaginateState<T>,
 }
 
@@ -28,6 +29,7 @@ impl<T> Paginate<T> {
     pub(super) fn new(client: &DandiClient, url: HttpUrl) -> Self {
         Paginate {
             client: client.inner.clone(),
+            cache: client.api_cache.clone(),
             state: PaginateState::Yielding {
                 results: Vec::new().into_iter(),
                 next: Some(url),
@@ -65,8 +67,11 @@ where
                     if let Some(item) = results.next() {
                         return Some(Ok(item)).into();
                     } else if let Some(url) = next.take() {
-                        *this.state =
-                            PaginateState::Requesting(this.client.get_json::<Page<T>>(url).boxed());
+                        let fut = match this.cache {
+                            Some(cache) => cache.get_json::<Page<T>>(this.client, url).boxed(),
+                            None => this.client.get_json::<Page<T>>(url).boxed(),
+                        };
+                        *this.state = PaginateState::Requesting(fut);
                     } else {
                         *this.state = PaginateState::Done;
                     }
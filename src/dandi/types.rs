@@ -1,8 +1,13 @@
-use super::{DandisetId, VersionId};
+use super::{DandisetId, MetadataAllowlist, VersionId};
+use crate::dav::ErrorClass;
 use crate::httputil::HttpUrl;
 use crate::paths::{PureDirPath, PurePath};
-use crate::s3::{PrefixedS3Client, S3Entry, S3Folder, S3Location, S3Object};
-use serde::{de::Deserializer, Deserialize};
+use crate::s3::{
+    PrefixedS3Client, S3BadObject, S3Entry, S3Folder, S3Location, S3Object, S3Settings,
+};
+use crate::zarrchecksum::ZarrChecksumStats;
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::Deserialize;
 use thiserror::Error;
 use time::OffsetDateTime;
 
@@ -50,7 +55,7 @@ pub(crate) struct Dandiset {
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 pub(super) struct RawDandisetVersion {
     pub(super) version: VersionId,
-    //name: String,
+    name: String,
     //asset_count: u64,
     size: i64,
     //status: ...,
@@ -64,6 +69,7 @@ impl RawDandisetVersion {
     pub(super) fn with_metadata_url(self, metadata_url: HttpUrl) -> DandisetVersion {
         DandisetVersion {
             version: self.version,
+            name: self.name,
             size: self.size,
             created: self.created,
             modified: self.modified,
@@ -75,6 +81,7 @@ impl RawDandisetVersion {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct DandisetVersion {
     pub(crate) version: VersionId,
+    pub(crate) name: String,
     pub(crate) size: i64,
     pub(crate) created: OffsetDateTime,
     pub(crate) modified: OffsetDateTime,
@@ -85,14 +92,25 @@ pub(crate) struct DandisetVersion {
 pub(super) struct RawVersionInfo {
     #[serde(flatten)]
     properties: RawDandisetVersion,
-    metadata: VersionMetadata,
+    metadata: serde_json::Value,
 }
 
 impl RawVersionInfo {
-    pub(super) fn with_metadata_url(self, metadata_url: HttpUrl) -> VersionInfo {
+    /// Convert to a `VersionInfo`, redacting any metadata fields not in
+    /// `allowlist` (if given)
+    pub(super) fn with_metadata_url(
+        mut self,
+        metadata_url: HttpUrl,
+        allowlist: Option<&MetadataAllowlist>,
+    ) -> VersionInfo {
+        if let Some(allowlist) = allowlist {
+            allowlist.apply(&mut self.metadata);
+        }
+        let citation = VersionCitation::from_metadata(&self.metadata);
         VersionInfo {
             properties: self.properties.with_metadata_url(metadata_url),
-            metadata: self.metadata,
+            citation,
+            metadata: VersionMetadata::from_json(self.metadata),
         }
     }
 }
@@ -100,32 +118,93 @@ impl RawVersionInfo {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct VersionInfo {
     pub(crate) properties: DandisetVersion,
+    pub(crate) citation: VersionCitation,
     pub(crate) metadata: VersionMetadata,
 }
 
+/// The details needed to cite a Dandiset version, as taken from its metadata.
+/// Fields that are absent from or were redacted from the metadata are left
+/// empty.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct VersionCitation {
+    /// The version's DOI
+    pub(crate) doi: Option<String>,
+
+    /// The version's recommended citation string
+    pub(crate) citation: Option<String>,
+
+    /// The licenses that the version's data is available under, as SPDX
+    /// identifiers prefixed with "spdx:"
+    pub(crate) license: Vec<String>,
+}
+
+impl VersionCitation {
+    /// Extract citation details from a version's JSON metadata
+    fn from_metadata(metadata: &serde_json::Value) -> VersionCitation {
+        let string_field = |key| {
+            metadata
+                .get(key)
+                .and_then(serde_json::Value::as_str)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+        };
+        let license = metadata
+            .get("license")
+            .and_then(serde_json::Value::as_array)
+            .map(|licenses| {
+                licenses
+                    .iter()
+                    .filter_map(serde_json::Value::as_str)
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        VersionCitation {
+            doi: string_field("doi"),
+            citation: string_field("citation"),
+            license,
+        }
+    }
+
+    /// Returns true if none of the citation details are known
+    pub(crate) fn is_empty(&self) -> bool {
+        self.doi.is_none() && self.citation.is_none() && self.license.is_empty()
+    }
+}
+
+/// A version's metadata serialized as YAML.  The serialization is stored as
+/// [`Bytes`] so that cached renderings can be cloned and served without
+/// copying.
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub(crate) struct VersionMetadata(pub(super) Vec<u8>);
+pub(crate) struct VersionMetadata(pub(super) Bytes);
 
 impl VersionMetadata {
+    /// Construct a `VersionMetadata` by serializing the given deserialized
+    /// JSON metadata as YAML
+    pub(super) fn from_json(data: serde_json::Value) -> VersionMetadata {
+        VersionMetadata(dump_json_as_yaml(data))
+    }
+
     pub(crate) fn len(&self) -> usize {
         self.0.len()
     }
 }
 
-impl From<VersionMetadata> for Vec<u8> {
-    fn from(value: VersionMetadata) -> Vec<u8> {
+impl From<VersionMetadata> for Bytes {
+    fn from(value: VersionMetadata) -> Bytes {
         value.0
     }
 }
 
-impl<'de> Deserialize<'de> for VersionMetadata {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let data = serde_json::Value::deserialize(deserializer)?;
-        Ok(VersionMetadata(dump_json_as_yaml(data).into_bytes()))
-    }
+/// An item in a `/dandisets/{dandiset_id}/versions/{version_id}/assets/`
+/// response, deserialized without validating the path or requiring a size so
+/// that malformed entries can be reported by `dandidav verify`
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub(crate) struct ListedAsset {
+    pub(crate) asset_id: String,
+    pub(crate) path: String,
+    #[serde(default)]
+    pub(crate) size: Option<i64>,
 }
 
 // Item in a `/dandisets/{dandiset_id}/versions/{version_id}/assets/paths/`
@@ -140,6 +219,9 @@ pub(crate) enum FolderEntry {
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct AssetFolder {
     pub(crate) path: PureDirPath,
+
+    /// The total size of all assets under the folder, if known
+    pub(crate) size: Option<i64>,
 }
 
 impl From<RawFolderEntry> for FolderEntry {
@@ -152,6 +234,7 @@ impl From<RawFolderEntry> for FolderEntry {
         } else {
             FolderEntry::Folder(AssetFolder {
                 path: entry.path.to_dir_path(),
+                size: Some(entry.aggregate_size),
             })
         }
     }
@@ -162,6 +245,7 @@ impl From<RawFolderEntry> for FolderEntry {
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 struct RawFolderEntry {
     path: PurePath,
+    aggregate_size: i64,
     asset: Option<RawFolderEntryAsset>,
 }
 
@@ -183,6 +267,24 @@ pub(crate) enum Asset {
     Zarr(ZarrAsset),
 }
 
+impl Asset {
+    /// Return the size of the asset in bytes
+    pub(crate) fn size(&self) -> i64 {
+        match self {
+            Asset::Blob(blob) => blob.size,
+            Asset::Zarr(zarr) => zarr.size,
+        }
+    }
+
+    /// Return the asset's ID
+    pub(crate) fn asset_id(&self) -> &str {
+        match self {
+            Asset::Blob(blob) => &blob.asset_id,
+            Asset::Zarr(zarr) => &zarr.asset_id,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct BlobAsset {
     pub(crate) asset_id: String,
@@ -204,6 +306,10 @@ impl BlobAsset {
         self.metadata.digest.dandi_etag.as_deref()
     }
 
+    pub(crate) fn sha256(&self) -> Option<&str> {
+        self.metadata.digest.sha256.as_deref()
+    }
+
     pub(crate) fn archive_url(&self) -> Option<&HttpUrl> {
         self.metadata
             .content_url
@@ -217,6 +323,15 @@ impl BlobAsset {
             .iter()
             .find(|url| S3Location::parse_url(url.as_url()).is_ok())
     }
+
+    /// Return whether access to the asset is restricted due to its Dandiset
+    /// being under embargo
+    pub(crate) fn is_embargoed(&self) -> bool {
+        self.metadata
+            .access
+            .iter()
+            .any(|req| req.status == EMBARGOED_ACCESS)
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -232,23 +347,50 @@ pub(crate) struct ZarrAsset {
 }
 
 impl ZarrAsset {
-    pub(crate) fn s3location(&self) -> Option<S3Location> {
+    /// Return the Zarr checksum of the Zarr, if known
+    pub(crate) fn checksum(&self) -> Option<&str> {
+        self.metadata.digest.zarr_checksum.as_deref()
+    }
+
+    /// Return the number of entries in the Zarr, as determined from its Zarr
+    /// checksum.  Returns `Ok(None)` if the Zarr's checksum is not known.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the Zarr's checksum is not in the expected format
+    pub(crate) fn entry_count(&self) -> Result<Option<u64>, BadZarrChecksum> {
+        self.checksum()
+            .map(|checksum| {
+                ZarrChecksumStats::parse(checksum)
+                    .map(|stats| stats.entry_count)
+                    .ok_or_else(|| BadZarrChecksum {
+                        asset_id: self.asset_id.clone(),
+                        checksum: checksum.to_owned(),
+                    })
+            })
+            .transpose()
+    }
+
+    /// Return the location of the Zarr on S3, as determined from the first
+    /// of its `contentUrl`s that `settings` recognizes as an S3 URL
+    pub(crate) fn s3location(&self, settings: &S3Settings) -> Option<S3Location> {
         self.metadata
             .content_url
             .iter()
-            .find_map(|url| S3Location::parse_url(url.as_url()).ok())
+            .find_map(|url| settings.parse_url(url.as_url()).ok())
     }
 
     pub(crate) fn make_resource(&self, value: S3Entry) -> DandiResource {
         match value {
             S3Entry::Folder(folder) => DandiResource::ZarrFolder(self.make_folder(folder)),
             S3Entry::Object(obj) => DandiResource::ZarrEntry(self.make_entry(obj)),
+            S3Entry::BadObject(obj) => DandiResource::Unavailable(self.make_unavailable(obj)),
         }
     }
 
     /// Return a `ZarrFolder` for the folder within this Zarr described by
     /// `folder`
-    fn make_folder(&self, folder: S3Folder) -> ZarrFolder {
+    pub(super) fn make_folder(&self, folder: S3Folder) -> ZarrFolder {
         ZarrFolder {
             zarr_path: self.path.clone(),
             path: folder.key_prefix,
@@ -256,7 +398,7 @@ impl ZarrAsset {
     }
 
     /// Return a `ZarrEntry` for the entry within this Zarr described by `obj`
-    fn make_entry(&self, obj: S3Object) -> ZarrEntry {
+    pub(super) fn make_entry(&self, obj: S3Object) -> ZarrEntry {
         ZarrEntry {
             zarr_path: self.path.clone(),
             path: obj.key,
@@ -266,6 +408,15 @@ impl ZarrAsset {
             url: obj.download_url,
         }
     }
+
+    /// Return an `UnavailableResource` for the entry within this Zarr whose
+    /// S3 details could not be parsed
+    fn make_unavailable(&self, obj: S3BadObject) -> UnavailableResource {
+        UnavailableResource {
+            path: self.path.to_dir_path().join(&obj.key),
+            class: ErrorClass::BadGateway,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
@@ -274,12 +425,26 @@ pub(crate) struct AssetMetadata {
     encoding_format: Option<String>,
     content_url: Vec<HttpUrl>,
     digest: AssetDigests,
+    #[serde(default)]
+    access: Vec<AccessRequirements>,
+}
+
+/// The value of [`AccessRequirements::status`] for embargoed assets
+static EMBARGOED_ACCESS: &str = "dandi:EmbargoedAccess";
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub(crate) struct AccessRequirements {
+    status: String,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 pub(crate) struct AssetDigests {
     #[serde(rename = "dandi:dandi-etag")]
     dandi_etag: Option<String>,
+    #[serde(rename = "dandi:dandi-zarr-checksum")]
+    zarr_checksum: Option<String>,
+    #[serde(rename = "dandi:sha2-256")]
+    sha256: Option<String>,
 }
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
@@ -341,23 +506,32 @@ pub(crate) enum AssetTypeError {
     Both { asset_id: String },
 }
 
+/// Error returned when a Zarr asset's checksum is not in the expected format
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+#[error("Zarr asset {asset_id} has malformed Zarr checksum {checksum:?}")]
+pub(crate) struct BadZarrChecksum {
+    pub(crate) asset_id: String,
+    pub(crate) checksum: String,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) enum DandiResource {
     Folder(AssetFolder),
     Asset(Asset),
     ZarrFolder(ZarrFolder),
     ZarrEntry(ZarrEntry),
+    Unavailable(UnavailableResource),
 }
 
-impl DandiResource {
-    pub(super) fn with_s3(self, s3: PrefixedS3Client) -> DandiResourceWithS3 {
-        match self {
-            DandiResource::Folder(r) => DandiResourceWithS3::Folder(r),
-            DandiResource::Asset(r) => DandiResourceWithS3::Asset(r),
-            DandiResource::ZarrFolder(folder) => DandiResourceWithS3::ZarrFolder { folder, s3 },
-            DandiResource::ZarrEntry(r) => DandiResourceWithS3::ZarrEntry(r),
-        }
-    }
+/// A child resource of a collection whose details could not be retrieved
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct UnavailableResource {
+    /// The path to the resource within its Dandiset version
+    pub(crate) path: PurePath,
+
+    /// The type of error that prevented the resource's details from being
+    /// retrieved
+    pub(crate) class: ErrorClass,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -371,6 +545,10 @@ impl ZarrFolder {
         match value {
             S3Entry::Folder(folder) => DandiResource::ZarrFolder(self.make_folder(folder)),
             S3Entry::Object(obj) => DandiResource::ZarrEntry(self.make_entry(obj)),
+            S3Entry::BadObject(obj) => DandiResource::Unavailable(UnavailableResource {
+                path: self.zarr_path.to_dir_path().join(&obj.key),
+                class: ErrorClass::BadGateway,
+            }),
         }
     }
 
@@ -414,6 +592,15 @@ pub(super) enum DandiResourceWithS3 {
     ZarrEntry(ZarrEntry),
 }
 
+impl From<AtAssetPath> for DandiResource {
+    fn from(value: AtAssetPath) -> DandiResource {
+        match value {
+            AtAssetPath::Folder(r) => DandiResource::Folder(r),
+            AtAssetPath::Asset(r) => DandiResource::Asset(r),
+        }
+    }
+}
+
 impl From<AtAssetPath> for DandiResourceWithS3 {
     fn from(value: AtAssetPath) -> DandiResourceWithS3 {
         match value {
@@ -452,21 +639,121 @@ pub(crate) enum DandiResourceWithChildren {
     ZarrEntry(ZarrEntry),
 }
 
-/// Serialize the given deserialized JSON value as YAML
+/// Information about an Archive instance, as returned by `/api/info/`
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub(crate) struct ArchiveInfo {
+    /// The version of the DANDI metadata schema used by the instance
+    pub(crate) schema_version: String,
+
+    /// The version of the Archive's API server software
+    pub(crate) version: String,
+}
+
+/// Serialize the given deserialized JSON value as YAML directly into a bytes
+/// buffer
 ///
 /// # Panics
 ///
 /// Panics if the value cannot be serialized.  This should not happen.
-fn dump_json_as_yaml(data: serde_json::Value) -> String {
-    serde_yaml::to_string(&data).expect("converting JSON to YAML should not fail")
+fn dump_json_as_yaml(data: serde_json::Value) -> Bytes {
+    let mut buf = BytesMut::new().writer();
+    serde_yaml::to_writer(&mut buf, &data).expect("converting JSON to YAML should not fail");
+    buf.into_inner().freeze()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use indoc::indoc;
+    use rstest::rstest;
     use serde_json::json;
 
+    #[rstest]
+    #[case(None, Ok(None))]
+    #[case(Some("6ddc4625befef8d6f9796835648162be-509--710206390"), Ok(Some(509)))]
+    #[case(
+        Some("6ddc4625befef8d6f9796835648162be-509"),
+        Err(BadZarrChecksum {
+            asset_id: "0123".into(),
+            checksum: "6ddc4625befef8d6f9796835648162be-509".into(),
+        })
+    )]
+    fn zarr_entry_count(
+        #[case] checksum: Option<&str>,
+        #[case] count: Result<Option<u64>, BadZarrChecksum>,
+    ) {
+        let zarr = ZarrAsset {
+            asset_id: "0123".into(),
+            zarr_id: "4567".into(),
+            path: "foo.zarr".parse().unwrap(),
+            size: 710206390,
+            created: OffsetDateTime::UNIX_EPOCH,
+            modified: OffsetDateTime::UNIX_EPOCH,
+            metadata: AssetMetadata {
+                encoding_format: None,
+                content_url: Vec::new(),
+                digest: AssetDigests {
+                    dandi_etag: None,
+                    zarr_checksum: checksum.map(String::from),
+                    sha256: None,
+                },
+                access: Vec::new(),
+            },
+            metadata_url: "https://api.example.com/assets/0123/".parse().unwrap(),
+        };
+        assert_eq!(zarr.entry_count(), count);
+    }
+
+    #[rstest]
+    #[case(json!([]), false)]
+    #[case(json!([{"schemaKey": "AccessRequirements", "status": "dandi:OpenAccess"}]), false)]
+    #[case(
+        json!([{"schemaKey": "AccessRequirements", "status": "dandi:EmbargoedAccess"}]),
+        true
+    )]
+    fn blob_is_embargoed(#[case] access: serde_json::Value, #[case] embargoed: bool) {
+        let metadata = serde_json::from_value::<AssetMetadata>(json!({
+            "contentUrl": ["https://api.example.com/api/assets/0123/download/"],
+            "digest": {},
+            "access": access,
+        }))
+        .unwrap();
+        let blob = BlobAsset {
+            asset_id: "0123".into(),
+            blob_id: "4567".into(),
+            path: "foo.nwb".parse().unwrap(),
+            size: 42,
+            created: OffsetDateTime::UNIX_EPOCH,
+            modified: OffsetDateTime::UNIX_EPOCH,
+            metadata,
+            metadata_url: "https://api.example.com/assets/0123/".parse().unwrap(),
+        };
+        assert_eq!(blob.is_embargoed(), embargoed);
+    }
+
+    #[test]
+    fn version_citation_from_metadata() {
+        let metadata = json!({
+            "doi": "10.48324/dandi.000001/0.210512.1623",
+            "citation": "Doe, Jane (2021) Brainscan of a Unicorn (Version 0.210512.1623) [Data set]. DANDI archive. https://doi.org/10.48324/dandi.000001/0.210512.1623",
+            "license": ["spdx:CC-BY-4.0", 42, "spdx:CC0-1.0"],
+        });
+        assert_eq!(
+            VersionCitation::from_metadata(&metadata),
+            VersionCitation {
+                doi: Some("10.48324/dandi.000001/0.210512.1623".into()),
+                citation: Some("Doe, Jane (2021) Brainscan of a Unicorn (Version 0.210512.1623) [Data set]. DANDI archive. https://doi.org/10.48324/dandi.000001/0.210512.1623".into()),
+                license: vec!["spdx:CC-BY-4.0".into(), "spdx:CC0-1.0".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn version_citation_missing() {
+        let citation = VersionCitation::from_metadata(&json!({"doi": "", "name": "Foo"}));
+        assert!(citation.is_empty());
+    }
+
     #[test]
     fn test_dump_json_as_yaml() {
         let data = json! ({
@@ -483,7 +770,7 @@ mod tests {
         });
         let s = dump_json_as_yaml(data);
         assert_eq!(
-            s,
+            std::str::from_utf8(&s).unwrap(),
             indoc! {"
             key: value
             int: 42
@@ -0,0 +1,151 @@
+//! Limiting the number of concurrent upstream requests made on behalf of a
+//! single Dandiset
+//!
+//! A client recursively crawling one very large Dandiset can keep many
+//! requests to the Archive in flight at once, leaving little upstream
+//! capacity for clients browsing other Dandisets.  When a per-Dandiset limit
+//! is configured, each incoming request for a resource under a Dandiset is
+//! handled with access to that Dandiset's pool of permits, and every upstream
+//! request made while handling it must first obtain a permit from the pool.
+//! Requests for different Dandisets draw from different pools and so do not
+//! wait on each other.
+//!
+//! As with [request budgets](crate::budget), the pool is tracked in a
+//! task-local variable, so only upstream requests made from the task handling
+//! the incoming request (and not from background tasks spawned by it) are
+//! limited.
+use crate::dandi::DandisetId;
+use std::collections::HashMap;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+tokio::task_local! {
+    /// The pool of permits for the Dandiset that the incoming request being
+    /// handled by the current task is for
+    static POOL: DandisetPool;
+}
+
+/// The pools of permits for making upstream requests on behalf of each
+/// Dandiset that currently has requests in progress
+#[derive(Debug)]
+pub(crate) struct DandisetLimits {
+    /// The number of permits in each pool
+    limit: NonZeroUsize,
+
+    /// The pools, keyed by Dandiset ID.  A pool is discarded once no incoming
+    /// requests for its Dandiset are being handled.
+    pools: Mutex<HashMap<DandisetId, Weak<Semaphore>>>,
+}
+
+impl DandisetLimits {
+    /// Construct a `DandisetLimits` that allows up to `limit` concurrent
+    /// upstream requests per Dandiset
+    pub(crate) fn new(limit: NonZeroUsize) -> DandisetLimits {
+        DandisetLimits {
+            limit,
+            pools: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Return the pool of permits for upstream requests made on behalf of
+    /// `dandiset_id`, creating it if no requests for the Dandiset are
+    /// currently in progress
+    pub(crate) fn pool(&self, dandiset_id: &DandisetId) -> DandisetPool {
+        let mut pools = self.pools.lock().unwrap_or_else(PoisonError::into_inner);
+        let semaphore = if let Some(semaphore) = pools.get(dandiset_id).and_then(Weak::upgrade) {
+            semaphore
+        } else {
+            pools.retain(|_, pool| pool.strong_count() > 0);
+            let semaphore = Arc::new(Semaphore::new(self.limit.get()));
+            pools.insert(dandiset_id.clone(), Arc::downgrade(&semaphore));
+            semaphore
+        };
+        DandisetPool {
+            dandiset_id: dandiset_id.clone(),
+            semaphore,
+        }
+    }
+}
+
+/// A handle on the pool of permits for a single Dandiset
+#[derive(Clone, Debug)]
+pub(crate) struct DandisetPool {
+    dandiset_id: DandisetId,
+    semaphore: Arc<Semaphore>,
+}
+
+/// Run `fut` with its upstream requests limited by `pool`, if given
+pub(crate) async fn with_dandiset_pool<F: Future>(pool: Option<DandisetPool>, fut: F) -> F::Output {
+    match pool {
+        Some(pool) => POOL.scope(pool, fut).await,
+        None => fut.await,
+    }
+}
+
+/// Wait for a permit to make an upstream request on behalf of the current
+/// task's Dandiset.  The permit should be held until the request has been
+/// sent.  If the current task is not limited, this returns `None`
+/// immediately.
+pub(crate) async fn acquire() -> Option<OwnedSemaphorePermit> {
+    let pool = POOL.try_with(Clone::clone).ok()?;
+    if let Ok(permit) = Arc::clone(&pool.semaphore).try_acquire_owned() {
+        return Some(permit);
+    }
+    tracing::debug!(
+        dandiset_id = %pool.dandiset_id,
+        "Waiting for capacity to make upstream request for Dandiset",
+    );
+    pool.semaphore.acquire_owned().await.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn dandiset(s: &str) -> DandisetId {
+        s.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn limited_per_dandiset() {
+        let limits = DandisetLimits::new(NonZeroUsize::new(1).unwrap());
+        let pool1 = limits.pool(&dandiset("000001"));
+        let pool2 = limits.pool(&dandiset("000002"));
+        with_dandiset_pool(Some(pool1.clone()), async {
+            let held = acquire().await;
+            assert!(held.is_some());
+            // A second permit for the same Dandiset is not available ...
+            let r = tokio::time::timeout(Duration::from_millis(50), acquire()).await;
+            assert!(r.is_err());
+            // ... but one for a different Dandiset is.
+            let other = with_dandiset_pool(Some(pool2), acquire()).await;
+            assert!(other.is_some());
+            drop(held);
+            assert!(acquire().await.is_some());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn pools_are_shared_while_in_use() {
+        let limits = DandisetLimits::new(NonZeroUsize::new(1).unwrap());
+        let pool = limits.pool(&dandiset("000001"));
+        let again = limits.pool(&dandiset("000001"));
+        assert!(Arc::ptr_eq(&pool.semaphore, &again.semaphore));
+        drop(pool);
+        drop(again);
+        let fresh = limits.pool(&dandiset("000002"));
+        // Creating a new pool discards the unused one.
+        assert_eq!(limits.pools.lock().unwrap().len(), 1);
+        drop(fresh);
+    }
+
+    #[tokio::test]
+    async fn unlimited() {
+        assert!(acquire().await.is_none());
+        assert!(with_dandiset_pool(None, acquire()).await.is_none());
+    }
+}
@@ -0,0 +1,94 @@
+//! Fixtures for benchmarking the serialization of `PROPFIND` responses
+//!
+//! This module is only public so that the benchmarks under `benches/` can
+//! exercise the same code that serves real requests.
+use super::path::VersionSpec;
+use super::policy::PropertyPolicy;
+use super::types::{DavResource, DavResourceWithChildren};
+use super::xml::{Multistatus, PropFind};
+use crate::dandi::{DandiResource, DandiResourceWithChildren, ZarrEntry, ZarrFolder};
+use crate::paths::{PureDirPath, PurePath};
+use time::macros::datetime;
+
+/// The resources in a depth-1 `PROPFIND` listing of a folder of chunks
+/// within a Zarr in a draft Dandiset version, along with an `allprop` query
+/// for them
+#[derive(Clone, Debug)]
+pub struct ZarrFolderListing {
+    resources: Vec<DavResource>,
+    query: PropFind,
+    policy: PropertyPolicy,
+}
+
+impl ZarrFolderListing {
+    /// Construct a listing of a Zarr folder containing `entries` chunks
+    pub fn new(entries: usize) -> ZarrFolderListing {
+        let zarr_path = "fRLy/zfa6zGT.zarr"
+            .parse::<PurePath>()
+            .expect("Zarr path should be valid");
+        let folder = ZarrFolder {
+            zarr_path: zarr_path.clone(),
+            path: "0/"
+                .parse::<PureDirPath>()
+                .expect("folder path should be valid"),
+        };
+        let children = (0..entries)
+            .map(|i| {
+                let path = format!("0/0.0.{i}")
+                    .parse::<PurePath>()
+                    .expect("entry path should be valid");
+                let url = format!(
+                    "https://dandiarchive.s3.amazonaws.com/zarr/bf47be1a-4fed-4105-bcb4-c52534a45b82/{path}"
+                )
+                .parse()
+                .expect("entry URL should be valid");
+                DandiResource::ZarrEntry(ZarrEntry {
+                    zarr_path: zarr_path.clone(),
+                    path,
+                    size: 1_048_576_i64.saturating_add(i64::try_from(i).unwrap_or(i64::MAX)),
+                    modified: datetime!(2024-07-08 23:18:03 UTC),
+                    etag: format!("\"{i:032x}\""),
+                    url,
+                })
+            })
+            .collect();
+        let resources = DavResourceWithChildren::from(DandiResourceWithChildren::ZarrFolder {
+            folder,
+            children,
+        })
+        .under_version_path(
+            &"000002".parse().expect("Dandiset ID should be valid"),
+            &VersionSpec::Draft,
+            &"draft".parse().expect("version ID should be valid"),
+        )
+        .into_vec();
+        ZarrFolderListing {
+            resources,
+            query: PropFind::default(),
+            policy: PropertyPolicy::default(),
+        }
+    }
+
+    /// Evaluate the query against each resource in the listing, as is done
+    /// before serializing a `PROPFIND` response
+    pub fn responses(&self) -> PropfindResponse {
+        PropfindResponse(Multistatus {
+            response: self
+                .resources
+                .iter()
+                .map(|r| self.query.find(r, &self.policy))
+                .collect(),
+        })
+    }
+}
+
+/// A `multistatus` document awaiting serialization
+#[derive(Clone, Debug)]
+pub struct PropfindResponse(Multistatus);
+
+impl PropfindResponse {
+    /// Serialize the document as XML
+    pub fn to_xml(&self) -> String {
+        self.0.to_xml()
+    }
+}
@@ -0,0 +1,377 @@
+//! Construction of [`DandiDav`] instances
+use super::credentials::UserClients;
+use super::html::Templater;
+use super::i18n::Language;
+use super::lock::LockManager;
+use super::mount::{dedup_mounts, DEFAULT_ROOT_MOUNTS};
+use super::objcache::ObjectCache;
+use super::policy::{CollectionProperty, PropertyPolicy};
+use super::propcache::PropfindCache;
+use super::util::{Depth, FiniteDepth, ZarrMode};
+use super::{DandiDav, OtherInstance};
+use crate::audit::AuditLog;
+use crate::dandi::DandiClient;
+use crate::dandisetlimit::DandisetLimits;
+use crate::hotobjects::HotObjects;
+use crate::httputil::Client;
+use crate::mountlimit::Mount;
+use crate::zarrman::ZarrManClient;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+/// A builder for [`DandiDav`] instances.  Options that are not set take on the
+/// same defaults as the corresponding command-line options.
+pub(crate) struct DandiDavBuilder {
+    dandi: DandiClient,
+    zarrman: ZarrManClient,
+    templater: Arc<Templater>,
+    user_clients: Option<UserClients>,
+    prefer_s3_redirects: bool,
+    s3_content_disposition: bool,
+    upstream_request_budget: Option<NonZeroUsize>,
+    dandiset_concurrency: Option<NonZeroUsize>,
+    zarr_mode: ZarrMode,
+    default_language: Language,
+    audit_log: Option<Arc<AuditLog>>,
+    other_instance: Option<OtherInstance>,
+    max_propfind_size: Option<usize>,
+    omit_collection_properties: Vec<CollectionProperty>,
+    range_proxy: Option<Client>,
+    object_cache: Option<ObjectCache>,
+    blob_proxy: Option<Client>,
+    propfind_cache_ttl: Option<Duration>,
+    hot_objects: Option<Arc<HotObjects>>,
+    propfind_default_depth: Depth,
+    propfind_max_depth: FiniteDepth,
+    propfind_infinite_depth: bool,
+    root_mounts: Vec<Mount>,
+}
+
+impl DandiDavBuilder {
+    pub(super) fn new(
+        dandi: DandiClient,
+        zarrman: ZarrManClient,
+        templater: Arc<Templater>,
+    ) -> DandiDavBuilder {
+        DandiDavBuilder {
+            dandi,
+            zarrman,
+            templater,
+            user_clients: None,
+            prefer_s3_redirects: false,
+            s3_content_disposition: false,
+            upstream_request_budget: None,
+            dandiset_concurrency: None,
+            zarr_mode: ZarrMode::default(),
+            default_language: Language::default(),
+            audit_log: None,
+            other_instance: None,
+            max_propfind_size: None,
+            omit_collection_properties: Vec::new(),
+            range_proxy: None,
+            object_cache: None,
+            blob_proxy: None,
+            propfind_cache_ttl: None,
+            hot_objects: None,
+            propfind_default_depth: Depth::Infinity,
+            propfind_max_depth: FiniteDepth::One,
+            propfind_infinite_depth: false,
+            root_mounts: DEFAULT_ROOT_MOUNTS.to_vec(),
+        }
+    }
+
+    /// Handle requests with `Authorization` headers using per-user clients
+    /// from `clients`, if given, that send the requests' credentials to the
+    /// Archive
+    pub(crate) fn user_clients(mut self, clients: Option<UserClients>) -> Self {
+        self.user_clients = clients;
+        self
+    }
+
+    /// Redirect `GET` requests for blob assets directly to S3 instead of to
+    /// Archive download URLs
+    pub(crate) fn prefer_s3_redirects(mut self, flag: bool) -> Self {
+        self.prefer_s3_redirects = flag;
+        self
+    }
+
+    /// Add `response-content-disposition` query parameters to S3 URLs that
+    /// blob asset requests are redirected to.  Requires
+    /// [`prefer_s3_redirects()`](Self::prefer_s3_redirects).
+    pub(crate) fn s3_content_disposition(mut self, flag: bool) -> Self {
+        self.s3_content_disposition = flag;
+        self
+    }
+
+    /// Limit the number of upstream requests made while handling a single
+    /// incoming request
+    pub(crate) fn upstream_request_budget(mut self, budget: Option<NonZeroUsize>) -> Self {
+        self.upstream_request_budget = budget;
+        self
+    }
+
+    /// Limit the number of concurrent upstream requests made on behalf of
+    /// each Dandiset
+    pub(crate) fn dandiset_concurrency(mut self, limit: Option<NonZeroUsize>) -> Self {
+        self.dandiset_concurrency = limit;
+        self
+    }
+
+    /// Set how to present Zarr assets under `/dandisets/`
+    pub(crate) fn zarr_mode(mut self, mode: ZarrMode) -> Self {
+        self.zarr_mode = mode;
+        self
+    }
+
+    /// Set the language in which to display HTML views to clients that do not
+    /// request a supported language
+    pub(crate) fn default_language(mut self, lang: Language) -> Self {
+        self.default_language = lang;
+        self
+    }
+
+    /// Record served download redirects in `log`, if given
+    pub(crate) fn audit_log(mut self, log: Option<Arc<AuditLog>>) -> Self {
+        self.audit_log = log;
+        self
+    }
+
+    /// Point users to `instance`, if given, when they request a Dandiset that
+    /// only exists there
+    pub(crate) fn other_instance(mut self, instance: Option<OtherInstance>) -> Self {
+        self.other_instance = instance;
+        self
+    }
+
+    /// Truncate `PROPFIND` responses at the given size in bytes, if any
+    pub(crate) fn max_propfind_size(mut self, size: Option<usize>) -> Self {
+        self.max_propfind_size = size;
+        self
+    }
+
+    /// Omit the given properties of collections from `PROPFIND` responses
+    /// unless they are explicitly requested
+    pub(crate) fn omit_collection_properties<I>(mut self, props: I) -> Self
+    where
+        I: IntoIterator<Item = CollectionProperty>,
+    {
+        self.omit_collection_properties = props.into_iter().collect();
+        self
+    }
+
+    /// Use `client`, if given, to fetch ranges of Zarr entries from S3 on
+    /// behalf of clients that send `GET` requests with `Range` headers
+    pub(crate) fn range_proxy(mut self, client: Option<Client>) -> Self {
+        self.range_proxy = client;
+        self
+    }
+
+    /// Serve small Zarr entries from `cache`, if given.  Requires
+    /// [`range_proxy()`](Self::range_proxy).
+    pub(crate) fn object_cache(mut self, cache: Option<ObjectCache>) -> Self {
+        self.object_cache = cache;
+        self
+    }
+
+    /// Use `client`, if given, to stream blob assets from S3 through the
+    /// server instead of redirecting requests for them
+    pub(crate) fn blob_proxy(mut self, client: Option<Client>) -> Self {
+        self.blob_proxy = client;
+        self
+    }
+
+    /// Cache `PROPFIND` responses for the root & index collections for `ttl`,
+    /// if given
+    pub(crate) fn propfind_cache_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.propfind_cache_ttl = ttl;
+        self
+    }
+
+    /// Track the most frequently downloaded paths with `tracker`, if given
+    pub(crate) fn hot_objects(mut self, tracker: Option<Arc<HotObjects>>) -> Self {
+        self.hot_objects = tracker;
+        self
+    }
+
+    /// Set the depth to use for `PROPFIND` requests without `Depth` headers
+    pub(crate) fn propfind_default_depth(mut self, depth: Depth) -> Self {
+        self.propfind_default_depth = depth;
+        self
+    }
+
+    /// Set the maximum depth permitted for `PROPFIND` requests
+    pub(crate) fn propfind_max_depth(mut self, depth: FiniteDepth) -> Self {
+        self.propfind_max_depth = depth;
+        self
+    }
+
+    /// Stream listings of entire trees in response to `PROPFIND` requests
+    /// with infinite depth inside Dandiset versions & Zarrs
+    pub(crate) fn propfind_infinite_depth(mut self, flag: bool) -> Self {
+        self.propfind_infinite_depth = flag;
+        self
+    }
+
+    /// Set the top-level collections listed at the root of the hierarchy, in
+    /// the order in which they are listed
+    pub(crate) fn root_mounts(mut self, mounts: Vec<Mount>) -> Self {
+        self.root_mounts = mounts;
+        self
+    }
+
+    /// Construct the `DandiDav`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an option was set without another option that it
+    /// depends on or together with an option that it conflicts with
+    pub(crate) fn build(self) -> Result<DandiDav, BuildDandiDavError> {
+        if self.blob_proxy.is_some() && self.s3_content_disposition {
+            return Err(BuildDandiDavError::BlobProxyWithContentDisposition);
+        }
+        if self.blob_proxy.is_some() && self.prefer_s3_redirects {
+            return Err(BuildDandiDavError::BlobProxyWithS3Redirects);
+        }
+        if self.s3_content_disposition && !self.prefer_s3_redirects {
+            return Err(BuildDandiDavError::ContentDispositionWithoutS3Redirects);
+        }
+        if self.object_cache.is_some() && self.range_proxy.is_none() {
+            return Err(BuildDandiDavError::ObjectCacheWithoutRangeProxy);
+        }
+        let property_policy =
+            PropertyPolicy::omitting_from_collections(self.omit_collection_properties)
+                .with_redirects(self.prefer_s3_redirects, self.s3_content_disposition)
+                .with_root_name(self.templater.title().to_owned());
+        Ok(DandiDav {
+            user_clients: self.user_clients,
+            dandi: self.dandi,
+            zarrman: self.zarrman,
+            templater: self.templater,
+            prefer_s3_redirects: self.prefer_s3_redirects,
+            s3_content_disposition: self.s3_content_disposition,
+            upstream_request_budget: self.upstream_request_budget,
+            dandiset_limits: self
+                .dandiset_concurrency
+                .map(|limit| Arc::new(DandisetLimits::new(limit))),
+            zarr_mode: self.zarr_mode,
+            default_language: self.default_language,
+            audit_log: self.audit_log,
+            other_instance: self.other_instance,
+            max_propfind_size: self.max_propfind_size,
+            property_policy: Arc::new(property_policy),
+            range_proxy: self.range_proxy,
+            object_cache: self.object_cache,
+            blob_proxy: self.blob_proxy,
+            propfind_cache: self.propfind_cache_ttl.map(PropfindCache::new),
+            hot_objects: self.hot_objects,
+            locks: Arc::new(LockManager::new()),
+            propfind_default_depth: self.propfind_default_depth,
+            propfind_max_depth: self.propfind_max_depth,
+            propfind_infinite_depth: self.propfind_infinite_depth,
+            root_mounts: dedup_mounts(self.root_mounts),
+        })
+    }
+}
+
+/// Error returned when a [`DandiDavBuilder`] has an invalid combination of
+/// options
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+pub(crate) enum BuildDandiDavError {
+    #[error("proxying blobs cannot be combined with adding Content-Disposition to S3 redirects")]
+    BlobProxyWithContentDisposition,
+
+    #[error("proxying blobs cannot be combined with preferring S3 redirects")]
+    BlobProxyWithS3Redirects,
+
+    #[error("adding Content-Disposition to S3 redirects requires preferring S3 redirects")]
+    ContentDispositionWithoutS3Redirects,
+
+    #[error("the object cache requires range proxying to be enabled")]
+    ObjectCacheWithoutRangeProxy,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dav::html::HtmlSnippets;
+    use crate::zarrman::ManifestFetcher;
+
+    fn builder() -> DandiDavBuilder {
+        DandiDav::builder(
+            DandiClient::new(
+                "https://api.example.com/api".parse().unwrap(),
+                None,
+                false,
+                0,
+                None,
+                None,
+            )
+            .unwrap(),
+            ZarrManClient::new(ManifestFetcher::new(1000, Vec::new(), None).unwrap()),
+            Arc::new(Templater::new("dandidav".into(), &HtmlSnippets::default()).unwrap()),
+        )
+    }
+
+    #[tokio::test]
+    async fn build_defaults() {
+        let dav = builder().build().unwrap();
+        assert!(dav.user_clients.is_none());
+        assert_eq!(dav.propfind_default_depth, Depth::Infinity);
+        assert_eq!(dav.propfind_max_depth, FiniteDepth::One);
+        assert!(!dav.propfind_infinite_depth);
+        assert_eq!(dav.root_mounts, DEFAULT_ROOT_MOUNTS);
+    }
+
+    #[tokio::test]
+    async fn build_s3_content_disposition() {
+        assert_eq!(
+            builder().s3_content_disposition(true).build().err(),
+            Some(BuildDandiDavError::ContentDispositionWithoutS3Redirects)
+        );
+        let dav = builder()
+            .prefer_s3_redirects(true)
+            .s3_content_disposition(true)
+            .build()
+            .unwrap();
+        assert!(dav.prefer_s3_redirects && dav.s3_content_disposition);
+    }
+
+    #[tokio::test]
+    async fn build_object_cache_without_range_proxy() {
+        let dir = std::env::temp_dir().join(format!("dandidav-objcache-{}", uuid::Uuid::new_v4()));
+        let cache = ObjectCache::new(dir.clone(), 1_000_000).unwrap();
+        assert_eq!(
+            builder().object_cache(Some(cache)).build().err(),
+            Some(BuildDandiDavError::ObjectCacheWithoutRangeProxy)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn build_blob_proxy_with_s3_redirects() {
+        assert_eq!(
+            builder()
+                .blob_proxy(Some(Client::new(None).unwrap()))
+                .prefer_s3_redirects(true)
+                .build()
+                .err(),
+            Some(BuildDandiDavError::BlobProxyWithS3Redirects)
+        );
+        assert_eq!(
+            builder()
+                .blob_proxy(Some(Client::new(None).unwrap()))
+                .prefer_s3_redirects(true)
+                .s3_content_disposition(true)
+                .build()
+                .err(),
+            Some(BuildDandiDavError::BlobProxyWithContentDisposition)
+        );
+        let dav = builder()
+            .blob_proxy(Some(Client::new(None).unwrap()))
+            .build()
+            .unwrap();
+        assert!(dav.blob_proxy.is_some());
+    }
+}
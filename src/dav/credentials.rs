@@ -0,0 +1,213 @@
+//! Forwarding of the credentials in incoming requests to the Archive
+//!
+//! When enabled, a WebDAV request with an `Authorization` header is handled
+//! using a `DandiClient` that authenticates to the Archive with the DANDI API
+//! token given in the header, so that each user sees exactly the Dandisets
+//! that their token grants access to.  The token can be supplied either as a
+//! bearer token or as the password (or, if the password is empty, the
+//! username) of HTTP Basic credentials, the latter being the only form that
+//! most WebDAV clients support.
+use crate::consts::{USER_CLIENT_CACHE_SIZE, USER_CLIENT_IDLE_TTL};
+use crate::dandi::{ApiCacheUsage, DandiClient};
+use crate::httputil::BuildClientError;
+use axum::{
+    body::Body,
+    http::{header::WWW_AUTHENTICATE, response::Response, HeaderValue, StatusCode},
+    response::IntoResponse,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use moka::future::{Cache, CacheBuilder};
+use std::ops::Add;
+use thiserror::Error;
+
+/// The challenge sent in the `WWW-Authenticate` header of responses to
+/// requests with unusable credentials
+static CHALLENGE: &str = r#"Basic realm="DANDI Archive", charset="UTF-8""#;
+
+/// A collection of `DandiClient`s, one per DANDI API token seen in incoming
+/// requests.  At most [`USER_CLIENT_CACHE_SIZE`] clients are kept at once, and
+/// each is given an equal share of the base client's API response cache
+/// capacity.
+#[derive(Clone, Debug)]
+pub(crate) struct UserClients {
+    /// The client from which per-user clients take their settings
+    base: DandiClient,
+
+    /// Per-user clients, keyed by the `Authorization` header values that they
+    /// send to the Archive
+    clients: Cache<HeaderValue, DandiClient>,
+}
+
+impl UserClients {
+    /// Construct a new `UserClients` for creating per-user clients with the
+    /// same settings as `base`
+    pub(crate) fn new(base: DandiClient) -> UserClients {
+        UserClients {
+            base,
+            clients: CacheBuilder::new(USER_CLIENT_CACHE_SIZE)
+                .name("user-clients")
+                .time_to_idle(USER_CLIENT_IDLE_TTL)
+                .build(),
+        }
+    }
+
+    /// Return the client to use for a request with the given `Authorization`
+    /// header value, creating it if necessary
+    pub(super) async fn client_for(
+        &self,
+        authorization: &HeaderValue,
+    ) -> Result<DandiClient, CredentialsError> {
+        let key = archive_authorization(authorization).ok_or(CredentialsError::Unusable)?;
+        if let Some(client) = self.clients.get(&key).await {
+            return Ok(client);
+        }
+        let client = self
+            .base
+            .with_authorization(key.clone(), USER_CLIENT_CACHE_SIZE)?;
+        self.clients.insert(key, client.clone()).await;
+        Ok(client)
+    }
+
+    /// Return the approximate total number of entries in the per-user
+    /// clients' caches of Archive data
+    pub(crate) fn cache_len(&self) -> u64 {
+        self.clients
+            .iter()
+            .map(|(_, client)| client.cache_len())
+            .sum()
+    }
+
+    /// Return the amount of memory used by the per-user clients' caches of
+    /// Archive API responses
+    pub(crate) fn api_cache_usage(&self) -> ApiCacheUsage {
+        self.clients
+            .iter()
+            .map(|(_, client)| client.api_cache_usage())
+            .fold(ApiCacheUsage::default(), Add::add)
+    }
+
+    /// Discard all per-user clients along with their cached Archive data
+    pub(crate) fn clear_caches(&self) {
+        self.clients.invalidate_all();
+    }
+}
+
+/// Convert the value of an incoming request's `Authorization` header into the
+/// value with which to authenticate to the Archive.  Returns `None` if the
+/// header does not contain a token in a supported form.
+fn archive_authorization(authorization: &HeaderValue) -> Option<HeaderValue> {
+    let (scheme, credentials) = authorization.to_str().ok()?.trim().split_once(' ')?;
+    let credentials = credentials.trim();
+    let token = if scheme.eq_ignore_ascii_case("basic") {
+        let decoded = String::from_utf8(STANDARD.decode(credentials).ok()?).ok()?;
+        let (username, password) = decoded.split_once(':')?;
+        if password.is_empty() {
+            username.to_owned()
+        } else {
+            password.to_owned()
+        }
+    } else if scheme.eq_ignore_ascii_case("bearer") || scheme.eq_ignore_ascii_case("token") {
+        credentials.to_owned()
+    } else {
+        return None;
+    };
+    let token = token.trim();
+    if token.is_empty() || token.contains(char::is_whitespace) {
+        return None;
+    }
+    let mut value = HeaderValue::try_from(format!("token {token}")).ok()?;
+    value.set_sensitive(true);
+    Some(value)
+}
+
+/// Error returned when a request's credentials cannot be used
+#[derive(Debug, Error)]
+pub(super) enum CredentialsError {
+    /// The `Authorization` header did not contain a token in a supported form
+    #[error("Authorization header does not contain a usable DANDI API token")]
+    Unusable,
+
+    /// Constructing a client for the credentials failed
+    #[error(transparent)]
+    Build(#[from] BuildClientError),
+}
+
+impl IntoResponse for CredentialsError {
+    fn into_response(self) -> Response<Body> {
+        match self {
+            CredentialsError::Unusable => {
+                tracing::info!(
+                    status = StatusCode::UNAUTHORIZED.as_u16(),
+                    "Request has unusable credentials"
+                );
+                (
+                    StatusCode::UNAUTHORIZED,
+                    [(WWW_AUTHENTICATE, HeaderValue::from_static(CHALLENGE))],
+                    self.to_string(),
+                )
+                    .into_response()
+            }
+            CredentialsError::Build(_) => {
+                let e = anyhow::Error::from(self);
+                tracing::error!(error = ?e, "Failed to create client for request's credentials");
+                (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")).into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("Bearer abc123", Some("token abc123"))]
+    #[case("bearer  abc123 ", Some("token abc123"))]
+    #[case("token abc123", Some("token abc123"))]
+    // "user:abc123"
+    #[case("Basic dXNlcjphYmMxMjM=", Some("token abc123"))]
+    // "abc123:"
+    #[case("Basic YWJjMTIzOg==", Some("token abc123"))]
+    // "user:"
+    #[case("Basic dXNlcjo=", Some("token user"))]
+    // ":"
+    #[case("Basic Og==", None)]
+    // "abc123" (no colon)
+    #[case("Basic YWJjMTIz", None)]
+    #[case("Basic !!!", None)]
+    #[case("Bearer", None)]
+    #[case("Bearer ", None)]
+    #[case("Bearer abc 123", None)]
+    #[case("Digest username=\"user\"", None)]
+    fn test_archive_authorization(#[case] value: &str, #[case] expected: Option<&str>) {
+        let value = HeaderValue::from_str(value).unwrap();
+        let auth = archive_authorization(&value);
+        assert_eq!(auth.as_ref().map(|v| v.to_str().unwrap()), expected);
+        if let Some(auth) = auth {
+            assert!(auth.is_sensitive());
+        }
+    }
+
+    #[tokio::test]
+    async fn clear_caches_drops_clients() {
+        let base = DandiClient::new(
+            "https://api.example.com/api".parse().unwrap(),
+            None,
+            false,
+            0,
+            None,
+            None,
+        )
+        .unwrap();
+        let users = UserClients::new(base);
+        let auth = HeaderValue::from_static("Bearer abc123");
+        users.client_for(&auth).await.unwrap();
+        users.clients.run_pending_tasks().await;
+        assert_eq!(users.clients.entry_count(), 1);
+        assert_eq!(users.cache_len(), 0);
+        users.clear_caches();
+        users.clients.run_pending_tasks().await;
+        assert_eq!(users.clients.entry_count(), 0);
+    }
+}
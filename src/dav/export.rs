@@ -0,0 +1,247 @@
+//! Resolution of a selection of paths within a Dandiset version into a single
+//! manifest of downloadable files, for `GET` requests on version collections
+//! with `paths` query parameters
+use super::types::{DavItem, DavResource, DavResourceWithChildren, HasProperties, ResourceKind};
+use super::util::version_path;
+use super::{lookup_escaped, DavError, ErrorClass, VersionHandler};
+use crate::consts::{EXPORT_CONCURRENCY, EXPORT_MAX_FILES};
+use crate::httputil::HttpUrl;
+use crate::paths::{PureDirPath, PurePath};
+use crate::s3::S3Location;
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::collections::{BTreeSet, VecDeque};
+
+/// The name of the virtual metadata file at the root of each version
+const DANDISET_YAML: &str = "dandiset.yaml";
+
+/// A manifest of all files at or beneath a selection of paths within a
+/// Dandiset version
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub(super) struct ExportManifest {
+    /// The files selected, in the order in which they were found
+    files: Vec<ExportedFile>,
+
+    /// The selected paths and descendants thereof that could not be resolved
+    errors: Vec<ExportError>,
+
+    /// Whether the listing of files was cut short upon reaching
+    /// [`EXPORT_MAX_FILES`]
+    truncated: bool,
+}
+
+/// A file in an [`ExportManifest`]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct ExportedFile {
+    /// The file's path relative to the root of the version
+    path: String,
+
+    /// The absolute path at which the file is served by `dandidav`
+    href: String,
+
+    /// The type of file
+    #[serde(rename = "type")]
+    kind: ResourceKind,
+
+    /// The size of the file, if known
+    size: Option<i64>,
+
+    /// A URL from which the file's content can be downloaded directly.  This
+    /// is `None` for files generated by `dandidav` (which should be fetched
+    /// via `href`) and for files in private buckets.
+    download_url: Option<String>,
+}
+
+/// A path in a selection that could not be resolved
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct ExportError {
+    /// The path relative to the root of the version
+    path: String,
+
+    /// The HTTP status code that a request for the path would have received
+    status: u16,
+}
+
+/// Settings for converting resources into [`ExportedFile`]s
+pub(super) struct ExportOptions<F> {
+    /// See [`super::DandiDav::prefer_s3_redirects`]
+    pub(super) prefer_s3_redirects: bool,
+
+    /// See [`super::DandiDav::s3_content_disposition`]
+    pub(super) s3_content_disposition: bool,
+
+    /// Function for identifying download URLs that point to private S3
+    /// buckets
+    pub(super) locate_private: F,
+}
+
+/// Resolve each of `paths` within the version served by `handler` and return
+/// a manifest of all files at or beneath them.  The paths are initially
+/// looked up concurrently; collections among them are then expanded
+/// recursively until [`EXPORT_MAX_FILES`] files have been found.
+///
+/// Files are listed at most once, even if selected multiple times.  Zarrs
+/// are listed as single files according to `handler`'s Zarr mode, which
+/// should be [`super::ZarrMode::Collapsed`].
+pub(super) async fn export<F>(
+    handler: &VersionHandler<'_>,
+    paths: Vec<PurePath>,
+    opts: ExportOptions<F>,
+) -> ExportManifest
+where
+    F: Fn(&HttpUrl) -> Option<S3Location>,
+{
+    let mut exporter = Exporter {
+        root: version_path(handler.dandiset_id, handler.version_spec),
+        opts,
+        seen: BTreeSet::new(),
+        queue: VecDeque::new(),
+        manifest: ExportManifest::default(),
+    };
+    let resolved = futures_util::stream::iter(paths)
+        .map(|p| async move { resolve(handler, p.clone()).await.map_err(|e| (p, e)) })
+        .buffered(EXPORT_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    for r in resolved {
+        match r {
+            Ok(res) => exporter.add(res),
+            Err((p, e)) => exporter.fail(p.to_string(), e.class()),
+        }
+    }
+    while let Some(p) = exporter.next_folder() {
+        match handler.get_resource_with_children(&p).await {
+            Ok(res) => exporter.add(res),
+            Err(e) => exporter.fail(p.to_string(), e.class()),
+        }
+    }
+    exporter.manifest
+}
+
+/// Look up the resource at `path` in the version served by `handler`
+async fn resolve(
+    handler: &VersionHandler<'_>,
+    path: PurePath,
+) -> Result<DavResourceWithChildren, DavError> {
+    if path == DANDISET_YAML {
+        handler
+            .get_dandiset_yaml()
+            .await
+            .map(DavResourceWithChildren::Item)
+    } else {
+        lookup_escaped(&path, |p| async move {
+            handler.get_resource_with_children(&p).await
+        })
+        .await
+    }
+}
+
+/// State for the construction of an [`ExportManifest`]
+struct Exporter<F> {
+    /// The path at which the version is served
+    root: PureDirPath,
+
+    opts: ExportOptions<F>,
+
+    /// The paths of the files added to `manifest` so far, for deduplication
+    seen: BTreeSet<PurePath>,
+
+    /// Folders (relative to `root`) whose contents have yet to be fetched
+    queue: VecDeque<PurePath>,
+
+    manifest: ExportManifest,
+}
+
+impl<F> Exporter<F>
+where
+    F: Fn(&HttpUrl) -> Option<S3Location>,
+{
+    /// Add a resolved resource (and, if it is a collection, its children) to
+    /// the manifest
+    fn add(&mut self, res: DavResourceWithChildren) {
+        match res {
+            DavResourceWithChildren::Collection { children, .. } => {
+                for child in children {
+                    self.add_child(child);
+                }
+            }
+            DavResourceWithChildren::Item(item) => self.add_item(item),
+        }
+    }
+
+    /// Add an immediate child of a resolved collection to the manifest,
+    /// queueing it for expansion if it is a collection itself
+    fn add_child(&mut self, child: DavResource) {
+        match child {
+            DavResource::Collection(col) => {
+                if let Some(p) = col
+                    .path
+                    .as_ref()
+                    .and_then(|p| p.relative_to(&self.root))
+                    .and_then(|p| p.as_ref().trim_end_matches('/').parse::<PurePath>().ok())
+                {
+                    if !self.queue.contains(&p) {
+                        self.queue.push_back(p);
+                    }
+                }
+            }
+            DavResource::Item(item) => self.add_item(item),
+            DavResource::Unavailable(u) => {
+                let path = self.relative(&u.path);
+                self.fail(path, u.class);
+            }
+        }
+    }
+
+    /// Add a file to the manifest, unless it is already present or the
+    /// manifest is full
+    fn add_item(&mut self, mut item: DavItem) {
+        if self.seen.contains(&item.path) {
+            return;
+        }
+        if self.manifest.files.len() >= EXPORT_MAX_FILES {
+            self.manifest.truncated = true;
+            return;
+        }
+        item.mark_private(&self.opts.locate_private);
+        let download_url = item
+            .download_url(
+                self.opts.prefer_s3_redirects,
+                self.opts.s3_content_disposition,
+            )
+            .map(|url| url.to_string());
+        self.manifest.files.push(ExportedFile {
+            path: self.relative(&item.path),
+            href: item.href().as_ref().to_owned(),
+            kind: item.kind,
+            size: item.size,
+            download_url,
+        });
+        self.seen.insert(item.path);
+    }
+
+    /// Record that the resource at `path` could not be resolved
+    fn fail(&mut self, path: String, class: ErrorClass) {
+        self.manifest.errors.push(ExportError {
+            path,
+            status: class.to_status().as_u16(),
+        });
+    }
+
+    /// Return the next queued folder to expand, or `None` if there are no
+    /// more or the manifest is full
+    fn next_folder(&mut self) -> Option<PurePath> {
+        if self.manifest.files.len() >= EXPORT_MAX_FILES {
+            self.manifest.truncated |= !self.queue.is_empty();
+            return None;
+        }
+        self.queue.pop_front()
+    }
+
+    /// Return `path` (a path at which a resource is served) relative to the
+    /// version root
+    fn relative(&self, path: &PurePath) -> String {
+        path.relative_to(&self.root)
+            .map_or_else(|| path.to_string(), |p| p.to_string())
+    }
+}
@@ -0,0 +1,201 @@
+//! Content negotiation between the YAML & JSON representations of metadata
+//! documents
+use super::i18n::parse_qvalue;
+use crate::consts::{JSON_CONTENT_TYPE, YAML_CONTENT_TYPE};
+use bytes::{BufMut, Bytes, BytesMut};
+use thiserror::Error;
+
+/// A serialization format in which `dandidav` can serve metadata documents
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(super) enum MetadataFormat {
+    Yaml,
+    Json,
+}
+
+impl MetadataFormat {
+    /// Return the format of a generated document with the given
+    /// "Content-Type", if it is one that can be converted
+    pub(super) fn from_content_type(content_type: &str) -> Option<MetadataFormat> {
+        if content_type == YAML_CONTENT_TYPE {
+            Some(MetadataFormat::Yaml)
+        } else if content_type == JSON_CONTENT_TYPE {
+            Some(MetadataFormat::Json)
+        } else {
+            None
+        }
+    }
+
+    /// Return the format (if any) denoted by the given media type
+    fn from_media_type(media_type: &str) -> Option<MetadataFormat> {
+        let media_type = media_type.to_ascii_lowercase();
+        match media_type.as_str() {
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => {
+                Some(MetadataFormat::Yaml)
+            }
+            "application/json" | "text/json" => Some(MetadataFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Return the "Content-Type" value for documents in this format
+    pub(super) fn content_type(self) -> &'static str {
+        match self {
+            MetadataFormat::Yaml => YAML_CONTENT_TYPE,
+            MetadataFormat::Json => JSON_CONTENT_TYPE,
+        }
+    }
+
+    /// Select the format requested by the value of an `Accept` header for a
+    /// document natively in the format `native`.  The native format is used
+    /// unless the header explicitly prefers the other format; wildcard media
+    /// ranges and unknown media types are ignored.
+    pub(super) fn negotiate(accept: &str, native: MetadataFormat) -> MetadataFormat {
+        let mut best: Option<(MetadataFormat, u16)> = None;
+        for item in accept.split(',') {
+            let mut params = item.split(';');
+            let media_type = params.next().unwrap_or_default().trim();
+            let Some(format) = MetadataFormat::from_media_type(media_type) else {
+                continue;
+            };
+            let q = params
+                .find_map(|p| {
+                    let (k, v) = p.split_once('=')?;
+                    k.trim()
+                        .eq_ignore_ascii_case("q")
+                        .then(|| parse_qvalue(v.trim()))
+                })
+                .unwrap_or(Some(1000));
+            match q {
+                Some(q)
+                    if q > 0
+                        && best.map_or(true, |(bestfmt, bestq)| {
+                            bestq < q || (bestq == q && format == native && bestfmt != native)
+                        }) =>
+                {
+                    best = Some((format, q));
+                }
+                _ => (),
+            }
+        }
+        best.map_or(native, |(format, _)| format)
+    }
+
+    /// Convert the document `doc` from this format to `target`.  If the
+    /// formats are the same, `doc` is returned unchanged.
+    pub(super) fn convert(self, doc: Bytes, target: MetadataFormat) -> Result<Bytes, ConvertError> {
+        match (self, target) {
+            (MetadataFormat::Yaml, MetadataFormat::Json) => {
+                let data = serde_yaml::from_slice::<serde_json::Value>(&doc)?;
+                let mut buf = BytesMut::new().writer();
+                serde_json::to_writer_pretty(&mut buf, &data)?;
+                buf.get_mut().put_u8(b'\n');
+                Ok(buf.into_inner().freeze())
+            }
+            (MetadataFormat::Json, MetadataFormat::Yaml) => {
+                let data = serde_json::from_slice::<serde_json::Value>(&doc)?;
+                let mut buf = BytesMut::new().writer();
+                serde_yaml::to_writer(&mut buf, &data)?;
+                Ok(buf.into_inner().freeze())
+            }
+            _ => Ok(doc),
+        }
+    }
+}
+
+/// Error returned when a metadata document cannot be converted to another
+/// format
+#[derive(Debug, Error)]
+pub(crate) enum ConvertError {
+    #[error("failed to process metadata as YAML")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("failed to process metadata as JSON")]
+    Json(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("application/json", MetadataFormat::Yaml, MetadataFormat::Json)]
+    #[case("application/yaml", MetadataFormat::Json, MetadataFormat::Yaml)]
+    #[case("text/yaml", MetadataFormat::Yaml, MetadataFormat::Yaml)]
+    #[case("*/*", MetadataFormat::Yaml, MetadataFormat::Yaml)]
+    #[case("text/html, */*;q=0.8", MetadataFormat::Json, MetadataFormat::Json)]
+    #[case("Application/JSON", MetadataFormat::Yaml, MetadataFormat::Json)]
+    #[case(
+        "application/json;q=0.5, application/yaml",
+        MetadataFormat::Yaml,
+        MetadataFormat::Yaml
+    )]
+    #[case(
+        "application/yaml;q=0.5, application/json",
+        MetadataFormat::Yaml,
+        MetadataFormat::Json
+    )]
+    #[case(
+        "application/json, application/yaml",
+        MetadataFormat::Yaml,
+        MetadataFormat::Yaml
+    )]
+    #[case("application/json;q=0", MetadataFormat::Yaml, MetadataFormat::Yaml)]
+    #[case("application/json;q=2", MetadataFormat::Yaml, MetadataFormat::Yaml)]
+    fn test_negotiate(
+        #[case] accept: &str,
+        #[case] native: MetadataFormat,
+        #[case] expected: MetadataFormat,
+    ) {
+        assert_eq!(MetadataFormat::negotiate(accept, native), expected);
+    }
+
+    #[test]
+    fn yaml_to_json() {
+        let yaml = Bytes::from_static(
+            indoc! {"
+            name: Brainscan of a Unicorn
+            int: 42
+            license:
+            - spdx:CC-BY-4.0
+            void: null
+        "}
+            .as_bytes(),
+        );
+        let json = MetadataFormat::Yaml
+            .convert(yaml, MetadataFormat::Json)
+            .unwrap();
+        assert_eq!(
+            std::str::from_utf8(&json).unwrap(),
+            indoc! {r#"
+            {
+              "name": "Brainscan of a Unicorn",
+              "int": 42,
+              "license": [
+                "spdx:CC-BY-4.0"
+              ],
+              "void": null
+            }
+        "#}
+        );
+    }
+
+    #[test]
+    fn json_to_yaml() {
+        let json = Bytes::from_static(br#"{"name": "Foo", "version": "0.210512.1623", "n": 1}"#);
+        let yaml = MetadataFormat::Json
+            .convert(json, MetadataFormat::Yaml)
+            .unwrap();
+        assert_eq!(
+            std::str::from_utf8(&yaml).unwrap(),
+            "name: Foo\nversion: 0.210512.1623\nn: 1\n"
+        );
+    }
+
+    #[test]
+    fn convert_bad_yaml() {
+        let r =
+            MetadataFormat::Yaml.convert(Bytes::from_static(b"foo: [bar"), MetadataFormat::Json);
+        assert!(matches!(r, Err(ConvertError::Yaml(_))));
+    }
+}
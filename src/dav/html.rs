@@ -1,18 +1,129 @@
 //! Rendering resource listings as HTML documents
-use super::util::Href;
-use super::{DavCollection, DavItem, DavResource, ResourceKind};
-use crate::consts::HTML_TIMESTAMP_FORMAT;
-use crate::paths::Component;
+use super::i18n::{Language, Messages};
+use super::mount::MountDescription;
+use super::path::DavPath;
+use super::util::{version_path, Href};
+use super::{DavCollection, DavItem, DavResource, DavUnavailable, HasProperties, ResourceKind};
+use crate::consts::{
+    HTML_STREAM_BATCH_SIZE, HTML_STREAM_BUFFER, HTML_TIMESTAMP_FORMAT, ZARR_EXTENSIONS,
+};
+use crate::dandi::{DandisetId, VersionCitation};
+use crate::mountlimit::Mount;
+use crate::paths::{escape_name, escape_path, Component, PureDirPath, PurePath};
+use crate::upstream::UpstreamMonitor;
+use crate::zarrman::ZarrNodeType;
+use axum::body::{Body, Bytes};
 use humansize::{format_size_i, BINARY};
-use serde::{ser::Serializer, Serialize};
-use std::collections::HashMap;
-use tera::{Context, Error, Filter, Tera, Value};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tera::{escape_html, Context, Error, Filter, Tera, Value};
 use thiserror::Error;
 use time::OffsetDateTime;
 
-/// The [Tera](https://keats.github.io/tera/) template for HTML collection
-/// views
-static COLLECTION_TEMPLATE: &str = include_str!("templates/collection.html.tera");
+/// The name of the [Tera](https://keats.github.io/tera/) template for the
+/// start of an HTML collection view, up through the opening of the table body
+static HEAD_TEMPLATE: &str = "collection-head.html";
+
+/// The name of the template for a batch of rows of an HTML collection view
+static ROWS_TEMPLATE: &str = "collection-rows.html";
+
+/// The name of the template for the end of an HTML collection view, starting
+/// at the close of the table body
+static FOOT_TEMPLATE: &str = "collection-foot.html";
+
+/// The names & sources of the templates for HTML collection views.  A view is
+/// rendered as the output of the head template, followed by the output of the
+/// rows template for each successive batch of rows, followed by the output of
+/// the foot template, so that views of large collections can be sent to the
+/// client in pieces.
+static COLLECTION_TEMPLATES: [(&str, &str); 3] = [
+    (
+        HEAD_TEMPLATE,
+        include_str!("templates/collection-head.html.tera"),
+    ),
+    (
+        ROWS_TEMPLATE,
+        include_str!("templates/collection-rows.html.tera"),
+    ),
+    (
+        FOOT_TEMPLATE,
+        include_str!("templates/collection-foot.html.tera"),
+    ),
+];
+
+/// The name & source of the template for the page shown when a requested
+/// Dandiset exists only on another Archive instance
+static OTHER_INSTANCE_TEMPLATE: (&str, &str) = (
+    "other-instance.html",
+    include_str!("templates/other-instance.html.tera"),
+);
+
+/// The names of the partial templates included by the page templates, along with their default sources.  Each partial can be
+/// overridden by a snippet supplied via [`HtmlSnippets`].
+static PARTIAL_TEMPLATES: [(&str, &str); 3] = [
+    ("extra-head.html", ""),
+    ("header.html", ""),
+    ("footer.html", include_str!("templates/footer.html.tera")),
+];
+
+/// Operator-supplied snippets of HTML to inject into HTML collection views in
+/// place of the default partial templates.  Each snippet is itself a Tera
+/// template with access to the same variables as the rest of the page, and
+/// its output is not escaped.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct HtmlSnippets {
+    /// HTML to add to the end of each page's `<head>` element
+    pub(crate) extra_head: Option<String>,
+
+    /// HTML to add to the start of each page's `<body>` element
+    pub(crate) header: Option<String>,
+
+    /// HTML to display in each page's `<footer>` element instead of the
+    /// default `dandidav` version information
+    pub(crate) footer: Option<String>,
+}
+
+impl HtmlSnippets {
+    /// Return the snippet (if any) overriding the partial template with the
+    /// given name
+    fn get(&self, template_name: &str) -> Option<&str> {
+        match template_name {
+            "extra-head.html" => self.extra_head.as_deref(),
+            "header.html" => self.header.as_deref(),
+            "footer.html" => self.footer.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// A color scheme for HTML views, for making it clear to users which of
+/// several `dandidav` deployments (e.g., production or staging) they are
+/// browsing
+#[derive(clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) enum Theme {
+    /// The standard color scheme
+    #[default]
+    Default,
+
+    /// A color scheme marking the deployment as serving staging data
+    Staging,
+
+    /// A color scheme marking the deployment as a development instance
+    Development,
+}
+
+impl Theme {
+    /// Return the CSS class to apply to the `<body>` of HTML views in order
+    /// to select the theme, if any
+    fn css_class(self) -> Option<&'static str> {
+        match self {
+            Theme::Default => None,
+            Theme::Staging => Some("theme-staging"),
+            Theme::Development => Some("theme-development"),
+        }
+    }
+}
 
 /// A template manager
 pub(crate) struct Templater {
@@ -21,132 +132,724 @@ pub(crate) struct Templater {
 
     /// Site title to display in HTML responses
     title: String,
+
+    /// URL of a logo to display next to the site title, if any
+    logo: Option<String>,
+
+    /// Color scheme of HTML responses
+    theme: Theme,
+
+    /// Monitor of the Archive's metadata schema version, for warning users
+    /// when it is newer than any that `dandidav` has been tested against
+    upstream: Option<Arc<UpstreamMonitor>>,
+
+    /// Descriptions of top-level collections to show in the root view and in
+    /// the collections' own views
+    mount_descriptions: BTreeMap<Mount, String>,
 }
 
 impl Templater {
     /// Create a new templater with site title `title` and load all templates
-    /// into it
+    /// into it, with the partial templates overridden by any snippets in
+    /// `snippets`
     ///
     /// # Errors
     ///
-    /// If any template fails to load, a [`TemplateError::Load`] is returned.
-    pub(crate) fn new(title: String) -> Result<Self, TemplateError> {
+    /// If any template or snippet fails to load, a [`TemplateError::Load`] is
+    /// returned.
+    pub(crate) fn new(title: String, snippets: &HtmlSnippets) -> Result<Self, TemplateError> {
         let mut engine = Tera::default();
+        // All text in template contexts is escaped when the contexts are
+        // built (see `HtmlText`), so Tera must not escape it again.
+        engine.autoescape_on(Vec::new());
         engine.register_filter("formatsize", FormatSizeFilter);
-        engine
-            .add_raw_template("collection.html", COLLECTION_TEMPLATE)
-            .map_err(|source| TemplateError::Load {
-                template_name: "collection.html",
-                source,
-            })?;
-        Ok(Templater { engine, title })
+        for (template_name, default) in PARTIAL_TEMPLATES {
+            // Partials are included inline, so trailing newlines (as found at
+            // the ends of most files) are dropped.
+            let source = snippets.get(template_name).unwrap_or(default).trim_end();
+            engine
+                .add_raw_template(template_name, source)
+                .map_err(|source| TemplateError::Load {
+                    template_name,
+                    source,
+                })?;
+        }
+        for (template_name, source) in COLLECTION_TEMPLATES
+            .into_iter()
+            .chain(std::iter::once(OTHER_INSTANCE_TEMPLATE))
+        {
+            engine
+                .add_raw_template(template_name, source)
+                .map_err(|source| TemplateError::Load {
+                    template_name,
+                    source,
+                })?;
+        }
+        Ok(Templater {
+            engine,
+            title,
+            logo: None,
+            theme: Theme::default(),
+            upstream: None,
+            mount_descriptions: BTreeMap::new(),
+        })
+    }
+
+    /// Display the logo at the URL `logo` (if any) next to the site title and
+    /// use the color scheme `theme` in HTML responses
+    pub(crate) fn with_branding(mut self, logo: Option<String>, theme: Theme) -> Templater {
+        self.logo = logo;
+        self.theme = theme;
+        self
+    }
+
+    /// Display a warning in HTML collection views whenever `upstream` reports
+    /// that the Archive is using an untested newer metadata schema
+    pub(crate) fn with_upstream(mut self, upstream: Arc<UpstreamMonitor>) -> Templater {
+        self.upstream = Some(upstream);
+        self
+    }
+
+    /// Return the site title
+    pub(super) fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Show the given descriptions of top-level collections in HTML views.
+    /// If a collection is given more than one description, the last one
+    /// wins.
+    pub(crate) fn with_mount_descriptions(
+        mut self,
+        descriptions: Vec<MountDescription>,
+    ) -> Templater {
+        self.mount_descriptions = descriptions
+            .into_iter()
+            .map(|md| (md.mount, md.text))
+            .collect();
+        self
+    }
+
+    /// Return the escaped description of the top-level collection `mount`,
+    /// if it has one
+    fn mount_description(&self, mount: Option<Mount>) -> Option<HtmlText> {
+        mount
+            .and_then(|m| self.mount_descriptions.get(&m))
+            .map(|text| HtmlText::escape(text))
     }
 
     /// Render an HTML document containing a table listing the resources in
-    /// `entries`.  `pathparts` contains the individual components of the
-    /// request URL path.
+    /// `entries`, with UI text in the language `language`.  `pathparts`
+    /// contains the individual components of the request URL path.  If
+    /// `citation` is given, the collection's citation details are displayed
+    /// above the table.
     pub(super) fn render_collection(
         &self,
         entries: Vec<DavResource>,
+        citation: Option<VersionCitation>,
         pathparts: Vec<Component>,
+        language: Language,
     ) -> Result<String, TemplateError> {
-        let template_name = "collection.html";
-        let colctx = self.collection_context(entries, pathparts);
+        let mut html = String::new();
+        self.render_collection_pieces(
+            entries,
+            citation,
+            pathparts,
+            language,
+            usize::MAX,
+            |piece| {
+                html.push_str(&piece);
+                true
+            },
+        )?;
+        Ok(html)
+    }
+
+    /// Render an HTML document listing the resources in `entries` (as for
+    /// [`Templater::render_collection()`]) as a response body that is sent
+    /// to the client in pieces as they are rendered.
+    ///
+    /// Rendering takes place on Tokio's blocking thread pool, a batch of rows
+    /// at a time, and is paused while the client is slow to receive the
+    /// rendered pieces, so that the whole document is never held in memory at
+    /// once.  If rendering fails partway through, the error is logged and the
+    /// body ends with an error, causing the connection to be aborted.
+    pub(super) fn render_collection_stream(
+        self: Arc<Self>,
+        entries: Vec<DavResource>,
+        citation: Option<VersionCitation>,
+        pathparts: Vec<Component>,
+        language: Language,
+    ) -> Body {
+        let (tx, mut rx) =
+            tokio::sync::mpsc::channel::<Result<Bytes, TemplateError>>(HTML_STREAM_BUFFER);
+        tokio::task::spawn_blocking(move || {
+            let r = self.render_collection_pieces(
+                entries,
+                citation,
+                pathparts,
+                language,
+                HTML_STREAM_BATCH_SIZE,
+                // Once the receiver is dropped (e.g., because the client
+                // disconnected), there's no point in rendering any more.
+                |piece| tx.blocking_send(Ok(Bytes::from(piece))).is_ok(),
+            );
+            if let Err(e) = r {
+                tracing::error!(error = ?e, "Failed to render streamed collection view");
+                // If the receiver is gone, there's no one to tell.
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+        Body::from_stream(futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx)))
+    }
+
+    /// Render an HTML document listing the resources in `entries`, passing
+    /// each successive piece of the document to `sink` as it is rendered.
+    /// Rows of the table are rendered `batch_size` at a time.  If `sink`
+    /// returns `false`, rendering stops early.
+    fn render_collection_pieces<F>(
+        &self,
+        entries: Vec<DavResource>,
+        citation: Option<VersionCitation>,
+        pathparts: Vec<Component>,
+        language: Language,
+        batch_size: usize,
+        mut sink: F,
+    ) -> Result<(), TemplateError>
+    where
+        F: FnMut(String) -> bool,
+    {
+        let (colctx, rows) = self.collection_context(entries, citation, pathparts, language);
+        let msg = colctx.msg.clone();
+        let show_descriptions = colctx.show_descriptions;
         let context =
             Context::from_serialize(colctx).map_err(|source| TemplateError::MakeContext {
+                template_name: HEAD_TEMPLATE,
+                source,
+            })?;
+        if !sink(self.render(HEAD_TEMPLATE, &context)?) {
+            return Ok(());
+        }
+        for batch in rows.chunks(batch_size) {
+            let mut rowctx = Context::new();
+            rowctx.insert("rows", batch);
+            rowctx.insert("msg", &msg);
+            rowctx.insert("show_descriptions", &show_descriptions);
+            if !sink(self.render(ROWS_TEMPLATE, &rowctx)?) {
+                return Ok(());
+            }
+        }
+        sink(self.render(FOOT_TEMPLATE, &context)?);
+        Ok(())
+    }
+
+    /// Render an HTML document, with text in the language `language`,
+    /// informing the user that the Dandiset `dandiset_id` does not exist on
+    /// this Archive instance but can be found on another instance at `href`
+    pub(super) fn render_other_instance(
+        &self,
+        dandiset_id: &DandisetId,
+        href: &str,
+        language: Language,
+    ) -> Result<String, TemplateError> {
+        let (template_name, _) = OTHER_INSTANCE_TEMPLATE;
+        let messages = language.messages();
+        let ctx = OtherInstanceContext {
+            lang: HtmlText::escape(language.code()),
+            title: HtmlText::escape(&format!("{} \u{2014} {}", self.title, messages.not_found)),
+            theme: self.theme.css_class().map(HtmlText::escape),
+            message: HtmlText::escape(
+                &messages
+                    .other_instance
+                    .replace("{dandiset_id}", dandiset_id.as_ref()),
+            ),
+            href: HtmlText::escape(href),
+            package_url: HtmlText::escape(env!("CARGO_PKG_REPOSITORY")),
+            package_version: HtmlText::escape(env!("CARGO_PKG_VERSION")),
+            package_commit: option_env!("GIT_COMMIT").map(HtmlText::escape),
+        };
+        let context =
+            Context::from_serialize(ctx).map_err(|source| TemplateError::MakeContext {
                 template_name,
                 source,
             })?;
+        self.render(template_name, &context)
+    }
+
+    /// Render the template named `template_name` with the given context
+    fn render(
+        &self,
+        template_name: &'static str,
+        context: &Context,
+    ) -> Result<String, TemplateError> {
         self.engine
-            .render(template_name, &context)
+            .render(template_name, context)
             .map_err(|source| TemplateError::Render {
                 template_name,
                 source,
             })
     }
 
-    /// Construct the context for displaying the given `entries`.  `pathparts`
-    /// contains the individual components of the request URL path.
+    /// Construct the context for displaying the given `entries` (and
+    /// `citation`, if any) in the language `language`, along with the table
+    /// rows to display.  `pathparts` contains the individual components of
+    /// the request URL path.
     fn collection_context(
         &self,
         entries: Vec<DavResource>,
+        citation: Option<VersionCitation>,
         pathparts: Vec<Component>,
-    ) -> CollectionContext {
+        language: Language,
+    ) -> (CollectionContext, Vec<RowContext>) {
+        let messages = language.messages();
         let mut rows = entries.into_iter().map(ColRow::from).collect::<Vec<_>>();
-        rows.sort_unstable();
+        // The root lists the top-level collections in their configured order
+        if !pathparts.is_empty() {
+            rows.sort_unstable();
+        }
         if let Some((_, pp)) = pathparts.split_last() {
             rows.insert(
                 0,
                 ColRow::parentdir(Href::from_path(&abs_dir_from_components(pp))),
             );
         }
+        let export_root = export_root(&pathparts);
+        let description =
+            self.mount_description(match DavPath::from_components(pathparts.clone()) {
+                Some(DavPath::DandisetIndex) => Some(Mount::Dandisets),
+                Some(DavPath::ZarrIndex) => Some(Mount::Zarrs),
+                _ => None,
+            });
+        let rows = rows
+            .into_iter()
+            .map(|row| {
+                let description = self.mount_description(match row.kind {
+                    ResourceKind::DandisetIndex => Some(Mount::Dandisets),
+                    ResourceKind::ZarrIndex => Some(Mount::Zarrs),
+                    _ => None,
+                });
+                RowContext::new(row, messages, export_root.as_ref(), description)
+            })
+            .collect::<Vec<_>>();
         let title_path = abs_dir_from_components(&pathparts);
         let title = format!("{} \u{2014} {}", self.title, title_path);
-        CollectionContext {
-            title,
+        let colctx = CollectionContext {
+            lang: HtmlText::escape(language.code()),
+            msg: MessagesContext::from(messages),
+            title: HtmlText::escape(&title),
+            path: HtmlText::escape(&title_path),
+            logo: self.logo.as_deref().map(HtmlText::escape),
+            theme: self.theme.css_class().map(HtmlText::escape),
             breadcrumbs: self.make_breadcrumbs(pathparts),
-            rows,
-            package_url: env!("CARGO_PKG_REPOSITORY"),
-            package_version: env!("CARGO_PKG_VERSION"),
-            package_commit: option_env!("GIT_COMMIT"),
-        }
+            citation: citation.as_ref().map(CitationContext::from),
+            upstream_warning: self
+                .upstream
+                .as_ref()
+                .and_then(|u| u.newer_schema_version())
+                .map(|v| HtmlText::escape(&messages.upstream_warning.replace("{version}", &v))),
+            export_action: export_root
+                .as_ref()
+                .map(|root| HtmlText::escape(Href::from_path(&format!("/{root}")).as_ref())),
+            description,
+            show_descriptions: rows.iter().any(|r| r.description.is_some()),
+            package_url: HtmlText::escape(env!("CARGO_PKG_REPOSITORY")),
+            package_version: HtmlText::escape(env!("CARGO_PKG_VERSION")),
+            package_commit: option_env!("GIT_COMMIT").map(HtmlText::escape),
+        };
+        (colctx, rows)
     }
 
     /// Create breadcrumbs for the given request URL path components
     fn make_breadcrumbs(&self, pathparts: Vec<Component>) -> Vec<Link> {
         let mut links = Vec::with_capacity(pathparts.len().saturating_add(1));
-        let mut cumpath = String::from("/");
         links.push(Link {
-            text: self.title.clone(),
-            href: Href::from_path(&cumpath),
+            text: HtmlText::escape(&self.title),
+            href: HtmlText::escape(Href::from_path("/").as_ref()),
         });
-        for p in pathparts {
-            cumpath.push_str(&p);
-            cumpath.push('/');
-            links.push(Link {
-                text: p.into(),
-                href: Href::from_path(&cumpath),
-            });
+        if let Some(path) = PurePath::from_components(pathparts) {
+            for dirpath in path.to_dir_path().ancestors() {
+                links.push(Link {
+                    text: HtmlText::escape(dirpath.name_str()),
+                    href: HtmlText::escape(Href::from_path(&format!("/{dirpath}")).as_ref()),
+                });
+            }
         }
         links
     }
 }
 
-/// Context to provide to the `collection.html` template
+/// Return the path of the Dandiset version whose resources can be selected
+/// for download in the HTML view of the collection at the request URL path
+/// with components `pathparts`.  This is the case for the version itself and
+/// for its asset folders, but not for Zarrs or anything inside them, as
+/// their entries cannot be exported.
+fn export_root(pathparts: &[Component]) -> Option<PureDirPath> {
+    match DavPath::from_components(pathparts.to_vec())? {
+        DavPath::Version {
+            dandiset_id,
+            version,
+        } => Some(version_path(&dandiset_id, &version)),
+        DavPath::DandiResource {
+            dandiset_id,
+            version,
+            path,
+        } if !path.to_dir_path().ancestors().any(|p| {
+            p.extension()
+                .is_some_and(|ext| ZARR_EXTENSIONS.contains(&ext))
+        }) =>
+        {
+            Some(version_path(&dandiset_id, &version))
+        }
+        _ => None,
+    }
+}
+
+/// A string that has been escaped for safe inclusion in HTML text & quoted
+/// attribute values.
+///
+/// Template contexts store all of their text as `HtmlText` values (never as
+/// plain strings), and so anything derived from asset paths or other
+/// Archive-supplied data is guaranteed to be escaped before it reaches a
+/// template.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+struct HtmlText(String);
+
+impl HtmlText {
+    /// Escape `s` for inclusion in HTML
+    fn escape(s: &str) -> HtmlText {
+        HtmlText(escape_html(s))
+    }
+}
+
+/// Context to provide to the `collection-head.html` & `collection-foot.html`
+/// templates
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 struct CollectionContext {
+    /// Language code of the page
+    lang: HtmlText,
+
+    /// UI text in the page's language
+    msg: MessagesContext,
+
     /// Page title
-    title: String,
+    title: HtmlText,
+
+    /// Path of the collection, as shown in the table's caption
+    path: HtmlText,
+
+    /// URL of the logo to display next to the site title
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logo: Option<HtmlText>,
+
+    /// CSS class selecting the page's color scheme
+    #[serde(skip_serializing_if = "Option::is_none")]
+    theme: Option<HtmlText>,
 
     /// Breadcrumb links
     breadcrumbs: Vec<Link>,
 
-    /// Rows of the table
-    rows: Vec<ColRow>,
+    /// Citation details of the Dandiset version being displayed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    citation: Option<CitationContext>,
+
+    /// Warning that the Archive is using an untested newer metadata schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    upstream_warning: Option<HtmlText>,
+
+    /// URL of the Dandiset version to request export manifests of selected
+    /// resources from, if resources in the collection can be selected for
+    /// download
+    #[serde(skip_serializing_if = "Option::is_none")]
+    export_action: Option<HtmlText>,
+
+    /// The operator-supplied description of the collection, if it is a
+    /// top-level collection with one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<HtmlText>,
+
+    /// Whether the table has a column of descriptions, i.e., whether any of
+    /// the rows has one
+    show_descriptions: bool,
 
     /// URL to link "dandidav" in the page's footer to
-    package_url: &'static str,
+    package_url: HtmlText,
 
     /// `dandidav` version
-    package_version: &'static str,
+    package_version: HtmlText,
 
     /// Current `dandidav` commit hash (if known)
     #[serde(skip_serializing_if = "Option::is_none")]
-    package_commit: Option<&'static str>,
+    package_commit: Option<HtmlText>,
+}
+
+/// The citation details of a Dandiset version, as provided to the
+/// `collection-head.html` template
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct CitationContext {
+    /// The version's DOI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    doi: Option<HtmlText>,
+
+    /// The URL at which the DOI resolves
+    #[serde(skip_serializing_if = "Option::is_none")]
+    doi_url: Option<HtmlText>,
+
+    /// The version's recommended citation string
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<HtmlText>,
+
+    /// The SPDX identifiers of the version's licenses
+    licenses: Vec<HtmlText>,
+}
+
+impl From<&VersionCitation> for CitationContext {
+    fn from(citation: &VersionCitation) -> CitationContext {
+        CitationContext {
+            doi: citation.doi.as_deref().map(HtmlText::escape),
+            doi_url: citation
+                .doi
+                .as_deref()
+                .map(|doi| HtmlText::escape(&format!("https://doi.org/{doi}"))),
+            text: citation.citation.as_deref().map(HtmlText::escape),
+            licenses: citation
+                .license
+                .iter()
+                .map(|lic| HtmlText::escape(lic.strip_prefix("spdx:").unwrap_or(lic)))
+                .collect(),
+        }
+    }
+}
+
+/// The UI text of an HTML view, as provided to the templates; see
+/// [`Messages`] for the meanings of the fields
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct MessagesContext {
+    name: HtmlText,
+    kind: HtmlText,
+    size: HtmlText,
+    created: HtmlText,
+    modified: HtmlText,
+    description: HtmlText,
+    metadata: HtmlText,
+    entries: HtmlText,
+    skip_link: HtmlText,
+    location: HtmlText,
+    contents: HtmlText,
+    not_available: HtmlText,
+    doi: HtmlText,
+    cite_as: HtmlText,
+    license: HtmlText,
+    select: HtmlText,
+    download_selected: HtmlText,
+    download_failed: HtmlText,
+}
+
+impl From<&Messages> for MessagesContext {
+    fn from(messages: &Messages) -> MessagesContext {
+        MessagesContext {
+            name: HtmlText::escape(messages.name),
+            kind: HtmlText::escape(messages.kind),
+            size: HtmlText::escape(messages.size),
+            created: HtmlText::escape(messages.created),
+            modified: HtmlText::escape(messages.modified),
+            description: HtmlText::escape(messages.description),
+            metadata: HtmlText::escape(messages.metadata),
+            entries: HtmlText::escape(messages.entries),
+            skip_link: HtmlText::escape(messages.skip_link),
+            location: HtmlText::escape(messages.location),
+            contents: HtmlText::escape(messages.contents),
+            not_available: HtmlText::escape(messages.not_available),
+            doi: HtmlText::escape(messages.doi),
+            cite_as: HtmlText::escape(messages.cite_as),
+            license: HtmlText::escape(messages.license),
+            select: HtmlText::escape(messages.select),
+            download_selected: HtmlText::escape(messages.download_selected),
+            download_failed: HtmlText::escape(messages.download_failed),
+        }
+    }
+}
+
+/// Context to provide to the `other-instance.html` template
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct OtherInstanceContext {
+    /// Language code of the page
+    lang: HtmlText,
+
+    /// Page title
+    title: HtmlText,
+
+    /// CSS class selecting the page's color scheme
+    #[serde(skip_serializing_if = "Option::is_none")]
+    theme: Option<HtmlText>,
+
+    /// Message telling the user where the Dandiset can be found
+    message: HtmlText,
+
+    /// URL of the requested resource on the other Archive instance
+    href: HtmlText,
+
+    /// URL to link "dandidav" in the page's footer to
+    package_url: HtmlText,
+
+    /// `dandidav` version
+    package_version: HtmlText,
+
+    /// Current `dandidav` commit hash (if known)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package_commit: Option<HtmlText>,
 }
 
 /// A hyperlink to display in an HTML document
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 struct Link {
     /// The text of the link
-    text: String,
+    text: HtmlText,
 
     /// The value of the link's `href` attribute
-    href: Href,
+    href: HtmlText,
+}
+
+/// A row of a table listing the resources within a collection, as provided
+/// to the `collection-rows.html` template
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct RowContext {
+    /// The name to display for the resource
+    name: HtmlText,
+
+    /// URL to link the resource to
+    href: HtmlText,
+
+    /// Tooltip for the link to the resource, giving the resource's basename
+    /// when a different name is displayed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<HtmlText>,
+
+    /// Accessible name for the link to the resource, for when the resource's
+    /// name alone is not descriptive (i.e., for the parent directory)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<HtmlText>,
+
+    /// `true` iff the resource is a collection
+    is_dir: bool,
+
+    /// An icon marking the resource as a Zarr group or array, if it is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zarr_node: Option<ZarrNodeIcon>,
+
+    /// The resource's path relative to the Dandiset version, in escaped form,
+    /// if it can be selected for download
+    #[serde(skip_serializing_if = "Option::is_none")]
+    export_path: Option<HtmlText>,
+
+    /// Type of resource
+    kind: HtmlText,
+
+    /// The size of the resource
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<i64>,
+
+    /// The number of entries in the resource, if it is a Zarr
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry_count: Option<u64>,
+
+    /// The timestamp at which the resource was created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<HtmlText>,
+
+    /// The timestamp at which the resource was last modified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<HtmlText>,
+
+    /// A URL for retrieving the resource's associated metadata (if any) from
+    /// the Archive instance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata_url: Option<HtmlText>,
+
+    /// The operator-supplied description of the resource, if it is a
+    /// top-level collection with one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<HtmlText>,
+}
+
+impl RowContext {
+    /// Construct the context for displaying `row`, with the type of resource
+    /// described using `messages`.  If `export_root` is given, the row can be
+    /// selected for download as part of the Dandiset version at that path.
+    fn new(
+        row: ColRow,
+        messages: &Messages,
+        export_root: Option<&PureDirPath>,
+        description: Option<HtmlText>,
+    ) -> RowContext {
+        let export_path = export_root
+            .filter(|_| row.kind.is_exportable())
+            .zip(row.path.as_deref())
+            .and_then(|(root, path)| path.strip_prefix(root.as_ref()))
+            .filter(|rel| !rel.is_empty())
+            .map(|rel| HtmlText::escape(&escape_path(rel)));
+        let (name, title) = match row.display_name {
+            Some(display) => (
+                HtmlText::escape(&display),
+                Some(HtmlText::escape(&row.name)),
+            ),
+            None => (HtmlText::escape(&row.name), None),
+        };
+        RowContext {
+            name,
+            href: HtmlText::escape(row.href.as_ref()),
+            title,
+            label: (row.kind == ResourceKind::Parent)
+                .then(|| HtmlText::escape(messages.kind_name(row.kind))),
+            is_dir: row.is_dir,
+            zarr_node: row.zarr_node_type.map(|nt| ZarrNodeIcon::new(nt, messages)),
+            export_path,
+            kind: HtmlText::escape(messages.kind_name(row.kind)),
+            size: row.size,
+            entry_count: row.entry_count,
+            created: row
+                .created
+                .map(|ts| HtmlText::escape(&format_timestamp(ts))),
+            modified: row
+                .modified
+                .map(|ts| HtmlText::escape(&format_timestamp(ts))),
+            metadata_url: row.metadata_url.map(|u| HtmlText::escape(u.as_ref())),
+            description,
+        }
+    }
 }
 
-/// A row of a table listing the resources within a collection
-#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+/// An icon displayed next to the name of a Zarr group or array, as provided
+/// to the `collection-rows.html` template
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct ZarrNodeIcon {
+    /// The icon character
+    icon: &'static str,
+
+    /// The CSS class selecting the icon's styling
+    class: &'static str,
+
+    /// Accessible name & tooltip for the icon
+    label: HtmlText,
+}
+
+impl ZarrNodeIcon {
+    fn new(node_type: ZarrNodeType, messages: &Messages) -> ZarrNodeIcon {
+        let (icon, label) = match node_type {
+            ZarrNodeType::Group => ("\u{229E}", messages.zarr_group),
+            ZarrNodeType::Array => ("\u{25A6}", messages.zarr_array),
+        };
+        ZarrNodeIcon {
+            icon,
+            class: node_type.as_str(),
+            label: HtmlText::escape(label),
+        }
+    }
+}
+
+/// A row of a table listing the resources within a collection.  Rows are
+/// sorted in this form (i.e., by unescaped name) before being converted to
+/// [`RowContext`]s.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 struct ColRow {
     /// Resource basename
     name: String,
@@ -157,31 +860,34 @@ struct ColRow {
     /// `true` iff the resource is a collection
     is_dir: bool,
 
+    /// Whether the resource is a Zarr group or array, if it is one
+    zarr_node_type: Option<ZarrNodeType>,
+
+    /// The path at which the resource is served by `dandidav`, without a
+    /// trailing slash, if known
+    path: Option<String>,
+
     /// Type of resource
     kind: ResourceKind,
 
     /// The size of the resource
-    #[serde(skip_serializing_if = "Option::is_none")]
     size: Option<i64>,
 
+    /// The number of entries in the resource, if it is a Zarr
+    entry_count: Option<u64>,
+
     /// The timestamp at which the resource was created
-    #[serde(
-        skip_serializing_if = "Option::is_none",
-        serialize_with = "maybe_timestamp"
-    )]
     created: Option<OffsetDateTime>,
 
     /// The timestamp at which the resource was last modified
-    #[serde(
-        skip_serializing_if = "Option::is_none",
-        serialize_with = "maybe_timestamp"
-    )]
     modified: Option<OffsetDateTime>,
 
     /// A URL for retrieving the resource's associated metadata (if any) from
     /// the Archive instance
-    #[serde(skip_serializing_if = "Option::is_none")]
     metadata_url: Option<Href>,
+
+    /// The name to display for the resource in place of `name`, if any
+    display_name: Option<String>,
 }
 
 impl ColRow {
@@ -192,11 +898,15 @@ impl ColRow {
             name: "..".to_owned(),
             href,
             is_dir: true,
+            zarr_node_type: None,
+            path: None,
             kind: ResourceKind::Parent,
             size: None,
+            entry_count: None,
             created: None,
             modified: None,
             metadata_url: None,
+            display_name: None,
         }
     }
 }
@@ -206,6 +916,7 @@ impl From<DavResource> for ColRow {
         match res {
             DavResource::Collection(col) => col.into(),
             DavResource::Item(item) => item.into(),
+            DavResource::Unavailable(r) => r.into(),
         }
     }
 }
@@ -213,14 +924,21 @@ impl From<DavResource> for ColRow {
 impl From<DavCollection> for ColRow {
     fn from(col: DavCollection) -> ColRow {
         ColRow {
-            name: col.name().unwrap_or("/").to_owned(),
+            name: escape_name(col.name().unwrap_or("/")).into_owned(),
             href: col.web_link(),
             is_dir: true,
+            zarr_node_type: col.zarr_node_type,
+            path: col
+                .path
+                .as_ref()
+                .map(|p| p.as_ref().trim_end_matches('/').to_owned()),
             kind: col.kind,
             size: col.size,
+            entry_count: col.entry_count.ok().flatten(),
             created: col.created,
             modified: col.modified,
             metadata_url: col.metadata_url.map(Into::into),
+            display_name: col.display_name.map(|n| escape_name(&n).into_owned()),
         }
     }
 }
@@ -228,14 +946,37 @@ impl From<DavCollection> for ColRow {
 impl From<DavItem> for ColRow {
     fn from(item: DavItem) -> ColRow {
         ColRow {
-            name: item.name().to_owned(),
+            name: escape_name(item.name()).into_owned(),
             href: item.web_link(),
             is_dir: false,
+            zarr_node_type: None,
+            path: Some(item.path.to_string()),
             kind: item.kind,
             size: item.size,
+            entry_count: None,
             created: item.created,
             modified: item.modified,
             metadata_url: item.metadata_url.map(Into::into),
+            display_name: None,
+        }
+    }
+}
+
+impl From<DavUnavailable> for ColRow {
+    fn from(res: DavUnavailable) -> ColRow {
+        ColRow {
+            name: escape_name(res.name()).into_owned(),
+            href: res.href(),
+            is_dir: false,
+            zarr_node_type: None,
+            path: None,
+            kind: ResourceKind::Unavailable,
+            size: None,
+            entry_count: None,
+            created: None,
+            modified: None,
+            metadata_url: None,
+            display_name: None,
         }
     }
 }
@@ -264,22 +1005,11 @@ pub(crate) enum TemplateError {
     },
 }
 
-/// If `ts` is non-`None`, format it and serialize the resulting string to
-/// `serializer`
-fn maybe_timestamp<S: Serializer>(
-    ts: &Option<OffsetDateTime>,
-    serializer: S,
-) -> Result<S::Ok, S::Error> {
-    match ts.as_ref() {
-        Some(ts) => {
-            let s = ts
-                .to_offset(time::UtcOffset::UTC)
-                .format(&HTML_TIMESTAMP_FORMAT)
-                .expect("timestamp formatting should not fail");
-            serializer.serialize_some(&s)
-        }
-        None => serializer.serialize_none(),
-    }
+/// Format a timestamp in UTC for display in an HTML view
+fn format_timestamp(ts: OffsetDateTime) -> String {
+    ts.to_offset(time::UtcOffset::UTC)
+        .format(&HTML_TIMESTAMP_FORMAT)
+        .expect("timestamp formatting should not fail")
 }
 
 /// Given an iterator of `&Component` values, join them together with forward
@@ -326,7 +1056,7 @@ fn formatsize(size: i64) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::dav::types::Redirect;
+    use crate::dav::types::{ArchiveIds, Digests, Redirect};
     use rstest::rstest;
 
     #[rstest]
@@ -344,32 +1074,106 @@ mod tests {
         assert_eq!(formatsize(size), s);
     }
 
+    mod escaping {
+        use super::*;
+        use crate::dav::util::arb_hostile_component;
+        use crate::dav::DavContent;
+        use proptest::prelude::*;
+        use testutils::parse_collection_page;
+
+        proptest! {
+            #[test]
+            fn hostile_names(
+                dirname in arb_hostile_component(),
+                filename in arb_hostile_component(),
+            ) {
+                let title = r#"<b>"dandidav"</b> & 'co'"#;
+                let templater = Templater::new(title.to_owned(), &HtmlSnippets::default()).unwrap();
+                let entries = vec![
+                    DavResource::Item(DavItem {
+                        path: format!("{dirname}/{filename}").parse().unwrap(),
+                        created: None,
+                        modified: None,
+                        content_type: None,
+                        size: Some(42),
+                        etag: None,
+                        annex_key: None,
+                        permalink: None,
+                        archive_ids: ArchiveIds::default(),
+                        digests: Digests::default(),
+                        s3_version_id: None,
+                        kind: ResourceKind::Blob,
+                        content: DavContent::Blob(Bytes::new()),
+                        metadata_url: None,
+                    }),
+                ];
+                let rendered = templater
+                    .render_collection(entries, None, vec![dirname.clone()], Language::En)
+                    .unwrap();
+                let page = parse_collection_page(&rendered).unwrap();
+                let crumbs = page
+                    .breadcrumbs
+                    .into_iter()
+                    .map(|link| link.text)
+                    .collect::<Vec<_>>();
+                prop_assert_eq!(crumbs, vec![title.to_owned(), dirname.to_string()]);
+                prop_assert_eq!(page.table.len(), 2);
+                let entry = &page.table[1];
+                prop_assert_eq!(&entry.name.text, &*escape_name(&filename));
+                let href = Href::from_path(&format!(
+                    "/{}/{}",
+                    escape_name(&dirname),
+                    escape_name(&filename)
+                ));
+                prop_assert_eq!(&entry.name.href, href.as_ref());
+                prop_assert_eq!(&entry.typekind, "Blob asset");
+            }
+        }
+    }
+
     mod render_collection {
         use super::*;
-        use crate::dav::{DavContent, DavResourceWithChildren};
+        use crate::dandi::ArchiveInfo;
+        use crate::dav::{DavContent, DavResourceWithChildren, ErrorClass, DEFAULT_ROOT_MOUNTS};
+        use http_body::Body as _;
+        use http_body_util::BodyExt;
         use pretty_assertions::assert_eq;
         use std::borrow::Cow;
+        use testutils::{check_accessibility, parse_collection_page};
         use time::macros::datetime;
 
         #[test]
         fn basic() {
-            let templater = Templater::new("Dandidav Test".to_owned()).unwrap();
+            let templater =
+                Templater::new("Dandidav Test".to_owned(), &HtmlSnippets::default()).unwrap();
             let entries = vec![
                 DavResource::Collection(DavCollection {
                     path: Some("foo/bar/baz/a.zarr/".parse().unwrap()),
                     created: Some(datetime!(2021-01-01 01:23:45 UTC)),
                     modified: Some(datetime!(2023-12-31 12:34:56 UTC)),
                     size: Some(1234567890),
+                    entry_count: Ok(None),
+                    permalink: None,
+                    archive_ids: ArchiveIds::default(),
+                    citation: VersionCitation::default(),
                     kind: ResourceKind::Zarr,
                     metadata_url: None,
+                    zarr_node_type: None,
+                    display_name: None,
                 }),
                 DavResource::Collection(DavCollection {
                     path: Some(r#"foo/bar/baz/"quoted"/"#.parse().unwrap()),
                     created: None,
                     modified: None,
                     size: None,
+                    entry_count: Ok(None),
+                    permalink: None,
+                    archive_ids: ArchiveIds::default(),
+                    citation: VersionCitation::default(),
                     kind: ResourceKind::Directory,
                     metadata_url: None,
+                    zarr_node_type: None,
+                    display_name: None,
                 }),
                 DavResource::Item(DavItem {
                     path: "foo/bar/baz/empty.txt".parse().unwrap(),
@@ -378,6 +1182,11 @@ mod tests {
                     content_type: Some("text/plain".into()),
                     size: Some(0),
                     etag: Some(r#""00000000""#.into()),
+                    annex_key: None,
+                    permalink: None,
+                    archive_ids: ArchiveIds::default(),
+                    digests: Digests::default(),
+                    s3_version_id: None,
                     kind: ResourceKind::Blob,
                     content: DavContent::Redirect(Redirect::Direct(
                         "https://dandiarchive-test.s3.amazonaws.com/blobs/empty.txt"
@@ -397,6 +1206,11 @@ mod tests {
                     content_type: Some("application/octet-stream".into()),
                     size: Some(123456),
                     etag: Some(r#""abcdefgh""#.into()),
+                    annex_key: None,
+                    permalink: None,
+                    archive_ids: ArchiveIds::default(),
+                    digests: Digests::default(),
+                    s3_version_id: None,
                     kind: ResourceKind::Blob,
                     content: DavContent::Redirect(Redirect::Direct(
                         "https://dandiarchive-test.s3.amazonaws.com/blobs/spaced%20file.dat"
@@ -416,19 +1230,26 @@ mod tests {
                     content_type: Some("text/yaml".into()),
                     size: Some(42),
                     etag: None,
+                    annex_key: None,
+                    permalink: None,
+                    archive_ids: ArchiveIds::default(),
+                    digests: Digests::default(),
+                    s3_version_id: None,
                     kind: ResourceKind::VersionMetadata,
-                    content: DavContent::Blob(Vec::new()),
+                    content: DavContent::Blob(Bytes::new()),
                     metadata_url: None,
                 }),
             ];
             let rendered = templater
                 .render_collection(
                     entries,
+                    None,
                     vec![
                         "foo".parse().unwrap(),
                         "bar".parse().unwrap(),
                         "baz".parse().unwrap(),
                     ],
+                    Language::En,
                 )
                 .unwrap();
             let commit_str = match option_env!("GIT_COMMIT") {
@@ -451,13 +1272,16 @@ mod tests {
 
         #[test]
         fn root() {
-            let templater = Templater::new("Dandidav Test".to_owned()).unwrap();
+            let templater =
+                Templater::new("Dandidav Test".to_owned(), &HtmlSnippets::default()).unwrap();
             let DavResourceWithChildren::Collection { children, .. } =
-                DavResourceWithChildren::root()
+                DavResourceWithChildren::root(&DEFAULT_ROOT_MOUNTS)
             else {
                 panic!("DavResourceWithChildren::root() should be a Collection");
             };
-            let rendered = templater.render_collection(children, Vec::new()).unwrap();
+            let rendered = templater
+                .render_collection(children, None, Vec::new(), Language::En)
+                .unwrap();
             let commit_str = match option_env!("GIT_COMMIT") {
                 Some(s) => Cow::from(format!(", commit {s}")),
                 None => Cow::from(""),
@@ -475,5 +1299,371 @@ mod tests {
             .replacen("{commit}", &commit_str, 1);
             assert_eq!(rendered, expected);
         }
+
+        #[test]
+        fn snippets() {
+            let snippets = HtmlSnippets {
+                extra_head: Some("<script src=\"/matomo.js\"></script>\n".to_owned()),
+                header: Some("<p class=\"notice\">Privacy notice</p>\n".to_owned()),
+                footer: Some("Funded by the NIH &mdash; {{title}}\n".to_owned()),
+            };
+            let templater = Templater::new("Dandidav Test".to_owned(), &snippets).unwrap();
+            let rendered = templater
+                .render_collection(Vec::new(), None, Vec::new(), Language::En)
+                .unwrap();
+            assert!(rendered.contains(
+                "href=\"/.static/styles.css\"/><script src=\"/matomo.js\"></script>\n</head>"
+            ));
+            assert!(
+                rendered.contains("Skip to listing</a><p class=\"notice\">Privacy notice</p>\n")
+            );
+            assert!(rendered.contains(
+                "<footer>\n        Funded by the NIH &mdash; Dandidav Test \u{2014} &#x2F;\n    </footer>"
+            ));
+            assert!(!rendered.contains("dandidav</a>, v"));
+        }
+
+        #[test]
+        fn branding() {
+            let templater = Templater::new("Dandidav Staging".to_owned(), &HtmlSnippets::default())
+                .unwrap()
+                .with_branding(Some("/logo.png".to_owned()), Theme::Staging);
+            let pathparts = vec!["foo".parse::<Component>().unwrap()];
+            let rendered = templater
+                .render_collection(many_entries(1), None, pathparts, Language::En)
+                .unwrap();
+            assert!(rendered.contains("<body class=\"theme-staging\">"));
+            assert!(rendered.contains(
+                "<a href=\"&#x2F;\"><img class=\"logo\" src=\"&#x2F;logo.png\" alt=\"\"/>Dandidav Staging</a> /"
+            ));
+            assert_eq!(rendered.matches("<img").count(), 1);
+            assert_eq!(check_accessibility(&rendered).unwrap(), Vec::new());
+            let page = parse_collection_page(&rendered).unwrap();
+            assert_eq!(page.breadcrumbs[0].text, "Dandidav Staging");
+        }
+
+        #[test]
+        fn bad_snippet() {
+            let snippets = HtmlSnippets {
+                footer: Some("{% if %}".to_owned()),
+                ..HtmlSnippets::default()
+            };
+            let r = Templater::new("Dandidav Test".to_owned(), &snippets);
+            assert!(matches!(
+                r,
+                Err(TemplateError::Load {
+                    template_name: "footer.html",
+                    ..
+                })
+            ));
+        }
+
+        #[rstest]
+        #[case(Language::En)]
+        #[case(Language::De)]
+        #[case(Language::Es)]
+        #[case(Language::Fr)]
+        fn accessible(#[case] language: Language) {
+            let templater =
+                Templater::new("Dandidav Test".to_owned(), &HtmlSnippets::default()).unwrap();
+            let pathparts = vec!["foo".parse::<Component>().unwrap()];
+            let rendered = templater
+                .render_collection(many_entries(3), None, pathparts, language)
+                .unwrap();
+            assert_eq!(check_accessibility(&rendered).unwrap(), Vec::new());
+        }
+
+        #[test]
+        fn zarr_node_icons() {
+            let templater =
+                Templater::new("Dandidav Test".to_owned(), &HtmlSnippets::default()).unwrap();
+            let folder = |name: &str, zarr_node_type| {
+                DavResource::Collection(DavCollection {
+                    path: Some(format!("zarrs/abc/a.zarr/{name}/").parse().unwrap()),
+                    created: None,
+                    modified: None,
+                    size: None,
+                    entry_count: Ok(None),
+                    permalink: None,
+                    archive_ids: ArchiveIds::default(),
+                    citation: VersionCitation::default(),
+                    kind: ResourceKind::Directory,
+                    metadata_url: None,
+                    zarr_node_type,
+                    display_name: None,
+                })
+            };
+            let entries = vec![
+                folder("0", Some(ZarrNodeType::Array)),
+                folder("c", None),
+                folder("labels", Some(ZarrNodeType::Group)),
+            ];
+            let pathparts = vec![
+                "zarrs".parse::<Component>().unwrap(),
+                "abc".parse::<Component>().unwrap(),
+                "a.zarr".parse::<Component>().unwrap(),
+            ];
+            let rendered = templater
+                .render_collection(entries, None, pathparts, Language::En)
+                .unwrap();
+            assert!(rendered.contains(
+                r#"<span class="zarr-node array" role="img" title="Zarr array" aria-label="Zarr array">▦</span> <a href="&#x2F;zarrs&#x2F;abc&#x2F;a.zarr&#x2F;0&#x2F;">"#
+            ));
+            assert!(rendered.contains(
+                r#"<span class="zarr-node group" role="img" title="Zarr group" aria-label="Zarr group">⊞</span> <a href="&#x2F;zarrs&#x2F;abc&#x2F;a.zarr&#x2F;labels&#x2F;">"#
+            ));
+            assert_eq!(rendered.matches("zarr-node").count(), 2);
+            assert_eq!(check_accessibility(&rendered).unwrap(), Vec::new());
+            let page = parse_collection_page(&rendered).unwrap();
+            assert_eq!(page.into_names(), ["../", "0/", "c/", "labels/"]);
+        }
+
+        #[test]
+        fn selection_checkboxes() {
+            let templater =
+                Templater::new("Dandidav Test".to_owned(), &HtmlSnippets::default()).unwrap();
+            let collection = |path: &str, kind| {
+                DavResource::Collection(DavCollection {
+                    path: Some(path.parse().unwrap()),
+                    created: None,
+                    modified: None,
+                    size: None,
+                    entry_count: Ok(None),
+                    permalink: None,
+                    archive_ids: ArchiveIds::default(),
+                    citation: VersionCitation::default(),
+                    kind,
+                    metadata_url: None,
+                    zarr_node_type: None,
+                    display_name: None,
+                })
+            };
+            let entries = vec![
+                collection("dandisets/000001/draft/foo/bar/", ResourceKind::Directory),
+                collection("dandisets/000001/draft/foo/img.zarr/", ResourceKind::Zarr),
+                DavResource::Item(DavItem {
+                    path: "dandisets/000001/draft/foo/notes.txt ".parse().unwrap(),
+                    created: None,
+                    modified: None,
+                    content_type: None,
+                    size: Some(42),
+                    etag: None,
+                    annex_key: None,
+                    permalink: None,
+                    archive_ids: ArchiveIds::default(),
+                    digests: Digests::default(),
+                    s3_version_id: None,
+                    kind: ResourceKind::Blob,
+                    content: DavContent::Missing,
+                    metadata_url: None,
+                }),
+                DavResource::Unavailable(DavUnavailable {
+                    path: "dandisets/000001/draft/foo/gone.nwb".parse().unwrap(),
+                    class: ErrorClass::BadGateway,
+                }),
+            ];
+            let pathparts = ["dandisets", "000001", "draft", "foo"]
+                .into_iter()
+                .map(|s| s.parse::<Component>().unwrap())
+                .collect::<Vec<_>>();
+            let rendered = templater
+                .render_collection(entries, None, pathparts, Language::En)
+                .unwrap();
+            assert!(rendered.contains(r#"<script src="/.static/download.js" defer></script>"#));
+            assert!(rendered.contains(
+                r#"<form class="download-selected" id="download-selected" method="get" action="&#x2F;dandisets&#x2F;000001&#x2F;draft&#x2F;""#
+            ));
+            assert!(rendered.contains(
+                r#"<input type="checkbox" form="download-selected" name="paths" value="foo&#x2F;bar" aria-label="Select bar"/>"#
+            ));
+            assert!(rendered.contains(r#"value="foo&#x2F;img.zarr""#));
+            assert!(rendered
+                .contains(r#"value="foo&#x2F;notes.txt%20" aria-label="Select notes.txt%20"/>"#));
+            assert_eq!(rendered.matches(r#"type="checkbox""#).count(), 3);
+            assert_eq!(check_accessibility(&rendered).unwrap(), Vec::new());
+            let page = parse_collection_page(&rendered).unwrap();
+            assert_eq!(
+                page.into_names(),
+                ["../", "bar/", "gone.nwb", "img.zarr/", "notes.txt%20"]
+            );
+        }
+
+        #[rstest]
+        #[case(&[], None)]
+        #[case(&["dandisets", "000001"], None)]
+        #[case(&["dandisets", "000001", "draft"], Some("dandisets/000001/draft/"))]
+        #[case(&["dandisets", "000001", "latest", "foo"], Some("dandisets/000001/latest/"))]
+        #[case(
+            &["dandisets", "000001", "releases", "0.210512.1623", "foo", "bar"],
+            Some("dandisets/000001/releases/0.210512.1623/")
+        )]
+        #[case(&["dandisets", "000001", "draft", "foo", "img.zarr"], None)]
+        #[case(&["dandisets", "000001", "draft", "img.ngff", "0"], None)]
+        #[case(&["zarrs", "abc", "a.zarr"], None)]
+        fn test_export_root(#[case] parts: &[&str], #[case] root: Option<&str>) {
+            let parts = parts
+                .iter()
+                .map(|s| s.parse::<Component>().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(export_root(&parts).as_ref().map(AsRef::<str>::as_ref), root);
+        }
+
+        #[test]
+        fn display_names() {
+            let templater =
+                Templater::new("Dandidav Test".to_owned(), &HtmlSnippets::default()).unwrap();
+            let entries = vec![DavResource::Collection(DavCollection {
+                path: Some(
+                    "zarrs/128/4a1/1284a14f-fe4f-4dc3-b10d-48e5db8bf18d/"
+                        .parse()
+                        .unwrap(),
+                ),
+                created: None,
+                modified: None,
+                size: None,
+                entry_count: Ok(None),
+                permalink: None,
+                archive_ids: ArchiveIds::default(),
+                citation: VersionCitation::default(),
+                kind: ResourceKind::Directory,
+                metadata_url: None,
+                zarr_node_type: None,
+                display_name: Some("1284a14f\u{2026}".into()),
+            })];
+            let pathparts = vec![
+                "zarrs".parse::<Component>().unwrap(),
+                "128".parse::<Component>().unwrap(),
+                "4a1".parse::<Component>().unwrap(),
+            ];
+            let rendered = templater
+                .render_collection(entries, None, pathparts, Language::En)
+                .unwrap();
+            assert!(rendered.contains(
+                r#"<a href="&#x2F;zarrs&#x2F;128&#x2F;4a1&#x2F;1284a14f-fe4f-4dc3-b10d-48e5db8bf18d&#x2F;" title="1284a14f-fe4f-4dc3-b10d-48e5db8bf18d">1284a14f…/</a>"#
+            ));
+            assert!(rendered.contains(r#"<a href="&#x2F;zarrs&#x2F;128&#x2F;4a1&#x2F;">4a1</a>"#));
+            assert_eq!(check_accessibility(&rendered).unwrap(), Vec::new());
+        }
+
+        #[test]
+        fn upstream_warning() {
+            let upstream = Arc::new(UpstreamMonitor::new());
+            let templater = Templater::new("Dandidav Test".to_owned(), &HtmlSnippets::default())
+                .unwrap()
+                .with_upstream(Arc::clone(&upstream));
+            let rendered = templater
+                .render_collection(Vec::new(), None, Vec::new(), Language::En)
+                .unwrap();
+            assert!(!rendered.contains("upstream-warning"));
+            upstream.record(ArchiveInfo {
+                schema_version: "9.0.0".into(),
+                version: "1.0.0".into(),
+            });
+            let rendered = templater
+                .render_collection(Vec::new(), None, Vec::new(), Language::En)
+                .unwrap();
+            assert!(rendered.contains(
+                r#"<p class="upstream-warning" role="alert">The DANDI Archive is using version 9.0.0 of its metadata schema, which this server has not been tested with. Some resources may not be displayed correctly.</p>"#
+            ));
+            assert_eq!(check_accessibility(&rendered).unwrap(), Vec::new());
+        }
+
+        /// Construct `n` blob items in the folder "foo/"
+        fn many_entries(n: usize) -> Vec<DavResource> {
+            (0..n)
+                .map(|i| {
+                    DavResource::Item(DavItem {
+                        path: format!("foo/file{i:04}.dat").parse().unwrap(),
+                        created: Some(datetime!(2021-02-03 06:47:50 UTC)),
+                        modified: Some(datetime!(2022-03-10 12:03:29 UTC)),
+                        content_type: Some("application/octet-stream".into()),
+                        size: Some(1024),
+                        etag: None,
+                        annex_key: None,
+                        permalink: None,
+                        archive_ids: ArchiveIds::default(),
+                        digests: Digests::default(),
+                        s3_version_id: None,
+                        kind: ResourceKind::Blob,
+                        content: DavContent::Missing,
+                        metadata_url: None,
+                    })
+                })
+                .collect()
+        }
+
+        #[rstest]
+        #[case(0, 7)]
+        #[case(1, 7)]
+        #[case(20, 1)]
+        #[case(20, 7)]
+        #[case(21, 7)]
+        fn batched(#[case] n: usize, #[case] batch_size: usize) {
+            let templater =
+                Templater::new("Dandidav Test".to_owned(), &HtmlSnippets::default()).unwrap();
+            let pathparts = vec!["foo".parse::<Component>().unwrap()];
+            let whole = templater
+                .render_collection(many_entries(n), None, pathparts.clone(), Language::En)
+                .unwrap();
+            let mut pieces = Vec::new();
+            templater
+                .render_collection_pieces(
+                    many_entries(n),
+                    None,
+                    pathparts,
+                    Language::En,
+                    batch_size,
+                    |piece| {
+                        pieces.push(piece);
+                        true
+                    },
+                )
+                .unwrap();
+            // head + row batches (including the parent directory row) + foot
+            assert_eq!(pieces.len(), (n + 1).div_ceil(batch_size) + 2);
+            assert_eq!(pieces.concat(), whole);
+        }
+
+        #[test]
+        fn stop_early() {
+            let templater =
+                Templater::new("Dandidav Test".to_owned(), &HtmlSnippets::default()).unwrap();
+            let mut pieces = 0;
+            templater
+                .render_collection_pieces(
+                    many_entries(20),
+                    None,
+                    Vec::new(),
+                    Language::En,
+                    5,
+                    |_| {
+                        pieces += 1;
+                        pieces < 2
+                    },
+                )
+                .unwrap();
+            assert_eq!(pieces, 2);
+        }
+
+        #[tokio::test]
+        async fn streamed() {
+            let templater = Arc::new(
+                Templater::new("Dandidav Test".to_owned(), &HtmlSnippets::default()).unwrap(),
+            );
+            let pathparts = vec!["foo".parse::<Component>().unwrap()];
+            let n = HTML_STREAM_BATCH_SIZE * 2 + 1;
+            let whole = templater
+                .render_collection(many_entries(n), None, pathparts.clone(), Language::En)
+                .unwrap();
+            let body = Arc::clone(&templater).render_collection_stream(
+                many_entries(n),
+                None,
+                pathparts,
+                Language::En,
+            );
+            assert_eq!(body.size_hint().exact(), None);
+            let streamed = body.collect().await.unwrap().to_bytes();
+            assert_eq!(std::str::from_utf8(&streamed).unwrap(), whole);
+        }
     }
 }
@@ -0,0 +1,477 @@
+//! Localization of the text in HTML views
+use super::types::ResourceKind;
+use axum::{
+    body::Body,
+    extract::FromRequestParts,
+    http::{header::ACCEPT_LANGUAGE, request::Parts},
+    response::Response,
+};
+
+/// A language in which HTML views can be displayed
+#[derive(clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) enum Language {
+    /// English
+    #[default]
+    En,
+
+    /// German
+    De,
+
+    /// Spanish
+    Es,
+
+    /// French
+    Fr,
+}
+
+impl Language {
+    /// All supported languages
+    const ALL: [Language; 4] = [Language::En, Language::De, Language::Es, Language::Fr];
+
+    /// Return the language's ISO 639-1 code, for use in `lang` attributes
+    pub(super) fn code(self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::De => "de",
+            Language::Es => "es",
+            Language::Fr => "fr",
+        }
+    }
+
+    /// Return the catalog of UI text in the language
+    pub(super) fn messages(self) -> &'static Messages {
+        match self {
+            Language::En => &EN,
+            Language::De => &DE,
+            Language::Es => &ES,
+            Language::Fr => &FR,
+        }
+    }
+
+    /// Return the supported language (if any) matching the given language
+    /// tag, ignoring any region or other subtags
+    fn from_tag(tag: &str) -> Option<Language> {
+        let primary = tag.split('-').next().unwrap_or(tag);
+        Language::ALL
+            .into_iter()
+            .find(|lang| primary.eq_ignore_ascii_case(lang.code()))
+    }
+
+    /// Select the most preferred supported language from the value of an
+    /// `Accept-Language` header.  Returns `None` if the header does not list
+    /// any supported languages with a nonzero quality value, in which case the
+    /// operator's default language should be used.
+    pub(super) fn negotiate(accept_language: &str) -> Option<Language> {
+        let mut best: Option<(Language, u16)> = None;
+        for item in accept_language.split(',') {
+            let mut params = item.split(';');
+            let tag = params.next().unwrap_or_default().trim();
+            let Some(lang) = Language::from_tag(tag) else {
+                continue;
+            };
+            let q = params
+                .find_map(|p| {
+                    let (k, v) = p.split_once('=')?;
+                    k.trim()
+                        .eq_ignore_ascii_case("q")
+                        .then(|| parse_qvalue(v.trim()))
+                })
+                .unwrap_or(Some(1000));
+            match q {
+                Some(q) if q > 0 && best.map_or(true, |(_, bestq)| bestq < q) => {
+                    best = Some((lang, q));
+                }
+                _ => (),
+            }
+        }
+        best.map(|(lang, _)| lang)
+    }
+}
+
+/// Parse an HTTP quality value (a number from 0 to 1 with at most three
+/// decimal places) into an integer number of thousandths
+pub(super) fn parse_qvalue(s: &str) -> Option<u16> {
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let frac = format!("{frac:0<3}").parse::<u16>().ok()?;
+    match int {
+        "0" => Some(frac),
+        "1" if frac == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+/// The language requested by the client via the `Accept-Language` header, if
+/// any of the requested languages are supported
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(super) struct RequestedLanguage(pub(super) Option<Language>);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestedLanguage {
+    type Rejection = Response<Body>;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let lang = parts
+            .headers
+            .get_all(ACCEPT_LANGUAGE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .find_map(Language::negotiate);
+        Ok(RequestedLanguage(lang))
+    }
+}
+
+/// A catalog of the UI text shown in HTML views
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct Messages {
+    /// Header of the column of resource names
+    pub(super) name: &'static str,
+
+    /// Header of the column of resource types
+    pub(super) kind: &'static str,
+
+    /// Header of the column of resource sizes
+    pub(super) size: &'static str,
+
+    /// Header of the column of creation timestamps
+    pub(super) created: &'static str,
+
+    /// Header of the column of modification timestamps
+    pub(super) modified: &'static str,
+
+    /// Header of the column of descriptions of top-level collections
+    pub(super) description: &'static str,
+
+    /// Text of links to resources' metadata
+    pub(super) metadata: &'static str,
+
+    /// Word displayed after the number of entries in a Zarr
+    pub(super) entries: &'static str,
+
+    /// Text of the link for skipping straight to the table of resources
+    pub(super) skip_link: &'static str,
+
+    /// Label for the breadcrumbs showing the current location
+    pub(super) location: &'static str,
+
+    /// Words preceding the collection's path in the table's caption
+    pub(super) contents: &'static str,
+
+    /// Label for table cells with no value
+    pub(super) not_available: &'static str,
+
+    /// Label for a Dandiset version's DOI
+    pub(super) doi: &'static str,
+
+    /// Label for a Dandiset version's recommended citation
+    pub(super) cite_as: &'static str,
+
+    /// Label for a Dandiset version's licenses
+    pub(super) license: &'static str,
+
+    /// Label for the icon marking Zarr groups
+    pub(super) zarr_group: &'static str,
+
+    /// Label for the icon marking Zarr arrays
+    pub(super) zarr_array: &'static str,
+
+    /// Label for the checkbox selecting a resource for download, followed by
+    /// the resource's name
+    pub(super) select: &'static str,
+
+    /// Label for the button that downloads the selected resources
+    pub(super) download_selected: &'static str,
+
+    /// Message shown when the selected resources could not be downloaded
+    pub(super) download_failed: &'static str,
+
+    /// Warning shown when the Archive uses a newer metadata schema than any
+    /// that `dandidav` has been tested against.  `{version}` is replaced with
+    /// the schema version.
+    pub(super) upstream_warning: &'static str,
+
+    /// Title of pages for resources that were not found
+    pub(super) not_found: &'static str,
+
+    /// Message shown when a requested Dandiset exists only on another
+    /// Archive instance.  `{dandiset_id}` is replaced with the Dandiset ID.
+    pub(super) other_instance: &'static str,
+
+    /// Display names of the kinds of resources
+    kinds: KindNames,
+}
+
+impl Messages {
+    /// Return the display name for the given kind of resource
+    pub(super) fn kind_name(&self, kind: ResourceKind) -> &'static str {
+        let k = &self.kinds;
+        match kind {
+            ResourceKind::Root => k.root,
+            ResourceKind::Parent => k.parent,
+            ResourceKind::DandisetIndex => k.dandiset_index,
+            ResourceKind::Dandiset => k.dandiset,
+            ResourceKind::DandisetReleases => k.dandiset_releases,
+            ResourceKind::Version => k.version,
+            ResourceKind::VersionMetadata => k.version_metadata,
+            ResourceKind::Directory => k.directory,
+            ResourceKind::Blob => k.blob,
+            ResourceKind::Zarr => k.zarr,
+            ResourceKind::ZarrEntry => k.zarr_entry,
+            ResourceKind::ZarrInfo => k.zarr_info,
+            ResourceKind::ZarrIndex => k.zarr_index,
+            ResourceKind::Unavailable => k.unavailable,
+        }
+    }
+}
+
+/// Display names of the kinds of resources; see [`ResourceKind`] for the
+/// meaning of each field
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct KindNames {
+    root: &'static str,
+    parent: &'static str,
+    dandiset_index: &'static str,
+    dandiset: &'static str,
+    dandiset_releases: &'static str,
+    version: &'static str,
+    version_metadata: &'static str,
+    directory: &'static str,
+    blob: &'static str,
+    zarr: &'static str,
+    zarr_entry: &'static str,
+    zarr_info: &'static str,
+    zarr_index: &'static str,
+    unavailable: &'static str,
+}
+
+static EN: Messages = Messages {
+    name: "Name",
+    kind: "Type",
+    size: "Size",
+    created: "Created",
+    modified: "Modified",
+    description: "Description",
+    metadata: "metadata",
+    entries: "entries",
+    skip_link: "Skip to listing",
+    location: "Location",
+    contents: "Contents of",
+    not_available: "Not available",
+    doi: "DOI",
+    cite_as: "Cite as",
+    license: "License",
+    zarr_group: "Zarr group",
+    zarr_array: "Zarr array",
+    select: "Select",
+    download_selected: "Download selected",
+    download_failed: "The selected files could not be retrieved.",
+    upstream_warning: "The DANDI Archive is using version {version} of its metadata schema, which this server has not been tested with. Some resources may not be displayed correctly.",
+    not_found: "Not found",
+    other_instance: "Dandiset {dandiset_id} does not exist on this instance, but it does exist on another instance:",
+    kinds: KindNames {
+        root: "Root",
+        parent: "Parent directory",
+        dandiset_index: "Dandisets",
+        dandiset: "Dandiset",
+        dandiset_releases: "Published versions",
+        version: "Dandiset version",
+        version_metadata: "Version metadata",
+        directory: "Directory",
+        blob: "Blob asset",
+        zarr: "Zarr asset",
+        zarr_entry: "Zarr entry",
+        zarr_info: "Zarr summary",
+        zarr_index: "Zarrs",
+        unavailable: "Unavailable",
+    },
+};
+
+static DE: Messages = Messages {
+    name: "Name",
+    kind: "Typ",
+    size: "Größe",
+    created: "Erstellt",
+    modified: "Geändert",
+    description: "Beschreibung",
+    metadata: "Metadaten",
+    entries: "Einträge",
+    skip_link: "Zur Auflistung springen",
+    location: "Standort",
+    contents: "Inhalt von",
+    not_available: "Nicht verfügbar",
+    doi: "DOI",
+    cite_as: "Zitieren als",
+    license: "Lizenz",
+    zarr_group: "Zarr-Gruppe",
+    zarr_array: "Zarr-Array",
+    select: "Auswählen",
+    download_selected: "Auswahl herunterladen",
+    download_failed: "Die ausgewählten Dateien konnten nicht abgerufen werden.",
+    upstream_warning: "Das DANDI-Archiv verwendet Version {version} seines Metadatenschemas, mit der dieser Server nicht getestet wurde. Einige Ressourcen werden möglicherweise nicht korrekt angezeigt.",
+    not_found: "Nicht gefunden",
+    other_instance:
+        "Dandiset {dandiset_id} existiert nicht auf dieser Instanz, aber auf einer anderen Instanz:",
+    kinds: KindNames {
+        root: "Wurzel",
+        parent: "Übergeordnetes Verzeichnis",
+        dandiset_index: "Dandisets",
+        dandiset: "Dandiset",
+        dandiset_releases: "Veröffentlichte Versionen",
+        version: "Dandiset-Version",
+        version_metadata: "Versionsmetadaten",
+        directory: "Verzeichnis",
+        blob: "Blob-Asset",
+        zarr: "Zarr-Asset",
+        zarr_entry: "Zarr-Eintrag",
+        zarr_info: "Zarr-Zusammenfassung",
+        zarr_index: "Zarrs",
+        unavailable: "Nicht verfügbar",
+    },
+};
+
+static ES: Messages = Messages {
+    name: "Nombre",
+    kind: "Tipo",
+    size: "Tamaño",
+    created: "Creado",
+    modified: "Modificado",
+    description: "Descripción",
+    metadata: "metadatos",
+    entries: "entradas",
+    skip_link: "Saltar al listado",
+    location: "Ubicación",
+    contents: "Contenido de",
+    not_available: "No disponible",
+    doi: "DOI",
+    cite_as: "Cómo citar",
+    license: "Licencia",
+    zarr_group: "Grupo Zarr",
+    zarr_array: "Array Zarr",
+    select: "Seleccionar",
+    download_selected: "Descargar selección",
+    download_failed: "No se pudieron obtener los archivos seleccionados.",
+    upstream_warning: "El DANDI Archive utiliza la versión {version} de su esquema de metadatos, con la que este servidor no se ha probado. Es posible que algunos recursos no se muestren correctamente.",
+    not_found: "No encontrado",
+    other_instance:
+        "El Dandiset {dandiset_id} no existe en esta instancia, pero sí existe en otra instancia:",
+    kinds: KindNames {
+        root: "Raíz",
+        parent: "Directorio superior",
+        dandiset_index: "Dandisets",
+        dandiset: "Dandiset",
+        dandiset_releases: "Versiones publicadas",
+        version: "Versión de Dandiset",
+        version_metadata: "Metadatos de la versión",
+        directory: "Directorio",
+        blob: "Asset blob",
+        zarr: "Asset Zarr",
+        zarr_entry: "Entrada Zarr",
+        zarr_info: "Resumen Zarr",
+        zarr_index: "Zarrs",
+        unavailable: "No disponible",
+    },
+};
+
+static FR: Messages = Messages {
+    name: "Nom",
+    kind: "Type",
+    size: "Taille",
+    created: "Créé",
+    modified: "Modifié",
+    description: "Description",
+    metadata: "métadonnées",
+    entries: "entrées",
+    skip_link: "Aller à la liste",
+    location: "Emplacement",
+    contents: "Contenu de",
+    not_available: "Non disponible",
+    doi: "DOI",
+    cite_as: "Citer comme",
+    license: "Licence",
+    zarr_group: "Groupe Zarr",
+    zarr_array: "Tableau Zarr",
+    select: "Sélectionner",
+    download_selected: "Télécharger la sélection",
+    download_failed: "Impossible de récupérer les fichiers sélectionnés.",
+    upstream_warning: "L'archive DANDI utilise la version {version} de son schéma de métadonnées, avec laquelle ce serveur n'a pas été testé. Certaines ressources peuvent ne pas s'afficher correctement.",
+    not_found: "Introuvable",
+    other_instance: "Le Dandiset {dandiset_id} n'existe pas sur cette instance, mais il existe sur une autre instance :",
+    kinds: KindNames {
+        root: "Racine",
+        parent: "Répertoire parent",
+        dandiset_index: "Dandisets",
+        dandiset: "Dandiset",
+        dandiset_releases: "Versions publiées",
+        version: "Version du Dandiset",
+        version_metadata: "Métadonnées de la version",
+        directory: "Répertoire",
+        blob: "Asset blob",
+        zarr: "Asset Zarr",
+        zarr_entry: "Entrée Zarr",
+        zarr_info: "Résumé Zarr",
+        zarr_index: "Zarrs",
+        unavailable: "Indisponible",
+    },
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("", None)]
+    #[case("en", Some(Language::En))]
+    #[case("de-DE", Some(Language::De))]
+    #[case("DE-at", Some(Language::De))]
+    #[case("ja", None)]
+    #[case("*", None)]
+    #[case("ja, fr;q=0.8, en;q=0.5", Some(Language::Fr))]
+    #[case("en;q=0.5, es;q=0.9", Some(Language::Es))]
+    #[case("es;q=0.5, de;q=0.5", Some(Language::Es))]
+    #[case("fr;q=0, de;q=0.001", Some(Language::De))]
+    #[case("fr;q=0", None)]
+    #[case("fr;q=1.5, en;q=0.2", Some(Language::En))]
+    #[case("fr;q=bad", None)]
+    #[case(" de ; q=0.7 ,en;q=0.6", Some(Language::De))]
+    fn test_negotiate(#[case] header: &str, #[case] lang: Option<Language>) {
+        assert_eq!(Language::negotiate(header), lang);
+    }
+
+    #[rstest]
+    #[case("0", Some(0))]
+    #[case("0.", Some(0))]
+    #[case("0.5", Some(500))]
+    #[case("0.123", Some(123))]
+    #[case("0.1234", None)]
+    #[case("1", Some(1000))]
+    #[case("1.000", Some(1000))]
+    #[case("1.001", None)]
+    #[case("2", None)]
+    #[case(".5", None)]
+    fn test_parse_qvalue(#[case] s: &str, #[case] q: Option<u16>) {
+        assert_eq!(parse_qvalue(s), q);
+    }
+
+    #[test]
+    fn english_kind_names() {
+        for kind in [
+            ResourceKind::Parent,
+            ResourceKind::DandisetIndex,
+            ResourceKind::Dandiset,
+            ResourceKind::DandisetReleases,
+            ResourceKind::Version,
+            ResourceKind::VersionMetadata,
+            ResourceKind::Directory,
+            ResourceKind::Blob,
+            ResourceKind::Zarr,
+            ResourceKind::ZarrEntry,
+            ResourceKind::ZarrInfo,
+            ResourceKind::ZarrIndex,
+            ResourceKind::Unavailable,
+        ] {
+            assert_eq!(EN.kind_name(kind), kind.as_str());
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Machine-readable JSON listings of collections, for `GET` requests that ask
+//! for them instead of HTML views
+use super::i18n::parse_qvalue;
+use super::types::{DavResource, HasProperties, ResourceKind};
+use super::util::format_creationdate;
+use serde::Serialize;
+
+/// A representation in which a collection can be served in response to a
+/// `GET` request
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub(super) enum ListingFormat {
+    /// An HTML view rendered by [`super::Templater`]
+    #[default]
+    Html,
+
+    /// A JSON array of [`ListingEntry`] objects
+    Json,
+}
+
+impl ListingFormat {
+    /// Parse a `format` query parameter value, case-insensitively
+    pub(super) fn parse(s: &str) -> Option<ListingFormat> {
+        if s.eq_ignore_ascii_case("html") {
+            Some(ListingFormat::Html)
+        } else if s.eq_ignore_ascii_case("json") {
+            Some(ListingFormat::Json)
+        } else {
+            None
+        }
+    }
+
+    /// Select the format requested by the value of an `Accept` header.  JSON
+    /// is only selected if the header gives `application/json` a higher
+    /// quality than `text/html`; wildcard media ranges and other media types
+    /// are ignored, so that browsers always receive HTML.
+    pub(super) fn negotiate(accept: &str) -> ListingFormat {
+        let mut html_q = 0;
+        let mut json_q = 0;
+        for item in accept.split(',') {
+            let mut params = item.split(';');
+            let media_type = params.next().unwrap_or_default().trim();
+            let slot = if media_type.eq_ignore_ascii_case("text/html") {
+                &mut html_q
+            } else if media_type.eq_ignore_ascii_case("application/json") {
+                &mut json_q
+            } else {
+                continue;
+            };
+            let q = params
+                .find_map(|p| {
+                    let (k, v) = p.split_once('=')?;
+                    k.trim()
+                        .eq_ignore_ascii_case("q")
+                        .then(|| parse_qvalue(v.trim()))
+                })
+                .unwrap_or(Some(1000));
+            if let Some(q) = q {
+                *slot = (*slot).max(q);
+            }
+        }
+        if json_q > html_q {
+            ListingFormat::Json
+        } else {
+            ListingFormat::Html
+        }
+    }
+}
+
+/// A child of a collection in a JSON listing
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(super) struct ListingEntry {
+    /// The resource's basename
+    name: String,
+
+    /// The type of resource
+    #[serde(rename = "type")]
+    kind: ResourceKind,
+
+    /// `true` iff the resource is a collection
+    collection: bool,
+
+    /// The size of the resource, if known
+    size: Option<i64>,
+
+    /// The timestamp at which the resource was last modified, in RFC 3339
+    /// format, if known
+    modified: Option<String>,
+
+    /// The absolute path at which the resource is served by `dandidav`, in
+    /// percent-encoded form
+    href: String,
+
+    /// A URL for retrieving the resource's associated metadata (if any) from
+    /// the Archive instance
+    metadata_url: Option<String>,
+
+    /// For resources whose details could not be retrieved, the HTTP status
+    /// code that a request for the resource would have received
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+}
+
+impl From<DavResource> for ListingEntry {
+    fn from(res: DavResource) -> ListingEntry {
+        let href = res.href().as_ref().to_owned();
+        match res {
+            DavResource::Collection(col) => ListingEntry {
+                name: col.name().unwrap_or("/").to_owned(),
+                kind: col.kind,
+                collection: true,
+                size: col.size,
+                modified: col.modified.map(format_creationdate),
+                href,
+                metadata_url: col.metadata_url.map(|u| u.to_string()),
+                status: None,
+            },
+            DavResource::Item(item) => ListingEntry {
+                name: item.name().to_owned(),
+                kind: item.kind,
+                collection: false,
+                size: item.size,
+                modified: item.modified.map(format_creationdate),
+                href,
+                metadata_url: item.metadata_url.map(|u| u.to_string()),
+                status: None,
+            },
+            DavResource::Unavailable(r) => ListingEntry {
+                name: r.name().to_owned(),
+                kind: ResourceKind::Unavailable,
+                collection: false,
+                size: None,
+                modified: None,
+                href,
+                metadata_url: None,
+                status: Some(r.class.to_status().as_u16()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("application/json", ListingFormat::Json)]
+    #[case("application/json, text/html;q=0.9", ListingFormat::Json)]
+    #[case("text/html, application/json", ListingFormat::Html)]
+    #[case("text/html;q=0.5, application/json;q=0.8", ListingFormat::Json)]
+    #[case("application/json;q=0", ListingFormat::Html)]
+    #[case(
+        "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+        ListingFormat::Html
+    )]
+    #[case("*/*", ListingFormat::Html)]
+    #[case("APPLICATION/JSON", ListingFormat::Json)]
+    #[case("application/json;q=bogus", ListingFormat::Html)]
+    fn test_negotiate(#[case] accept: &str, #[case] format: ListingFormat) {
+        assert_eq!(ListingFormat::negotiate(accept), format);
+    }
+
+    #[rstest]
+    #[case("json", Some(ListingFormat::Json))]
+    #[case("JSON", Some(ListingFormat::Json))]
+    #[case("html", Some(ListingFormat::Html))]
+    #[case("xml", None)]
+    #[case("", None)]
+    fn test_parse(#[case] s: &str, #[case] format: Option<ListingFormat>) {
+        assert_eq!(ListingFormat::parse(s), format);
+    }
+}
@@ -0,0 +1,193 @@
+//! Ephemeral locks granted in response to `LOCK` requests
+//!
+//! Windows' WebDAV redirector refuses to map shares on servers that do not
+//! support locking (DAV compliance class 2), so `dandidav` grants every `LOCK`
+//! request for an existing resource.  As nothing served by `dandidav` can be
+//! modified, the locks are never enforced and never conflict with each other;
+//! they are only remembered in memory until they expire or are released so
+//! that clients can refresh & unlock them.
+use super::util::Href;
+use super::xml::{ActiveLock, LockDepth, LockInfo};
+use crate::consts::{LOCK_TIMEOUT_SECS, MAX_LOCKS};
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// The set of unexpired locks, keyed by lock token
+#[derive(Debug, Default)]
+pub(super) struct LockManager {
+    locks: Mutex<HashMap<String, HeldLock>>,
+}
+
+impl LockManager {
+    pub(super) fn new() -> LockManager {
+        LockManager::default()
+    }
+
+    /// Grant a new lock on the resource with href `root`.  Fails if
+    /// [`MAX_LOCKS`] unexpired locks are already held.
+    pub(super) fn lock(
+        &self,
+        root: Href,
+        depth: LockDepth,
+        info: LockInfo,
+    ) -> Result<ActiveLock, TooManyLocks> {
+        let now = Instant::now();
+        let mut locks = self.locks.lock().unwrap_or_else(PoisonError::into_inner);
+        locks.retain(|_, held| held.expires > now);
+        if locks.len() >= MAX_LOCKS {
+            return Err(TooManyLocks);
+        }
+        let lock = ActiveLock {
+            scope: info.scope,
+            depth,
+            owner: info.owner,
+            timeout_secs: LOCK_TIMEOUT_SECS,
+            token: format!("opaquelocktoken:{}", uuid::Uuid::new_v4()),
+            root,
+        };
+        locks.insert(
+            lock.token.clone(),
+            HeldLock {
+                lock: lock.clone(),
+                expires: now + Duration::from_secs(LOCK_TIMEOUT_SECS),
+            },
+        );
+        Ok(lock)
+    }
+
+    /// Restart the timeout of the unexpired lock with token `token`, provided
+    /// that it applies to the resource with href `href`, and return the lock
+    pub(super) fn refresh(&self, href: &Href, token: &str) -> Option<ActiveLock> {
+        let now = Instant::now();
+        let mut locks = self.locks.lock().unwrap_or_else(PoisonError::into_inner);
+        let held = locks
+            .get_mut(token)
+            .filter(|held| held.expires > now && held.covers(href))?;
+        held.expires = now + Duration::from_secs(LOCK_TIMEOUT_SECS);
+        Some(held.lock.clone())
+    }
+
+    /// Release the unexpired lock with token `token`, provided that it applies
+    /// to the resource with href `href`.  Returns `false` if there is no such
+    /// lock.
+    pub(super) fn unlock(&self, href: &Href, token: &str) -> bool {
+        let now = Instant::now();
+        let mut locks = self.locks.lock().unwrap_or_else(PoisonError::into_inner);
+        if locks
+            .get(token)
+            .is_some_and(|held| held.expires > now && held.covers(href))
+        {
+            locks.remove(token);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// A lock and the time at which it expires
+#[derive(Clone, Debug)]
+struct HeldLock {
+    lock: ActiveLock,
+    expires: Instant,
+}
+
+impl HeldLock {
+    /// Test whether the lock applies to the resource with href `href`, i.e.,
+    /// whether `href` is the lock's root or (for a depth-infinity lock on a
+    /// collection) is a member of the root
+    fn covers(&self, href: &Href) -> bool {
+        let root = self.lock.root.as_ref();
+        let href = href.as_ref();
+        href == root
+            || (self.lock.depth == LockDepth::Infinity
+                && root.ends_with('/')
+                && href.starts_with(root))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("too many locks are currently held")]
+pub(super) struct TooManyLocks;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dav::xml::{LockOwner, LockScope};
+
+    fn lockinfo() -> LockInfo {
+        LockInfo {
+            scope: LockScope::Exclusive,
+            owner: Some(LockOwner::Href("jdoe".into())),
+        }
+    }
+
+    #[test]
+    fn lock_refresh_unlock() {
+        let locks = LockManager::new();
+        let root = Href::from_path("/dandisets/000001/draft/dandiset.yaml");
+        let lock = locks
+            .lock(root.clone(), LockDepth::Zero, lockinfo())
+            .unwrap();
+        assert!(lock.token.starts_with("opaquelocktoken:"));
+        assert_eq!(lock.root, root);
+        assert_eq!(lock.timeout_secs, LOCK_TIMEOUT_SECS);
+        assert_eq!(lock.owner, Some(LockOwner::Href("jdoe".into())));
+        assert_eq!(locks.refresh(&root, &lock.token), Some(lock.clone()));
+        assert!(locks.unlock(&root, &lock.token));
+        assert_eq!(locks.refresh(&root, &lock.token), None);
+        assert!(!locks.unlock(&root, &lock.token));
+    }
+
+    #[test]
+    fn locks_do_not_conflict() {
+        let locks = LockManager::new();
+        let root = Href::from_path("/dandisets/");
+        let lock1 = locks
+            .lock(root.clone(), LockDepth::Infinity, lockinfo())
+            .unwrap();
+        let lock2 = locks
+            .lock(root.clone(), LockDepth::Infinity, lockinfo())
+            .unwrap();
+        assert_ne!(lock1.token, lock2.token);
+        assert!(locks.unlock(&root, &lock1.token));
+        assert!(locks.unlock(&root, &lock2.token));
+    }
+
+    #[test]
+    fn unknown_token() {
+        let locks = LockManager::new();
+        let root = Href::from_path("/dandisets/");
+        let token = "opaquelocktoken:e71d4fae-5dec-4ed6-9a1a-f4a6e0b5a8f3";
+        assert_eq!(locks.refresh(&root, token), None);
+        assert!(!locks.unlock(&root, token));
+    }
+
+    #[test]
+    fn infinite_depth_covers_members() {
+        let locks = LockManager::new();
+        let root = Href::from_path("/dandisets/000001/draft/");
+        let member = Href::from_path("/dandisets/000001/draft/sub-RAT123/");
+        let other = Href::from_path("/dandisets/000002/draft/");
+        let lock = locks.lock(root, LockDepth::Infinity, lockinfo()).unwrap();
+        assert_eq!(locks.refresh(&other, &lock.token), None);
+        assert!(!locks.unlock(&other, &lock.token));
+        assert_eq!(locks.refresh(&member, &lock.token), Some(lock.clone()));
+        assert!(locks.unlock(&member, &lock.token));
+    }
+
+    #[test]
+    fn zero_depth_does_not_cover_members() {
+        let locks = LockManager::new();
+        let root = Href::from_path("/dandisets/000001/draft/");
+        let member = Href::from_path("/dandisets/000001/draft/sub-RAT123/");
+        let lock = locks
+            .lock(root.clone(), LockDepth::Zero, lockinfo())
+            .unwrap();
+        assert_eq!(locks.refresh(&member, &lock.token), None);
+        assert!(!locks.unlock(&member, &lock.token));
+        assert!(locks.unlock(&root, &lock.token));
+    }
+}
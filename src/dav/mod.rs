@@ -1,49 +1,120 @@
 //! The WebDAV component of `dandidav`
+#[doc(hidden)]
+pub mod bench;
+mod builder;
+mod credentials;
+mod export;
+mod format;
 mod html;
+mod i18n;
+mod listing;
+mod lock;
+mod mount;
+mod notfound;
+mod objcache;
 mod path;
+mod policy;
+mod propcache;
+mod proxy;
+mod rewrite;
 mod types;
 mod util;
 mod xml;
-pub(crate) use self::html::Templater;
+use self::builder::DandiDavBuilder;
+pub(crate) use self::credentials::UserClients;
+use self::format::{ConvertError, MetadataFormat};
 use self::html::*;
+pub(crate) use self::html::{HtmlSnippets, Templater, Theme};
+pub(crate) use self::i18n::Language;
+use self::listing::{ListingEntry, ListingFormat};
+use self::lock::LockManager;
+pub(crate) use self::mount::{MountDescription, DEFAULT_ROOT_MOUNTS};
+use self::notfound::{is_archive_404, NotFound};
+pub(crate) use self::objcache::ObjectCache;
 use self::path::*;
+pub(crate) use self::policy::CollectionProperty;
+use self::policy::PropertyPolicy;
+use self::propcache::{PropfindCache, PropfindKey};
 use self::types::*;
 use self::util::*;
+pub(crate) use self::util::{Depth, FiniteDepth, ZarrMode};
+pub(crate) use self::xml::sanitize_xml_text;
 use self::xml::*;
-use crate::consts::{DAV_XML_CONTENT_TYPE, HTML_CONTENT_TYPE};
+use crate::audit::{AuditLog, ClientClass, DownloadRecord};
+use crate::blocking::run_blocking;
+use crate::budget::with_budget;
+use crate::consts::{
+    DAV_XML_CONTENT_TYPE, HTML_CONTENT_TYPE, HTML_STREAM_THRESHOLD, JSON_CONTENT_TYPE,
+    PROPFIND_STREAM_BUFFER, ZARR_MODE_HEADER,
+};
 use crate::dandi::*;
+use crate::dandisetlimit::{with_dandiset_pool, DandisetLimits};
+use crate::hotobjects::HotObjects;
+use crate::httputil::{Client, HttpError, HttpUrl};
+use crate::mountlimit::Mount;
 use crate::paths::Component;
 use crate::paths::PurePath;
 use crate::zarrman::*;
 use axum::{
     body::Body,
     extract::Request,
-    http::{header::CONTENT_TYPE, response::Response, StatusCode},
+    http::{
+        header::{
+            HeaderName, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE,
+            IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, USER_AGENT, VARY,
+        },
+        response::Response,
+        StatusCode,
+    },
     response::{IntoResponse, Redirect},
     RequestExt,
 };
 use futures_util::TryStreamExt;
 use std::convert::Infallible;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
 use thiserror::Error;
+use time::OffsetDateTime;
 
 /// HTTP headers to include in all responses for WebDAV resources
 const WEBDAV_RESPONSE_HEADERS: [(&str, &str); 2] = [
-    ("Allow", "GET, HEAD, OPTIONS, PROPFIND"),
+    ("Allow", "GET, HEAD, OPTIONS, PROPFIND, LOCK, UNLOCK"),
     // <http://www.webdav.org/specs/rfc4918.html#HEADER_DAV>
-    ("DAV", "1, 3"),
+    ("DAV", "1, 2, 3"),
+];
+
+/// HTTP headers to include in HTML responses for WebDAV resources
+const HTML_RESPONSE_HEADERS: [(HeaderName, &str); 2] = [
+    (CONTENT_TYPE, HTML_CONTENT_TYPE),
+    // HTML views are displayed in the language requested by the client
+    (VARY, "Accept-Language"),
 ];
 
-/// Manager for handling WebDAV requests
+/// Marker inserted into the extensions of `GET` requests that were originally
+/// `HEAD` requests.  A response with this marker in its extensions already has
+/// an empty body and all of the headers that the `GET` response would have
+/// had, so its `Content-Length` should not be recomputed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct HeadRequest;
+
+/// Manager for handling WebDAV requests.  Instances are constructed via
+/// [`DandiDav::builder()`].
+#[derive(Clone)]
 pub(crate) struct DandiDav {
     /// A client for fetching data from the Dandi Archive
-    pub(crate) dandi: DandiClient,
+    dandi: DandiClient,
+
+    /// Per-user clients for the Dandi Archive, used in place of `dandi` for
+    /// requests with `Authorization` headers.  If `None`, such headers are
+    /// ignored.
+    user_clients: Option<UserClients>,
 
     /// A client for fetching data from
     /// <https://github.com/dandi/zarr-manifests>
-    pub(crate) zarrman: ZarrManClient,
+    zarrman: ZarrManClient,
 
     /// Manager for templating of HTML responses
-    pub(crate) templater: Templater,
+    templater: Arc<Templater>,
 
     /// Whether `GET` requests for blob assets should be responded to with
     /// redirects to S3 (`true`) or to Archive download URLs that then redirect
@@ -53,13 +124,186 @@ pub(crate) struct DandiDav {
     /// the blob ID.  On the other hand, certain WebDAV clients (i.e., davfs2)
     /// do not support multi-step redirects, so setting this to `true` is
     /// necessary to allow such clients to download from `dandidav`.
-    pub(crate) prefer_s3_redirects: bool,
+    prefer_s3_redirects: bool,
+
+    /// Whether to add `response-content-disposition` query parameters to S3
+    /// URLs that blob asset requests are redirected to when
+    /// `prefer_s3_redirects` is `true`, so that blobs are still downloaded to
+    /// the same filenames as their assets
+    s3_content_disposition: bool,
+
+    /// The maximum number of requests that may be made to upstream servers
+    /// while handling a single incoming request, if any
+    upstream_request_budget: Option<NonZeroUsize>,
+
+    /// Limits on the number of concurrent upstream requests made on behalf of
+    /// each Dandiset, if any
+    dandiset_limits: Option<Arc<DandisetLimits>>,
+
+    /// How to present Zarr assets under `/dandisets/`
+    zarr_mode: ZarrMode,
+
+    /// The language in which to display HTML views to clients that do not
+    /// request a supported language via `Accept-Language`
+    default_language: Language,
+
+    /// A record of recently-served download redirects, if enabled
+    audit_log: Option<Arc<AuditLog>>,
+
+    /// Another Archive instance (served by a separate `dandidav` deployment)
+    /// to point users to when they request a Dandiset that only exists there
+    other_instance: Option<OtherInstance>,
+
+    /// The size in bytes at which `PROPFIND` responses are truncated, if any
+    max_propfind_size: Option<usize>,
+
+    /// Rules for which properties to report in `PROPFIND` responses
+    property_policy: Arc<PropertyPolicy>,
+
+    /// A client for fetching ranges of Zarr entries from S3 on behalf of
+    /// clients that send `GET` requests with `Range` headers.  If `None`,
+    /// such requests are redirected like any other.
+    range_proxy: Option<Client>,
+
+    /// A local disk cache from which small Zarr entries are served when range
+    /// proxying is enabled.  If `None`, only ranged requests for Zarr entries
+    /// are proxied, and the ranges are always fetched from S3.
+    object_cache: Option<ObjectCache>,
+
+    /// A short-lived cache of `PROPFIND` responses for the root & index
+    /// collections, used for requests without credentials.  If `None`, such
+    /// responses are not cached.
+    propfind_cache: Option<PropfindCache>,
+
+    /// A client for streaming blob assets from S3 through the server on
+    /// behalf of clients that send `GET` requests for them.  If `None`, such
+    /// requests are redirected.
+    blob_proxy: Option<Client>,
+
+    /// Tracker of the most frequently downloaded paths, if enabled.  Objects
+    /// at hot paths are admitted to the object cache under a larger size
+    /// limit.
+    hot_objects: Option<Arc<HotObjects>>,
+
+    /// The locks granted in response to `LOCK` requests, which are never
+    /// enforced.  See the [`lock`] module for more information.
+    locks: Arc<LockManager>,
+
+    /// The depth to use for `PROPFIND` requests without `Depth` headers
+    propfind_default_depth: Depth,
+
+    /// The maximum depth permitted for `PROPFIND` requests.  Requests with
+    /// greater depths are answered with 403 responses.
+    propfind_max_depth: FiniteDepth,
+
+    /// Whether to answer `PROPFIND` requests with infinite depth for paths
+    /// inside Dandiset versions or beneath `/zarrs/` by streaming a listing of
+    /// the entire tree beneath the path.  If `false`, such requests are
+    /// answered with 403 responses.
+    propfind_infinite_depth: bool,
+
+    /// The top-level collections listed at the root of the hierarchy, in the
+    /// order in which they are listed
+    root_mounts: Vec<Mount>,
+}
+
+/// Details of a `GET` request that matter if the request turns out to be for
+/// a downloadable resource or an HTML view
+#[derive(Clone, Debug)]
+struct DownloadRequest {
+    /// Whether the request was originally a `HEAD` request, in which case
+    /// large collection views are not rendered, as the response body would be
+    /// discarded anyway
+    head: bool,
+
+    /// The request's URL path, for recording in the audit log
+    uri_path: String,
+
+    /// The type of client that made the request, for recording in the audit
+    /// log
+    client_class: ClientClass,
+
+    /// The value of the request's `Range` header, if any.  If this is set,
+    /// the request is for a Zarr entry, and range proxying is enabled, the
+    /// requested range is fetched (or read from the object cache) and
+    /// returned instead of redirecting.
+    range: Option<HeaderValue>,
+
+    /// The value of the request's `If-None-Match` header, if any.  If this
+    /// matches the ETag of the requested resource, a 304 response is
+    /// returned instead of redirecting or serving the resource.
+    if_none_match: Option<HeaderValue>,
+
+    /// The value of the request's `If-Modified-Since` header, if any.  If the
+    /// request has no `If-None-Match` header and the requested item has not
+    /// been modified since this time, a 304 response is returned instead of
+    /// redirecting or serving the item.
+    if_modified_since: Option<HeaderValue>,
+
+    /// The value of the request's `If-Range` header, if any.  If this does not
+    /// match the current version of the requested item, the `Range` header is
+    /// ignored and the complete item is returned.
+    if_range: Option<HeaderValue>,
+
+    /// The value of the request's `Accept` header, if any.  If this is set
+    /// and the request is for a metadata document generated by `dandidav`,
+    /// the document is converted to the requested format if possible.
+    accept: Option<HeaderValue>,
+
+    /// The representation requested for the resource if it is a collection
+    listing_format: ListingFormat,
+}
+
+/// Another Archive instance served by a separate `dandidav` deployment, such
+/// as a staging instance for a `dandidav` serving the production instance
+#[derive(Clone, Debug)]
+pub(crate) struct OtherInstance {
+    /// The base URL of the `dandidav` deployment serving the other instance
+    pub(crate) url: HttpUrl,
+
+    /// A client for the other instance's API
+    pub(crate) dandi: DandiClient,
 }
 
 impl DandiDav {
+    /// Return a [`DandiDavBuilder`] for constructing a `DandiDav` that
+    /// fetches data using `dandi` & `zarrman` and renders HTML views with
+    /// `templater`
+    pub(crate) fn builder(
+        dandi: DandiClient,
+        zarrman: ZarrManClient,
+        templater: Arc<Templater>,
+    ) -> DandiDavBuilder {
+        DandiDavBuilder::new(dandi, zarrman, templater)
+    }
+
     /// Handle an incoming HTTP request and return a response.  This method
     /// must return `Result<T, Infallible>` for compatibility with `axum`.
     ///
+    /// If `user_clients` is set and the request has an `Authorization`
+    /// header, the request is handled using the per-user client for the
+    /// header's credentials (bypassing `propfind_cache`, as what such users
+    /// can see may differ); otherwise, it is handled using `dandi`.
+    pub(crate) async fn handle_request(
+        &self,
+        req: Request<Body>,
+    ) -> Result<Response<Body>, Infallible> {
+        if let Some(ref users) = self.user_clients {
+            if let Some(authorization) = req.headers().get(AUTHORIZATION) {
+                return match users.client_for(authorization).await {
+                    Ok(dandi) => self.inner_handle_request(&dandi, None, req).await,
+                    Err(e) => Ok(e.into_response()),
+                };
+            }
+        }
+        self.inner_handle_request(&self.dandi, self.propfind_cache.as_ref(), req)
+            .await
+    }
+
+    /// Handle an incoming HTTP request using `dandi` and return a
+    /// response.  `PROPFIND` responses for the root & index collections are
+    /// looked up in & stored in `propfind_cache`, if given.
+    ///
     /// The request parameters from the URL path and (for `PROPFIND`) "Depth"
     /// header & request body are parsed & extracted and then passed to the
     /// appropriate method for the request's verb for dedicated handling.
@@ -67,29 +311,328 @@ impl DandiDav {
     /// Any errors returned are logged and converted to 4xx or 5xx responses,
     /// as appropriate.  The final response also has
     /// [`WEBDAV_RESPONSE_HEADERS`] added.
-    pub(crate) async fn handle_request(
+    async fn inner_handle_request(
         &self,
+        dandi: &DandiClient,
+        propfind_cache: Option<&PropfindCache>,
         req: Request<Body>,
     ) -> Result<Response<Body>, Infallible> {
-        let resp = match req.extract::<DavRequest, _>().await {
-            Ok(DavRequest::Get { path, pathparts }) => self.get(&path, pathparts).await,
-            Ok(DavRequest::Propfind { path, depth, query }) => {
-                self.propfind(&path, depth, query).await
+        let client_class = ClientClass::from_user_agent(
+            req.headers().get(USER_AGENT).and_then(|v| v.to_str().ok()),
+        );
+        let uri_path = req.uri().path().to_owned();
+        let if_none_match = req.headers().get(IF_NONE_MATCH).cloned();
+        let if_modified_since = req.headers().get(IF_MODIFIED_SINCE).cloned();
+        let if_range = req.headers().get(IF_RANGE).cloned();
+        let accept = req.headers().get(ACCEPT).cloned();
+        let head = req.extensions().get::<HeadRequest>().is_some();
+        // Boxed, as the future is large and is moved into `with_budget()`
+        let handled = Box::pin(async {
+            let request = match req.extract::<DavRequest, _>().await {
+                Ok(request) => request,
+                Err(r) => return Ok(r),
+            };
+            let path = request.path().cloned();
+            let pool = path
+                .as_ref()
+                .and_then(DavPath::dandiset_id)
+                .zip(self.dandiset_limits.as_ref())
+                .map(|(dandiset_id, limits)| limits.pool(dandiset_id));
+            // Boxed, as the future is large
+            let r = with_dandiset_pool(
+                pool,
+                Box::pin(async {
+                    match request {
+                        DavRequest::Get {
+                            path,
+                            zarr_pin: Some(pin),
+                            ..
+                        }
+                        | DavRequest::Propfind {
+                            path,
+                            zarr_pin: Some(pin),
+                            ..
+                        } => self.pin_zarr(dandi, &path, &pin, &uri_path).await,
+                        DavRequest::Get {
+                            path,
+                            export_paths: Some(paths),
+                            ..
+                        } => self.export(dandi, &path, paths).await,
+                        DavRequest::Get {
+                            path,
+                            pathparts,
+                            zarr_mode,
+                            zarr_pin: None,
+                            language,
+                            range,
+                            export_paths: None,
+                            listing_format,
+                        } => {
+                            let zarr_mode = zarr_mode.unwrap_or(self.zarr_mode);
+                            let language = language.unwrap_or(self.default_language);
+                            match self
+                                .get(
+                                    dandi,
+                                    &path,
+                                    pathparts,
+                                    zarr_mode,
+                                    language,
+                                    DownloadRequest {
+                                        head,
+                                        uri_path: uri_path.clone(),
+                                        client_class,
+                                        range,
+                                        if_none_match,
+                                        if_modified_since,
+                                        if_range,
+                                        accept,
+                                        listing_format,
+                                    },
+                                )
+                                .await
+                            {
+                                Err(e) if e.class() == ErrorClass::NotFound => self
+                                    .other_instance_hint(dandi, &path, &uri_path, language)
+                                    .await
+                                    .unwrap_or(Err(e)),
+                                r => r,
+                            }
+                        }
+                        DavRequest::Propfind {
+                            path,
+                            depth,
+                            query,
+                            zarr_mode,
+                            zarr_pin: None,
+                        } => {
+                            let zarr_mode = zarr_mode.unwrap_or(self.zarr_mode);
+                            let depth = depth.unwrap_or(self.propfind_default_depth);
+                            match depth.within(self.propfind_max_depth) {
+                                Some(depth) => {
+                                    self.propfind(
+                                        dandi,
+                                        &path,
+                                        depth,
+                                        query,
+                                        zarr_mode,
+                                        propfind_cache,
+                                    )
+                                    .await
+                                }
+                                None if depth == Depth::Infinity
+                                    && self.propfind_infinite_depth
+                                    && path.allows_infinite_depth() =>
+                                {
+                                    self.propfind_infinite(dandi, &path, query, zarr_mode).await
+                                }
+                                None => Ok(finite_depth_error()),
+                            }
+                        }
+                        DavRequest::Lock {
+                            path,
+                            depth,
+                            info,
+                            if_token,
+                        } => self.lock(dandi, &path, depth, info, if_token).await,
+                        DavRequest::Unlock { path, token } => {
+                            self.unlock(dandi, &path, &token).await
+                        }
+                        DavRequest::Options => Ok(StatusCode::NO_CONTENT.into_response()),
+                        DavRequest::Redirect { location } => {
+                            Ok(Redirect::permanent(&location).into_response())
+                        }
+                    }
+                }),
+            )
+            .await;
+            match r {
+                Err(e) if e.class() == ErrorClass::NotFound => Ok(self
+                    .not_found_response(dandi, e, path.as_ref(), &uri_path)
+                    .await),
+                r => r,
             }
-            Ok(DavRequest::Options) => Ok(StatusCode::NO_CONTENT.into_response()),
-            Err(r) => Ok(r),
+        });
+        let resp = match self.upstream_request_budget {
+            Some(limit) => match with_budget(limit.get(), handled).await {
+                (_, true) => {
+                    tracing::info!(%limit, "Request exceeded its upstream request budget");
+                    Ok(budget_exceeded_error(limit))
+                }
+                (r, false) => r,
+            },
+            None => handled.await,
         };
         let resp = resp.unwrap_or_else(|e| {
                 let class = e.class();
                 let e = anyhow::Error::from(e);
                 tracing::info!(error = ?e, status = class.to_status().as_u16(), "Error processing request");
-                if class == ErrorClass::NotFound {
-                    not_found()
-                } else {
-                    (class.to_status(), format!("{e:?}")).into_response()
-                }
+                (class.to_status(), format!("{e:?}")).into_response()
             });
-        Ok((WEBDAV_RESPONSE_HEADERS, resp).into_response())
+        let mut resp = (WEBDAV_RESPONSE_HEADERS, resp).into_response();
+        // Responses may differ depending on the requested Zarr mode.  This is
+        // appended rather than set so as not to replace any other `Vary`
+        // header already on the response.
+        resp.headers_mut()
+            .append(VARY, HeaderValue::from_static(ZARR_MODE_HEADER));
+        Ok(resp)
+    }
+
+    /// Log the "not found" error `e` encountered while handling a request for
+    /// `path` (with original request path `uri_path`) and return a 404
+    /// response describing which level of path resolution failed.
+    ///
+    /// When the error is a 404 from the Archive that could be due to either
+    /// the Dandiset or the version not existing, additional requests are made
+    /// to the Archive to determine which is the case.
+    async fn not_found_response(
+        &self,
+        dandi: &DandiClient,
+        e: DavError,
+        path: Option<&DavPath>,
+        uri_path: &str,
+    ) -> Response<Body> {
+        let nf = match NotFound::from_error(&e) {
+            Some(nf) => nf,
+            None => self.diagnose_not_found(dandi, path, uri_path).await,
+        };
+        let e = anyhow::Error::from(e);
+        tracing::info!(
+            error = ?e,
+            status = StatusCode::NOT_FOUND.as_u16(),
+            not_found = nf.kind(),
+            "Error processing request"
+        );
+        nf.into_response()
+    }
+
+    /// Determine which level of resolution of `path` failed for a "not found"
+    /// error that does not identify the level itself
+    async fn diagnose_not_found(
+        &self,
+        dandi: &DandiClient,
+        path: Option<&DavPath>,
+        uri_path: &str,
+    ) -> NotFound {
+        let fallback = || NotFound::Path {
+            path: uri_path.to_owned(),
+        };
+        let Some(path) = path else {
+            return fallback();
+        };
+        let Some(dandiset_id) = path.dandiset_id() else {
+            return fallback();
+        };
+        let d = dandi.dandiset(dandiset_id.clone());
+        if is_archive_404(&d.get().await) {
+            return NotFound::Dandiset {
+                dandiset_id: dandiset_id.clone(),
+            };
+        }
+        let version = match path.version() {
+            Some(VersionSpec::Draft) => Some(VersionId::Draft),
+            Some(VersionSpec::Published(v)) => Some(VersionId::Published(v.clone())),
+            Some(VersionSpec::Latest) | None => None,
+        };
+        if let Some(version) = version {
+            if is_archive_404(&d.version(version.clone()).get().await) {
+                return NotFound::Version {
+                    dandiset_id: dandiset_id.clone(),
+                    version,
+                };
+            }
+        }
+        match path {
+            DavPath::AssetById { asset_id, .. } => NotFound::AssetId {
+                asset_id: asset_id.to_string(),
+            },
+            _ => fallback(),
+        }
+    }
+
+    /// Handle a request for the given `path` that is pinned to the Zarr
+    /// snapshot `pin` by redirecting to the corresponding path in the Zarr's
+    /// manifest under `/zarrs/`.  `uri_path` is the original request path,
+    /// used to determine whether the redirect target should end in a slash.
+    async fn pin_zarr(
+        &self,
+        dandi: &DandiClient,
+        path: &DavPath,
+        pin: &ZarrPin,
+        uri_path: &str,
+    ) -> Result<Response<Body>, DavError> {
+        let no_snapshot = || DavError::NoZarrSnapshot {
+            path: uri_path.to_owned(),
+        };
+        let DavPath::DandiResource {
+            dandiset_id,
+            version,
+            path,
+        } = path
+        else {
+            return Err(no_snapshot());
+        };
+        let Some((zarr, entry_path)) = self
+            .get_version_handler(dandi, dandiset_id, version, self.zarr_mode)
+            .await?
+            .endpoint
+            .locate_zarr(path)
+            .await?
+        else {
+            return Err(no_snapshot());
+        };
+        let checksum = match pin {
+            ZarrPin::Current => zarr.checksum().ok_or_else(no_snapshot)?,
+            ZarrPin::Checksum(checksum) => checksum,
+        };
+        let manifest_path =
+            ManifestPath::for_zarr(&zarr.zarr_id, checksum).ok_or_else(no_snapshot)?;
+        let mut location = format!("/{}", manifest_path.to_web_path());
+        if let Some(p) = entry_path {
+            location.push_str(p.as_ref());
+            if uri_path.ends_with('/') {
+                location.push('/');
+            }
+        }
+        Ok(Redirect::temporary(Href::from_path(&location).as_ref()).into_response())
+    }
+
+    /// If `path` refers to a Dandiset that does not exist on this Archive
+    /// instance but does exist on the other instance (if one is configured),
+    /// return a 404 response with an HTML page (in the language `language`)
+    /// linking to `uri_path` on the `dandidav` serving the other instance.
+    /// Otherwise, return `None`.
+    async fn other_instance_hint(
+        &self,
+        dandi: &DandiClient,
+        path: &DavPath,
+        uri_path: &str,
+        language: Language,
+    ) -> Option<Result<Response<Body>, DavError>> {
+        let other = self.other_instance.as_ref()?;
+        let dandiset_id = path.dandiset_id()?;
+        // The 404 may have been for a nonexistent resource within a Dandiset
+        // that does exist here, in which case there's nothing to suggest.
+        match dandi.dandiset(dandiset_id.clone()).get().await {
+            Err(e) if e.class() == ErrorClass::NotFound => (),
+            _ => return None,
+        }
+        match other.dandi.dandiset(dandiset_id.clone()).get().await {
+            Ok(_) => (),
+            Err(e) => {
+                if e.class() != ErrorClass::NotFound {
+                    let e = anyhow::Error::from(e);
+                    tracing::info!(error = ?e, "Failed to query other Archive instance");
+                }
+                return None;
+            }
+        }
+        let href = format!("{}{uri_path}", other.url.as_str().trim_end_matches('/'));
+        Some(
+            self.templater
+                .render_other_instance(dandiset_id, &href, language)
+                .map(|html| (StatusCode::NOT_FOUND, HTML_RESPONSE_HEADERS, html).into_response())
+                .map_err(Into::into),
+        )
     }
 
     /// Handle a `GET` request for the given `path`.
@@ -97,78 +640,524 @@ impl DandiDav {
     /// `pathparts` contains the individual components of the request URL path
     /// prior to parsing into `path`.  It is needed for things like breadcrumbs
     /// in HTML views of collection resources.
+    ///
+    /// `language` is the language in which to display HTML views of
+    /// collection resources.
+    ///
+    /// `download` describes the request for the purposes of serving a
+    /// download or HTML view.
     async fn get(
         &self,
+        dandi: &DandiClient,
         path: &DavPath,
         pathparts: Vec<Component>,
+        zarr_mode: ZarrMode,
+        language: Language,
+        download: DownloadRequest,
     ) -> Result<Response<Body>, DavError> {
-        match self.get_resource_with_children(path).await? {
-            DavResourceWithChildren::Collection { children, .. } => {
-                let html = self.templater.render_collection(children, pathparts)?;
-                Ok(([(CONTENT_TYPE, HTML_CONTENT_TYPE)], html).into_response())
+        let mut res = self
+            .get_resource_with_children(dandi, path, zarr_mode)
+            .await?;
+        self.mark_private_downloads(dandi, &mut res);
+        match res {
+            DavResourceWithChildren::Collection { col, children } => {
+                let mut resp = self
+                    .get_collection(col, children, pathparts, language, &download)
+                    .await?;
+                // Collections may be served as either HTML or JSON
+                resp.headers_mut()
+                    .append(VARY, HeaderValue::from_static("Accept"));
+                Ok(resp)
             }
             DavResourceWithChildren::Item(DavItem {
                 content_type,
+                modified,
                 content: DavContent::Blob(blob),
                 ..
             }) => {
-                if let Some(ct) = content_type {
-                    Ok(([(CONTENT_TYPE, ct)], blob).into_response())
+                let native = content_type
+                    .as_deref()
+                    .and_then(MetadataFormat::from_content_type);
+                // Whether the format of the response was negotiated via the
+                // `Accept` header
+                let negotiated = native.is_some();
+                let (blob, content_type) = match native {
+                    Some(native) => {
+                        let format = download
+                            .accept
+                            .as_ref()
+                            .and_then(|v| v.to_str().ok())
+                            .map_or(native, |accept| MetadataFormat::negotiate(accept, native));
+                        let blob = if format == native {
+                            blob
+                        } else {
+                            run_blocking(move || native.convert(blob, format)).await?
+                        };
+                        (blob, Some(HeaderValue::from_static(format.content_type())))
+                    }
+                    None => (
+                        blob,
+                        content_type.and_then(|ct| HeaderValue::try_from(ct).ok()),
+                    ),
+                };
+                let etag = content_etag(&blob);
+                let mut resp = if is_not_modified(
+                    download.if_none_match.as_ref(),
+                    download.if_modified_since.as_ref(),
+                    Some(&etag),
+                    modified,
+                ) {
+                    StatusCode::NOT_MODIFIED.into_response()
                 } else {
-                    Ok(blob.into_response())
+                    let range = download
+                        .range
+                        .as_ref()
+                        .filter(|_| if_range_matches(download.if_range.as_ref(), &etag, modified));
+                    let mut resp = blob_response(blob, range);
+                    if let Some(ct) = content_type {
+                        resp.headers_mut().insert(CONTENT_TYPE, ct);
+                    }
+                    resp
+                };
+                let headers = resp.headers_mut();
+                headers.insert(ETAG, etag);
+                if let Some(m) = modified {
+                    headers.insert(LAST_MODIFIED, last_modified(m));
                 }
+                if negotiated {
+                    headers.insert(VARY, HeaderValue::from_static("Accept"));
+                }
+                Ok(resp)
             }
             DavResourceWithChildren::Item(DavItem {
+                path,
                 content: DavContent::Redirect(redir),
+                kind,
+                size,
+                modified,
+                etag: raw_etag,
+                content_type,
                 ..
-            }) => Ok(
-                Redirect::temporary(redir.get_url(self.prefer_s3_redirects).as_str())
-                    .into_response(),
-            ),
+            }) => {
+                let etag = raw_etag
+                    .as_deref()
+                    .map(entity_tag)
+                    .and_then(|e| HeaderValue::from_str(&e).ok());
+                let hot = self
+                    .hot_objects
+                    .as_ref()
+                    .is_some_and(|tracker| tracker.record(&download.uri_path));
+                if is_not_modified(
+                    download.if_none_match.as_ref(),
+                    download.if_modified_since.as_ref(),
+                    etag.as_ref(),
+                    modified,
+                ) {
+                    let mut resp = StatusCode::NOT_MODIFIED.into_response();
+                    if let Some(tag) = etag {
+                        resp.headers_mut().insert(ETAG, tag);
+                    }
+                    if let Some(m) = modified {
+                        resp.headers_mut().insert(LAST_MODIFIED, last_modified(m));
+                    }
+                    return Ok(resp);
+                }
+                if let Some(ref log) = self.audit_log {
+                    log.record(DownloadRecord {
+                        timestamp: OffsetDateTime::now_utc(),
+                        path: download.uri_path,
+                        client_class: download.client_class,
+                    });
+                }
+                let (proxy_url, target) = match redir {
+                    types::Redirect::Private { ref location, .. } => {
+                        let url = dandi.presign_download(location).await?;
+                        (url.clone(), url)
+                    }
+                    types::Redirect::Embargoed(ref url) => {
+                        let url = dandi.resolve_download(url).await?;
+                        (url.clone(), url)
+                    }
+                    _ => {
+                        let target = redir.target_url(
+                            path.name_str(),
+                            self.prefer_s3_redirects,
+                            self.s3_content_disposition,
+                        );
+                        (redir.get_url(true).clone(), target)
+                    }
+                };
+                let range = download.range.filter(|_| match etag {
+                    Some(ref tag) => if_range_matches(download.if_range.as_ref(), tag, modified),
+                    None => download.if_range.is_none(),
+                });
+                if let (Some(client), ResourceKind::Blob) = (&self.blob_proxy, kind) {
+                    let mut resp =
+                        proxy::proxy_object(client, proxy_url, range, download.head).await?;
+                    if download.head {
+                        resp.extensions_mut().insert(HeadRequest);
+                    }
+                    // Error responses (i.e., 416s) keep S3's Content-Type, as
+                    // it describes the error body.
+                    let success = resp.status().is_success();
+                    let headers = resp.headers_mut();
+                    if let Some(ct) = content_type
+                        .filter(|_| success)
+                        .and_then(|ct| HeaderValue::try_from(ct).ok())
+                    {
+                        headers.insert(CONTENT_TYPE, ct);
+                    }
+                    if let Some(tag) = etag {
+                        headers.insert(ETAG, tag);
+                    }
+                    if let Some(m) = modified {
+                        headers.insert(LAST_MODIFIED, last_modified(m));
+                    }
+                    return Ok(resp);
+                }
+                if let (Some(client), ResourceKind::ZarrEntry) = (&self.range_proxy, kind) {
+                    if let (Some(cache), Some(digest)) = (
+                        self.object_cache
+                            .as_ref()
+                            .filter(|_| size.is_some_and(|sz| ObjectCache::accepts(sz, hot))),
+                        raw_etag,
+                    ) {
+                        let data = cache.get(client, proxy_url, &digest).await?;
+                        return Ok(objcache::serve_cached(data, range.as_ref(), etag));
+                    }
+                    if let Some(range) = range {
+                        return Ok(proxy::proxy_range(client, proxy_url, range).await?);
+                    }
+                }
+                let mut resp = Redirect::temporary(target.as_str()).into_response();
+                if let Some(tag) = etag {
+                    resp.headers_mut().insert(ETAG, tag);
+                }
+                if let Some(m) = modified {
+                    resp.headers_mut().insert(LAST_MODIFIED, last_modified(m));
+                }
+                Ok(resp)
+            }
             DavResourceWithChildren::Item(DavItem {
                 content: DavContent::Missing,
                 ..
-            }) => {
-                // TODO: Log something
-                Ok(not_found())
+            }) => Ok(not_found(&download.uri_path)),
+        }
+    }
+
+    /// Respond to a `GET` request for the collection `col` with children
+    /// `children` with either an HTML view or a JSON listing, as requested by
+    /// `download`
+    async fn get_collection(
+        &self,
+        col: DavCollection,
+        children: Vec<DavResource>,
+        pathparts: Vec<Component>,
+        language: Language,
+        download: &DownloadRequest,
+    ) -> Result<Response<Body>, DavError> {
+        let body = match download.listing_format {
+            ListingFormat::Json => {
+                let entries = children
+                    .into_iter()
+                    .map(ListingEntry::from)
+                    .collect::<Vec<_>>();
+                run_blocking(move || {
+                    serde_json::to_string(&entries).expect("ListingEntry should serialize")
+                })
+                .await
+            }
+            ListingFormat::Html if children.len() > HTML_STREAM_THRESHOLD => {
+                if download.head {
+                    // Streamed views have no known length or ETag, so the
+                    // headers can be produced without rendering anything.
+                    let mut resp = (HTML_RESPONSE_HEADERS, Body::empty()).into_response();
+                    resp.extensions_mut().insert(HeadRequest);
+                    return Ok(resp);
+                }
+                let body = Arc::clone(&self.templater).render_collection_stream(
+                    children,
+                    col.displayed_citation(),
+                    pathparts,
+                    language,
+                );
+                return Ok((HTML_RESPONSE_HEADERS, body).into_response());
+            }
+            ListingFormat::Html => {
+                let templater = Arc::clone(&self.templater);
+                let citation = col.displayed_citation();
+                run_blocking(move || {
+                    templater.render_collection(children, citation, pathparts, language)
+                })
+                .await?
+            }
+        };
+        let etag = content_etag(body.as_bytes());
+        if is_not_modified(
+            download.if_none_match.as_ref(),
+            download.if_modified_since.as_ref(),
+            Some(&etag),
+            None,
+        ) {
+            return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response());
+        }
+        match download.listing_format {
+            ListingFormat::Json => {
+                Ok(([(CONTENT_TYPE, JSON_CONTENT_TYPE)], [(ETAG, etag)], body).into_response())
+            }
+            ListingFormat::Html => {
+                Ok((HTML_RESPONSE_HEADERS, [(ETAG, etag)], body).into_response())
             }
         }
     }
 
+    /// Handle a `GET` request with `paths` query parameters for the given
+    /// `path`, which must be that of a Dandiset version, by responding with a
+    /// JSON manifest of all files at or beneath the given `paths` within the
+    /// version.  Zarrs are always listed as single files.
+    async fn export(
+        &self,
+        dandi: &DandiClient,
+        path: &DavPath,
+        paths: Vec<PurePath>,
+    ) -> Result<Response<Body>, DavError> {
+        let DavPath::Version {
+            dandiset_id,
+            version,
+        } = path
+        else {
+            return Ok((
+                StatusCode::BAD_REQUEST,
+                "The \"paths\" query parameter is only supported for Dandiset versions\n",
+            )
+                .into_response());
+        };
+        let handler = self
+            .get_version_handler(dandi, dandiset_id, version, ZarrMode::Collapsed)
+            .await?;
+        let opts = export::ExportOptions {
+            prefer_s3_redirects: self.prefer_s3_redirects,
+            s3_content_disposition: self.s3_content_disposition,
+            locate_private: |url: &HttpUrl| dandi.private_location(url),
+        };
+        let manifest = export::export(&handler, paths, opts).await;
+        let body = serde_json::to_string(&manifest).expect("ExportManifest should serialize");
+        Ok(([(CONTENT_TYPE, JSON_CONTENT_TYPE)], body).into_response())
+    }
+
+    /// Mark the downloads of the items in `res` that are stored in private S3
+    /// buckets as such
+    fn mark_private_downloads(&self, dandi: &DandiClient, res: &mut DavResourceWithChildren) {
+        let locate = |url: &HttpUrl| dandi.private_location(url);
+        match res {
+            DavResourceWithChildren::Collection { children, .. } => {
+                for child in children {
+                    if let DavResource::Item(item) = child {
+                        item.mark_private(locate);
+                    }
+                }
+            }
+            DavResourceWithChildren::Item(item) => item.mark_private(locate),
+        }
+    }
+
     /// Handle a `PROPFIND` request for the given `path`.  `depth` is the value
     /// of the `Depth` header, and `query` is the parsed request body (with an
     /// empty body already defaulted to "allprop" as per the RFC).
     async fn propfind(
         &self,
+        dandi: &DandiClient,
         path: &DavPath,
         depth: FiniteDepth,
         query: PropFind,
+        zarr_mode: ZarrMode,
+        cache: Option<&PropfindCache>,
     ) -> Result<Response<Body>, DavError> {
+        let cached = cache.and_then(|c| Some((c, PropfindKey::new(path, depth, &query)?)));
+        if let Some((c, ref key)) = cached {
+            if let Some(body) = c.get(key).await {
+                return Ok(multistatus_response(body));
+            }
+        }
         let resources = match depth {
-            FiniteDepth::Zero => vec![self.get_resource(path).await?],
-            FiniteDepth::One => self.get_resource_with_children(path).await?.into_vec(),
+            FiniteDepth::Zero => vec![self.get_resource(dandi, path, zarr_mode).await?],
+            FiniteDepth::One => self
+                .get_resource_with_children(dandi, path, zarr_mode)
+                .await?
+                .into_vec(),
         };
-        let response = resources
-            .into_iter()
-            .map(|r| query.find(&r))
-            .collect::<Vec<_>>();
+        let max_size = self.max_propfind_size;
+        let policy = Arc::clone(&self.property_policy);
+        let (body, has_errors) = run_blocking(move || {
+            let response = resources
+                .into_iter()
+                .map(|r| query.find(&r, &policy))
+                .collect::<Vec<_>>();
+            let ms = Multistatus { response };
+            let has_errors = ms.has_errors();
+            let body = match max_size {
+                Some(limit) => ms.into_xml_limited(limit),
+                None => ms.to_xml(),
+            };
+            (body, has_errors)
+        })
+        .await;
+        let body = bytes::Bytes::from(body);
+        // Don't keep serving failures that may be transient
+        if let Some((c, key)) = cached.filter(|_| !has_errors) {
+            c.insert(key, body.clone()).await;
+        }
+        Ok(multistatus_response(body))
+    }
+
+    /// Handle a `PROPFIND` request with infinite depth for `path`, streaming
+    /// the `response` elements for the resource and everything beneath it to
+    /// the client as they are retrieved.
+    ///
+    /// The resource itself is fetched before the response is started, so that
+    /// errors for it are reported with the appropriate status.  The tree
+    /// beneath it is then walked in a separate task under the same upstream
+    /// request budget & Dandiset concurrency limit as the incoming request.
+    /// Responses produced this way are never truncated.
+    async fn propfind_infinite(
+        &self,
+        dandi: &DandiClient,
+        path: &DavPath,
+        query: PropFind,
+        zarr_mode: ZarrMode,
+    ) -> Result<Response<Body>, DavError> {
+        let top = self
+            .get_resource_with_children(dandi, path, zarr_mode)
+            .await?;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(PROPFIND_STREAM_BUFFER);
+        let pool = path
+            .dandiset_id()
+            .zip(self.dandiset_limits.as_ref())
+            .map(|(dandiset_id, limits)| limits.pool(dandiset_id));
+        let budget = self.upstream_request_budget;
+        // The walk outlives the handling of the request, so it needs its own
+        // handles on the `DandiDav` and client.
+        let dav = self.clone();
+        let dandi = dandi.clone();
+        tokio::spawn(async move {
+            // Boxed, as the future is large
+            let walk = Box::pin(dav.walk_tree(&dandi, top, zarr_mode, &query, &tx));
+            let r = with_dandiset_pool(pool, async {
+                match budget {
+                    Some(limit) => with_budget(limit.get(), walk).await.0,
+                    None => walk.await,
+                }
+            })
+            .await;
+            if let Err(e) = r {
+                let e = anyhow::Error::from(e);
+                tracing::info!(error = ?e, "Aborting streamed PROPFIND response");
+                // If the receiver is gone, there's no one to tell.
+                let _ = tx.send(Err(e)).await;
+            }
+        });
         Ok((
             StatusCode::MULTI_STATUS,
             [(CONTENT_TYPE, DAV_XML_CONTENT_TYPE)],
-            (Multistatus { response }).to_xml()?,
+            Body::from_stream(futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx))),
         )
             .into_response())
     }
 
+    /// Walk the tree rooted at `top` depth-first, sending the serialized
+    /// `PROPFIND` responses for each resource in it through `tx`, one
+    /// collection listing at a time.  A collection whose children cannot be
+    /// retrieved is reported with the error's status in place of its
+    /// properties.
+    ///
+    /// Returns an error if the walk cannot continue because the upstream
+    /// request budget has been spent.  If the receiver is dropped, the walk
+    /// stops early.
+    async fn walk_tree(
+        &self,
+        dandi: &DandiClient,
+        top: DavResourceWithChildren,
+        zarr_mode: ZarrMode,
+        query: &PropFind,
+        tx: &tokio::sync::mpsc::Sender<Result<bytes::Bytes, anyhow::Error>>,
+    ) -> Result<(), DavError> {
+        let mut encoder = MultistatusEncoder::new();
+        let mut pending = Vec::new();
+        let mut listing = Some(top);
+        loop {
+            let batch = match listing.take() {
+                Some(DavResourceWithChildren::Collection { col, children }) => {
+                    let mut batch = vec![DavResource::Collection(col)];
+                    let start = pending.len();
+                    for child in children {
+                        match child {
+                            DavResource::Collection(c) => pending.push(c),
+                            r => batch.push(r),
+                        }
+                    }
+                    // Visit subcollections in listing order
+                    pending[start..].reverse();
+                    batch
+                }
+                Some(DavResourceWithChildren::Item(item)) => vec![DavResource::Item(item)],
+                None => Vec::new(),
+            };
+            for r in &batch {
+                encoder.push(&query.find(r, &self.property_policy));
+            }
+            if !batch.is_empty()
+                && tx
+                    .send(Ok(bytes::Bytes::from(encoder.take())))
+                    .await
+                    .is_err()
+            {
+                return Ok(());
+            }
+            let Some(col) = pending.pop() else {
+                break;
+            };
+            let Some(path) = col.path.as_ref().and_then(DavPath::from_dir_path) else {
+                continue;
+            };
+            match self
+                .get_resource_with_children(dandi, &path, zarr_mode)
+                .await
+            {
+                Ok(res) => listing = Some(res),
+                Err(e) if e.class() == ErrorClass::ServiceUnavailable => return Err(e),
+                Err(e) => {
+                    let class = e.class();
+                    let e = anyhow::Error::from(e);
+                    tracing::info!(error = ?e, "Failed to list collection in streamed PROPFIND response");
+                    let Some(dirpath) = col.path else {
+                        continue;
+                    };
+                    let path = match dirpath.parent() {
+                        Some(parent) => parent.join(&PurePath::from(dirpath.name())),
+                        None => PurePath::from(dirpath.name()),
+                    };
+                    let unavailable = DavResource::Unavailable(DavUnavailable { path, class });
+                    encoder.push(&query.find(&unavailable, &self.property_policy));
+                }
+            }
+        }
+        // If the receiver is gone, there's no one to tell.
+        let _ = tx.send(Ok(bytes::Bytes::from(encoder.finish()))).await;
+        Ok(())
+    }
+
     /// Obtain a handler for fetching resources for the given version of the
     /// given Dandiset.  If `version` is `VersionSpec::Latest`, the most recent
-    /// published version of the Dandiset is used.
+    /// published version of the Dandiset is used.  Zarr assets fetched by the
+    /// handler are presented according to `zarr_mode`.
     async fn get_version_handler<'a>(
         &'a self,
+        dandi: &'a DandiClient,
         dandiset_id: &'a DandisetId,
         version_spec: &'a VersionSpec,
+        zarr_mode: ZarrMode,
     ) -> Result<VersionHandler<'a>, DavError> {
-        let d = self.dandi.dandiset(dandiset_id.clone());
+        let d = dandi.dandiset(dandiset_id.clone());
         let endpoint = match version_spec {
             VersionSpec::Draft => d.version(VersionId::Draft),
             VersionSpec::Published(v) => d.version(VersionId::Published(v.clone())),
@@ -185,16 +1174,89 @@ impl DandiDav {
             dandiset_id,
             version_spec,
             endpoint,
+            zarrman: &self.zarrman,
+            zarr_mode,
         })
     }
 
+    /// Handle a `LOCK` request for `path`.  If `info` is `None`, the request
+    /// is a refresh of the existing lock with token `if_token`; otherwise, a
+    /// new lock is granted, regardless of any other locks held on the
+    /// resource.
+    async fn lock(
+        &self,
+        dandi: &DandiClient,
+        path: &DavPath,
+        depth: LockDepth,
+        info: Option<LockInfo>,
+        if_token: Option<String>,
+    ) -> Result<Response<Body>, DavError> {
+        let href = self.get_resource(dandi, path, self.zarr_mode).await?.href();
+        let Some(info) = info else {
+            let Some(token) = if_token else {
+                return Ok((
+                    StatusCode::BAD_REQUEST,
+                    "Lock refresh requests must include a lock token in an \"If\" header\n",
+                )
+                    .into_response());
+            };
+            return Ok(match self.locks.refresh(&href, &token) {
+                Some(lock) => (
+                    [(CONTENT_TYPE, DAV_XML_CONTENT_TYPE)],
+                    lock.to_lock_response_xml(),
+                )
+                    .into_response(),
+                None => StatusCode::PRECONDITION_FAILED.into_response(),
+            });
+        };
+        match self.locks.lock(href, depth, info) {
+            Ok(lock) => {
+                let token = HeaderValue::from_str(&format!("<{}>", lock.token))
+                    .expect("lock token should be a valid header value");
+                Ok((
+                    [
+                        (CONTENT_TYPE, HeaderValue::from_static(DAV_XML_CONTENT_TYPE)),
+                        (HeaderName::from_static("lock-token"), token),
+                    ],
+                    lock.to_lock_response_xml(),
+                )
+                    .into_response())
+            }
+            Err(e) => {
+                tracing::warn!("Refusing LOCK request: {e}");
+                Ok((StatusCode::SERVICE_UNAVAILABLE, format!("503\n{e}\n")).into_response())
+            }
+        }
+    }
+
+    /// Handle an `UNLOCK` request for `path` releasing the lock with token
+    /// `token`
+    async fn unlock(
+        &self,
+        dandi: &DandiClient,
+        path: &DavPath,
+        token: &str,
+    ) -> Result<Response<Body>, DavError> {
+        let href = self.get_resource(dandi, path, self.zarr_mode).await?.href();
+        if self.locks.unlock(&href, token) {
+            Ok(StatusCode::NO_CONTENT.into_response())
+        } else {
+            Ok(lock_token_mismatch_error())
+        }
+    }
+
     /// Get details on the resource at the given `path`
-    async fn get_resource(&self, path: &DavPath) -> Result<DavResource, DavError> {
+    async fn get_resource(
+        &self,
+        dandi: &DandiClient,
+        path: &DavPath,
+        zarr_mode: ZarrMode,
+    ) -> Result<DavResource, DavError> {
         match path {
             DavPath::Root => Ok(DavResource::root()),
             DavPath::DandisetIndex => Ok(DavResource::Collection(DavCollection::dandiset_index())),
             DavPath::Dandiset { dandiset_id } => {
-                let ds = self.dandi.dandiset(dandiset_id.clone()).get().await?;
+                let ds = dandi.dandiset(dandiset_id.clone()).get().await?;
                 Ok(DavResource::Collection(ds.into()))
             }
             DavPath::DandisetReleases { dandiset_id } => {
@@ -208,7 +1270,7 @@ impl DandiDav {
                 dandiset_id,
                 version,
             } => self
-                .get_version_handler(dandiset_id, version)
+                .get_version_handler(dandi, dandiset_id, version, zarr_mode)
                 .await?
                 .get()
                 .await
@@ -217,25 +1279,42 @@ impl DandiDav {
                 dandiset_id,
                 version,
             } => self
-                .get_version_handler(dandiset_id, version)
+                .get_version_handler(dandi, dandiset_id, version, zarr_mode)
                 .await?
                 .get_dandiset_yaml()
                 .await
                 .map(DavResource::Item),
-            DavPath::DandiResource {
+            DavPath::AssetById {
                 dandiset_id,
                 version,
-                path,
+                asset_id,
             } => {
-                self.get_version_handler(dandiset_id, version)
+                self.get_version_handler(dandi, dandiset_id, version, zarr_mode)
                     .await?
-                    .get_resource(path)
+                    .get_asset_by_id(asset_id)
                     .await
             }
+            DavPath::DandiResource {
+                dandiset_id,
+                version,
+                path,
+            } => {
+                let handler = &self
+                    .get_version_handler(dandi, dandiset_id, version, zarr_mode)
+                    .await?;
+                lookup_escaped(path, |p| async move { handler.get_resource(&p).await }).await
+            }
             DavPath::ZarrIndex => Ok(DavResource::Collection(DavCollection::zarr_index())),
+            DavPath::ZarrEntryByChecksum { digest } => {
+                let entry = self.zarrman.get_entry_by_checksum(digest).await?;
+                Ok(DavResource::Item(entry.into()))
+            }
             DavPath::ZarrPath { path } => {
-                let res = self.zarrman.get_resource(path).await?;
-                Ok(DavResource::from(res))
+                lookup_escaped(path, |p| async move {
+                    let res = self.zarrman.get_resource(&p).await?;
+                    Ok(DavResource::from(res))
+                })
+                .await
             }
         }
     }
@@ -247,14 +1326,15 @@ impl DandiDav {
     /// include `dandiset.yaml` as a virtual asset.
     async fn get_resource_with_children(
         &self,
+        dandi: &DandiClient,
         path: &DavPath,
+        zarr_mode: ZarrMode,
     ) -> Result<DavResourceWithChildren, DavError> {
         match path {
-            DavPath::Root => Ok(DavResourceWithChildren::root()),
+            DavPath::Root => Ok(DavResourceWithChildren::root(&self.root_mounts)),
             DavPath::DandisetIndex => {
                 let col = DavCollection::dandiset_index();
-                let children = self
-                    .dandi
+                let children = dandi
                     .get_all_dandisets()
                     .map_ok(|ds| DavResource::Collection(ds.into()))
                     .try_collect::<Vec<_>>()
@@ -262,14 +1342,16 @@ impl DandiDav {
                 Ok(DavResourceWithChildren::Collection { col, children })
             }
             DavPath::Dandiset { dandiset_id } => {
-                let mut ds = self.dandi.dandiset(dandiset_id.clone()).get().await?;
+                let mut ds = dandi.dandiset(dandiset_id.clone()).get().await?;
                 let draft = DavResource::Collection(DavCollection::dandiset_version(
+                    dandiset_id,
                     ds.draft_version.clone(),
                     version_path(dandiset_id, &VersionSpec::Draft),
                 ));
                 let children = match ds.most_recent_published_version.take() {
                     Some(v) => {
                         let latest = DavCollection::dandiset_version(
+                            dandiset_id,
                             v,
                             version_path(dandiset_id, &VersionSpec::Latest),
                         );
@@ -288,13 +1370,15 @@ impl DandiDav {
                 // have any published releases?
                 let col = DavCollection::dandiset_releases(dandiset_id);
                 let mut children = Vec::new();
-                let endpoint = self.dandi.dandiset(dandiset_id.clone());
+                let endpoint = dandi.dandiset(dandiset_id.clone());
                 let mut stream = endpoint.get_all_versions();
                 while let Some(v) = stream.try_next().await? {
                     if let VersionId::Published(ref pvid) = v.version {
                         let path = version_path(dandiset_id, &VersionSpec::Published(pvid.clone()));
                         children.push(DavResource::Collection(DavCollection::dandiset_version(
-                            v, path,
+                            dandiset_id,
+                            v,
+                            path,
                         )));
                     }
                 }
@@ -304,7 +1388,9 @@ impl DandiDav {
                 dandiset_id,
                 version,
             } => {
-                let handler = self.get_version_handler(dandiset_id, version).await?;
+                let handler = self
+                    .get_version_handler(dandi, dandiset_id, version, zarr_mode)
+                    .await?;
                 let (col, dsyaml) = handler.get().await?;
                 let mut children = handler.get_root_children().await?;
                 children.push(DavResource::Item(dsyaml));
@@ -314,21 +1400,34 @@ impl DandiDav {
                 dandiset_id,
                 version,
             } => self
-                .get_version_handler(dandiset_id, version)
+                .get_version_handler(dandi, dandiset_id, version, zarr_mode)
                 .await?
                 .get_dandiset_yaml()
                 .await
                 .map(DavResourceWithChildren::Item),
-            DavPath::DandiResource {
+            DavPath::AssetById {
                 dandiset_id,
                 version,
-                path,
+                asset_id,
             } => {
-                self.get_version_handler(dandiset_id, version)
+                self.get_version_handler(dandi, dandiset_id, version, zarr_mode)
                     .await?
-                    .get_resource_with_children(path)
+                    .get_asset_by_id_with_children(asset_id)
                     .await
             }
+            DavPath::DandiResource {
+                dandiset_id,
+                version,
+                path,
+            } => {
+                let handler = &self
+                    .get_version_handler(dandi, dandiset_id, version, zarr_mode)
+                    .await?;
+                lookup_escaped(path, |p| async move {
+                    handler.get_resource_with_children(&p).await
+                })
+                .await
+            }
             DavPath::ZarrIndex => {
                 let col = DavCollection::zarr_index();
                 let children = self
@@ -340,14 +1439,38 @@ impl DandiDav {
                     .collect();
                 Ok(DavResourceWithChildren::Collection { col, children })
             }
+            DavPath::ZarrEntryByChecksum { digest } => {
+                let entry = self.zarrman.get_entry_by_checksum(digest).await?;
+                Ok(DavResourceWithChildren::Item(entry.into()))
+            }
             DavPath::ZarrPath { path } => {
-                let res = self.zarrman.get_resource_with_children(path).await?;
-                Ok(DavResourceWithChildren::from(res))
+                lookup_escaped(path, |p| async move {
+                    let res = self.zarrman.get_resource_with_children(&p).await?;
+                    Ok(DavResourceWithChildren::from(res))
+                })
+                .await
             }
         }
     }
 }
 
+/// Look up a resource by calling `lookup` on `path`.  If nothing is found and
+/// `path` contains escaped forms of names that need escaping (see
+/// [`crate::paths::escape_name()`]), look up the unescaped path instead.
+async fn lookup_escaped<T, F, Fut>(path: &PurePath, lookup: F) -> Result<T, DavError>
+where
+    F: Fn(PurePath) -> Fut,
+    Fut: std::future::Future<Output = Result<T, DavError>>,
+{
+    match lookup(path.clone()).await {
+        Err(e) if e.class() == ErrorClass::NotFound => match path.unescaped() {
+            Some(p) => lookup(p).await,
+            None => Err(e),
+        },
+        r => r,
+    }
+}
+
 /// A handler for fetching resources belonging to a certain Dandiset & version.
 ///
 /// Resources returned by this type's methods all have their paths prefixed
@@ -357,6 +1480,8 @@ struct VersionHandler<'a> {
     dandiset_id: &'a DandisetId,
     version_spec: &'a VersionSpec,
     endpoint: VersionEndpoint<'a>,
+    zarrman: &'a ZarrManClient,
+    zarr_mode: ZarrMode,
 }
 
 impl VersionHandler<'_> {
@@ -366,12 +1491,17 @@ impl VersionHandler<'_> {
     async fn get(&self) -> Result<(DavCollection, DavItem), DavError> {
         let VersionInfo {
             properties,
+            citation,
             metadata,
         } = self.endpoint.get().await?;
         let path = version_path(self.dandiset_id, self.version_spec);
-        let col = DavCollection::dandiset_version(properties, path);
-        let dandiset_yaml =
-            DavItem::from(metadata).under_version_path(self.dandiset_id, self.version_spec);
+        let col = DavCollection::dandiset_version(self.dandiset_id, properties, path)
+            .with_citation(citation);
+        let dandiset_yaml = DavItem::from(metadata).under_version_path(
+            self.dandiset_id,
+            self.version_spec,
+            self.endpoint.version_id(),
+        );
         Ok((col, dandiset_yaml))
     }
 
@@ -380,9 +1510,7 @@ impl VersionHandler<'_> {
     async fn get_root_children(&self) -> Result<Vec<DavResource>, DandiError> {
         self.endpoint
             .get_root_children()
-            .map_ok(|res| {
-                DavResource::from(res).under_version_path(self.dandiset_id, self.version_spec)
-            })
+            .map_ok(|res| self.convert(res))
             .try_collect::<Vec<_>>()
             .await
     }
@@ -390,13 +1518,20 @@ impl VersionHandler<'_> {
     /// Get the version's `dandiset.yaml` file
     async fn get_dandiset_yaml(&self) -> Result<DavItem, DavError> {
         let md = self.endpoint.get_metadata().await?;
-        Ok(DavItem::from(md).under_version_path(self.dandiset_id, self.version_spec))
+        Ok(DavItem::from(md).under_version_path(
+            self.dandiset_id,
+            self.version_spec,
+            self.endpoint.version_id(),
+        ))
     }
 
     /// Get details on the resource at the given `path`
     async fn get_resource(&self, path: &PurePath) -> Result<DavResource, DavError> {
-        let res = self.endpoint.get_resource(path).await?;
-        Ok(DavResource::from(res).under_version_path(self.dandiset_id, self.version_spec))
+        let res = match self.zarr_mode {
+            ZarrMode::Expanded => self.endpoint.get_resource(path).await?,
+            ZarrMode::Collapsed => self.endpoint.get_opaque_resource(path).await?,
+        };
+        Ok(self.convert(res))
     }
 
     /// Get details on the resource at the given `path` along with its
@@ -405,9 +1540,113 @@ impl VersionHandler<'_> {
         &self,
         path: &PurePath,
     ) -> Result<DavResourceWithChildren, DavError> {
-        let res = self.endpoint.get_resource_with_children(path).await?;
-        Ok(DavResourceWithChildren::from(res)
-            .under_version_path(self.dandiset_id, self.version_spec))
+        let res = match self.zarr_mode {
+            ZarrMode::Expanded => self.endpoint.get_resource_with_children(path).await?,
+            ZarrMode::Collapsed => {
+                match self
+                    .endpoint
+                    .get_opaque_resource_with_children(path)
+                    .await?
+                {
+                    DandiResourceWithChildren::Folder { folder, children } => {
+                        let col = DavCollection::from(folder).under_version_path(
+                            self.dandiset_id,
+                            self.version_spec,
+                            self.endpoint.version_id(),
+                        );
+                        let children = children.into_iter().map(|r| self.convert(r)).collect();
+                        return Ok(DavResourceWithChildren::Collection { col, children });
+                    }
+                    DandiResourceWithChildren::Zarr { zarr, .. } => {
+                        let item = self.collapse_zarr(zarr).under_version_path(
+                            self.dandiset_id,
+                            self.version_spec,
+                            self.endpoint.version_id(),
+                        );
+                        return Ok(DavResourceWithChildren::Item(item));
+                    }
+                    res => res,
+                }
+            }
+        };
+        Ok(DavResourceWithChildren::from(res).under_version_path(
+            self.dandiset_id,
+            self.version_spec,
+            self.endpoint.version_id(),
+        ))
+    }
+
+    /// Get details on the asset with the given asset ID.  The resource is
+    /// returned with its asset ID-based path.
+    async fn get_asset_by_id(&self, asset_id: &str) -> Result<DavResource, DavError> {
+        let asset = self.endpoint.get_asset_by_id(asset_id).await?;
+        Ok(self.convert(DandiResource::Asset(asset)).at_permalink())
+    }
+
+    /// Get details on the asset with the given asset ID along with its
+    /// immediate child resources (if any).  The asset is returned with its
+    /// asset ID-based path.
+    ///
+    /// As nothing is served beneath an asset ID-based path, the entries of an
+    /// expanded Zarr are not listed here; they can be found at the Zarr's
+    /// path-based path instead.
+    async fn get_asset_by_id_with_children(
+        &self,
+        asset_id: &str,
+    ) -> Result<DavResourceWithChildren, DavError> {
+        let res = match self.endpoint.get_asset_by_id(asset_id).await? {
+            Asset::Zarr(zarr) if self.zarr_mode == ZarrMode::Expanded => {
+                DavResourceWithChildren::Collection {
+                    col: DavCollection::from(zarr).under_version_path(
+                        self.dandiset_id,
+                        self.version_spec,
+                        self.endpoint.version_id(),
+                    ),
+                    children: Vec::new(),
+                }
+            }
+            Asset::Zarr(zarr) => {
+                DavResourceWithChildren::Item(self.collapse_zarr(zarr).under_version_path(
+                    self.dandiset_id,
+                    self.version_spec,
+                    self.endpoint.version_id(),
+                ))
+            }
+            Asset::Blob(blob) => {
+                DavResourceWithChildren::Item(DavItem::from(blob).under_version_path(
+                    self.dandiset_id,
+                    self.version_spec,
+                    self.endpoint.version_id(),
+                ))
+            }
+        };
+        Ok(res.at_permalink())
+    }
+
+    /// Convert a resource returned by the version endpoint into a
+    /// `DavResource` under the version's path, applying the handler's Zarr
+    /// mode
+    fn convert(&self, res: DandiResource) -> DavResource {
+        let res = match res {
+            DandiResource::Asset(Asset::Zarr(zarr)) if self.zarr_mode == ZarrMode::Collapsed => {
+                DavResource::Item(self.collapse_zarr(zarr))
+            }
+            res => DavResource::from(res),
+        };
+        res.under_version_path(
+            self.dandiset_id,
+            self.version_spec,
+            self.endpoint.version_id(),
+        )
+    }
+
+    /// Convert a Zarr asset into an opaque `DavItem` that redirects to the
+    /// Zarr's manifest
+    fn collapse_zarr(&self, zarr: ZarrAsset) -> DavItem {
+        let manifest_url = zarr
+            .checksum()
+            .and_then(|checksum| self.zarrman.manifest_url(&zarr.zarr_id, checksum));
+        DavItem::collapsed_zarr(zarr, manifest_url)
     }
 }
 
@@ -423,8 +1662,12 @@ pub(crate) enum DavError {
     NoLatestVersion { dandiset_id: DandisetId },
     #[error(transparent)]
     Template(#[from] TemplateError),
-    #[error(transparent)]
-    Xml(#[from] ToXmlError),
+    #[error("failed to fetch range of Zarr entry")]
+    Proxy(#[from] HttpError),
+    #[error("no Zarr snapshot is available for {path:?}")]
+    NoZarrSnapshot { path: String },
+    #[error("failed to convert metadata document to requested format")]
+    Convert(#[from] ConvertError),
 }
 
 impl DavError {
@@ -433,8 +1676,11 @@ impl DavError {
         match self {
             DavError::Dandi(e) => e.class(),
             DavError::ZarrMan(e) => e.class(),
-            DavError::NoLatestVersion { .. } => ErrorClass::NotFound,
-            DavError::Template(_) | DavError::Xml(_) => ErrorClass::Internal,
+            DavError::NoLatestVersion { .. } | DavError::NoZarrSnapshot { .. } => {
+                ErrorClass::NotFound
+            }
+            DavError::Proxy(e) => e.class(),
+            DavError::Template(_) | DavError::Convert(_) => ErrorClass::Internal,
         }
     }
 }
@@ -456,16 +1702,21 @@ pub(crate) enum ErrorClass {
 
     /// The error was ultimately caused by something going wrong in `dandidav`
     Internal,
+
+    /// The error was caused by the incoming request needing more upstream
+    /// requests than it is permitted to make
+    ServiceUnavailable,
 }
 
 impl ErrorClass {
     /// Return the HTTP status code matching this error class
-    fn to_status(self) -> StatusCode {
+    pub(crate) fn to_status(self) -> StatusCode {
         match self {
             ErrorClass::NotFound => StatusCode::NOT_FOUND,
             ErrorClass::BadGateway => StatusCode::BAD_GATEWAY,
             ErrorClass::GatewayTimeout => StatusCode::GATEWAY_TIMEOUT,
             ErrorClass::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorClass::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
         }
     }
 }
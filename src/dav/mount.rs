@@ -0,0 +1,116 @@
+//! The top-level collections listed at the root of the hierarchy
+use super::types::DavCollection;
+use crate::mountlimit::Mount;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The top-level collections listed at the root when no layout is
+/// configured, in the order in which they are listed
+pub(crate) const DEFAULT_ROOT_MOUNTS: [Mount; 2] = [Mount::Dandisets, Mount::Zarrs];
+
+/// Return the `DavCollection` for the top-level collection `mount`
+pub(super) fn mount_collection(mount: Mount) -> DavCollection {
+    match mount {
+        Mount::Dandisets => DavCollection::dandiset_index(),
+        Mount::Zarrs => DavCollection::zarr_index(),
+    }
+}
+
+/// Remove repeated mounts from `mounts`, keeping the first occurrence of each
+pub(super) fn dedup_mounts(mounts: Vec<Mount>) -> Vec<Mount> {
+    let mut deduped = Vec::with_capacity(mounts.len());
+    for m in mounts {
+        if !deduped.contains(&m) {
+            deduped.push(m);
+        }
+    }
+    deduped
+}
+
+/// A description of a top-level collection to show in HTML views, as given on
+/// the command line in the form `{mount}={text}`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct MountDescription {
+    pub(super) mount: Mount,
+    pub(super) text: String,
+}
+
+impl FromStr for MountDescription {
+    type Err = ParseMountDescriptionError;
+
+    fn from_str(s: &str) -> Result<MountDescription, ParseMountDescriptionError> {
+        let (mount, text) = s
+            .split_once('=')
+            .ok_or(ParseMountDescriptionError::NoEquals)?;
+        let mount = match mount.trim() {
+            "dandisets" => Mount::Dandisets,
+            "zarrs" => Mount::Zarrs,
+            other => return Err(ParseMountDescriptionError::UnknownMount(other.to_owned())),
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(ParseMountDescriptionError::Empty);
+        }
+        Ok(MountDescription {
+            mount,
+            text: text.to_owned(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub(crate) enum ParseMountDescriptionError {
+    #[error("expected a value of the form MOUNT=TEXT")]
+    NoEquals,
+    #[error("unknown mount {0:?}; expected \"dandisets\" or \"zarrs\"")]
+    UnknownMount(String),
+    #[error("description is empty")]
+    Empty,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(vec![], vec![])]
+    #[case(vec![Mount::Zarrs, Mount::Dandisets], vec![Mount::Zarrs, Mount::Dandisets])]
+    #[case(
+        vec![Mount::Zarrs, Mount::Dandisets, Mount::Zarrs],
+        vec![Mount::Zarrs, Mount::Dandisets]
+    )]
+    #[case(vec![Mount::Dandisets, Mount::Dandisets], vec![Mount::Dandisets])]
+    fn test_dedup_mounts(#[case] mounts: Vec<Mount>, #[case] expected: Vec<Mount>) {
+        assert_eq!(dedup_mounts(mounts), expected);
+    }
+
+    #[rstest]
+    #[case("zarrs=Zarr manifests mirror", Mount::Zarrs, "Zarr manifests mirror")]
+    #[case(
+        " dandisets = DANDI production archive ",
+        Mount::Dandisets,
+        "DANDI production archive"
+    )]
+    #[case("zarrs=a=b", Mount::Zarrs, "a=b")]
+    fn parse_mount_description(#[case] s: &str, #[case] mount: Mount, #[case] text: &str) {
+        assert_eq!(
+            s.parse::<MountDescription>(),
+            Ok(MountDescription {
+                mount,
+                text: text.to_owned()
+            })
+        );
+    }
+
+    #[rstest]
+    #[case("zarrs", ParseMountDescriptionError::NoEquals)]
+    #[case(
+        "blobs=Blobs",
+        ParseMountDescriptionError::UnknownMount("blobs".into())
+    )]
+    #[case("zarrs= ", ParseMountDescriptionError::Empty)]
+    fn parse_bad_mount_description(#[case] s: &str, #[case] err: ParseMountDescriptionError) {
+        assert_eq!(s.parse::<MountDescription>(), Err(err));
+    }
+}
@@ -0,0 +1,225 @@
+//! Diagnosis of "404 Not Found" errors
+//!
+//! A request can fail to find anything at several different levels of path
+//! resolution.  So that users get an actionable message and operators can see
+//! which kinds of 404s are most common, `dandidav` determines which level
+//! failed, reports it in the body of the 404 response, and logs it in the
+//! `not_found` field of the error message.
+use super::DavError;
+use crate::dandi::{DandiError, DandisetId, VersionId};
+use crate::httputil::HttpError;
+use crate::paths::PurePath;
+use crate::zarrman::ZarrManError;
+use axum::{
+    body::Body,
+    http::{response::Response, StatusCode},
+    response::IntoResponse,
+};
+use std::fmt;
+
+/// The level of path resolution at which a request failed to find anything
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) enum NotFound {
+    /// The request path does not correspond to any resource, for reasons not
+    /// covered by the other variants
+    Path { path: String },
+
+    /// The requested Dandiset does not exist
+    Dandiset { dandiset_id: DandisetId },
+
+    /// The requested Dandiset exists, but the requested version does not
+    Version {
+        dandiset_id: DandisetId,
+        version: VersionId,
+    },
+
+    /// The latest version of a Dandiset was requested, but the Dandiset has
+    /// not been published
+    NoPublishedVersion { dandiset_id: DandisetId },
+
+    /// No asset or folder exists at the requested path within a Dandiset
+    /// version
+    AssetPath { path: PurePath },
+
+    /// The Dandiset version does not contain an asset with the requested ID
+    AssetId { asset_id: String },
+
+    /// The requested entry does not exist within a Zarr
+    ZarrEntry {
+        zarr_path: String,
+        entry_path: PurePath,
+    },
+
+    /// No cached Zarr manifest contains an entry with the requested digest
+    ZarrChecksum { digest: String },
+
+    /// A Zarr snapshot was requested for a resource that has none
+    ZarrSnapshot { path: String },
+}
+
+impl NotFound {
+    /// If `e` by itself identifies the level of path resolution that failed,
+    /// return the corresponding `NotFound`
+    pub(super) fn from_error(e: &DavError) -> Option<NotFound> {
+        match e {
+            DavError::Dandi(e) => NotFound::from_dandi_error(e),
+            DavError::ZarrMan(ZarrManError::ManifestPathNotFound {
+                manifest_path,
+                entry_path,
+            }) => Some(NotFound::ZarrEntry {
+                zarr_path: manifest_path.to_web_path().to_string(),
+                entry_path: entry_path.clone(),
+            }),
+            DavError::ZarrMan(ZarrManError::ChecksumNotFound { digest }) => {
+                Some(NotFound::ZarrChecksum {
+                    digest: digest.clone(),
+                })
+            }
+            DavError::NoLatestVersion { dandiset_id } => Some(NotFound::NoPublishedVersion {
+                dandiset_id: dandiset_id.clone(),
+            }),
+            DavError::NoZarrSnapshot { path } => {
+                Some(NotFound::ZarrSnapshot { path: path.clone() })
+            }
+            _ => None,
+        }
+    }
+
+    fn from_dandi_error(e: &DandiError) -> Option<NotFound> {
+        match e {
+            DandiError::PathNotFound { path } | DandiError::PathUnderBlob { path, .. } => {
+                Some(NotFound::AssetPath { path: path.clone() })
+            }
+            DandiError::ZarrEntryNotFound {
+                zarr_path,
+                entry_path,
+            } => Some(NotFound::ZarrEntry {
+                zarr_path: zarr_path.to_string(),
+                entry_path: entry_path.clone(),
+            }),
+            DandiError::Shared(e) => NotFound::from_dandi_error(e),
+            _ => None,
+        }
+    }
+
+    /// Return a short name for the level of path resolution that failed, for
+    /// use in logs
+    pub(super) fn kind(&self) -> &'static str {
+        match self {
+            NotFound::Path { .. } => "path",
+            NotFound::Dandiset { .. } => "dandiset",
+            NotFound::Version { .. } => "version",
+            NotFound::NoPublishedVersion { .. } => "no-published-version",
+            NotFound::AssetPath { .. } => "asset-path",
+            NotFound::AssetId { .. } => "asset-id",
+            NotFound::ZarrEntry { .. } => "zarr-entry",
+            NotFound::ZarrChecksum { .. } => "zarr-checksum",
+            NotFound::ZarrSnapshot { .. } => "zarr-snapshot",
+        }
+    }
+}
+
+impl fmt::Display for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotFound::Path { path } => write!(f, "no such path: {path}"),
+            NotFound::Dandiset { dandiset_id } => write!(f, "no such Dandiset: {dandiset_id}"),
+            NotFound::Version {
+                dandiset_id,
+                version,
+            } => write!(f, "Dandiset {dandiset_id} has no version {version}"),
+            NotFound::NoPublishedVersion { dandiset_id } => {
+                write!(f, "Dandiset {dandiset_id} has no published versions")
+            }
+            NotFound::AssetPath { path } => write!(f, "no asset or folder at path: {path}"),
+            NotFound::AssetId { asset_id } => write!(f, "no asset with ID: {asset_id}"),
+            NotFound::ZarrEntry {
+                zarr_path,
+                entry_path,
+            } => write!(f, "no entry {entry_path} in Zarr {zarr_path}"),
+            NotFound::ZarrChecksum { digest } => {
+                write!(f, "no known Zarr entry has checksum: {digest}")
+            }
+            NotFound::ZarrSnapshot { path } => {
+                write!(f, "no Zarr snapshot is available for {path}")
+            }
+        }
+    }
+}
+
+impl IntoResponse for NotFound {
+    fn into_response(self) -> Response<Body> {
+        (StatusCode::NOT_FOUND, format!("404 Not Found: {self}\n")).into_response()
+    }
+}
+
+/// Returns true if `r` is an error caused by a 404 response from the Archive
+pub(super) fn is_archive_404<T>(r: &Result<T, DandiError>) -> bool {
+    fn check(e: &DandiError) -> bool {
+        match e {
+            DandiError::Http(HttpError::NotFound { .. }) => true,
+            DandiError::Shared(e) => check(e),
+            _ => false,
+        }
+    }
+    r.as_ref().is_err_and(check)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(
+        NotFound::Dandiset {dandiset_id: "000123".parse().unwrap()},
+        "dandiset",
+        "404 Not Found: no such Dandiset: 000123\n"
+    )]
+    #[case(
+        NotFound::Version {
+            dandiset_id: "000123".parse().unwrap(),
+            version: "0.240101.1234".parse().unwrap(),
+        },
+        "version",
+        "404 Not Found: Dandiset 000123 has no version 0.240101.1234\n"
+    )]
+    #[case(
+        NotFound::AssetPath {path: "sub-01/foo.nwb".parse().unwrap()},
+        "asset-path",
+        "404 Not Found: no asset or folder at path: sub-01/foo.nwb\n"
+    )]
+    #[case(
+        NotFound::ZarrEntry {
+            zarr_path: "foo.zarr".into(),
+            entry_path: "0/1".parse().unwrap(),
+        },
+        "zarr-entry",
+        "404 Not Found: no entry 0/1 in Zarr foo.zarr\n"
+    )]
+    #[tokio::test]
+    async fn test_response(#[case] nf: NotFound, #[case] kind: &str, #[case] body: &str) {
+        assert_eq!(nf.kind(), kind);
+        let resp = nf.into_response();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(std::str::from_utf8(&bytes).unwrap(), body);
+    }
+
+    #[test]
+    fn from_shared_error() {
+        let e = DavError::Dandi(DandiError::Shared(std::sync::Arc::new(
+            DandiError::PathNotFound {
+                path: "foo/bar".parse().unwrap(),
+            },
+        )));
+        assert_eq!(
+            NotFound::from_error(&e),
+            Some(NotFound::AssetPath {
+                path: "foo/bar".parse().unwrap()
+            })
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Local disk cache of small objects fetched from S3 when proxying
+//!
+//! When many clients read the same small Zarr entries (e.g., `.zarray` and
+//! `.zattrs` metadata files), fetching each one from S3 for every request is
+//! wasteful.  The cache stores the complete contents of such objects in files
+//! in a local directory, keyed by digest, and evicts the least recently used
+//! objects once the total size of the cached objects exceeds a budget.
+use super::util::blob_response;
+use crate::blocking::run_blocking;
+use crate::consts::{
+    DEFAULT_CONTENT_TYPE, PROXY_CACHE_MAX_HOT_OBJECT_SIZE, PROXY_CACHE_MAX_OBJECT_SIZE,
+};
+use crate::httputil::{Client, HttpError, HttpUrl};
+use axum::{
+    body::{Body, Bytes},
+    http::{
+        header::{CONTENT_TYPE, ETAG},
+        HeaderValue, Response,
+    },
+};
+use moka::{
+    future::{Cache, CacheBuilder},
+    notification::RemovalCause,
+    policy::EvictionPolicy,
+};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// A size-bounded LRU cache of object contents stored as files in a local
+/// directory
+#[derive(Clone, Debug)]
+pub(crate) struct ObjectCache {
+    /// The directory in which cached objects are stored
+    dir: PathBuf,
+
+    /// Index of the objects currently stored in `dir`, mapping file names to
+    /// object sizes.  Evicting an entry from the index deletes its file.
+    index: Cache<String, u64>,
+}
+
+impl ObjectCache {
+    /// Construct a new cache that stores objects in `dir` (creating it if it
+    /// does not exist) and holds up to `capacity` bytes of objects.  Any
+    /// cache files left in `dir` by a previous run are removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if creating or clearing out `dir` fails
+    pub(crate) fn new(dir: PathBuf, capacity: u64) -> std::io::Result<ObjectCache> {
+        std::fs::create_dir_all(&dir)?;
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| is_cache_file_name(name.trim_end_matches(".tmp")))
+            {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        let evict_dir = dir.clone();
+        let index = CacheBuilder::new(capacity)
+            .name("proxy-object-cache")
+            .eviction_policy(EvictionPolicy::lru())
+            .weigher(|_, &size: &u64| u32::try_from(size).unwrap_or(u32::MAX))
+            .eviction_listener(move |name: std::sync::Arc<String>, _, cause| {
+                // A replaced entry refers to the same file as its replacement,
+                // so the file must be left in place.
+                if cause != RemovalCause::Replaced {
+                    remove_cache_file(&evict_dir.join(&*name));
+                }
+            })
+            .build();
+        Ok(ObjectCache { dir, index })
+    }
+
+    /// Return whether an object of the given size is small enough to be
+    /// cached.  Objects at hot paths are subject to a larger size limit.
+    pub(super) fn accepts(size: i64, hot: bool) -> bool {
+        let limit = if hot {
+            PROXY_CACHE_MAX_HOT_OBJECT_SIZE
+        } else {
+            PROXY_CACHE_MAX_OBJECT_SIZE
+        };
+        u64::try_from(size).is_ok_and(|sz| sz <= limit)
+    }
+
+    /// Return the contents of the object with digest `digest`, reading them
+    /// from the cache if present or else fetching them from `url` and storing
+    /// them in the cache.
+    ///
+    /// Failures to read or write cache files are logged, and the object is
+    /// then served from (or after fetching from) `url` as though it had not
+    /// been cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if fetching the object from `url` fails
+    pub(super) async fn get(
+        &self,
+        client: &Client,
+        url: HttpUrl,
+        digest: &str,
+    ) -> Result<Bytes, HttpError> {
+        let name = cache_file_name(digest);
+        let path = self.dir.join(&name);
+        if self.index.get(&name).await.is_some() {
+            let p = path.clone();
+            match run_blocking(move || std::fs::read(p)).await {
+                Ok(data) => return Ok(Bytes::from(data)),
+                Err(e) => {
+                    tracing::warn!(error = ?e, digest, "Failed to read cached object; refetching");
+                    self.index.invalidate(&name).await;
+                }
+            }
+        }
+        let data = client.get_bytes(url).await?;
+        let size = data.len() as u64;
+        let contents = data.clone();
+        match run_blocking(move || write_cache_file(&path, &contents)).await {
+            Ok(()) => self.index.insert(name, size).await,
+            Err(e) => tracing::warn!(error = ?e, digest, "Failed to store object in cache"),
+        }
+        Ok(data)
+    }
+}
+
+/// Return a response for a `GET` request for a cached object with contents
+/// `data` and ETag `etag`, honoring `range` (the request's `Range` header, if
+/// any) if it specifies a single byte range.  Other `Range` values are
+/// ignored, and the complete object is returned.
+pub(super) fn serve_cached(
+    data: Bytes,
+    range: Option<&HeaderValue>,
+    etag: Option<HeaderValue>,
+) -> Response<Body> {
+    let mut resp = blob_response(data, range);
+    let headers = resp.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(DEFAULT_CONTENT_TYPE));
+    if let Some(tag) = etag {
+        headers.insert(ETAG, tag);
+    }
+    resp
+}
+
+/// Return the name of the file in which the object with digest `digest` is
+/// stored.  Digests are hashed so that the result is always a valid file name
+/// regardless of what characters the digest contains.
+fn cache_file_name(digest: &str) -> String {
+    hex::encode(Sha256::digest(digest.as_bytes()))
+}
+
+/// Test whether `name` is of the form returned by [`cache_file_name()`]
+fn is_cache_file_name(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Write `data` to `path` by first writing to a temporary file and then
+/// renaming it into place, so that a partially-written file is never read
+fn write_cache_file(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Delete the cache file at `path`, logging any failure other than the file
+/// not existing
+fn remove_cache_file(path: &Path) {
+    match std::fs::remove_file(path) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => {
+            tracing::warn!(error = ?e, path = %path.display(), "Failed to delete evicted cache file");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{
+        header::{ACCEPT_RANGES, CONTENT_RANGE},
+        StatusCode,
+    };
+    use http_body_util::BodyExt;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[tokio::test]
+    async fn fetch_once() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/zarr/.zarray"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"0123456789".as_slice()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let dir = std::env::temp_dir().join(format!("dandidav-objcache-{}", uuid::Uuid::new_v4()));
+        let leftover = dir.join(cache_file_name("stale"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&leftover, b"stale").unwrap();
+        std::fs::write(dir.join("README"), b"not ours").unwrap();
+        let cache = ObjectCache::new(dir.clone(), 1 << 20).unwrap();
+        assert!(!leftover.exists());
+        assert!(dir.join("README").exists());
+        let client = Client::new(None).unwrap();
+        let url = format!("{}/zarr/.zarray", server.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        for _ in 0..3 {
+            let data = cache.get(&client, url.clone(), "abcdef").await.unwrap();
+            assert_eq!(data, b"0123456789".as_slice());
+        }
+        assert_eq!(
+            std::fs::read(dir.join(cache_file_name("abcdef"))).unwrap(),
+            b"0123456789"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn serve_range() {
+        let resp = serve_cached(
+            Bytes::from_static(b"0123456789"),
+            Some(&HeaderValue::from_static("bytes=2-5")),
+            Some(HeaderValue::from_static("\"abcdef\"")),
+        );
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.headers().get(CONTENT_RANGE).unwrap(), "bytes 2-5/10");
+        assert_eq!(resp.headers().get(ETAG).unwrap(), "\"abcdef\"");
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, b"2345".as_slice());
+    }
+
+    #[tokio::test]
+    async fn serve_unsatisfiable() {
+        let resp = serve_cached(
+            Bytes::from_static(b"0123456789"),
+            Some(&HeaderValue::from_static("bytes=20-")),
+            None,
+        );
+        assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(resp.headers().get(CONTENT_RANGE).unwrap(), "bytes */10");
+    }
+
+    #[tokio::test]
+    async fn serve_whole() {
+        let resp = serve_cached(Bytes::from_static(b"0123456789"), None, None);
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(ACCEPT_RANGES).unwrap(), "bytes");
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, b"0123456789".as_slice());
+    }
+}
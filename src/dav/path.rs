@@ -1,7 +1,7 @@
-//! Parsing request paths
-use crate::consts::FAST_NOT_EXIST;
+//! Parsing & generating request paths
+use crate::consts::{BY_ASSET_ID_DIR, BY_CHECKSUM_DIR, FAST_NOT_EXIST};
 use crate::dandi::{DandisetId, PublishedVersionId};
-use crate::paths::{Component, ParseComponentError, PurePath};
+use crate::paths::{Component, ParseComponentError, PureDirPath, PurePath};
 
 /// A parsed request path
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -44,6 +44,21 @@ pub(super) enum DavPath {
         version: VersionSpec,
     },
 
+    /// An asset identified by its asset ID, served at
+    /// `.by-asset-id/{asset_id}` immediately beneath each version path.  This
+    /// takes precedence over any real asset paths beginning with
+    /// `.by-asset-id/`, which are thus unreachable.
+    AssetById {
+        /// The Dandiset ID
+        dandiset_id: DandisetId,
+
+        /// The version specifier
+        version: VersionSpec,
+
+        /// The asset ID
+        asset_id: Component,
+    },
+
     /// Any other path beneath a Dandiset version path
     DandiResource {
         /// The Dandiset ID
@@ -59,6 +74,10 @@ pub(super) enum DavPath {
     /// The top of the Zarr manifest tree at `/zarrs/`
     ZarrIndex,
 
+    /// A Zarr entry identified by the digest of its S3 object, served at
+    /// `/zarrs/.by-checksum/{digest}`
+    ZarrEntryByChecksum { digest: Component },
+
     /// A path beneath `/zarrs/`
     ZarrPath { path: PurePath },
 }
@@ -68,61 +87,384 @@ impl DavPath {
     ///
     /// Returns `None` if the request path is invalid/does not exist.
     pub(super) fn from_components(parts: Vec<Component>) -> Option<DavPath> {
-        let mut iter = parts.into_iter();
-        let Some(p1) = iter.next() else {
-            return Some(DavPath::Root);
-        };
-        if p1.eq_ignore_ascii_case("dandisets") {
-            let Some(did) = iter.next() else {
-                return Some(DavPath::DandisetIndex);
-            };
-            let Ok(dandiset_id) = did.parse::<DandisetId>() else {
-                return None;
-            };
-            let Some(p3) = iter.next() else {
-                return Some(DavPath::Dandiset { dandiset_id });
-            };
-            let version = if p3.eq_ignore_ascii_case("releases") {
-                let Some(v) = iter.next() else {
-                    return Some(DavPath::DandisetReleases { dandiset_id });
-                };
-                let Ok(pv) = v.parse::<PublishedVersionId>() else {
-                    return None;
-                };
-                VersionSpec::Published(pv)
-            } else if p3.eq_ignore_ascii_case("latest") {
-                VersionSpec::Latest
-            } else if p3.eq_ignore_ascii_case("draft") {
-                VersionSpec::Draft
-            } else {
-                return None;
-            };
-            match PurePath::from_components(iter) {
-                None => Some(DavPath::Version {
-                    dandiset_id,
-                    version,
-                }),
-                Some(p) if p == "dandiset.yaml" => Some(DavPath::DandisetYaml {
-                    dandiset_id,
-                    version,
-                }),
-                Some(path) => Some(DavPath::DandiResource {
-                    dandiset_id,
-                    version,
-                    path,
-                }),
+        ROUTES
+            .iter()
+            .find_map(|route| route.capture(&parts))
+            .and_then(|(route, captures)| (route.build)(captures))
+    }
+
+    /// Return the route in [`ROUTES`] that produces this path
+    fn route(&self) -> &'static Route {
+        match self {
+            DavPath::Root => &ROOT,
+            DavPath::DandisetIndex => &DANDISET_INDEX,
+            DavPath::Dandiset { .. } => &DANDISET,
+            DavPath::DandisetReleases { .. } => &DANDISET_RELEASES,
+            DavPath::Version { .. } => &VERSION,
+            DavPath::DandisetYaml { .. } => &DANDISET_YAML,
+            DavPath::AssetById { .. } => &ASSET_BY_ID,
+            DavPath::DandiResource { .. } => &DANDI_RESOURCE,
+            DavPath::ZarrIndex => &ZARR_INDEX,
+            DavPath::ZarrEntryByChecksum { .. } => &ZARR_BY_CHECKSUM,
+            DavPath::ZarrPath { .. } => &ZARR_PATH,
+        }
+    }
+
+    /// Parse the path of a collection served by `dandidav` (as found in a
+    /// listing of its parent) into a `DavPath`.  Returns `None` if the path
+    /// does not route to anything.
+    pub(super) fn from_dir_path(path: &PureDirPath) -> Option<DavPath> {
+        let parts = path
+            .component_strs()
+            .map(|s| s.parse::<Component>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        DavPath::from_components(parts)
+    }
+
+    /// Returns true if `PROPFIND` requests with infinite depth may be
+    /// answered for this path, i.e., if the path is inside a Dandiset version
+    /// or beneath `/zarrs/`
+    pub(super) fn allows_infinite_depth(&self) -> bool {
+        match self {
+            DavPath::Version { .. }
+            | DavPath::AssetById { .. }
+            | DavPath::DandiResource { .. }
+            | DavPath::ZarrPath { .. } => true,
+            DavPath::Root
+            | DavPath::DandisetIndex
+            | DavPath::Dandiset { .. }
+            | DavPath::DandisetReleases { .. }
+            | DavPath::DandisetYaml { .. }
+            | DavPath::ZarrIndex
+            | DavPath::ZarrEntryByChecksum { .. } => false,
+        }
+    }
+
+    /// Return the request path (without leading slash) for this path, with
+    /// the version specifier (if any) in canonical form.  Unless the path is
+    /// the root path (which has no non-empty form), a trailing slash is
+    /// included, making the result suitable for use as the path of a
+    /// collection.
+    pub(super) fn to_dir_path(&self) -> Option<PureDirPath> {
+        let mut s = String::new();
+        for seg in self.route().pattern {
+            match seg {
+                Segment::Keyword(word) | Segment::Exact(word) => s.push_str(word),
+                Segment::DandisetId => {
+                    s.push_str(self.dandiset_id().expect("route should have Dandiset ID"));
+                }
+                Segment::Version => match self.version().expect("route should have version") {
+                    VersionSpec::Draft => s.push_str("draft"),
+                    VersionSpec::Published(v) => {
+                        s.push_str("releases/");
+                        s.push_str(v);
+                    }
+                    VersionSpec::Latest => s.push_str("latest"),
+                },
+                Segment::AssetId => match self {
+                    DavPath::AssetById { asset_id, .. } => s.push_str(asset_id),
+                    _ => unreachable!("only AssetById routes have asset IDs"),
+                },
+                Segment::Digest => match self {
+                    DavPath::ZarrEntryByChecksum { digest } => s.push_str(digest),
+                    _ => unreachable!("only ZarrEntryByChecksum routes have digests"),
+                },
+                Segment::Rest => match self {
+                    DavPath::DandiResource { path, .. } | DavPath::ZarrPath { path } => {
+                        s.push_str(path);
+                    }
+                    _ => unreachable!("only resource routes have trailing paths"),
+                },
+            }
+            s.push('/');
+        }
+        PureDirPath::try_from(s).ok()
+    }
+
+    /// Return the version specifier of the Dandiset version that the path is
+    /// in, if any
+    pub(super) fn version(&self) -> Option<&VersionSpec> {
+        match self {
+            DavPath::Version { version, .. }
+            | DavPath::DandisetYaml { version, .. }
+            | DavPath::AssetById { version, .. }
+            | DavPath::DandiResource { version, .. } => Some(version),
+            DavPath::Root
+            | DavPath::DandisetIndex
+            | DavPath::Dandiset { .. }
+            | DavPath::DandisetReleases { .. }
+            | DavPath::ZarrIndex
+            | DavPath::ZarrEntryByChecksum { .. }
+            | DavPath::ZarrPath { .. } => None,
+        }
+    }
+
+    /// Return the ID of the Dandiset that the path is in, if any
+    pub(super) fn dandiset_id(&self) -> Option<&DandisetId> {
+        match self {
+            DavPath::Dandiset { dandiset_id }
+            | DavPath::DandisetReleases { dandiset_id }
+            | DavPath::Version { dandiset_id, .. }
+            | DavPath::DandisetYaml { dandiset_id, .. }
+            | DavPath::AssetById { dandiset_id, .. }
+            | DavPath::DandiResource { dandiset_id, .. } => Some(dandiset_id),
+            DavPath::Root
+            | DavPath::DandisetIndex
+            | DavPath::ZarrIndex
+            | DavPath::ZarrEntryByChecksum { .. }
+            | DavPath::ZarrPath { .. } => None,
+        }
+    }
+}
+
+/// A route served by `dandidav`: a pattern of request path components along
+/// with a function for constructing a [`DavPath`] from the values captured by
+/// the pattern
+#[derive(Debug)]
+struct Route {
+    pattern: &'static [Segment],
+    build: fn(Captures) -> Option<DavPath>,
+}
+
+/// A portion of a [`Route`] pattern
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Segment {
+    /// A fixed path component, matched case-insensitively
+    Keyword(&'static str),
+
+    /// A fixed path component, matched case-sensitively
+    Exact(&'static str),
+
+    /// A Dandiset ID
+    DandisetId,
+
+    /// A version specifier: `draft`, `latest` (both case-insensitive), or
+    /// `releases/{version_id}` (spanning two path components)
+    Version,
+
+    /// Any single path component, taken as an asset ID
+    AssetId,
+
+    /// Any single path component, taken as an S3 object digest
+    Digest,
+
+    /// All remaining path components, if any.  Only valid at the end of a
+    /// pattern.
+    Rest,
+}
+
+/// The values captured from a request path by a [`Route`] pattern
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Captures {
+    dandiset_id: Option<DandisetId>,
+    version: Option<VersionSpec>,
+    asset_id: Option<Component>,
+    digest: Option<Component>,
+    rest: Option<PurePath>,
+}
+
+impl Route {
+    /// If the request path components `parts` match the route's pattern,
+    /// return the route along with the captured values
+    fn capture(&'static self, parts: &[Component]) -> Option<(&'static Route, Captures)> {
+        let mut captures = Captures::default();
+        let mut parts = parts;
+        for seg in self.pattern {
+            if *seg == Segment::Rest {
+                captures.rest = PurePath::from_components(parts.iter().cloned());
+                parts = &[];
+                continue;
             }
-        } else if p1.eq_ignore_ascii_case("zarrs") {
-            match PurePath::from_components(iter) {
-                None => Some(DavPath::ZarrIndex),
-                Some(path) => Some(DavPath::ZarrPath { path }),
+            let (p, rest) = parts.split_first()?;
+            parts = rest;
+            match seg {
+                Segment::Keyword(word) => {
+                    if !p.eq_ignore_ascii_case(word) {
+                        return None;
+                    }
+                }
+                Segment::Exact(word) => {
+                    if p != word {
+                        return None;
+                    }
+                }
+                Segment::DandisetId => captures.dandiset_id = Some(p.parse().ok()?),
+                Segment::Version => {
+                    captures.version = Some(if p.eq_ignore_ascii_case("draft") {
+                        VersionSpec::Draft
+                    } else if p.eq_ignore_ascii_case("latest") {
+                        VersionSpec::Latest
+                    } else if p.eq_ignore_ascii_case("releases") {
+                        let (v, rest) = parts.split_first()?;
+                        parts = rest;
+                        VersionSpec::Published(v.parse().ok()?)
+                    } else {
+                        return None;
+                    });
+                }
+                Segment::AssetId => captures.asset_id = Some(p.clone()),
+                Segment::Digest => captures.digest = Some(p.clone()),
+                Segment::Rest => unreachable!("Rest should have been handled above"),
             }
-        } else {
-            None
         }
+        parts.is_empty().then_some((self, captures))
     }
 }
 
+static ROOT: Route = Route {
+    pattern: &[],
+    build: |_| Some(DavPath::Root),
+};
+
+static DANDISET_INDEX: Route = Route {
+    pattern: &[Segment::Keyword("dandisets")],
+    build: |_| Some(DavPath::DandisetIndex),
+};
+
+static DANDISET: Route = Route {
+    pattern: &[Segment::Keyword("dandisets"), Segment::DandisetId],
+    build: |c| {
+        Some(DavPath::Dandiset {
+            dandiset_id: c.dandiset_id?,
+        })
+    },
+};
+
+static DANDISET_RELEASES: Route = Route {
+    pattern: &[
+        Segment::Keyword("dandisets"),
+        Segment::DandisetId,
+        Segment::Keyword("releases"),
+    ],
+    build: |c| {
+        Some(DavPath::DandisetReleases {
+            dandiset_id: c.dandiset_id?,
+        })
+    },
+};
+
+static VERSION: Route = Route {
+    pattern: &[
+        Segment::Keyword("dandisets"),
+        Segment::DandisetId,
+        Segment::Version,
+    ],
+    build: |c| {
+        Some(DavPath::Version {
+            dandiset_id: c.dandiset_id?,
+            version: c.version?,
+        })
+    },
+};
+
+static DANDISET_YAML: Route = Route {
+    pattern: &[
+        Segment::Keyword("dandisets"),
+        Segment::DandisetId,
+        Segment::Version,
+        Segment::Exact("dandiset.yaml"),
+    ],
+    build: |c| {
+        Some(DavPath::DandisetYaml {
+            dandiset_id: c.dandiset_id?,
+            version: c.version?,
+        })
+    },
+};
+
+static ASSET_BY_ID: Route = Route {
+    pattern: &[
+        Segment::Keyword("dandisets"),
+        Segment::DandisetId,
+        Segment::Version,
+        Segment::Exact(BY_ASSET_ID_DIR),
+        Segment::AssetId,
+    ],
+    build: |c| {
+        Some(DavPath::AssetById {
+            dandiset_id: c.dandiset_id?,
+            version: c.version?,
+            asset_id: c.asset_id?,
+        })
+    },
+};
+
+/// Any other path beneath [`BY_ASSET_ID_DIR`] does not exist
+static BY_ASSET_ID_RESERVED: Route = Route {
+    pattern: &[
+        Segment::Keyword("dandisets"),
+        Segment::DandisetId,
+        Segment::Version,
+        Segment::Exact(BY_ASSET_ID_DIR),
+        Segment::Rest,
+    ],
+    build: |_| None,
+};
+
+static DANDI_RESOURCE: Route = Route {
+    pattern: &[
+        Segment::Keyword("dandisets"),
+        Segment::DandisetId,
+        Segment::Version,
+        Segment::Rest,
+    ],
+    build: |c| {
+        Some(DavPath::DandiResource {
+            dandiset_id: c.dandiset_id?,
+            version: c.version?,
+            path: c.rest?,
+        })
+    },
+};
+
+static ZARR_INDEX: Route = Route {
+    pattern: &[Segment::Keyword("zarrs")],
+    build: |_| Some(DavPath::ZarrIndex),
+};
+
+static ZARR_BY_CHECKSUM: Route = Route {
+    pattern: &[
+        Segment::Keyword("zarrs"),
+        Segment::Exact(BY_CHECKSUM_DIR),
+        Segment::Digest,
+    ],
+    build: |c| Some(DavPath::ZarrEntryByChecksum { digest: c.digest? }),
+};
+
+/// Any other path beneath [`BY_CHECKSUM_DIR`] does not exist
+static BY_CHECKSUM_RESERVED: Route = Route {
+    pattern: &[
+        Segment::Keyword("zarrs"),
+        Segment::Exact(BY_CHECKSUM_DIR),
+        Segment::Rest,
+    ],
+    build: |_| None,
+};
+
+static ZARR_PATH: Route = Route {
+    pattern: &[Segment::Keyword("zarrs"), Segment::Rest],
+    build: |c| Some(DavPath::ZarrPath { path: c.rest? }),
+};
+
+/// The routes served by `dandidav`.  A request path is handled by the first
+/// route in this list whose pattern matches it; if that route's `build`
+/// function returns `None`, the path does not exist.
+static ROUTES: [&Route; 13] = [
+    &ROOT,
+    &DANDISET_INDEX,
+    &DANDISET,
+    &DANDISET_RELEASES,
+    &VERSION,
+    &DANDISET_YAML,
+    &ASSET_BY_ID,
+    &BY_ASSET_ID_RESERVED,
+    &DANDI_RESOURCE,
+    &ZARR_INDEX,
+    &ZARR_BY_CHECKSUM,
+    &BY_CHECKSUM_RESERVED,
+    &ZARR_PATH,
+];
+
 /// A Dandiset version as specified in a request path
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(super) enum VersionSpec {
@@ -575,6 +917,28 @@ mod tests {
             });
         }
 
+        #[rstest]
+        #[case("/dandisets/000123/draft/.by-asset-id/0fa3b2e1-1a1e-4c73-9d3d-6a1c8e9f0b7a")]
+        #[case("/dandisets/000123/draft/.by-asset-id/0fa3b2e1-1a1e-4c73-9d3d-6a1c8e9f0b7a/")]
+        #[case("/dandisets/000123/draft//.by-asset-id//0fa3b2e1-1a1e-4c73-9d3d-6a1c8e9f0b7a")]
+        fn test_asset_by_id(#[case] s: &str) {
+            let parts = split_uri_path(s).unwrap();
+            assert_matches!(DavPath::from_components(parts), Some(DavPath::AssetById {dandiset_id, version, asset_id}) => {
+                assert_eq!(dandiset_id, "000123");
+                assert_eq!(version, VersionSpec::Draft);
+                assert_eq!(asset_id, "0fa3b2e1-1a1e-4c73-9d3d-6a1c8e9f0b7a");
+            });
+        }
+
+        #[rstest]
+        #[case("/dandisets/000123/draft/.by-asset-id")]
+        #[case("/dandisets/000123/draft/.by-asset-id/")]
+        #[case("/dandisets/000123/draft/.by-asset-id/0fa3b2e1-1a1e-4c73-9d3d-6a1c8e9f0b7a/foo")]
+        fn test_bad_asset_by_id(#[case] s: &str) {
+            let parts = split_uri_path(s).unwrap();
+            assert_eq!(DavPath::from_components(parts), None);
+        }
+
         #[rstest]
         #[case("/zarrs")]
         #[case("/zarrs/")]
@@ -598,5 +962,66 @@ mod tests {
                 assert_eq!(path, respath);
             });
         }
+
+        #[rstest]
+        #[case("/zarrs/.by-checksum/7b5af4c6c28047c83dd86e4814bc0272")]
+        #[case("/zarrs/.by-checksum/7b5af4c6c28047c83dd86e4814bc0272/")]
+        #[case("/Zarrs//.by-checksum/7b5af4c6c28047c83dd86e4814bc0272")]
+        fn test_zarr_entry_by_checksum(#[case] s: &str) {
+            let parts = split_uri_path(s).unwrap();
+            assert_matches!(DavPath::from_components(parts), Some(DavPath::ZarrEntryByChecksum {digest}) => {
+                assert_eq!(digest, "7b5af4c6c28047c83dd86e4814bc0272");
+            });
+        }
+
+        #[rstest]
+        #[case("/zarrs/.by-checksum")]
+        #[case("/zarrs/.by-checksum/")]
+        #[case("/zarrs/.by-checksum/7b5af4c6c28047c83dd86e4814bc0272/foo")]
+        fn test_bad_zarr_entry_by_checksum(#[case] s: &str) {
+            let parts = split_uri_path(s).unwrap();
+            assert_eq!(DavPath::from_components(parts), None);
+        }
+    }
+
+    mod dav_path_to_dir_path {
+        use super::*;
+        use rstest::rstest;
+
+        #[rstest]
+        #[case("/", None)]
+        #[case("/Dandisets", Some("dandisets/"))]
+        #[case("/dandisets/000123", Some("dandisets/000123/"))]
+        #[case("/dandisets/000123/Releases", Some("dandisets/000123/releases/"))]
+        #[case("/dandisets/000123/DRAFT", Some("dandisets/000123/draft/"))]
+        #[case("/dandisets/000123/latest/", Some("dandisets/000123/latest/"))]
+        #[case(
+            "/dandisets/000123/releases/0.240123.42",
+            Some("dandisets/000123/releases/0.240123.42/")
+        )]
+        #[case(
+            "/dandisets/000123/draft/dandiset.yaml",
+            Some("dandisets/000123/draft/dandiset.yaml/")
+        )]
+        #[case(
+            "/dandisets/000123/latest/.by-asset-id/abc",
+            Some("dandisets/000123/latest/.by-asset-id/abc/")
+        )]
+        #[case(
+            "/dandisets/000123/draft/foo//bar",
+            Some("dandisets/000123/draft/foo/bar/")
+        )]
+        #[case("/zarrs", Some("zarrs/"))]
+        #[case("/zarrs/123/abc", Some("zarrs/123/abc/"))]
+        #[case("/zarrs/.by-checksum/abc", Some("zarrs/.by-checksum/abc/"))]
+        fn test_to_dir_path(#[case] s: &str, #[case] dirpath: Option<&str>) {
+            let parts = split_uri_path(s).unwrap();
+            let path = DavPath::from_components(parts).unwrap();
+            assert_eq!(path.to_dir_path().as_deref(), dirpath);
+            if let Some(dirpath) = dirpath {
+                let parts = split_uri_path(dirpath).unwrap();
+                assert_eq!(DavPath::from_components(parts), Some(path));
+            }
+        }
     }
 }
@@ -0,0 +1,106 @@
+//! Deployment-specific rules for which WebDAV properties are reported
+use super::types::{HasProperties, PropertyError};
+use super::xml::{PropValue, Property};
+
+/// A standard WebDAV property that can be omitted from collection resources.
+/// Several WebDAV clients mishandle these properties on collections (e.g., by
+/// treating a collection with a `getcontentlength` as a file).
+#[derive(clap::ValueEnum, Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum CollectionProperty {
+    #[value(name = "getcontentlength")]
+    GetContentLength,
+
+    #[value(name = "getcontenttype")]
+    GetContentType,
+}
+
+impl From<CollectionProperty> for Property {
+    fn from(value: CollectionProperty) -> Property {
+        match value {
+            CollectionProperty::GetContentLength => Property::GetContentLength,
+            CollectionProperty::GetContentType => Property::GetContentType,
+        }
+    }
+}
+
+/// A layer between resources and `PROPFIND` responses that determines which
+/// of a resource's properties are reported and supplies the server settings
+/// that some properties' values depend on.  The default policy reports all
+/// properties and reports download URLs as though `--prefer-s3-redirects` were
+/// not given.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct PropertyPolicy {
+    /// Properties that are never reported for collection resources
+    omit_from_collections: Vec<Property>,
+
+    /// The value of `--prefer-s3-redirects`, used to determine the
+    /// "download-url" property
+    prefer_s3_redirects: bool,
+
+    /// The value of `--s3-content-disposition`, used to determine the
+    /// "download-url" property
+    s3_content_disposition: bool,
+
+    /// The "displayname" property of resources with no name of their own
+    /// (i.e., the root collection)
+    root_name: Option<String>,
+}
+
+impl PropertyPolicy {
+    /// Construct a policy that omits the given properties from collection
+    /// resources
+    pub(crate) fn omitting_from_collections<I>(props: I) -> PropertyPolicy
+    where
+        I: IntoIterator<Item = CollectionProperty>,
+    {
+        let mut omit_from_collections = props.into_iter().map(Property::from).collect::<Vec<_>>();
+        omit_from_collections.sort();
+        omit_from_collections.dedup();
+        PropertyPolicy {
+            omit_from_collections,
+            ..PropertyPolicy::default()
+        }
+    }
+
+    /// Report download URLs as resolved under the given
+    /// `--prefer-s3-redirects` and `--s3-content-disposition` settings
+    pub(crate) fn with_redirects(
+        mut self,
+        prefer_s3_redirects: bool,
+        s3_content_disposition: bool,
+    ) -> PropertyPolicy {
+        self.prefer_s3_redirects = prefer_s3_redirects;
+        self.s3_content_disposition = s3_content_disposition;
+        self
+    }
+
+    /// Report `name` as the "displayname" of the root collection
+    pub(crate) fn with_root_name(mut self, name: String) -> PropertyPolicy {
+        self.root_name = Some(name);
+        self
+    }
+
+    /// Return the value of the given property of `res` under this policy.
+    /// Properties omitted by the policy evaluate to `None`, as though `res`
+    /// did not have them.
+    pub(super) fn property<P: HasProperties>(
+        &self,
+        res: &P,
+        prop: &Property,
+    ) -> Result<Option<PropValue>, PropertyError> {
+        if res.is_collection() && self.omit_from_collections.contains(prop) {
+            Ok(None)
+        } else if *prop == Property::DownloadUrl {
+            Ok(res
+                .download_url(self.prefer_s3_redirects, self.s3_content_disposition)
+                .map(|url| url.to_string().into()))
+        } else if *prop == Property::DisplayName {
+            Ok(res
+                .displayname()
+                .or_else(|| self.root_name.clone())
+                .map(Into::into))
+        } else {
+            res.property(prop)
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Short-lived cache of `PROPFIND` responses for the top of the hierarchy
+//!
+//! When a share is mapped as a network drive, the Windows WebDAV redirector
+//! sends a burst of `OPTIONS` & `PROPFIND` requests for the same few
+//! collections.  `OPTIONS` responses are static, but listing `/dandisets/` or
+//! `/zarrs/` requires enumerating every Dandiset on the Archive or every entry
+//! at the top of the Zarr manifest tree.  When enabled, the rendered
+//! responses to `PROPFIND` requests for the root and the index collections are
+//! therefore kept for a few seconds and reused for identical requests.
+use super::path::DavPath;
+use super::util::FiniteDepth;
+use super::xml::PropFind;
+use crate::consts::PROPFIND_CACHE_BYTES;
+use bytes::Bytes;
+use moka::future::{Cache, CacheBuilder};
+use std::time::Duration;
+
+/// A cache of rendered `PROPFIND` response bodies for the root and index
+/// collections
+#[derive(Clone, Debug)]
+pub(super) struct PropfindCache {
+    responses: Cache<PropfindKey, Bytes>,
+}
+
+impl PropfindCache {
+    /// Construct a new `PropfindCache` that keeps responses for `ttl`
+    pub(super) fn new(ttl: Duration) -> PropfindCache {
+        PropfindCache {
+            responses: CacheBuilder::new(PROPFIND_CACHE_BYTES)
+                .name("propfind-responses")
+                .weigher(|_, body: &Bytes| u32::try_from(body.len()).unwrap_or(u32::MAX))
+                .time_to_live(ttl)
+                .build(),
+        }
+    }
+
+    /// Return the cached response body for the given key, if any
+    pub(super) async fn get(&self, key: &PropfindKey) -> Option<Bytes> {
+        self.responses.get(key).await
+    }
+
+    /// Cache the response body `body` for the given key
+    pub(super) async fn insert(&self, key: PropfindKey, body: Bytes) {
+        self.responses.insert(key, body).await;
+    }
+}
+
+/// The parts of a `PROPFIND` request that determine its response, for
+/// requests whose responses are cacheable
+///
+/// The Zarr mode is not part of the key, as it does not affect the root or
+/// index collections.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(super) struct PropfindKey {
+    /// Which of the cacheable collections was requested
+    path: IndexPath,
+
+    /// The request's depth
+    depth: FiniteDepth,
+
+    /// The request's query
+    query: PropFind,
+}
+
+impl PropfindKey {
+    /// Return the key for a `PROPFIND` request for `path` with the given depth
+    /// & query, or `None` if responses for `path` are not cached
+    pub(super) fn new(path: &DavPath, depth: FiniteDepth, query: &PropFind) -> Option<PropfindKey> {
+        let path = match path {
+            DavPath::Root => IndexPath::Root,
+            DavPath::DandisetIndex => IndexPath::DandisetIndex,
+            DavPath::ZarrIndex => IndexPath::ZarrIndex,
+            _ => return None,
+        };
+        Some(PropfindKey {
+            path,
+            depth,
+            query: query.clone(),
+        })
+    }
+}
+
+/// A collection whose `PROPFIND` responses are cached
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+enum IndexPath {
+    Root,
+    DandisetIndex,
+    ZarrIndex,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_only_for_indexes() {
+        let query = PropFind::PropName;
+        assert!(PropfindKey::new(&DavPath::Root, FiniteDepth::One, &query).is_some());
+        assert!(PropfindKey::new(&DavPath::DandisetIndex, FiniteDepth::Zero, &query).is_some());
+        assert!(PropfindKey::new(&DavPath::ZarrIndex, FiniteDepth::One, &query).is_some());
+        let dandiset = DavPath::Dandiset {
+            dandiset_id: "000001".parse().unwrap(),
+        };
+        assert!(PropfindKey::new(&dandiset, FiniteDepth::One, &query).is_none());
+    }
+
+    #[tokio::test]
+    async fn expires() {
+        let cache = PropfindCache::new(Duration::from_millis(50));
+        let key = PropfindKey::new(&DavPath::Root, FiniteDepth::One, &PropFind::PropName).unwrap();
+        cache
+            .insert(key.clone(), Bytes::from_static(b"<multistatus/>"))
+            .await;
+        assert_eq!(
+            cache.get(&key).await,
+            Some(Bytes::from_static(b"<multistatus/>"))
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(cache.get(&key).await, None);
+    }
+}
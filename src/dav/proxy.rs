@@ -0,0 +1,336 @@
+//! Proxying of S3 objects
+//!
+//! `GET` requests for Zarr entries and blob assets are normally answered with
+//! redirects to the objects' S3 URLs.  Some HTTP clients do not resend a
+//! request's `Range` header after following a redirect, and so Zarr stores
+//! that read parts of chunks break when used with such clients.  When enabled,
+//! ranged requests for Zarr entries are instead answered by fetching the
+//! requested range from S3 and passing it back to the client.
+//!
+//! Other clients (such as old versions of davfs2 and the Windows WebDAV
+//! redirector) mishandle redirects altogether.  For their sake, requests for
+//! blob assets can also be answered by streaming the whole object (or the
+//! requested range of it) from S3 through the server.
+use crate::httputil::{Client, HttpError, HttpUrl};
+use axum::{
+    body::Body,
+    http::{
+        header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, LAST_MODIFIED},
+        HeaderName, HeaderValue, Response, StatusCode,
+    },
+};
+
+/// Headers of upstream responses that are passed through to the client
+static PASSTHROUGH_HEADERS: [HeaderName; 6] = [
+    ACCEPT_RANGES,
+    CONTENT_LENGTH,
+    CONTENT_RANGE,
+    CONTENT_TYPE,
+    ETAG,
+    LAST_MODIFIED,
+];
+
+/// Fetch the byte range(s) given by the `Range` header value `range` from
+/// `url` and convert the upstream response into a response for the client,
+/// streaming the body through.
+///
+/// A 416 ("Range Not Satisfiable") upstream response is passed through as-is.
+pub(super) async fn proxy_range(
+    client: &Client,
+    url: HttpUrl,
+    range: HeaderValue,
+) -> Result<Response<Body>, HttpError> {
+    let upstream = client.get_range(url, range).await?;
+    Ok(passthrough(upstream, false))
+}
+
+/// Fetch the object at `url` — or, if `range` is given, the byte range(s) of
+/// it given by that `Range` header value — and convert the upstream response
+/// into a response for the client, streaming the body through.  If `head` is
+/// true, only the object's headers are reported, and the response has an
+/// empty body.
+///
+/// As presigned S3 URLs are only valid for the method they were signed for,
+/// `HEAD` requests are answered by fetching the object's first byte with a
+/// `GET` and reconstructing the object's headers from the response.
+///
+/// A 416 ("Range Not Satisfiable") upstream response is passed through as-is.
+pub(super) async fn proxy_object(
+    client: &Client,
+    url: HttpUrl,
+    range: Option<HeaderValue>,
+    head: bool,
+) -> Result<Response<Body>, HttpError> {
+    if head {
+        let upstream = client
+            .get_range(url, HeaderValue::from_static("bytes=0-0"))
+            .await?;
+        return Ok(head_response(upstream));
+    }
+    let upstream = if let Some(range) = range {
+        client.get_range(url, range).await?
+    } else {
+        client.get(url).await?
+    };
+    Ok(passthrough(upstream, false))
+}
+
+/// Convert the upstream response to a `GET` request for the first byte of an
+/// object into a bodiless response describing the whole object
+fn head_response(upstream: reqwest::Response) -> Response<Body> {
+    let total = upstream
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit_once('/'))
+        .and_then(|(_, total)| total.parse::<u64>().ok());
+    let status = upstream.status();
+    // S3 answers a range request for an empty object with a 416 whose
+    // Content-Range gives the object's size as zero.
+    let whole = status == StatusCode::PARTIAL_CONTENT
+        || (status == StatusCode::RANGE_NOT_SATISFIABLE && total == Some(0));
+    let mut resp = passthrough(upstream, true);
+    if whole {
+        *resp.status_mut() = StatusCode::OK;
+        let headers = resp.headers_mut();
+        headers.remove(CONTENT_RANGE);
+        match total {
+            Some(total) => {
+                headers.insert(CONTENT_LENGTH, HeaderValue::from(total));
+            }
+            None => {
+                headers.remove(CONTENT_LENGTH);
+            }
+        }
+    }
+    resp
+}
+
+/// Convert the upstream response `upstream` into a response for the client
+/// with the same status and [`PASSTHROUGH_HEADERS`].  If `head` is false, the
+/// body is streamed through; otherwise, the response has an empty body.
+fn passthrough(upstream: reqwest::Response, head: bool) -> Response<Body> {
+    let mut builder = Response::builder().status(upstream.status());
+    for name in &PASSTHROUGH_HEADERS {
+        if let Some(value) = upstream.headers().get(name) {
+            builder = builder.header(name, value);
+        }
+    }
+    let body = if head {
+        Body::empty()
+    } else {
+        Body::from_stream(futures_util::stream::try_unfold(
+            upstream,
+            |mut upstream| async move {
+                Ok::<_, reqwest::Error>(upstream.chunk().await?.map(|chunk| (chunk, upstream)))
+            },
+        ))
+    };
+    builder
+        .body(body)
+        .expect("response built from upstream status and headers should be valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    async fn mock_s3() -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/zarr/0/0"))
+            .and(header("Range", "bytes=2-5"))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .insert_header("Content-Range", "bytes 2-5/10")
+                    .insert_header("Content-Type", "application/octet-stream")
+                    .insert_header("ETag", "\"0123456789abcdef\"")
+                    .insert_header("X-Amz-Request-Id", "ABCDEF")
+                    .set_body_bytes(b"2345".as_slice()),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/zarr/0/0"))
+            .and(header("Range", "bytes=20-"))
+            .respond_with(ResponseTemplate::new(416).insert_header("Content-Range", "bytes */10"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/blobs/abc/def"))
+            .and(header("Range", "bytes=2-5"))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .insert_header("Content-Range", "bytes 2-5/10")
+                    .set_body_bytes(b"2345".as_slice()),
+            )
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/blobs/abc/def"))
+            .and(header("Range", "bytes=0-0"))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .insert_header("Content-Range", "bytes 0-0/10")
+                    .insert_header("Content-Type", "application/x-nwb")
+                    .insert_header("Accept-Ranges", "bytes")
+                    .set_body_bytes(b"0".as_slice()),
+            )
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/blobs/abc/def"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Type", "application/x-nwb")
+                    .insert_header("Accept-Ranges", "bytes")
+                    .set_body_bytes(b"0123456789".as_slice()),
+            )
+            .mount(&server)
+            .await;
+        // Like a URL presigned for `GET`, this object cannot be fetched with
+        // `HEAD`.
+        Mock::given(method("HEAD"))
+            .and(path("/blobs/abc/def"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/blobs/abc/empty"))
+            .and(header("Range", "bytes=0-0"))
+            .respond_with(ResponseTemplate::new(416).insert_header("Content-Range", "bytes */0"))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn partial_content() {
+        let server = mock_s3().await;
+        let client = Client::new(None).unwrap();
+        let url = format!("{}/zarr/0/0", server.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let resp = proxy_range(&client, url, HeaderValue::from_static("bytes=2-5"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.headers().get(CONTENT_RANGE).unwrap(), "bytes 2-5/10");
+        assert_eq!(resp.headers().get(CONTENT_LENGTH).unwrap(), "4");
+        assert_eq!(
+            resp.headers().get(CONTENT_TYPE).unwrap(),
+            "application/octet-stream"
+        );
+        assert_eq!(resp.headers().get(ETAG).unwrap(), "\"0123456789abcdef\"");
+        assert!(!resp.headers().contains_key("X-Amz-Request-Id"));
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, b"2345".as_slice());
+    }
+
+    #[tokio::test]
+    async fn range_not_satisfiable() {
+        let server = mock_s3().await;
+        let client = Client::new(None).unwrap();
+        let url = format!("{}/zarr/0/0", server.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let resp = proxy_range(&client, url, HeaderValue::from_static("bytes=20-"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(resp.headers().get(CONTENT_RANGE).unwrap(), "bytes */10");
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let server = mock_s3().await;
+        let client = Client::new(None).unwrap();
+        let url = format!("{}/zarr/0/1", server.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let r = proxy_range(&client, url, HeaderValue::from_static("bytes=2-5")).await;
+        assert!(matches!(r, Err(HttpError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn whole_object() {
+        let server = mock_s3().await;
+        let client = Client::new(None).unwrap();
+        let url = format!("{}/blobs/abc/def", server.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let resp = proxy_object(&client, url, None, false).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(CONTENT_LENGTH).unwrap(), "10");
+        assert_eq!(
+            resp.headers().get(CONTENT_TYPE).unwrap(),
+            "application/x-nwb"
+        );
+        assert_eq!(resp.headers().get(ACCEPT_RANGES).unwrap(), "bytes");
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, b"0123456789".as_slice());
+    }
+
+    #[tokio::test]
+    async fn object_range() {
+        let server = mock_s3().await;
+        let client = Client::new(None).unwrap();
+        let url = format!("{}/blobs/abc/def", server.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let resp = proxy_object(
+            &client,
+            url,
+            Some(HeaderValue::from_static("bytes=2-5")),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.headers().get(CONTENT_RANGE).unwrap(), "bytes 2-5/10");
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, b"2345".as_slice());
+    }
+
+    #[tokio::test]
+    async fn object_head() {
+        let server = mock_s3().await;
+        let client = Client::new(None).unwrap();
+        let url = format!("{}/blobs/abc/def", server.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let resp = proxy_object(&client, url, None, true).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(CONTENT_LENGTH).unwrap(), "10");
+        assert_eq!(
+            resp.headers().get(CONTENT_TYPE).unwrap(),
+            "application/x-nwb"
+        );
+        assert!(!resp.headers().contains_key(CONTENT_RANGE));
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert!(body.is_empty());
+        let received = server.received_requests().await.unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].method.as_str(), "GET");
+    }
+
+    #[tokio::test]
+    async fn empty_object_head() {
+        let server = mock_s3().await;
+        let client = Client::new(None).unwrap();
+        let url = format!("{}/blobs/abc/empty", server.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let resp = proxy_object(&client, url, None, true).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(CONTENT_LENGTH).unwrap(), "0");
+        assert!(!resp.headers().contains_key(CONTENT_RANGE));
+    }
+}
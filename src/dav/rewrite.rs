@@ -0,0 +1,139 @@
+//! Redirection of friendly DANDI URL forms to canonical request paths
+//!
+//! DOIs and the DANDI web UI refer to Dandisets with paths like
+//! `/dandiset/000123/0.230101.1234` or `/DANDI:000123/0.230101.1234`.  So
+//! that such paths can be pasted onto the end of `dandidav`'s URL, `GET`
+//! requests for them are redirected to the corresponding paths under
+//! `/dandisets/`.
+use super::path::{DavPath, VersionSpec};
+use crate::dandi::{DandisetId, PublishedVersionId};
+use crate::paths::Component;
+
+/// The table of friendly URL forms
+static REWRITES: [Rewrite; 2] = [
+    Rewrite {
+        extract: dandi_prefixed_id,
+        consumed: 1,
+    },
+    Rewrite {
+        extract: dandiset_dir_id,
+        consumed: 2,
+    },
+];
+
+/// An entry in the table of friendly URL forms
+#[derive(Clone, Copy, Debug)]
+struct Rewrite {
+    /// A function that extracts a Dandiset ID from the start of a request
+    /// path, if the path is in this form
+    extract: fn(&[Component]) -> Option<DandisetId>,
+
+    /// The number of path components that make up the Dandiset portion of
+    /// the form; any further components identify the version
+    consumed: usize,
+}
+
+/// Return the canonical request path (with a trailing slash) that the request
+/// path with components `parts` should be redirected to, or `None` if the
+/// path is not in any of the friendly URL forms.
+///
+/// The supported forms (in which the prefixes are case-insensitive) are:
+///
+/// - `/DANDI:{dandiset_id}` and `/dandiset/{dandiset_id}`, which redirect to
+///   `/dandisets/{dandiset_id}/`
+///
+/// - Either of the above followed by `/draft`, `/latest`, or
+///   `/{version_id}`, which redirect to the corresponding version path under
+///   `/dandisets/{dandiset_id}/`.  A trailing `/files` component (as used by
+///   the web UI) is ignored.
+pub(super) fn rewrite(parts: &[Component]) -> Option<String> {
+    REWRITES.iter().find_map(|rw| {
+        let dandiset_id = (rw.extract)(parts)?;
+        let rest = parts.get(rw.consumed..)?;
+        let rest = match rest {
+            [pre @ .., last] if last.eq_ignore_ascii_case("files") => pre,
+            _ => rest,
+        };
+        let target = match rest {
+            [] => DavPath::Dandiset { dandiset_id },
+            [v] => {
+                let version = if v.eq_ignore_ascii_case("draft") {
+                    VersionSpec::Draft
+                } else if v.eq_ignore_ascii_case("latest") {
+                    VersionSpec::Latest
+                } else {
+                    VersionSpec::Published(v.parse::<PublishedVersionId>().ok()?)
+                };
+                DavPath::Version {
+                    dandiset_id,
+                    version,
+                }
+            }
+            _ => return None,
+        };
+        Some(format!("/{}", target.to_dir_path()?))
+    })
+}
+
+/// Extract the Dandiset ID from a path starting with `DANDI:{dandiset_id}`
+fn dandi_prefixed_id(parts: &[Component]) -> Option<DandisetId> {
+    let first = parts.first()?;
+    let (prefix, did) = first.split_once(':')?;
+    if !prefix.eq_ignore_ascii_case("dandi") {
+        return None;
+    }
+    did.parse::<DandisetId>().ok()
+}
+
+/// Extract the Dandiset ID from a path starting with `dandiset/{dandiset_id}`
+fn dandiset_dir_id(parts: &[Component]) -> Option<DandisetId> {
+    let [first, did, ..] = parts else {
+        return None;
+    };
+    if !first.eq_ignore_ascii_case("dandiset") {
+        return None;
+    }
+    did.parse::<DandisetId>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dav::path::split_uri_path;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("/DANDI:000123", Some("/dandisets/000123/"))]
+    #[case("/dandi:000123/", Some("/dandisets/000123/"))]
+    #[case(
+        "/DANDI:000123/0.230101.1234",
+        Some("/dandisets/000123/releases/0.230101.1234/")
+    )]
+    #[case("/DANDI:000123/draft", Some("/dandisets/000123/draft/"))]
+    #[case("/DANDI:000123/Latest", Some("/dandisets/000123/latest/"))]
+    #[case("/dandiset/000123", Some("/dandisets/000123/"))]
+    #[case("/Dandiset/000123/", Some("/dandisets/000123/"))]
+    #[case(
+        "/dandiset/000123/0.230101.1234",
+        Some("/dandisets/000123/releases/0.230101.1234/")
+    )]
+    #[case("/dandiset/000123/draft", Some("/dandisets/000123/draft/"))]
+    #[case("/dandiset/000123/draft/files", Some("/dandisets/000123/draft/"))]
+    #[case(
+        "/dandiset/000123/0.230101.1234/files",
+        Some("/dandisets/000123/releases/0.230101.1234/")
+    )]
+    #[case("/", None)]
+    #[case("/dandisets/000123/", None)]
+    #[case("/dandiset/", None)]
+    #[case("/dandiset/123", None)]
+    #[case("/DANDI:123", None)]
+    #[case("/DOI:000123", None)]
+    #[case("/dandiset/000123/1.0", None)]
+    #[case("/dandiset/000123/draft/foo.nwb", None)]
+    #[case("/dandiset/000123/files", Some("/dandisets/000123/"))]
+    fn test_rewrite(#[case] path: &str, #[case] target: Option<&str>) {
+        let parts = split_uri_path(path).unwrap();
+        assert_eq!(rewrite(&parts).as_deref(), target);
+    }
+}
@@ -0,0 +1,70 @@
+// Downloading of the resources selected in an HTML collection view.
+//
+// The form's checkboxes submit the selected paths as `paths` query parameters
+// to the Dandiset version, which replies with a JSON manifest of all files at
+// or beneath those paths.  Without this script, submitting the form simply
+// displays the manifest; with it, each file in the manifest is downloaded in
+// turn.
+
+"use strict";
+
+// Pause between successive downloads so that browsers don't drop any of them
+const DOWNLOAD_INTERVAL_MS = 250;
+
+function sleep(ms) {
+    return new Promise((resolve) => setTimeout(resolve, ms));
+}
+
+function downloadFile(file) {
+    const link = document.createElement("a");
+    link.href = file.download_url ?? file.href;
+    link.download = file.path.split("/").pop();
+    document.body.append(link);
+    link.click();
+    link.remove();
+}
+
+async function downloadSelected(form) {
+    const params = new URLSearchParams(new FormData(form));
+    if (!params.has("paths")) {
+        return;
+    }
+    const status = form.querySelector(".download-status");
+    const button = form.querySelector("button");
+    status.textContent = "";
+    button.disabled = true;
+    try {
+        const url = new URL(form.action);
+        url.search = params.toString();
+        const response = await fetch(url, {
+            headers: { Accept: "application/json" },
+        });
+        if (!response.ok) {
+            throw new Error(`HTTP ${response.status}`);
+        }
+        const manifest = await response.json();
+        for (const file of manifest.files) {
+            downloadFile(file);
+            await sleep(DOWNLOAD_INTERVAL_MS);
+        }
+        if (manifest.errors.length > 0 || manifest.truncated) {
+            status.textContent = form.dataset.failed;
+        }
+    } catch (e) {
+        console.error("Failed to download selected files:", e);
+        status.textContent = form.dataset.failed;
+    } finally {
+        button.disabled = false;
+    }
+}
+
+document.addEventListener("DOMContentLoaded", () => {
+    const form = document.getElementById("download-selected");
+    if (form === null) {
+        return;
+    }
+    form.addEventListener("submit", (event) => {
+        event.preventDefault();
+        downloadSelected(form);
+    });
+});
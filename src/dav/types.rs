@@ -1,19 +1,31 @@
-use super::util::{format_creationdate, format_modifieddate, version_path, Href};
+use super::mount::mount_collection;
+use super::path::DavPath;
+use super::util::{
+    content_disposition, format_creationdate, format_modifieddate, version_path, Href,
+};
 use super::xml::{PropValue, Property};
-use super::VersionSpec;
-use crate::consts::{DEFAULT_CONTENT_TYPE, YAML_CONTENT_TYPE};
+use super::{ErrorClass, VersionSpec};
+use crate::annex::AnnexKey;
+use crate::consts::{BY_ASSET_ID_DIR, DEFAULT_CONTENT_TYPE, JSON_CONTENT_TYPE, YAML_CONTENT_TYPE};
 use crate::dandi::*;
 use crate::httputil::HttpUrl;
-use crate::paths::{PureDirPath, PurePath};
+use crate::mountlimit::Mount;
+use crate::paths::{escape_name, escape_path, needs_escape, PureDirPath, PurePath};
+use crate::s3::S3Location;
 use crate::zarrman::*;
+use axum::http::StatusCode;
+use bytes::Bytes;
 use enum_dispatch::enum_dispatch;
 use serde::{ser::Serializer, Serialize};
+use thiserror::Error;
 use time::OffsetDateTime;
 
 /// Trait for querying the values of WebDAV properties from WebDAV resources
 ///
 /// If a property is queried on a resource that does not have it defined, the
-/// query method should return `None`.
+/// query method should return `None`.  If a property is defined for a
+/// resource but its value could not be determined, the query method should
+/// return a [`PropertyError`].
 #[enum_dispatch]
 pub(super) trait HasProperties {
     /// Return the value of the "href" element to use in a "response" for this
@@ -31,7 +43,10 @@ pub(super) trait HasProperties {
 
     /// Return the value of the "displayname" property
     ///
-    /// For `dandidav`, this is the same as the resource's filename.
+    /// For `dandidav`, this is the resource's filename (or, for Dandiset
+    /// versions and Zarr ID folders under `/zarrs/`, a more descriptive
+    /// display name), escaped if it contains control characters or leading or
+    /// trailing whitespace.
     fn displayname(&self) -> Option<String>;
 
     /// Return the value of the "getcontentlength" property
@@ -49,10 +64,79 @@ pub(super) trait HasProperties {
     /// Return `true` iff this is a collection resource
     fn is_collection(&self) -> bool;
 
-    /// Return the value of the given property.  `Property::Custom` inputs will
-    /// always evaluate to `None`.
-    fn property(&self, prop: &Property) -> Option<PropValue> {
-        match prop {
+    /// Return the value of the `dandidav`-specific "aggregate-size" property,
+    /// the total size of all non-collection resources within a collection
+    fn aggregate_size(&self) -> Option<i64>;
+
+    /// Return the value of the `dandidav`-specific "entry-count" property, the
+    /// number of non-collection resources within a collection
+    fn entry_count(&self) -> Result<Option<u64>, PropertyError>;
+
+    /// Return the value of the `dandidav`-specific "annex-key" property, the
+    /// git-annex key for a non-collection resource's content
+    fn annex_key(&self) -> Option<String>;
+
+    /// Return the value of the `dandidav`-specific "permalink" property, the
+    /// URL path at which an asset is served by asset ID
+    fn permalink(&self) -> Option<Href>;
+
+    /// Return the value of the `dandidav`-specific "s3-version-id" property,
+    /// the S3 version ID of the object that a non-collection resource's
+    /// download URL is pinned to
+    fn s3_version_id(&self) -> Option<String>;
+
+    /// Return the value of the `dandidav`-specific "download-url" property,
+    /// the URL that a `GET` request for a non-collection resource is
+    /// redirected to under the given `--prefer-s3-redirects` and
+    /// `--s3-content-disposition` settings
+    fn download_url(&self, _prefer_s3: bool, _s3_content_disposition: bool) -> Option<HttpUrl> {
+        None
+    }
+
+    /// Return the identifiers of the Archive entities that the resource
+    /// corresponds to, for reporting via `dandidav`-specific properties
+    fn archive_ids(&self) -> Option<&ArchiveIds> {
+        None
+    }
+
+    /// Return the digests of the resource's content, for reporting via
+    /// `dandidav`-specific properties
+    fn digests(&self) -> Option<&Digests> {
+        None
+    }
+
+    /// Return the value of the `dandidav`-specific "doi" property, the DOI of
+    /// a Dandiset version
+    fn doi(&self) -> Option<String> {
+        None
+    }
+
+    /// Return the value of the `dandidav`-specific "zarr-node-type" property,
+    /// whether a folder within a Zarr is a Zarr group or array
+    fn zarr_node_type(&self) -> Option<ZarrNodeType> {
+        None
+    }
+
+    /// Return the value of the `dandidav`-specific "exact-name" property, the
+    /// unescaped filename of a resource whose name is escaped in its "href"
+    /// and "displayname"
+    fn exact_name(&self) -> Option<String> {
+        None
+    }
+
+    /// If the resource's details could not be retrieved, return the status to
+    /// report for the resource in place of its properties
+    fn status(&self) -> Option<StatusCode> {
+        None
+    }
+
+    /// Return the value of the given property.  `Property::Custom` inputs, as
+    /// well as the properties used to mark truncated responses and
+    /// `Property::DownloadUrl` (whose value depends on server settings; see
+    /// [`PropertyPolicy`](super::policy::PropertyPolicy)), will always
+    /// evaluate to `None`.
+    fn property(&self, prop: &Property) -> Result<Option<PropValue>, PropertyError> {
+        Ok(match prop {
             Property::CreationDate => self.creationdate().map(Into::into),
             Property::DisplayName => self.displayname().map(Into::into),
             Property::GetContentLength => self.getcontentlength().map(Into::into),
@@ -66,9 +150,142 @@ pub(super) trait HasProperties {
                     Some(PropValue::Empty)
                 }
             }
-            Property::Custom(_) => None,
+            // Locks granted by `LOCK` requests are never enforced, so they
+            // are not reported here (which also keeps cached `PROPFIND`
+            // responses from going stale when locks come & go)
+            Property::LockDiscovery => Some(PropValue::Empty),
+            Property::SupportedLock => Some(PropValue::SupportedLock),
+            Property::AggregateSize => self.aggregate_size().map(Into::into),
+            Property::EntryCount => self.entry_count()?.map(Into::into),
+            Property::AnnexKey => self.annex_key().map(Into::into),
+            Property::Permalink => self.permalink().map(|href| href.as_ref().to_owned().into()),
+            Property::S3VersionId => self.s3_version_id().map(Into::into),
+            Property::DandisetId => self
+                .archive_ids()
+                .and_then(|ids| ids.dandiset_id.as_ref())
+                .map(|id| id.to_string().into()),
+            Property::VersionId => self
+                .archive_ids()
+                .and_then(|ids| ids.version_id.as_ref())
+                .map(|id| id.to_string().into()),
+            Property::AssetId => self
+                .archive_ids()
+                .and_then(|ids| ids.asset_id.clone())
+                .map(Into::into),
+            Property::AssetPath => self
+                .archive_ids()
+                .and_then(|ids| ids.asset_path.as_ref())
+                .map(|p| p.to_string().into()),
+            Property::BlobId => self
+                .archive_ids()
+                .and_then(|ids| ids.blob_id.clone())
+                .map(Into::into),
+            Property::ZarrId => self
+                .archive_ids()
+                .and_then(|ids| ids.zarr_id.clone())
+                .map(Into::into),
+            Property::DandiEtag => self
+                .digests()
+                .and_then(|d| d.dandi_etag.clone())
+                .map(Into::into),
+            Property::Sha256 => self
+                .digests()
+                .and_then(|d| d.sha256.clone())
+                .map(Into::into),
+            Property::Doi => self.doi().map(Into::into),
+            Property::ZarrNodeType => self.zarr_node_type().map(|nt| nt.to_string().into()),
+            Property::ExactName => self.exact_name().map(Into::into),
+            Property::DownloadUrl
+            | Property::Truncated
+            | Property::Continuation
+            | Property::Custom(_) => None,
+        })
+    }
+}
+
+/// Identifiers of the Archive entities that a resource corresponds to, so
+/// that clients can look the resource up via the Archive API without first
+/// mapping `dandidav` paths to Archive IDs
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(super) struct ArchiveIds {
+    /// The ID of the Dandiset that the resource belongs to
+    pub(super) dandiset_id: Option<DandisetId>,
+
+    /// The ID of the Dandiset version that the resource belongs to
+    pub(super) version_id: Option<VersionId>,
+
+    /// The ID of the asset that the resource is
+    pub(super) asset_id: Option<String>,
+
+    /// The path of the asset within its Dandiset version
+    pub(super) asset_path: Option<PurePath>,
+
+    /// The ID of the blob underlying the asset, if it is a blob asset
+    pub(super) blob_id: Option<String>,
+
+    /// The ID of the Zarr underlying the asset, if it is a Zarr asset
+    pub(super) zarr_id: Option<String>,
+}
+
+impl ArchiveIds {
+    /// Return the identifiers for a blob asset
+    fn blob(blob: &BlobAsset) -> ArchiveIds {
+        ArchiveIds {
+            asset_id: Some(blob.asset_id.clone()),
+            asset_path: Some(blob.path.clone()),
+            blob_id: Some(blob.blob_id.clone()),
+            ..ArchiveIds::default()
+        }
+    }
+
+    /// Return the identifiers for a Zarr asset
+    fn zarr(zarr: &ZarrAsset) -> ArchiveIds {
+        ArchiveIds {
+            asset_id: Some(zarr.asset_id.clone()),
+            asset_path: Some(zarr.path.clone()),
+            zarr_id: Some(zarr.zarr_id.clone()),
+            ..ArchiveIds::default()
         }
     }
+
+    /// Return the identifiers for a Dandiset version
+    fn version(dandiset_id: &DandisetId, version_id: &VersionId) -> ArchiveIds {
+        ArchiveIds {
+            dandiset_id: Some(dandiset_id.clone()),
+            version_id: Some(version_id.clone()),
+            ..ArchiveIds::default()
+        }
+    }
+}
+
+/// Digests of a resource's content as computed by the Archive, reported via
+/// `dandidav`-specific properties so that clients can verify downloads
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(super) struct Digests {
+    /// The DANDI ETag of the content, if it is a blob asset
+    pub(super) dandi_etag: Option<String>,
+
+    /// The SHA-256 digest of the content, if it is a blob asset whose digest
+    /// has been computed
+    pub(super) sha256: Option<String>,
+}
+
+impl Digests {
+    /// Return the digests of a blob asset
+    fn blob(blob: &BlobAsset) -> Digests {
+        Digests {
+            dandi_etag: blob.etag().map(String::from),
+            sha256: blob.sha256().map(String::from),
+        }
+    }
+}
+
+/// Error returned when the value of a WebDAV property that is defined for a
+/// resource could not be determined
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub(super) enum PropertyError {
+    #[error("could not determine Zarr entry count")]
+    ZarrEntryCount(#[source] BadZarrChecksum),
 }
 
 /// Information about a WebDAV resource, not including child resources
@@ -78,6 +295,7 @@ pub(super) trait HasProperties {
 pub(super) enum DavResource {
     Collection(DavCollection),
     Item(DavItem),
+    Unavailable(DavUnavailable),
 }
 
 impl DavResource {
@@ -88,23 +306,39 @@ impl DavResource {
     }
 
     /// Prefix the resource's path with the path at which `dandidav` serves the
-    /// given Dandiset & version under `/dandisets/`.
+    /// given Dandiset & version under `/dandisets/`, and record the Dandiset
+    /// ID and `version_id` (the ID of the version, which differs from
+    /// `version` when the latter is `latest`) in its Archive identifiers.
     ///
     /// See [`version_path()`] for more information.
     pub(super) fn under_version_path(
         self,
         dandiset_id: &DandisetId,
         version: &VersionSpec,
+        version_id: &VersionId,
     ) -> DavResource {
         match self {
             DavResource::Collection(col) => {
-                DavResource::Collection(col.under_version_path(dandiset_id, version))
+                DavResource::Collection(col.under_version_path(dandiset_id, version, version_id))
             }
             DavResource::Item(item) => {
-                DavResource::Item(item.under_version_path(dandiset_id, version))
+                DavResource::Item(item.under_version_path(dandiset_id, version, version_id))
+            }
+            DavResource::Unavailable(r) => {
+                DavResource::Unavailable(r.under_version_path(dandiset_id, version))
             }
         }
     }
+
+    /// If the resource has a permalink, serve it at that path instead of its
+    /// path-based path
+    pub(super) fn at_permalink(self) -> DavResource {
+        match self {
+            DavResource::Collection(col) => DavResource::Collection(col.at_permalink()),
+            DavResource::Item(item) => DavResource::Item(item.at_permalink()),
+            r @ DavResource::Unavailable(_) => r,
+        }
+    }
 }
 
 impl From<DandiResource> for DavResource {
@@ -115,6 +349,7 @@ impl From<DandiResource> for DavResource {
             DandiResource::Asset(Asset::Zarr(zarr)) => DavResource::Collection(zarr.into()),
             DandiResource::ZarrFolder(folder) => DavResource::Collection(folder.into()),
             DandiResource::ZarrEntry(entry) => DavResource::Item(entry.into()),
+            DandiResource::Unavailable(r) => DavResource::Unavailable(r.into()),
         }
     }
 }
@@ -126,12 +361,14 @@ impl From<ZarrManResource> for DavResource {
             ZarrManResource::Manifest(folder) => DavResource::Collection(folder.into()),
             ZarrManResource::ManFolder(folder) => DavResource::Collection(folder.into()),
             ZarrManResource::ManEntry(entry) => DavResource::Item(entry.into()),
+            ZarrManResource::ZarrInfo(info) => DavResource::Item(info.into()),
         }
     }
 }
 
 /// Information about a WebDAV resource and its immediate child resources (if
 /// any)
+#[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(super) enum DavResourceWithChildren {
     Collection {
@@ -146,39 +383,58 @@ pub(super) enum DavResourceWithChildren {
 
 impl DavResourceWithChildren {
     /// Construct a `DavResourceWithChildren` representing the root of the
-    /// hierarchy served by `dandidav`
-    pub(super) fn root() -> Self {
+    /// hierarchy served by `dandidav`, listing the given top-level
+    /// collections
+    pub(super) fn root(mounts: &[Mount]) -> Self {
         DavResourceWithChildren::Collection {
             col: DavCollection::root(),
-            children: vec![
-                DavResource::Collection(DavCollection::dandiset_index()),
-                DavResource::Collection(DavCollection::zarr_index()),
-            ],
+            children: mounts
+                .iter()
+                .map(|&m| DavResource::Collection(mount_collection(m)))
+                .collect(),
         }
     }
 
     /// Prefix the paths of the resource and its child resources with the path
     /// at which `dandidav` serves the given Dandiset & version under
-    /// `/dandisets/`.
+    /// `/dandisets/`, and record the Dandiset ID and `version_id` in their
+    /// Archive identifiers.
     ///
     /// See [`version_path()`] for more information.
     pub(super) fn under_version_path(
         self,
         dandiset_id: &DandisetId,
         version: &VersionSpec,
+        version_id: &VersionId,
     ) -> DavResourceWithChildren {
         match self {
             DavResourceWithChildren::Collection { col, children } => {
                 DavResourceWithChildren::Collection {
-                    col: col.under_version_path(dandiset_id, version),
+                    col: col.under_version_path(dandiset_id, version, version_id),
                     children: children
                         .into_iter()
-                        .map(|r| r.under_version_path(dandiset_id, version))
+                        .map(|r| r.under_version_path(dandiset_id, version, version_id))
                         .collect(),
                 }
             }
+            DavResourceWithChildren::Item(item) => DavResourceWithChildren::Item(
+                item.under_version_path(dandiset_id, version, version_id),
+            ),
+        }
+    }
+
+    /// If the resource has a permalink, serve it at that path instead of its
+    /// path-based path.  The paths of the child resources are left as-is.
+    pub(super) fn at_permalink(self) -> DavResourceWithChildren {
+        match self {
+            DavResourceWithChildren::Collection { col, children } => {
+                DavResourceWithChildren::Collection {
+                    col: col.at_permalink(),
+                    children,
+                }
+            }
             DavResourceWithChildren::Item(item) => {
-                DavResourceWithChildren::Item(item.under_version_path(dandiset_id, version))
+                DavResourceWithChildren::Item(item.at_permalink())
             }
         }
     }
@@ -250,6 +506,7 @@ impl From<ZarrManResourceWithChildren> for DavResourceWithChildren {
                 children: map_children(children),
             },
             ManEntry(entry) => DavResourceWithChildren::Item(entry.into()),
+            ZarrInfo(info) => DavResourceWithChildren::Item(info.into()),
         }
     }
 }
@@ -277,12 +534,38 @@ pub(super) struct DavCollection {
     /// non-collection resources within the collection.
     pub(super) size: Option<i64>,
 
+    /// The number of non-collection resources within the collection, when
+    /// known.  This is currently only set for Zarrs.  If the resource is a
+    /// Zarr whose entry count could not be determined, this is an error.
+    pub(super) entry_count: Result<Option<u64>, PropertyError>,
+
+    /// The path at which the resource is also served by asset ID, if it is an
+    /// asset
+    pub(super) permalink: Option<PurePath>,
+
+    /// Identifiers of the Archive entities that the resource corresponds to
+    pub(super) archive_ids: ArchiveIds,
+
+    /// The details needed to cite the resource, if it is a Dandiset version
+    /// whose metadata has been retrieved
+    pub(super) citation: VersionCitation,
+
     /// The type of resource, for display in the "Type" column of HTML tables
     pub(super) kind: ResourceKind,
 
     /// A URL for retrieving the resource's associated metadata (if any) from
     /// the Archive instance
     pub(super) metadata_url: Option<HttpUrl>,
+
+    /// Whether the collection is a Zarr group or array, if it is a folder in
+    /// a Zarr served under `/zarrs/` whose node type could be determined
+    pub(super) zarr_node_type: Option<ZarrNodeType>,
+
+    /// A more descriptive name to show for the collection in place of the
+    /// base name of its path, if any.  This only affects the "displayname"
+    /// property and the text of links in HTML views; the collection is still
+    /// served at (and breadcrumbs are still built from) `path`.
+    pub(super) display_name: Option<String>,
 }
 
 impl DavCollection {
@@ -295,21 +578,26 @@ impl DavCollection {
     /// collection as an absolute URL path (including leading slash)
     pub(super) fn web_link(&self) -> Href {
         match self.path {
-            Some(ref p) => Href::from_path(&format!("/{p}")),
+            Some(ref p) => Href::from_path(&format!("/{}", escape_path(p))),
             None => Href::from_path("/"),
         }
     }
 
     /// Prefix the resource's path with the path at which `dandidav` serves the
-    /// given Dandiset & version under `/dandisets/`.
+    /// given Dandiset & version under `/dandisets/`, and record the Dandiset
+    /// ID and `version_id` in its Archive identifiers.
     ///
     /// See [`version_path()`] for more information.
     pub(super) fn under_version_path(
         mut self,
         dandiset_id: &DandisetId,
         version: &VersionSpec,
+        version_id: &VersionId,
     ) -> DavCollection {
         let vpath = version_path(dandiset_id, version);
+        self.permalink = self.permalink.map(|p| vpath.join(&p));
+        self.archive_ids.dandiset_id = Some(dandiset_id.clone());
+        self.archive_ids.version_id = Some(version_id.clone());
         let path = match self.path {
             Some(p) => vpath.join_dir(&p),
             None => vpath,
@@ -318,6 +606,15 @@ impl DavCollection {
         self
     }
 
+    /// If the collection has a permalink, serve it at that path instead of its
+    /// path-based path
+    pub(super) fn at_permalink(mut self) -> DavCollection {
+        if let Some(ref p) = self.permalink {
+            self.path = Some(p.to_dir_path());
+        }
+        self
+    }
+
     /// Construct a `DavCollection` representing the root of the hierarchy
     /// served by `dandidav`
     pub(super) fn root() -> Self {
@@ -326,8 +623,14 @@ impl DavCollection {
             created: None,
             modified: None,
             size: None,
+            entry_count: Ok(None),
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            citation: VersionCitation::default(),
             kind: ResourceKind::Root,
             metadata_url: None,
+            zarr_node_type: None,
+            display_name: None,
         }
     }
 
@@ -335,16 +638,18 @@ impl DavCollection {
     /// `/dandisets/`
     pub(super) fn dandiset_index() -> Self {
         DavCollection {
-            path: Some(
-                "dandisets/"
-                    .parse::<PureDirPath>()
-                    .expect(r#""dandisets/" should be a valid dir path"#),
-            ),
+            path: DavPath::DandisetIndex.to_dir_path(),
             created: None,
             modified: None,
             size: None,
+            entry_count: Ok(None),
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            citation: VersionCitation::default(),
             kind: ResourceKind::DandisetIndex,
             metadata_url: None,
+            zarr_node_type: None,
+            display_name: None,
         }
     }
 
@@ -352,45 +657,76 @@ impl DavCollection {
     /// Dandiset's published versions at `/dandiset/{dandiset_id}/releases/`
     pub(super) fn dandiset_releases(dandiset_id: &DandisetId) -> Self {
         DavCollection {
-            path: Some(
-                PureDirPath::try_from(format!("dandisets/{dandiset_id}/releases/"))
-                    .expect("should be a valid dir path"),
-            ),
+            path: DavPath::DandisetReleases {
+                dandiset_id: dandiset_id.clone(),
+            }
+            .to_dir_path(),
             created: None,
             modified: None,
             size: None,
+            entry_count: Ok(None),
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            citation: VersionCitation::default(),
             kind: ResourceKind::DandisetReleases,
             metadata_url: None,
+            zarr_node_type: None,
+            display_name: None,
         }
     }
 
-    /// Construct a `DavCollection` representing the Dandiset version `v`
-    /// as served at path `path`
-    pub(super) fn dandiset_version(v: DandisetVersion, path: PureDirPath) -> Self {
+    /// Construct a `DavCollection` representing the version `v` of the
+    /// Dandiset with ID `dandiset_id` as served at path `path`
+    pub(super) fn dandiset_version(
+        dandiset_id: &DandisetId,
+        v: DandisetVersion,
+        path: PureDirPath,
+    ) -> Self {
+        let display_name = version_display_name(&path, &v);
         DavCollection {
             path: Some(path),
             created: Some(v.created),
             modified: Some(v.modified),
             size: Some(v.size),
+            entry_count: Ok(None),
+            permalink: None,
+            archive_ids: ArchiveIds::version(dandiset_id, &v.version),
+            citation: VersionCitation::default(),
             kind: ResourceKind::Version,
             metadata_url: Some(v.metadata_url),
+            zarr_node_type: None,
+            display_name: Some(display_name),
         }
     }
 
+    /// Attach the given citation details to a Dandiset version collection
+    pub(super) fn with_citation(mut self, citation: VersionCitation) -> Self {
+        self.citation = citation;
+        self
+    }
+
+    /// Return the citation details to display in the collection's HTML view,
+    /// if any are known
+    pub(super) fn displayed_citation(&self) -> Option<VersionCitation> {
+        (!self.citation.is_empty()).then(|| self.citation.clone())
+    }
+
     /// Construct a `DavCollection` representing the top of the Zarr manifest
     /// tree at `/zarrs/`
     pub(super) fn zarr_index() -> Self {
         DavCollection {
-            path: Some(
-                "zarrs/"
-                    .parse::<PureDirPath>()
-                    .expect(r#""zarrs/" should be a valid dir path"#),
-            ),
+            path: DavPath::ZarrIndex.to_dir_path(),
             created: None,
             modified: None,
             size: None,
+            entry_count: Ok(None),
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            citation: VersionCitation::default(),
             kind: ResourceKind::ZarrIndex,
             metadata_url: None,
+            zarr_node_type: None,
+            display_name: None,
         }
     }
 }
@@ -405,7 +741,10 @@ impl HasProperties for DavCollection {
     }
 
     fn displayname(&self) -> Option<String> {
-        self.name().map(String::from)
+        self.display_name
+            .as_deref()
+            .or_else(|| self.name())
+            .map(|name| escape_name(name).into_owned())
     }
 
     fn getcontentlength(&self) -> Option<i64> {
@@ -427,33 +766,88 @@ impl HasProperties for DavCollection {
     fn is_collection(&self) -> bool {
         true
     }
+
+    fn aggregate_size(&self) -> Option<i64> {
+        self.size
+    }
+
+    fn entry_count(&self) -> Result<Option<u64>, PropertyError> {
+        self.entry_count.clone()
+    }
+
+    fn annex_key(&self) -> Option<String> {
+        None
+    }
+
+    fn permalink(&self) -> Option<Href> {
+        self.permalink
+            .as_ref()
+            .map(|p| Href::from_path(&format!("/{p}")))
+    }
+
+    fn s3_version_id(&self) -> Option<String> {
+        None
+    }
+
+    fn archive_ids(&self) -> Option<&ArchiveIds> {
+        Some(&self.archive_ids)
+    }
+
+    fn doi(&self) -> Option<String> {
+        self.citation.doi.clone()
+    }
+
+    fn zarr_node_type(&self) -> Option<ZarrNodeType> {
+        self.zarr_node_type
+    }
+
+    fn exact_name(&self) -> Option<String> {
+        self.name()
+            .filter(|&name| needs_escape(name))
+            .map(String::from)
+    }
 }
 
 impl From<Dandiset> for DavCollection {
     fn from(ds: Dandiset) -> DavCollection {
         DavCollection {
-            path: Some(
-                PureDirPath::try_from(format!("dandisets/{}/", ds.identifier))
-                    .expect("should be a valid dir path"),
-            ),
+            path: DavPath::Dandiset {
+                dandiset_id: ds.identifier.clone(),
+            }
+            .to_dir_path(),
             created: Some(ds.created),
             modified: Some(ds.modified),
             size: None,
+            entry_count: Ok(None),
+            permalink: None,
+            archive_ids: ArchiveIds {
+                dandiset_id: Some(ds.identifier),
+                ..ArchiveIds::default()
+            },
+            citation: VersionCitation::default(),
             kind: ResourceKind::Dandiset,
             metadata_url: None,
+            zarr_node_type: None,
+            display_name: None,
         }
     }
 }
 
 impl From<AssetFolder> for DavCollection {
-    fn from(AssetFolder { path }: AssetFolder) -> DavCollection {
+    fn from(AssetFolder { path, size }: AssetFolder) -> DavCollection {
         DavCollection {
             path: Some(path),
             created: None,
             modified: None,
-            size: None,
+            size,
+            entry_count: Ok(None),
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            citation: VersionCitation::default(),
             kind: ResourceKind::Directory,
             metadata_url: None,
+            zarr_node_type: None,
+            display_name: None,
         }
     }
 }
@@ -465,8 +859,14 @@ impl From<ZarrAsset> for DavCollection {
             created: Some(zarr.created),
             modified: Some(zarr.modified),
             size: Some(zarr.size),
+            entry_count: zarr.entry_count().map_err(PropertyError::ZarrEntryCount),
+            permalink: Some(asset_permalink(&zarr.asset_id)),
+            archive_ids: ArchiveIds::zarr(&zarr),
+            citation: VersionCitation::default(),
             kind: ResourceKind::Zarr,
             metadata_url: Some(zarr.metadata_url),
+            zarr_node_type: None,
+            display_name: None,
         }
     }
 }
@@ -478,51 +878,107 @@ impl From<ZarrFolder> for DavCollection {
             created: None,
             modified: None,
             size: None,
+            entry_count: Ok(None),
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            citation: VersionCitation::default(),
             kind: ResourceKind::Directory,
             metadata_url: None,
+            zarr_node_type: None,
+            display_name: None,
         }
     }
 }
 
 impl From<WebFolder> for DavCollection {
-    fn from(WebFolder { web_path }: WebFolder) -> DavCollection {
+    fn from(folder: WebFolder) -> DavCollection {
+        let display_name = folder.display_name();
         DavCollection {
-            path: Some(web_path),
+            path: Some(folder.web_path),
             created: None,
             modified: None,
             size: None,
+            entry_count: Ok(None),
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            citation: VersionCitation::default(),
             kind: ResourceKind::Directory,
             metadata_url: None,
+            zarr_node_type: None,
+            display_name,
         }
     }
 }
 
 impl From<Manifest> for DavCollection {
-    fn from(Manifest { path }: Manifest) -> DavCollection {
+    fn from(Manifest { path, node_type }: Manifest) -> DavCollection {
+        let stats = path.checksum_stats();
         DavCollection {
             path: Some(path.to_web_path()),
             created: None,
             modified: None,
-            size: None,
+            size: stats.map(|st| st.size),
+            entry_count: Ok(stats.map(|st| st.entry_count)),
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            citation: VersionCitation::default(),
             kind: ResourceKind::Zarr,
             metadata_url: None,
+            zarr_node_type: node_type,
+            display_name: None,
         }
     }
 }
 
 impl From<ManifestFolder> for DavCollection {
-    fn from(ManifestFolder { web_path }: ManifestFolder) -> DavCollection {
+    fn from(
+        ManifestFolder {
+            web_path,
+            node_type,
+        }: ManifestFolder,
+    ) -> DavCollection {
         DavCollection {
             path: Some(web_path),
             created: None,
             modified: None,
             size: None,
+            entry_count: Ok(None),
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            citation: VersionCitation::default(),
             kind: ResourceKind::Directory,
             metadata_url: None,
+            zarr_node_type: node_type,
+            display_name: None,
         }
     }
 }
 
+/// Return the name to display for the Dandiset version `v` served at `path`:
+/// the base name of `path` followed by the date on which the version was
+/// published (or, for a draft, last modified), plus the version ID itself if
+/// `path` is an alias like `latest`
+fn version_display_name(path: &PureDirPath, v: &DandisetVersion) -> String {
+    let name = path.name_str();
+    let date = match v.version {
+        VersionId::Draft => v.modified.date(),
+        VersionId::Published(_) => v.created.date(),
+    };
+    if name == v.version.as_ref() {
+        format!("{name} ({date})")
+    } else {
+        format!("{name} ({}, {date})", v.version)
+    }
+}
+
+/// Return the path, relative to a Dandiset version path, at which the asset
+/// with the given ID is served by asset ID
+fn asset_permalink(asset_id: &str) -> PurePath {
+    format!("{BY_ASSET_ID_DIR}/{asset_id}")
+        .parse::<PurePath>()
+        .expect("asset permalink should be a valid path")
+}
+
 /// Information on a non-collection resource
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(super) struct DavItem {
@@ -544,6 +1000,23 @@ pub(super) struct DavItem {
     /// The resource's ETag
     pub(super) etag: Option<String>,
 
+    /// The git-annex key for the resource's content, if it can be determined
+    pub(super) annex_key: Option<AnnexKey>,
+
+    /// The path at which the resource is also served by asset ID, if it is an
+    /// asset
+    pub(super) permalink: Option<PurePath>,
+
+    /// Identifiers of the Archive entities that the resource corresponds to
+    pub(super) archive_ids: ArchiveIds,
+
+    /// Digests of the resource's content as computed by the Archive
+    pub(super) digests: Digests,
+
+    /// The S3 version ID of the object that the resource's download URL is
+    /// pinned to, if any
+    pub(super) s3_version_id: Option<String>,
+
     /// The type of resource, for display in the "Type" column of HTML tables
     pub(super) kind: ResourceKind,
 
@@ -564,33 +1037,100 @@ impl DavItem {
     /// Return the link to use for the resource in the HTML view of its parent
     /// collection as an absolute URL path (including leading slash)
     pub(super) fn web_link(&self) -> Href {
-        if let DavContent::Redirect(ref redir) = self.content {
+        match self.content {
+            // Presigned URLs expire, so downloads from private buckets and of
+            // embargoed assets have to go through `dandidav`.
+            DavContent::Redirect(Redirect::Private { .. } | Redirect::Embargoed(_)) => {
+                Href::from_path(&format!("/{}", escape_path(&self.path)))
+            }
             // Link directly to the download URL in the web view in order to
             // save a request
-            redir.get_url(false).into()
-        } else {
-            Href::from_path(&format!("/{}", self.path))
+            DavContent::Redirect(ref redir) => redir.get_url(false).into(),
+            _ => Href::from_path(&format!("/{}", escape_path(&self.path))),
+        }
+    }
+
+    /// If the resource's S3 download URL is for an object in a private
+    /// bucket, as determined by `locate_private` returning the object's
+    /// location, mark the resource's redirect as private
+    pub(super) fn mark_private<F>(&mut self, locate_private: F)
+    where
+        F: Fn(&HttpUrl) -> Option<S3Location>,
+    {
+        if let DavContent::Redirect(ref mut redir) = self.content {
+            let url = redir.get_url(true);
+            if let Some(location) = locate_private(url) {
+                *redir = Redirect::Private {
+                    url: url.clone(),
+                    location,
+                };
+            }
+        }
+    }
+
+    /// Construct a `DavItem` for presenting a Zarr asset as a single opaque
+    /// file that redirects to the Zarr's manifest at `manifest_url`.
+    ///
+    /// As the redirect target is not the Zarr itself, the Zarr's size is not
+    /// reported as the item's size.
+    pub(super) fn collapsed_zarr(zarr: ZarrAsset, manifest_url: Option<HttpUrl>) -> DavItem {
+        let etag = zarr.checksum().map(String::from);
+        let permalink = Some(asset_permalink(&zarr.asset_id));
+        let archive_ids = ArchiveIds::zarr(&zarr);
+        let content = match manifest_url {
+            Some(url) => DavContent::Redirect(Redirect::Direct(url)),
+            None => DavContent::Missing,
+        };
+        DavItem {
+            path: zarr.path,
+            created: Some(zarr.created),
+            modified: Some(zarr.modified),
+            content_type: Some(JSON_CONTENT_TYPE.to_owned()),
+            size: None,
+            etag,
+            annex_key: None,
+            permalink,
+            archive_ids,
+            digests: Digests::default(),
+            s3_version_id: None,
+            kind: ResourceKind::Zarr,
+            content,
+            metadata_url: Some(zarr.metadata_url),
         }
     }
 
     /// Prefix the resource's path with the path at which `dandidav` serves the
-    /// given Dandiset & version under `/dandisets/`.
+    /// given Dandiset & version under `/dandisets/`, and record the Dandiset
+    /// ID and `version_id` in its Archive identifiers.
     ///
     /// See [`version_path()`] for more information.
     pub(super) fn under_version_path(
         mut self,
         dandiset_id: &DandisetId,
         version: &VersionSpec,
+        version_id: &VersionId,
     ) -> DavItem {
-        let path = version_path(dandiset_id, version).join(&self.path);
-        self.path = path;
+        let vpath = version_path(dandiset_id, version);
+        self.permalink = self.permalink.map(|p| vpath.join(&p));
+        self.archive_ids.dandiset_id = Some(dandiset_id.clone());
+        self.archive_ids.version_id = Some(version_id.clone());
+        self.path = vpath.join(&self.path);
+        self
+    }
+
+    /// If the item has a permalink, serve it at that path instead of its
+    /// path-based path
+    pub(super) fn at_permalink(mut self) -> DavItem {
+        if let Some(ref p) = self.permalink {
+            self.path = p.clone();
+        }
         self
     }
 }
 
 impl HasProperties for DavItem {
     fn href(&self) -> Href {
-        Href::from_path(&format!("/{}", self.path))
+        Href::from_path(&format!("/{}", escape_path(&self.path)))
     }
 
     fn creationdate(&self) -> Option<String> {
@@ -598,7 +1138,7 @@ impl HasProperties for DavItem {
     }
 
     fn displayname(&self) -> Option<String> {
-        Some(self.name().to_owned())
+        Some(escape_name(self.name()).into_owned())
     }
 
     fn getcontentlength(&self) -> Option<i64> {
@@ -620,12 +1160,59 @@ impl HasProperties for DavItem {
     fn is_collection(&self) -> bool {
         false
     }
+
+    fn aggregate_size(&self) -> Option<i64> {
+        None
+    }
+
+    fn entry_count(&self) -> Result<Option<u64>, PropertyError> {
+        Ok(None)
+    }
+
+    fn annex_key(&self) -> Option<String> {
+        self.annex_key.as_ref().map(ToString::to_string)
+    }
+
+    fn permalink(&self) -> Option<Href> {
+        self.permalink
+            .as_ref()
+            .map(|p| Href::from_path(&format!("/{p}")))
+    }
+
+    fn s3_version_id(&self) -> Option<String> {
+        self.s3_version_id.clone()
+    }
+
+    fn download_url(&self, prefer_s3: bool, s3_content_disposition: bool) -> Option<HttpUrl> {
+        match self.content {
+            // Presigned URLs are generated anew for each request and expire,
+            // so they are not reported.
+            DavContent::Redirect(Redirect::Private { .. } | Redirect::Embargoed(_)) => None,
+            DavContent::Redirect(ref redir) => {
+                Some(redir.target_url(self.name(), prefer_s3, s3_content_disposition))
+            }
+            _ => None,
+        }
+    }
+
+    fn archive_ids(&self) -> Option<&ArchiveIds> {
+        Some(&self.archive_ids)
+    }
+
+    fn digests(&self) -> Option<&Digests> {
+        Some(&self.digests)
+    }
+
+    fn exact_name(&self) -> Option<String> {
+        let name = self.name();
+        needs_escape(name).then(|| name.to_owned())
+    }
 }
 
 impl From<VersionMetadata> for DavItem {
     fn from(value: VersionMetadata) -> DavItem {
         let len = value.len();
-        let blob = Vec::<u8>::from(value);
+        let blob = Bytes::from(value);
         DavItem {
             path: "dandiset.yaml"
                 .parse::<PurePath>()
@@ -635,6 +1222,11 @@ impl From<VersionMetadata> for DavItem {
             content_type: Some(YAML_CONTENT_TYPE.to_owned()),
             size: i64::try_from(len).ok(),
             etag: None,
+            annex_key: None,
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            digests: Digests::default(),
+            s3_version_id: None,
             kind: ResourceKind::VersionMetadata,
             content: DavContent::Blob(blob),
             metadata_url: None,
@@ -650,7 +1242,17 @@ impl From<BlobAsset> for DavItem {
             .unwrap_or(DEFAULT_CONTENT_TYPE)
             .to_owned();
         let etag = blob.etag().map(String::from);
+        let annex_key = blob
+            .sha256()
+            .and_then(|digest| AnnexKey::sha256(blob.size, digest));
+        let archive_ids = ArchiveIds::blob(&blob);
+        let digests = Digests::blob(&blob);
         let content = match (blob.archive_url(), blob.s3_url()) {
+            // Embargoed blobs can only be downloaded with authorization, so
+            // their S3 URLs are of no use to clients.
+            (Some(archive), _) if blob.is_embargoed() => {
+                DavContent::Redirect(Redirect::Embargoed(archive.clone()))
+            }
             (Some(archive), Some(s3)) => DavContent::Redirect(Redirect::Alt {
                 s3: s3.clone(),
                 archive: archive.clone(),
@@ -666,6 +1268,11 @@ impl From<BlobAsset> for DavItem {
             content_type: Some(content_type),
             size: Some(blob.size),
             etag,
+            annex_key,
+            permalink: Some(asset_permalink(&blob.asset_id)),
+            archive_ids,
+            digests,
+            s3_version_id: None,
             kind: ResourceKind::Blob,
             content,
             metadata_url: Some(blob.metadata_url),
@@ -675,6 +1282,7 @@ impl From<BlobAsset> for DavItem {
 
 impl From<ZarrEntry> for DavItem {
     fn from(entry: ZarrEntry) -> DavItem {
+        let annex_key = AnnexKey::md5_from_etag(entry.size, &entry.etag);
         DavItem {
             path: entry.zarr_path.to_dir_path().join(&entry.path),
             created: None,
@@ -682,6 +1290,11 @@ impl From<ZarrEntry> for DavItem {
             content_type: None,
             size: Some(entry.size),
             etag: Some(entry.etag),
+            annex_key,
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            digests: Digests::default(),
+            s3_version_id: None,
             kind: ResourceKind::ZarrEntry,
             content: DavContent::Redirect(Redirect::Direct(entry.url)),
             metadata_url: None,
@@ -691,6 +1304,7 @@ impl From<ZarrEntry> for DavItem {
 
 impl From<ManifestEntry> for DavItem {
     fn from(entry: ManifestEntry) -> DavItem {
+        let annex_key = AnnexKey::md5_from_etag(entry.size, &entry.etag);
         DavItem {
             path: entry.web_path,
             created: None,
@@ -698,6 +1312,11 @@ impl From<ManifestEntry> for DavItem {
             content_type: None,
             size: Some(entry.size),
             etag: Some(entry.etag),
+            annex_key,
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            digests: Digests::default(),
+            s3_version_id: Some(entry.version_id),
             kind: ResourceKind::ZarrEntry,
             content: DavContent::Redirect(Redirect::Direct(entry.url)),
             metadata_url: None,
@@ -705,6 +1324,124 @@ impl From<ManifestEntry> for DavItem {
     }
 }
 
+impl From<ZarrInfo> for DavItem {
+    fn from(info: ZarrInfo) -> DavItem {
+        let mut blob = serde_json::to_vec_pretty(&info).expect("ZarrInfo should serialize");
+        blob.push(b'\n');
+        DavItem {
+            path: info.web_path,
+            created: None,
+            modified: info.last_modified,
+            content_type: Some(JSON_CONTENT_TYPE.to_owned()),
+            size: i64::try_from(blob.len()).ok(),
+            etag: None,
+            annex_key: None,
+            permalink: None,
+            archive_ids: ArchiveIds::default(),
+            digests: Digests::default(),
+            s3_version_id: None,
+            kind: ResourceKind::ZarrInfo,
+            content: DavContent::Blob(Bytes::from(blob)),
+            metadata_url: None,
+        }
+    }
+}
+
+/// Information on a child resource whose details could not be retrieved
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct DavUnavailable {
+    /// The path at which the resource is served by `dandidav`
+    pub(super) path: PurePath,
+
+    /// The type of error that prevented the resource's details from being
+    /// retrieved
+    pub(super) class: ErrorClass,
+}
+
+impl DavUnavailable {
+    /// Return the base name of the resource's path
+    pub(super) fn name(&self) -> &str {
+        self.path.name_str()
+    }
+
+    /// Prefix the resource's path with the path at which `dandidav` serves the
+    /// given Dandiset & version under `/dandisets/`.
+    ///
+    /// See [`version_path()`] for more information.
+    pub(super) fn under_version_path(
+        mut self,
+        dandiset_id: &DandisetId,
+        version: &VersionSpec,
+    ) -> DavUnavailable {
+        self.path = version_path(dandiset_id, version).join(&self.path);
+        self
+    }
+}
+
+impl HasProperties for DavUnavailable {
+    fn href(&self) -> Href {
+        Href::from_path(&format!("/{}", escape_path(&self.path)))
+    }
+
+    fn creationdate(&self) -> Option<String> {
+        None
+    }
+
+    fn displayname(&self) -> Option<String> {
+        None
+    }
+
+    fn getcontentlength(&self) -> Option<i64> {
+        None
+    }
+
+    fn getcontenttype(&self) -> Option<String> {
+        None
+    }
+
+    fn getetag(&self) -> Option<String> {
+        None
+    }
+
+    fn getlastmodified(&self) -> Option<String> {
+        None
+    }
+
+    fn is_collection(&self) -> bool {
+        false
+    }
+
+    fn aggregate_size(&self) -> Option<i64> {
+        None
+    }
+
+    fn entry_count(&self) -> Result<Option<u64>, PropertyError> {
+        Ok(None)
+    }
+
+    fn annex_key(&self) -> Option<String> {
+        None
+    }
+
+    fn permalink(&self) -> Option<Href> {
+        None
+    }
+
+    fn s3_version_id(&self) -> Option<String> {
+        None
+    }
+
+    fn status(&self) -> Option<StatusCode> {
+        Some(self.class.to_status())
+    }
+}
+
+impl From<UnavailableResource> for DavUnavailable {
+    fn from(UnavailableResource { path, class }: UnavailableResource) -> DavUnavailable {
+        DavUnavailable { path, class }
+    }
+}
+
 /// The content of a non-collection resource or a link thereto
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(super) enum DavContent {
@@ -713,7 +1450,7 @@ pub(super) enum DavContent {
     ///
     /// This is only used for `dandiset.yaml` resources, for which the content
     /// is automatically generated by `dandidav`.
-    Blob(Vec<u8>),
+    Blob(Bytes),
 
     /// A URL that `dandidav` should redirect to when a `GET` request is made
     /// for the resource
@@ -732,6 +1469,17 @@ pub(super) enum Redirect {
     /// An S3 URL and an Archive instance URL, to be selected between based on
     /// whether `--prefer-s3-redirects` was supplied at program invocation
     Alt { s3: HttpUrl, archive: HttpUrl },
+
+    /// The URL of an object in a private S3 bucket.  Requests are redirected
+    /// to a presigned URL for the object, and HTML views link to the resource
+    /// via `dandidav` rather than directly.
+    Private { url: HttpUrl, location: S3Location },
+
+    /// The Archive download URL of an embargoed asset.  Requests are
+    /// redirected to the presigned URL that the Archive redirects
+    /// authenticated requests for the download URL to, and HTML views link to
+    /// the resource via `dandidav` rather than directly.
+    Embargoed(HttpUrl),
 }
 
 impl Redirect {
@@ -741,7 +1489,7 @@ impl Redirect {
     /// otherwise, they resolve to their `archive` field.
     pub(super) fn get_url(&self, prefer_s3: bool) -> &HttpUrl {
         match self {
-            Redirect::Direct(u) => u,
+            Redirect::Direct(u) | Redirect::Private { url: u, .. } | Redirect::Embargoed(u) => u,
             Redirect::Alt { s3, archive } => {
                 if prefer_s3 {
                     s3
@@ -751,6 +1499,26 @@ impl Redirect {
             }
         }
     }
+
+    /// Return the URL that a `GET` request for a resource named `name` is
+    /// redirected to.  `prefer_s3` is as for [`Redirect::get_url()`]; if
+    /// `s3_content_disposition` is also `true`, S3 URLs chosen from `Alt`
+    /// variants are given a `response-content-disposition` query parameter
+    /// naming `name`.
+    ///
+    /// `Private` and `Embargoed` variants resolve to their unsigned URL.
+    pub(super) fn target_url(
+        &self,
+        name: &str,
+        prefer_s3: bool,
+        s3_content_disposition: bool,
+    ) -> HttpUrl {
+        let mut target = self.get_url(prefer_s3).clone();
+        if prefer_s3 && s3_content_disposition && matches!(self, Redirect::Alt { .. }) {
+            target.append_query_param("response-content-disposition", &content_disposition(name));
+        }
+        target
+    }
 }
 
 /// An enumeration of resource types for use in the "Type" column of HTML views
@@ -790,8 +1558,14 @@ pub(super) enum ResourceKind {
     /// A Zarr entry
     ZarrEntry,
 
+    /// The virtual `.zarr-info` file summarizing a Zarr under `/zarrs/`
+    ZarrInfo,
+
     /// The top of the Zarr manifest tree at `/zarrs/`
     ZarrIndex,
+
+    /// A resource whose details could not be retrieved
+    Unavailable,
 }
 
 impl ResourceKind {
@@ -809,9 +1583,23 @@ impl ResourceKind {
             ResourceKind::Blob => "Blob asset",
             ResourceKind::Zarr => "Zarr asset",
             ResourceKind::ZarrEntry => "Zarr entry",
+            ResourceKind::ZarrInfo => "Zarr summary",
             ResourceKind::ZarrIndex => "Zarrs",
+            ResourceKind::Unavailable => "Unavailable",
         }
     }
+
+    /// Return whether resources of this kind within a Dandiset version can be
+    /// included in an export manifest of the version
+    pub(super) fn is_exportable(self) -> bool {
+        matches!(
+            self,
+            ResourceKind::VersionMetadata
+                | ResourceKind::Directory
+                | ResourceKind::Blob
+                | ResourceKind::Zarr
+        )
+    }
 }
 
 impl Serialize for ResourceKind {
@@ -1,29 +1,55 @@
+use super::i18n::{Language, RequestedLanguage};
+use super::listing::ListingFormat;
+use super::notfound::NotFound;
 use super::path::{split_uri_path, DavPath};
-use super::xml::PropFind;
+use super::rewrite::rewrite;
+use super::xml::{LockDepth, LockInfo, PropFind};
 use super::VersionSpec;
-use crate::consts::DAV_XML_CONTENT_TYPE;
+use crate::consts::{
+    DAV_XML_CONTENT_TYPE, EXPORT_MAX_PATHS, YAML_BODY_CHUNK_SIZE, ZARR_MODE_HEADER,
+};
 use crate::dandi::DandisetId;
 use crate::httputil::HttpUrl;
-use crate::paths::{Component, PureDirPath};
+use crate::paths::{Component, PureDirPath, PurePath};
+use crate::zarrchecksum::ZarrChecksumStats;
 use axum::{
     body::Body,
     extract::{FromRequest, FromRequestParts, Request},
-    http::{header::CONTENT_TYPE, request::Parts, response::Response, Method, StatusCode},
+    http::{
+        header::{
+            HeaderName, HeaderValue, ACCEPT, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE,
+            CONTENT_TYPE, RANGE,
+        },
+        request::Parts,
+        response::Response,
+        Method, StatusCode,
+    },
     response::IntoResponse,
     RequestExt,
 };
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
 use indoc::indoc;
 use percent_encoding::{percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use serde::{ser::Serializer, Serialize};
-use std::fmt::{self, Write};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::num::NonZeroUsize;
 use time::{
     format_description::{well_known::Rfc3339, FormatItem},
     macros::format_description,
-    OffsetDateTime,
+    OffsetDateTime, PrimitiveDateTime,
 };
+use url::form_urlencoded;
+
+/// The `Repr-Digest` header defined by RFC 9530
+static REPR_DIGEST: HeaderName = HeaderName::from_static("repr-digest");
+
+/// The legacy `Digest` header defined by RFC 3230
+static DIGEST: HeaderName = HeaderName::from_static("digest");
 
 /// Timestamp format for display of the "getlastmodified" property in WebDAV
-/// XML documents
+/// XML documents and for `Last-Modified` & `If-Modified-Since` headers
 static RFC1123: &[FormatItem<'_>] = format_description!(
     "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT"
 );
@@ -39,8 +65,25 @@ static PERCENT_ESCAPED: &AsciiSet = &NON_ALPHANUMERIC
     .remove(b'_')
     .remove(b'~');
 
-/// Response body to return in reply to `PROPFIND` requests with missing or
-/// "infinite" `Depth` headers
+/// ASCII bytes to percent-encode in `filename*` parameters of
+/// `Content-Disposition` values, i.e., everything other than RFC 8187's
+/// `attr-char`
+static ATTR_CHAR_ESCAPED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
+
+/// Response body to return in reply to `PROPFIND` requests whose depth
+/// (explicit or defaulted) exceeds the maximum permitted depth
 static INFINITE_DEPTH_RESPONSE: &str = indoc! {r#"
 <?xml version="1.0" encoding="utf-8"?>
 <error xmlns="DAV:">
@@ -48,6 +91,15 @@ static INFINITE_DEPTH_RESPONSE: &str = indoc! {r#"
 </error>
 "#};
 
+/// Response body to return in reply to `UNLOCK` requests whose lock tokens
+/// do not identify locks on the requested resources
+static LOCK_TOKEN_MISMATCH_RESPONSE: &str = indoc! {r#"
+<?xml version="1.0" encoding="utf-8"?>
+<error xmlns="DAV:">
+    <lock-token-matches-request-uri />
+</error>
+"#};
+
 /// Return the path at which `dandidav` serves the given Dandiset & version
 /// under `/dandisets/`.
 ///
@@ -57,20 +109,12 @@ static INFINITE_DEPTH_RESPONSE: &str = indoc! {r#"
 /// - `dandiset/{dandiset_id}/latest/`
 /// - `dandiset/{dandiset_id}/releases/{version_id}/`
 pub(super) fn version_path(dandiset_id: &DandisetId, version: &VersionSpec) -> PureDirPath {
-    fn writer(s: &mut String, dandiset_id: &DandisetId, version: &VersionSpec) -> fmt::Result {
-        write!(s, "dandisets/{dandiset_id}/")?;
-        match version {
-            VersionSpec::Draft => write!(s, "draft")?,
-            VersionSpec::Published(v) => write!(s, "releases/{v}")?,
-            VersionSpec::Latest => write!(s, "latest")?,
-        }
-        write!(s, "/")?;
-        Ok(())
+    DavPath::Version {
+        dandiset_id: dandiset_id.clone(),
+        version: version.clone(),
     }
-
-    let mut s = String::new();
-    writer(&mut s, dandiset_id, version).expect("writing to a String shouldn't fail");
-    PureDirPath::try_from(s).expect("should be a valid dir path")
+    .to_dir_path()
+    .expect("version path should be nonempty")
 }
 
 /// Format a timestamp for display as a "creationdate" property in a WebDAV XML
@@ -100,6 +144,28 @@ pub(super) enum DavRequest {
         /// `path`.  This is needed for things like breadcrumbs in HTML views
         /// of collection resources.
         pathparts: Vec<Component>,
+
+        /// The Zarr mode requested by the client, if any
+        zarr_mode: Option<ZarrMode>,
+
+        /// The Zarr snapshot that the client requested to be pinned to, if
+        /// any
+        zarr_pin: Option<ZarrPin>,
+
+        /// The supported language most preferred by the client for HTML
+        /// views, if any
+        language: Option<Language>,
+
+        /// The value of the `Range` header, if any
+        range: Option<HeaderValue>,
+
+        /// The paths within a Dandiset version selected for export via
+        /// `paths` query parameters, if any
+        export_paths: Option<Vec<PurePath>>,
+
+        /// The representation requested for the resource if it is a
+        /// collection
+        listing_format: ListingFormat,
     },
 
     /// A `PROPFIND` request
@@ -107,16 +173,65 @@ pub(super) enum DavRequest {
         /// The request path
         path: DavPath,
 
-        /// The value of the `Depth` header
-        depth: FiniteDepth,
+        /// The value of the `Depth` header, or `None` if it was absent
+        depth: Option<Depth>,
 
         /// The parsed request body.  (Empty bodies are defaulted to "allprop"
         /// during parsing as per the RFC.)
         query: PropFind,
+
+        /// The Zarr mode requested by the client, if any
+        zarr_mode: Option<ZarrMode>,
+
+        /// The Zarr snapshot that the client requested to be pinned to, if
+        /// any
+        zarr_pin: Option<ZarrPin>,
+    },
+
+    /// A `LOCK` request
+    Lock {
+        /// The request path
+        path: DavPath,
+
+        /// The value of the `Depth` header, which defaults to infinity
+        depth: LockDepth,
+
+        /// The parsed request body, or `None` if the body was empty, in which
+        /// case the request is to refresh an existing lock
+        info: Option<LockInfo>,
+
+        /// The lock token submitted in the `If` header, if any
+        if_token: Option<String>,
+    },
+
+    /// An `UNLOCK` request
+    Unlock {
+        /// The request path
+        path: DavPath,
+
+        /// The lock token given in the `Lock-Token` header
+        token: String,
     },
 
     /// An `OPTIONS` request
     Options,
+
+    /// A `GET` request for a friendly URL form that is redirected to the
+    /// given canonical path (see [`rewrite()`])
+    Redirect { location: String },
+}
+
+impl DavRequest {
+    /// Return the request path, if the request has one
+    pub(super) fn path(&self) -> Option<&DavPath> {
+        match self {
+            DavRequest::Get { path, .. }
+            | DavRequest::Propfind { path, .. }
+            | DavRequest::Lock { path, .. }
+            | DavRequest::Unlock { path, .. } => Some(path),
+            DavRequest::Options | DavRequest::Redirect { .. } => None,
+        }
+    }
 }
 
 impl<S: Send + Sync> FromRequest<S> for DavRequest {
@@ -127,56 +242,407 @@ impl<S: Send + Sync> FromRequest<S> for DavRequest {
         match req.method() {
             &Method::GET => {
                 let Some(pathparts) = split_uri_path(uri_path) else {
-                    // TODO: Log something
-                    return Err(not_found());
+                    return Err(not_found(uri_path));
                 };
+                if let Some(location) = rewrite(&pathparts) {
+                    return Ok(DavRequest::Redirect { location });
+                }
                 let Some(path) = DavPath::from_components(pathparts.clone()) else {
-                    // TODO: Log something
-                    return Err(not_found());
+                    return Err(not_found(uri_path));
                 };
-                Ok(DavRequest::Get { path, pathparts })
+                let range = req.headers().get(RANGE).cloned();
+                let (
+                    RequestedZarrMode(zarr_mode),
+                    RequestedZarrPin(zarr_pin),
+                    RequestedLanguage(language),
+                    RequestedExportPaths(export_paths),
+                    RequestedListingFormat(listing_format),
+                ) = req.extract_with_state(state).await?;
+                Ok(DavRequest::Get {
+                    path,
+                    pathparts,
+                    zarr_mode,
+                    zarr_pin,
+                    language,
+                    range,
+                    export_paths,
+                    listing_format,
+                })
             }
             &Method::OPTIONS => Ok(DavRequest::Options),
             m if m.as_str().eq_ignore_ascii_case("PROPFIND") => {
                 let Some(path) = split_uri_path(uri_path).and_then(DavPath::from_components) else {
-                    // TODO: Log something
-                    return Err(not_found());
+                    return Err(not_found(uri_path));
                 };
-                let (depth, query) = req
-                    .extract_with_state::<(FiniteDepth, PropFind), _, _>(state)
+                let (
+                    RequestedDepth(depth),
+                    RequestedZarrMode(zarr_mode),
+                    RequestedZarrPin(zarr_pin),
+                    query,
+                ) = req
+                    .extract_with_state::<(
+                        RequestedDepth,
+                        RequestedZarrMode,
+                        RequestedZarrPin,
+                        PropFind,
+                    ), _, _>(state)
                     .await?;
-                Ok(DavRequest::Propfind { path, depth, query })
+                Ok(DavRequest::Propfind {
+                    path,
+                    depth,
+                    query,
+                    zarr_mode,
+                    zarr_pin,
+                })
+            }
+            m if m.as_str().eq_ignore_ascii_case("LOCK") => {
+                let Some(path) = split_uri_path(uri_path).and_then(DavPath::from_components) else {
+                    return Err(not_found(uri_path));
+                };
+                let depth = match req.headers().get("Depth").map(|v| v.to_str()) {
+                    Some(Ok("0")) => LockDepth::Zero,
+                    Some(Ok("infinity")) | None => LockDepth::Infinity,
+                    _ => {
+                        return Err(
+                            (StatusCode::BAD_REQUEST, "Invalid \"Depth\" header\n").into_response()
+                        )
+                    }
+                };
+                let if_token = req
+                    .headers()
+                    .get("If")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(parse_if_token);
+                let blob = Bytes::from_request(req, state)
+                    .await
+                    .map_err(IntoResponse::into_response)?;
+                let info = if blob.iter().all(u8::is_ascii_whitespace) {
+                    None
+                } else {
+                    match LockInfo::from_xml(blob) {
+                        Ok(info) => Some(info),
+                        Err(_) => {
+                            return Err(
+                                (StatusCode::BAD_REQUEST, "Invalid request body\n").into_response()
+                            )
+                        }
+                    }
+                };
+                Ok(DavRequest::Lock {
+                    path,
+                    depth,
+                    info,
+                    if_token,
+                })
+            }
+            m if m.as_str().eq_ignore_ascii_case("UNLOCK") => {
+                let Some(path) = split_uri_path(uri_path).and_then(DavPath::from_components) else {
+                    return Err(not_found(uri_path));
+                };
+                let Some(token) = req
+                    .headers()
+                    .get("Lock-Token")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|s| s.trim().strip_prefix('<')?.strip_suffix('>'))
+                else {
+                    return Err((
+                        StatusCode::BAD_REQUEST,
+                        "Missing or invalid \"Lock-Token\" header\n",
+                    )
+                        .into_response());
+                };
+                Ok(DavRequest::Unlock {
+                    path,
+                    token: token.to_owned(),
+                })
             }
             _ => Err(StatusCode::METHOD_NOT_ALLOWED.into_response()),
         }
     }
 }
 
-/// A non-infinite `Depth` WebDAV header value
+/// Extract the first lock token from the value of an `If` header (RFC 4918,
+/// §10.4), e.g., `(<opaquelocktoken:...>)` or
+/// `<http://example.com/foo> (<opaquelocktoken:...>)`
+fn parse_if_token(value: &str) -> Option<String> {
+    let (_, rest) = value.split_once("(<")?;
+    let (token, _) = rest.split_once('>')?;
+    Some(token.to_owned())
+}
+
+/// How Zarr assets under `/dandisets/` are presented
+#[derive(clap::ValueEnum, Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) enum ZarrMode {
+    /// Zarr assets are collections of their entries
+    #[default]
+    Expanded,
+
+    /// Zarr assets are non-collection resources that redirect to their Zarr
+    /// manifests, and their entries are not served
+    Collapsed,
+}
+
+impl ZarrMode {
+    /// Parse a Zarr mode name, case-insensitively
+    fn parse(s: &str) -> Option<ZarrMode> {
+        if s.eq_ignore_ascii_case("expanded") {
+            Some(ZarrMode::Expanded)
+        } else if s.eq_ignore_ascii_case("collapsed") {
+            Some(ZarrMode::Collapsed)
+        } else {
+            None
+        }
+    }
+}
+
+/// The Zarr mode requested for a single request, either via the
+/// `X-Dandidav-Zarr` header or the `zarr` query parameter.  If both are
+/// given, the query parameter takes precedence.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(super) struct RequestedZarrMode(pub(super) Option<ZarrMode>);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestedZarrMode {
+    type Rejection = Response<Body>;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let from_query = parts.uri.query().and_then(|q| {
+            form_urlencoded::parse(q.as_bytes())
+                .find(|(k, _)| k == "zarr")
+                .map(|(_, v)| v.into_owned())
+        });
+        let value = match from_query {
+            Some(v) => Some(v),
+            None => match parts.headers.get(ZARR_MODE_HEADER).map(|v| v.to_str()) {
+                Some(Ok(v)) => Some(v.to_owned()),
+                Some(Err(_)) => return Err(bad_zarr_mode()),
+                None => None,
+            },
+        };
+        match value {
+            Some(v) => match ZarrMode::parse(v.trim()) {
+                Some(mode) => Ok(RequestedZarrMode(Some(mode))),
+                None => Err(bad_zarr_mode()),
+            },
+            None => Ok(RequestedZarrMode(None)),
+        }
+    }
+}
+
+/// The representation requested for a collection in response to a `GET`
+/// request, either via the `format` query parameter or the `Accept` header.
+/// If both are given, the query parameter takes precedence.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub(super) enum FiniteDepth {
+pub(super) struct RequestedListingFormat(pub(super) ListingFormat);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestedListingFormat {
+    type Rejection = Response<Body>;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let from_query = parts.uri.query().and_then(|q| {
+            form_urlencoded::parse(q.as_bytes())
+                .find(|(k, _)| k == "format")
+                .map(|(_, v)| v.into_owned())
+        });
+        if let Some(v) = from_query {
+            return match ListingFormat::parse(v.trim()) {
+                Some(format) => Ok(RequestedListingFormat(format)),
+                None => Err((
+                    StatusCode::BAD_REQUEST,
+                    "Invalid listing format; must be \"html\" or \"json\"\n",
+                )
+                    .into_response()),
+            };
+        }
+        let format = parts
+            .headers
+            .get(ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .map_or_else(ListingFormat::default, ListingFormat::negotiate);
+        Ok(RequestedListingFormat(format))
+    }
+}
+
+/// Return a 400 response for a request with an invalid Zarr mode
+fn bad_zarr_mode() -> Response<Body> {
+    (
+        StatusCode::BAD_REQUEST,
+        "Invalid Zarr mode; must be \"expanded\" or \"collapsed\"\n",
+    )
+        .into_response()
+}
+
+/// A snapshot of a Zarr, as identified by the Zarr's checksum, that a request
+/// for a resource in a Zarr under `/dandisets/` is pinned to.  Such requests
+/// are redirected to the corresponding paths in the Zarr's manifest under
+/// `/zarrs/`, so that all further browsing of the Zarr sees the same
+/// snapshot.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) enum ZarrPin {
+    /// Pin to the Zarr's current checksum
+    Current,
+
+    /// Pin to the given checksum
+    Checksum(String),
+}
+
+impl ZarrPin {
+    /// Parse a `zarr-checksum` query parameter value.  An empty value or
+    /// "current" (case-insensitive) selects the Zarr's current checksum.
+    fn parse(s: &str) -> Option<ZarrPin> {
+        let s = s.trim();
+        if s.is_empty() || s.eq_ignore_ascii_case("current") {
+            Some(ZarrPin::Current)
+        } else if ZarrChecksumStats::parse(s).is_some() {
+            Some(ZarrPin::Checksum(s.to_owned()))
+        } else {
+            None
+        }
+    }
+}
+
+/// The Zarr snapshot requested for a single request via the `zarr-checksum`
+/// query parameter
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct RequestedZarrPin(pub(super) Option<ZarrPin>);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestedZarrPin {
+    type Rejection = Response<Body>;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(value) = parts.uri.query().and_then(|q| {
+            form_urlencoded::parse(q.as_bytes())
+                .find(|(k, _)| k == "zarr-checksum")
+                .map(|(_, v)| v.into_owned())
+        }) else {
+            return Ok(RequestedZarrPin(None));
+        };
+        match ZarrPin::parse(&value) {
+            Some(pin) => Ok(RequestedZarrPin(Some(pin))),
+            None => Err((StatusCode::BAD_REQUEST, "Invalid Zarr checksum\n").into_response()),
+        }
+    }
+}
+
+/// The paths within a Dandiset version selected for export via one or more
+/// `paths` query parameters.  Leading & trailing slashes are ignored.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) struct RequestedExportPaths(pub(super) Option<Vec<PurePath>>);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestedExportPaths {
+    type Rejection = Response<Body>;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(query) = parts.uri.query() else {
+            return Ok(RequestedExportPaths(None));
+        };
+        let mut paths = Vec::new();
+        for (_, v) in form_urlencoded::parse(query.as_bytes()).filter(|(k, _)| k == "paths") {
+            let Ok(p) = v.trim_matches('/').parse::<PurePath>() else {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid export path: {v:?}\n"),
+                )
+                    .into_response());
+            };
+            if paths.len() >= EXPORT_MAX_PATHS {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Too many export paths; at most {EXPORT_MAX_PATHS} are permitted\n"),
+                )
+                    .into_response());
+            }
+            paths.push(p);
+        }
+        Ok(RequestedExportPaths((!paths.is_empty()).then_some(paths)))
+    }
+}
+
+/// A `Depth` WebDAV header value
+#[derive(clap::ValueEnum, Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Depth {
+    #[value(name = "0")]
     Zero,
+
+    #[value(name = "1")]
     One,
+
+    #[value(name = "infinity")]
+    Infinity,
 }
 
-impl<S: Send + Sync> FromRequestParts<S> for FiniteDepth {
+impl Depth {
+    /// Return the depth as a [`FiniteDepth`] if it does not exceed `max`
+    pub(super) fn within(self, max: FiniteDepth) -> Option<FiniteDepth> {
+        match self {
+            Depth::Zero => Some(FiniteDepth::Zero),
+            Depth::One if max == FiniteDepth::One => Some(FiniteDepth::One),
+            _ => None,
+        }
+    }
+}
+
+/// The `Depth` header of a request, if any
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(super) struct RequestedDepth(pub(super) Option<Depth>);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestedDepth {
     type Rejection = Response<Body>;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
         match parts.headers.get("Depth").map(|v| v.to_str()) {
-            Some(Ok("0")) => Ok(FiniteDepth::Zero),
-            Some(Ok("1")) => Ok(FiniteDepth::One),
-            Some(Ok("infinity")) | None => Err((
-                StatusCode::FORBIDDEN,
-                [(CONTENT_TYPE, DAV_XML_CONTENT_TYPE)],
-                INFINITE_DEPTH_RESPONSE,
-            )
-                .into_response()),
+            Some(Ok("0")) => Ok(RequestedDepth(Some(Depth::Zero))),
+            Some(Ok("1")) => Ok(RequestedDepth(Some(Depth::One))),
+            Some(Ok("infinity")) => Ok(RequestedDepth(Some(Depth::Infinity))),
+            None => Ok(RequestedDepth(None)),
             _ => Err((StatusCode::BAD_REQUEST, "Invalid \"Depth\" header\n").into_response()),
         }
     }
 }
 
+/// A non-infinite `Depth` WebDAV header value
+#[derive(clap::ValueEnum, Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum FiniteDepth {
+    #[value(name = "0")]
+    Zero,
+
+    #[value(name = "1")]
+    One,
+}
+
+/// Generate a 403 response with a `propfind-finite-depth` precondition error
+/// body (RFC 4918, §9.1) for a `PROPFIND` request whose depth exceeds the
+/// maximum permitted depth
+pub(super) fn finite_depth_error() -> Response<Body> {
+    (
+        StatusCode::FORBIDDEN,
+        [(CONTENT_TYPE, DAV_XML_CONTENT_TYPE)],
+        INFINITE_DEPTH_RESPONSE,
+    )
+        .into_response()
+}
+
+/// Generate a 409 response with a `lock-token-matches-request-uri`
+/// precondition error body (RFC 4918, §9.11) for an `UNLOCK` request whose
+/// lock token does not identify a lock on the requested resource
+pub(super) fn lock_token_mismatch_error() -> Response<Body> {
+    (
+        StatusCode::CONFLICT,
+        [(CONTENT_TYPE, DAV_XML_CONTENT_TYPE)],
+        LOCK_TOKEN_MISMATCH_RESPONSE,
+    )
+        .into_response()
+}
+
+/// Return a 207 response with the serialized `multistatus` document `body`
+pub(super) fn multistatus_response<B: Into<Body>>(body: B) -> Response<Body> {
+    (
+        StatusCode::MULTI_STATUS,
+        [(CONTENT_TYPE, DAV_XML_CONTENT_TYPE)],
+        body.into(),
+    )
+        .into_response()
+}
+
 /// A percent-encoded URI or URI path, for use in the `href` attribute of an
 /// HTML `<a>` tag or in a `<DAV:href>` tag in a `PROPFIND` response
 #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
@@ -216,16 +682,439 @@ impl Serialize for Href {
     }
 }
 
-/// Generate a 404 response
-pub(super) fn not_found() -> Response<Body> {
-    (StatusCode::NOT_FOUND, "404\n").into_response()
+/// Log & generate a 404 response for a request path that does not correspond
+/// to any resource
+pub(super) fn not_found(uri_path: &str) -> Response<Body> {
+    let nf = NotFound::Path {
+        path: uri_path.to_owned(),
+    };
+    tracing::info!(
+        not_found = nf.kind(),
+        "Request path does not correspond to any resource"
+    );
+    nf.into_response()
+}
+
+/// Generate the response for an incoming request that needed more than
+/// `limit` requests to upstream servers
+pub(super) fn budget_exceeded_error(limit: NonZeroUsize) -> Response<Body> {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        format!(
+            "503\nThis request would require more than {limit} requests to upstream servers.  Try a less expensive request, such as a PROPFIND with a smaller Depth.\n"
+        ),
+    )
+        .into_response()
+}
+
+/// Return `etag` as a quoted HTTP entity tag, adding quotes if it does not
+/// already have them
+pub(super) fn entity_tag(etag: &str) -> String {
+    if etag.len() >= 2 && etag.starts_with('"') && etag.ends_with('"') {
+        etag.to_owned()
+    } else {
+        format!("\"{etag}\"")
+    }
+}
+
+/// Return whether the `If-None-Match` header value `header` matches the
+/// quoted entity tag `etag`.  As specified by RFC 9110, §13.1.2, entity tags
+/// are compared using weak comparison, and `*` matches any entity tag.
+pub(super) fn if_none_match(header: &HeaderValue, etag: &str) -> bool {
+    let Ok(s) = header.to_str() else {
+        return false;
+    };
+    s.split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Return whether a `GET` request with the given `If-None-Match` and
+/// `If-Modified-Since` header values should be answered with a 304 response
+/// for a representation with entity tag `etag` (quoted) and modification time
+/// `modified`.  As specified by RFC 9110, §13.2.2, `If-Modified-Since` is
+/// ignored when `If-None-Match` is present.
+pub(super) fn is_not_modified(
+    if_none_match_header: Option<&HeaderValue>,
+    if_modified_since: Option<&HeaderValue>,
+    etag: Option<&HeaderValue>,
+    modified: Option<OffsetDateTime>,
+) -> bool {
+    if let Some(header) = if_none_match_header {
+        etag.and_then(|tag| tag.to_str().ok())
+            .is_some_and(|tag| if_none_match(header, tag))
+    } else if let Some(since) = if_modified_since
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_http_date)
+    {
+        // HTTP dates only have one-second precision.
+        modified.is_some_and(|m| m.unix_timestamp() <= since.unix_timestamp())
+    } else {
+        false
+    }
+}
+
+/// Return whether a `Range` header should be honored for a request with the
+/// given `If-Range` header value (if any) and a representation with strong
+/// entity tag `etag` (quoted) and modification time `modified`.  As specified
+/// by RFC 9110, §13.1.5, an entity tag validator must match `etag` under
+/// strong comparison, and a date validator must exactly match `modified`.
+pub(super) fn if_range_matches(
+    if_range: Option<&HeaderValue>,
+    etag: &HeaderValue,
+    modified: Option<OffsetDateTime>,
+) -> bool {
+    let Some(header) = if_range else {
+        return true;
+    };
+    let Ok(value) = header.to_str().map(str::trim) else {
+        return false;
+    };
+    if value.starts_with('"') {
+        etag.to_str().is_ok_and(|tag| tag == value)
+    } else if value.starts_with("W/") {
+        false
+    } else {
+        parse_http_date(value)
+            .zip(modified)
+            .is_some_and(|(date, m)| date.unix_timestamp() == m.unix_timestamp())
+    }
+}
+
+/// Parse an HTTP date in the preferred (RFC 1123) format
+fn parse_http_date(s: &str) -> Option<OffsetDateTime> {
+    PrimitiveDateTime::parse(s.trim(), &RFC1123)
+        .ok()
+        .map(PrimitiveDateTime::assume_utc)
+}
+
+/// Return a `Last-Modified` header value for the modification time `dt`
+pub(super) fn last_modified(dt: OffsetDateTime) -> HeaderValue {
+    HeaderValue::try_from(format_modifieddate(dt))
+        .expect("formatted timestamp should be a valid header value")
+}
+
+/// Return a strong entity tag for the response body `content` generated by
+/// `dandidav`, derived from a hash of the body
+pub(super) fn content_etag(content: &[u8]) -> HeaderValue {
+    let digest = Sha256::digest(content);
+    HeaderValue::from_str(&format!("\"{}\"", hex::encode(&digest[..16])))
+        .expect("hex digest should be a valid header value")
+}
+
+/// Return a `Content-Disposition` value instructing clients to save a
+/// download under the name `filename`.
+///
+/// Following RFC 6266, the name is given both as a plain `filename` parameter,
+/// in which non-ASCII characters, quotes, and backslashes are replaced by
+/// underscores, and as a UTF-8 `filename*` parameter.
+pub(super) fn content_disposition(filename: &str) -> String {
+    let fallback = filename
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    let encoded = percent_encode(filename.as_bytes(), ATTR_CHAR_ESCAPED);
+    format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+/// Split `blob` into chunks of at most `chunk_size` bytes without copying,
+/// for streaming as a response body
+pub(super) fn blob_chunks(blob: Bytes, chunk_size: usize) -> impl Iterator<Item = Bytes> {
+    let len = blob.len();
+    (0..len)
+        .step_by(chunk_size.max(1))
+        .map(move |start| blob.slice(start..len.min(start.saturating_add(chunk_size))))
+}
+
+/// Construct a response body that streams `blob` in chunks of
+/// [`YAML_BODY_CHUNK_SIZE`] bytes
+pub(super) fn blob_body(blob: Bytes) -> Body {
+    Body::from_stream(futures_util::stream::iter(
+        blob_chunks(blob, YAML_BODY_CHUNK_SIZE).map(Ok::<_, Infallible>),
+    ))
+}
+
+/// Return a response for a `GET` request for an in-memory resource with
+/// contents `blob`, honoring `range` (the request's `Range` header, if any) if
+/// it specifies a single byte range.  Other `Range` values are ignored, and
+/// the complete resource is returned.  Successful responses carry digest
+/// headers computed from `blob` so that clients can verify its integrity.
+pub(super) fn blob_response(blob: Bytes, range: Option<&HeaderValue>) -> Response<Body> {
+    let len = blob.len() as u64;
+    let digests = digest_headers(&blob);
+    let mut resp = match range.and_then(|r| ByteRange::parse(r, len)) {
+        Some(ByteRange::Satisfiable { start, end }) => {
+            let content_range = format!("bytes {start}-{end}/{len}");
+            #[allow(clippy::cast_possible_truncation)]
+            let body = blob.slice((start as usize)..=(end as usize));
+            (
+                StatusCode::PARTIAL_CONTENT,
+                [
+                    (
+                        CONTENT_RANGE,
+                        HeaderValue::try_from(content_range)
+                            .expect("Content-Range value should be a valid header value"),
+                    ),
+                    (CONTENT_LENGTH, HeaderValue::from(body.len())),
+                ],
+                digests,
+                blob_body(body),
+            )
+                .into_response()
+        }
+        Some(ByteRange::Unsatisfiable) => (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(CONTENT_RANGE, format!("bytes */{len}"))],
+        )
+            .into_response(),
+        None => (
+            [(CONTENT_LENGTH, HeaderValue::from(blob.len()))],
+            digests,
+            blob_body(blob),
+        )
+            .into_response(),
+    };
+    resp.headers_mut()
+        .insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    resp
+}
+
+/// Return the `Repr-Digest` and `Digest` headers giving the SHA-256 digest of
+/// a representation with contents `content`.  As the digest is of the
+/// complete representation, the same headers apply to responses for any byte
+/// range of it.
+fn digest_headers(content: &[u8]) -> [(HeaderName, HeaderValue); 2] {
+    let digest = STANDARD.encode(Sha256::digest(content));
+    let repr_digest = HeaderValue::try_from(format!("sha-256=:{digest}:"))
+        .expect("base64 digest should be a valid header value");
+    let legacy = HeaderValue::try_from(format!("SHA-256={digest}"))
+        .expect("base64 digest should be a valid header value");
+    [(REPR_DIGEST.clone(), repr_digest), (DIGEST.clone(), legacy)]
+}
+
+/// A single byte range requested by a `Range` header, resolved against the
+/// length of the object
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum ByteRange {
+    /// The range covers bytes `start` through `end`, inclusive
+    Satisfiable { start: u64, end: u64 },
+
+    /// The range lies entirely outside the object
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Parse a `Range` header value specifying a single byte range and
+    /// resolve it against an object of length `len`.  Returns `None` if the
+    /// value is malformed or specifies multiple ranges.
+    fn parse(value: &HeaderValue, len: u64) -> Option<ByteRange> {
+        let spec = value.to_str().ok()?.trim().strip_prefix("bytes=")?;
+        if spec.contains(',') {
+            return None;
+        }
+        let (first, last) = spec.split_once('-')?;
+        let (first, last) = (first.trim(), last.trim());
+        let (start, end) = if first.is_empty() {
+            let suffix = last.parse::<u64>().ok()?;
+            if suffix == 0 || len == 0 {
+                return Some(ByteRange::Unsatisfiable);
+            }
+            (len.saturating_sub(suffix), len - 1)
+        } else {
+            let start = first.parse::<u64>().ok()?;
+            let end = if last.is_empty() {
+                u64::MAX
+            } else {
+                last.parse::<u64>().ok()?
+            };
+            if end < start {
+                return None;
+            }
+            if start >= len {
+                return Some(ByteRange::Unsatisfiable);
+            }
+            (start, end.min(len - 1))
+        };
+        Some(ByteRange::Satisfiable { start, end })
+    }
+}
+
+/// A [`proptest`] strategy for generating path components made up largely of
+/// characters that are significant in HTML & XML, for testing that such names
+/// are escaped properly when rendered
+#[cfg(test)]
+pub(super) fn arb_hostile_component() -> impl proptest::strategy::Strategy<Value = Component> {
+    use proptest::prelude::*;
+    proptest::collection::vec(
+        prop_oneof![
+            3 => prop::sample::select(vec![
+                '<', '>', '&', '"', '\'', '=', '`', ' ', '#', '%', '?', ';', '!', '-', '{', '}',
+            ]),
+            1 => prop::sample::select(vec!['a', 'b', 'z', 'A', 'Z', '0', '9', '.', '\u{e9}', '\u{2603}']),
+        ],
+        1..24,
+    )
+    .prop_filter_map("not a valid path component", |chars| {
+        chars.into_iter().collect::<String>().parse::<Component>().ok()
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rstest::rstest;
     use time::macros::datetime;
 
+    #[rstest]
+    #[case("bytes=2-5", Some(ByteRange::Satisfiable { start: 2, end: 5 }))]
+    #[case("bytes=2-", Some(ByteRange::Satisfiable { start: 2, end: 9 }))]
+    #[case("bytes=5-100", Some(ByteRange::Satisfiable { start: 5, end: 9 }))]
+    #[case("bytes=-3", Some(ByteRange::Satisfiable { start: 7, end: 9 }))]
+    #[case("bytes=-30", Some(ByteRange::Satisfiable { start: 0, end: 9 }))]
+    #[case("bytes=10-", Some(ByteRange::Unsatisfiable))]
+    #[case("bytes=-0", Some(ByteRange::Unsatisfiable))]
+    #[case("bytes=5-2", None)]
+    #[case("bytes=0-1,4-5", None)]
+    #[case("bytes=x-", None)]
+    #[case("items=0-1", None)]
+    fn test_parse_byte_range(#[case] value: &str, #[case] range: Option<ByteRange>) {
+        assert_eq!(
+            ByteRange::parse(&HeaderValue::from_str(value).unwrap(), 10),
+            range
+        );
+    }
+
+    #[rstest]
+    #[case("", 4, &[])]
+    #[case("abc", 4, &["abc"])]
+    #[case("abcd", 4, &["abcd"])]
+    #[case("abcdefghij", 4, &["abcd", "efgh", "ij"])]
+    fn test_blob_chunks(#[case] blob: &'static str, #[case] size: usize, #[case] chunks: &[&str]) {
+        let actual = blob_chunks(Bytes::from_static(blob.as_bytes()), size).collect::<Vec<_>>();
+        assert_eq!(actual, chunks);
+    }
+
+    #[rstest]
+    #[case(
+        "sub-RAT123.nwb",
+        "attachment; filename=\"sub-RAT123.nwb\"; filename*=UTF-8''sub-RAT123.nwb"
+    )]
+    #[case(
+        "foo bar;baz.txt",
+        "attachment; filename=\"foo bar;baz.txt\"; filename*=UTF-8''foo%20bar%3Bbaz.txt"
+    )]
+    #[case(
+        "caf\u{e9} \"quoted\".tsv",
+        "attachment; filename=\"caf_ _quoted_.tsv\"; filename*=UTF-8''caf%C3%A9%20%22quoted%22.tsv"
+    )]
+    fn test_content_disposition(#[case] filename: &str, #[case] value: &str) {
+        assert_eq!(content_disposition(filename), value);
+    }
+
+    #[rstest]
+    #[case("abc123-4", "\"abc123-4\"")]
+    #[case("\"0123456789abcdef\"", "\"0123456789abcdef\"")]
+    #[case("\"", "\"\"\"")]
+    fn test_entity_tag(#[case] etag: &str, #[case] tag: &str) {
+        assert_eq!(entity_tag(etag), tag);
+    }
+
+    #[rstest]
+    #[case("(<opaquelocktoken:abc>)", Some("opaquelocktoken:abc"))]
+    #[case(
+        "<http://example.com/dandisets/> (<opaquelocktoken:abc>)",
+        Some("opaquelocktoken:abc")
+    )]
+    #[case(
+        "(<opaquelocktoken:abc>) (<opaquelocktoken:def>)",
+        Some("opaquelocktoken:abc")
+    )]
+    #[case("([\"abc123-4\"])", None)]
+    #[case("(<opaquelocktoken:abc)", None)]
+    #[case("", None)]
+    fn test_parse_if_token(#[case] value: &str, #[case] token: Option<&str>) {
+        assert_eq!(parse_if_token(value).as_deref(), token);
+    }
+
+    #[rstest]
+    #[case("\"abc123-4\"", true)]
+    #[case("W/\"abc123-4\"", true)]
+    #[case("*", true)]
+    #[case("\"foo\", \"abc123-4\"", true)]
+    #[case("\"foo\",W/\"abc123-4\"", true)]
+    #[case("\"foo\"", false)]
+    #[case("abc123-4", false)]
+    #[case("\"ABC123-4\"", false)]
+    #[case("", false)]
+    fn test_if_none_match(#[case] header: &str, #[case] matches: bool) {
+        let header = HeaderValue::from_str(header).unwrap();
+        assert_eq!(if_none_match(&header, "\"abc123-4\""), matches);
+    }
+
+    #[rstest]
+    #[case(Some("\"abc123-4\""), None, true)]
+    #[case(Some("\"foo\""), None, false)]
+    // If-None-Match takes precedence over If-Modified-Since
+    #[case(Some("\"foo\""), Some("Fri, 05 Jan 2024 12:34:56 GMT"), false)]
+    #[case(None, Some("Fri, 05 Jan 2024 12:34:56 GMT"), true)]
+    #[case(None, Some("Sat, 06 Jan 2024 00:00:00 GMT"), true)]
+    #[case(None, Some("Fri, 05 Jan 2024 12:34:55 GMT"), false)]
+    #[case(None, Some("yesterday"), false)]
+    #[case(None, None, false)]
+    fn test_is_not_modified(
+        #[case] inm: Option<&str>,
+        #[case] ims: Option<&str>,
+        #[case] not_modified: bool,
+    ) {
+        let inm = inm.map(|v| HeaderValue::from_str(v).unwrap());
+        let ims = ims.map(|v| HeaderValue::from_str(v).unwrap());
+        let etag = HeaderValue::from_static("\"abc123-4\"");
+        let modified = datetime!(2024-01-05 12:34:56.789 UTC);
+        assert_eq!(
+            is_not_modified(inm.as_ref(), ims.as_ref(), Some(&etag), Some(modified)),
+            not_modified
+        );
+    }
+
+    #[test]
+    fn test_digest_headers() {
+        let [(repr_name, repr_value), (legacy_name, legacy_value)] = digest_headers(b"hello");
+        assert_eq!(repr_name, "repr-digest");
+        assert_eq!(
+            repr_value,
+            "sha-256=:LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=:"
+        );
+        assert_eq!(legacy_name, "digest");
+        assert_eq!(
+            legacy_value,
+            "SHA-256=LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ="
+        );
+    }
+
+    #[rstest]
+    #[case(None, true)]
+    #[case(Some("\"abc123-4\""), true)]
+    #[case(Some("\"foo\""), false)]
+    #[case(Some("W/\"abc123-4\""), false)]
+    #[case(Some("Fri, 05 Jan 2024 12:34:56 GMT"), true)]
+    #[case(Some("Fri, 05 Jan 2024 12:34:57 GMT"), false)]
+    #[case(Some("yesterday"), false)]
+    fn test_if_range_matches(#[case] if_range: Option<&str>, #[case] matches: bool) {
+        let if_range = if_range.map(|v| HeaderValue::from_str(v).unwrap());
+        let etag = HeaderValue::from_static("\"abc123-4\"");
+        let modified = datetime!(2024-01-05 12:34:56.789 UTC);
+        assert_eq!(
+            if_range_matches(if_range.as_ref(), &etag, Some(modified)),
+            matches
+        );
+    }
+
     #[test]
     fn test_href_from_path() {
         let s = "/~cleesh/foo bar/baz_quux.gnusto/red&green?blue";
@@ -0,0 +1,353 @@
+use super::*;
+use crate::dav::util::Href;
+use bytes::{Buf, Bytes};
+use thiserror::Error;
+use xml::reader::{Error as XmlError, ParserConfig2, XmlEvent};
+
+/// Estimated number of bytes in the serialization of a `LOCK` response body,
+/// used to size the output buffer up front
+const LOCK_RESPONSE_SIZE_HINT: usize = 640;
+
+/// The scope of a write lock
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(in crate::dav) enum LockScope {
+    Exclusive,
+    Shared,
+}
+
+impl LockScope {
+    fn name(self) -> &'static str {
+        match self {
+            LockScope::Exclusive => "exclusive",
+            LockScope::Shared => "shared",
+        }
+    }
+}
+
+/// The `Depth` of a lock, which determines whether the lock applies to the
+/// members of a locked collection
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(in crate::dav) enum LockDepth {
+    Zero,
+    Infinity,
+}
+
+/// The client-supplied description of a lock's owner
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(in crate::dav) enum LockOwner {
+    /// The owner was given as a `<DAV:href>` (as done by Windows' WebDAV
+    /// redirector)
+    Href(String),
+    /// The owner was given as free text
+    Text(String),
+}
+
+/// The parsed body of a `LOCK` request that creates a new lock
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(in crate::dav) struct LockInfo {
+    pub(in crate::dav) scope: LockScope,
+    pub(in crate::dav) owner: Option<LockOwner>,
+}
+
+impl LockInfo {
+    /// Parse a `<lockinfo>` document.  Only the lock scope, the lock type
+    /// (which must be "write"), and the text of the owner are extracted; any
+    /// other elements are ignored.
+    pub(in crate::dav) fn from_xml(blob: Bytes) -> Result<LockInfo, LockInfoError> {
+        let reader = ParserConfig2::new()
+            .ignore_invalid_encoding_declarations(false)
+            .allow_multiple_root_elements(false)
+            .trim_whitespace(true)
+            .create_reader(blob.reader());
+        let mut tag_stack = Vec::<Tag>::new();
+        let mut scope = None;
+        let mut write = false;
+        let mut owner = None::<String>;
+        let mut owner_href = false;
+        for event in reader {
+            use XmlEvent::*;
+            match event? {
+                StartElement { name, .. } => {
+                    let tag = Tag::new(name.local_name, name.namespace);
+                    let parent = tag_stack.last().and_then(Tag::dav_name);
+                    match (tag_stack.len(), parent, tag.dav_name()) {
+                        (0, _, Some("lockinfo")) => (),
+                        (0, _, _) => return Err(LockInfoError::NotLockInfo),
+                        (1, _, Some("owner")) => owner = Some(String::new()),
+                        (2, Some("lockscope"), Some("exclusive")) => {
+                            scope = Some(LockScope::Exclusive);
+                        }
+                        (2, Some("lockscope"), Some("shared")) => scope = Some(LockScope::Shared),
+                        (2, Some("locktype"), Some("write")) => write = true,
+                        (2, Some("owner"), Some("href")) => owner_href = true,
+                        _ => (),
+                    }
+                    tag_stack.push(tag);
+                }
+                EndElement { .. } => {
+                    tag_stack.pop();
+                }
+                Characters(s) | CData(s) => {
+                    if tag_stack.get(1).and_then(Tag::dav_name) == Some("owner") {
+                        if let Some(text) = owner.as_mut() {
+                            text.push_str(&s);
+                        }
+                    }
+                }
+                StartDocument { .. }
+                | EndDocument
+                | Comment(..)
+                | Whitespace(..)
+                | ProcessingInstruction { .. } => (),
+            }
+        }
+        let Some(scope) = scope else {
+            return Err(LockInfoError::NoScope);
+        };
+        if !write {
+            return Err(LockInfoError::NotWriteLock);
+        }
+        let owner = owner
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                if owner_href {
+                    LockOwner::Href(s)
+                } else {
+                    LockOwner::Text(s)
+                }
+            });
+        Ok(LockInfo { scope, owner })
+    }
+}
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub(crate) enum LockInfoError {
+    #[error("failed to parse XML")]
+    Xml(#[from] XmlError),
+    #[error("XML is not a <lockinfo> document")]
+    NotLockInfo,
+    #[error("<lockinfo> does not specify a lock scope")]
+    NoScope,
+    #[error("<lockinfo> does not request a write lock")]
+    NotWriteLock,
+}
+
+/// A lock granted in response to a `LOCK` request
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(in crate::dav) struct ActiveLock {
+    pub(in crate::dav) scope: LockScope,
+    pub(in crate::dav) depth: LockDepth,
+    pub(in crate::dav) owner: Option<LockOwner>,
+    /// The number of seconds after which the lock expires unless refreshed
+    pub(in crate::dav) timeout_secs: u64,
+    /// The lock token, an `opaquelocktoken:` URI
+    pub(in crate::dav) token: String,
+    /// The href of the locked resource
+    pub(in crate::dav) root: Href,
+}
+
+impl ActiveLock {
+    /// Serialize as the `<prop>` document returned as the body of a response
+    /// to a `LOCK` request
+    pub(in crate::dav) fn to_lock_response_xml(&self) -> String {
+        let mut writer = XmlWriter::new(String::new(), LOCK_RESPONSE_SIZE_HINT);
+        writer.tag_xmlns("prop", DAV_XMLNS, |writer| {
+            writer.tag("lockdiscovery", |writer| self.write_xml(writer));
+        });
+        let mut s = writer.into_string();
+        s.push('\n');
+        s
+    }
+
+    fn write_xml(&self, writer: &mut XmlWriter) {
+        writer.tag("activelock", |writer| {
+            writer.tag("locktype", |writer| writer.empty_tag("write"));
+            writer.tag("lockscope", |writer| writer.empty_tag(self.scope.name()));
+            writer.text_tag(
+                "depth",
+                match self.depth {
+                    LockDepth::Zero => "0",
+                    LockDepth::Infinity => "infinity",
+                },
+            );
+            match self.owner {
+                Some(LockOwner::Href(ref href)) => {
+                    writer.tag("owner", |writer| writer.text_tag("href", href));
+                }
+                Some(LockOwner::Text(ref text)) => writer.text_tag("owner", text),
+                None => (),
+            }
+            writer.text_tag("timeout", &format!("Second-{}", self.timeout_secs));
+            writer.tag("locktoken", |writer| writer.text_tag("href", &self.token));
+            writer.tag("lockroot", |writer| {
+                writer.text_tag("href", self.root.as_ref());
+            });
+        });
+    }
+}
+
+/// Write the contents of a `supportedlock` property, which advertises that
+/// both exclusive and shared write locks can be requested
+pub(super) fn write_supported_locks(writer: &mut XmlWriter) {
+    for scope in [LockScope::Exclusive, LockScope::Shared] {
+        writer.tag("lockentry", |writer| {
+            writer.tag("lockscope", |writer| writer.empty_tag(scope.name()));
+            writer.tag("locktype", |writer| writer.empty_tag("write"));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_windows_lockinfo() {
+        let s = indoc! {r#"
+            <?xml version="1.0" encoding="utf-8" ?>
+            <D:lockinfo xmlns:D="DAV:">
+                <D:lockscope><D:exclusive/></D:lockscope>
+                <D:locktype><D:write/></D:locktype>
+                <D:owner><D:href>EXAMPLE\jdoe</D:href></D:owner>
+            </D:lockinfo>
+        "#};
+        let info = LockInfo::from_xml(Bytes::from(s)).unwrap();
+        assert_eq!(
+            info,
+            LockInfo {
+                scope: LockScope::Exclusive,
+                owner: Some(LockOwner::Href(r"EXAMPLE\jdoe".into())),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_shared_text_owner() {
+        let s = indoc! {r#"
+            <?xml version="1.0" encoding="utf-8" ?>
+            <lockinfo xmlns="DAV:">
+                <locktype><write/></locktype>
+                <lockscope><shared/></lockscope>
+                <owner>
+                    Jane Doe
+                </owner>
+            </lockinfo>
+        "#};
+        let info = LockInfo::from_xml(Bytes::from(s)).unwrap();
+        assert_eq!(
+            info,
+            LockInfo {
+                scope: LockScope::Shared,
+                owner: Some(LockOwner::Text("Jane Doe".into())),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_no_owner() {
+        let s = indoc! {r#"
+            <?xml version="1.0" encoding="utf-8" ?>
+            <lockinfo xmlns="DAV:">
+                <lockscope><exclusive/></lockscope>
+                <locktype><write/></locktype>
+                <owner/>
+            </lockinfo>
+        "#};
+        let info = LockInfo::from_xml(Bytes::from(s)).unwrap();
+        assert_eq!(
+            info,
+            LockInfo {
+                scope: LockScope::Exclusive,
+                owner: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_not_lockinfo() {
+        let s = indoc! {r#"
+            <?xml version="1.0" encoding="utf-8" ?>
+            <propfind xmlns="DAV:">
+                <allprop/>
+            </propfind>
+        "#};
+        assert_matches!(
+            LockInfo::from_xml(Bytes::from(s)),
+            Err(LockInfoError::NotLockInfo)
+        );
+    }
+
+    #[test]
+    fn parse_no_scope() {
+        let s = indoc! {r#"
+            <?xml version="1.0" encoding="utf-8" ?>
+            <lockinfo xmlns="DAV:">
+                <locktype><write/></locktype>
+            </lockinfo>
+        "#};
+        assert_matches!(
+            LockInfo::from_xml(Bytes::from(s)),
+            Err(LockInfoError::NoScope)
+        );
+    }
+
+    #[test]
+    fn parse_no_write() {
+        let s = indoc! {r#"
+            <?xml version="1.0" encoding="utf-8" ?>
+            <lockinfo xmlns="DAV:">
+                <lockscope><exclusive/></lockscope>
+                <locktype><read xmlns="https://example.com/ns"/></locktype>
+            </lockinfo>
+        "#};
+        assert_matches!(
+            LockInfo::from_xml(Bytes::from(s)),
+            Err(LockInfoError::NotWriteLock)
+        );
+    }
+
+    #[test]
+    fn lock_response_xml() {
+        let lock = ActiveLock {
+            scope: LockScope::Exclusive,
+            depth: LockDepth::Infinity,
+            owner: Some(LockOwner::Href(r"EXAMPLE\jdoe".into())),
+            timeout_secs: 3600,
+            token: "opaquelocktoken:e71d4fae-5dec-4ed6-9a1a-f4a6e0b5a8f3".into(),
+            root: Href::from_path("/dandisets/000001/"),
+        };
+        assert_eq!(
+            lock.to_lock_response_xml(),
+            indoc! {r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <prop xmlns="DAV:">
+                <lockdiscovery>
+                    <activelock>
+                        <locktype>
+                            <write />
+                        </locktype>
+                        <lockscope>
+                            <exclusive />
+                        </lockscope>
+                        <depth>infinity</depth>
+                        <owner>
+                            <href>EXAMPLE\jdoe</href>
+                        </owner>
+                        <timeout>Second-3600</timeout>
+                        <locktoken>
+                            <href>opaquelocktoken:e71d4fae-5dec-4ed6-9a1a-f4a6e0b5a8f3</href>
+                        </locktoken>
+                        <lockroot>
+                            <href>/dandisets/000001/</href>
+                        </lockroot>
+                    </activelock>
+                </lockdiscovery>
+            </prop>
+            "#}
+        );
+    }
+}
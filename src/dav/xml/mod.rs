@@ -1,13 +1,17 @@
 //! Working with WebDAV XML documents
+mod lock;
 mod multistatus;
 mod propfind;
+mod writer;
+pub(super) use self::lock::*;
 pub(super) use self::multistatus::*;
 pub(super) use self::propfind::*;
-use crate::consts::DAV_XMLNS;
+use self::writer::{is_xml_char, XmlWriter};
+use crate::consts::{DANDIDAV_XMLNS, DAV_XMLNS};
+use std::borrow::Cow;
 use std::fmt;
-use xml::writer::Error as WriteError;
 
-#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub(in crate::dav) enum Property {
     CreationDate,
     DisplayName,
@@ -17,8 +21,66 @@ pub(in crate::dav) enum Property {
     GetETag,
     GetLastModified,
     ResourceType,
-    //LockDiscovery,
-    //SupportedLock,
+    LockDiscovery,
+    SupportedLock,
+    /// `dandidav`-specific property giving the total size of all
+    /// non-collection resources within a collection
+    AggregateSize,
+    /// `dandidav`-specific property giving the number of non-collection
+    /// resources within a collection
+    EntryCount,
+    /// `dandidav`-specific property giving the git-annex key for a
+    /// non-collection resource's content
+    AnnexKey,
+    /// `dandidav`-specific property giving the URL path at which an asset is
+    /// served by asset ID
+    Permalink,
+    /// `dandidav`-specific property giving the S3 version ID of the object
+    /// that a Zarr entry's download URL is pinned to
+    S3VersionId,
+    /// `dandidav`-specific property giving the URL that `GET` requests for a
+    /// non-collection resource are redirected to
+    DownloadUrl,
+    /// `dandidav`-specific property giving the ID of the Dandiset that a
+    /// resource belongs to
+    DandisetId,
+    /// `dandidav`-specific property giving the ID of the Dandiset version
+    /// that a resource belongs to
+    VersionId,
+    /// `dandidav`-specific property giving the Archive ID of an asset
+    AssetId,
+    /// `dandidav`-specific property giving the path of an asset within its
+    /// Dandiset version on the Archive
+    AssetPath,
+    /// `dandidav`-specific property giving the Archive ID of the blob
+    /// underlying a blob asset
+    BlobId,
+    /// `dandidav`-specific property giving the Archive ID of the Zarr
+    /// underlying a Zarr asset
+    ZarrId,
+    /// `dandidav`-specific property giving the DANDI ETag of a blob asset's
+    /// content
+    DandiEtag,
+    /// `dandidav`-specific property giving the SHA-256 digest of a blob
+    /// asset's content
+    Sha256,
+    /// `dandidav`-specific property giving the DOI of a Dandiset version
+    Doi,
+    /// `dandidav`-specific property giving whether a folder within a Zarr is
+    /// a Zarr group or array
+    ZarrNodeType,
+    /// `dandidav`-specific property giving the unescaped filename of a
+    /// resource whose name contains control characters or leading or trailing
+    /// whitespace
+    ExactName,
+    /// `dandidav`-specific property, reported only on a truncated `PROPFIND`
+    /// response's first resource, giving the number of resources left out of
+    /// the response
+    Truncated,
+    /// `dandidav`-specific property, reported only on a truncated `PROPFIND`
+    /// response's first resource, giving the href of the first resource left
+    /// out of the response
+    Continuation,
     Custom(Tag),
 }
 
@@ -32,24 +94,46 @@ impl Property {
             Property::GetETag,
             Property::GetLastModified,
             Property::ResourceType,
+            Property::LockDiscovery,
+            Property::SupportedLock,
         ]
         .into_iter()
     }
 
-    fn write_xml(&self, writer: &mut XmlWriter, value: &PropValue) -> Result<(), WriteError> {
+    fn write_xml(&self, writer: &mut XmlWriter, value: &PropValue) {
         match self {
-            Property::CreationDate => writer.start_tag("creationdate")?,
-            Property::DisplayName => writer.start_tag("displayname")?,
-            Property::GetContentLength => writer.start_tag("getcontentlength")?,
-            Property::GetContentType => writer.start_tag("getcontenttype")?,
-            Property::GetETag => writer.start_tag("getetag")?,
-            Property::GetLastModified => writer.start_tag("getlastmodified")?,
-            Property::ResourceType => writer.start_tag("resourcetype")?,
-            Property::Custom(tag) => writer.start_tag_ns(&tag.name, &tag.namespace)?,
+            Property::CreationDate => writer.start_tag("creationdate"),
+            Property::DisplayName => writer.start_tag("displayname"),
+            Property::GetContentLength => writer.start_tag("getcontentlength"),
+            Property::GetContentType => writer.start_tag("getcontenttype"),
+            Property::GetETag => writer.start_tag("getetag"),
+            Property::GetLastModified => writer.start_tag("getlastmodified"),
+            Property::ResourceType => writer.start_tag("resourcetype"),
+            Property::LockDiscovery => writer.start_tag("lockdiscovery"),
+            Property::SupportedLock => writer.start_tag("supportedlock"),
+            Property::AggregateSize => writer.start_tag_ns("aggregate-size", DANDIDAV_XMLNS),
+            Property::EntryCount => writer.start_tag_ns("entry-count", DANDIDAV_XMLNS),
+            Property::AnnexKey => writer.start_tag_ns("annex-key", DANDIDAV_XMLNS),
+            Property::Permalink => writer.start_tag_ns("permalink", DANDIDAV_XMLNS),
+            Property::S3VersionId => writer.start_tag_ns("s3-version-id", DANDIDAV_XMLNS),
+            Property::DownloadUrl => writer.start_tag_ns("download-url", DANDIDAV_XMLNS),
+            Property::DandisetId => writer.start_tag_ns("dandiset-id", DANDIDAV_XMLNS),
+            Property::VersionId => writer.start_tag_ns("version-id", DANDIDAV_XMLNS),
+            Property::AssetId => writer.start_tag_ns("asset-id", DANDIDAV_XMLNS),
+            Property::AssetPath => writer.start_tag_ns("asset-path", DANDIDAV_XMLNS),
+            Property::BlobId => writer.start_tag_ns("blob-id", DANDIDAV_XMLNS),
+            Property::ZarrId => writer.start_tag_ns("zarr-id", DANDIDAV_XMLNS),
+            Property::DandiEtag => writer.start_tag_ns("dandi-etag", DANDIDAV_XMLNS),
+            Property::Sha256 => writer.start_tag_ns("sha2-256", DANDIDAV_XMLNS),
+            Property::Doi => writer.start_tag_ns("doi", DANDIDAV_XMLNS),
+            Property::ZarrNodeType => writer.start_tag_ns("zarr-node-type", DANDIDAV_XMLNS),
+            Property::ExactName => writer.start_tag_ns("exact-name", DANDIDAV_XMLNS),
+            Property::Truncated => writer.start_tag_ns("truncated", DANDIDAV_XMLNS),
+            Property::Continuation => writer.start_tag_ns("continuation", DANDIDAV_XMLNS),
+            Property::Custom(tag) => writer.start_tag_ns(&tag.name, &tag.namespace),
         }
-        value.write_xml(writer)?;
-        writer.end_tag()?;
-        Ok(())
+        value.write_xml(writer);
+        writer.end_tag();
     }
 }
 
@@ -63,6 +147,27 @@ impl From<Tag> for Property {
             Some("getetag") => Property::GetETag,
             Some("getlastmodified") => Property::GetLastModified,
             Some("resourcetype") => Property::ResourceType,
+            Some("lockdiscovery") => Property::LockDiscovery,
+            Some("supportedlock") => Property::SupportedLock,
+            None if tag.dandidav_name() == Some("aggregate-size") => Property::AggregateSize,
+            None if tag.dandidav_name() == Some("entry-count") => Property::EntryCount,
+            None if tag.dandidav_name() == Some("annex-key") => Property::AnnexKey,
+            None if tag.dandidav_name() == Some("permalink") => Property::Permalink,
+            None if tag.dandidav_name() == Some("s3-version-id") => Property::S3VersionId,
+            None if tag.dandidav_name() == Some("download-url") => Property::DownloadUrl,
+            None if tag.dandidav_name() == Some("dandiset-id") => Property::DandisetId,
+            None if tag.dandidav_name() == Some("version-id") => Property::VersionId,
+            None if tag.dandidav_name() == Some("asset-id") => Property::AssetId,
+            None if tag.dandidav_name() == Some("asset-path") => Property::AssetPath,
+            None if tag.dandidav_name() == Some("blob-id") => Property::BlobId,
+            None if tag.dandidav_name() == Some("zarr-id") => Property::ZarrId,
+            None if tag.dandidav_name() == Some("dandi-etag") => Property::DandiEtag,
+            None if tag.dandidav_name() == Some("sha2-256") => Property::Sha256,
+            None if tag.dandidav_name() == Some("doi") => Property::Doi,
+            None if tag.dandidav_name() == Some("zarr-node-type") => Property::ZarrNodeType,
+            None if tag.dandidav_name() == Some("exact-name") => Property::ExactName,
+            None if tag.dandidav_name() == Some("truncated") => Property::Truncated,
+            None if tag.dandidav_name() == Some("continuation") => Property::Continuation,
             _ => Property::Custom(tag),
         }
     }
@@ -75,17 +180,22 @@ pub(in crate::dav) enum PropValue {
     Empty,
     // `<resourcetype>` value for collections
     Collection,
+    // `<supportedlock>` value for all resources
+    SupportedLock,
     String(String),
     Int(i64),
+    UInt(u64),
 }
 
 impl PropValue {
-    fn write_xml(&self, writer: &mut XmlWriter) -> Result<(), WriteError> {
+    fn write_xml(&self, writer: &mut XmlWriter) {
         match self {
-            PropValue::Empty => Ok(()),
+            PropValue::Empty => (),
             PropValue::Collection => writer.empty_tag("collection"),
+            PropValue::SupportedLock => write_supported_locks(writer),
             PropValue::String(s) => writer.text(s),
-            PropValue::Int(i) => writer.text(&format!("{i}")),
+            PropValue::Int(i) => writer.number(i),
+            PropValue::UInt(u) => writer.number(u),
         }
     }
 }
@@ -102,7 +212,13 @@ impl From<i64> for PropValue {
     }
 }
 
-#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+impl From<u64> for PropValue {
+    fn from(value: u64) -> PropValue {
+        PropValue::UInt(value)
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub(super) struct Tag {
     pub(super) name: String,
     pub(super) namespace: String,
@@ -119,6 +235,10 @@ impl Tag {
     pub(super) fn dav_name(&self) -> Option<&str> {
         (self.namespace == DAV_XMLNS).then(|| &*self.name)
     }
+
+    pub(super) fn dandidav_name(&self) -> Option<&str> {
+        (self.namespace == DANDIDAV_XMLNS).then(|| &*self.name)
+    }
 }
 
 impl fmt::Display for Tag {
@@ -126,3 +246,97 @@ impl fmt::Display for Tag {
         write!(f, "{{{}}}{}", self.namespace, self.name)
     }
 }
+
+/// Replace every character in `s` that cannot appear in an XML 1.0 document
+/// (i.e., most ASCII control characters, U+FFFE, and U+FFFF) with U+FFFD
+/// REPLACEMENT CHARACTER.
+///
+/// Such characters cannot be escaped, even as character references, and so
+/// text containing them (e.g., asset paths with embedded control characters)
+/// would otherwise produce malformed documents.  Markup-significant
+/// characters like `<` and `&` are left as-is for the XML writer to escape.
+pub(crate) fn sanitize_xml_text(s: &str) -> Cow<'_, str> {
+    if s.chars().all(is_xml_char) {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(
+            s.chars()
+                .map(|c| if is_xml_char(c) { c } else { '\u{FFFD}' })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use proptest::prelude::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case('\t', true)]
+    #[case('\n', true)]
+    #[case('\r', true)]
+    #[case(' ', true)]
+    #[case('<', true)]
+    #[case('&', true)]
+    #[case('\u{7F}', true)]
+    #[case('\u{85}', true)]
+    #[case('\u{D7FF}', true)]
+    #[case('\u{E000}', true)]
+    #[case('\u{FFFD}', true)]
+    #[case('\u{10000}', true)]
+    #[case('\u{10FFFF}', true)]
+    #[case('\0', false)]
+    #[case('\u{8}', false)]
+    #[case('\u{B}', false)]
+    #[case('\u{C}', false)]
+    #[case('\u{E}', false)]
+    #[case('\u{1B}', false)]
+    #[case('\u{1F}', false)]
+    #[case('\u{FFFE}', false)]
+    #[case('\u{FFFF}', false)]
+    fn test_is_xml_char(#[case] c: char, #[case] ok: bool) {
+        assert_eq!(is_xml_char(c), ok);
+    }
+
+    #[test]
+    fn sanitize_clean_text() {
+        let s = "foo <bar> & \"baz\"\tquux\r\n\u{2603}";
+        assert_matches!(sanitize_xml_text(s), Cow::Borrowed(t) => {
+            assert_eq!(t, s);
+        });
+    }
+
+    #[test]
+    fn sanitize_all_illegal_chars() {
+        let illegal = ('\0'..='\u{1F}')
+            .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+            .chain(['\u{FFFE}', '\u{FFFF}'])
+            .collect::<Vec<_>>();
+        assert_eq!(illegal.len(), 31);
+        for c in illegal {
+            let s = format!("foo{c}bar");
+            assert_eq!(
+                sanitize_xml_text(&s),
+                "foo\u{FFFD}bar",
+                "{c:?} was not replaced"
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn sanitized_text_is_valid(
+            s in proptest::collection::vec(
+                prop_oneof![prop::char::range('\0', '\u{1F}'), any::<char>()],
+                0..32,
+            ).prop_map(String::from_iter)
+        ) {
+            let sanitized = sanitize_xml_text(&s);
+            prop_assert!(sanitized.chars().all(is_xml_char));
+            prop_assert_eq!(sanitized.chars().count(), s.chars().count());
+        }
+    }
+}
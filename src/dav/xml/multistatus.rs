@@ -1,8 +1,11 @@
 use super::*;
 use crate::dav::util::Href;
+use smallvec::SmallVec;
 use std::collections::BTreeMap;
-use thiserror::Error;
-use xml::writer::{events::XmlEvent, EmitterConfig, Error as WriteError, EventWriter};
+
+/// Estimated number of bytes in the serialization of a single `response`
+/// element, used to size the output buffer up front
+const RESPONSE_SIZE_HINT: usize = 768;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(in crate::dav) struct Multistatus {
@@ -11,143 +14,186 @@ pub(in crate::dav) struct Multistatus {
 }
 
 impl Multistatus {
-    pub(in crate::dav) fn to_xml(&self) -> Result<String, ToXmlError> {
-        let mut writer = XmlWriter::new();
+    pub(in crate::dav) fn to_xml(&self) -> String {
+        self.write_xml(String::new(), None).0
+    }
+
+    /// Returns true if any resource or property in the document could not be
+    /// retrieved, i.e., if any response has an overall status or any
+    /// `propstat` has a 5xx status
+    pub(in crate::dav) fn has_errors(&self) -> bool {
+        self.response.iter().any(DavResponse::has_errors)
+    }
+
+    /// Serialize as XML, leaving out any further responses once the document
+    /// has reached `limit` bytes in size.  The first response, which is taken
+    /// to be for the requested resource, is always included.
+    ///
+    /// If any responses are left out, the first response is marked with a
+    /// `truncated` property giving the number of responses omitted and a
+    /// `continuation` property giving the href of the first omitted resource.
+    pub(in crate::dav) fn into_xml_limited(mut self, limit: usize) -> String {
+        let (s, written) = self.write_xml(String::new(), Some(limit));
+        let omitted = self.response.split_off(written);
+        let Some(next) = omitted.first() else {
+            return s;
+        };
+        if let Some(first) = self.response.first_mut() {
+            first.mark_truncated(omitted.len(), &next.href);
+        }
+        // Reuse the first document's buffer, which is already about the
+        // right size
+        self.write_xml(s, None).0
+    }
+
+    /// Serialize as XML into `buf` (after clearing it), stopping before the
+    /// next response once the document has reached `limit` bytes (if given).
+    /// Returns the document and the number of responses written.
+    fn write_xml(&self, buf: String, limit: Option<usize>) -> (String, usize) {
+        let capacity = self
+            .response
+            .len()
+            .saturating_add(1)
+            .saturating_mul(RESPONSE_SIZE_HINT);
+        let mut writer = XmlWriter::new(buf, capacity);
+        let mut written = 0;
         writer.tag_xmlns("multistatus", DAV_XMLNS, |writer| {
             for r in &self.response {
-                r.write_xml(writer)?;
+                if written > 0 && limit.is_some_and(|lim| writer.size() >= lim) {
+                    break;
+                }
+                r.write_xml(writer);
+                written += 1;
             }
-            Ok(())
-        })?;
-        let mut s = writer.into_string()?;
+        });
+        let mut s = writer.into_string();
+        s.push('\n');
+        (s, written)
+    }
+}
+
+/// A serializer of `multistatus` documents that produces the document a piece
+/// at a time, for streaming responses to clients as they are generated
+pub(in crate::dav) struct MultistatusEncoder {
+    writer: XmlWriter,
+}
+
+impl MultistatusEncoder {
+    pub(in crate::dav) fn new() -> MultistatusEncoder {
+        let mut writer = XmlWriter::new(String::new(), RESPONSE_SIZE_HINT);
+        writer.start_tag_ns("multistatus", DAV_XMLNS);
+        MultistatusEncoder { writer }
+    }
+
+    /// Serialize `response` as the next element of the document
+    pub(in crate::dav) fn push(&mut self, response: &DavResponse) {
+        response.write_xml(&mut self.writer);
+    }
+
+    /// Remove & return the portion of the document serialized since the last
+    /// call to `take()`
+    pub(in crate::dav) fn take(&mut self) -> String {
+        self.writer.take()
+    }
+
+    /// Close the document and return the remainder of it
+    pub(in crate::dav) fn finish(mut self) -> String {
+        self.writer.end_tag();
+        let mut s = self.writer.into_string();
         s.push('\n');
-        Ok(s)
+        s
     }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(in crate::dav) struct DavResponse {
     pub(in crate::dav) href: Href,
-    // TODO: RFC 4918 says <response> can contain (href*, status) as an
-    // alternative to propstat.  When does that apply?
-    pub(in crate::dav) propstat: Vec<PropStat>,
+    /// The resource's properties, grouped by status.  There are at most three
+    /// groups (found, missing, and failed properties), so they are stored
+    /// inline.
+    pub(in crate::dav) propstat: SmallVec<[PropStat; 3]>,
+    /// A status for the resource as a whole, reported in place of `propstat`
+    /// when the resource's properties could not be retrieved
+    pub(in crate::dav) status: Option<String>,
     //error
     //responsedescription
     pub(in crate::dav) location: Option<Href>,
 }
 
 impl DavResponse {
-    fn write_xml(&self, writer: &mut XmlWriter) -> Result<(), WriteError> {
+    /// Add properties to the response indicating that the listing it heads
+    /// was truncated, with `omitted` resources left out starting at `next`
+    fn mark_truncated(&mut self, omitted: usize, next: &Href) {
+        let props = [
+            (
+                Property::Truncated,
+                PropValue::UInt(u64::try_from(omitted).unwrap_or(u64::MAX)),
+            ),
+            (
+                Property::Continuation,
+                PropValue::String(next.as_ref().to_owned()),
+            ),
+        ];
+        if let Some(ps) = self
+            .propstat
+            .iter_mut()
+            .find(|ps| ps.status == "HTTP/1.1 200 OK")
+        {
+            ps.prop.extend(props);
+        } else {
+            self.propstat.push(PropStat {
+                prop: BTreeMap::from(props),
+                status: "HTTP/1.1 200 OK",
+            });
+        }
+    }
+
+    /// Returns true if the response reports the resource as a whole as
+    /// unavailable or reports any properties as having failed
+    fn has_errors(&self) -> bool {
+        self.status.is_some()
+            || self
+                .propstat
+                .iter()
+                .any(|ps| ps.status.starts_with("HTTP/1.1 5"))
+    }
+
+    fn write_xml(&self, writer: &mut XmlWriter) {
         writer.tag("response", |writer| {
-            writer.text_tag("href", self.href.as_ref())?;
-            for p in &self.propstat {
-                p.write_xml(writer)?;
+            writer.text_tag("href", self.href.as_ref());
+            if let Some(ref status) = self.status {
+                writer.text_tag("status", status);
+            } else {
+                for p in &self.propstat {
+                    p.write_xml(writer);
+                }
             }
             if let Some(ref loc) = self.location {
-                writer.tag("location", |writer| writer.text_tag("href", loc.as_ref()))?;
+                writer.tag("location", |writer| writer.text_tag("href", loc.as_ref()));
             }
-            Ok(())
-        })
+        });
     }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(in crate::dav) struct PropStat {
     pub(in crate::dav) prop: BTreeMap<Property, PropValue>,
-    pub(in crate::dav) status: String,
+    pub(in crate::dav) status: &'static str,
     //error
     //responsedescription
 }
 
 impl PropStat {
-    fn write_xml(&self, writer: &mut XmlWriter) -> Result<(), WriteError> {
+    fn write_xml(&self, writer: &mut XmlWriter) {
         writer.tag("propstat", |writer| {
             writer.tag("prop", |writer| {
                 for (k, v) in &self.prop {
-                    k.write_xml(writer, v)?;
+                    k.write_xml(writer, v);
                 }
-                Ok(())
-            })?;
-            writer.text_tag("status", &self.status)?;
-            Ok(())
-        })
-    }
-}
-
-pub(in crate::dav) struct XmlWriter(EventWriter<Vec<u8>>);
-
-impl XmlWriter {
-    fn new() -> Self {
-        XmlWriter(
-            EmitterConfig::new()
-                .indent_string("    ")
-                .perform_indent(true)
-                .write_document_declaration(true)
-                .create_writer(Vec::new()),
-        )
-    }
-
-    fn into_string(self) -> Result<String, std::str::Utf8Error> {
-        let buf = self.0.into_inner();
-        String::from_utf8(buf).map_err(|e| e.utf8_error())
-    }
-
-    fn tag_xmlns<F>(&mut self, name: &str, ns: &str, func: F) -> Result<(), WriteError>
-    where
-        F: FnOnce(&mut Self) -> Result<(), WriteError>,
-    {
-        self.start_tag_ns(name, ns)?;
-        func(self)?;
-        self.end_tag()?;
-        Ok(())
-    }
-
-    fn tag<F>(&mut self, name: &str, func: F) -> Result<(), WriteError>
-    where
-        F: FnOnce(&mut Self) -> Result<(), WriteError>,
-    {
-        self.start_tag(name)?;
-        func(self)?;
-        self.end_tag()?;
-        Ok(())
-    }
-
-    pub(super) fn start_tag(&mut self, name: &str) -> Result<(), WriteError> {
-        self.0.write(XmlEvent::start_element(name))
-    }
-
-    pub(super) fn start_tag_ns(&mut self, name: &str, ns: &str) -> Result<(), WriteError> {
-        self.0.write(XmlEvent::start_element(name).default_ns(ns))
+            });
+            writer.text_tag("status", self.status);
+        });
     }
-
-    pub(super) fn end_tag(&mut self) -> Result<(), WriteError> {
-        self.0.write(XmlEvent::end_element())
-    }
-
-    pub(super) fn empty_tag(&mut self, name: &str) -> Result<(), WriteError> {
-        self.start_tag(name)?;
-        self.end_tag()?;
-        Ok(())
-    }
-
-    pub(super) fn text(&mut self, text: &str) -> Result<(), WriteError> {
-        self.0.write(XmlEvent::characters(text))
-    }
-
-    fn text_tag(&mut self, name: &str, text: &str) -> Result<(), WriteError> {
-        self.start_tag(name)?;
-        self.text(text)?;
-        self.end_tag()?;
-        Ok(())
-    }
-}
-
-#[derive(Debug, Error)]
-pub(crate) enum ToXmlError {
-    #[error("failed to generate XML")]
-    Xml(#[from] WriteError),
-    #[error("generated XML was not valid UTF-8")]
-    Decode(#[from] std::str::Utf8Error),
 }
 
 #[cfg(test)]
@@ -156,6 +202,7 @@ mod tests {
     use crate::httputil::HttpUrl;
     use indoc::indoc;
     use pretty_assertions::assert_eq;
+    use smallvec::smallvec;
 
     #[test]
     fn multistatus_to_xml() {
@@ -163,18 +210,19 @@ mod tests {
             response: vec![
                 DavResponse {
                     href: Href::from_path("/foo/"),
-                    propstat: vec![PropStat {
+                    propstat: smallvec![PropStat {
                         prop: BTreeMap::from([
                             (Property::ResourceType, PropValue::Collection),
                             (Property::DisplayName, PropValue::String("foo".into())),
                         ]),
-                        status: "HTTP/1.1 200 OK".into(),
+                        status: "HTTP/1.1 200 OK",
                     }],
+                    status: None,
                     location: None,
                 },
                 DavResponse {
                     href: Href::from_path("/foo/bar.txt"),
-                    propstat: vec![PropStat {
+                    propstat: smallvec![PropStat {
                         prop: BTreeMap::from([
                             (
                                 Property::CreationDate,
@@ -196,13 +244,14 @@ mod tests {
                             ),
                             (Property::ResourceType, PropValue::Empty),
                         ]),
-                        status: "HTTP/1.1 200 OK".into(),
+                        status: "HTTP/1.1 200 OK",
                     }],
+                    status: None,
                     location: None,
                 },
                 DavResponse {
                     href: Href::from_path("/foo/quux.dat"),
-                    propstat: vec![PropStat {
+                    propstat: smallvec![PropStat {
                         prop: BTreeMap::from([
                             (Property::DisplayName, PropValue::String("quux.dat".into())),
                             (Property::GetContentLength, PropValue::Int(65535)),
@@ -220,8 +269,9 @@ mod tests {
                             ),
                             (Property::ResourceType, PropValue::Empty),
                         ]),
-                        status: "HTTP/1.1 307 TEMPORARY REDIRECT".into(),
+                        status: "HTTP/1.1 307 TEMPORARY REDIRECT",
                     }],
+                    status: None,
                     location: Some(
                         "https://www.example.com/data/quux.dat"
                             .parse::<HttpUrl>()
@@ -233,7 +283,7 @@ mod tests {
         };
 
         assert_eq!(
-            value.to_xml().unwrap(),
+            value.to_xml(),
             indoc! {r#"
             <?xml version="1.0" encoding="UTF-8"?>
             <multistatus xmlns="DAV:">
@@ -285,4 +335,266 @@ mod tests {
         "#}
         );
     }
+
+    #[test]
+    fn status_only_response_to_xml() {
+        let value = Multistatus {
+            response: vec![
+                DavResponse {
+                    href: Href::from_path("/foo/"),
+                    propstat: smallvec![PropStat {
+                        prop: BTreeMap::from([(Property::ResourceType, PropValue::Collection)]),
+                        status: "HTTP/1.1 200 OK",
+                    }],
+                    status: None,
+                    location: None,
+                },
+                DavResponse {
+                    href: Href::from_path("/foo/bar.txt"),
+                    propstat: SmallVec::new(),
+                    status: Some("HTTP/1.1 502 BAD GATEWAY".into()),
+                    location: None,
+                },
+            ],
+        };
+        assert_eq!(
+            value.to_xml(),
+            indoc! {r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <multistatus xmlns="DAV:">
+                <response>
+                    <href>/foo/</href>
+                    <propstat>
+                        <prop>
+                            <resourcetype>
+                                <collection />
+                            </resourcetype>
+                        </prop>
+                        <status>HTTP/1.1 200 OK</status>
+                    </propstat>
+                </response>
+                <response>
+                    <href>/foo/bar.txt</href>
+                    <status>HTTP/1.1 502 BAD GATEWAY</status>
+                </response>
+            </multistatus>
+        "#}
+        );
+    }
+
+    fn named_response(path: &str, name: &str) -> DavResponse {
+        DavResponse {
+            href: Href::from_path(path),
+            propstat: smallvec![PropStat {
+                prop: BTreeMap::from([(Property::DisplayName, PropValue::String(name.into()))]),
+                status: "HTTP/1.1 200 OK",
+            }],
+            status: None,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn into_xml_limited_truncated() {
+        let value = Multistatus {
+            response: vec![
+                named_response("/foo/", "foo"),
+                named_response("/foo/bar.txt", "bar.txt"),
+                named_response("/foo/baz.txt", "baz.txt"),
+                named_response("/foo/quux.txt", "quux.txt"),
+            ],
+        };
+        assert_eq!(
+            value.into_xml_limited(300),
+            indoc! {r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <multistatus xmlns="DAV:">
+                <response>
+                    <href>/foo/</href>
+                    <propstat>
+                        <prop>
+                            <displayname>foo</displayname>
+                            <truncated xmlns="https://github.com/dandi/dandidav/ns">2</truncated>
+                            <continuation xmlns="https://github.com/dandi/dandidav/ns">/foo/baz.txt</continuation>
+                        </prop>
+                        <status>HTTP/1.1 200 OK</status>
+                    </propstat>
+                </response>
+                <response>
+                    <href>/foo/bar.txt</href>
+                    <propstat>
+                        <prop>
+                            <displayname>bar.txt</displayname>
+                        </prop>
+                        <status>HTTP/1.1 200 OK</status>
+                    </propstat>
+                </response>
+            </multistatus>
+            "#}
+        );
+    }
+
+    #[test]
+    fn encoder_matches_to_xml() {
+        let value = Multistatus {
+            response: vec![
+                named_response("/foo/", "foo"),
+                named_response("/foo/bar.txt", "bar.txt"),
+                named_response("/foo/baz.txt", "baz.txt"),
+            ],
+        };
+        let mut encoder = MultistatusEncoder::new();
+        let mut xml = String::new();
+        for r in &value.response {
+            encoder.push(r);
+            xml.push_str(&encoder.take());
+        }
+        xml.push_str(&encoder.finish());
+        assert_eq!(xml, value.to_xml());
+    }
+
+    #[test]
+    fn into_xml_limited_keeps_first_response() {
+        let value = Multistatus {
+            response: vec![
+                named_response("/foo/", "foo"),
+                named_response("/foo/bar.txt", "bar.txt"),
+            ],
+        };
+        let xml = value.into_xml_limited(1);
+        let resources = testutils::parse_propfind_response(&xml).unwrap();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].href, "/foo/");
+        assert!(xml.contains(">1</truncated>"));
+    }
+
+    #[test]
+    fn into_xml_limited_under_limit() {
+        let value = Multistatus {
+            response: vec![
+                named_response("/foo/", "foo"),
+                named_response("/foo/bar.txt", "bar.txt"),
+            ],
+        };
+        assert_eq!(value.clone().into_xml_limited(1_000_000), value.to_xml());
+    }
+
+    #[test]
+    fn has_errors() {
+        let mut value = Multistatus {
+            response: vec![
+                named_response("/foo/", "foo"),
+                named_response("/foo/bar.txt", "bar.txt"),
+            ],
+        };
+        value.response[1].propstat.push(PropStat {
+            prop: BTreeMap::from([(Property::GetContentType, PropValue::Empty)]),
+            status: "HTTP/1.1 404 NOT FOUND",
+        });
+        assert!(!value.has_errors());
+        value.response[1].propstat.push(PropStat {
+            prop: BTreeMap::from([(Property::EntryCount, PropValue::Empty)]),
+            status: "HTTP/1.1 500 INTERNAL SERVER ERROR",
+        });
+        assert!(value.has_errors());
+        value.response[1] = DavResponse {
+            href: Href::from_path("/foo/baz.txt"),
+            propstat: SmallVec::new(),
+            status: Some("HTTP/1.1 502 BAD GATEWAY".into()),
+            location: None,
+        };
+        assert!(value.has_errors());
+    }
+
+    mod escaping {
+        use super::*;
+        use crate::dav::util::arb_hostile_component;
+        use pretty_assertions::assert_eq;
+        use proptest::prelude::*;
+        use testutils::{parse_propfind_response, Trinary};
+
+        proptest! {
+            #[test]
+            fn hostile_names(name in arb_hostile_component()) {
+                let href = Href::from_path(&format!("/foo/{name}"));
+                let value = Multistatus {
+                    response: vec![DavResponse {
+                        href: href.clone(),
+                        propstat: smallvec![PropStat {
+                            prop: BTreeMap::from([
+                                (Property::DisplayName, PropValue::String(name.to_string())),
+                                (Property::ResourceType, PropValue::Empty),
+                            ]),
+                            status: "HTTP/1.1 200 OK",
+                        }],
+                        status: None,
+                        location: None,
+                    }],
+                };
+                let resources = parse_propfind_response(&value.to_xml()).unwrap();
+                prop_assert_eq!(resources.len(), 1);
+                prop_assert_eq!(&resources[0].href, href.as_ref());
+                // quick-xml trims leading & trailing whitespace from text
+                prop_assert_eq!(
+                    &resources[0].display_name,
+                    &Trinary::Set(name.trim().to_owned())
+                );
+            }
+
+            #[test]
+            fn arbitrary_names(
+                name in proptest::collection::vec(
+                    prop_oneof![
+                        prop::char::range('\0', '\u{1F}'),
+                        prop::char::range('\u{FFFE}', '\u{FFFF}'),
+                        any::<char>(),
+                    ],
+                    0..32,
+                ).prop_map(String::from_iter)
+            ) {
+                let value = Multistatus {
+                    response: vec![DavResponse {
+                        href: Href::from_path("/foo"),
+                        propstat: smallvec![PropStat {
+                            prop: BTreeMap::from([(
+                                Property::DisplayName,
+                                PropValue::String(name),
+                            )]),
+                            status: "HTTP/1.1 200 OK",
+                        }],
+                        status: None,
+                        location: None,
+                    }],
+                };
+                let xml = value.to_xml();
+                prop_assert!(xml.chars().all(is_xml_char), "Invalid character in XML: {xml:?}");
+                prop_assert!(parse_propfind_response(&xml).is_ok(), "Malformed XML: {xml:?}");
+            }
+        }
+
+        #[test]
+        fn control_characters() {
+            let value = Multistatus {
+                response: vec![DavResponse {
+                    href: Href::from_path("/foo/bar\u{1}\u{1B}[31mbaz\u{FFFF}"),
+                    propstat: smallvec![PropStat {
+                        prop: BTreeMap::from([(
+                            Property::DisplayName,
+                            PropValue::String("bar\u{1}\u{1B}[31mbaz\u{FFFF}".into()),
+                        )]),
+                        status: "HTTP/1.1 200 OK",
+                    }],
+                    status: None,
+                    location: None,
+                }],
+            };
+            let resources = parse_propfind_response(&value.to_xml()).unwrap();
+            assert_eq!(resources.len(), 1);
+            assert_eq!(resources[0].href, "/foo/bar%01%1B%5B31mbaz%EF%BF%BF");
+            assert_eq!(
+                resources[0].display_name,
+                Trinary::Set("bar\u{FFFD}\u{FFFD}[31mbaz\u{FFFD}".into())
+            );
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use super::multistatus::{DavResponse, PropStat};
 use super::{PropValue, Property, Tag};
+use crate::dav::policy::PropertyPolicy;
 use crate::dav::types::HasProperties;
 use axum::{
     body::Body,
@@ -8,12 +9,13 @@ use axum::{
     response::IntoResponse,
 };
 use bytes::{Buf, Bytes};
+use smallvec::SmallVec;
 use std::collections::BTreeMap;
 use std::fmt;
 use thiserror::Error;
 use xml::reader::{Error as XmlError, ParserConfig2, XmlEvent};
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub(in crate::dav) enum PropFind {
     AllProp { include: Vec<Property> },
     Prop(Vec<Property>),
@@ -44,57 +46,99 @@ impl PropFind {
         parser.finish().map_err(Into::into)
     }
 
-    pub(in crate::dav) fn find<P: HasProperties>(&self, res: &P) -> DavResponse {
+    /// Evaluate the query against the given resource.  Requested properties
+    /// that the resource does not have are reported with a 404 status, and
+    /// properties whose values could not be determined are reported with a
+    /// 500 status.  If the resource's details could not be retrieved at all,
+    /// only the resource's status is reported.  Properties omitted by
+    /// `policy` are treated as though the resource did not have them.
+    pub(in crate::dav) fn find<P: HasProperties>(
+        &self,
+        res: &P,
+        policy: &PropertyPolicy,
+    ) -> DavResponse {
+        if let Some(status) = res.status() {
+            return DavResponse {
+                href: res.href(),
+                propstat: SmallVec::new(),
+                status: Some(format!(
+                    "HTTP/1.1 {} {}",
+                    status.as_str(),
+                    status.canonical_reason().unwrap_or_default().to_uppercase()
+                )),
+                location: None,
+            };
+        }
         let mut found = BTreeMap::new();
         let mut missing = BTreeMap::new();
+        let mut failed = BTreeMap::new();
+        let mut query = |prop: &Property, report_missing: bool| match policy.property(res, prop) {
+            Ok(Some(value)) => {
+                found.insert(prop.clone(), value);
+            }
+            Ok(None) => {
+                if report_missing {
+                    missing.insert(prop.clone(), PropValue::Empty);
+                }
+            }
+            Err(e) => {
+                tracing::warn!(
+                    href = res.href().as_ref(),
+                    property = ?prop,
+                    error = ?e,
+                    "Failed to determine value of property",
+                );
+                failed.insert(prop.clone(), PropValue::Empty);
+            }
+        };
         match self {
             PropFind::AllProp { include } => {
                 for prop in Property::iter_standard() {
-                    if let Some(value) = res.property(&prop) {
-                        found.insert(prop, value);
-                    }
+                    query(&prop, false);
                 }
                 for prop in include {
-                    if let Some(value) = res.property(prop) {
-                        found.insert(prop.clone(), value);
-                    } else {
-                        missing.insert(prop.clone(), PropValue::Empty);
-                    }
+                    query(prop, true);
                 }
             }
             PropFind::Prop(props) => {
                 for prop in props {
-                    if let Some(value) = res.property(prop) {
-                        found.insert(prop.clone(), value);
-                    } else {
-                        missing.insert(prop.clone(), PropValue::Empty);
-                    }
+                    query(prop, true);
                 }
             }
             PropFind::PropName => {
                 for prop in Property::iter_standard() {
-                    if res.property(&prop).is_some() {
+                    // Properties whose values could not be determined are
+                    // still defined for the resource, so their names are
+                    // reported.
+                    if !matches!(policy.property(res, &prop), Ok(None)) {
                         found.insert(prop, PropValue::Empty);
                     }
                 }
             }
         }
-        let mut propstat = Vec::with_capacity(2);
-        if !found.is_empty() || missing.is_empty() {
+        let mut propstat = SmallVec::new();
+        if !found.is_empty() || (missing.is_empty() && failed.is_empty()) {
             propstat.push(PropStat {
                 prop: found,
-                status: "HTTP/1.1 200 OK".into(),
+                status: "HTTP/1.1 200 OK",
             });
         }
         if !missing.is_empty() {
             propstat.push(PropStat {
                 prop: missing,
-                status: "HTTP/1.1 404 NOT FOUND".into(),
+                status: "HTTP/1.1 404 NOT FOUND",
+            });
+        }
+        if !failed.is_empty() {
+            propstat.push(PropStat {
+                prop: failed,
+                status: "HTTP/1.1 500 INTERNAL SERVER ERROR",
             });
         }
         DavResponse {
             href: res.href(),
             propstat,
+            status: None,
             // TODO: Should `location` be set to redirect URLs?
             location: None,
         }
@@ -336,6 +380,200 @@ mod tests {
     use super::*;
     use indoc::indoc;
 
+    mod find {
+        use super::*;
+        use crate::dandi::{BadZarrChecksum, VersionCitation};
+        use crate::dav::types::{
+            ArchiveIds, DavCollection, DavItem, DavUnavailable, PropertyError, ResourceKind,
+        };
+        use crate::dav::util::Href;
+        use crate::dav::ErrorClass;
+        use crate::zarrman::{ManifestEntry, ManifestFolder, ZarrNodeType};
+        use pretty_assertions::assert_eq;
+        use rstest::rstest;
+
+        fn bad_zarr() -> DavCollection {
+            DavCollection {
+                path: Some("foo/bar.zarr/".parse().unwrap()),
+                created: None,
+                modified: None,
+                size: Some(42),
+                entry_count: Err(PropertyError::ZarrEntryCount(BadZarrChecksum {
+                    asset_id: "0123".into(),
+                    checksum: "not-a-checksum".into(),
+                })),
+                permalink: None,
+                archive_ids: ArchiveIds::default(),
+                citation: VersionCitation::default(),
+                kind: ResourceKind::Zarr,
+                metadata_url: None,
+                zarr_node_type: None,
+                display_name: None,
+            }
+        }
+
+        #[test]
+        fn prop_with_error() {
+            let custom = Property::Custom(Tag {
+                namespace: "http://ns.example.com/boxschema/".into(),
+                name: "author".into(),
+            });
+            let query = PropFind::Prop(vec![
+                Property::DisplayName,
+                Property::EntryCount,
+                custom.clone(),
+            ]);
+            let response = query.find(&bad_zarr(), &PropertyPolicy::default());
+            assert_eq!(
+                response.propstat.into_vec(),
+                vec![
+                    PropStat {
+                        prop: BTreeMap::from([(
+                            Property::DisplayName,
+                            PropValue::String("bar.zarr".into())
+                        )]),
+                        status: "HTTP/1.1 200 OK",
+                    },
+                    PropStat {
+                        prop: BTreeMap::from([(custom, PropValue::Empty)]),
+                        status: "HTTP/1.1 404 NOT FOUND",
+                    },
+                    PropStat {
+                        prop: BTreeMap::from([(Property::EntryCount, PropValue::Empty)]),
+                        status: "HTTP/1.1 500 INTERNAL SERVER ERROR",
+                    },
+                ]
+            );
+        }
+
+        #[test]
+        fn only_error() {
+            let query = PropFind::Prop(vec![Property::EntryCount]);
+            let response = query.find(&bad_zarr(), &PropertyPolicy::default());
+            assert_eq!(
+                response.propstat.into_vec(),
+                vec![PropStat {
+                    prop: BTreeMap::from([(Property::EntryCount, PropValue::Empty)]),
+                    status: "HTTP/1.1 500 INTERNAL SERVER ERROR",
+                }]
+            );
+        }
+
+        #[test]
+        fn allprop_include_error() {
+            let query = PropFind::AllProp {
+                include: vec![Property::EntryCount],
+            };
+            let response = query.find(&bad_zarr(), &PropertyPolicy::default());
+            assert_eq!(response.propstat.len(), 2);
+            assert_eq!(response.propstat[0].status, "HTTP/1.1 200 OK");
+            assert!(response.propstat[0]
+                .prop
+                .contains_key(&Property::GetContentLength));
+            assert_eq!(
+                response.propstat[1],
+                PropStat {
+                    prop: BTreeMap::from([(Property::EntryCount, PropValue::Empty)]),
+                    status: "HTTP/1.1 500 INTERNAL SERVER ERROR",
+                }
+            );
+        }
+
+        #[rstest]
+        #[case(ErrorClass::BadGateway, "HTTP/1.1 502 BAD GATEWAY")]
+        #[case(ErrorClass::NotFound, "HTTP/1.1 404 NOT FOUND")]
+        fn unavailable(#[case] class: ErrorClass, #[case] status: &str) {
+            let res = DavUnavailable {
+                path: "foo/bar.zarr/0/0.1".parse().unwrap(),
+                class,
+            };
+            for query in [
+                PropFind::default(),
+                PropFind::Prop(vec![Property::DisplayName]),
+                PropFind::PropName,
+            ] {
+                assert_eq!(
+                    query.find(&res, &PropertyPolicy::default()),
+                    DavResponse {
+                        href: Href::from_path("/foo/bar.zarr/0/0.1"),
+                        propstat: SmallVec::new(),
+                        status: Some(status.into()),
+                        location: None,
+                    }
+                );
+            }
+        }
+
+        #[test]
+        fn manifest_entry_version_id() {
+            let entry = DavItem::from(ManifestEntry {
+                web_path: "zarrs/128/4a1/1284a14f-fe4f-4dc3-b10d-48e5db8bf18d/6ddc4625befef8d6f9796835648162be-509--710206390.zarr/0/0/0/0".parse().unwrap(),
+                size: 1024,
+                modified: time::macros::datetime!(2022-03-16 02:34:44 UTC),
+                etag: "\"b6ab3ee6f4a6d4a1f7d8bd7ee7fb2bbf\"".into(),
+                version_id: "VwOSu7IVLAQcQHcqOesmlrEDm2sL_Tfs".into(),
+                url: "https://dandiarchive.s3.amazonaws.com/zarr/1284a14f-fe4f-4dc3-b10d-48e5db8bf18d/0/0/0/0?versionId=VwOSu7IVLAQcQHcqOesmlrEDm2sL_Tfs".parse().unwrap(),
+            });
+            let query = PropFind::Prop(vec![Property::S3VersionId]);
+            assert_eq!(
+                query
+                    .find(&entry, &PropertyPolicy::default())
+                    .propstat
+                    .into_vec(),
+                vec![PropStat {
+                    prop: BTreeMap::from([(
+                        Property::S3VersionId,
+                        PropValue::String("VwOSu7IVLAQcQHcqOesmlrEDm2sL_Tfs".into())
+                    )]),
+                    status: "HTTP/1.1 200 OK",
+                }]
+            );
+        }
+
+        #[rstest]
+        #[case(ZarrNodeType::Group, "group")]
+        #[case(ZarrNodeType::Array, "array")]
+        fn manifest_folder_node_type(#[case] node_type: ZarrNodeType, #[case] value: &str) {
+            let folder = DavCollection::from(ManifestFolder {
+                web_path: "zarrs/128/4a1/1284a14f-fe4f-4dc3-b10d-48e5db8bf18d/6ddc4625befef8d6f9796835648162be-509--710206390.zarr/labels/".parse().unwrap(),
+                node_type: Some(node_type),
+            });
+            let query = PropFind::Prop(vec![Property::ZarrNodeType]);
+            assert_eq!(
+                query
+                    .find(&folder, &PropertyPolicy::default())
+                    .propstat
+                    .into_vec(),
+                vec![PropStat {
+                    prop: BTreeMap::from([(
+                        Property::ZarrNodeType,
+                        PropValue::String(value.into())
+                    )]),
+                    status: "HTTP/1.1 200 OK",
+                }]
+            );
+        }
+
+        #[test]
+        fn manifest_folder_no_node_type() {
+            let folder = DavCollection::from(ManifestFolder {
+                web_path: "zarrs/128/4a1/1284a14f-fe4f-4dc3-b10d-48e5db8bf18d/6ddc4625befef8d6f9796835648162be-509--710206390.zarr/0/0/".parse().unwrap(),
+                node_type: None,
+            });
+            let query = PropFind::Prop(vec![Property::ZarrNodeType]);
+            assert_eq!(
+                query
+                    .find(&folder, &PropertyPolicy::default())
+                    .propstat
+                    .into_vec(),
+                vec![PropStat {
+                    prop: BTreeMap::from([(Property::ZarrNodeType, PropValue::Empty)]),
+                    status: "HTTP/1.1 404 NOT FOUND",
+                }]
+            );
+        }
+    }
+
     #[test]
     fn parse_prop() {
         let s = indoc! {r#"
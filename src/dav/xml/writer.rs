@@ -0,0 +1,265 @@
+//! A low-level writer of XML documents
+use std::fmt::{self, Write};
+
+/// The string used for each level of indentation in generated documents
+const INDENT: &str = "    ";
+
+/// A writer of indented XML documents that builds the document directly in a
+/// `String`.
+///
+/// Elements that contain child elements have their children placed on
+/// separate, indented lines; elements that contain only text are written on a
+/// single line, and elements without any content are written as empty-element
+/// tags.  Mixing text and child elements in a single element is not
+/// supported.
+pub(super) struct XmlWriter {
+    /// The document written so far
+    buf: String,
+
+    /// The names of the currently-open elements, concatenated
+    names: String,
+
+    /// For each currently-open element, the offset in `names` at which its
+    /// name starts and whether any child elements have been written in it
+    open: Vec<(usize, bool)>,
+
+    /// The default namespaces declared by the currently-open elements,
+    /// concatenated
+    namespaces: String,
+
+    /// For each currently-open element that declares a default namespace,
+    /// the element's depth and the offset in `namespaces` at which the
+    /// namespace starts
+    declared: Vec<(usize, usize)>,
+
+    /// Whether the most recent start tag has yet to be closed with a `>`,
+    /// i.e., whether the element it opened is still empty
+    in_start_tag: bool,
+}
+
+impl XmlWriter {
+    /// Create a writer that writes to `buf` (after clearing it), reserving
+    /// room for a document of `capacity` bytes
+    pub(super) fn new(mut buf: String, capacity: usize) -> Self {
+        buf.clear();
+        buf.reserve(capacity);
+        buf.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        XmlWriter {
+            buf,
+            names: String::new(),
+            open: Vec::new(),
+            namespaces: String::new(),
+            declared: Vec::new(),
+            in_start_tag: false,
+        }
+    }
+
+    /// Return the number of bytes written so far
+    pub(super) fn size(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub(super) fn into_string(self) -> String {
+        self.buf
+    }
+
+    /// Remove & return the output written since the last call to `take()`
+    /// (or since the writer was created), leaving any open elements open
+    pub(super) fn take(&mut self) -> String {
+        std::mem::take(&mut self.buf)
+    }
+
+    pub(super) fn tag_xmlns<F>(&mut self, name: &str, ns: &str, func: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        self.start_tag_ns(name, ns);
+        func(self);
+        self.end_tag();
+    }
+
+    pub(super) fn tag<F>(&mut self, name: &str, func: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        self.start_tag(name);
+        func(self);
+        self.end_tag();
+    }
+
+    pub(super) fn start_tag(&mut self, name: &str) {
+        self.start_element(name, None);
+    }
+
+    /// Start an element with the given name in which the default namespace is
+    /// `ns`.  The namespace is only declared if it differs from the default
+    /// namespace already in scope.
+    pub(super) fn start_tag_ns(&mut self, name: &str, ns: &str) {
+        self.start_element(name, Some(ns));
+    }
+
+    fn start_element(&mut self, name: &str, ns: Option<&str>) {
+        self.close_start_tag();
+        if let Some((_, has_children)) = self.open.last_mut() {
+            *has_children = true;
+        }
+        self.newline(self.open.len());
+        self.buf.push('<');
+        self.buf.push_str(name);
+        if let Some(ns) = ns.filter(|&ns| self.default_namespace() != Some(ns)) {
+            self.buf.push_str(" xmlns=\"");
+            push_escaped(&mut self.buf, ns, true);
+            self.buf.push('"');
+            self.declared.push((self.open.len(), self.namespaces.len()));
+            self.namespaces.push_str(ns);
+        }
+        self.open.push((self.names.len(), false));
+        self.names.push_str(name);
+        self.in_start_tag = true;
+    }
+
+    pub(super) fn end_tag(&mut self) {
+        let (start, has_children) = self
+            .open
+            .pop()
+            .expect("end_tag() should only be called when an element is open");
+        if self.in_start_tag {
+            self.buf.push_str(" />");
+            self.in_start_tag = false;
+        } else {
+            if has_children {
+                self.newline(self.open.len());
+            }
+            self.buf.push_str("</");
+            self.buf.push_str(&self.names[start..]);
+            self.buf.push('>');
+        }
+        self.names.truncate(start);
+        if let Some(&(depth, ns_start)) = self.declared.last() {
+            if depth == self.open.len() {
+                self.declared.pop();
+                self.namespaces.truncate(ns_start);
+            }
+        }
+    }
+
+    /// Return the default namespace currently in scope, if any
+    fn default_namespace(&self) -> Option<&str> {
+        self.declared
+            .last()
+            .map(|&(_, ns_start)| &self.namespaces[ns_start..])
+    }
+
+    pub(super) fn empty_tag(&mut self, name: &str) {
+        self.start_tag(name);
+        self.end_tag();
+    }
+
+    /// Write text content, escaping markup characters and replacing any
+    /// characters that are not allowed in XML documents
+    pub(super) fn text(&mut self, text: &str) {
+        self.close_start_tag();
+        push_escaped(&mut self.buf, text, false);
+    }
+
+    /// Write the `Display` representation of a number as text content
+    pub(super) fn number<N: fmt::Display>(&mut self, n: N) {
+        self.close_start_tag();
+        let _ = write!(self.buf, "{n}");
+    }
+
+    pub(super) fn text_tag(&mut self, name: &str, text: &str) {
+        self.start_tag(name);
+        self.text(text);
+        self.end_tag();
+    }
+
+    fn close_start_tag(&mut self) {
+        if std::mem::take(&mut self.in_start_tag) {
+            self.buf.push('>');
+        }
+    }
+
+    fn newline(&mut self, level: usize) {
+        self.buf.push('\n');
+        for _ in 0..level {
+            self.buf.push_str(INDENT);
+        }
+    }
+}
+
+/// Append `s` to `buf`, escaping characters that are significant in XML
+/// markup and replacing characters that cannot appear in XML documents with
+/// U+FFFD REPLACEMENT CHARACTER (see
+/// [`sanitize_xml_text()`](super::sanitize_xml_text)).  If `attribute`
+/// is true, `s` is escaped for use as a double-quoted attribute value.
+fn push_escaped(buf: &mut String, s: &str, attribute: bool) {
+    let mut copied = 0;
+    for (i, c) in s.char_indices() {
+        let replacement = match c {
+            '<' => "&lt;",
+            '>' => "&gt;",
+            '&' => "&amp;",
+            '"' if attribute => "&quot;",
+            '\'' if attribute => "&apos;",
+            // Literal whitespace in attribute values is normalized to spaces
+            // by XML parsers, so it must be written as character references
+            // in order to survive a round trip
+            '\t' if attribute => "&#9;",
+            '\n' if attribute => "&#10;",
+            '\r' if attribute => "&#13;",
+            c if !is_xml_char(c) => "\u{FFFD}",
+            _ => continue,
+        };
+        buf.push_str(&s[copied..i]);
+        buf.push_str(replacement);
+        copied = i + c.len_utf8();
+    }
+    buf.push_str(&s[copied..]);
+}
+
+/// Returns `true` iff `c` is a character that may appear in an XML 1.0
+/// document, either literally or as a character reference.
+///
+/// See <https://www.w3.org/TR/xml/#charsets>.
+pub(super) fn is_xml_char(c: char) -> bool {
+    matches!(
+        c,
+        '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("plain", false, "plain")]
+    #[case("<a & b>", false, "&lt;a &amp; b&gt;")]
+    #[case("\"it's\"", false, "\"it's\"")]
+    #[case("\"it's\"", true, "&quot;it&apos;s&quot;")]
+    #[case("a\tb\nc\rd", false, "a\tb\nc\rd")]
+    #[case("a\tb\nc\rd", true, "a&#9;b&#10;c&#13;d")]
+    #[case("bad\u{1}char\u{FFFF}", true, "bad\u{FFFD}char\u{FFFD}")]
+    fn test_push_escaped(#[case] s: &str, #[case] attribute: bool, #[case] expected: &str) {
+        let mut buf = String::from("=");
+        push_escaped(&mut buf, s, attribute);
+        assert_eq!(buf, format!("={expected}"));
+    }
+
+    #[test]
+    fn attribute_whitespace() {
+        let mut writer = XmlWriter::new(String::new(), 0);
+        writer.tag_xmlns("root", "urn:x\ty\nz", |writer| writer.empty_tag("child"));
+        assert_eq!(
+            writer.into_string(),
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<root xmlns=\"urn:x&#9;y&#10;z\">\n",
+                "    <child />\n",
+                "</root>",
+            )
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Atom feed of recent Dandiset version publications
+use crate::atom::{AtomEntry, AtomFeed};
+use crate::consts::{
+    ATOM_CONTENT_TYPE, PUBLICATION_FEED_CONCURRENCY, PUBLICATION_FEED_SIZE, PUBLICATION_FEED_TTL,
+};
+use crate::dandi::{DandiClient, DandiError, DandisetId, DandisetVersion, VersionId};
+use crate::httputil::HttpUrl;
+use crate::refresh::{cache_lifetime, Fetched, Refreshing};
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, response::Response},
+    response::IntoResponse,
+};
+use futures_util::{StreamExt, TryStreamExt};
+use humansize::{format_size_i, BINARY};
+use moka::future::{Cache, CacheBuilder};
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+/// A generator of an Atom feed listing the most recent publication of each
+/// Dandiset, newest first.  The rendered feed is cached for
+/// [`PUBLICATION_FEED_TTL`] so that frequent polling by feed readers does not
+/// result in a crawl of the Archive for every request, and it is re-rendered
+/// in the background when requested shortly before or after it expires.
+#[derive(Clone, Debug)]
+pub(crate) struct PublicationFeed {
+    /// Client for the Archive instance whose publications are listed
+    dandi: DandiClient,
+
+    /// The most recently rendered feed document, stored under the unit key
+    cache: Cache<(), Fetched<Arc<str>>>,
+
+    /// Whether the feed is currently being re-rendered in the background
+    refreshing: Refreshing<()>,
+}
+
+impl PublicationFeed {
+    pub(crate) fn new(dandi: DandiClient) -> PublicationFeed {
+        let cache = CacheBuilder::new(1)
+            .name("publication-feed")
+            .time_to_live(cache_lifetime(PUBLICATION_FEED_TTL))
+            .build();
+        PublicationFeed {
+            dandi,
+            cache,
+            refreshing: Refreshing::new(),
+        }
+    }
+
+    /// Return a response containing the rendered feed
+    pub(crate) async fn response(&self) -> Response<Body> {
+        // Note: This response should not have WebDAV headers (DAV, Allow)
+        let fetch = async {
+            self.render()
+                .await
+                .map(|feed| Fetched::new(feed, PUBLICATION_FEED_TTL))
+        };
+        match self.cache.try_get_with((), fetch).await {
+            Ok(feed) => {
+                if feed.needs_refresh() {
+                    if feed.is_stale() {
+                        tracing::debug!("Serving stale publication feed while refreshing");
+                    }
+                    self.refresh();
+                }
+                ([(CONTENT_TYPE, ATOM_CONTENT_TYPE)], feed.value.to_string()).into_response()
+            }
+            Err(e) => {
+                let class = e.class();
+                let e = anyhow::Error::from(e);
+                tracing::info!(error = ?e, status = class.to_status().as_u16(), "Failed to generate publication feed");
+                (class.to_status(), format!("{e:?}")).into_response()
+            }
+        }
+    }
+
+    /// Spawn a background task for re-rendering the feed and replacing the
+    /// cached document with it.  Nothing is done if the feed is already being
+    /// re-rendered.
+    fn refresh(&self) {
+        let Some(guard) = self.refreshing.start(()) else {
+            return;
+        };
+        let this = self.clone();
+        tokio::spawn(async move {
+            let _guard = guard;
+            match this.render().await {
+                Ok(feed) => {
+                    this.cache
+                        .insert((), Fetched::new(feed, PUBLICATION_FEED_TTL))
+                        .await;
+                }
+                Err(e) => tracing::debug!(error = ?e, "Failed to refresh publication feed"),
+            }
+        });
+    }
+
+    /// Fetch the most recent publications from the Archive and render them
+    /// as an Atom feed
+    async fn render(&self) -> Result<Arc<str>, DandiError> {
+        let mut latest = self
+            .dandi
+            .get_all_dandisets()
+            .try_filter_map(|ds| {
+                std::future::ready(Ok(ds
+                    .most_recent_published_version
+                    .map(|v| (ds.identifier, v))))
+            })
+            .try_collect::<Vec<_>>()
+            .await?;
+        latest.sort_by_key(|(_, v)| std::cmp::Reverse(v.created));
+        latest.truncate(PUBLICATION_FEED_SIZE);
+        let publications = futures_util::stream::iter(latest)
+            .map(|(dandiset_id, version)| async move {
+                let info = self
+                    .dandi
+                    .dandiset(dandiset_id.clone())
+                    .version(version.version.clone())
+                    .get()
+                    .await?;
+                Ok::<_, DandiError>(Publication {
+                    dandiset_id,
+                    version,
+                    doi: info.citation.doi,
+                })
+            })
+            .buffered(PUBLICATION_FEED_CONCURRENCY)
+            .try_collect::<Vec<_>>()
+            .await?;
+        let feed = publications_feed(&publications, self.dandi.api_url());
+        Ok(Arc::from(feed.to_xml()))
+    }
+}
+
+/// A published version of a Dandiset to list in the feed
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Publication {
+    /// The ID of the Dandiset
+    dandiset_id: DandisetId,
+
+    /// Details on the published version
+    version: DandisetVersion,
+
+    /// The version's DOI, if known
+    doi: Option<String>,
+}
+
+impl Publication {
+    /// Return an [`AtomEntry`] describing the publication
+    fn to_entry(&self) -> AtomEntry {
+        let mut summary = vec![format!(
+            "Dandiset {} version {}",
+            self.dandiset_id, self.version.version
+        )];
+        if let Some(doi) = &self.doi {
+            summary.push(format!("DOI: {doi}"));
+        }
+        summary.push(format!(
+            "size: {}",
+            format_size_i(self.version.size, BINARY)
+        ));
+        let link = match &self.version.version {
+            VersionId::Published(v) => format!("/dandisets/{}/releases/{}/", self.dandiset_id, v),
+            VersionId::Draft => format!("/dandisets/{}/draft/", self.dandiset_id),
+        };
+        AtomEntry {
+            id: self.version.metadata_url.to_string(),
+            title: self.version.name.clone(),
+            updated: self.version.created,
+            link,
+            summary: Some(summary.join("; ")),
+        }
+    }
+}
+
+/// Construct an Atom feed of `publications` (sorted newest first) on the
+/// Archive instance with API URL `api_url`
+fn publications_feed(publications: &[Publication], api_url: &HttpUrl) -> AtomFeed {
+    AtomFeed {
+        id: format!("{api_url}#publications"),
+        title: String::from("DANDI Archive publications"),
+        author: String::from("DANDI Archive"),
+        updated: publications
+            .first()
+            .map_or(OffsetDateTime::UNIX_EPOCH, |p| p.version.created),
+        entries: publications.iter().map(Publication::to_entry).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn publication_entry() {
+        let publication = Publication {
+            dandiset_id: "000001".parse().unwrap(),
+            version: DandisetVersion {
+                version: VersionId::Published("0.230629.1955".parse().unwrap()),
+                name: String::from("Brainscan of a Unicorn"),
+                size: 176031,
+                created: datetime!(2023-06-29 19:55:31.388489 UTC),
+                modified: datetime!(2023-06-29 19:55:35.080882 UTC),
+                metadata_url: "https://api.example.com/dandisets/000001/versions/0.230629.1955/"
+                    .parse()
+                    .unwrap(),
+            },
+            doi: Some(String::from("10.48324/dandi.000001/0.230629.1955")),
+        };
+        assert_eq!(
+            publication.to_entry(),
+            AtomEntry {
+                id: String::from("https://api.example.com/dandisets/000001/versions/0.230629.1955/"),
+                title: String::from("Brainscan of a Unicorn"),
+                updated: datetime!(2023-06-29 19:55:31.388489 UTC),
+                link: String::from("/dandisets/000001/releases/0.230629.1955/"),
+                summary: Some(String::from(
+                    "Dandiset 000001 version 0.230629.1955; DOI: 10.48324/dandi.000001/0.230629.1955; size: 171.91 KiB"
+                )),
+            }
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Tracking of the most frequently downloaded paths
+use crate::consts::{HOT_OBJECT_PROMOTION_THRESHOLD, HOT_OBJECT_REPORT_SIZE};
+use crate::openapi::ApiSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+/// A bounded tracker of how often each downloadable path is requested
+///
+/// At most `capacity` paths are tracked at once.  When a new path is requested
+/// while the tracker is full, the least-requested tracked path is replaced by
+/// the new one, which inherits the replaced path's count plus one (the
+/// "Space-Saving" algorithm).  As a result, counts are upper bounds, but every
+/// path requested more than `total / capacity` times is guaranteed to be
+/// tracked.
+#[derive(Debug)]
+pub(crate) struct HotObjects {
+    /// Request counts for the tracked paths
+    counts: Mutex<HashMap<String, u64>>,
+
+    /// The maximum number of paths to track
+    capacity: usize,
+}
+
+impl HotObjects {
+    /// Construct a new `HotObjects` that tracks up to `capacity` paths
+    pub(crate) fn new(capacity: usize) -> HotObjects {
+        HotObjects {
+            counts: Mutex::new(HashMap::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Record a request for `path` and return whether the path is now hot
+    /// enough to be promoted into caches
+    pub(crate) fn record(&self, path: &str) -> bool {
+        if self.capacity == 0 {
+            return false;
+        }
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        let count = if let Some(c) = counts.get_mut(path) {
+            *c += 1;
+            *c
+        } else {
+            let mut count = 1;
+            if counts.len() >= self.capacity {
+                if let Some((victim, min)) = counts
+                    .iter()
+                    .min_by_key(|&(_, &c)| c)
+                    .map(|(p, &c)| (p.clone(), c))
+                {
+                    counts.remove(&victim);
+                    count += min;
+                }
+            }
+            counts.insert(path.to_owned(), count);
+            count
+        };
+        count >= HOT_OBJECT_PROMOTION_THRESHOLD
+    }
+
+    /// Return a report on the most frequently requested tracked paths
+    pub(crate) fn report(&self) -> HotObjectReport {
+        let counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut objects = counts
+            .iter()
+            .map(|(path, &requests)| HotObject {
+                path: path.clone(),
+                requests,
+                promoted: requests >= HOT_OBJECT_PROMOTION_THRESHOLD,
+            })
+            .collect::<Vec<_>>();
+        drop(counts);
+        objects.sort_by(|a, b| {
+            b.requests
+                .cmp(&a.requests)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        objects.truncate(HOT_OBJECT_REPORT_SIZE);
+        HotObjectReport {
+            tracked_paths: self.capacity,
+            promotion_threshold: HOT_OBJECT_PROMOTION_THRESHOLD,
+            objects,
+        }
+    }
+}
+
+/// A report on the most frequently requested downloadable paths, as served at
+/// `/.admin/hot-objects`
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct HotObjectReport {
+    /// The maximum number of paths tracked at once
+    pub(crate) tracked_paths: usize,
+
+    /// The number of requests at which a path is promoted into caches
+    pub(crate) promotion_threshold: u64,
+
+    /// The most frequently requested paths, most requested first
+    pub(crate) objects: Vec<HotObject>,
+}
+
+/// Request statistics for a single downloadable path
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct HotObject {
+    /// The request path
+    pub(crate) path: String,
+
+    /// The approximate number of requests made for the path
+    pub(crate) requests: u64,
+
+    /// Whether the path has been requested often enough to be promoted into
+    /// caches
+    pub(crate) promoted: bool,
+}
+
+impl ApiSchema for HotObjectReport {
+    fn schema() -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "description": "The most frequently requested downloadable paths",
+            "properties": {
+                "tracked_paths": {"type": "integer"},
+                "promotion_threshold": {"type": "integer"},
+                "objects": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "path": {"type": "string"},
+                            "requests": {"type": "integer"},
+                            "promoted": {"type": "boolean"},
+                        },
+                        "required": ["path", "requests", "promoted"],
+                    },
+                },
+            },
+            "required": ["tracked_paths", "promotion_threshold", "objects"],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_and_promote() {
+        let hot = HotObjects::new(10);
+        for _ in 1..HOT_OBJECT_PROMOTION_THRESHOLD {
+            assert!(!hot.record("/a"));
+        }
+        assert!(hot.record("/a"));
+        assert!(!hot.record("/b"));
+        let report = hot.report();
+        assert_eq!(report.tracked_paths, 10);
+        assert_eq!(
+            report.objects,
+            [
+                HotObject {
+                    path: "/a".into(),
+                    requests: HOT_OBJECT_PROMOTION_THRESHOLD,
+                    promoted: true,
+                },
+                HotObject {
+                    path: "/b".into(),
+                    requests: 1,
+                    promoted: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn replace_least_requested() {
+        let hot = HotObjects::new(2);
+        hot.record("/a");
+        hot.record("/a");
+        hot.record("/b");
+        hot.record("/c");
+        let report = hot.report();
+        let objects = report
+            .objects
+            .iter()
+            .map(|obj| (obj.path.as_str(), obj.requests))
+            .collect::<Vec<_>>();
+        assert_eq!(objects, [("/a", 2), ("/c", 2)]);
+    }
+
+    #[test]
+    fn disabled() {
+        let hot = HotObjects::new(0);
+        for _ in 0..HOT_OBJECT_PROMOTION_THRESHOLD {
+            assert!(!hot.record("/a"));
+        }
+        assert!(hot.report().objects.is_empty());
+    }
+}
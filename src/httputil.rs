@@ -1,7 +1,15 @@
 //! HTTP utilities
-use crate::consts::USER_AGENT;
+use crate::budget::{charge, BudgetExceeded};
+use crate::chaos::ChaosMonkey;
+use crate::consts::{JSON_CONTENT_TYPE, USER_AGENT};
+use crate::dandisetlimit;
 use crate::dav::ErrorClass;
-use reqwest::{Method, Request, Response, StatusCode};
+use crate::recorder::FixtureRecorder;
+use bytes::Bytes;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE, LOCATION, RANGE},
+    redirect, Method, Request, Response, StatusCode,
+};
 use reqwest_middleware::{Middleware, Next};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use serde::{
@@ -11,36 +19,35 @@ use serde::{
 use std::fmt;
 use std::future::Future;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 use tracing::Instrument;
 use url::Url;
 
+/// The number of times that clients retry failed requests by default
+const DEFAULT_MAX_RETRIES: u32 = 4;
+
 /// An HTTP client that logs all requests and retries failed requests
 #[derive(Debug, Clone)]
 pub(crate) struct Client(reqwest_middleware::ClientWithMiddleware);
 
 impl Client {
-    /// Construct a new client
+    /// Construct a new client with the default middleware stack.  If
+    /// `recorder` is given, the responses to all `GET` requests made by the
+    /// client are recorded by it.
     ///
     /// # Errors
     ///
     /// Returns an error if construction of the inner `reqwest::Client` fails
-    pub(crate) fn new() -> Result<Client, BuildClientError> {
-        let retry_policy = ExponentialBackoff::builder()
-            .base(2)
-            .build_with_max_retries(4);
-        let client = reqwest_middleware::ClientBuilder::new(
-            reqwest::ClientBuilder::new()
-                .user_agent(USER_AGENT)
-                .timeout(std::time::Duration::from_secs(10))
-                .build()?,
-        )
-        .with(SimpleReqwestLogger)
-        // Retry network errors and responses of 408, 429, or 5xx up to four
-        // times, sleeping for 1s/2s/4s/8s before each retry attempt.
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build();
-        Ok(Client(client))
+    pub(crate) fn new(recorder: Option<Arc<FixtureRecorder>>) -> Result<Client, BuildClientError> {
+        Client::builder().recorder(recorder).build()
+    }
+
+    /// Return a [`ClientBuilder`] for configuring a client for a specific
+    /// backend
+    pub(crate) fn builder() -> ClientBuilder {
+        ClientBuilder::new()
     }
 
     /// Perform an HTTP request with the given method to the given URL
@@ -54,40 +61,171 @@ impl Client {
         method: Method,
         url: HttpUrl,
     ) -> Result<Response, HttpError> {
+        let r = self.send(method, url.clone()).await?;
+        check_status(url, r)
+    }
+
+    /// Perform an HTTP request with the given method to the given URL and
+    /// return the response regardless of its status
+    ///
+    /// # Errors
+    ///
+    /// If sending the request fails or the current incoming request's budget
+    /// of upstream requests has been spent, an error is returned.
+    pub(crate) async fn send(&self, method: Method, url: HttpUrl) -> Result<Response, HttpError> {
+        charge().map_err(|source| HttpError::Budget {
+            url: url.clone(),
+            source,
+        })?;
+        let _permit = dandisetlimit::acquire().await;
+        self.0
+            .request(method, Url::from(url.clone()))
+            .send()
+            .await
+            .map_err(|source| HttpError::Send { url, source })
+    }
+
+    /// Perform a `GET` request to the given URL for the byte range(s) given by
+    /// the `Range` header value `range`
+    ///
+    /// # Errors
+    ///
+    /// If sending the request fails or the response has a 4xx or 5xx status
+    /// other than 416 ("Range Not Satisfiable"), an error is returned.
+    pub(crate) async fn get_range(
+        &self,
+        url: HttpUrl,
+        range: HeaderValue,
+    ) -> Result<Response, HttpError> {
+        charge().map_err(|source| HttpError::Budget {
+            url: url.clone(),
+            source,
+        })?;
+        let _permit = dandisetlimit::acquire().await;
         let r = self
             .0
-            .request(method, Url::from(url.clone()))
+            .get(Url::from(url.clone()))
+            .header(RANGE, range)
             .send()
             .await
             .map_err(|source| HttpError::Send {
                 url: url.clone(),
                 source,
             })?;
-        if r.status() == StatusCode::NOT_FOUND {
-            return Err(HttpError::NotFound { url });
+        if r.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            return Ok(r);
         }
-        r.error_for_status()
-            .map_err(|source| HttpError::Status { url, source })
+        check_status(url, r)
     }
 
-    /// Perform a `HEAD` request to the given URL
+    /// Perform a `GET` request to the given URL
     ///
     /// # Errors
     ///
     /// If sending the request fails or the response has a 4xx or 5xx status,
     /// an error is returned.
-    pub(crate) async fn head(&self, url: HttpUrl) -> Result<Response, HttpError> {
-        self.request(Method::HEAD, url).await
+    pub(crate) async fn get(&self, url: HttpUrl) -> Result<Response, HttpError> {
+        self.request(Method::GET, url).await
     }
 
-    /// Perform a `GET` request to the given URL
+    /// Perform a `GET` request to the given URL and return the complete
+    /// response body
+    ///
+    /// # Errors
+    ///
+    /// If sending the request fails, the response has a 4xx or 5xx status, or
+    /// reading the response body fails, an error is returned.
+    pub(crate) async fn get_bytes(&self, url: HttpUrl) -> Result<Bytes, HttpError> {
+        self.get(url.clone())
+            .await?
+            .bytes()
+            .await
+            .map_err(move |source| HttpError::Read { url, source })
+    }
+
+    /// Perform a `GET` request to the given URL, which is expected to respond
+    /// with a redirect, and return the URL redirected to.  The client must
+    /// have been constructed with `follow_redirects` set to false.
+    ///
+    /// # Errors
+    ///
+    /// If sending the request fails, the response has a 4xx or 5xx status, or
+    /// the response does not redirect to an HTTP(S) URL, an error is returned.
+    pub(crate) async fn get_redirect(&self, url: HttpUrl) -> Result<HttpUrl, HttpError> {
+        let r = self.get(url.clone()).await?;
+        if r.status().is_redirection() {
+            if let Some(target) = r
+                .headers()
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|s| s.parse::<HttpUrl>().ok())
+            {
+                return Ok(target);
+            }
+        }
+        Err(HttpError::NoRedirect {
+            url,
+            status: r.status(),
+        })
+    }
+
+    /// Perform a `POST` request to the given URL with the given JSON body and
+    /// additional headers
     ///
     /// # Errors
     ///
     /// If sending the request fails or the response has a 4xx or 5xx status,
     /// an error is returned.
-    pub(crate) async fn get(&self, url: HttpUrl) -> Result<Response, HttpError> {
-        self.request(Method::GET, url).await
+    pub(crate) async fn post_json(
+        &self,
+        url: HttpUrl,
+        body: String,
+        headers: HeaderMap,
+    ) -> Result<Response, HttpError> {
+        let r = self
+            .0
+            .post(Url::from(url.clone()))
+            .header(CONTENT_TYPE, JSON_CONTENT_TYPE)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await
+            .map_err(|source| HttpError::Send {
+                url: url.clone(),
+                source,
+            })?;
+        check_status(url, r)
+    }
+
+    /// Perform a `GET` request to the given URL with the given additional
+    /// headers and deserialize the response body as JSON into `T`.  `timeout`
+    /// replaces the client's default timeout for the request.
+    ///
+    /// # Errors
+    ///
+    /// If sending the request fails, the response has a 4xx or 5xx status, or
+    /// deserialization of the response body fails, an error is returned.
+    pub(crate) async fn get_json_with_headers<T: DeserializeOwned>(
+        &self,
+        url: HttpUrl,
+        headers: HeaderMap,
+        timeout: Duration,
+    ) -> Result<T, HttpError> {
+        let r = self
+            .0
+            .get(Url::from(url.clone()))
+            .headers(headers)
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(|source| HttpError::Send {
+                url: url.clone(),
+                source,
+            })?;
+        check_status(url.clone(), r)?
+            .json::<T>()
+            .await
+            .map_err(move |source| HttpError::Deserialize { url, source })
     }
 
     /// Perform a `GET` request to the given URL and deserialize the response
@@ -117,6 +255,150 @@ impl Client {
     }
 }
 
+/// A builder for [`Client`]s, letting each backend configure its own stack of
+/// middleware.
+///
+/// Every client logs its requests.  The layers of a client's middleware stack
+/// are, from outermost to innermost:
+///
+/// - logging
+/// - the fixture recorder, if any, so that only the final response to each
+///   request is recorded
+/// - retrying of failed requests, unless disabled
+/// - the middleware added with [`ClientBuilder::with()`], in the order added,
+///   followed by failure injection, if [installed](crate::chaos)
+pub(crate) struct ClientBuilder {
+    /// Headers to send with every request
+    headers: HeaderMap,
+
+    /// Whether to follow redirects
+    follow_redirects: bool,
+
+    /// Recorder of the responses to `GET` requests
+    recorder: Option<Arc<FixtureRecorder>>,
+
+    /// The maximum number of times to retry a failed request
+    max_retries: u32,
+
+    /// Whether the timeout applies to each read rather than to whole requests
+    streaming: bool,
+
+    /// Backend-specific middleware
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl ClientBuilder {
+    fn new() -> ClientBuilder {
+        ClientBuilder {
+            headers: HeaderMap::new(),
+            follow_redirects: true,
+            recorder: None,
+            max_retries: DEFAULT_MAX_RETRIES,
+            streaming: false,
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Send `headers` with every request
+    pub(crate) fn default_headers(mut self, headers: HeaderMap) -> ClientBuilder {
+        self.headers = headers;
+        self
+    }
+
+    /// Set whether to follow redirects.  If false, redirect responses are
+    /// returned to the caller instead.  Defaults to true.
+    pub(crate) fn follow_redirects(mut self, follow: bool) -> ClientBuilder {
+        self.follow_redirects = follow;
+        self
+    }
+
+    /// Record the responses to all `GET` requests made by the client with
+    /// `recorder`, if given
+    pub(crate) fn recorder(mut self, recorder: Option<Arc<FixtureRecorder>>) -> ClientBuilder {
+        self.recorder = recorder;
+        self
+    }
+
+    /// Retry network errors and responses of 408, 429, or 5xx up to `n`
+    /// times, with exponential backoff starting at one second.  Zero disables
+    /// retrying.  Defaults to four.
+    pub(crate) fn max_retries(mut self, n: u32) -> ClientBuilder {
+        self.max_retries = n;
+        self
+    }
+
+    /// Set whether the client's timeout applies to each read from the server
+    /// rather than to each request as a whole, so that large response bodies
+    /// can be streamed for as long as they keep arriving.  Defaults to false.
+    pub(crate) fn streaming(mut self, streaming: bool) -> ClientBuilder {
+        self.streaming = streaming;
+        self
+    }
+
+    /// Add `middleware` to the client's stack beneath the retry middleware,
+    /// so that it sees each attempt at a request
+    pub(crate) fn with<M: Middleware>(mut self, middleware: M) -> ClientBuilder {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Construct the client
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if construction of the inner `reqwest::Client` fails
+    pub(crate) fn build(self) -> Result<Client, BuildClientError> {
+        // Failures are injected innermost so that they are retried and seen
+        // by all other middleware like real failures.
+        let builder = match ChaosMonkey::installed() {
+            Some(monkey) => self.with(monkey),
+            None => self,
+        };
+        builder.build_stack()
+    }
+
+    fn build_stack(self) -> Result<Client, BuildClientError> {
+        let redirect_policy = if self.follow_redirects {
+            redirect::Policy::default()
+        } else {
+            redirect::Policy::none()
+        };
+        let builder = reqwest::ClientBuilder::new()
+            .user_agent(USER_AGENT)
+            .default_headers(self.headers)
+            .redirect(redirect_policy);
+        let builder = if self.streaming {
+            builder.read_timeout(Duration::from_secs(10))
+        } else {
+            builder.timeout(Duration::from_secs(10))
+        };
+        let mut client =
+            reqwest_middleware::ClientBuilder::new(builder.build()?).with(SimpleReqwestLogger);
+        if let Some(recorder) = self.recorder {
+            client = client.with_arc(recorder);
+        }
+        if self.max_retries > 0 {
+            let retry_policy = ExponentialBackoff::builder()
+                .base(2)
+                .build_with_max_retries(self.max_retries);
+            client = client.with(RetryTransientMiddleware::new_with_policy(retry_policy));
+        }
+        for middleware in self.middleware {
+            client = client.with_arc(middleware);
+        }
+        Ok(Client(client.build()))
+    }
+}
+
+/// Return `r` if its status is not 4xx or 5xx; otherwise, return an error
+pub(crate) fn check_status(url: HttpUrl, r: Response) -> Result<Response, HttpError> {
+    if r.status() == StatusCode::NOT_FOUND {
+        return Err(HttpError::NotFound { url });
+    }
+    r.error_for_status()
+        .map_err(|source| HttpError::Status { url, source })
+}
+
 /// Middleware for a `reqwest::Client` that adds logging of HTTP requests and
 /// their responses
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -172,12 +454,40 @@ pub(crate) enum HttpError {
         source: reqwest::Error,
     },
 
+    /// Reading the response body failed
+    #[error("failed to read response body from {url}")]
+    Read {
+        url: HttpUrl,
+        source: reqwest::Error,
+    },
+
     /// Deserializing the response body as JSON failed
     #[error("failed to deserialize response body from {url}")]
     Deserialize {
         url: HttpUrl,
         source: reqwest::Error,
     },
+
+    /// The server did not respond with a redirect to an HTTP(S) URL when one
+    /// was expected
+    #[error("request to {url} returned {status} instead of a redirect")]
+    NoRedirect { url: HttpUrl, status: StatusCode },
+
+    /// Deserializing a previously-read response body as JSON failed
+    #[error("failed to deserialize response body from {url}")]
+    Parse {
+        url: HttpUrl,
+        source: serde_json::Error,
+    },
+
+    /// The request was not made because the incoming request on whose behalf
+    /// it would have been made had already used up its budget of upstream
+    /// requests
+    #[error("not making request to {url}")]
+    Budget {
+        url: HttpUrl,
+        source: BudgetExceeded,
+    },
 }
 
 impl HttpError {
@@ -185,8 +495,11 @@ impl HttpError {
     pub(crate) fn class(&self) -> ErrorClass {
         match self {
             HttpError::NotFound { .. } => ErrorClass::NotFound,
+            HttpError::Budget { .. } => ErrorClass::ServiceUnavailable,
             HttpError::Send { source, .. } if source.is_timeout() => ErrorClass::GatewayTimeout,
-            HttpError::Deserialize { source, .. } if source.is_timeout() => {
+            HttpError::Read { source, .. } | HttpError::Deserialize { source, .. }
+                if source.is_timeout() =>
+            {
                 ErrorClass::GatewayTimeout
             }
             _ => ErrorClass::BadGateway,
@@ -195,7 +508,7 @@ impl HttpError {
 }
 
 /// A wrapper around [`url::Url`] that enforces a scheme of "http" or "https"
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub(crate) struct HttpUrl(Url);
 
 impl HttpUrl {
@@ -377,4 +690,51 @@ mod tests {
             "https://api.github.com/foo?bar=baz&quux=with+space&bar=rod"
         );
     }
+
+    /// Middleware that counts the requests passing through it
+    #[derive(Clone, Debug, Default)]
+    struct Counter(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl Counter {
+        fn get(&self) -> usize {
+            self.0.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Middleware for Counter {
+        async fn handle(
+            &self,
+            req: Request,
+            extensions: &mut axum::http::Extensions,
+            next: Next<'_>,
+        ) -> reqwest_middleware::Result<Response> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            next.run(req, extensions).await
+        }
+    }
+
+    #[rstest]
+    #[case(0, 1)]
+    #[case(1, 2)]
+    #[tokio::test]
+    async fn builder_max_retries(#[case] max_retries: u32, #[case] attempts: usize) {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("GET"))
+            .respond_with(wiremock::ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        let counter = Counter::default();
+        let client = Client::builder()
+            .max_retries(max_retries)
+            .with(counter.clone())
+            .build()
+            .unwrap();
+        let url = server.uri().parse::<HttpUrl>().unwrap();
+        let r = client.get(url).await;
+        assert!(matches!(r, Err(HttpError::Status { .. })));
+        // Added middleware sees every attempt
+        assert_eq!(counter.get(), attempts);
+        assert_eq!(server.received_requests().await.unwrap().len(), attempts);
+    }
 }
@@ -0,0 +1,1293 @@
+//! The `dandidav` server.  The library target exists so that the benchmarks
+//! under `benches/` can reach the server's internals; the binary target is
+//! the actual program.
+#[macro_use]
+mod validstr;
+
+mod admin;
+mod annex;
+mod atom;
+mod audit;
+mod blocking;
+mod budget;
+mod cachepolicy;
+mod changes;
+mod chaos;
+mod consts;
+mod dandi;
+mod dandisetlimit;
+mod dav;
+mod effective;
+mod feed;
+mod hotobjects;
+mod httputil;
+mod logsample;
+mod memguard;
+mod mountlimit;
+mod openapi;
+mod paths;
+mod recorder;
+mod refresh;
+mod s3;
+mod sdnotify;
+mod server;
+mod snapshot;
+mod streamutil;
+mod telemetry;
+mod tests;
+mod tls;
+mod upstream;
+mod verify;
+mod webhook;
+mod zarrchecksum;
+mod zarrman;
+use crate::admin::{json_response, require_admin_token};
+use crate::audit::{AuditLog, DownloadRecord};
+use crate::cachepolicy::apply_cache_policy;
+use crate::changes::{atom_changes, json_changes, ChangeRecord, Since};
+use crate::chaos::ChaosMonkey;
+use crate::consts::*;
+use crate::dandi::{DandiClient, DandisetId, MetadataAllowlist, VersionId};
+#[doc(hidden)]
+pub use crate::dav::bench;
+use crate::dav::{
+    CollectionProperty, DandiDav, Depth, FiniteDepth, HeadRequest, HtmlSnippets, Language,
+    MountDescription, ObjectCache, OtherInstance, Templater, Theme, UserClients, ZarrMode,
+    DEFAULT_ROOT_MOUNTS,
+};
+use crate::effective::EffectiveConfig;
+use crate::feed::PublicationFeed;
+use crate::hotobjects::{HotObjectReport, HotObjects};
+use crate::httputil::{Client, HttpUrl};
+use crate::logsample::{RequestSampler, SampleRate, SampledWriter};
+use crate::memguard::{guard_memory, MemoryGuard, MemoryReport};
+use crate::mountlimit::{limit_mounts, Mount, MountLimit, MountLimits};
+use crate::openapi::{DocumentedRouter, Endpoint};
+use crate::recorder::FixtureRecorder;
+use crate::s3::S3Settings;
+use crate::sdnotify::Notifier;
+use crate::server::ServerConfig;
+use crate::snapshot::{install_warmup, CacheSnapshot, SnapshotSource};
+use crate::telemetry::{count_requests, Telemetry};
+use crate::tls::TlsSettings;
+use crate::upstream::{UpstreamMonitor, UpstreamReport};
+use crate::verify::verify;
+use crate::webhook::WebhookNotifier;
+use crate::zarrman::{ManifestFetcher, ZarrManClient};
+use anyhow::Context;
+use axum::{
+    body::Body,
+    error_handling::HandleErrorLayer,
+    extract::{Request, State},
+    http::{
+        header::{
+            HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_LENGTH,
+            CONTENT_SECURITY_POLICY, CONTENT_TYPE, REFERRER_POLICY, SERVER, USER_AGENT,
+            X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+        },
+        response::Response,
+        Method, StatusCode,
+    },
+    middleware::{self, Next},
+    Router,
+};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use http_body::Body as _;
+use smartstring::alias::CompactString;
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tower::{service_fn, ServiceBuilder};
+use tower_governor::{
+    governor::GovernorConfigBuilder, key_extractor::SmartIpKeyExtractor, GovernorLayer,
+};
+use tower_http::{set_header::response::SetResponseHeaderLayer, trace::TraceLayer};
+use tracing::Level;
+use tracing_subscriber::{filter::Targets, fmt::time::OffsetTime, prelude::*};
+
+/// The content of the CSS stylesheet to serve at `/.static/styles.css`
+static STYLESHEET: &str = include_str!("dav/static/styles.css");
+
+/// The content of the script for downloading selected resources in HTML views
+/// to serve at `/.static/download.js`
+static DOWNLOAD_SCRIPT: &str = include_str!("dav/static/download.js");
+
+/// The content of the `robots.txt` file to serve at `/robots.txt`
+static ROBOTS_TXT: &str = "User-agent: *\nDisallow: /\n";
+
+/// The body to return with 408 Request Timeout responses
+static REQUEST_TIMEOUT_BODY: &str = "Request could not be completed in time\n";
+
+/// WebDAV view to DANDI Archive
+///
+/// See <https://github.com/dandi/dandidav> for more information.
+#[derive(Clone, Debug, Eq, Parser, PartialEq)]
+#[command(version = env!("VERSION_WITH_GIT"))]
+struct Arguments {
+    #[command(flatten)]
+    config: Config,
+
+    /// Inject random delays & failures into all upstream requests made via
+    /// `dandidav`'s HTTP clients (for use in development only)
+    #[arg(long, hide = true)]
+    chaos: bool,
+
+    /// Under `--chaos`, fail this fraction (between 0 and 1) of upstream
+    /// requests with synthetic 503 responses
+    #[arg(
+        long,
+        default_value = "0.1",
+        hide = true,
+        requires = "chaos",
+        value_name = "RATE"
+    )]
+    chaos_error_rate: SampleRate,
+
+    /// Under `--chaos`, delay each upstream request by a random amount of
+    /// time up to this many milliseconds
+    #[arg(
+        long,
+        default_value_t = 500,
+        hide = true,
+        requires = "chaos",
+        value_name = "MILLISECONDS"
+    )]
+    chaos_max_delay: u64,
+
+    /// Close client connections whose clients take longer than this many
+    /// seconds to send the headers of a request.  0 disables the timeout.
+    #[arg(long, default_value_t = 30, value_name = "SECONDS")]
+    header_read_timeout: u64,
+
+    /// IP address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    ip_addr: IpAddr,
+
+    /// Close client connections that have gone this many seconds without a
+    /// request
+    #[arg(long, value_name = "SECONDS")]
+    keep_alive_timeout: Option<u64>,
+
+    /// Log the opening & closing of each client connection along with
+    /// per-connection statistics
+    #[arg(long)]
+    log_connections: bool,
+
+    /// Only log this fraction (between 0 and 1) of requests that complete
+    /// quickly without warnings or errors.  Requests that log warnings or
+    /// errors or that take at least `--slow-request-threshold` seconds are
+    /// always logged in full.
+    #[arg(long, default_value_t, value_name = "RATE")]
+    log_sample_rate: SampleRate,
+
+    /// Do not spawn more than this many threads for running CPU-heavy work,
+    /// such as rendering large collection listings.  Defaults to 512.
+    #[arg(long, value_name = "INT")]
+    max_blocking_threads: Option<NonZeroUsize>,
+
+    /// Do not allow more than this many connections to be open at once from
+    /// a single client IP address.  The limit applies to the address of the
+    /// connecting peer, so do not use this option when running behind a
+    /// reverse proxy (e.g., on Heroku).
+    #[arg(long, value_name = "INT")]
+    max_connections_per_ip: Option<NonZeroUsize>,
+
+    /// Close client connections after serving this many requests on them
+    #[arg(long, value_name = "INT")]
+    max_requests_per_connection: Option<NonZeroUsize>,
+
+    /// Port to listen on
+    #[arg(short, long, default_value_t = 8080)]
+    port: u16,
+
+    /// When `--log-sample-rate` is less than 1, always log requests that take
+    /// at least this many seconds to complete
+    #[arg(long, default_value_t = 5, value_name = "SECONDS")]
+    slow_request_threshold: u64,
+
+    /// Serve over TLS using the certificate chain in this PEM file
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// Require TLS clients to present certificates signed by one of the CA
+    /// certificates in this PEM file
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
+
+    /// Only accept TLS clients whose certificate subjects (either full
+    /// distinguished names or common names) are listed in this file, one per
+    /// line
+    #[arg(long, value_name = "PATH", requires = "tls_client_ca")]
+    tls_client_allowlist: Option<PathBuf>,
+
+    /// Serve over TLS using the private key in this PEM file
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Handle requests using this many worker threads.  Defaults to the number
+    /// of CPU cores.
+    #[arg(long, value_name = "INT")]
+    worker_threads: Option<NonZeroUsize>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Operations other than serving that `dandidav` can perform
+#[derive(Clone, Debug, Eq, PartialEq, Subcommand)]
+enum Command {
+    /// Crawl the asset listing of a Dandiset version and print a JSON report
+    /// of any anomalies that dandidav would encounter when serving it
+    Verify {
+        /// The ID of the Dandiset to check
+        dandiset_id: DandisetId,
+
+        /// The version of the Dandiset to check
+        #[arg(default_value = "draft")]
+        version: VersionId,
+    },
+}
+
+#[derive(Args, Clone, Debug, Eq, PartialEq)]
+struct Config {
+    /// Read a bearer token from this file.  The administrative endpoints
+    /// under `/.admin/` (including snapshots of the caches at
+    /// `/.admin/cache-snapshot`) are only served to clients that present the
+    /// token, and the token is used when fetching a snapshot with
+    /// `--warm-from`.
+    #[arg(long, alias = "cache-snapshot-token-file", value_name = "PATH")]
+    admin_token_file: Option<PathBuf>,
+
+    /// Cache up to this many megabytes of responses from the DANDI Archive
+    /// API in memory.  0 disables caching.
+    #[arg(long, default_value_t = 0, value_name = "INT")]
+    api_cache_mb: u64,
+
+    /// Store large responses cached due to `--api-cache-mb` gzip-compressed,
+    /// decompressing them each time they are used
+    #[arg(long)]
+    api_cache_compress: bool,
+
+    /// Discard responses cached due to `--api-cache-mb` after this many
+    /// seconds
+    #[arg(long, default_value = "60", value_name = "SECONDS")]
+    api_cache_ttl: NonZeroU64,
+
+    /// API URL of the DANDI Archive instance to serve
+    #[arg(long, default_value = DEFAULT_API_URL, value_name = "URL")]
+    api_url: HttpUrl,
+
+    /// Also append a record of each download redirect to this file as JSON
+    /// Lines
+    #[arg(long, value_name = "PATH")]
+    audit_log_file: Option<PathBuf>,
+
+    /// Keep a record of the most recent this-many download redirects in
+    /// memory, queryable at `/.admin/downloads`
+    #[arg(long, default_value_t = 0, value_name = "INT")]
+    audit_log_size: usize,
+
+    /// DANDI Archive API token to authenticate requests to the Archive with,
+    /// allowing access to embargoed Dandisets
+    #[arg(
+        long,
+        env = "DANDI_API_KEY",
+        hide_env_values = true,
+        value_name = "TOKEN"
+    )]
+    dandi_api_token: Option<String>,
+
+    /// Page size to use when requesting paginated results from the DANDI
+    /// Archive API
+    #[arg(long, value_name = "INT")]
+    dandi_page_size: Option<usize>,
+
+    /// Make no more than the given number of concurrent upstream requests on
+    /// behalf of requests for any single Dandiset, so that crawling one
+    /// Dandiset does not use up the upstream capacity available for others
+    #[arg(long, value_name = "INT")]
+    dandiset_concurrency: Option<NonZeroUsize>,
+
+    /// Language in which to display HTML views to clients that do not request
+    /// a supported language via the "Accept-Language" header
+    #[arg(long, value_enum, default_value_t, value_name = "LANG")]
+    default_language: Language,
+
+    /// Authenticate requests to the Archive made on behalf of WebDAV requests
+    /// with "Authorization" headers using the DANDI API tokens in those
+    /// headers (as bearer tokens or Basic auth passwords), so that each user
+    /// sees the embargoed Dandisets that they have access to
+    #[arg(long)]
+    forward_credentials: bool,
+
+    /// Track how often the most frequently requested this-many downloadable
+    /// paths are requested, reporting them at `/.admin/hot-objects` and
+    /// promoting hot Zarr entries into the `--proxy-cache-dir` cache
+    #[arg(long, default_value_t = 0, value_name = "INT")]
+    hot_objects_size: usize,
+
+    /// Value of the "X-Content-Type-Options" header to send with HTML
+    /// responses.  An empty value disables the header.
+    #[arg(long, default_value = DEFAULT_HTML_CONTENT_TYPE_OPTIONS, value_name = "VALUE")]
+    html_content_type_options: HeaderValue,
+
+    /// Value of the "Content-Security-Policy" header to send with HTML
+    /// responses.  An empty value disables the header.
+    #[arg(long, default_value = DEFAULT_HTML_CSP, value_name = "POLICY")]
+    html_csp: HeaderValue,
+
+    /// Add the contents of this file (an HTML snippet, processed as a Tera
+    /// template) to the end of the `<head>` of HTML collection pages
+    #[arg(long, value_name = "PATH")]
+    html_extra_head: Option<PathBuf>,
+
+    /// Display the contents of this file (an HTML snippet, processed as a Tera
+    /// template) in the footer of HTML collection pages in place of the
+    /// default version information
+    #[arg(long, value_name = "PATH")]
+    html_footer: Option<PathBuf>,
+
+    /// Value of the "X-Frame-Options" header to send with HTML responses.  An
+    /// empty value disables the header.
+    #[arg(long, default_value = DEFAULT_HTML_FRAME_OPTIONS, value_name = "VALUE")]
+    html_frame_options: HeaderValue,
+
+    /// Add the contents of this file (an HTML snippet, processed as a Tera
+    /// template) to the start of the `<body>` of HTML collection pages
+    #[arg(long, value_name = "PATH")]
+    html_header: Option<PathBuf>,
+
+    /// Display the image at this URL next to the site title in HTML
+    /// collection pages
+    #[arg(long, value_name = "URL")]
+    html_logo: Option<String>,
+
+    /// Value of the "Referrer-Policy" header to send with HTML responses.  An
+    /// empty value disables the header.
+    #[arg(long, default_value = DEFAULT_HTML_REFERRER_POLICY, value_name = "POLICY")]
+    html_referrer_policy: HeaderValue,
+
+    /// Color scheme of HTML pages, for distinguishing between deployments
+    /// serving production, staging, or development data
+    #[arg(long, value_enum, default_value_t, value_name = "THEME")]
+    html_theme: Theme,
+
+    /// Log the process's memory usage at the start & end of each incoming
+    /// request
+    #[arg(long)]
+    log_memory: bool,
+
+    /// Truncate the XML bodies of `PROPFIND` responses once they reach this
+    /// many bytes, leaving out the remaining resources and marking the
+    /// response as truncated.  By default, there is no limit.
+    #[arg(long, value_name = "BYTES")]
+    max_propfind_size: Option<NonZeroUsize>,
+
+    /// When the process's memory usage exceeds this many megabytes, clear all
+    /// caches and respond to new collection listing requests with 503 errors
+    /// until usage drops back down
+    #[arg(long, value_name = "INT")]
+    memory_limit_mb: Option<NonZeroU64>,
+
+    /// Only expose these Dandiset metadata fields in `dandiset.yaml` files,
+    /// given as a comma-separated list of dotted field paths (e.g.,
+    /// `name,contributor.name`).  The values of all other fields are replaced
+    /// with "[redacted]".
+    #[arg(long, value_name = "FIELD,...")]
+    metadata_allowlist: Option<MetadataAllowlist>,
+
+    /// Handle no more than the given number of requests for the given mount
+    /// ("dandisets" or "zarrs") at once, so that heavy traffic to one mount
+    /// does not starve the other.  Can be specified multiple times.
+    #[arg(long = "mount-concurrency", value_name = "MOUNT=INT")]
+    mount_concurrency: Vec<MountLimit>,
+
+    /// Describe the given top-level collection ("dandisets" or "zarrs") with
+    /// the given text in the root HTML view and in the collection's own HTML
+    /// view.  Can be specified multiple times.
+    #[arg(long = "mount-description", value_name = "MOUNT=TEXT")]
+    mount_descriptions: Vec<MountDescription>,
+
+    /// Do not report this WebDAV property for collections, for the sake of
+    /// clients that mishandle it.  Can be specified multiple times.
+    #[arg(long = "omit-collection-property", value_enum, value_name = "PROPERTY")]
+    omit_collection_properties: Vec<CollectionProperty>,
+
+    /// API URL of another DANDI Archive instance (such as the staging
+    /// instance) that is served by a separate `dandidav` deployment.  GET
+    /// requests for Dandisets that exist only on the other instance are
+    /// answered with a page linking to them under `--other-instance-url`.
+    #[arg(long, requires = "other_instance_url", value_name = "URL")]
+    other_instance_api_url: Option<HttpUrl>,
+
+    /// Base URL of the `dandidav` deployment serving the instance given by
+    /// `--other-instance-api-url`
+    #[arg(long, requires = "other_instance_api_url", value_name = "URL")]
+    other_instance_url: Option<HttpUrl>,
+
+    /// Redirect requests for blob assets directly to S3 instead of to Archive
+    /// URLs that redirect to signed S3 URLs
+    #[arg(long)]
+    prefer_s3_redirects: bool,
+
+    /// When an asset is accessed, fetch the metadata for the other assets in
+    /// the same folder in the background
+    #[arg(long)]
+    prefetch_siblings: bool,
+
+    /// Name of an S3 bucket whose objects are not publicly readable.  Zarrs in
+    /// the bucket are listed and downloads from it are presigned using AWS
+    /// credentials from the environment.  This option may be given multiple
+    /// times.
+    #[arg(long = "private-bucket", value_name = "BUCKET")]
+    private_buckets: Vec<String>,
+
+    /// Reuse the responses to `PROPFIND` requests for the root and the
+    /// `/dandisets/` & `/zarrs/` collections for this many seconds, so that
+    /// bursts of identical requests (as sent by the Windows WebDAV redirector
+    /// when mapping a drive) do not each enumerate the collections anew
+    #[arg(long, value_name = "SECONDS")]
+    propfind_cache_ttl: Option<NonZeroU64>,
+
+    /// Treat `PROPFIND` requests without `Depth` headers as having this
+    /// depth
+    #[arg(long, value_enum, default_value = "infinity", value_name = "DEPTH")]
+    propfind_default_depth: Depth,
+
+    /// Answer `PROPFIND` requests with infinite depth for paths inside
+    /// Dandiset versions or beneath `/zarrs/` by streaming a listing of the
+    /// entire tree beneath the path.  Such responses are not subject to
+    /// `--max-propfind-size`.
+    #[arg(long)]
+    propfind_infinite_depth: bool,
+
+    /// Answer `PROPFIND` requests with depths greater than this with 403
+    /// errors.  Infinite depths are only permitted as allowed by
+    /// `--propfind-infinite-depth`.
+    #[arg(long, value_enum, default_value = "1", value_name = "DEPTH")]
+    propfind_max_depth: FiniteDepth,
+
+    /// Answer `GET` requests for blob assets by streaming the assets from S3
+    /// through the server, rather than by redirecting to S3.  Cannot be
+    /// combined with `--prefer-s3-redirects`.
+    #[arg(long)]
+    proxy_blobs: bool,
+
+    /// Store small Zarr entries fetched from S3 in this directory and serve
+    /// `GET` requests for them from there.  Requires `--proxy-zarr-ranges`.
+    #[arg(long, requires = "proxy_zarr_ranges", value_name = "DIR")]
+    proxy_cache_dir: Option<PathBuf>,
+
+    /// Limit the `--proxy-cache-dir` cache to storing no more than this many
+    /// megabytes of objects at once
+    #[arg(long, default_value_t = 256, value_name = "INT")]
+    proxy_cache_mb: u64,
+
+    /// Answer `GET` requests for Zarr entries that have `Range` headers by
+    /// fetching the requested ranges from S3 and passing them back, rather
+    /// than by redirecting to S3
+    #[arg(long)]
+    proxy_zarr_ranges: bool,
+
+    /// Save the responses to all requests made to the Archive & Zarr manifest
+    /// tree in this directory as mock archive response stubs (for use in
+    /// development)
+    #[arg(long, value_name = "DIR")]
+    record_fixtures: Option<PathBuf>,
+
+    /// List this top-level collection at the root of the hierarchy.  Can be
+    /// specified multiple times; the collections are listed in the order
+    /// given.  Collections that are not listed are still served.
+    #[arg(
+        long = "root-mount",
+        value_enum,
+        default_values = ["dandisets", "zarrs"],
+        value_name = "MOUNT"
+    )]
+    root_mounts: Vec<Mount>,
+
+    /// When redirecting requests for blob assets directly to S3, add a
+    /// `response-content-disposition` query parameter to the S3 URL so that
+    /// the blob is saved under the asset's filename.  Only use this if the
+    /// buckets being served honor the parameter on unsigned requests.
+    #[arg(long, requires = "prefer_s3_redirects")]
+    s3_content_disposition: bool,
+
+    /// Base URL of an S3-compatible service (such as MinIO or a mirror of the
+    /// Archive's buckets) to use instead of AWS when accessing S3 directly.
+    /// Buckets on the service are addressed path-style.
+    #[arg(long, value_name = "URL")]
+    s3_endpoint: Option<HttpUrl>,
+
+    /// Region to use for all S3 buckets instead of looking up each bucket's
+    /// region
+    #[arg(long, value_name = "REGION")]
+    s3_region: Option<String>,
+
+    /// Send a report of aggregate usage statistics this many seconds apart
+    /// when `--telemetry-url` is given
+    #[arg(long, default_value = "3600", value_name = "SECONDS")]
+    telemetry_interval: NonZeroU64,
+
+    /// Periodically `POST` anonymous, aggregate usage statistics (request
+    /// counts by route class, client family, and response status class) to
+    /// this URL.  No telemetry is sent unless this option is given.
+    #[arg(long, value_name = "URL")]
+    telemetry_url: Option<HttpUrl>,
+
+    /// Site name to use in HTML collection pages
+    #[arg(short = 'T', long, default_value = env!("CARGO_PKG_NAME"))]
+    title: String,
+
+    /// Respond with a 503 to any request that would require more than this
+    /// many requests to the Archive and other upstream servers
+    #[arg(long, value_name = "INT")]
+    upstream_request_budget: Option<NonZeroUsize>,
+
+    /// At startup, fetch a snapshot of the caches of the primary `dandidav`
+    /// instance at this base URL and use it to warm this instance's caches
+    #[arg(long, requires = "admin_token_file", value_name = "URL")]
+    warm_from: Option<HttpUrl>,
+
+    /// Check the Archive for new & updated Dandiset versions this many
+    /// seconds apart when notifying webhooks
+    #[arg(long, default_value = "300", value_name = "SECONDS")]
+    webhook_interval: NonZeroU64,
+
+    /// Sign webhook notifications with the secret key in this file
+    #[arg(long, value_name = "PATH")]
+    webhook_secret_file: Option<PathBuf>,
+
+    /// `POST` a JSON notification to this URL whenever new or updated
+    /// Dandiset versions are detected.  Can be specified multiple times.
+    #[arg(long = "webhook-url", value_name = "URL")]
+    webhook_urls: Vec<HttpUrl>,
+
+    /// Limit the Zarr manifest cache to storing no more than this many
+    /// megabytes of parsed manifests at once
+    #[arg(short = 'Z', long, default_value_t = 100, value_name = "INT")]
+    zarrman_cache_mb: u64,
+
+    /// Fetch Zarr manifests from the manifest tree at this base URL.  Can be
+    /// specified multiple times to configure mirrors of the manifest tree,
+    /// which are tried in order when earlier URLs fail.
+    #[arg(long = "zarr-manifest-url", value_name = "URL")]
+    zarr_manifest_urls: Vec<HttpUrl>,
+
+    /// How to present Zarr assets under `/dandisets/`: as collections of
+    /// their entries ("expanded") or as single files that redirect to their
+    /// Zarr manifests ("collapsed")
+    #[arg(long, value_enum, default_value_t, value_name = "MODE")]
+    zarr_mode: ZarrMode,
+
+    /// When the chunks in a Zarr folder are accessed in sequence, fetch the
+    /// details of this many following chunks in the background.  0 disables
+    /// readahead.
+    #[arg(long, default_value_t = 0, value_name = "INT")]
+    zarr_readahead: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            admin_token_file: None,
+            api_cache_compress: false,
+            api_cache_mb: 0,
+            api_cache_ttl: NonZeroU64::new(60).expect("60 should be nonzero"),
+            api_url: DEFAULT_API_URL
+                .parse::<HttpUrl>()
+                .expect("DEFAULT_API_URL should be a valid HttpUrl"),
+            audit_log_file: None,
+            audit_log_size: 0,
+            dandi_api_token: None,
+            dandi_page_size: None,
+            dandiset_concurrency: None,
+            default_language: Language::default(),
+            forward_credentials: false,
+            hot_objects_size: 0,
+            html_content_type_options: HeaderValue::from_static(DEFAULT_HTML_CONTENT_TYPE_OPTIONS),
+            html_csp: HeaderValue::from_static(DEFAULT_HTML_CSP),
+            html_extra_head: None,
+            html_footer: None,
+            html_frame_options: HeaderValue::from_static(DEFAULT_HTML_FRAME_OPTIONS),
+            html_header: None,
+            html_logo: None,
+            html_referrer_policy: HeaderValue::from_static(DEFAULT_HTML_REFERRER_POLICY),
+            html_theme: Theme::default(),
+            log_memory: false,
+            max_propfind_size: None,
+            memory_limit_mb: None,
+            metadata_allowlist: None,
+            mount_concurrency: Vec::new(),
+            mount_descriptions: Vec::new(),
+            omit_collection_properties: Vec::new(),
+            other_instance_api_url: None,
+            other_instance_url: None,
+            prefer_s3_redirects: false,
+            prefetch_siblings: false,
+            private_buckets: Vec::new(),
+            propfind_cache_ttl: None,
+            propfind_default_depth: Depth::Infinity,
+            propfind_infinite_depth: false,
+            propfind_max_depth: FiniteDepth::One,
+            proxy_blobs: false,
+            proxy_cache_dir: None,
+            proxy_cache_mb: 256,
+            proxy_zarr_ranges: false,
+            record_fixtures: None,
+            root_mounts: DEFAULT_ROOT_MOUNTS.to_vec(),
+            s3_content_disposition: false,
+            s3_endpoint: None,
+            s3_region: None,
+            telemetry_interval: NonZeroU64::new(3600).expect("3600 should be nonzero"),
+            telemetry_url: None,
+            title: env!("CARGO_PKG_NAME").into(),
+            upstream_request_budget: None,
+            warm_from: None,
+            webhook_interval: NonZeroU64::new(300).expect("300 should be nonzero"),
+            webhook_secret_file: None,
+            webhook_urls: Vec::new(),
+            zarrman_cache_mb: 100,
+            zarr_manifest_urls: Vec::new(),
+            zarr_mode: ZarrMode::default(),
+            zarr_readahead: 0,
+        }
+    }
+}
+
+/// Run `dandidav` with the command-line arguments of the current process
+///
+/// # Errors
+///
+/// Returns an error if setting up or running the server fails
+// See
+// <https://docs.rs/tracing-subscriber/latest/tracing_subscriber/fmt/time/struct.OffsetTime.html#method.local_rfc_3339>
+// for why the runtime is only started after the timezone offset is determined
+pub fn main() -> anyhow::Result<()> {
+    let timer =
+        OffsetTime::local_rfc_3339().context("failed to determine local timezone offset")?;
+    let matches = Arguments::command().get_matches();
+    let effective = EffectiveConfig::from_matches(&Arguments::command(), &matches);
+    let args = Arguments::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let sampler = (!args.log_sample_rate.is_all()).then(|| {
+        RequestSampler::new(
+            args.log_sample_rate,
+            Duration::from_secs(args.slow_request_threshold),
+        )
+    });
+    tracing_subscriber::registry()
+        .with(sampler)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_timer(timer)
+                .with_writer(SampledWriter),
+        )
+        .with(
+            Targets::new()
+                .with_target(env!("CARGO_CRATE_NAME"), Level::TRACE)
+                .with_target("aws_config", Level::DEBUG)
+                .with_target("reqwest", Level::TRACE)
+                .with_target("reqwest_retry", Level::TRACE)
+                .with_target("tower_http", Level::TRACE)
+                .with_default(Level::INFO),
+        )
+        .init();
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_all();
+    if let Some(n) = args.worker_threads {
+        builder.worker_threads(n.get());
+    }
+    if let Some(n) = args.max_blocking_threads {
+        builder.max_blocking_threads(n.get());
+    }
+    let rt = builder.build().context("failed to build async runtime")?;
+    rt.block_on(run(args, effective))
+}
+
+async fn run(args: Arguments, effective: EffectiveConfig) -> anyhow::Result<()> {
+    if args.chaos {
+        ChaosMonkey::new(
+            args.chaos_error_rate,
+            Duration::from_millis(args.chaos_max_delay),
+        )
+        .install();
+    }
+    if let Some(Command::Verify {
+        dandiset_id,
+        version,
+    }) = args.command
+    {
+        return run_verify(args.config, dandiset_id, version).await;
+    }
+    let app = get_app(args.config, effective)?;
+    let server_cfg = ServerConfig {
+        keep_alive_timeout: args.keep_alive_timeout.map(Duration::from_secs),
+        max_requests_per_connection: args.max_requests_per_connection,
+        header_read_timeout: (args.header_read_timeout > 0)
+            .then(|| Duration::from_secs(args.header_read_timeout)),
+        log_connections: args.log_connections,
+        max_connections_per_ip: args.max_connections_per_ip,
+    };
+    let tls = match (args.tls_cert, args.tls_key) {
+        (Some(cert), Some(key)) => Some(
+            TlsSettings::load(
+                &cert,
+                &key,
+                args.tls_client_ca.as_deref(),
+                args.tls_client_allowlist.as_deref(),
+            )
+            .context("failed to set up TLS")?,
+        ),
+        _ => None,
+    };
+    let listener = tokio::net::TcpListener::bind((args.ip_addr, args.port))
+        .await
+        .context("failed to bind listener")?;
+    if let Some(notifier) = Notifier::from_env() {
+        notifier.ready();
+        notifier.install_watchdog();
+    }
+    server::serve(listener, app, server_cfg, tls).await
+}
+
+/// Check the asset listing of the given Dandiset version on the Archive
+/// instance configured in `cfg` and print a JSON report of the anomalies found
+/// to standard output
+async fn run_verify(
+    cfg: Config,
+    dandiset_id: DandisetId,
+    version: VersionId,
+) -> anyhow::Result<()> {
+    let client = DandiClient::new(
+        cfg.api_url,
+        cfg.dandi_page_size,
+        false,
+        0,
+        api_authorization(cfg.dandi_api_token.as_deref())?,
+        None,
+    )?;
+    let report = verify(&client, dandiset_id, version)
+        .await
+        .context("failed to crawl asset listing")?;
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, &report).context("failed to write report")?;
+    writeln!(stdout).context("failed to write report")?;
+    Ok(())
+}
+
+fn get_app(cfg: Config, effective: EffectiveConfig) -> anyhow::Result<Router> {
+    let recorder = cfg
+        .record_fixtures
+        .map(FixtureRecorder::new)
+        .transpose()
+        .context("failed to create fixture directory")?
+        .map(Arc::new);
+    let dandi = DandiClient::new(
+        cfg.api_url,
+        cfg.dandi_page_size,
+        cfg.prefetch_siblings,
+        cfg.zarr_readahead,
+        api_authorization(cfg.dandi_api_token.as_deref())?,
+        recorder.clone(),
+    )?
+    .with_api_cache(
+        cfg.api_cache_mb.saturating_mul(1_000_000),
+        Duration::from_secs(cfg.api_cache_ttl.get()),
+        cfg.api_cache_compress,
+    )
+    .with_metadata_allowlist(cfg.metadata_allowlist)
+    .with_private_buckets(cfg.private_buckets.into_iter().map(CompactString::from))
+    .with_s3_settings(S3Settings {
+        endpoint: cfg.s3_endpoint,
+        region: cfg.s3_region,
+    });
+    let other_instance = match (cfg.other_instance_url, cfg.other_instance_api_url) {
+        (Some(url), Some(api_url)) => Some(OtherInstance {
+            url,
+            dandi: DandiClient::new(api_url, cfg.dandi_page_size, false, 0, None, None)?,
+        }),
+        _ => None,
+    };
+    let zarrfetcher = ManifestFetcher::new(
+        cfg.zarrman_cache_mb * 1_000_000,
+        cfg.zarr_manifest_urls,
+        recorder,
+    )?;
+    zarrfetcher.install_periodic_dump(ZARR_MANIFEST_CACHE_DUMP_PERIOD);
+    let user_clients = cfg
+        .forward_credentials
+        .then(|| UserClients::new(dandi.clone()));
+    let memguard = Arc::new(MemoryGuard::new(
+        cfg.memory_limit_mb
+            .map(|mb| mb.get().saturating_mul(1_000_000)),
+        dandi.clone(),
+        user_clients.clone(),
+        zarrfetcher.clone(),
+    ));
+    let admin_token = cfg
+        .admin_token_file
+        .map(|p| read_admin_token(&p))
+        .transpose()?;
+    let snapshot_source = admin_token.is_some().then(|| SnapshotSource {
+        dandi: dandi.clone(),
+        zarrfetcher: zarrfetcher.clone(),
+    });
+    if let (Some(url), Some(token)) = (cfg.warm_from, &admin_token) {
+        install_warmup(
+            Client::new(None)?,
+            url,
+            Arc::clone(token),
+            dandi.clone(),
+            zarrfetcher.clone(),
+        );
+    }
+    let zarrman = ZarrManClient::new(zarrfetcher);
+    let snippets = HtmlSnippets {
+        extra_head: read_snippet(cfg.html_extra_head.as_deref())?,
+        header: read_snippet(cfg.html_header.as_deref())?,
+        footer: read_snippet(cfg.html_footer.as_deref())?,
+    };
+    let upstream = Arc::new(UpstreamMonitor::new());
+    Arc::clone(&upstream).install(dandi.clone(), UPSTREAM_CHECK_PERIOD);
+    let templater = Arc::new(
+        Templater::new(cfg.title, &snippets)?
+            .with_branding(cfg.html_logo, cfg.html_theme)
+            .with_upstream(Arc::clone(&upstream))
+            .with_mount_descriptions(cfg.mount_descriptions),
+    );
+    let audit_log = if cfg.audit_log_size > 0 || cfg.audit_log_file.is_some() {
+        Some(Arc::new(
+            AuditLog::new(cfg.audit_log_size, cfg.audit_log_file.as_deref())
+                .context("failed to open audit log file")?,
+        ))
+    } else {
+        None
+    };
+    let hot_objects =
+        (cfg.hot_objects_size > 0).then(|| Arc::new(HotObjects::new(cfg.hot_objects_size)));
+    if !cfg.webhook_urls.is_empty() {
+        let secret = cfg
+            .webhook_secret_file
+            .map(|p| {
+                std::fs::read_to_string(&p)
+                    .with_context(|| format!("failed to read webhook secret {}", p.display()))
+                    .map(|s| s.trim().as_bytes().to_vec())
+            })
+            .transpose()?;
+        WebhookNotifier::new(dandi.clone(), cfg.webhook_urls, secret)?
+            .install(Duration::from_secs(cfg.webhook_interval.get()));
+    }
+    let telemetry = cfg
+        .telemetry_url
+        .map(Telemetry::new)
+        .transpose()?
+        .map(Arc::new);
+    if let Some(t) = &telemetry {
+        Arc::clone(t).install(Duration::from_secs(cfg.telemetry_interval.get()));
+    }
+    let changes_client = dandi.clone();
+    let publication_feed = Arc::new(PublicationFeed::new(dandi.clone()));
+    let dav = Arc::new(
+        DandiDav::builder(dandi, zarrman, templater)
+            .user_clients(user_clients)
+            .prefer_s3_redirects(cfg.prefer_s3_redirects)
+            .s3_content_disposition(cfg.s3_content_disposition)
+            .upstream_request_budget(cfg.upstream_request_budget)
+            .dandiset_concurrency(cfg.dandiset_concurrency)
+            .zarr_mode(cfg.zarr_mode)
+            .default_language(cfg.default_language)
+            .audit_log(audit_log.clone())
+            .other_instance(other_instance)
+            .max_propfind_size(cfg.max_propfind_size.map(NonZeroUsize::get))
+            .omit_collection_properties(cfg.omit_collection_properties)
+            .range_proxy(
+                cfg.proxy_zarr_ranges
+                    .then(|| Client::new(None))
+                    .transpose()?,
+            )
+            .blob_proxy(
+                cfg.proxy_blobs
+                    .then(|| Client::builder().streaming(true).build())
+                    .transpose()?,
+            )
+            .object_cache(
+                cfg.proxy_cache_dir
+                    .map(|dir| {
+                        ObjectCache::new(dir.clone(), cfg.proxy_cache_mb * 1_000_000).with_context(
+                            || format!("failed to set up object cache in {}", dir.display()),
+                        )
+                    })
+                    .transpose()?,
+            )
+            .hot_objects(hot_objects.clone())
+            .propfind_cache_ttl(
+                cfg.propfind_cache_ttl
+                    .map(|secs| Duration::from_secs(secs.get())),
+            )
+            .propfind_default_depth(cfg.propfind_default_depth)
+            .propfind_max_depth(cfg.propfind_max_depth)
+            .propfind_infinite_depth(cfg.propfind_infinite_depth)
+            .root_mounts(cfg.root_mounts)
+            .build()?,
+    );
+    let mut docrouter = DocumentedRouter::new()
+        .get(
+            Endpoint::document(
+                "/.static/styles.css",
+                "Stylesheet for HTML views",
+                CSS_CONTENT_TYPE,
+            ),
+            || async {
+                // Note: This response should not have WebDAV headers (DAV, Allow)
+                ([(CONTENT_TYPE, CSS_CONTENT_TYPE)], STYLESHEET)
+            },
+        )
+        .get(
+            Endpoint::document(
+                "/.static/download.js",
+                "Script for downloading selected resources in HTML views",
+                JS_CONTENT_TYPE,
+            ),
+            || async {
+                // Note: This response should not have WebDAV headers (DAV, Allow)
+                ([(CONTENT_TYPE, JS_CONTENT_TYPE)], DOWNLOAD_SCRIPT)
+            },
+        )
+        .get(
+            Endpoint::document(
+                "/robots.txt",
+                "Robots exclusion file denying all robots",
+                CSS_CONTENT_TYPE,
+            ),
+            || async {
+                // Note: This response should not have WebDAV headers (DAV, Allow)
+                ([(CONTENT_TYPE, CSS_CONTENT_TYPE)], ROBOTS_TXT)
+            },
+        );
+    docrouter = docrouter.get(
+        Endpoint::json::<MemoryReport>("/.admin/memory", "Report on memory usage"),
+        {
+            let memguard = Arc::clone(&memguard);
+            move || {
+                let memguard = Arc::clone(&memguard);
+                async move { json_response(&memguard.report()) }
+            }
+        },
+    );
+    docrouter = docrouter.get(
+        Endpoint::json::<EffectiveConfig>(
+            "/.admin/config",
+            "The resolved command-line options of the running server",
+        ),
+        {
+            let effective = Arc::new(effective);
+            move || {
+                let effective = Arc::clone(&effective);
+                async move { json_response(&*effective) }
+            }
+        },
+    );
+    docrouter = docrouter.get(
+        Endpoint::json::<UpstreamReport>(
+            "/.admin/upstream",
+            "The versions of the Archive's API server & metadata schema and whether they have been tested",
+        ),
+        move || {
+            let upstream = Arc::clone(&upstream);
+            async move { json_response(&upstream.report()) }
+        },
+    );
+    docrouter = docrouter
+        .get(
+            Endpoint::json::<Vec<ChangeRecord>>(
+                "/changes",
+                "Dandisets & versions modified after the `since` query parameter, newest first",
+            ),
+            {
+                let dandi = changes_client.clone();
+                move |Since(since): Since| {
+                    let dandi = dandi.clone();
+                    async move { json_changes(&dandi, since).await }
+                }
+            },
+        )
+        .get(
+            Endpoint::document(
+                "/changes.atom",
+                "Atom feed of Dandisets & versions modified after the `since` query parameter",
+                ATOM_CONTENT_TYPE,
+            ),
+            move |Since(since): Since| {
+                let dandi = changes_client.clone();
+                async move { atom_changes(&dandi, since).await }
+            },
+        );
+    docrouter = docrouter.get(
+        Endpoint::document(
+            "/feed.xml",
+            "Atom feed of the most recent publication of each Dandiset, newest first",
+            ATOM_CONTENT_TYPE,
+        ),
+        move || {
+            let feed = Arc::clone(&publication_feed);
+            async move { feed.response().await }
+        },
+    );
+    if let Some(log) = audit_log {
+        docrouter = docrouter.get(
+            Endpoint::json::<Vec<DownloadRecord>>(
+                "/.admin/downloads",
+                "Most recent download redirects, newest first",
+            ),
+            move || {
+                let log = Arc::clone(&log);
+                async move { json_response(&log.recent()) }
+            },
+        );
+    }
+    if let Some(source) = snapshot_source {
+        docrouter = docrouter.get(
+            Endpoint::json::<CacheSnapshot>(
+                CACHE_SNAPSHOT_PATH,
+                "Snapshot of caches for warming standby instances",
+            ),
+            move || {
+                let source = source.clone();
+                // Note: This response should not have WebDAV headers (DAV, Allow)
+                async move { source.serve().await }
+            },
+        );
+    }
+    if let Some(tracker) = hot_objects {
+        docrouter = docrouter.get(
+            Endpoint::json::<HotObjectReport>(
+                "/.admin/hot-objects",
+                "Most frequently requested downloadable paths",
+            ),
+            move || {
+                let tracker = Arc::clone(&tracker);
+                async move { json_response(&tracker.report()) }
+            },
+        );
+    }
+    let mut app = docrouter.finish();
+    app = app
+        .fallback_service(service_fn(move |req: Request| {
+            let dav = Arc::clone(&dav);
+            async move { dav.handle_request(req).await }
+        }))
+        .layer(middleware::from_fn(handle_head))
+        .layer(middleware::from_fn_with_state(
+            admin_token,
+            require_admin_token,
+        ));
+    let html_headers = [
+        (CONTENT_SECURITY_POLICY, cfg.html_csp),
+        (X_CONTENT_TYPE_OPTIONS, cfg.html_content_type_options),
+        (X_FRAME_OPTIONS, cfg.html_frame_options),
+        (REFERRER_POLICY, cfg.html_referrer_policy),
+    ]
+    .into_iter()
+    .filter(|(_, value)| !value.is_empty())
+    .collect::<Vec<_>>();
+    if !html_headers.is_empty() {
+        app = app.layer(middleware::from_fn_with_state(
+            Arc::<[_]>::from(html_headers),
+            set_html_security_headers,
+        ));
+    }
+    if cfg.log_memory {
+        app = app.layer(middleware::from_fn(log_memory));
+    }
+    app = app.layer(middleware::from_fn_with_state(memguard, guard_memory));
+    if let Some(limits) = MountLimits::new(&cfg.mount_concurrency) {
+        app = app.layer(middleware::from_fn_with_state(
+            Arc::new(limits),
+            limit_mounts,
+        ));
+    }
+    app = app
+        .layer(middleware::from_fn(apply_cache_policy))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            SERVER,
+            HeaderValue::from_static(SERVER_VALUE),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_static("*"),
+        ))
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(|_| async {
+                    (StatusCode::REQUEST_TIMEOUT, REQUEST_TIMEOUT_BODY)
+                }))
+                .timeout(Duration::from_secs(25)),
+        )
+        .layer(GovernorLayer {
+            config: Arc::new(
+                GovernorConfigBuilder::default()
+                    .key_extractor(SmartIpKeyExtractor)
+                    .finish()
+                    .expect("building GovernorConfig should not fail"),
+            ),
+        });
+    if let Some(t) = telemetry {
+        app = app.layer(middleware::from_fn_with_state(t, count_requests));
+    }
+    app = app.layer(
+        TraceLayer::new_for_http()
+            .make_span_with(|request: &Request<_>| {
+                tracing::debug_span!(
+                    "request",
+                    method = %request.method(),
+                    uri = %request.uri(),
+                    request_id = request.headers().get("X-Request-ID").and_then(|v| v.to_str().ok()),
+                )
+            })
+            .on_request(|request: &Request<_>, _span: &tracing::span::Span| {
+                tracing::debug!(
+                    user_agent = request.headers().get(USER_AGENT).and_then(|v| v.to_str().ok()),
+                    "starting processing request",
+                );
+            }),
+    );
+    Ok(app)
+}
+
+/// Construct the `Authorization` header value for authenticating requests to
+/// the Archive with the API token `token`, if given
+fn api_authorization(token: Option<&str>) -> anyhow::Result<Option<HeaderValue>> {
+    token
+        .map(|t| {
+            HeaderValue::try_from(format!("token {}", t.trim()))
+                .context("DANDI API token contains invalid characters")
+        })
+        .transpose()
+}
+
+/// Read the admin bearer token from the file at `path`, with leading &
+/// trailing whitespace removed
+fn read_admin_token(path: &Path) -> anyhow::Result<Arc<str>> {
+    let token = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read admin token {}", path.display()))?;
+    let token = token.trim();
+    if token.is_empty() || HeaderValue::try_from(format!("Bearer {token}")).is_err() {
+        anyhow::bail!(
+            "admin token in {} is empty or contains invalid characters",
+            path.display()
+        );
+    }
+    Ok(Arc::from(token))
+}
+
+/// Read the HTML snippet at `path`, if given
+fn read_snippet(path: Option<&Path>) -> anyhow::Result<Option<String>> {
+    path.map(|p| {
+        std::fs::read_to_string(p)
+            .with_context(|| format!("failed to read HTML snippet {}", p.display()))
+    })
+    .transpose()
+}
+
+/// Handle `HEAD` requests by converting them to `GET` requests (marked with
+/// [`HeadRequest`] so that handlers can skip producing expensive bodies) and
+/// discarding the resulting response body
+async fn handle_head(method: Method, mut request: Request<Body>, next: Next) -> Response<Body> {
+    if method == Method::HEAD {
+        *request.method_mut() = Method::GET;
+        request.extensions_mut().insert(HeadRequest);
+        let mut resp = next.run(request).await;
+        if resp.extensions().get::<HeadRequest>().is_none() {
+            if let Some(sz) = resp.body().size_hint().exact() {
+                resp.headers_mut().insert(CONTENT_LENGTH, sz.into());
+            }
+        }
+        *resp.body_mut() = Body::empty();
+        resp
+    } else {
+        next.run(request).await
+    }
+}
+
+/// Add the given headers to all HTML responses (but not to WebDAV XML or other
+/// responses) that do not already have them
+async fn set_html_security_headers(
+    State(headers): State<Arc<[(HeaderName, HeaderValue)]>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response<Body> {
+    let mut resp = next.run(request).await;
+    let is_html = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("text/html"));
+    if is_html {
+        for (name, value) in headers.iter() {
+            if !resp.headers().contains_key(name) {
+                resp.headers_mut().insert(name.clone(), value.clone());
+            }
+        }
+    }
+    resp
+}
+
+async fn log_memory(request: Request<Body>, next: Next) -> Response<Body> {
+    fn getmem(rel: &str) -> Option<memory_stats::MemoryStats> {
+        if let Some(stats) = memory_stats::memory_stats() {
+            tracing::info!(
+                "Memory usage {} request: {} physical, {} virtual",
+                rel,
+                stats.physical_mem,
+                stats.virtual_mem,
+            );
+            Some(stats)
+        } else {
+            tracing::info!("Failed to get memory usage {rel} request");
+            None
+        }
+    }
+
+    let mem_before = getmem("before");
+    let r = next.run(request).await;
+    let mem_after = getmem("after");
+    if let Some((before, after)) = mem_before.zip(mem_after) {
+        tracing::info!(
+            "Change in memory usage: physical {}, virtual {}",
+            UsizeDiff::new(before.physical_mem, after.physical_mem),
+            UsizeDiff::new(before.virtual_mem, after.virtual_mem),
+        );
+    } else {
+        tracing::info!("Change in memory usage could not be computed");
+    }
+    r
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct UsizeDiff {
+    before: usize,
+    after: usize,
+}
+
+impl UsizeDiff {
+    fn new(before: usize, after: usize) -> UsizeDiff {
+        UsizeDiff { before, after }
+    }
+}
+
+impl fmt::Display for UsizeDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            if self.after < self.before { '-' } else { '+' },
+            self.before.abs_diff(self.after)
+        )
+    }
+}
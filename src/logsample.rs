@@ -0,0 +1,276 @@
+//! Sampling of request logs
+//!
+//! On a busy instance, logging every event for every request produces a
+//! large volume of output, most of it for routine requests that completed
+//! quickly and successfully.  When a sample rate below 1 is configured, the
+//! log output for each request (i.e., the events within its `request` span)
+//! is held in a buffer until the request either finishes or logs a warning or
+//! error:
+//!
+//! - If the request logs a warning or error, its buffered output is written
+//!   out immediately, and the rest of its output is written as it occurs.
+//!
+//! - If the request takes at least the configured threshold to complete, its
+//!   buffered output is written out when it finishes.
+//!
+//! - Otherwise, the request's output is discarded, except for a sample of
+//!   requests (chosen when they start, at the configured rate) whose output
+//!   is written as it occurs.
+//!
+//! Events outside of request spans are always logged.
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{span, Event, Level, Subscriber};
+use tracing_subscriber::{fmt::MakeWriter, layer::Context, registry::LookupSpan, Layer};
+
+/// The name of the spans created for incoming requests
+static REQUEST_SPAN_NAME: &str = "request";
+
+/// The denominator of [`SampleRate`]s
+const PARTS_PER_MILLION: u64 = 1_000_000;
+
+thread_local! {
+    /// The buffer that log output for the event currently being logged on
+    /// this thread should be written to, or `None` if the output should be
+    /// written to stderr
+    static CAPTURE: RefCell<Option<Arc<Mutex<Vec<u8>>>>> = const { RefCell::new(None) };
+}
+
+/// A fraction between 0 and 1 inclusive, such as the fraction of routine
+/// requests whose logs are kept
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct SampleRate(u64);
+
+impl SampleRate {
+    /// Returns true if all requests are sampled
+    pub(crate) fn is_all(self) -> bool {
+        self.0 >= PARTS_PER_MILLION
+    }
+
+    /// Randomly return `true` with a probability equal to the rate
+    pub(crate) fn sample_random(self) -> bool {
+        fastrand::u64(0..PARTS_PER_MILLION) < self.0
+    }
+}
+
+impl Default for SampleRate {
+    fn default() -> SampleRate {
+        SampleRate(PARTS_PER_MILLION)
+    }
+}
+
+impl fmt::Display for SampleRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[allow(clippy::cast_precision_loss)]
+        let rate = self.0 as f64 / PARTS_PER_MILLION as f64;
+        write!(f, "{rate}")
+    }
+}
+
+impl FromStr for SampleRate {
+    type Err = ParseSampleRateError;
+
+    fn from_str(s: &str) -> Result<SampleRate, ParseSampleRateError> {
+        match s.trim().parse::<f64>() {
+            #[allow(
+                clippy::cast_possible_truncation,
+                clippy::cast_precision_loss,
+                clippy::cast_sign_loss
+            )]
+            Ok(rate) if (0.0..=1.0).contains(&rate) => {
+                Ok(SampleRate((rate * PARTS_PER_MILLION as f64).round() as u64))
+            }
+            _ => Err(ParseSampleRateError),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("expected a number between 0 and 1")]
+pub(crate) struct ParseSampleRateError;
+
+/// A [`Layer`] that holds back the log output of routine requests as
+/// described in the module documentation.
+///
+/// The layer does not write any output itself; it must be installed along
+/// with a formatting layer that writes to [`SampledWriter`].
+#[derive(Debug)]
+pub(crate) struct RequestSampler {
+    /// The fraction of routine requests whose output is kept
+    rate: SampleRate,
+
+    /// Requests that take at least this long have their output kept
+    slow_threshold: Duration,
+
+    /// The number of requests started so far
+    started: AtomicU64,
+}
+
+impl RequestSampler {
+    pub(crate) fn new(rate: SampleRate, slow_threshold: Duration) -> RequestSampler {
+        RequestSampler {
+            rate,
+            slow_threshold,
+            started: AtomicU64::new(0),
+        }
+    }
+
+    /// Decide whether the next request to start is sampled.  Sampled requests
+    /// are spread evenly over the sequence of requests.
+    fn sample_next(&self) -> bool {
+        let n = self.started.fetch_add(1, Ordering::Relaxed);
+        n.wrapping_mul(self.rate.0) / PARTS_PER_MILLION
+            != n.wrapping_add(1).wrapping_mul(self.rate.0) / PARTS_PER_MILLION
+    }
+}
+
+impl<S> Layer<S> for RequestSampler
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let metadata = attrs.metadata();
+        if metadata.name() != REQUEST_SPAN_NAME || metadata.target() != env!("CARGO_CRATE_NAME") {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(RequestLog {
+                start: Instant::now(),
+                buffer: (!self.sample_next()).then(Arc::default),
+            });
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(request) = ctx.event_scope(event).and_then(|mut scope| {
+            scope.find(|span| span.extensions().get::<RequestLog>().is_some())
+        }) else {
+            CAPTURE.with(|c| *c.borrow_mut() = None);
+            return;
+        };
+        let mut extensions = request.extensions_mut();
+        let Some(log) = extensions.get_mut::<RequestLog>() else {
+            return;
+        };
+        if *event.metadata().level() <= Level::WARN {
+            log.flush();
+        }
+        CAPTURE.with(|c| *c.borrow_mut() = log.buffer.clone());
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(&id) {
+            if let Some(mut log) = span.extensions_mut().remove::<RequestLog>() {
+                if log.start.elapsed() >= self.slow_threshold {
+                    log.flush();
+                }
+            }
+        }
+    }
+}
+
+/// Per-request state stored in the extensions of request spans
+#[derive(Debug)]
+struct RequestLog {
+    /// When the request started
+    start: Instant,
+
+    /// The request's log output so far, if it is being held back
+    buffer: Option<Arc<Mutex<Vec<u8>>>>,
+}
+
+impl RequestLog {
+    /// Write out any held-back output, and stop holding back further output
+    fn flush(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            let buffer = buffer.lock().unwrap_or_else(PoisonError::into_inner);
+            let _ = io::stderr().lock().write_all(&buffer);
+        }
+    }
+}
+
+/// A [`MakeWriter`] for use by formatting layers installed alongside a
+/// [`RequestSampler`].  Output for events in requests whose output is being
+/// held back is written to the requests' buffers; all other output is written
+/// to stderr.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct SampledWriter;
+
+impl<'a> MakeWriter<'a> for SampledWriter {
+    type Writer = EventWriter;
+
+    fn make_writer(&'a self) -> EventWriter {
+        match CAPTURE.with(|c| c.borrow().clone()) {
+            Some(buffer) => EventWriter::Buffer(buffer),
+            None => EventWriter::Stderr(io::stderr()),
+        }
+    }
+}
+
+/// The writer returned by [`SampledWriter`]
+#[derive(Debug)]
+pub(crate) enum EventWriter {
+    Buffer(Arc<Mutex<Vec<u8>>>),
+    Stderr(io::Stderr),
+}
+
+impl Write for EventWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            EventWriter::Buffer(buffer) => buffer
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .write(buf),
+            EventWriter::Stderr(stderr) => stderr.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            EventWriter::Buffer(_) => Ok(()),
+            EventWriter::Stderr(stderr) => stderr.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("1", SampleRate(1_000_000))]
+    #[case("0", SampleRate(0))]
+    #[case("0.25", SampleRate(250_000))]
+    #[case(" 0.001 ", SampleRate(1000))]
+    fn test_parse_sample_rate(#[case] s: &str, #[case] rate: SampleRate) {
+        assert_eq!(s.parse::<SampleRate>(), Ok(rate));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("-0.5")]
+    #[case("1.5")]
+    #[case("NaN")]
+    #[case("half")]
+    fn test_parse_bad_sample_rate(#[case] s: &str) {
+        assert_eq!(s.parse::<SampleRate>(), Err(ParseSampleRateError));
+    }
+
+    #[rstest]
+    #[case(SampleRate(1_000_000), 100)]
+    #[case(SampleRate(250_000), 25)]
+    #[case(SampleRate(10_000), 1)]
+    #[case(SampleRate(0), 0)]
+    fn test_sample_next(#[case] rate: SampleRate, #[case] expected: usize) {
+        let sampler = RequestSampler::new(rate, Duration::from_secs(1));
+        let sampled = (0..100).filter(|_| sampler.sample_next()).count();
+        assert_eq!(sampled, expected);
+    }
+}
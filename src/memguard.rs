@@ -0,0 +1,423 @@
+//! Tracking of memory usage and shedding of load when it grows too high
+//!
+//! Responses for very large collections (especially PROPFIND responses for
+//! Zarrs with many entries) are built in memory in full, and so a burst of
+//! them, on top of whatever is held in `dandidav`'s caches, can push the
+//! process's memory usage high enough to get it killed.  When a soft memory
+//! limit is configured, [`MemoryGuard`] clears the caches and responds to new
+//! expensive requests with 503 errors while usage is over the limit.
+use crate::dandi::DandiClient;
+use crate::dav::UserClients;
+use crate::openapi::ApiSchema;
+use crate::zarrman::ManifestFetcher;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{
+        header::{CONTENT_TYPE, RETRY_AFTER},
+        HeaderValue, Method, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use http_body::{Body as _, Frame, SizeHint};
+use pin_project::{pin_project, pinned_drop};
+use serde::Serialize;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// The body to return with 503 responses to requests that are shed due to
+/// high memory usage
+static MEMORY_LIMIT_BODY: &str = "Server is low on memory; try again later\n";
+
+/// The value of the `Retry-After` header sent with 503 responses to requests
+/// that are shed due to high memory usage
+static MEMORY_LIMIT_RETRY_AFTER: &str = "30";
+
+/// Tracker of the memory used by the caches & in-flight responses, with an
+/// optional soft limit on the process's memory usage
+#[derive(Debug)]
+pub(crate) struct MemoryGuard {
+    /// The memory usage in bytes above which caches are cleared and expensive
+    /// requests are refused.  `None` means there is no limit.
+    soft_limit: Option<u64>,
+
+    /// The total size in bytes of response bodies that have been constructed
+    /// but not yet fully sent
+    in_flight: Arc<AtomicU64>,
+
+    /// Whether memory usage was over the soft limit as of the last check
+    over_limit: AtomicBool,
+
+    /// The client whose caches of Archive data are tracked
+    dandi: DandiClient,
+
+    /// The per-user clients whose caches of Archive data are tracked, if
+    /// credentials are forwarded
+    user_clients: Option<UserClients>,
+
+    /// The client whose cache of Zarr manifests is tracked
+    zarrfetcher: ManifestFetcher,
+}
+
+impl MemoryGuard {
+    /// Construct a new `MemoryGuard` for the caches of the given clients.  If
+    /// `soft_limit` is non-`None`, it is the memory usage in bytes above which
+    /// load is shed.
+    pub(crate) fn new(
+        soft_limit: Option<u64>,
+        dandi: DandiClient,
+        user_clients: Option<UserClients>,
+        zarrfetcher: ManifestFetcher,
+    ) -> MemoryGuard {
+        MemoryGuard {
+            soft_limit,
+            in_flight: Arc::new(AtomicU64::new(0)),
+            over_limit: AtomicBool::new(false),
+            dandi,
+            user_clients,
+            zarrfetcher,
+        }
+    }
+
+    /// Return a report on current memory usage
+    pub(crate) fn report(&self) -> MemoryReport {
+        let stats = memory_stats::memory_stats();
+        let api_cache = self.dandi.api_cache_usage()
+            + self
+                .user_clients
+                .as_ref()
+                .map(UserClients::api_cache_usage)
+                .unwrap_or_default();
+        let mut report = MemoryReport {
+            physical_mem: stats.map(|st| as_u64(st.physical_mem)),
+            virtual_mem: stats.map(|st| as_u64(st.virtual_mem)),
+            zarr_manifest_cache_size: self.zarrfetcher.cache_size(),
+            archive_cache_len: self
+                .dandi
+                .cache_len()
+                .saturating_add(self.user_clients.as_ref().map_or(0, UserClients::cache_len)),
+            api_cache_size: api_cache.stored_size,
+            api_cache_uncompressed_size: api_cache.uncompressed_size,
+            in_flight_response_size: self.in_flight.load(Ordering::Relaxed),
+            soft_limit: self.soft_limit,
+            over_limit: false,
+        };
+        report.over_limit = self.soft_limit.is_some_and(|lim| report.usage() > lim);
+        report
+    }
+
+    /// Return the memory usage to compare against the soft limit: the
+    /// process's physical memory usage if known, or else the tracked sizes of
+    /// the Zarr manifest cache & in-flight responses.
+    ///
+    /// Unlike [`MemoryGuard::report()`], this does not tally up the sizes of
+    /// the Archive API response caches, which would require visiting every
+    /// cache entry of every client on every expensive request.
+    fn usage(&self) -> u64 {
+        memory_stats::memory_stats().map_or_else(
+            || {
+                self.zarrfetcher
+                    .cache_size()
+                    .saturating_add(self.in_flight.load(Ordering::Relaxed))
+            },
+            |st| as_u64(st.physical_mem),
+        )
+    }
+
+    /// Check whether memory usage is over the soft limit.  If it is, the
+    /// caches are cleared.
+    fn check(&self) -> bool {
+        let usage = self.usage();
+        let over_limit = self.soft_limit.is_some_and(|lim| usage > lim);
+        let was_over = self.over_limit.swap(over_limit, Ordering::Relaxed);
+        if over_limit {
+            if !was_over {
+                tracing::warn!(
+                    usage,
+                    soft_limit = self.soft_limit,
+                    "Memory usage is over soft limit; clearing caches and refusing expensive requests",
+                );
+            }
+            self.dandi.clear_caches();
+            if let Some(ref users) = self.user_clients {
+                users.clear_caches();
+            }
+            self.zarrfetcher.clear_cache();
+        } else if was_over {
+            tracing::info!(
+                usage,
+                soft_limit = self.soft_limit,
+                "Memory usage is back under soft limit",
+            );
+        }
+        over_limit
+    }
+
+    /// Wrap `body` so that its size is counted as in-flight until it is
+    /// dropped.  Bodies of unknown size are returned unchanged.
+    fn track(&self, body: Body) -> Body {
+        match body.size_hint().exact() {
+            Some(size) if size > 0 => {
+                self.in_flight.fetch_add(size, Ordering::Relaxed);
+                Body::new(TrackedBody {
+                    inner: body,
+                    size,
+                    in_flight: Arc::clone(&self.in_flight),
+                })
+            }
+            _ => body,
+        }
+    }
+}
+
+/// A report on the memory usage of the process, as served at
+/// `/.admin/memory`.  All sizes are in bytes.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct MemoryReport {
+    /// The process's physical memory usage, if it could be determined
+    pub(crate) physical_mem: Option<u64>,
+
+    /// The process's virtual memory usage, if it could be determined
+    pub(crate) virtual_mem: Option<u64>,
+
+    /// The approximate total size of the cached Zarr manifests
+    pub(crate) zarr_manifest_cache_size: u64,
+
+    /// The approximate number of entries in the caches of Archive data,
+    /// including those of per-user clients
+    pub(crate) archive_cache_len: u64,
+
+    /// The total size of the cached Archive API responses as stored in memory,
+    /// including those of per-user clients
+    pub(crate) api_cache_size: u64,
+
+    /// The total size that the cached Archive API responses would take up if
+    /// none were compressed
+    pub(crate) api_cache_uncompressed_size: u64,
+
+    /// The total size of response bodies that have not yet been fully sent
+    pub(crate) in_flight_response_size: u64,
+
+    /// The configured soft memory limit, if any
+    pub(crate) soft_limit: Option<u64>,
+
+    /// Whether memory usage is over the soft limit
+    pub(crate) over_limit: bool,
+}
+
+impl MemoryReport {
+    /// Return the memory usage to compare against the soft limit: the
+    /// process's physical memory usage if known, or else the tracked sizes of
+    /// the Zarr manifest cache & in-flight responses
+    fn usage(&self) -> u64 {
+        self.physical_mem.unwrap_or_else(|| {
+            self.zarr_manifest_cache_size
+                .saturating_add(self.in_flight_response_size)
+        })
+    }
+}
+
+impl ApiSchema for MemoryReport {
+    fn schema() -> serde_json::Value {
+        let size = serde_json::json!({"type": "integer", "minimum": 0});
+        let opt_size = serde_json::json!({"type": ["integer", "null"], "minimum": 0});
+        serde_json::json!({
+            "type": "object",
+            "description": "Memory usage of the server; all sizes are in bytes",
+            "properties": {
+                "physical_mem": opt_size,
+                "virtual_mem": opt_size,
+                "zarr_manifest_cache_size": size,
+                "archive_cache_len": size,
+                "api_cache_size": size,
+                "api_cache_uncompressed_size": size,
+                "in_flight_response_size": size,
+                "soft_limit": opt_size,
+                "over_limit": {"type": "boolean"},
+            },
+            "required": [
+                "physical_mem",
+                "virtual_mem",
+                "zarr_manifest_cache_size",
+                "archive_cache_len",
+                "api_cache_size",
+                "api_cache_uncompressed_size",
+                "in_flight_response_size",
+                "soft_limit",
+                "over_limit",
+            ],
+        })
+    }
+}
+
+/// Middleware that refuses expensive requests with a 503 response while
+/// memory usage is over the soft limit and tracks the sizes of response bodies
+/// while they are being sent
+pub(crate) async fn guard_memory(
+    State(guard): State<Arc<MemoryGuard>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    if guard.soft_limit.is_some() && is_expensive(&request) && guard.check() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [
+                (
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("text/plain; charset=utf-8"),
+                ),
+                (
+                    RETRY_AFTER,
+                    HeaderValue::from_static(MEMORY_LIMIT_RETRY_AFTER),
+                ),
+            ],
+            MEMORY_LIMIT_BODY,
+        )
+            .into_response();
+    }
+    let resp = next.run(request).await;
+    let (parts, body) = resp.into_parts();
+    Response::from_parts(parts, guard.track(body))
+}
+
+/// Return whether `request` is one whose response may be expensive to
+/// construct: a `PROPFIND` with a nonzero depth or a `GET` of a collection
+fn is_expensive(request: &Request) -> bool {
+    let method = request.method();
+    if method == "PROPFIND" {
+        request
+            .headers()
+            .get("Depth")
+            .map_or(true, |depth| depth.as_bytes().trim_ascii() != b"0")
+    } else if method == Method::GET || method == Method::HEAD {
+        request.uri().path().ends_with('/')
+    } else {
+        false
+    }
+}
+
+/// Convert a `usize` memory measurement to a `u64`
+fn as_u64(n: usize) -> u64 {
+    u64::try_from(n).unwrap_or(u64::MAX)
+}
+
+/// A response body whose size is subtracted from the in-flight total when it
+/// is dropped
+#[pin_project(PinnedDrop)]
+#[derive(Debug)]
+struct TrackedBody {
+    /// The wrapped body
+    #[pin]
+    inner: Body,
+
+    /// The size of the wrapped body
+    size: u64,
+
+    /// The in-flight total to subtract `size` from
+    in_flight: Arc<AtomicU64>,
+}
+
+impl http_body::Body for TrackedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        self.project().inner.poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl PinnedDrop for TrackedBody {
+    fn drop(self: Pin<&mut Self>) {
+        self.in_flight.fetch_sub(self.size, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("PROPFIND", "/dandisets/", Some("1"), true)]
+    #[case("PROPFIND", "/dandisets/", None, true)]
+    #[case("PROPFIND", "/dandisets/", Some("0"), false)]
+    #[case("PROPFIND", "/dandisets/000001/draft/foo.nwb", Some("1"), true)]
+    #[case("GET", "/dandisets/", None, true)]
+    #[case("HEAD", "/dandisets/", None, true)]
+    #[case("GET", "/dandisets/000001/draft/foo.nwb", None, false)]
+    #[case("OPTIONS", "/dandisets/", None, false)]
+    fn test_is_expensive(
+        #[case] method: &str,
+        #[case] path: &str,
+        #[case] depth: Option<&str>,
+        #[case] expensive: bool,
+    ) {
+        let mut builder = Request::builder().method(method).uri(path);
+        if let Some(d) = depth {
+            builder = builder.header("Depth", d);
+        }
+        let request = builder.body(Body::empty()).unwrap();
+        assert_eq!(is_expensive(&request), expensive);
+    }
+
+    #[test]
+    fn usage_without_physical_mem() {
+        let report = MemoryReport {
+            physical_mem: None,
+            virtual_mem: None,
+            zarr_manifest_cache_size: 1000,
+            archive_cache_len: 42,
+            api_cache_size: 0,
+            api_cache_uncompressed_size: 0,
+            in_flight_response_size: 234,
+            soft_limit: None,
+            over_limit: false,
+        };
+        assert_eq!(report.usage(), 1234);
+    }
+
+    #[test]
+    fn tracked_body() {
+        let in_flight = Arc::new(AtomicU64::new(0));
+        let guard = MemoryGuard {
+            soft_limit: None,
+            in_flight: Arc::clone(&in_flight),
+            over_limit: AtomicBool::new(false),
+            dandi: DandiClient::new(
+                "https://api.example.com/api".parse().unwrap(),
+                None,
+                false,
+                0,
+                None,
+                None,
+            )
+            .unwrap(),
+            user_clients: None,
+            zarrfetcher: ManifestFetcher::new(1000, Vec::new(), None).unwrap(),
+        };
+        let body1 = guard.track(Body::from("0123456789"));
+        assert_eq!(in_flight.load(Ordering::Relaxed), 10);
+        let body2 = guard.track(Body::from("abcde"));
+        assert_eq!(in_flight.load(Ordering::Relaxed), 15);
+        drop(body1);
+        assert_eq!(in_flight.load(Ordering::Relaxed), 5);
+        drop(body2);
+        assert_eq!(in_flight.load(Ordering::Relaxed), 0);
+    }
+}
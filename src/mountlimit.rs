@@ -0,0 +1,196 @@
+//! Per-mount limits on the number of requests handled at once
+//!
+//! Bulk traffic to one part of the hierarchy (such as clients fetching every
+//! chunk of a Zarr under `/zarrs/`) can otherwise occupy all of `dandidav`'s
+//! handler & upstream capacity, leaving interactive browsing of `/dandisets/`
+//! waiting behind it.  When limits are configured, each mount gets its own
+//! pool of [`MountLimits`] permits, so that saturating one mount does not
+//! delay requests for the others.  Requests that cannot obtain a permit within
+//! [`MOUNT_QUEUE_TIMEOUT`] are answered with 503 errors.
+use crate::consts::MOUNT_QUEUE_TIMEOUT;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::Semaphore;
+
+/// The value of the `Retry-After` header sent with 503 responses to requests
+/// that could not obtain a permit for their mount in time
+static MOUNT_BUSY_RETRY_AFTER: &str = "10";
+
+/// A top-level subtree of the hierarchy served by `dandidav`
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub(crate) enum Mount {
+    /// `/dandisets/`
+    #[value(name = "dandisets")]
+    Dandisets,
+
+    /// `/zarrs/`
+    #[value(name = "zarrs")]
+    Zarrs,
+}
+
+impl Mount {
+    /// Return the mount that the request path `path` is under, if any
+    fn for_path(path: &str) -> Option<Mount> {
+        let first = path.trim_start_matches('/').split('/').next()?;
+        if first.eq_ignore_ascii_case("dandisets") {
+            Some(Mount::Dandisets)
+        } else if first.eq_ignore_ascii_case("zarrs") {
+            Some(Mount::Zarrs)
+        } else {
+            None
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Mount::Dandisets => "dandisets",
+            Mount::Zarrs => "zarrs",
+        }
+    }
+}
+
+impl fmt::Display for Mount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A limit on the number of requests for a given mount that may be handled at
+/// once, as given on the command line in the form `{mount}={limit}`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct MountLimit {
+    mount: Mount,
+    limit: NonZeroUsize,
+}
+
+impl FromStr for MountLimit {
+    type Err = ParseMountLimitError;
+
+    fn from_str(s: &str) -> Result<MountLimit, ParseMountLimitError> {
+        let (mount, limit) = s.split_once('=').ok_or(ParseMountLimitError::NoEquals)?;
+        let mount = match mount.trim() {
+            "dandisets" => Mount::Dandisets,
+            "zarrs" => Mount::Zarrs,
+            other => return Err(ParseMountLimitError::UnknownMount(other.to_owned())),
+        };
+        let limit = limit
+            .trim()
+            .parse::<NonZeroUsize>()
+            .map_err(|_| ParseMountLimitError::InvalidLimit(limit.to_owned()))?;
+        Ok(MountLimit { mount, limit })
+    }
+}
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub(crate) enum ParseMountLimitError {
+    #[error("expected a value of the form MOUNT=INT")]
+    NoEquals,
+    #[error("unknown mount {0:?}; expected \"dandisets\" or \"zarrs\"")]
+    UnknownMount(String),
+    #[error("invalid limit {0:?}; expected a positive integer")]
+    InvalidLimit(String),
+}
+
+/// The pools of permits for handling requests under each mount that has a
+/// limit
+#[derive(Debug)]
+pub(crate) struct MountLimits {
+    pools: BTreeMap<Mount, Arc<Semaphore>>,
+}
+
+impl MountLimits {
+    /// Construct a `MountLimits` from the given limits.  If a mount is given
+    /// more than once, the last limit for it wins.  Returns `None` if `limits`
+    /// is empty.
+    pub(crate) fn new(limits: &[MountLimit]) -> Option<MountLimits> {
+        let pools = limits
+            .iter()
+            .map(|ml| (ml.mount, Arc::new(Semaphore::new(ml.limit.get()))))
+            .collect::<BTreeMap<_, _>>();
+        (!pools.is_empty()).then_some(MountLimits { pools })
+    }
+}
+
+/// Middleware for making requests under a limited mount wait for a permit
+/// from the mount's pool before being handled
+pub(crate) async fn limit_mounts(
+    State(limits): State<Arc<MountLimits>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response<Body> {
+    let Some((mount, pool)) = Mount::for_path(request.uri().path())
+        .and_then(|m| limits.pools.get(&m).map(|pool| (m, Arc::clone(pool))))
+    else {
+        return next.run(request).await;
+    };
+    let permit = match tokio::time::timeout(MOUNT_QUEUE_TIMEOUT, pool.acquire_owned()).await {
+        Ok(Ok(permit)) => permit,
+        Ok(Err(_)) => unreachable!("mount semaphores should never be closed"),
+        Err(_) => {
+            tracing::warn!(%mount, "Timed out waiting for capacity to handle request");
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(
+                    RETRY_AFTER,
+                    HeaderValue::from_static(MOUNT_BUSY_RETRY_AFTER),
+                )],
+                format!("Server is busy handling requests under /{mount}/; try again later\n"),
+            )
+                .into_response();
+        }
+    };
+    let resp = next.run(request).await;
+    drop(permit);
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("/dandisets/000001/draft/", Some(Mount::Dandisets))]
+    #[case("/Dandisets", Some(Mount::Dandisets))]
+    #[case("/zarrs/056/", Some(Mount::Zarrs))]
+    #[case("//zarrs/", Some(Mount::Zarrs))]
+    #[case("/", None)]
+    #[case("/.admin/memory", None)]
+    #[case("/zarrsx/", None)]
+    fn test_mount_for_path(#[case] path: &str, #[case] mount: Option<Mount>) {
+        assert_eq!(Mount::for_path(path), mount);
+    }
+
+    #[rstest]
+    #[case("zarrs=16", Mount::Zarrs, 16)]
+    #[case(" dandisets = 64 ", Mount::Dandisets, 64)]
+    fn test_parse_mount_limit(#[case] s: &str, #[case] mount: Mount, #[case] limit: usize) {
+        assert_eq!(
+            s.parse::<MountLimit>(),
+            Ok(MountLimit {
+                mount,
+                limit: NonZeroUsize::new(limit).unwrap()
+            })
+        );
+    }
+
+    #[rstest]
+    #[case("zarrs")]
+    #[case("zarrs=0")]
+    #[case("zarrs=-1")]
+    #[case("feeds=4")]
+    fn test_parse_bad_mount_limit(#[case] s: &str) {
+        assert!(s.parse::<MountLimit>().is_err());
+    }
+}
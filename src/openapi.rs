@@ -0,0 +1,253 @@
+//! Machine-readable description of the non-WebDAV endpoints
+//!
+//! Alongside the WebDAV hierarchy, `dandidav` serves a handful of plain HTTP
+//! endpoints (static files and JSON admin reports).  These are registered via
+//! [`DocumentedRouter`], which records a description of each one as it is
+//! added, and the resulting descriptions are served as an OpenAPI document at
+//! [`OPENAPI_PATH`].
+use crate::consts::JSON_CONTENT_TYPE;
+use axum::{
+    handler::Handler,
+    http::header::CONTENT_TYPE,
+    routing::{get, Router},
+};
+use serde_json::{json, Map, Value};
+
+/// The path at which the OpenAPI document is served
+pub(crate) static OPENAPI_PATH: &str = "/.well-known/openapi.json";
+
+/// The version of the OpenAPI specification that the document conforms to
+static OPENAPI_VERSION: &str = "3.1.0";
+
+/// A type that can describe its JSON serialization as a JSON Schema, for use
+/// in the OpenAPI document
+pub(crate) trait ApiSchema {
+    /// Return a JSON Schema describing the serialization of the type
+    fn schema() -> Value;
+}
+
+impl<T: ApiSchema> ApiSchema for Vec<T> {
+    fn schema() -> Value {
+        json!({"type": "array", "items": T::schema()})
+    }
+}
+
+/// A description of a `GET` endpoint for inclusion in the OpenAPI document
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Endpoint {
+    /// The path at which the endpoint is served
+    path: &'static str,
+
+    /// A short summary of what the endpoint returns
+    summary: &'static str,
+
+    /// The `Content-Type` of successful responses
+    content_type: &'static str,
+
+    /// A JSON Schema describing successful responses, if they are JSON
+    schema: Option<Value>,
+}
+
+impl Endpoint {
+    /// Describe an endpoint at `path` that returns a non-JSON document of
+    /// the given content type
+    pub(crate) fn document(
+        path: &'static str,
+        summary: &'static str,
+        content_type: &'static str,
+    ) -> Endpoint {
+        Endpoint {
+            path,
+            summary,
+            content_type,
+            schema: None,
+        }
+    }
+
+    /// Describe an endpoint at `path` that returns a serialized `T` as JSON
+    pub(crate) fn json<T: ApiSchema>(path: &'static str, summary: &'static str) -> Endpoint {
+        Endpoint {
+            path,
+            summary,
+            content_type: JSON_CONTENT_TYPE,
+            schema: Some(T::schema()),
+        }
+    }
+
+    /// Return the OpenAPI path item object for the endpoint
+    fn path_item(&self) -> Value {
+        let mut media_type = Map::new();
+        if let Some(schema) = &self.schema {
+            media_type.insert("schema".into(), schema.clone());
+        }
+        json!({
+            "get": {
+                "summary": self.summary,
+                "responses": {
+                    "200": {
+                        "description": "Success",
+                        "content": {
+                            self.content_type: media_type,
+                        },
+                    },
+                },
+            },
+        })
+    }
+}
+
+/// A [`Router`] that records a description of each endpoint added to it
+#[derive(Debug, Default)]
+pub(crate) struct DocumentedRouter {
+    /// The router to which the endpoints are added
+    router: Router,
+
+    /// Descriptions of the endpoints added so far
+    endpoints: Vec<Endpoint>,
+}
+
+impl DocumentedRouter {
+    pub(crate) fn new() -> DocumentedRouter {
+        DocumentedRouter::default()
+    }
+
+    /// Serve `GET` requests for `endpoint.path` with `handler`
+    pub(crate) fn get<H, T>(mut self, endpoint: Endpoint, handler: H) -> DocumentedRouter
+    where
+        H: Handler<T, ()>,
+        T: 'static,
+    {
+        self.router = self.router.route(endpoint.path, get(handler));
+        self.endpoints.push(endpoint);
+        self
+    }
+
+    /// Add an endpoint serving the OpenAPI document at [`OPENAPI_PATH`] and
+    /// return the resulting [`Router`]
+    pub(crate) fn finish(self) -> Router {
+        let DocumentedRouter {
+            router,
+            mut endpoints,
+        } = self;
+        endpoints.push(Endpoint::document(
+            OPENAPI_PATH,
+            "This OpenAPI document",
+            JSON_CONTENT_TYPE,
+        ));
+        let body = openapi_document(&endpoints).to_string();
+        router.route(
+            OPENAPI_PATH,
+            get(|| async move {
+                // Note: This response should not have WebDAV headers (DAV, Allow)
+                ([(CONTENT_TYPE, JSON_CONTENT_TYPE)], body)
+            }),
+        )
+    }
+}
+
+/// Construct an OpenAPI document describing `endpoints`
+fn openapi_document(endpoints: &[Endpoint]) -> Value {
+    let paths = endpoints
+        .iter()
+        .map(|ep| (ep.path.to_owned(), ep.path_item()))
+        .collect::<Map<_, _>>();
+    json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": env!("CARGO_PKG_NAME"),
+            "description": "Non-WebDAV endpoints of dandidav, a WebDAV view to DANDI Archive",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{ClientClass, DownloadRecord};
+    use crate::memguard::MemoryReport;
+    use pretty_assertions::assert_eq;
+    use serde::Serialize;
+    use std::collections::BTreeSet;
+    use time::macros::datetime;
+
+    /// Assert that the object properties listed in `T`'s schema are the same
+    /// as the fields in the serialization of `value`
+    fn assert_schema_matches<T: ApiSchema + Serialize>(value: &T) {
+        let schema = T::schema();
+        let Value::Object(serialized) = serde_json::to_value(value).unwrap() else {
+            panic!("value did not serialize to an object");
+        };
+        let properties = schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let fields = serialized.keys().cloned().collect::<BTreeSet<_>>();
+        assert_eq!(properties, fields);
+        let required = schema["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_str().unwrap().to_owned())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(required, fields);
+    }
+
+    #[test]
+    fn memory_report_schema() {
+        assert_schema_matches(&MemoryReport {
+            physical_mem: Some(1),
+            virtual_mem: Some(2),
+            zarr_manifest_cache_size: 3,
+            archive_cache_len: 4,
+            api_cache_size: 5,
+            api_cache_uncompressed_size: 6,
+            in_flight_response_size: 7,
+            soft_limit: None,
+            over_limit: false,
+        });
+    }
+
+    #[test]
+    fn download_record_schema() {
+        assert_schema_matches(&DownloadRecord {
+            timestamp: datetime!(2024-01-01 00:00:00 UTC),
+            path: "/dandisets/000001/draft/foo.nwb".into(),
+            client_class: ClientClass::WebDav,
+        });
+    }
+
+    #[test]
+    fn document() {
+        let endpoints = vec![
+            Endpoint::document("/robots.txt", "Robots exclusion file", "text/plain"),
+            Endpoint::json::<Vec<DownloadRecord>>("/.admin/downloads", "Recent downloads"),
+        ];
+        let doc = openapi_document(&endpoints);
+        assert_eq!(doc["openapi"], "3.1.0");
+        assert_eq!(doc["info"]["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            doc["paths"]["/robots.txt"],
+            json!({
+                "get": {
+                    "summary": "Robots exclusion file",
+                    "responses": {
+                        "200": {
+                            "description": "Success",
+                            "content": {"text/plain": {}},
+                        },
+                    },
+                },
+            })
+        );
+        let content = &doc["paths"]["/.admin/downloads"]["get"]["responses"]["200"]["content"];
+        assert_eq!(content[JSON_CONTENT_TYPE]["schema"]["type"], "array");
+        assert_eq!(
+            content[JSON_CONTENT_TYPE]["schema"]["items"],
+            DownloadRecord::schema()
+        );
+    }
+}
@@ -30,6 +30,15 @@ validstr!(
     "a plain path component"
 );
 
+/// Return the extension of the file name `name` — the portion after the final
+/// period, without the period — following the same rules as
+/// [`std::path::Path::extension()`]: a name without a period, or whose only
+/// period is its first character, has no extension.
+pub(super) fn extension_of(name: &str) -> Option<&str> {
+    let (stem, ext) = name.rsplit_once('.')?;
+    (!stem.is_empty()).then_some(ext)
+}
+
 impl Component {
     pub(crate) fn strip_suffix(&self, suffix: &str) -> Option<Component> {
         let s = self.0.strip_suffix(suffix)?;
@@ -104,4 +113,22 @@ mod tests {
     fn test_strip_suffix(#[case] c: Component, #[case] suffix: &str, #[case] res: Option<&str>) {
         assert_eq!(c.strip_suffix(suffix).as_deref(), res);
     }
+
+    #[rstest]
+    #[case("foo", None)]
+    #[case("foo.nwb", Some("nwb"))]
+    #[case("foo.ome.zarr", Some("zarr"))]
+    #[case(".zarr", None)]
+    #[case(".foo.zarr", Some("zarr"))]
+    #[case("..zarr", Some("zarr"))]
+    #[case("foo.", Some(""))]
+    fn test_extension_of(#[case] name: &str, #[case] ext: Option<&str>) {
+        assert_eq!(extension_of(name), ext);
+        assert_eq!(
+            std::path::Path::new(name)
+                .extension()
+                .and_then(|s| s.to_str()),
+            ext
+        );
+    }
 }
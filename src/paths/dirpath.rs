@@ -1,3 +1,4 @@
+use super::component::extension_of;
 use super::{Component, PurePath};
 use thiserror::Error;
 
@@ -49,6 +50,13 @@ impl PureDirPath {
         Component(self.name_str().into())
     }
 
+    /// Return the extension of the final component of the path (without the
+    /// leading period), if any.  A component whose only period is at the
+    /// start (e.g., `.zarr`) has no extension.
+    pub(crate) fn extension(&self) -> Option<&str> {
+        extension_of(self.name_str())
+    }
+
     pub(crate) fn parent(&self) -> Option<PureDirPath> {
         let i = self.0.trim_end_matches('/').rfind('/')?;
         Some(PureDirPath(self.0[..=i].to_owned()))
@@ -79,6 +87,12 @@ impl PureDirPath {
     pub(crate) fn component_strs(&self) -> std::str::Split<'_, char> {
         self.0.trim_end_matches('/').split('/')
     }
+
+    /// Iterate over the directory paths formed by each leading portion of
+    /// this path, from the first component down to the whole path
+    pub(crate) fn ancestors(&self) -> Ancestors<'_> {
+        Ancestors::new(&self.0)
+    }
 }
 
 impl From<Component> for PureDirPath {
@@ -89,6 +103,32 @@ impl From<Component> for PureDirPath {
     }
 }
 
+/// Iterator over the directory paths formed by the leading portions of a path
+/// that end in a forward slash, shortest first
+#[derive(Clone, Debug)]
+pub(crate) struct Ancestors<'a> {
+    s: &'a str,
+    inner: std::str::MatchIndices<'a, char>,
+}
+
+impl<'a> Ancestors<'a> {
+    pub(super) fn new(s: &'a str) -> Self {
+        let inner = s.match_indices('/');
+        Ancestors { s, inner }
+    }
+}
+
+impl Iterator for Ancestors<'_> {
+    type Item = PureDirPath;
+
+    fn next(&mut self) -> Option<PureDirPath> {
+        let (i, _) = self.inner.next()?;
+        Some(PureDirPath(self.s[..=i].to_owned()))
+    }
+}
+
+impl std::iter::FusedIterator for Ancestors<'_> {}
+
 #[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
 pub(crate) enum ParsePureDirPathError {
     #[error("path does not end with a forward slash")]
@@ -160,6 +200,24 @@ mod tests {
         assert_eq!(dirpath.name_str(), name);
     }
 
+    #[rstest]
+    #[case("foo/", None)]
+    #[case("foo.zarr/", Some("zarr"))]
+    #[case("foo.zarr/bar/", None)]
+    #[case("foo/bar.ome.zarr/", Some("zarr"))]
+    #[case("foo/.zarr/", None)]
+    fn test_extension(#[case] dirpath: PureDirPath, #[case] ext: Option<&str>) {
+        assert_eq!(dirpath.extension(), ext);
+    }
+
+    #[rstest]
+    #[case("foo/", vec!["foo/"])]
+    #[case("foo/bar/", vec!["foo/", "foo/bar/"])]
+    #[case("foo/bar/baz/", vec!["foo/", "foo/bar/", "foo/bar/baz/"])]
+    fn test_ancestors(#[case] dirpath: PureDirPath, #[case] ancestors: Vec<&str>) {
+        assert_eq!(dirpath.ancestors().collect::<Vec<_>>(), ancestors);
+    }
+
     #[rstest]
     #[case("foo/", "bar", "foo/bar")]
     #[case("foo/", "baz/quux", "foo/baz/quux")]
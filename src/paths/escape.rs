@@ -0,0 +1,180 @@
+//! Reversible escaping of path components that break clients
+//!
+//! Some assets have names containing control characters or leading/trailing
+//! whitespace.  Such names cannot be represented in XML documents, are
+//! silently altered by many clients (e.g., Windows strips trailing spaces),
+//! and so cannot be round-tripped through WebDAV.  In responses, `dandidav`
+//! therefore replaces these names with escaped forms in which the problematic
+//! characters — along with any `%` characters, so that the escaping can be
+//! reversed unambiguously — are percent-encoded.  Names that do not need
+//! escaping are left unchanged.
+//!
+//! When a request for an escaped path finds nothing, the path is unescaped
+//! and looked up again, so that the resources remain reachable.
+use super::PurePath;
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// Returns true if `c` must be escaped wherever it occurs in a name
+fn is_always_escaped(c: char) -> bool {
+    c.is_control()
+}
+
+/// Returns true if `c` must be escaped when it occurs in the leading or
+/// trailing run of such characters in a name
+fn is_edge_escaped(c: char) -> bool {
+    c.is_whitespace()
+}
+
+/// Returns true if the path component `name` must be escaped before being
+/// shown to clients
+pub(crate) fn needs_escape(name: &str) -> bool {
+    name.chars().any(is_always_escaped)
+        || name.starts_with(is_edge_escaped)
+        || name.ends_with(is_edge_escaped)
+}
+
+/// Return the escaped form of the path component `name`.  If the name does
+/// not need escaping, it is returned unchanged.
+pub(crate) fn escape_name(name: &str) -> Cow<'_, str> {
+    if !needs_escape(name) {
+        return Cow::Borrowed(name);
+    }
+    let start = name.len() - name.trim_start_matches(is_edge_escaped).len();
+    let end = name.trim_end_matches(is_edge_escaped).len().max(start);
+    let mut escaped = String::with_capacity(name.len() * 2);
+    for (i, c) in name.char_indices() {
+        if c == '%' || is_always_escaped(c) || i < start || i >= end {
+            let mut buf = [0u8; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                let _ = write!(escaped, "%{b:02X}");
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// If `s` is the escaped form of a path component that needs escaping, return
+/// the original component.  Otherwise, return `None`.
+pub(crate) fn unescape_name(s: &str) -> Option<String> {
+    if !s.contains('%') {
+        return None;
+    }
+    let name = percent_encoding::percent_decode_str(s)
+        .decode_utf8()
+        .ok()?
+        .into_owned();
+    (needs_escape(&name) && escape_name(&name) == s).then_some(name)
+}
+
+/// Escape each component of the slash-separated path `path`
+pub(crate) fn escape_path(path: &str) -> Cow<'_, str> {
+    if !path.split('/').any(needs_escape) {
+        return Cow::Borrowed(path);
+    }
+    let mut escaped = String::with_capacity(path.len() * 2);
+    for (i, part) in path.split('/').enumerate() {
+        if i > 0 {
+            escaped.push('/');
+        }
+        escaped.push_str(&escape_name(part));
+    }
+    Cow::Owned(escaped)
+}
+
+impl PurePath {
+    /// If any components of this path are escaped forms of names that need
+    /// escaping, return the path with those components unescaped
+    pub(crate) fn unescaped(&self) -> Option<PurePath> {
+        let mut changed = false;
+        let mut parts = Vec::new();
+        for part in self.component_strs() {
+            match unescape_name(part) {
+                Some(name) => {
+                    changed = true;
+                    parts.push(Cow::Owned(name));
+                }
+                None => parts.push(Cow::Borrowed(part)),
+            }
+        }
+        if changed {
+            parts.join("/").parse::<PurePath>().ok()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("foo.nwb", "foo.nwb")]
+    #[case("foo bar.nwb", "foo bar.nwb")]
+    #[case("100%.nwb", "100%.nwb")]
+    #[case("foo.nwb ", "foo.nwb%20")]
+    #[case(" foo.nwb", "%20foo.nwb")]
+    #[case("foo \t", "foo%20%09")]
+    #[case("  ", "%20%20")]
+    #[case("foo\u{1B}[31mbar", "foo%1B[31mbar")]
+    #[case("foo\u{85}bar", "foo%C2%85bar")]
+    #[case("100% \u{7}", "100%25 %07")]
+    #[case("foo bar\u{3000}", "foo bar%E3%80%80")]
+    fn test_escape_name(#[case] name: &str, #[case] escaped: &str) {
+        assert_eq!(escape_name(name), escaped);
+        if name == escaped {
+            assert_eq!(unescape_name(escaped), None);
+        } else {
+            assert_eq!(unescape_name(escaped).as_deref(), Some(name));
+        }
+    }
+
+    #[rstest]
+    #[case("foo%20bar")]
+    #[case("foo%2")]
+    #[case("foo%FF%20")]
+    #[case("100%25")]
+    #[case("foo%20%09x%20")]
+    fn test_unescape_name_not_escaped(#[case] s: &str) {
+        assert_eq!(unescape_name(s), None);
+    }
+
+    #[rstest]
+    #[case("foo/bar.nwb", "foo/bar.nwb")]
+    #[case("/foo /bar.nwb/", "/foo%20/bar.nwb/")]
+    #[case("foo/\u{7}", "foo/%07")]
+    fn test_escape_path(#[case] path: &str, #[case] escaped: &str) {
+        assert_eq!(escape_path(path), escaped);
+    }
+
+    #[rstest]
+    #[case("foo/bar.nwb", None)]
+    #[case("foo%20/bar.nwb", Some("foo /bar.nwb"))]
+    #[case("foo%20/bar%07%20", Some("foo /bar\u{7} "))]
+    #[case("foo/100%25", None)]
+    #[case("foo/bar%00", None)]
+    fn test_purepath_unescaped(#[case] path: PurePath, #[case] unescaped: Option<&str>) {
+        assert_eq!(path.unescaped().as_deref(), unescaped);
+    }
+
+    proptest! {
+        #[test]
+        fn escape_roundtrip(name in "[a%\\x00-\\x1F \u{85}\u{3000}é]{1,12}") {
+            let escaped = escape_name(&name);
+            prop_assert!(!escaped.starts_with(char::is_whitespace));
+            prop_assert!(!escaped.ends_with(char::is_whitespace));
+            prop_assert!(!escaped.contains(char::is_control));
+            if needs_escape(&name) {
+                let unescaped = unescape_name(&escaped);
+                prop_assert_eq!(unescaped.as_deref(), Some(&*name));
+            } else {
+                prop_assert_eq!(escaped, &*name);
+            }
+        }
+    }
+}
@@ -1,7 +1,9 @@
 //! Path types with restricted formats
 mod component;
 mod dirpath;
+mod escape;
 mod purepath;
 pub(crate) use self::component::*;
 pub(crate) use self::dirpath::*;
+pub(crate) use self::escape::*;
 pub(crate) use self::purepath::*;
@@ -1,4 +1,4 @@
-use super::{Component, PureDirPath};
+use super::{Ancestors, Component, PureDirPath};
 use crate::consts::ZARR_EXTENSIONS;
 use thiserror::Error;
 
@@ -47,6 +47,13 @@ impl PurePath {
         PurePath(format!("{self}/{c}"))
     }
 
+    /// Return the path to the directory containing this path, or `None` if
+    /// this path consists of a single component
+    pub(crate) fn parent(&self) -> Option<PureDirPath> {
+        let i = self.0.rfind('/')?;
+        Some(PureDirPath(self.0[..=i].to_owned()))
+    }
+
     pub(crate) fn is_strictly_under(&self, other: &PureDirPath) -> bool {
         self.0.starts_with(&other.0)
     }
@@ -79,6 +86,12 @@ impl PurePath {
         self.0.split('/').map(|c| Component(c.into()))
     }
 
+    /// Iterate over the paths of the directories containing this path, from
+    /// the top-level directory down to the path's parent
+    pub(crate) fn ancestors(&self) -> Ancestors<'_> {
+        Ancestors::new(&self.0)
+    }
+
     pub(crate) fn push(&mut self, c: &Component) {
         self.0.push('/');
         self.0.push_str(c.as_ref());
@@ -116,15 +129,14 @@ pub(crate) enum ParsePurePathError {
 
 #[derive(Clone, Debug)]
 pub(crate) struct SplitZarrCandidates<'a> {
-    s: &'a str,
-    inner: std::str::MatchIndices<'a, char>,
+    path: &'a PurePath,
+    inner: Ancestors<'a>,
 }
 
 impl<'a> SplitZarrCandidates<'a> {
     fn new(path: &'a PurePath) -> Self {
-        let s = &path.0;
-        let inner = s.match_indices('/');
-        SplitZarrCandidates { s, inner }
+        let inner = path.ancestors();
+        SplitZarrCandidates { path, inner }
     }
 }
 
@@ -132,17 +144,18 @@ impl Iterator for SplitZarrCandidates<'_> {
     type Item = (PurePath, PurePath);
 
     fn next(&mut self) -> Option<(PurePath, PurePath)> {
-        for (i, _) in self.inner.by_ref() {
-            let zarrpath = &self.s[..i];
-            let entrypath = &self.s[(i + 1)..];
-            for ext in ZARR_EXTENSIONS {
-                if let Some(pre) = zarrpath.strip_suffix(ext) {
-                    if !pre.is_empty() && !pre.ends_with('/') {
-                        let zarrpath = PurePath(zarrpath.into());
-                        let entrypath = PurePath(entrypath.into());
-                        return Some((zarrpath, entrypath));
-                    }
-                }
+        for dirpath in self.inner.by_ref() {
+            if dirpath
+                .extension()
+                .is_some_and(|ext| ZARR_EXTENSIONS.contains(&ext))
+            {
+                let entrypath = self
+                    .path
+                    .relative_to(&dirpath)
+                    .expect("path should be under its ancestors");
+                let mut zarrpath = dirpath.0;
+                zarrpath.pop();
+                return Some((PurePath(zarrpath), entrypath));
             }
         }
         None
@@ -210,6 +223,14 @@ mod tests {
         assert_eq!(path.join_one(&c), res);
     }
 
+    #[rstest]
+    #[case("foo", None)]
+    #[case("foo/bar", Some("foo/"))]
+    #[case("foo/bar/baz.nwb", Some("foo/bar/"))]
+    fn test_parent(#[case] path: PurePath, #[case] parent: Option<&str>) {
+        assert_eq!(path.parent().as_ref().map(AsRef::as_ref), parent);
+    }
+
     #[rstest]
     #[case("foo/bar", "foo/", Some("bar"))]
     #[case("foo/bar/quux", "foo/", Some("bar/quux"))]
@@ -255,6 +276,14 @@ mod tests {
         );
     }
 
+    #[rstest]
+    #[case("foo", Vec::new())]
+    #[case("foo/bar", vec!["foo/"])]
+    #[case("foo/bar/baz.nwb", vec!["foo/", "foo/bar/"])]
+    fn test_ancestors(#[case] path: PurePath, #[case] ancestors: Vec<&str>) {
+        assert_eq!(path.ancestors().collect::<Vec<_>>(), ancestors);
+    }
+
     mod split_zarr_candidates {
         use super::*;
 
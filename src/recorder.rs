@@ -0,0 +1,336 @@
+//! Recording of upstream HTTP interactions as mock archive response stubs
+//!
+//! When enabled, the response to every `GET` request made by `dandidav`'s HTTP
+//! clients is saved in the response stub format described in
+//! `doc/mocks.md`, so that a directory of recordings can be served by the mock
+//! archive used in the tests.  The stubs for each upstream server are saved
+//! in a subdirectory named after the server's host.
+use crate::blocking::run_blocking;
+use axum::http::{self, StatusCode};
+use bytes::Bytes;
+use reqwest::{Method, Request, Response, ResponseBuilderExt};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use thiserror::Error;
+use url::Url;
+
+/// Placeholder for the mock archive's base URL in the `next` fields of
+/// paginated responses
+static BASE_URL_PLACEHOLDER: &str = "{base_url}";
+
+/// HTTP client middleware that saves the responses to outgoing `GET` requests
+/// as response stubs in a directory
+#[derive(Debug)]
+pub(crate) struct FixtureRecorder {
+    /// The directory in which to save the stubs
+    dir: Arc<Path>,
+
+    /// Lock held while updating a stub file, so that concurrent responses
+    /// for the same file do not clobber each other
+    lock: Arc<Mutex<()>>,
+}
+
+impl FixtureRecorder {
+    /// Construct a new `FixtureRecorder` that saves stubs beneath `dir`,
+    /// creating the directory if it does not exist
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created
+    pub(crate) fn new<P: Into<PathBuf>>(dir: P) -> std::io::Result<FixtureRecorder> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(FixtureRecorder {
+            dir: dir.into(),
+            lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Record the response with the given status and body for a `GET` request
+    /// to `url`.  Responses with non-JSON bodies are not recorded.
+    ///
+    /// The stub file is updated on the blocking thread pool.  Failures are
+    /// logged rather than returned, as recording is not allowed to affect the
+    /// serving of requests.
+    async fn record(&self, url: Url, status: StatusCode, body: Bytes) {
+        let dir = Arc::clone(&self.dir);
+        let lock = Arc::clone(&self.lock);
+        run_blocking(move || {
+            let Some(path) = stub_path(&dir, &url) else {
+                tracing::debug!(%url, "Not recording response for URL without host or path");
+                return;
+            };
+            let Ok(mut response) = serde_json::from_slice::<serde_json::Value>(&body) else {
+                tracing::debug!(%url, "Not recording non-JSON response");
+                return;
+            };
+            templatize_next(&mut response, &url);
+            let stub = Stub {
+                params: url
+                    .query_pairs()
+                    .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                    .collect(),
+                status: status.as_u16(),
+                response,
+            };
+            let _guard = lock.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Err(e) = update_stub_file(&path, stub) {
+                tracing::warn!(%url, path = %path.display(), error = %e, "Failed to record response");
+            } else {
+                tracing::debug!(%url, path = %path.display(), "Recorded response");
+            }
+        })
+        .await;
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for FixtureRecorder {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut http::Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let url = req.url().clone();
+        let is_get = req.method() == Method::GET;
+        let resp = next.run(req, extensions).await?;
+        if !is_get {
+            return Ok(resp);
+        }
+        // The body has to be read in full in order to record it, after which
+        // an equivalent response is reassembled for the caller.
+        let mut builder = http::Response::builder()
+            .status(resp.status())
+            .version(resp.version())
+            .url(resp.url().clone());
+        if let Some(headers) = builder.headers_mut() {
+            headers.extend(resp.headers().clone());
+        }
+        let status = resp.status();
+        let body = resp.bytes().await?;
+        self.record(url, status, body.clone()).await;
+        Ok(Response::from(builder.body(body).expect(
+            "reassembling a received response should not fail",
+        )))
+    }
+}
+
+/// A response stub, as read by the mock archive
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct Stub {
+    /// The query parameters of the request
+    params: BTreeMap<String, String>,
+
+    /// The status code of the response
+    #[serde(default = "default_status", skip_serializing_if = "is_default_status")]
+    status: u16,
+
+    /// The body of the response
+    response: serde_json::Value,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+fn is_default_status(status: &u16) -> bool {
+    *status == default_status()
+}
+
+/// Return the path of the stub file beneath `dir` in which to record
+/// responses for `url`, or `None` if the URL has no host or no non-empty path
+/// components
+fn stub_path(dir: &Path, url: &Url) -> Option<PathBuf> {
+    let host = url.host_str()?;
+    let mut parts = url
+        .path_segments()?
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<_>>();
+    let last = parts.pop()?;
+    let mut path = dir.join(host);
+    path.extend(parts);
+    path.push(format!("{last}.json"));
+    Some(path)
+}
+
+/// If `response` is a paginated response whose `next` URL points to the same
+/// server as `url`, replace the server's origin in the `next` URL with
+/// [`BASE_URL_PLACEHOLDER`] so that the mock archive will point it at itself
+fn templatize_next(response: &mut serde_json::Value, url: &Url) {
+    let Some(serde_json::Value::String(next)) =
+        response.as_object_mut().and_then(|m| m.get_mut("next"))
+    else {
+        return;
+    };
+    let origin = url.origin().ascii_serialization();
+    if let Some(rest) = next.strip_prefix(&origin) {
+        *next = format!("{BASE_URL_PLACEHOLDER}{rest}");
+    }
+}
+
+/// Add `stub` to the list of stubs in the file at `path` (creating the file
+/// and its parent directories if necessary), replacing any stub with the same
+/// query parameters
+fn update_stub_file(path: &Path, stub: Stub) -> Result<(), RecordError> {
+    let mut stubs = match std::fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str::<Vec<Stub>>(&text)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    stubs.retain(|st| st.params != stub.params);
+    stubs.push(stub);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut fp = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut fp, &stubs)?;
+    fp.write_all(b"\n")?;
+    fp.flush()?;
+    Ok(())
+}
+
+/// Error returned when updating a stub file fails
+#[derive(Debug, Error)]
+enum RecordError {
+    #[error("I/O error")]
+    Io(#[from] std::io::Error),
+    #[error("failed to (de)serialize stubs")]
+    Json(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::httputil::{Client, HttpUrl};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::sync::Arc;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_stub_path() {
+        let url = Url::parse("https://api.example.com/api/dandisets/000001/?page=2").unwrap();
+        assert_eq!(
+            stub_path(Path::new("fixtures"), &url),
+            Some(
+                [
+                    "fixtures",
+                    "api.example.com",
+                    "api",
+                    "dandisets",
+                    "000001.json"
+                ]
+                .iter()
+                .collect::<PathBuf>()
+            )
+        );
+        let url = Url::parse("https://api.example.com/").unwrap();
+        assert_eq!(stub_path(Path::new("fixtures"), &url), None);
+    }
+
+    #[test]
+    fn test_templatize_next() {
+        let url = Url::parse("https://api.example.com/api/dandisets/?page=1").unwrap();
+        let mut response = json!({
+            "next": "https://api.example.com/api/dandisets/?page=2",
+            "results": [],
+        });
+        templatize_next(&mut response, &url);
+        assert_eq!(
+            response,
+            json!({
+                "next": "{base_url}/api/dandisets/?page=2",
+                "results": [],
+            })
+        );
+    }
+
+    #[test]
+    fn test_templatize_foreign_next() {
+        let url = Url::parse("https://api.example.com/api/dandisets/?page=1").unwrap();
+        let mut response = json!({"next": "https://elsewhere.example.com/?page=2"});
+        templatize_next(&mut response, &url);
+        assert_eq!(
+            response,
+            json!({"next": "https://elsewhere.example.com/?page=2"})
+        );
+    }
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/dandisets/"))
+            .and(query_param("page", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "next": format!("{}/api/dandisets/?page=2", server.uri()),
+                "results": [{"identifier": "000001"}],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/dandisets/000002/"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({"detail": "Not found."})))
+            .mount(&server)
+            .await;
+
+        let dir = std::env::temp_dir().join(format!("dandidav-fixtures-{}", uuid::Uuid::new_v4()));
+        let recorder = Arc::new(FixtureRecorder::new(&dir).unwrap());
+        let client = Client::new(Some(recorder)).unwrap();
+        let url = format!("{}/api/dandisets/?page=1", server.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let page = client.get_json::<serde_json::Value>(url).await.unwrap();
+        assert_eq!(page["results"], json!([{"identifier": "000001"}]));
+        let url = format!("{}/api/dandisets/000002/", server.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        assert!(client.get(url).await.is_err());
+
+        let stubs = serde_json::from_str::<serde_json::Value>(
+            &std::fs::read_to_string(dir.join("127.0.0.1").join("api").join("dandisets.json"))
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            stubs,
+            json!([{
+                "params": {"page": "1"},
+                "response": {
+                    "next": "{base_url}/api/dandisets/?page=2",
+                    "results": [{"identifier": "000001"}],
+                },
+            }])
+        );
+
+        let mock = testutils::make_mock_archive(&dir.join("127.0.0.1")).await;
+        let r = reqwest::get(format!("{}/api/dandisets/?page=1", mock.uri()))
+            .await
+            .unwrap();
+        assert_eq!(r.status(), StatusCode::OK);
+        assert_eq!(
+            r.json::<serde_json::Value>().await.unwrap(),
+            json!({
+                "next": format!("{}/api/dandisets/?page=2", mock.uri()),
+                "results": [{"identifier": "000001"}],
+            })
+        );
+        let r = reqwest::get(format!("{}/api/dandisets/000002/", mock.uri()))
+            .await
+            .unwrap();
+        assert_eq!(r.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            r.json::<serde_json::Value>().await.unwrap(),
+            json!({"detail": "Not found."})
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
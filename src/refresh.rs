@@ -0,0 +1,165 @@
+//! Refreshing cached values in the background before they expire
+//!
+//! Caches with a time-to-live normally only fetch a new value once the old
+//! one has expired, making whichever client happens to request it next wait
+//! for the slow path.  Values stored in such caches are therefore wrapped in
+//! [`Fetched`], which records a point, shortly before expiry, after which the
+//! next access to the value should trigger a background refresh.  Values that
+//! are in use thus get replaced before they expire, while values that aren't
+//! are left to expire as usual.
+//!
+//! The refresh point of each value is jittered so that values fetched at the
+//! same time are not all refreshed at the same time.
+//!
+//! Caches holding [`Fetched`] values should keep their entries for
+//! [`CACHE_STALE_WINDOW`] past the nominal time-to-live (see
+//! [`cache_lifetime()`]).  A value that is accessed after its time-to-live
+//! but before it is evicted is then served stale while a single background
+//! task refreshes it, rather than every concurrent request missing the cache
+//! and fetching the value anew.
+use crate::consts::{CACHE_STALE_WINDOW, REFRESH_AHEAD_JITTER, REFRESH_AHEAD_START};
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// A cached value along with the time at which it should be refreshed
+#[derive(Clone, Debug)]
+pub(crate) struct Fetched<V> {
+    /// The cached value
+    pub(crate) value: V,
+
+    /// The time after which accessing the value should trigger a refresh
+    refresh_at: Instant,
+
+    /// The time after which the value is past its time-to-live and is only
+    /// served while awaiting a refresh
+    stale_at: Instant,
+}
+
+impl<V> Fetched<V> {
+    /// Wrap a value that was just fetched for storage in a cache whose
+    /// entries expire `ttl` after insertion
+    pub(crate) fn new(value: V, ttl: Duration) -> Fetched<V> {
+        let now = Instant::now();
+        Fetched {
+            value,
+            refresh_at: now + refresh_delay(ttl, fastrand::f64()),
+            stale_at: now + ttl,
+        }
+    }
+
+    /// Returns true if the value is close enough to expiry that it should be
+    /// refreshed
+    pub(crate) fn needs_refresh(&self) -> bool {
+        Instant::now() >= self.refresh_at
+    }
+
+    /// Returns true if the value is past its time-to-live and is only still
+    /// cached because of [`CACHE_STALE_WINDOW`]
+    pub(crate) fn is_stale(&self) -> bool {
+        Instant::now() >= self.stale_at
+    }
+}
+
+/// Return how long a cache of [`Fetched`] values with the given `ttl` should
+/// keep its entries, allowing for stale values to be served while they are
+/// refreshed
+pub(crate) fn cache_lifetime(ttl: Duration) -> Duration {
+    ttl.saturating_add(CACHE_STALE_WINDOW)
+}
+
+/// Return how long after insertion a value in a cache with the given `ttl`
+/// should be refreshed.  `jitter` is a number in `[0, 1)` selecting a point
+/// within the refresh window.
+fn refresh_delay(ttl: Duration, jitter: f64) -> Duration {
+    ttl.mul_f64(REFRESH_AHEAD_JITTER.mul_add(-jitter, REFRESH_AHEAD_START))
+}
+
+/// A record of which cache keys currently have background refreshes in
+/// progress, used to avoid refreshing the same value multiple times at once
+#[derive(Debug)]
+pub(crate) struct Refreshing<K>(Arc<Mutex<HashSet<K>>>);
+
+impl<K: Clone + Eq + Hash> Refreshing<K> {
+    pub(crate) fn new() -> Refreshing<K> {
+        Refreshing(Arc::new(Mutex::new(HashSet::new())))
+    }
+
+    /// Mark `key` as being refreshed and return a guard that unmarks it when
+    /// dropped.  Returns `None` if `key` is already being refreshed.
+    pub(crate) fn start(&self, key: K) -> Option<RefreshGuard<K>> {
+        let mut keys = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        keys.insert(key.clone()).then(|| RefreshGuard {
+            keys: Arc::clone(&self.0),
+            key,
+        })
+    }
+}
+
+impl<K> Clone for Refreshing<K> {
+    fn clone(&self) -> Refreshing<K> {
+        Refreshing(Arc::clone(&self.0))
+    }
+}
+
+/// A guard marking a cache key as being refreshed for as long as it lives
+#[derive(Debug)]
+pub(crate) struct RefreshGuard<K: Eq + Hash> {
+    keys: Arc<Mutex<HashSet<K>>>,
+    key: K,
+}
+
+impl<K: Eq + Hash> Drop for RefreshGuard<K> {
+    fn drop(&mut self) {
+        self.keys
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(0.0, 225.0)]
+    #[case(0.5, 202.5)]
+    #[case(0.9, 184.5)]
+    fn test_refresh_delay(#[case] jitter: f64, #[case] secs: f64) {
+        let delay = refresh_delay(Duration::from_secs(300), jitter);
+        assert!((delay.as_secs_f64() - secs).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fresh_value() {
+        let fetched = Fetched::new(42, Duration::from_secs(300));
+        assert!(!fetched.needs_refresh());
+        assert!(!fetched.is_stale());
+        let fetched = Fetched::new(42, Duration::ZERO);
+        assert!(fetched.needs_refresh());
+        assert!(fetched.is_stale());
+    }
+
+    #[test]
+    fn test_cache_lifetime() {
+        assert_eq!(
+            cache_lifetime(Duration::from_secs(300)),
+            Duration::from_secs(300) + CACHE_STALE_WINDOW
+        );
+        assert_eq!(cache_lifetime(Duration::MAX), Duration::MAX);
+    }
+
+    #[test]
+    fn refresh_once_at_a_time() {
+        let refreshing = Refreshing::new();
+        let guard = refreshing.start("foo");
+        assert!(guard.is_some());
+        assert!(refreshing.start("foo").is_none());
+        assert!(refreshing.start("bar").is_some());
+        drop(guard);
+        assert!(refreshing.start("foo").is_some());
+    }
+}
@@ -1,18 +1,26 @@
 //! Facilities for retrieving information from an S3 bucket
 mod streams;
 use self::streams::ListEntryPages;
+use crate::consts::DEFAULT_S3_REGION;
 use crate::dav::ErrorClass;
 use crate::httputil::{self, BuildClientError, HttpError, HttpUrl, ParseHttpUrlError};
 use crate::paths::{ParsePureDirPathError, ParsePurePathError, PureDirPath, PurePath};
 use crate::streamutil::TryStreamUtil;
 use crate::validstr::TryFromStringError;
-use aws_sdk_s3::{operation::list_objects_v2::ListObjectsV2Error, types::CommonPrefix, Client};
+use aws_sdk_s3::{
+    operation::{get_object::GetObjectError, list_objects_v2::ListObjectsV2Error},
+    presigning::{PresigningConfig, PresigningConfigError},
+    types::CommonPrefix,
+    Client,
+};
 use aws_smithy_runtime_api::client::{orchestrator::HttpResponse, result::SdkError};
 use aws_smithy_types_convert::date_time::DateTimeExt;
 use futures_util::{Stream, TryStreamExt};
+use reqwest::{Method, StatusCode};
 use smartstring::alias::CompactString;
 use std::cmp::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 use time::OffsetDateTime;
 use url::{Host, Url};
@@ -23,21 +31,74 @@ type ListObjectsError = SdkError<ListObjectsV2Error, HttpResponse>;
 pub(crate) struct S3Client {
     inner: Client,
     bucket: CompactString,
+    /// The URL of the bucket, used as the base for objects' download URLs
+    base_url: HttpUrl,
 }
 
 impl S3Client {
-    async fn new(bucket: CompactString, region: String) -> S3Client {
-        let config = aws_config::from_env()
+    /// Construct a client for the given bucket in the given region, sending
+    /// requests to the endpoint in `settings` (if any).  If `authenticated` is
+    /// true, requests are signed with credentials obtained from the
+    /// environment via the standard AWS credential provider chain; otherwise,
+    /// requests are made anonymously.
+    async fn new(
+        bucket: CompactString,
+        region: String,
+        authenticated: bool,
+        settings: &S3Settings,
+    ) -> S3Client {
+        let mut loader = aws_config::from_env()
             .app_name(
                 aws_config::AppName::new("dandidav")
                     .expect(r#""dandidav" should be a valid app name"#),
             )
-            .no_credentials()
-            .region(aws_config::Region::new(region))
-            .load()
-            .await;
-        let inner = Client::new(&config);
-        S3Client { inner, bucket }
+            .region(aws_config::Region::new(region));
+        if !authenticated {
+            loader = loader.no_credentials();
+        }
+        let config = loader.load().await;
+        let mut builder = aws_sdk_s3::config::Builder::from(&config);
+        if let Some(ref endpoint) = settings.endpoint {
+            builder = builder
+                .endpoint_url(endpoint.as_str().trim_end_matches('/'))
+                .force_path_style(true);
+        }
+        let inner = Client::from_conf(builder.build());
+        let base_url = settings.bucket_url(&bucket);
+        S3Client {
+            inner,
+            bucket,
+            base_url,
+        }
+    }
+
+    /// Return a presigned URL for downloading the object at `key` that
+    /// remains valid for `expires_in`.  This requires the client to have been
+    /// constructed with credentials.
+    pub(crate) async fn presign_get(
+        &self,
+        key: &str,
+        expires_in: Duration,
+    ) -> Result<HttpUrl, S3Error> {
+        let mkerr = |source| S3Error::Presign {
+            bucket: self.bucket.clone(),
+            key: key.to_owned(),
+            source,
+        };
+        let config =
+            PresigningConfig::expires_in(expires_in).map_err(|e| mkerr(PresignError::from(e)))?;
+        let request = self
+            .inner
+            .get_object()
+            .bucket(&*self.bucket)
+            .key(key)
+            .presigned(config)
+            .await
+            .map_err(|e| mkerr(PresignError::from(Box::new(e))))?;
+        request
+            .uri()
+            .parse::<HttpUrl>()
+            .map_err(|e| mkerr(PresignError::from(e)))
     }
 
     pub(crate) fn with_prefix(self: Arc<Self>, prefix: PureDirPath) -> PrefixedS3Client {
@@ -67,6 +128,9 @@ impl S3Client {
         let folder_cutoff = format!("{path}/");
         let mut stream = self.list_entry_pages(path);
         while let Some(page) = stream.try_next().await? {
+            if page.bad_objects.iter().any(|obj| &obj.key == path) {
+                return Ok(Some(S3Entry::BadObject(S3BadObject { key: path.clone() })));
+            }
             if !surpassed_objects {
                 for obj in page.objects {
                     match path.cmp(&obj.key) {
@@ -145,12 +209,102 @@ pub(crate) struct BucketSpec {
 }
 
 impl BucketSpec {
-    pub(crate) async fn into_s3client(self) -> Result<S3Client, GetBucketRegionError> {
-        let region = match self.region {
-            Some(region) => region,
-            None => get_bucket_region(&self.bucket).await?,
+    /// Construct a client for the bucket, looking up its region if not
+    /// known and not overridden by `settings`.  If `authenticated` is true,
+    /// the client signs its requests with credentials obtained from the
+    /// environment.
+    pub(crate) async fn into_s3client(
+        self,
+        authenticated: bool,
+        settings: &S3Settings,
+    ) -> Result<S3Client, GetBucketRegionError> {
+        let region = match (&settings.region, self.region) {
+            (Some(region), _) => region.clone(),
+            (None, Some(region)) => region,
+            // Region lookup only works for buckets on AWS
+            (None, None) if settings.endpoint.is_some() => DEFAULT_S3_REGION.to_owned(),
+            (None, None) => get_bucket_region(&self.bucket).await?,
         };
-        Ok(S3Client::new(self.bucket, region).await)
+        Ok(S3Client::new(self.bucket, region, authenticated, settings).await)
+    }
+}
+
+/// Overrides for how S3 is accessed, for use with S3-compatible services
+/// other than AWS (e.g., MinIO) and with non-AWS mirrors of the Archive's
+/// buckets
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct S3Settings {
+    /// The base URL of the S3 service to send requests to instead of AWS.
+    /// Buckets on the service are addressed path-style, i.e., objects are
+    /// located at `{endpoint}/{bucket}/{key}`.
+    pub(crate) endpoint: Option<HttpUrl>,
+
+    /// The region to use for all buckets instead of their actual regions
+    pub(crate) region: Option<String>,
+}
+
+impl S3Settings {
+    /// Parse an S3 URL into an `S3Location`.  If a custom endpoint is set,
+    /// path-style URLs beneath it are accepted in addition to the AWS URL
+    /// formats accepted by [`S3Location::parse_url()`].
+    pub(crate) fn parse_url(&self, url: &Url) -> Result<S3Location, S3UrlError> {
+        if let Some(path) = self
+            .endpoint
+            .as_ref()
+            .and_then(|ep| endpoint_subpath(ep.as_url(), url))
+        {
+            let (bucket, key) = path.split_once('/').unwrap_or((path, ""));
+            if bucket.is_empty() {
+                return Err(S3UrlError::NoBucket);
+            }
+            let key = percent_encoding::percent_decode_str(key)
+                .decode_utf8()
+                .map_err(S3UrlError::BadPath)?
+                .into_owned();
+            Ok(S3Location {
+                bucket_spec: BucketSpec {
+                    bucket: bucket.into(),
+                    region: None,
+                },
+                key,
+            })
+        } else {
+            S3Location::parse_url(url)
+        }
+    }
+
+    /// Return the URL of the given bucket, to which object keys are appended
+    /// to form download URLs
+    fn bucket_url(&self, bucket: &str) -> HttpUrl {
+        match self.endpoint {
+            Some(ref endpoint) => {
+                let mut url = endpoint.clone();
+                url.push(bucket);
+                url
+            }
+            None => format!("https://{bucket}.s3.amazonaws.com")
+                .parse::<HttpUrl>()
+                .expect("bucket should be a valid hostname component"),
+        }
+    }
+}
+
+/// If `url` is beneath `endpoint` (i.e., has the same scheme, host, & port and
+/// a path that starts with the endpoint's path), return the rest of `url`'s
+/// path (still percent-encoded)
+fn endpoint_subpath<'a>(endpoint: &Url, url: &'a Url) -> Option<&'a str> {
+    if url.scheme() != endpoint.scheme()
+        || url.host() != endpoint.host()
+        || url.port_or_known_default() != endpoint.port_or_known_default()
+    {
+        return None;
+    }
+    let base = endpoint.path();
+    let rest = url.path().strip_prefix(base)?;
+    if base.ends_with('/') {
+        Some(rest)
+    } else {
+        rest.strip_prefix('/')
     }
 }
 
@@ -229,12 +383,17 @@ pub(crate) enum S3UrlError {
     InvalidDomain,
     #[error("URL path does not decode to UTF-8")]
     BadPath(#[source] std::str::Utf8Error),
+    #[error("URL path does not contain a bucket name")]
+    NoBucket,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 struct S3EntryPage {
     folders: Vec<S3Folder>,
     objects: Vec<S3Object>,
+    /// Objects in the page whose details could not be parsed but whose keys
+    /// are well-formed paths
+    bad_objects: Vec<S3BadObject>,
 }
 
 impl IntoIterator for S3EntryPage {
@@ -248,15 +407,17 @@ impl IntoIterator for S3EntryPage {
 
 #[derive(Clone, Debug)]
 struct S3EntryPageIter {
-    folders_iter: std::vec::IntoIter<S3Folder>,
-    objects_iter: std::vec::IntoIter<S3Object>,
+    folders: std::vec::IntoIter<S3Folder>,
+    objects: std::vec::IntoIter<S3Object>,
+    bad_objects: std::vec::IntoIter<S3BadObject>,
 }
 
 impl S3EntryPageIter {
     fn new(page: S3EntryPage) -> S3EntryPageIter {
         S3EntryPageIter {
-            folders_iter: page.folders.into_iter(),
-            objects_iter: page.objects.into_iter(),
+            folders: page.folders.into_iter(),
+            objects: page.objects.into_iter(),
+            bad_objects: page.bad_objects.into_iter(),
         }
     }
 }
@@ -265,10 +426,11 @@ impl Iterator for S3EntryPageIter {
     type Item = S3Entry;
 
     fn next(&mut self) -> Option<S3Entry> {
-        self.folders_iter
+        self.folders
             .next()
             .map(S3Entry::Folder)
-            .or_else(|| self.objects_iter.next().map(S3Entry::Object))
+            .or_else(|| self.objects.next().map(S3Entry::Object))
+            .or_else(|| self.bad_objects.next().map(S3Entry::BadObject))
     }
 }
 
@@ -276,6 +438,7 @@ impl Iterator for S3EntryPageIter {
 pub(crate) enum S3Entry {
     Folder(S3Folder),
     Object(S3Object),
+    BadObject(S3BadObject),
 }
 
 impl S3Entry {
@@ -283,6 +446,7 @@ impl S3Entry {
         match self {
             S3Entry::Folder(r) => Some(S3Entry::Folder(r.relative_to(dirpath)?)),
             S3Entry::Object(r) => Some(S3Entry::Object(r.relative_to(dirpath)?)),
+            S3Entry::BadObject(r) => Some(S3Entry::BadObject(r.relative_to(dirpath)?)),
         }
     }
 }
@@ -323,9 +487,11 @@ pub(crate) struct S3Object {
 }
 
 impl S3Object {
+    /// Convert an object from an S3 listing into an `S3Object`.  `base_url`
+    /// is the URL of the object's bucket.
     fn try_from_aws_object(
         obj: aws_sdk_s3::types::Object,
-        bucket: &str,
+        base_url: &HttpUrl,
     ) -> Result<S3Object, TryFromAwsObjectError> {
         let Some(key) = obj.key else {
             return Err(TryFromAwsObjectError::NoKey);
@@ -340,9 +506,7 @@ impl S3Object {
             return Err(TryFromAwsObjectError::NoSize { key });
         };
         let keypath = PurePath::try_from(key.clone()).map_err(TryFromAwsObjectError::BadKey)?;
-        let mut download_url = format!("https://{bucket}.s3.amazonaws.com")
-            .parse::<HttpUrl>()
-            .expect("bucket should be a valid hostname component");
+        let mut download_url = base_url.clone();
         // Adding the key this way is necessary in order for URL-unsafe
         // characters to be percent-encoded:
         download_url.extend(key.split('/'));
@@ -376,13 +540,28 @@ impl S3Object {
     }
 }
 
+/// An object in an S3 bucket whose details (other than its key) were missing
+/// or invalid
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct S3BadObject {
+    pub(crate) key: PurePath,
+}
+
+impl S3BadObject {
+    pub(crate) fn relative_to(&self, dirpath: &PureDirPath) -> Option<S3BadObject> {
+        Some(S3BadObject {
+            key: self.key.relative_to(dirpath)?,
+        })
+    }
+}
+
 #[derive(Debug, Error)]
 pub(crate) enum S3Error {
     #[error("failed to list S3 objects in bucket {bucket:?} with prefix {prefix:?}")]
     ListObjects {
         bucket: CompactString,
         prefix: String,
-        source: ListObjectsError,
+        source: Box<ListObjectsError>,
     },
     #[error("invalid object found in S3 bucket {bucket:?} under prefix {prefix:?}")]
     BadObject {
@@ -396,15 +575,34 @@ pub(crate) enum S3Error {
         prefix: String,
         source: TryFromCommonPrefixError,
     },
+    #[error("failed to presign download URL for key {key:?} in bucket {bucket:?}")]
+    Presign {
+        bucket: CompactString,
+        key: String,
+        source: PresignError,
+    },
 }
 
 impl S3Error {
     /// Classify the general type of error
     pub(crate) fn class(&self) -> ErrorClass {
-        ErrorClass::BadGateway
+        match self {
+            S3Error::Presign { .. } => ErrorClass::Internal,
+            _ => ErrorClass::BadGateway,
+        }
     }
 }
 
+#[derive(Debug, Error)]
+pub(crate) enum PresignError {
+    #[error("invalid presigning configuration")]
+    Config(#[from] PresigningConfigError),
+    #[error("failed to sign request")]
+    Sign(#[from] Box<SdkError<GetObjectError, HttpResponse>>),
+    #[error("presigned URL is invalid")]
+    BadUrl(#[from] ParseHttpUrlError),
+}
+
 #[derive(Clone, Debug, Error, Eq, PartialEq)]
 pub(crate) enum TryFromCommonPrefixError {
     #[error(r#"CommonPrefix lacks "prefix" field"#)]
@@ -435,6 +633,22 @@ pub(crate) enum TryFromAwsObjectError {
     },
 }
 
+impl TryFromAwsObjectError {
+    /// Return the key of the invalid object, if it is known and is a
+    /// well-formed path
+    fn keypath(&self) -> Option<PurePath> {
+        match self {
+            TryFromAwsObjectError::NoKey | TryFromAwsObjectError::BadKey(_) => None,
+            TryFromAwsObjectError::NoLastModified { key }
+            | TryFromAwsObjectError::NoETag { key }
+            | TryFromAwsObjectError::NoSize { key }
+            | TryFromAwsObjectError::BadModified { key, .. } => {
+                PurePath::try_from(key.clone()).ok()
+            }
+        }
+    }
+}
+
 // The AWS SDK currently cannot be used for this:
 // <https://github.com/awslabs/aws-sdk-rust/issues/1052>
 pub(crate) async fn get_bucket_region(bucket: &str) -> Result<String, GetBucketRegionError> {
@@ -445,8 +659,15 @@ pub(crate) async fn get_bucket_region(bucket: &str) -> Result<String, GetBucketR
             url: url_str,
             source,
         })?;
-    let client = httputil::Client::new()?;
-    let r = client.head(url).await?;
+    let client = httputil::Client::new(None)?;
+    let r = client.send(Method::HEAD, url.clone()).await?;
+    // Private buckets respond to anonymous requests with a 403, but they still
+    // report their region.
+    let r = if r.status() == StatusCode::FORBIDDEN {
+        r
+    } else {
+        httputil::check_status(url, r)?
+    };
     match r.headers().get("x-amz-bucket-region").map(|hv| hv.to_str()) {
         Some(Ok(region)) => Ok(region.to_owned()),
         Some(Err(e)) => Err(GetBucketRegionError::BadHeader(e)),
@@ -526,4 +747,191 @@ mod tests {
         let r = S3Location::parse_url(&url);
         assert!(r.is_err());
     }
+
+    #[test]
+    fn bad_object_keypath() {
+        let obj = aws_sdk_s3::types::Object::builder()
+            .key("zarr/0123/0/0.1")
+            .size(42)
+            .build();
+        let e =
+            S3Object::try_from_aws_object(obj, &S3Settings::default().bucket_url("dandiarchive"))
+                .unwrap_err();
+        assert!(matches!(e, TryFromAwsObjectError::NoLastModified { .. }));
+        assert_eq!(e.keypath(), Some("zarr/0123/0/0.1".parse().unwrap()));
+    }
+
+    #[rstest]
+    #[case(aws_sdk_s3::types::Object::builder().size(42).build())]
+    #[case(aws_sdk_s3::types::Object::builder()
+        .key("zarr//0.1")
+        .last_modified(aws_sdk_s3::primitives::DateTime::from_secs(0))
+        .e_tag("\"abc\"")
+        .size(42)
+        .build())]
+    fn bad_object_no_keypath(#[case] obj: aws_sdk_s3::types::Object) {
+        let e =
+            S3Object::try_from_aws_object(obj, &S3Settings::default().bucket_url("dandiarchive"))
+                .unwrap_err();
+        assert_eq!(e.keypath(), None);
+    }
+
+    #[tokio::test]
+    async fn presign_get() {
+        let config = aws_sdk_s3::Config::builder()
+            .region(aws_config::Region::new("us-east-2"))
+            .credentials_provider(aws_sdk_s3::config::Credentials::new(
+                "AKIDEXAMPLE",
+                "SECRETEXAMPLE",
+                None,
+                None,
+                "test",
+            ))
+            .behavior_version_latest()
+            .build();
+        let client = S3Client {
+            inner: Client::from_conf(config),
+            bucket: "embargoed".into(),
+            base_url: S3Settings::default().bucket_url("embargoed"),
+        };
+        let url = client
+            .presign_get("blobs/abc/def/abcdef", Duration::from_secs(3600))
+            .await
+            .unwrap();
+        assert_eq!(
+            url.as_url().host_str(),
+            Some("embargoed.s3.us-east-2.amazonaws.com")
+        );
+        assert_eq!(url.as_url().path(), "/blobs/abc/def/abcdef");
+        let query = url.as_url().query().unwrap();
+        assert!(query.contains("X-Amz-Expires=3600"));
+        assert!(query.contains("X-Amz-Signature="));
+        assert!(query.contains("X-Amz-Credential=AKIDEXAMPLE%2F"));
+    }
+
+    #[rstest]
+    #[case(
+        "http://localhost:9000",
+        "http://localhost:9000/dandiarchive/zarr/0123/foo%20bar.txt",
+        "dandiarchive",
+        "zarr/0123/foo bar.txt"
+    )]
+    #[case(
+        "http://localhost:9000/",
+        "http://localhost:9000/dandiarchive/zarr/0123/",
+        "dandiarchive",
+        "zarr/0123/"
+    )]
+    #[case(
+        "https://mirror.example.com/s3",
+        "https://mirror.example.com/s3/dandiarchive/zarr/0123/",
+        "dandiarchive",
+        "zarr/0123/"
+    )]
+    #[case(
+        "http://localhost:9000",
+        "https://dandiarchive.s3.amazonaws.com/zarr/0123/",
+        "dandiarchive",
+        "zarr/0123/"
+    )]
+    fn test_settings_parse_url(
+        #[case] endpoint: HttpUrl,
+        #[case] url: Url,
+        #[case] bucket: &str,
+        #[case] key: &str,
+    ) {
+        let settings = S3Settings {
+            endpoint: Some(endpoint),
+            region: None,
+        };
+        let s3loc = settings.parse_url(&url).unwrap();
+        assert_eq!(s3loc.bucket_spec.bucket, bucket);
+        assert_eq!(s3loc.key, key);
+    }
+
+    #[rstest]
+    #[case("http://localhost:9000", "http://localhost:9001/dandiarchive/zarr/")]
+    #[case("http://localhost:9000", "https://localhost:9000/dandiarchive/zarr/")]
+    #[case("http://localhost:9000", "http://localhost:9000/")]
+    #[case(
+        "https://mirror.example.com/s3",
+        "https://mirror.example.com/s3x/dandiarchive/"
+    )]
+    fn test_settings_bad_urls(#[case] endpoint: HttpUrl, #[case] url: Url) {
+        let settings = S3Settings {
+            endpoint: Some(endpoint),
+            region: None,
+        };
+        assert!(settings.parse_url(&url).is_err());
+    }
+
+    #[tokio::test]
+    async fn list_custom_endpoint() {
+        use wiremock::{
+            matchers::{method, path_regex, query_param},
+            Mock, MockServer, ResponseTemplate,
+        };
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path_regex("^/dandiarchive/?$"))
+            .and(query_param("list-type", "2"))
+            .and(query_param("prefix", "zarr/0123/"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Type", "application/xml")
+                    .set_body_string(concat!(
+                        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                        r#"<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+                        "<Name>dandiarchive</Name>",
+                        "<Prefix>zarr/0123/</Prefix>",
+                        "<KeyCount>2</KeyCount>",
+                        "<MaxKeys>1000</MaxKeys>",
+                        "<Delimiter>/</Delimiter>",
+                        "<IsTruncated>false</IsTruncated>",
+                        "<Contents>",
+                        "<Key>zarr/0123/.zattrs</Key>",
+                        "<LastModified>2024-01-01T00:00:00.000Z</LastModified>",
+                        "<ETag>&quot;0123456789abcdef&quot;</ETag>",
+                        "<Size>42</Size>",
+                        "<StorageClass>STANDARD</StorageClass>",
+                        "</Contents>",
+                        "<CommonPrefixes><Prefix>zarr/0123/0/</Prefix></CommonPrefixes>",
+                        "</ListBucketResult>",
+                    )),
+            )
+            .mount(&server)
+            .await;
+        let settings = S3Settings {
+            endpoint: Some(server.uri().parse::<HttpUrl>().unwrap()),
+            region: None,
+        };
+        let client = BucketSpec {
+            bucket: "dandiarchive".into(),
+            region: None,
+        }
+        .into_s3client(false, &settings)
+        .await
+        .unwrap();
+        let entries = client
+            .get_folder_entries(&"zarr/0123/".parse::<PureDirPath>().unwrap())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0],
+            S3Entry::Folder(S3Folder {
+                key_prefix: "zarr/0123/0/".parse().unwrap(),
+            })
+        );
+        let S3Entry::Object(ref obj) = entries[1] else {
+            panic!("second entry should be an object: {:?}", entries[1]);
+        };
+        assert_eq!(obj.key, "zarr/0123/.zattrs");
+        assert_eq!(obj.size, 42);
+        assert_eq!(
+            obj.download_url.as_str(),
+            format!("{}/dandiarchive/zarr/0123/.zattrs", server.uri())
+        );
+    }
 }
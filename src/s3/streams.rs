@@ -1,7 +1,8 @@
 use super::{
-    ListObjectsError, S3Client, S3EntryPage, S3Error, S3Folder, S3Object, TryFromAwsObjectError,
-    TryFromCommonPrefixError,
+    ListObjectsError, S3BadObject, S3Client, S3EntryPage, S3Error, S3Folder, S3Object,
+    TryFromAwsObjectError, TryFromCommonPrefixError,
 };
+use crate::httputil::HttpUrl;
 use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Output;
 use aws_smithy_async::future::pagination_stream::PaginationStream;
 use futures_util::Stream;
@@ -16,6 +17,7 @@ use std::task::{ready, Context, Poll};
 #[must_use = "streams do nothing unless polled"]
 pub(super) struct ListEntryPages {
     bucket: CompactString,
+    base_url: HttpUrl,
     key_prefix: String,
     inner: Option<PaginationStream<Result<ListObjectsV2Output, ListObjectsError>>>,
 }
@@ -25,6 +27,7 @@ impl ListEntryPages {
         let key_prefix = key_prefix.into();
         ListEntryPages {
             bucket: client.bucket.clone(),
+            base_url: client.base_url.clone(),
             key_prefix: key_prefix.clone(),
             inner: Some(
                 client
@@ -51,7 +54,7 @@ impl ListEntryPages {
         self.die(S3Error::ListObjects {
             bucket: self.bucket.clone(),
             prefix: self.key_prefix.clone(),
-            source,
+            source: Box::new(source),
         })
     }
 
@@ -93,16 +96,28 @@ impl Stream for ListEntryPages {
             Ok(page) => page,
             Err(source) => return self.die_list_objects(source),
         };
-        let objects = match page
-            .contents
-            .unwrap_or_default()
-            .into_iter()
-            .map(|obj| S3Object::try_from_aws_object(obj, &self.bucket))
-            .collect::<Result<Vec<_>, _>>()
-        {
-            Ok(objects) => objects,
-            Err(source) => return self.die_bad_object(source),
-        };
+        let mut objects = Vec::new();
+        let mut bad_objects = Vec::new();
+        for obj in page.contents.unwrap_or_default() {
+            match S3Object::try_from_aws_object(obj, &self.base_url) {
+                Ok(obj) => objects.push(obj),
+                // Objects with usable keys but otherwise-invalid details are
+                // reported individually so that the rest of the listing can
+                // still be served.
+                Err(source) => match source.keypath() {
+                    Some(key) => {
+                        tracing::warn!(
+                            bucket = %self.bucket,
+                            prefix = self.key_prefix,
+                            error = ?source,
+                            "Invalid object found in S3 listing",
+                        );
+                        bad_objects.push(S3BadObject { key });
+                    }
+                    None => return self.die_bad_object(source),
+                },
+            }
+        }
         let folders = match page
             .common_prefixes
             .unwrap_or_default()
@@ -113,6 +128,11 @@ impl Stream for ListEntryPages {
             Ok(folders) => folders,
             Err(source) => return self.die_bad_prefix(source),
         };
-        Some(Ok(S3EntryPage { folders, objects })).into()
+        Some(Ok(S3EntryPage {
+            folders,
+            objects,
+            bad_objects,
+        }))
+        .into()
     }
 }
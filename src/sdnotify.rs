@@ -0,0 +1,191 @@
+//! Integration with the systemd service notification protocol
+//!
+//! When `dandidav` is run as a systemd service with `Type=notify`, systemd
+//! passes the path to a datagram socket in the `NOTIFY_SOCKET` environment
+//! variable.  `dandidav` sends `READY=1` to this socket once it is listening
+//! for connections, and, if the service has a `WatchdogSec` setting (passed
+//! to the process via `WATCHDOG_USEC`), it also sends `WATCHDOG=1` at half the
+//! watchdog interval from a task on the async runtime, so that systemd
+//! restarts the service if the runtime stops making progress.
+//!
+//! When `NOTIFY_SOCKET` is not set, or on non-Unix platforms, nothing is sent.
+use std::ffi::OsString;
+use std::time::Duration;
+
+/// A connection to the service manager's notification socket
+#[derive(Debug)]
+pub(crate) struct Notifier {
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+}
+
+impl Notifier {
+    /// Connect to the notification socket given by the `NOTIFY_SOCKET`
+    /// environment variable.  Returns `None` if the variable is not set or
+    /// the socket cannot be connected to, in which case a warning is logged.
+    pub(crate) fn from_env() -> Option<Notifier> {
+        let addr = std::env::var_os("NOTIFY_SOCKET").filter(|s| !s.is_empty())?;
+        match Notifier::connect(addr.clone()) {
+            Ok(notifier) => Some(notifier),
+            Err(e) => {
+                tracing::warn!(error = %e, notify_socket = ?addr, "Failed to connect to service manager notification socket");
+                None
+            }
+        }
+    }
+
+    /// Connect to the notification socket at `addr`.  An `addr` starting with
+    /// `@` refers to a socket in the Linux abstract namespace.
+    #[cfg(unix)]
+    fn connect(addr: OsString) -> std::io::Result<Notifier> {
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::net::UnixDatagram;
+        let socket = UnixDatagram::unbound()?;
+        match addr.as_bytes().strip_prefix(b"@") {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                socket.connect_addr(&addr)?;
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "abstract socket addresses are only supported on Linux",
+                ))
+            }
+            None => socket.connect(addr)?,
+        }
+        Ok(Notifier { socket })
+    }
+
+    #[cfg(not(unix))]
+    fn connect(_addr: OsString) -> std::io::Result<Notifier> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "service manager notification is only supported on Unix",
+        ))
+    }
+
+    /// Send a notification message (a newline-separated list of `KEY=VALUE`
+    /// assignments) to the service manager
+    #[cfg(unix)]
+    fn send(&self, msg: &str) -> std::io::Result<()> {
+        self.socket.send(msg.as_bytes()).map(|_| ())
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _msg: &str) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Inform the service manager that startup is complete
+    pub(crate) fn ready(&self) {
+        if let Err(e) = self.send("READY=1\nSTATUS=Listening for connections") {
+            tracing::warn!(error = %e, "Failed to send readiness notification to service manager");
+        } else {
+            tracing::debug!("Sent readiness notification to service manager");
+        }
+    }
+
+    /// If the service manager expects watchdog pings from this process, spawn
+    /// a task that sends them at half the requested interval
+    pub(crate) fn install_watchdog(self) {
+        let Some(period) = watchdog_period(
+            std::env::var_os("WATCHDOG_USEC"),
+            std::env::var_os("WATCHDOG_PID"),
+            std::process::id(),
+        ) else {
+            return;
+        };
+        tracing::debug!(
+            period_ms = period.as_millis(),
+            "Sending watchdog pings to service manager"
+        );
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.send("WATCHDOG=1") {
+                    tracing::warn!(error = %e, "Failed to send watchdog ping to service manager");
+                }
+            }
+        });
+    }
+}
+
+/// Determine how often to send watchdog pings given the values of the
+/// `WATCHDOG_USEC` and `WATCHDOG_PID` environment variables and the ID of the
+/// current process.  Returns `None` if no pings are expected from this
+/// process.
+fn watchdog_period(
+    usec: Option<OsString>,
+    pid: Option<OsString>,
+    self_pid: u32,
+) -> Option<Duration> {
+    if let Some(pid) = pid {
+        if pid.to_str()?.parse::<u32>().ok()? != self_pid {
+            return None;
+        }
+    }
+    let usec = usec?.to_str()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Some("30000000"), None, Some(Duration::from_secs(15)))]
+    #[case(Some("30000000"), Some("42"), Some(Duration::from_secs(15)))]
+    #[case(Some("30000000"), Some("43"), None)]
+    #[case(Some("30000000"), Some("foo"), None)]
+    #[case(Some("0"), None, None)]
+    #[case(Some("foo"), None, None)]
+    #[case(None, None, None)]
+    #[case(None, Some("42"), None)]
+    fn test_watchdog_period(
+        #[case] usec: Option<&str>,
+        #[case] pid: Option<&str>,
+        #[case] period: Option<Duration>,
+    ) {
+        assert_eq!(
+            watchdog_period(usec.map(OsString::from), pid.map(OsString::from), 42),
+            period
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn send_ready() {
+        use std::os::unix::net::UnixDatagram;
+        let path = std::env::temp_dir().join(format!("dandidav-notify-{}", uuid::Uuid::new_v4()));
+        let server = UnixDatagram::bind(&path).unwrap();
+        let notifier = Notifier::connect(path.clone().into_os_string()).unwrap();
+        notifier.ready();
+        let mut buf = [0u8; 256];
+        let n = server.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1\nSTATUS=Listening for connections");
+        drop(server);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn send_ready_abstract() {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+        let name = format!("dandidav-notify-{}", uuid::Uuid::new_v4());
+        let addr = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+        let server = UnixDatagram::bind_addr(&addr).unwrap();
+        let notifier = Notifier::connect(format!("@{name}").into()).unwrap();
+        notifier.ready();
+        let mut buf = [0u8; 256];
+        let n = server.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1\nSTATUS=Listening for connections");
+    }
+}
@@ -0,0 +1,456 @@
+//! Serving the application over HTTP with per-connection controls
+use crate::tls::TlsSettings;
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request},
+    http::{
+        header::{CONNECTION, CONTENT_TYPE},
+        HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Router,
+};
+use hyper::{body::Incoming, server::conn::http1};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Instant;
+use tower::ServiceExt;
+
+/// How long to wait before accepting further connections after an error
+/// accepting a connection (e.g., due to running out of file descriptors)
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(1);
+
+/// How long to wait for a client to complete a TLS handshake before closing
+/// the connection
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to keep a connection rejected for exceeding the per-client
+/// connection limit open while waiting to send it a 503 response
+const REJECT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The body of the response sent over connections that are rejected for
+/// exceeding the per-client connection limit
+static TOO_MANY_CONNECTIONS_BODY: &str = "Too many open connections from this client\n";
+
+/// Connection-level settings for the HTTP server
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct ServerConfig {
+    /// Close connections that have gone this long without a request.  `None`
+    /// means to keep idle connections open indefinitely.
+    pub(crate) keep_alive_timeout: Option<Duration>,
+
+    /// Close connections after serving this many requests.  `None` means
+    /// there is no limit.
+    pub(crate) max_requests_per_connection: Option<NonZeroUsize>,
+
+    /// Close connections whose clients take longer than this to send the
+    /// headers of a request.  `None` means there is no timeout.
+    pub(crate) header_read_timeout: Option<Duration>,
+
+    /// Whether to log the opening & closing of each connection along with
+    /// per-connection statistics
+    pub(crate) log_connections: bool,
+
+    /// The maximum number of connections that may be open at once from a
+    /// single client IP address.  `None` means there is no limit.
+    pub(crate) max_connections_per_ip: Option<NonZeroUsize>,
+}
+
+/// Accept connections on `listener` and serve `app` over each one according
+/// to `cfg`.  This function never returns.
+pub(crate) async fn serve(
+    listener: TcpListener,
+    app: Router,
+    cfg: ServerConfig,
+    tls: Option<TlsSettings>,
+) -> ! {
+    let tracker = Arc::new(ConnectionTracker::new(cfg.max_connections_per_ip));
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                if let Some(guard) = tracker.acquire(peer.ip()) {
+                    let app = app.clone();
+                    let tls = tls.clone();
+                    tokio::spawn(async move {
+                        match tls {
+                            Some(tls) => match tokio::time::timeout(
+                                TLS_HANDSHAKE_TIMEOUT,
+                                tls.accept(stream, peer),
+                            )
+                            .await
+                            {
+                                Ok(Ok(stream)) => serve_connection(stream, peer, app, cfg).await,
+                                Ok(Err(e)) => {
+                                    let e = anyhow::Error::from(e);
+                                    tracing::info!(%peer, error = ?e, "Rejecting TLS connection");
+                                }
+                                Err(_) => {
+                                    tracing::info!(%peer, "Rejecting TLS connection: handshake timed out");
+                                }
+                            },
+                            None => serve_connection(stream, peer, app, cfg).await,
+                        }
+                        drop(guard);
+                    });
+                } else {
+                    tracing::info!(%peer, "Rejecting connection: too many open connections from client");
+                    if tls.is_none() {
+                        tokio::spawn(reject_connection(stream, cfg));
+                    }
+                    // Connections to a TLS listener are simply dropped, as
+                    // performing a handshake just to send a 503 would defeat
+                    // the purpose of the limit
+                }
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to accept connection");
+                tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Serve `app` over the connection `stream` from the client at `peer`
+async fn serve_connection<S>(stream: S, peer: SocketAddr, app: Router, cfg: ServerConfig)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let stats = Arc::new(ConnectionStats::new());
+    if cfg.log_connections {
+        tracing::info!(%peer, "Connection opened");
+    }
+    let svc = {
+        let stats = Arc::clone(&stats);
+        hyper::service::service_fn(move |mut req: Request<Incoming>| {
+            let app = app.clone();
+            let stats = Arc::clone(&stats);
+            // Make the peer address available to the rate limiter &
+            // request handlers
+            req.extensions_mut().insert(ConnectInfo(peer));
+            let n = stats.start_request();
+            async move {
+                let mut resp = app.oneshot(req.map(Body::new)).await?;
+                if cfg
+                    .max_requests_per_connection
+                    .is_some_and(|max| n >= max.get())
+                {
+                    resp.headers_mut()
+                        .insert(CONNECTION, HeaderValue::from_static("close"));
+                }
+                stats.finish_request();
+                Ok::<_, Infallible>(resp)
+            }
+        })
+    };
+    let mut builder = http1::Builder::new();
+    builder
+        .timer(TokioTimer::new())
+        .header_read_timeout(cfg.header_read_timeout);
+    let conn = builder.serve_connection(TokioIo::new(stream), svc);
+    let mut conn = std::pin::pin!(conn);
+    let r = match cfg.keep_alive_timeout {
+        None => conn.await,
+        Some(timeout) => loop {
+            tokio::select! {
+                r = conn.as_mut() => break r,
+                () = tokio::time::sleep_until(stats.idle_deadline(timeout)) => {
+                    if stats.is_idle_for(timeout) {
+                        conn.as_mut().graceful_shutdown();
+                        break conn.as_mut().await;
+                    }
+                }
+            }
+        },
+    };
+    if cfg.log_connections {
+        let requests = stats.requests.load(Ordering::Relaxed);
+        let duration_ms = stats.opened.elapsed().as_millis();
+        match r {
+            Ok(()) => tracing::info!(%peer, requests, duration_ms, "Connection closed"),
+            Err(e) => tracing::info!(
+                %peer,
+                requests,
+                duration_ms,
+                error = %e,
+                "Connection closed with error"
+            ),
+        }
+    }
+}
+
+/// Respond to the first request on `stream` with a 503 and close the
+/// connection
+async fn reject_connection(stream: TcpStream, cfg: ServerConfig) {
+    let svc = hyper::service::service_fn(|_: Request<Incoming>| async {
+        Ok::<_, Infallible>(too_many_connections())
+    });
+    let mut builder = http1::Builder::new();
+    builder
+        .timer(TokioTimer::new())
+        .header_read_timeout(cfg.header_read_timeout)
+        .keep_alive(false);
+    // Errors (including timeouts) here are of no interest, as we're dropping
+    // the connection anyway.  The timeout ensures that clients who never send
+    // a request cannot keep rejected connections open.
+    let _ = tokio::time::timeout(
+        REJECT_CONNECTION_TIMEOUT,
+        builder.serve_connection(TokioIo::new(stream), svc),
+    )
+    .await;
+}
+
+/// The response sent over connections that are rejected for exceeding the
+/// per-client connection limit
+fn too_many_connections() -> Response<Body> {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [
+            (
+                CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; charset=utf-8"),
+            ),
+            (CONNECTION, HeaderValue::from_static("close")),
+        ],
+        TOO_MANY_CONNECTIONS_BODY,
+    )
+        .into_response()
+}
+
+/// Tracks the number of open connections from each client IP address and
+/// enforces a limit on them.
+///
+/// Connections are counted at the TCP level, before any request has been
+/// read, and so they are keyed by the address of the connecting peer rather
+/// than by a client address forwarded in a header (as the rate limiter's
+/// `SmartIpKeyExtractor` does).  Behind a reverse proxy or router (such as
+/// Heroku's), all connections come from the proxy, and so the limit must not
+/// be enabled in such deployments.
+#[derive(Debug)]
+struct ConnectionTracker {
+    /// The maximum number of connections per IP address, if any
+    limit: Option<NonZeroUsize>,
+
+    /// The number of open connections from each IP address that has any
+    open: Mutex<HashMap<IpAddr, usize>>,
+}
+
+impl ConnectionTracker {
+    fn new(limit: Option<NonZeroUsize>) -> ConnectionTracker {
+        ConnectionTracker {
+            limit,
+            open: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register a new connection from `ip`.  Returns `None` if `ip` already
+    /// has the maximum number of connections open; otherwise, returns a guard
+    /// that unregisters the connection when dropped.
+    fn acquire(self: &Arc<Self>, ip: IpAddr) -> Option<ConnectionGuard> {
+        let Some(limit) = self.limit else {
+            return Some(ConnectionGuard { tracker: None, ip });
+        };
+        let mut open = self.open.lock().unwrap_or_else(PoisonError::into_inner);
+        let count = open.entry(ip).or_insert(0);
+        if *count >= limit.get() {
+            return None;
+        }
+        *count += 1;
+        Some(ConnectionGuard {
+            tracker: Some(Arc::clone(self)),
+            ip,
+        })
+    }
+
+    /// Unregister a connection from `ip`
+    fn release(&self, ip: IpAddr) {
+        let mut open = self.open.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(count) = open.get_mut(&ip) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                open.remove(&ip);
+            }
+        }
+    }
+
+    /// Return the number of open connections from `ip`
+    #[cfg(test)]
+    fn open_count(&self, ip: IpAddr) -> usize {
+        let open = self.open.lock().unwrap_or_else(PoisonError::into_inner);
+        open.get(&ip).copied().unwrap_or_default()
+    }
+}
+
+/// A registration of an open connection with a [`ConnectionTracker`]
+#[derive(Debug)]
+struct ConnectionGuard {
+    /// The tracker with which the connection is registered, or `None` if
+    /// connections are not being limited
+    tracker: Option<Arc<ConnectionTracker>>,
+
+    /// The IP address of the client
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Some(ref tracker) = self.tracker {
+            tracker.release(self.ip);
+        }
+    }
+}
+
+/// Statistics on & activity tracking for a single connection
+#[derive(Debug)]
+struct ConnectionStats {
+    /// When the connection was opened
+    opened: Instant,
+
+    /// The number of requests received on the connection so far
+    requests: AtomicUsize,
+
+    /// The number of requests currently being handled
+    in_flight: AtomicUsize,
+
+    /// When a request was last received or completed
+    last_activity: Mutex<Instant>,
+}
+
+impl ConnectionStats {
+    fn new() -> ConnectionStats {
+        let now = Instant::now();
+        ConnectionStats {
+            opened: now,
+            requests: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            last_activity: Mutex::new(now),
+        }
+    }
+
+    /// Record the start of a request and return the number of requests
+    /// received so far, including this one
+    fn start_request(&self) -> usize {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        self.touch();
+        self.requests.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Record the completion of a request
+    fn finish_request(&self) {
+        self.touch();
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Set the time of last activity to now
+    fn touch(&self) {
+        *self
+            .last_activity
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+    }
+
+    /// Return the time of last activity
+    fn last_activity(&self) -> Instant {
+        *self
+            .last_activity
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Return the time at which the connection will have been idle for
+    /// `timeout`, assuming no further activity
+    fn idle_deadline(&self, timeout: Duration) -> Instant {
+        if self.in_flight.load(Ordering::Relaxed) > 0 {
+            Instant::now() + timeout
+        } else {
+            self.last_activity() + timeout
+        }
+    }
+
+    /// Return whether there are no requests in flight and there has been no
+    /// activity for at least `timeout`
+    fn is_idle_for(&self, timeout: Duration) -> bool {
+        self.in_flight.load(Ordering::Relaxed) == 0 && self.last_activity().elapsed() >= timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+
+    async fn spawn_server(cfg: ServerConfig) -> SocketAddr {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", get(|| async { "Hello!\n" }));
+        tokio::spawn(serve(listener, app, cfg, None));
+        addr
+    }
+
+    #[tokio::test]
+    async fn max_requests_per_connection() {
+        let addr = spawn_server(ServerConfig {
+            max_requests_per_connection: NonZeroUsize::new(1),
+            ..ServerConfig::default()
+        })
+        .await;
+        let r = reqwest::get(format!("http://{addr}/")).await.unwrap();
+        assert_eq!(
+            r.headers().get(CONNECTION).and_then(|v| v.to_str().ok()),
+            Some("close")
+        );
+        assert_eq!(r.text().await.unwrap(), "Hello!\n");
+    }
+
+    #[test]
+    fn connection_tracker() {
+        let tracker = Arc::new(ConnectionTracker::new(NonZeroUsize::new(2)));
+        let ip1 = IpAddr::from([127, 0, 0, 1]);
+        let ip2 = IpAddr::from([10, 0, 0, 1]);
+        let g1 = tracker.acquire(ip1).unwrap();
+        let g2 = tracker.acquire(ip1).unwrap();
+        assert!(tracker.acquire(ip1).is_none());
+        let g3 = tracker.acquire(ip2).unwrap();
+        assert_eq!(tracker.open_count(ip1), 2);
+        drop(g1);
+        assert_eq!(tracker.open_count(ip1), 1);
+        let g4 = tracker.acquire(ip1).unwrap();
+        drop(g2);
+        drop(g3);
+        drop(g4);
+        assert_eq!(tracker.open_count(ip1), 0);
+        assert_eq!(tracker.open_count(ip2), 0);
+        assert!(tracker.open.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reject_excess_connections() {
+        let addr = spawn_server(ServerConfig {
+            max_connections_per_ip: NonZeroUsize::new(1),
+            ..ServerConfig::default()
+        })
+        .await;
+        // Hold one connection open without sending a request:
+        let _held = TcpStream::connect(addr).await.unwrap();
+        // Give the server a chance to accept the held connection:
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let r = reqwest::get(format!("http://{addr}/")).await.unwrap();
+        assert_eq!(r.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(r.text().await.unwrap(), TOO_MANY_CONNECTIONS_BODY);
+    }
+
+    #[tokio::test]
+    async fn no_request_limit() {
+        let addr = spawn_server(ServerConfig::default()).await;
+        let r = reqwest::get(format!("http://{addr}/")).await.unwrap();
+        assert_eq!(r.headers().get(CONNECTION), None);
+        assert_eq!(r.text().await.unwrap(), "Hello!\n");
+    }
+}
@@ -0,0 +1,161 @@
+//! Transfer of cache contents between `dandidav` instances
+//!
+//! For high availability, a standby instance can be started with
+//! `--warm-from` pointing at a primary instance.  At startup, the standby
+//! fetches a [`CacheSnapshot`] of the primary's cached `dandiset.yaml`
+//! documents and parsed Zarr manifests from the primary's
+//! [`CACHE_SNAPSHOT_PATH`] endpoint and inserts them into its own caches, so
+//! that a failover does not begin with entirely cold caches.
+//!
+//! Like the other administrative endpoints, the endpoint is only served when
+//! an admin token is configured, and requests to it must supply the token as
+//! a bearer token (see [`crate::admin`]).  The same token is used to
+//! authenticate to the primary when warming.
+use crate::blocking::run_blocking;
+use crate::consts::{CACHE_SNAPSHOT_PATH, CACHE_SNAPSHOT_TIMEOUT, JSON_CONTENT_TYPE};
+use crate::dandi::{DandiClient, VersionMetadataSnapshot};
+use crate::httputil::{Client, HttpError, HttpUrl};
+use crate::openapi::ApiSchema;
+use crate::zarrman::{ManifestFetcher, ManifestSnapshot};
+use axum::{
+    body::Body,
+    http::{
+        header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+        response::Response,
+        StatusCode,
+    },
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The contents of an instance's caches that are transferred to standby
+/// instances
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct CacheSnapshot {
+    /// Cached `dandiset.yaml` documents
+    version_metadata: Vec<VersionMetadataSnapshot>,
+
+    /// Cached parsed Zarr manifests
+    zarr_manifests: Vec<ManifestSnapshot>,
+}
+
+impl CacheSnapshot {
+    /// Take a snapshot of the current contents of the given caches
+    fn take(dandi: &DandiClient, zarrfetcher: &ManifestFetcher) -> CacheSnapshot {
+        CacheSnapshot {
+            version_metadata: dandi.snapshot_version_metadata(),
+            zarr_manifests: zarrfetcher.snapshot(),
+        }
+    }
+
+    /// Insert the snapshot's contents into the given caches, keeping any
+    /// values that are already cached
+    async fn restore(self, dandi: &DandiClient, zarrfetcher: &ManifestFetcher) {
+        let version_metadata = dandi.restore_version_metadata(self.version_metadata).await;
+        let zarr_manifests = zarrfetcher.restore(self.zarr_manifests).await;
+        tracing::info!(
+            version_metadata,
+            zarr_manifests,
+            "Restored cache snapshot from primary instance"
+        );
+    }
+}
+
+impl ApiSchema for CacheSnapshot {
+    fn schema() -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "description": "Snapshot of cached Dandiset metadata & Zarr manifests, for warming standby instances",
+            "properties": {
+                "version_metadata": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "dandiset_id": {"type": "string"},
+                            "version_id": {"type": "string"},
+                            "document": {"type": "string"},
+                        },
+                        "required": ["dandiset_id", "version_id", "document"],
+                    },
+                },
+                "zarr_manifests": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "prefix": {"type": "string"},
+                            "zarr_id": {"type": "string"},
+                            "checksum": {"type": "string"},
+                            "manifest": {"type": "object"},
+                        },
+                        "required": ["prefix", "zarr_id", "checksum", "manifest"],
+                    },
+                },
+            },
+            "required": ["version_metadata", "zarr_manifests"],
+        })
+    }
+}
+
+/// The caches of a primary instance, served to standby instances
+#[derive(Clone, Debug)]
+pub(crate) struct SnapshotSource {
+    pub(crate) dandi: DandiClient,
+    pub(crate) zarrfetcher: ManifestFetcher,
+}
+
+impl SnapshotSource {
+    /// Respond to an (already authenticated) request for a cache snapshot
+    pub(crate) async fn serve(&self) -> Response<Body> {
+        let snapshot = CacheSnapshot::take(&self.dandi, &self.zarrfetcher);
+        match run_blocking(move || serde_json::to_string(&snapshot)).await {
+            Ok(body) => ([(CONTENT_TYPE, JSON_CONTENT_TYPE)], body).into_response(),
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to serialize cache snapshot");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// Spawn a task that uses `client` to fetch a cache snapshot from the primary
+/// instance at `primary_url` (authenticating with `token`) and restores it
+/// into the given caches.  Failures are logged, after which the instance
+/// continues with whatever its caches contain.
+pub(crate) fn install_warmup(
+    client: Client,
+    primary_url: HttpUrl,
+    token: Arc<str>,
+    dandi: DandiClient,
+    zarrfetcher: ManifestFetcher,
+) {
+    tokio::spawn(async move {
+        match fetch_snapshot(&client, primary_url, &token).await {
+            Ok(snapshot) => snapshot.restore(&dandi, &zarrfetcher).await,
+            Err(e) => {
+                let e = anyhow::Error::from(e);
+                tracing::warn!(error = ?e, "Failed to fetch cache snapshot from primary instance");
+            }
+        }
+    });
+}
+
+/// Use `client` to fetch a cache snapshot from the primary instance at
+/// `primary_url`, authenticating with `token`
+async fn fetch_snapshot(
+    client: &Client,
+    mut primary_url: HttpUrl,
+    token: &str,
+) -> Result<CacheSnapshot, HttpError> {
+    primary_url.extend(CACHE_SNAPSHOT_PATH.split('/').filter(|s| !s.is_empty()));
+    let mut headers = HeaderMap::new();
+    let mut auth = HeaderValue::try_from(format!("Bearer {token}"))
+        .expect("snapshot token should have been validated as a header value");
+    auth.set_sensitive(true);
+    headers.insert(AUTHORIZATION, auth);
+    client
+        .get_json_with_headers(primary_url, headers, CACHE_SNAPSHOT_TIMEOUT)
+        .await
+}
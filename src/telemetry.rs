@@ -0,0 +1,337 @@
+//! Opt-in reporting of anonymous, aggregate usage statistics
+//!
+//! When a telemetry URL is configured, `dandidav` counts incoming requests by
+//! coarse route class, by client family (see [`ClientClass`]), and by response
+//! status class, and it periodically `POST`s the counts accumulated since the
+//! previous report to the URL as a JSON [`TelemetryReport`], after which the
+//! counts are reset.  No request paths, IP addresses, or other details of
+//! individual requests are recorded.  If a report cannot be delivered, its
+//! counts are discarded.
+use crate::audit::ClientClass;
+use crate::httputil::{BuildClientError, Client, HttpUrl};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header::USER_AGENT, response::Response, StatusCode},
+    middleware::Next,
+};
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// A collector of aggregate request counts that periodically reports them to
+/// a configured URL
+#[derive(Debug)]
+pub(crate) struct Telemetry {
+    /// The counts accumulated since the last report
+    counters: Mutex<Counters>,
+
+    /// The HTTP client used for delivering reports
+    client: Client,
+
+    /// The URL to deliver reports to
+    url: HttpUrl,
+}
+
+impl Telemetry {
+    /// Construct a new `Telemetry` that reports to `url`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if construction of the HTTP client fails
+    pub(crate) fn new(url: HttpUrl) -> Result<Telemetry, BuildClientError> {
+        Ok(Telemetry {
+            counters: Mutex::new(Counters::new(OffsetDateTime::now_utc())),
+            client: Client::new(None)?,
+            url,
+        })
+    }
+
+    /// Count a request for `path` from a client in `client_class` that was
+    /// replied to with `status`
+    fn record(&self, path: &str, client_class: ClientClass, status: StatusCode) {
+        let mut counters = self.counters.lock().unwrap_or_else(PoisonError::into_inner);
+        counters.requests += 1;
+        *counters
+            .routes
+            .entry(RouteClass::for_path(path))
+            .or_default() += 1;
+        *counters.clients.entry(client_class).or_default() += 1;
+        *counters
+            .statuses
+            .entry(StatusClass::for_status(status))
+            .or_default() += 1;
+    }
+
+    /// Return a report of the counts accumulated since the last report, and
+    /// reset the counts
+    fn take_report(&self) -> TelemetryReport {
+        let now = OffsetDateTime::now_utc();
+        let counters = std::mem::replace(
+            &mut *self.counters.lock().unwrap_or_else(PoisonError::into_inner),
+            Counters::new(now),
+        );
+        TelemetryReport {
+            version: env!("CARGO_PKG_VERSION"),
+            period_start: counters.since,
+            period_end: now,
+            requests: counters.requests,
+            routes: counters.routes,
+            clients: counters.clients,
+            statuses: counters.statuses,
+        }
+    }
+
+    /// Spawn a task that delivers a report every `period`
+    // <https://github.com/rust-lang/rust-clippy/issues/14000>
+    #[allow(clippy::infinite_loop)]
+    pub(crate) fn install(self: Arc<Self>, period: Duration) {
+        let mut schedule = tokio::time::interval(period);
+        schedule.reset(); // Don't tick immediately
+        schedule.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        tokio::spawn(async move {
+            loop {
+                schedule.tick().await;
+                self.deliver().await;
+            }
+        });
+    }
+
+    /// Send a report of the counts accumulated since the last report to the
+    /// telemetry URL, logging any failure
+    async fn deliver(&self) {
+        let body = serde_json::to_string(&self.take_report())
+            .expect("serializing a TelemetryReport should not fail");
+        if let Err(e) = self
+            .client
+            .post_json(self.url.clone(), body, HeaderMap::new())
+            .await
+        {
+            let e = anyhow::Error::from(e);
+            tracing::warn!(error = ?e, url = %self.url, "Failed to deliver telemetry report");
+        }
+    }
+}
+
+/// Middleware for counting each request & its response in the given
+/// [`Telemetry`]
+pub(crate) async fn count_requests(
+    State(telemetry): State<Arc<Telemetry>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response<Body> {
+    let path = request.uri().path().to_owned();
+    let client_class = ClientClass::from_user_agent(
+        request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok()),
+    );
+    let resp = next.run(request).await;
+    telemetry.record(&path, client_class, resp.status());
+    resp
+}
+
+/// Request counts accumulated since a given time
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Counters {
+    /// The time at which counting started
+    since: OffsetDateTime,
+
+    /// The total number of requests
+    requests: u64,
+
+    /// The number of requests for each class of route
+    routes: BTreeMap<RouteClass, u64>,
+
+    /// The number of requests from each family of client
+    clients: BTreeMap<ClientClass, u64>,
+
+    /// The number of responses in each class of status code
+    statuses: BTreeMap<StatusClass, u64>,
+}
+
+impl Counters {
+    fn new(since: OffsetDateTime) -> Counters {
+        Counters {
+            since,
+            requests: 0,
+            routes: BTreeMap::new(),
+            clients: BTreeMap::new(),
+            statuses: BTreeMap::new(),
+        }
+    }
+}
+
+/// The JSON body of a telemetry report
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct TelemetryReport {
+    /// The version of `dandidav` sending the report
+    version: &'static str,
+
+    /// The start of the period covered by the report
+    #[serde(with = "time::serde::rfc3339")]
+    period_start: OffsetDateTime,
+
+    /// The end of the period covered by the report
+    #[serde(with = "time::serde::rfc3339")]
+    period_end: OffsetDateTime,
+
+    /// The total number of requests made during the period
+    requests: u64,
+
+    /// The number of requests for each class of route
+    routes: BTreeMap<RouteClass, u64>,
+
+    /// The number of requests from each family of client
+    clients: BTreeMap<ClientClass, u64>,
+
+    /// The number of responses in each class of status code
+    statuses: BTreeMap<StatusClass, u64>,
+}
+
+/// A coarse classification of request paths
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum RouteClass {
+    /// The root collection
+    Root,
+
+    /// Paths under `/dandisets/`
+    Dandisets,
+
+    /// Paths under `/zarrs/`
+    Zarrs,
+
+    /// The feeds of changes & new releases
+    Feeds,
+
+    /// Paths under `/.admin/`
+    Admin,
+
+    /// Anything else, including static assets and unknown paths
+    Other,
+}
+
+impl RouteClass {
+    /// Classify the request path `path`
+    fn for_path(path: &str) -> RouteClass {
+        let first = path
+            .trim_start_matches('/')
+            .split('/')
+            .next()
+            .unwrap_or_default();
+        match first {
+            "" => RouteClass::Root,
+            "dandisets" => RouteClass::Dandisets,
+            "zarrs" => RouteClass::Zarrs,
+            "changes" | "changes.atom" | "feed.xml" => RouteClass::Feeds,
+            ".admin" => RouteClass::Admin,
+            _ => RouteClass::Other,
+        }
+    }
+}
+
+/// A classification of HTTP status codes by their first digit
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+enum StatusClass {
+    #[serde(rename = "1xx")]
+    Informational,
+    #[serde(rename = "2xx")]
+    Success,
+    #[serde(rename = "3xx")]
+    Redirection,
+    #[serde(rename = "4xx")]
+    ClientError,
+    #[serde(rename = "5xx")]
+    ServerError,
+}
+
+impl StatusClass {
+    fn for_status(status: StatusCode) -> StatusClass {
+        if status.is_informational() {
+            StatusClass::Informational
+        } else if status.is_success() {
+            StatusClass::Success
+        } else if status.is_redirection() {
+            StatusClass::Redirection
+        } else if status.is_client_error() {
+            StatusClass::ClientError
+        } else {
+            StatusClass::ServerError
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[rstest]
+    #[case("/", RouteClass::Root)]
+    #[case("/dandisets/", RouteClass::Dandisets)]
+    #[case("/dandisets/000001/draft/foo.nwb", RouteClass::Dandisets)]
+    #[case("/zarrs/056/", RouteClass::Zarrs)]
+    #[case("/changes.atom", RouteClass::Feeds)]
+    #[case("/.admin/memory", RouteClass::Admin)]
+    #[case("/.static/styles.css", RouteClass::Other)]
+    #[case("/dandisetsx/", RouteClass::Other)]
+    fn test_route_class(#[case] path: &str, #[case] class: RouteClass) {
+        assert_eq!(RouteClass::for_path(path), class);
+    }
+
+    #[tokio::test]
+    async fn deliver_report() {
+        let receiver = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/telemetry"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&receiver)
+            .await;
+        let url = format!("{}/telemetry", receiver.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let telemetry = Telemetry::new(url).unwrap();
+        telemetry.record("/dandisets/", ClientClass::WebDav, StatusCode::MULTI_STATUS);
+        telemetry.record("/dandisets/000001/", ClientClass::Browser, StatusCode::OK);
+        telemetry.record(
+            "/zarrs/nonexistent/",
+            ClientClass::WebDav,
+            StatusCode::NOT_FOUND,
+        );
+        telemetry.record("/", ClientClass::Other, StatusCode::BAD_GATEWAY);
+        telemetry.deliver().await;
+        let requests = receiver.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        let mut body = serde_json::from_slice::<serde_json::Value>(&requests[0].body).unwrap();
+        let body = body.as_object_mut().unwrap();
+        assert!(body.remove("period_start").is_some_and(|v| v.is_string()));
+        assert!(body.remove("period_end").is_some_and(|v| v.is_string()));
+        assert_eq!(
+            serde_json::Value::from(body.clone()),
+            serde_json::json!({
+                "version": env!("CARGO_PKG_VERSION"),
+                "requests": 4,
+                "routes": {"root": 1, "dandisets": 2, "zarrs": 1},
+                "clients": {"webdav": 2, "browser": 1, "other": 1},
+                "statuses": {"2xx": 2, "4xx": 1, "5xx": 1},
+            })
+        );
+
+        // Counts are reset after each report:
+        telemetry.deliver().await;
+        let requests = receiver.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        let body = serde_json::from_slice::<serde_json::Value>(&requests[1].body).unwrap();
+        assert_eq!(body["requests"], 0);
+        assert_eq!(body["routes"], serde_json::json!({}));
+    }
+}
@@ -0,0 +1,100 @@
+//! End-to-end "golden" tests of the XML returned in response to `PROPFIND`
+//! requests
+//!
+//! Each test serves `dandidav` over a local socket (backed by the mock
+//! archive), makes a `PROPFIND` request to it, and compares the response body
+//! against a file in `src/testdata/golden/`.  To regenerate the files after an
+//! intentional change to the output, run the tests with the
+//! `DANDIDAV_UPDATE_GOLDEN` environment variable set to a nonempty value.
+use super::*;
+use crate::server::{serve, ServerConfig};
+use rstest::rstest;
+use std::net::SocketAddr;
+use std::path::Path;
+use tokio::net::TcpListener;
+
+/// Serve `dandidav` with the given configuration on an unused local port,
+/// returning the address at which it is served along with the mock archive
+/// backing it (which must be kept alive for the duration of the test)
+async fn spawn_dandidav(mut cfg: Config) -> (SocketAddr, wiremock::MockServer) {
+    let mock_archive =
+        testutils::make_mock_archive(concat!(env!("CARGO_MANIFEST_DIR"), "/src/testdata/stubs"))
+            .await;
+    cfg.api_url = format!("{}/api", mock_archive.uri())
+        .parse::<HttpUrl>()
+        .unwrap();
+    let app = get_app(cfg, EffectiveConfig::default()).unwrap();
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve(listener, app, ServerConfig::default(), None));
+    (addr, mock_archive)
+}
+
+/// Make a `PROPFIND` request with an empty body and the given `Depth` to the
+/// given path on the server at `addr`
+async fn propfind_over_http(addr: SocketAddr, path: &str, depth: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .request(
+            Method::from_bytes(b"PROPFIND").unwrap(),
+            format!("http://{addr}{path}"),
+        )
+        .header("Depth", depth)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Compare `body` against the contents of the golden file `name` (or, if
+/// `DANDIDAV_UPDATE_GOLDEN` is set, overwrite the file with `body`)
+fn check_golden(name: &str, body: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .join("testdata")
+        .join("golden")
+        .join(name);
+    if std::env::var_os("DANDIDAV_UPDATE_GOLDEN").is_some_and(|v| !v.is_empty()) {
+        std::fs::write(&path, body).unwrap();
+    } else {
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("failed to read {}: {e}", path.display()));
+        pretty_assertions::assert_eq!(body, expected);
+    }
+}
+
+#[rstest]
+#[case("/", "1", "root.xml")]
+#[case("/dandisets/", "1", "dandiset-index.xml")]
+#[case("/dandisets/000001/", "1", "dandiset.xml")]
+#[case("/dandisets/000001/releases/0.210512.1623/", "1", "version.xml")]
+#[case("/dandisets/000002/draft/fRLy/", "1", "asset-folder.xml")]
+#[case("/dandisets/000002/draft/fRLy/zfa6zGT.zarr/", "0", "zarr.xml")]
+#[tokio::test]
+async fn propfind(#[case] path: &str, #[case] depth: &str, #[case] golden: &str) {
+    let (addr, _mock_archive) = spawn_dandidav(Config {
+        dandi_page_size: Some(25),
+        ..Config::default()
+    })
+    .await;
+    let r = propfind_over_http(addr, path, depth).await;
+    assert_eq!(r.status(), StatusCode::MULTI_STATUS);
+    assert_eq!(
+        r.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()),
+        Some(DAV_XML_CONTENT_TYPE)
+    );
+    check_golden(golden, &r.text().await.unwrap());
+}
+
+#[rstest]
+#[case("/dandisets/000002/draft/fRLy/", "collapsed-asset-folder.xml")]
+#[tokio::test]
+async fn propfind_collapsed(#[case] path: &str, #[case] golden: &str) {
+    let (addr, _mock_archive) = spawn_dandidav(Config {
+        dandi_page_size: Some(25),
+        zarr_mode: ZarrMode::Collapsed,
+        ..Config::default()
+    })
+    .await;
+    let r = propfind_over_http(addr, path, "1").await;
+    assert_eq!(r.status(), StatusCode::MULTI_STATUS);
+    check_golden(golden, &r.text().await.unwrap());
+}
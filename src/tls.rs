@@ -0,0 +1,446 @@
+//! Serving over TLS, with optional client certificate authentication
+use rustls::{
+    crypto::ring::default_provider,
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::{ServerConfig, VerifierBuilderError, WebPkiClientVerifier},
+    RootCertStore,
+};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::net::TcpStream;
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+use x509_parser::{
+    asn1_rs::{Tag, ToDer},
+    prelude::{FromDer, X509Certificate, X509Name},
+};
+
+/// Settings for accepting TLS connections
+#[derive(Clone)]
+pub(crate) struct TlsSettings {
+    /// The acceptor that performs TLS handshakes
+    acceptor: TlsAcceptor,
+
+    /// If set, only clients whose certificate subjects are in this allowlist
+    /// are allowed to connect
+    allowlist: Option<Arc<SubjectAllowlist>>,
+}
+
+impl std::fmt::Debug for TlsSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsSettings")
+            .field("allowlist", &self.allowlist)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TlsSettings {
+    /// Construct a `TlsSettings` that serves the certificate chain in the PEM
+    /// file `cert` using the private key in the PEM file `key`.
+    ///
+    /// If `client_ca` is given, clients are required to present certificates
+    /// signed by one of the CA certificates in that PEM file.  If `allowlist`
+    /// is also given, the subjects of client certificates must additionally
+    /// be listed in that file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the files cannot be read or parsed or if
+    /// the TLS configuration cannot be constructed from their contents
+    pub(crate) fn load(
+        cert: &Path,
+        key: &Path,
+        client_ca: Option<&Path>,
+        allowlist: Option<&Path>,
+    ) -> Result<TlsSettings, TlsSetupError> {
+        let provider = Arc::new(default_provider());
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()?;
+        let builder = match client_ca {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for c in load_certs(path)? {
+                    roots.add(c).map_err(|source| TlsSetupError::BadCaCert {
+                        path: path.to_owned(),
+                        source,
+                    })?;
+                }
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                        .build()?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let config = builder.with_single_cert(load_certs(cert)?, load_key(key)?)?;
+        let allowlist = allowlist
+            .map(SubjectAllowlist::load)
+            .transpose()?
+            .map(Arc::new);
+        Ok(TlsSettings {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            allowlist,
+        })
+    }
+
+    /// Perform a TLS handshake over `stream` with the client at `peer` and
+    /// check the client's certificate against the allowlist, if any
+    pub(crate) async fn accept(
+        &self,
+        stream: TcpStream,
+        peer: SocketAddr,
+    ) -> Result<TlsStream<TcpStream>, TlsAcceptError> {
+        let stream = self.acceptor.accept(stream).await?;
+        if let Some(ref allowlist) = self.allowlist {
+            let (_, conn) = stream.get_ref();
+            let Some(cert) = conn.peer_certificates().and_then(|certs| certs.first()) else {
+                return Err(TlsAcceptError::NoClientCert);
+            };
+            let subject = ClientSubject::from_cert(cert)?;
+            if !allowlist.allows(&subject) {
+                return Err(TlsAcceptError::NotAllowed {
+                    subject: subject.dn,
+                });
+            }
+            tracing::debug!(%peer, subject = subject.dn, "Accepted client certificate");
+        }
+        Ok(stream)
+    }
+}
+
+/// Read all certificates from the PEM file at `path`
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsSetupError> {
+    let fp = File::open(path).map_err(|source| TlsSetupError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(fp))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|source| TlsSetupError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+    if certs.is_empty() {
+        return Err(TlsSetupError::NoCerts {
+            path: path.to_owned(),
+        });
+    }
+    Ok(certs)
+}
+
+/// Read the first private key from the PEM file at `path`
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsSetupError> {
+    let fp = File::open(path).map_err(|source| TlsSetupError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+    rustls_pemfile::private_key(&mut BufReader::new(fp))
+        .map_err(|source| TlsSetupError::Read {
+            path: path.to_owned(),
+            source,
+        })?
+        .ok_or_else(|| TlsSetupError::NoKey {
+            path: path.to_owned(),
+        })
+}
+
+/// A set of client certificate subjects that are allowed to connect.  Each
+/// entry is either a full subject distinguished name in RFC 4514 format (e.g.,
+/// `CN=alice,O=Example Clinic,C=US`) or a bare common name (e.g., `alice`).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct SubjectAllowlist(HashSet<String>);
+
+impl SubjectAllowlist {
+    /// Read an allowlist from the file at `path`, which must contain one
+    /// entry per line.  Leading & trailing whitespace is ignored, as are blank
+    /// lines and lines starting with `#`.
+    fn load(path: &Path) -> Result<SubjectAllowlist, TlsSetupError> {
+        let s = std::fs::read_to_string(path).map_err(|source| TlsSetupError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        Ok(SubjectAllowlist::parse(&s))
+    }
+
+    fn parse(s: &str) -> SubjectAllowlist {
+        SubjectAllowlist(
+            s.lines()
+                .map(str::trim)
+                .filter(|ln| !ln.is_empty() && !ln.starts_with('#'))
+                .map(String::from)
+                .collect(),
+        )
+    }
+
+    /// Return whether the client with the given certificate subject is
+    /// allowed to connect
+    fn allows(&self, subject: &ClientSubject) -> bool {
+        self.0.contains(&subject.dn)
+            || subject
+                .common_names
+                .iter()
+                .any(|cn| self.0.contains(cn.as_str()))
+    }
+}
+
+/// The subject of a client certificate
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ClientSubject {
+    /// The subject distinguished name in RFC 4514 format
+    dn: String,
+
+    /// The values of the subject's common name (CN) attributes
+    common_names: Vec<String>,
+}
+
+impl ClientSubject {
+    /// Extract the subject from a DER-encoded certificate
+    fn from_cert(cert: &CertificateDer<'_>) -> Result<ClientSubject, TlsAcceptError> {
+        let (_, cert) = X509Certificate::from_der(cert)
+            .map_err(|e| TlsAcceptError::BadClientCert(e.to_string()))?;
+        Ok(ClientSubject::from_name(cert.subject()))
+    }
+
+    /// Convert a parsed X.509 `Name` to a `ClientSubject`
+    fn from_name(name: &X509Name<'_>) -> ClientSubject {
+        let mut rdns = Vec::new();
+        let mut common_names = Vec::new();
+        for rdn in name.iter_rdn() {
+            let mut attrs = Vec::new();
+            for attr in rdn.iter() {
+                let oid = attr.attr_type().to_id_string();
+                let value = attr.attr_value();
+                let value = match decode_string(value.header.tag(), value.data) {
+                    Some(s) => {
+                        if oid == OID_COMMON_NAME {
+                            common_names.push(s.clone());
+                        }
+                        escape_value(&s)
+                    }
+                    // RFC 4514, section 2.4: values of unrecognized types
+                    // are written as "#" followed by the hex encoding of
+                    // their BER encoding
+                    None => format!("#{}", hex(&value.to_der_vec().unwrap_or_default())),
+                };
+                let name = attribute_name(&oid).map_or(oid, String::from);
+                attrs.push(format!("{name}={value}"));
+            }
+            rdns.push(attrs.join("+"));
+        }
+        // RFC 4514 lists RDNs starting from the last one in the encoding
+        rdns.reverse();
+        ClientSubject {
+            dn: rdns.join(","),
+            common_names,
+        }
+    }
+}
+
+const OID_COMMON_NAME: &str = "2.5.4.3";
+
+/// Return the RFC 4514 short name for the attribute type with the given OID,
+/// if it has one
+fn attribute_name(oid: &str) -> Option<&'static str> {
+    match oid {
+        OID_COMMON_NAME => Some("CN"),
+        "2.5.4.6" => Some("C"),
+        "2.5.4.7" => Some("L"),
+        "2.5.4.8" => Some("ST"),
+        "2.5.4.9" => Some("STREET"),
+        "2.5.4.10" => Some("O"),
+        "2.5.4.11" => Some("OU"),
+        "0.9.2342.19200300.100.1.1" => Some("UID"),
+        "0.9.2342.19200300.100.1.25" => Some("DC"),
+        _ => None,
+    }
+}
+
+/// Decode the contents of a DER-encoded string value with the given tag.
+/// Returns `None` if the tag is not that of a supported string type or if the
+/// contents are invalid.
+fn decode_string(tag: Tag, value: &[u8]) -> Option<String> {
+    match tag {
+        Tag::Utf8String | Tag::PrintableString | Tag::Ia5String => {
+            String::from_utf8(value.to_vec()).ok()
+        }
+        // Commonly used for Latin-1 text
+        Tag::TeletexString => Some(value.iter().map(|&b| char::from(b)).collect()),
+        Tag::BmpString => {
+            if value.len() % 2 != 0 {
+                return None;
+            }
+            let units = value
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes(c.try_into().expect("chunk should be two bytes")));
+            char::decode_utf16(units)
+                .collect::<Result<String, _>>()
+                .ok()
+        }
+        _ => None,
+    }
+}
+
+/// Escape an attribute value for use in an RFC 4514 string
+fn escape_value(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let last = s.chars().count().saturating_sub(1);
+    for (i, c) in s.chars().enumerate() {
+        let special = matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';')
+            || (i == 0 && matches!(c, '#' | ' '))
+            || (i == last && c == ' ');
+        if special {
+            out.push('\\');
+        }
+        if c == '\0' {
+            out.push_str("\\00");
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Encode bytes as lowercase hexadecimal
+fn hex(bs: &[u8]) -> String {
+    bs.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum TlsSetupError {
+    #[error("failed to read {}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("no certificates found in {}", path.display())]
+    NoCerts { path: PathBuf },
+    #[error("no private key found in {}", path.display())]
+    NoKey { path: PathBuf },
+    #[error("invalid CA certificate in {}", path.display())]
+    BadCaCert {
+        path: PathBuf,
+        source: rustls::Error,
+    },
+    #[error("failed to construct client certificate verifier")]
+    Verifier(#[from] VerifierBuilderError),
+    #[error("failed to construct TLS configuration")]
+    Rustls(#[from] rustls::Error),
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum TlsAcceptError {
+    #[error("TLS handshake failed")]
+    Handshake(#[from] std::io::Error),
+    #[error("client did not present a certificate")]
+    NoClientCert,
+    #[error("could not parse client certificate: {0}")]
+    BadClientCert(String),
+    #[error("client certificate subject {subject:?} is not in allowlist")]
+    NotAllowed { subject: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAG_SEQUENCE: u8 = 0x30;
+    const TAG_SET: u8 = 0x31;
+    const TAG_OID: u8 = 0x06;
+
+    /// DER-encode a TLV with the given tag & (short) contents
+    fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag, u8::try_from(contents.len()).unwrap()];
+        out.extend_from_slice(contents);
+        out
+    }
+
+    /// DER-encode an `AttributeTypeAndValue` with the given encoded OID
+    /// contents, value tag, & value
+    fn attr(oid: &[u8], tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut contents = tlv(TAG_OID, oid);
+        contents.extend(tlv(tag, value));
+        tlv(TAG_SEQUENCE, &contents)
+    }
+
+    /// Parse the DER encoding of a `Name` consisting of the given encoded
+    /// `RelativeDistinguishedName`s and convert it to a `ClientSubject`
+    fn subject(rdns: &[u8]) -> ClientSubject {
+        let der = tlv(TAG_SEQUENCE, rdns);
+        let (rest, name) = X509Name::from_der(&der).unwrap();
+        assert!(rest.is_empty());
+        ClientSubject::from_name(&name)
+    }
+
+    const CN: &[u8] = &[0x55, 0x04, 0x03];
+    const O: &[u8] = &[0x55, 0x04, 0x0A];
+    const C: &[u8] = &[0x55, 0x04, 0x06];
+
+    #[test]
+    fn subject_from_name() {
+        let mut rdns = tlv(TAG_SET, &attr(C, 0x13, b"US"));
+        rdns.extend(tlv(TAG_SET, &attr(O, 0x0C, b"Example Clinic, Inc.")));
+        rdns.extend(tlv(TAG_SET, &attr(CN, 0x0C, b"alice")));
+        let subject = subject(&rdns);
+        assert_eq!(subject.dn, r"CN=alice,O=Example Clinic\, Inc.,C=US");
+        assert_eq!(subject.common_names, ["alice"]);
+    }
+
+    #[test]
+    fn subject_multivalued_rdn() {
+        let mut set = attr(CN, 0x0C, b"bob");
+        set.extend(attr(&[0x2A, 0x03], 0x0C, b"x"));
+        let subject = subject(&tlv(TAG_SET, &set));
+        assert_eq!(subject.dn, "CN=bob+1.2.3=x");
+    }
+
+    #[test]
+    fn subject_unknown_value_type() {
+        let subject = subject(&tlv(TAG_SET, &attr(CN, 0x04, b"\x01\x02")));
+        assert_eq!(subject.dn, "CN=#04020102");
+        assert!(subject.common_names.is_empty());
+    }
+
+    #[test]
+    fn subject_bmp_string() {
+        let subject = subject(&tlv(TAG_SET, &attr(CN, 0x1E, b"\x00c\x00\xe9")));
+        assert_eq!(subject.dn, "CN=c\u{e9}");
+        assert_eq!(subject.common_names, ["c\u{e9}"]);
+    }
+
+    #[test]
+    fn cert_malformed() {
+        assert!(matches!(
+            ClientSubject::from_cert(&CertificateDer::from(vec![TAG_SEQUENCE, 0x05, 0x30])),
+            Err(TlsAcceptError::BadClientCert(_))
+        ));
+    }
+
+    #[test]
+    fn allowlist() {
+        let allowlist = SubjectAllowlist::parse(concat!(
+            "# Allowed clients\n",
+            "\n",
+            "  alice  \n",
+            "CN=bob,O=Example Clinic,C=US\n",
+        ));
+        let subject = |dn: &str, cn: &str| ClientSubject {
+            dn: dn.into(),
+            common_names: vec![cn.into()],
+        };
+        assert!(allowlist.allows(&subject("CN=alice,C=US", "alice")));
+        assert!(allowlist.allows(&subject("CN=bob,O=Example Clinic,C=US", "bob")));
+        assert!(!allowlist.allows(&subject("CN=bob,C=US", "bob")));
+        assert!(!allowlist.allows(&subject("CN=mallory", "mallory")));
+        assert!(!allowlist.allows(&subject("CN=# Allowed clients", "# Allowed clients")));
+    }
+
+    #[test]
+    fn test_escape_value() {
+        assert_eq!(escape_value("#a b "), r"\#a b\ ");
+        assert_eq!(escape_value("a+b;c"), r"a\+b\;c");
+    }
+}
@@ -0,0 +1,268 @@
+//! Tracking of the versions of the Archive's API server & metadata schema
+//!
+//! At startup and periodically thereafter, `dandidav` asks the Archive for
+//! the versions of its API server & metadata schema via `/api/info/` and
+//! compares the schema version against the range of versions that `dandidav`
+//! has been tested against.  The most recent observation is served as JSON at
+//! `/.admin/upstream`, and, when the Archive reports a newer schema version
+//! than any tested, a warning is logged and shown in HTML views so that
+//! breakage from Archive deploys can be caught early.
+use crate::consts::{MAX_TESTED_SCHEMA_VERSION, MIN_TESTED_SCHEMA_VERSION};
+use crate::dandi::{ArchiveInfo, DandiClient};
+use crate::openapi::ApiSchema;
+use serde::Serialize;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// A record of the most recently observed versions of the Archive's API
+/// server & metadata schema
+#[derive(Debug, Default)]
+pub(crate) struct UpstreamMonitor {
+    observation: Mutex<Option<Observation>>,
+}
+
+impl UpstreamMonitor {
+    pub(crate) fn new() -> UpstreamMonitor {
+        UpstreamMonitor::default()
+    }
+
+    /// Spawn a task that checks the versions reported by `dandi`'s Archive
+    /// instance immediately and then every `period`
+    // <https://github.com/rust-lang/rust-clippy/issues/14000>
+    #[allow(clippy::infinite_loop)]
+    pub(crate) fn install(self: Arc<Self>, dandi: DandiClient, period: Duration) {
+        let mut schedule = tokio::time::interval(period);
+        schedule.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        tokio::spawn(async move {
+            loop {
+                schedule.tick().await;
+                match dandi.get_info().await {
+                    Ok(info) => self.record(info),
+                    Err(e) => {
+                        let e = anyhow::Error::from(e);
+                        tracing::warn!(error = ?e, "Failed to fetch Archive version information");
+                    }
+                }
+            }
+        });
+    }
+
+    /// Record `info` as the latest information reported by the Archive,
+    /// logging a warning if its schema version is outside the tested range
+    pub(crate) fn record(&self, info: ArchiveInfo) {
+        let compatibility = Compatibility::for_schema_version(&info.schema_version);
+        if compatibility == Compatibility::Compatible {
+            tracing::info!(
+                api_version = %info.version,
+                schema_version = %info.schema_version,
+                "Archive is running a tested metadata schema version"
+            );
+        } else {
+            tracing::warn!(
+                api_version = %info.version,
+                schema_version = %info.schema_version,
+                ?compatibility,
+                "Archive is running an untested metadata schema version"
+            );
+        }
+        let obs = Observation {
+            api_version: info.version,
+            schema_version: info.schema_version,
+            compatibility,
+            checked_at: OffsetDateTime::now_utc(),
+        };
+        *self
+            .observation
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(obs);
+    }
+
+    /// If the Archive was last observed to be using a newer metadata schema
+    /// than any that `dandidav` has been tested against, return that schema
+    /// version
+    pub(crate) fn newer_schema_version(&self) -> Option<String> {
+        self.observation
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .filter(|obs| obs.compatibility == Compatibility::Newer)
+            .map(|obs| obs.schema_version.clone())
+    }
+
+    /// Return a report on the most recent observation, as served at
+    /// `/.admin/upstream`
+    pub(crate) fn report(&self) -> UpstreamReport {
+        let obs = self
+            .observation
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        UpstreamReport {
+            api_version: obs.as_ref().map(|o| o.api_version.clone()),
+            schema_version: obs.as_ref().map(|o| o.schema_version.clone()),
+            compatibility: obs
+                .as_ref()
+                .map_or(Compatibility::Unknown, |o| o.compatibility),
+            min_tested_schema_version: format_version(MIN_TESTED_SCHEMA_VERSION),
+            max_tested_schema_version: format_version(MAX_TESTED_SCHEMA_VERSION),
+            checked_at: obs.map(|o| o.checked_at),
+        }
+    }
+}
+
+/// Versions reported by the Archive at a certain time
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Observation {
+    api_version: String,
+    schema_version: String,
+    compatibility: Compatibility,
+    checked_at: OffsetDateTime,
+}
+
+/// How a metadata schema version compares to the range of versions that
+/// `dandidav` has been tested against
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Compatibility {
+    /// The version is within the tested range
+    Compatible,
+
+    /// The version is newer than any tested version
+    Newer,
+
+    /// The version is older than any tested version
+    Older,
+
+    /// The version could not be parsed, or no version has been observed yet
+    Unknown,
+}
+
+impl Compatibility {
+    fn for_schema_version(s: &str) -> Compatibility {
+        match parse_version(s) {
+            Some(v) if v > MAX_TESTED_SCHEMA_VERSION => Compatibility::Newer,
+            Some(v) if v < MIN_TESTED_SCHEMA_VERSION => Compatibility::Older,
+            Some(_) => Compatibility::Compatible,
+            None => Compatibility::Unknown,
+        }
+    }
+}
+
+/// A report on the versions of the Archive's API server & metadata schema, as
+/// served at `/.admin/upstream`
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct UpstreamReport {
+    /// The version of the Archive's API server, if known
+    api_version: Option<String>,
+
+    /// The version of the Archive's metadata schema, if known
+    schema_version: Option<String>,
+
+    /// How `schema_version` compares to the tested range
+    compatibility: Compatibility,
+
+    /// The earliest metadata schema version that `dandidav` has been tested
+    /// against
+    min_tested_schema_version: String,
+
+    /// The latest metadata schema version that `dandidav` has been tested
+    /// against
+    max_tested_schema_version: String,
+
+    /// When the versions were last successfully fetched
+    #[serde(with = "time::serde::rfc3339::option")]
+    checked_at: Option<OffsetDateTime>,
+}
+
+impl ApiSchema for UpstreamReport {
+    fn schema() -> serde_json::Value {
+        let opt_string = serde_json::json!({"type": ["string", "null"]});
+        serde_json::json!({
+            "type": "object",
+            "description": "Versions of the Archive's API server & metadata schema",
+            "properties": {
+                "api_version": opt_string,
+                "schema_version": opt_string,
+                "compatibility": {
+                    "type": "string",
+                    "enum": ["compatible", "newer", "older", "unknown"],
+                },
+                "min_tested_schema_version": {"type": "string"},
+                "max_tested_schema_version": {"type": "string"},
+                "checked_at": {"type": ["string", "null"], "format": "date-time"},
+            },
+            "required": [
+                "api_version",
+                "schema_version",
+                "compatibility",
+                "min_tested_schema_version",
+                "max_tested_schema_version",
+                "checked_at",
+            ],
+        })
+    }
+}
+
+/// Parse a version string of the form `X.Y.Z` into a (major, minor, patch)
+/// triple
+fn parse_version(s: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = s.split('.').map(|p| p.parse::<u32>().ok());
+    let major = parts.next()??;
+    let minor = parts.next()??;
+    let patch = parts.next()??;
+    parts.next().is_none().then_some((major, minor, patch))
+}
+
+fn format_version((major, minor, patch): (u32, u32, u32)) -> String {
+    format!("{major}.{minor}.{patch}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("0.6.3", Some((0, 6, 3)))]
+    #[case("0.10.0", Some((0, 10, 0)))]
+    #[case("0.6", None)]
+    #[case("0.6.3.1", None)]
+    #[case("0.6.x", None)]
+    #[case("", None)]
+    fn test_parse_version(#[case] s: &str, #[case] v: Option<(u32, u32, u32)>) {
+        assert_eq!(parse_version(s), v);
+    }
+
+    #[rstest]
+    #[case("0.6.3", Compatibility::Compatible)]
+    #[case("0.6.10", Compatibility::Compatible)]
+    #[case("0.6.11", Compatibility::Newer)]
+    #[case("0.7.0", Compatibility::Newer)]
+    #[case("0.5.2", Compatibility::Older)]
+    #[case("unknown", Compatibility::Unknown)]
+    fn test_compatibility(#[case] s: &str, #[case] compat: Compatibility) {
+        assert_eq!(Compatibility::for_schema_version(s), compat);
+    }
+
+    #[test]
+    fn record_newer() {
+        let monitor = UpstreamMonitor::new();
+        assert_eq!(monitor.newer_schema_version(), None);
+        assert_eq!(monitor.report().compatibility, Compatibility::Unknown);
+        monitor.record(ArchiveInfo {
+            schema_version: "0.7.0".into(),
+            version: "0.5.0".into(),
+        });
+        assert_eq!(monitor.newer_schema_version().as_deref(), Some("0.7.0"));
+        let report = monitor.report();
+        assert_eq!(report.compatibility, Compatibility::Newer);
+        assert_eq!(report.api_version.as_deref(), Some("0.5.0"));
+        assert!(report.checked_at.is_some());
+        monitor.record(ArchiveInfo {
+            schema_version: "0.6.3".into(),
+            version: "0.5.1".into(),
+        });
+        assert_eq!(monitor.newer_schema_version(), None);
+    }
+}
@@ -0,0 +1,292 @@
+//! The `dandidav verify` subcommand, which crawls the asset listing of a
+//! Dandiset version and reports anomalies that `dandidav` would encounter when
+//! serving it
+use crate::consts::{BY_ASSET_ID_DIR, FAST_NOT_EXIST};
+use crate::dandi::{DandiClient, DandiError, DandisetId, ListedAsset, VersionId};
+use crate::paths::PurePath;
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// A report on the anomalies found in a Dandiset version's asset listing
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct VerifyReport {
+    /// The ID of the Dandiset that was checked
+    pub(crate) dandiset_id: DandisetId,
+
+    /// The version of the Dandiset that was checked
+    pub(crate) version: VersionId,
+
+    /// The number of assets in the version
+    pub(crate) assets: usize,
+
+    /// The anomalies found, in order of asset path
+    pub(crate) anomalies: Vec<Anomaly>,
+}
+
+/// A problem with an asset in a Dandiset version's asset listing
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct Anomaly {
+    /// The type of problem
+    pub(crate) kind: AnomalyKind,
+
+    /// The path of the affected asset, as given by the Archive
+    pub(crate) path: String,
+
+    /// The ID of the affected asset
+    pub(crate) asset_id: String,
+
+    /// A human-readable description of the problem
+    pub(crate) detail: String,
+}
+
+/// The types of problems that can be found in asset listings
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum AnomalyKind {
+    /// The path is not a normalized relative path and so cannot be served
+    UnparsablePath,
+
+    /// The path is used by more than one asset
+    DuplicatePath,
+
+    /// The path differs from another asset's path only in letter case, which
+    /// clients on case-insensitive filesystems cannot represent
+    CaseCollision,
+
+    /// A leading portion of the path is itself the path of an asset, so it
+    /// would have to be both a file and a folder
+    FileFolderConflict,
+
+    /// The path coincides with a name that `dandidav` reserves or refuses to
+    /// look up, so the asset cannot be accessed through `dandidav`
+    UnreachablePath,
+
+    /// The path contains control characters, backslashes, or components with
+    /// leading or trailing whitespace
+    UnusualCharacters,
+
+    /// The Archive did not report a size for the asset
+    MissingSize,
+
+    /// The Archive reported a negative size for the asset
+    NegativeSize,
+}
+
+/// Crawl the asset listing for the given Dandiset version and return a report
+/// of any anomalies found
+pub(crate) async fn verify(
+    client: &DandiClient,
+    dandiset_id: DandisetId,
+    version: VersionId,
+) -> Result<VerifyReport, DandiError> {
+    let assets = client
+        .dandiset(dandiset_id.clone())
+        .version(version.clone())
+        .get_listed_assets()
+        .try_collect::<Vec<_>>()
+        .await?;
+    let anomalies = check_assets(&assets);
+    Ok(VerifyReport {
+        dandiset_id,
+        version,
+        assets: assets.len(),
+        anomalies,
+    })
+}
+
+/// Check the given asset listing for anomalies
+fn check_assets(assets: &[ListedAsset]) -> Vec<Anomaly> {
+    let all_paths = assets
+        .iter()
+        .map(|a| a.path.as_str())
+        .collect::<HashSet<_>>();
+    let mut seen = HashSet::new();
+    let mut folded = HashMap::<String, &str>::new();
+    let mut anomalies = Vec::new();
+    for asset in assets {
+        let mut report = |kind, detail: String| {
+            anomalies.push(Anomaly {
+                kind,
+                path: asset.path.clone(),
+                asset_id: asset.asset_id.clone(),
+                detail,
+            });
+        };
+        let path = asset.path.as_str();
+        if let Err(e) = path.parse::<PurePath>() {
+            report(AnomalyKind::UnparsablePath, format!("path is not {e}"));
+        }
+        if !seen.insert(path) {
+            report(
+                AnomalyKind::DuplicatePath,
+                "path is used by more than one asset".into(),
+            );
+        } else {
+            match folded.get(&path.to_lowercase()) {
+                Some(&other) => report(
+                    AnomalyKind::CaseCollision,
+                    format!("path differs only in case from {other:?}"),
+                ),
+                None => {
+                    folded.insert(path.to_lowercase(), path);
+                }
+            }
+        }
+        for (i, _) in path.match_indices('/') {
+            let prefix = &path[..i];
+            if all_paths.contains(prefix) {
+                report(
+                    AnomalyKind::FileFolderConflict,
+                    format!("leading portion {prefix:?} is also the path of an asset"),
+                );
+            }
+        }
+        if let Some(detail) = unreachability(path) {
+            report(AnomalyKind::UnreachablePath, detail);
+        }
+        let unusual = unusual_characters(path);
+        if !unusual.is_empty() {
+            report(
+                AnomalyKind::UnusualCharacters,
+                format!("path contains {}", unusual.join(", ")),
+            );
+        }
+        match asset.size {
+            None => report(
+                AnomalyKind::MissingSize,
+                "Archive did not report a size".into(),
+            ),
+            Some(size) if size < 0 => report(
+                AnomalyKind::NegativeSize,
+                format!("Archive reported a size of {size}"),
+            ),
+            Some(_) => (),
+        }
+    }
+    anomalies
+}
+
+/// If `path` cannot be accessed through `dandidav` because it coincides with a
+/// reserved name, return a description of why
+fn unreachability(path: &str) -> Option<String> {
+    if path == "dandiset.yaml" {
+        return Some("path is shadowed by the dandiset.yaml generated by dandidav".into());
+    }
+    if path.split('/').next() == Some(BY_ASSET_ID_DIR) {
+        return Some(format!(
+            "path is under the {BY_ASSET_ID_DIR} directory reserved by dandidav"
+        ));
+    }
+    path.split('/')
+        .find(|c| FAST_NOT_EXIST.iter().any(|n| c.eq_ignore_ascii_case(n)))
+        .map(|c| format!("path component {c:?} is always treated as nonexistent by dandidav"))
+}
+
+/// Return descriptions of the unusual characters & whitespace in `path`
+fn unusual_characters(path: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let controls = path
+        .chars()
+        .filter(|c| c.is_control())
+        .map(|c| c.escape_default().to_string())
+        .collect::<Vec<_>>();
+    if !controls.is_empty() {
+        found.push(format!("control characters ({})", controls.join(" ")));
+    }
+    if path.contains('\\') {
+        found.push("backslashes".into());
+    }
+    if path.split('/').any(|c| c.trim() != c) {
+        found.push("components with leading or trailing whitespace".into());
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn asset(path: &str, size: Option<i64>) -> ListedAsset {
+        ListedAsset {
+            asset_id: format!("id:{path}"),
+            path: path.into(),
+            size,
+        }
+    }
+
+    fn kinds(assets: &[ListedAsset]) -> Vec<(String, AnomalyKind)> {
+        check_assets(assets)
+            .into_iter()
+            .map(|a| (a.path, a.kind))
+            .collect()
+    }
+
+    #[test]
+    fn clean_listing() {
+        let assets = [
+            asset("foo/bar.nwb", Some(42)),
+            asset("foo/baz.nwb", Some(0)),
+            asset("quux.zarr", Some(1234)),
+        ];
+        assert_eq!(check_assets(&assets), Vec::new());
+    }
+
+    #[test]
+    fn anomalous_listing() {
+        let assets = [
+            asset("/abs.nwb", Some(1)),
+            asset("dandiset.yaml", Some(1)),
+            asset("foo", Some(1)),
+            asset("foo/bar.nwb", Some(1)),
+            asset("Foo/Bar.nwb", Some(1)),
+            asset("foo/bar.nwb", Some(1)),
+            asset("sub/.git/config", None),
+            asset("sub/tab\there.nwb", Some(-1)),
+        ];
+        assert_eq!(
+            kinds(&assets),
+            vec![
+                ("/abs.nwb".into(), AnomalyKind::UnparsablePath),
+                ("dandiset.yaml".into(), AnomalyKind::UnreachablePath),
+                ("foo/bar.nwb".into(), AnomalyKind::FileFolderConflict),
+                ("Foo/Bar.nwb".into(), AnomalyKind::CaseCollision),
+                ("foo/bar.nwb".into(), AnomalyKind::DuplicatePath),
+                ("foo/bar.nwb".into(), AnomalyKind::FileFolderConflict),
+                ("sub/.git/config".into(), AnomalyKind::UnreachablePath),
+                ("sub/.git/config".into(), AnomalyKind::MissingSize),
+                ("sub/tab\there.nwb".into(), AnomalyKind::UnusualCharacters),
+                ("sub/tab\there.nwb".into(), AnomalyKind::NegativeSize),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case("foo/bar.nwb", None)]
+    #[case(
+        "dandiset.yaml",
+        Some("path is shadowed by the dandiset.yaml generated by dandidav")
+    )]
+    #[case("sub/dandiset.yaml", None)]
+    #[case(
+        ".by-asset-id/foo",
+        Some("path is under the .by-asset-id directory reserved by dandidav")
+    )]
+    #[case(
+        "foo/.SVN/bar",
+        Some("path component \".SVN\" is always treated as nonexistent by dandidav")
+    )]
+    fn test_unreachability(#[case] path: &str, #[case] detail: Option<&str>) {
+        assert_eq!(unreachability(path).as_deref(), detail);
+    }
+
+    #[rstest]
+    #[case("foo/bar.nwb", &[])]
+    #[case("foo\\bar.nwb", &["backslashes"])]
+    #[case("foo /bar.nwb", &["components with leading or trailing whitespace"])]
+    #[case("foo/bar\u{7}\r.nwb", &["control characters (\\u{7} \\r)"])]
+    fn test_unusual_characters(#[case] path: &str, #[case] found: &[&str]) {
+        assert_eq!(unusual_characters(path), found);
+    }
+}
@@ -0,0 +1,312 @@
+//! Notification of webhooks about new & updated Dandiset versions
+use crate::changes::{get_changes, ChangeRecord};
+use crate::consts::WEBHOOK_SIGNATURE_HEADER;
+use crate::dandi::{DandiClient, DandiError};
+use crate::httputil::{BuildClientError, Client, HttpUrl};
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::Serialize;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use time::OffsetDateTime;
+
+/// A poller that periodically checks the Archive for Dandiset versions
+/// modified since the previous check and `POST`s a JSON [`Notification`] of
+/// any such versions to each configured webhook URL
+#[derive(Clone, Debug)]
+pub(crate) struct WebhookNotifier {
+    /// Client for the Archive instance to check for changes
+    dandi: DandiClient,
+
+    /// The HTTP client used for delivering notifications
+    client: Client,
+
+    /// The URLs to deliver notifications to
+    urls: Arc<[HttpUrl]>,
+
+    /// The secret key with which to sign notification bodies, if any
+    secret: Option<Arc<[u8]>>,
+}
+
+impl WebhookNotifier {
+    /// Construct a new `WebhookNotifier` that notifies `urls` of changes on
+    /// `dandi`'s Archive instance.  If `secret` is given, each notification
+    /// includes an HMAC-SHA256 signature of its body computed with `secret`
+    /// as the key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if construction of the HTTP client fails
+    pub(crate) fn new(
+        dandi: DandiClient,
+        urls: Vec<HttpUrl>,
+        secret: Option<Vec<u8>>,
+    ) -> Result<WebhookNotifier, BuildClientError> {
+        Ok(WebhookNotifier {
+            dandi,
+            client: Client::new(None)?,
+            urls: urls.into(),
+            secret: secret.map(Into::into),
+        })
+    }
+
+    /// Spawn a task that checks for changes every `period`, starting from the
+    /// current time.  If a check fails, the next check covers the same period
+    /// again, so that notifications that could not be delivered are resent.
+    // <https://github.com/rust-lang/rust-clippy/issues/14000>
+    #[allow(clippy::infinite_loop)]
+    pub(crate) fn install(self, period: Duration) {
+        let mut since = OffsetDateTime::now_utc();
+        let mut schedule = tokio::time::interval(period);
+        schedule.reset(); // Don't tick immediately
+        schedule.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        tokio::spawn(async move {
+            loop {
+                schedule.tick().await;
+                match self.check(since).await {
+                    Ok(latest) => since = latest,
+                    Err(e) => {
+                        let e = anyhow::Error::from(e);
+                        tracing::warn!(error = ?e, "Failed to notify webhooks of changes; will retry at next check");
+                    }
+                }
+            }
+        });
+    }
+
+    /// Notify the webhooks of all versions modified after `since`, and return
+    /// the latest `modified` timestamp seen, to use as `since` for the next
+    /// check.  Timestamps reported by the Archive are used rather than the
+    /// local clock so that changes are not missed due to clock skew.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if fetching the changes fails or if the notification
+    /// could not be delivered to every webhook, in which case `since` should
+    /// be used again for the next check.  Webhooks that did receive the
+    /// notification will then receive it again.
+    async fn check(&self, since: OffsetDateTime) -> Result<OffsetDateTime, WebhookError> {
+        let records = get_changes(&self.dandi, since).await?;
+        let Some(latest) = records.first().map(|r| r.modified) else {
+            return Ok(since);
+        };
+        let changes = records
+            .into_iter()
+            .filter(|r| r.version.is_some())
+            .collect::<Vec<_>>();
+        if !changes.is_empty() {
+            let body = serde_json::to_string(&Notification {
+                since,
+                changes: &changes,
+            })
+            .expect("serializing a Notification should not fail");
+            self.deliver(body).await?;
+        }
+        Ok(latest)
+    }
+
+    /// Send the notification `body` to each webhook URL, logging any failures
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if delivery to any of the URLs failed
+    async fn deliver(&self, body: String) -> Result<(), WebhookError> {
+        let mut headers = HeaderMap::new();
+        if let Some(secret) = &self.secret {
+            let signature = format!("sha256={}", sign(secret, body.as_bytes()));
+            headers.insert(
+                WEBHOOK_SIGNATURE_HEADER,
+                HeaderValue::from_str(&signature)
+                    .expect("hex digest should be a valid header value"),
+            );
+        }
+        let mut failed = 0;
+        for url in self.urls.iter() {
+            if let Err(e) = self
+                .client
+                .post_json(url.clone(), body.clone(), headers.clone())
+                .await
+            {
+                let e = anyhow::Error::from(e);
+                tracing::warn!(error = ?e, %url, "Failed to deliver webhook notification");
+                failed += 1;
+            }
+        }
+        if failed == 0 {
+            Ok(())
+        } else {
+            Err(WebhookError::Delivery {
+                failed,
+                total: self.urls.len(),
+            })
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum WebhookError {
+    #[error("failed to check Archive for changes")]
+    Changes(#[from] DandiError),
+
+    #[error("failed to deliver notification to {failed} of {total} webhooks")]
+    Delivery { failed: usize, total: usize },
+}
+
+/// The JSON body of a webhook notification
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+struct Notification<'a> {
+    /// The time after which the reported versions were modified
+    #[serde(with = "time::serde::rfc3339")]
+    since: OffsetDateTime,
+
+    /// The modified versions, newest first
+    changes: &'a [ChangeRecord],
+}
+
+/// Compute the hex-encoded HMAC-SHA256 of `body` using `secret` as the key
+fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret).expect("HMAC should accept keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[test]
+    fn test_sign() {
+        // Test case 2 from RFC 4231
+        assert_eq!(
+            sign(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn notify_changes() {
+        let mock_archive = testutils::make_mock_archive(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/testdata/stubs"
+        ))
+        .await;
+        let api_url = format!("{}/api", mock_archive.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let dandi = DandiClient::new(api_url, Some(25), false, 0, None, None).unwrap();
+        let receiver = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&receiver)
+            .await;
+        let hook_url = format!("{}/hook", receiver.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let notifier =
+            WebhookNotifier::new(dandi, vec![hook_url], Some(b"hunter2".to_vec())).unwrap();
+
+        let latest = notifier
+            .check(datetime!(2023-01-01 00:00:00 UTC))
+            .await
+            .unwrap();
+        assert_eq!(latest, datetime!(2024-05-18 17:53:27.096283 UTC));
+        let requests = receiver.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        let rq = &requests[0];
+        assert_eq!(
+            rq.headers.get("content-type").and_then(|v| v.to_str().ok()),
+            Some("application/json")
+        );
+        assert_eq!(
+            rq.headers
+                .get(WEBHOOK_SIGNATURE_HEADER)
+                .and_then(|v| v.to_str().ok()),
+            Some(format!("sha256={}", sign(b"hunter2", &rq.body)).as_str())
+        );
+        let body = serde_json::from_slice::<serde_json::Value>(&rq.body).unwrap();
+        assert_eq!(body["since"], "2023-01-01T00:00:00Z");
+        let versions = body["changes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| {
+                (
+                    r["dandiset_id"].as_str().unwrap(),
+                    r["version"].as_str().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            [
+                ("000002", "draft"),
+                ("000001", "draft"),
+                ("000001", "0.230629.1955")
+            ]
+        );
+
+        // Nothing has changed since the latest timestamp, so no further
+        // notification should be sent:
+        assert_eq!(notifier.check(latest).await.unwrap(), latest);
+        assert_eq!(receiver.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn resend_failed_notification() {
+        let mock_archive = testutils::make_mock_archive(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/testdata/stubs"
+        ))
+        .await;
+        let api_url = format!("{}/api", mock_archive.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let dandi = DandiClient::new(api_url, Some(25), false, 0, None, None).unwrap();
+        let receiver = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&receiver)
+            .await;
+        let hook_url = format!("{}/hook", receiver.uri())
+            .parse::<HttpUrl>()
+            .unwrap();
+        let mut notifier = WebhookNotifier::new(dandi, vec![hook_url], None).unwrap();
+        // Don't wait through retries with backoff:
+        notifier.client = Client::builder().max_retries(0).build().unwrap();
+
+        let since = datetime!(2023-01-01 00:00:00 UTC);
+        let r = notifier.check(since).await;
+        assert!(matches!(
+            r,
+            Err(WebhookError::Delivery {
+                failed: 1,
+                total: 1
+            })
+        ));
+        let requests = receiver.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        let failed_body = requests[0].body.clone();
+
+        receiver.reset().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&receiver)
+            .await;
+        let latest = notifier.check(since).await.unwrap();
+        assert_eq!(latest, datetime!(2024-05-18 17:53:27.096283 UTC));
+        let requests = receiver.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].body, failed_body);
+    }
+}
@@ -0,0 +1,68 @@
+//! Extracting summary statistics from Zarr checksums
+
+/// The statistics about a Zarr that are encoded in its Zarr checksum.
+///
+/// A Zarr checksum, as computed by the DANDI Archive and used to name Zarr
+/// manifests, has the form `{md5_digest}-{entry_count}--{total_size}`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct ZarrChecksumStats {
+    /// The number of entries in the Zarr
+    pub(crate) entry_count: u64,
+
+    /// The total size in bytes of all entries in the Zarr
+    pub(crate) size: i64,
+}
+
+impl ZarrChecksumStats {
+    /// Extract the statistics from a Zarr checksum.  Returns `None` if the
+    /// checksum is not in the expected format.
+    pub(crate) fn parse(checksum: &str) -> Option<ZarrChecksumStats> {
+        let (digest, rest) = checksum.split_once('-')?;
+        let (count, size) = rest.split_once("--")?;
+        if digest.len() != 32 || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        if !is_digits(count) || !is_digits(size) {
+            return None;
+        }
+        let entry_count = count.parse::<u64>().ok()?;
+        let size = size.parse::<i64>().ok()?;
+        Some(ZarrChecksumStats { entry_count, size })
+    }
+}
+
+/// Returns `true` iff `s` is a nonempty string of ASCII decimal digits
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn parse_valid() {
+        assert_eq!(
+            ZarrChecksumStats::parse("6ddc4625befef8d6f9796835648162be-509--710206390"),
+            Some(ZarrChecksumStats {
+                entry_count: 509,
+                size: 710206390
+            })
+        );
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("6ddc4625befef8d6f9796835648162be")]
+    #[case("6ddc4625befef8d6f9796835648162be-509-710206390")]
+    #[case("6ddc4625befef8d6f9796835648162be-509--")]
+    #[case("6ddc4625befef8d6f9796835648162be---710206390")]
+    #[case("6ddc4625befef8d6f9796835648162be-+509--710206390")]
+    #[case("6ddc4625befef8d6f9796835648162be-509---710206390")]
+    #[case("6ddc4625befef8d6f9796835648162-509--710206390")]
+    #[case("6ddc4625befef8d6f9796835648162bg-509--710206390")]
+    fn parse_invalid(#[case] s: &str) {
+        assert_eq!(ZarrChecksumStats::parse(s), None);
+    }
+}
@@ -1,10 +1,10 @@
 //! Constants and compile-time configuration for the `/zarrs/` hierarchy
 use std::time::Duration;
 
-/// The manifest root URL.
+/// The default manifest root URL.
 ///
 /// This is the base URL of the manifest tree (a URL hierarchy containing Zarr
-/// manifests).
+/// manifests) that is used when no manifest roots are configured.
 ///
 /// The current value is a subdirectory of a mirror of
 /// <https://github.com/dandi/zarr-manifests>.
@@ -21,3 +21,16 @@ pub(super) static ENTRY_DOWNLOAD_PREFIX: &str = "https://dandiarchive.s3.amazona
 
 /// Expire any manifest cache entries that haven't been accessed for this long
 pub(super) const MANIFEST_CACHE_IDLE_EXPIRY: Duration = Duration::from_secs(300);
+
+/// After a request to a manifest root fails, prefer other manifest roots for
+/// this long
+pub(super) const MANIFEST_MIRROR_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// When multiple manifest roots are configured, retry failed requests to a
+/// manifest root this many times before failing over to the next root
+pub(super) const MANIFEST_MIRROR_MAX_RETRIES: u32 = 1;
+
+/// The name of the virtual file at the root of each Zarr that summarizes the
+/// Zarr's contents.  If a Zarr contains an actual entry with this name, the
+/// entry is served instead.
+pub(super) static ZARR_INFO_NAME: &str = ".zarr-info";
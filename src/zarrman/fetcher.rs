@@ -1,15 +1,18 @@
-use super::consts::{MANIFEST_CACHE_IDLE_EXPIRY, MANIFEST_ROOT_URL};
-use super::manifest::Manifest;
+use super::consts::{MANIFEST_CACHE_IDLE_EXPIRY, MANIFEST_MIRROR_MAX_RETRIES};
+use super::manifest::{Manifest, ManifestEntry};
+use super::mirrors::ManifestMirrors;
 use super::resources::ManifestPath;
 use super::util::{Index, ZarrManError};
+use crate::blocking::run_blocking;
 use crate::httputil::{BuildClientError, Client, HttpError, HttpUrl};
-use crate::paths::PureDirPath;
+use crate::paths::{Component, PureDirPath, PurePath};
+use crate::recorder::FixtureRecorder;
 use get_size::GetSize;
 use moka::{
     future::{Cache, CacheBuilder},
     ops::compute::{CompResult, Op},
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -19,22 +22,38 @@ pub(crate) struct ManifestFetcher {
     /// The HTTP client used for making requests to the manifest tree
     inner: Client,
 
-    /// A cache of parsed manifest files, keyed by their path under
-    /// `MANIFEST_ROOT_URL`
+    /// A cache of parsed manifest files, keyed by their path under the
+    /// manifest root
     cache: Cache<ManifestPath, Arc<Manifest>>,
 
-    /// [`MANIFEST_ROOT_URL`], parsed into an [`HttpUrl`]
-    manifest_root_url: HttpUrl,
+    /// The manifest roots to fetch from, in order of preference
+    mirrors: Arc<ManifestMirrors>,
 }
 
 impl ManifestFetcher {
-    /// Construct a new client instance
+    /// Construct a new client instance that fetches from the manifest roots
+    /// at `manifest_roots`, in order of preference, failing over to later
+    /// roots when earlier ones are unavailable.  If `manifest_roots` is
+    /// empty, the default manifest root is used.  When there is more than one
+    /// root, failed requests are retried fewer times before failing over.  If
+    /// `recorder` is given, responses from the manifest tree are recorded by
+    /// it.
     ///
     /// # Errors
     ///
     /// Returns an error if construction of the inner `reqwest::Client` fails
-    pub(crate) fn new(cache_size: u64) -> Result<Self, BuildClientError> {
-        let inner = Client::new()?;
+    pub(crate) fn new(
+        cache_size: u64,
+        manifest_roots: Vec<HttpUrl>,
+        recorder: Option<Arc<FixtureRecorder>>,
+    ) -> Result<Self, BuildClientError> {
+        let mut builder = Client::builder().recorder(recorder);
+        if manifest_roots.len() > 1 {
+            // Fail over to the next root quickly rather than waiting out the
+            // full retry schedule against an unavailable one
+            builder = builder.max_retries(MANIFEST_MIRROR_MAX_RETRIES);
+        }
+        let inner = builder.build()?;
         let cache: Cache<ManifestPath, Arc<Manifest>> = CacheBuilder::new(cache_size)
             .name("zarr-manifests")
             .weigher(|_, manifest: &Arc<Manifest>| {
@@ -52,27 +71,35 @@ impl ManifestFetcher {
                 );
             })
             .build();
-        let manifest_root_url = MANIFEST_ROOT_URL
-            .parse::<HttpUrl>()
-            .expect("MANIFEST_ROOT_URL should be a valid HTTP URL");
         Ok(ManifestFetcher {
             inner,
             cache,
-            manifest_root_url,
+            mirrors: Arc::new(ManifestMirrors::new(manifest_roots)),
         })
     }
 
+    /// Return the URL of the Zarr manifest at the given [`ManifestPath`] in
+    /// the manifest tree, using the most preferred manifest root that is
+    /// currently healthy
+    pub(super) fn manifest_url(&self, path: &ManifestPath) -> HttpUrl {
+        path.under_manifest_root(self.mirrors.current_root())
+    }
+
     /// Retrieve the manifest index in the given directory of the manifest
     /// tree.
     ///
     /// `path` must be relative to the manifest root.  A `path` of `None`
     /// denotes the manifest root itself.
     pub(super) async fn fetch_index(&self, path: Option<&PureDirPath>) -> Result<Index, HttpError> {
-        let mut url = self.manifest_root_url.clone();
-        if let Some(p) = path {
-            url.extend(p.component_strs()).ensure_dirpath();
-        }
-        self.inner.get_json::<Index>(url).await
+        self.mirrors
+            .get_json::<Index, _>(&self.inner, |root| {
+                let mut url = root.clone();
+                if let Some(p) = path {
+                    url.extend(p.component_strs()).ensure_dirpath();
+                }
+                url
+            })
+            .await
     }
 
     /// Retrieve the Zarr manifest at the given [`ManifestPath`] in the
@@ -95,8 +122,8 @@ impl ManifestFetcher {
                         approx_cache_size = self.cache.weighted_size(),
                         "Cache miss for Zarr manifest; about to fetch from repository",
                     );
-                    self.inner
-                        .get_json::<Manifest>(path.under_manifest_root(&self.manifest_root_url))
+                    self.mirrors
+                        .get_json::<Manifest, _>(&self.inner, |root| path.under_manifest_root(root))
                         .await
                         .map(|zman| Op::Put(Arc::new(zman)))
                 } else {
@@ -136,6 +163,35 @@ impl ManifestFetcher {
         Ok(entry.into_value())
     }
 
+    /// Search the cached manifests for an entry whose ETag equals `etag` and
+    /// return the path to its manifest, its path within the Zarr, and its
+    /// details.  Manifests that are not currently cached are not searched.
+    pub(super) async fn find_cached_by_etag(
+        &self,
+        etag: &str,
+    ) -> Option<(ManifestPath, PurePath, ManifestEntry)> {
+        let manifests = self.cache.iter().collect::<Vec<_>>();
+        let etag = etag.to_owned();
+        run_blocking(move || {
+            manifests.into_iter().find_map(|(path, manifest)| {
+                manifest
+                    .find_by_etag(&etag)
+                    .map(|(entry_path, entry)| ((*path).clone(), entry_path, entry.clone()))
+            })
+        })
+        .await
+    }
+
+    /// Return the approximate total size in bytes of the cached manifests
+    pub(crate) fn cache_size(&self) -> u64 {
+        self.cache.weighted_size()
+    }
+
+    /// Discard all cached manifests
+    pub(crate) fn clear_cache(&self) {
+        self.cache.invalidate_all();
+    }
+
     // <https://github.com/rust-lang/rust-clippy/issues/14000>
     #[allow(clippy::infinite_loop)]
     pub(crate) fn install_periodic_dump(&self, period: Duration) {
@@ -181,6 +237,47 @@ impl ManifestFetcher {
             }
         }
     }
+
+    /// Return all cached manifests along with their paths
+    pub(crate) fn snapshot(&self) -> Vec<ManifestSnapshot> {
+        self.cache
+            .iter()
+            .map(|(path, manifest)| ManifestSnapshot {
+                prefix: path.prefix.clone(),
+                zarr_id: path.zarr_id.clone(),
+                checksum: path.checksum.clone(),
+                manifest,
+            })
+            .collect()
+    }
+
+    /// Insert the manifests in `snapshot` into the cache, skipping any paths
+    /// that are already cached, and return the number of manifests inserted
+    pub(crate) async fn restore(&self, snapshot: Vec<ManifestSnapshot>) -> usize {
+        let mut inserted = 0;
+        for ms in snapshot {
+            let path = ManifestPath {
+                prefix: ms.prefix,
+                zarr_id: ms.zarr_id,
+                checksum: ms.checksum,
+            };
+            if !self.cache.contains_key(&path) {
+                self.cache.insert(path, ms.manifest).await;
+                inserted += 1;
+            }
+        }
+        inserted
+    }
+}
+
+/// A cached Zarr manifest along with the components of its [`ManifestPath`],
+/// as transferred between instances when warming caches
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct ManifestSnapshot {
+    prefix: PureDirPath,
+    zarr_id: Component,
+    checksum: Component,
+    manifest: Arc<Manifest>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
@@ -1,15 +1,20 @@
+use super::resources::ZarrNodeType;
 use crate::paths::{Component, PurePath};
 use get_size::GetSize;
 use itertools::{Itertools, Position};
-use serde::Deserialize;
+use serde::{ser::SerializeTuple, Deserialize, Serialize, Serializer};
 use std::collections::BTreeMap;
 use time::OffsetDateTime;
 
 /// A parsed Zarr manifest
-#[derive(Clone, Debug, Deserialize, Eq, GetSize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Eq, GetSize, PartialEq, Serialize)]
 pub(super) struct Manifest {
     /// A tree of the Zarr's entries
     pub(super) entries: ManifestFolder,
+
+    /// Summary statistics on the Zarr's entries, if included in the manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) statistics: Option<ManifestStatistics>,
 }
 
 impl Manifest {
@@ -28,6 +33,76 @@ impl Manifest {
         }
         Some(EntryRef::Folder(folder))
     }
+
+    /// Return summary statistics on the Zarr's entries, taken from the
+    /// manifest's `statistics` field if present or else computed from the
+    /// entries themselves
+    pub(super) fn statistics(&self) -> ManifestStatistics {
+        if let Some(ref stats) = self.statistics {
+            return stats.clone();
+        }
+        let mut stats = ManifestStatistics {
+            entry_count: 0,
+            total_size: 0,
+            last_modified: None,
+        };
+        let mut stack = vec![&self.entries];
+        while let Some(folder) = stack.pop() {
+            for child in folder.values() {
+                match child {
+                    FolderEntry::Folder(f) => stack.push(f),
+                    FolderEntry::Entry(e) => {
+                        stats.entry_count += 1;
+                        stats.total_size += e.size;
+                        if stats.last_modified.map_or(true, |lm| lm < e.modified) {
+                            stats.last_modified = Some(e.modified);
+                        }
+                    }
+                }
+            }
+        }
+        stats
+    }
+
+    /// Find an entry in the manifest whose ETag equals `etag` (compared
+    /// case-insensitively) and return its path & details
+    pub(super) fn find_by_etag(&self, etag: &str) -> Option<(PurePath, &ManifestEntry)> {
+        let mut stack = vec![(None::<PurePath>, &self.entries)];
+        while let Some((prefix, folder)) = stack.pop() {
+            for (name, child) in folder {
+                let path = match prefix {
+                    Some(ref p) => p.join_one(name),
+                    None => PurePath::from(name.clone()),
+                };
+                match child {
+                    FolderEntry::Folder(f) => stack.push((Some(path), f)),
+                    FolderEntry::Entry(e) if e.etag.eq_ignore_ascii_case(etag) => {
+                        return Some((path, e))
+                    }
+                    FolderEntry::Entry(_) => (),
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Summary statistics on a Zarr's entries
+#[derive(Clone, Debug, Deserialize, Eq, GetSize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct ManifestStatistics {
+    /// The number of entries in the Zarr
+    #[serde(rename = "entries")]
+    pub(super) entry_count: u64,
+
+    /// The total size in bytes of the Zarr's entries
+    pub(super) total_size: i64,
+
+    /// The latest modification time of any entry in the Zarr, or `None` if
+    /// the Zarr is empty
+    #[get_size(size = 0)] // Nothing on the heap
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub(super) last_modified: Option<OffsetDateTime>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -40,7 +115,51 @@ pub(super) enum EntryRef<'a> {
 /// subdirectory names to the entries & subdirectories
 pub(super) type ManifestFolder = BTreeMap<Component, FolderEntry>;
 
-#[derive(Clone, Debug, Deserialize, Eq, GetSize, PartialEq)]
+/// Determine whether `folder` is a Zarr group or array from the metadata
+/// files among its entries.  Returns `None` if `folder` contains no Zarr
+/// metadata file or if its node type cannot be inferred.
+pub(super) fn node_type(folder: &ManifestFolder) -> Option<ZarrNodeType> {
+    if has_entry(folder, ".zgroup") {
+        Some(ZarrNodeType::Group)
+    } else if has_entry(folder, ".zarray") {
+        Some(ZarrNodeType::Array)
+    } else if has_entry(folder, "zarr.json") {
+        v3_node_type(folder)
+    } else {
+        None
+    }
+}
+
+/// Determine whether `folder`, a Zarr v3 node, is a group or array.
+///
+/// Zarr v3 records a node's type only inside its `zarr.json`, which is not
+/// available from the manifest, so the type is inferred from the folder's
+/// contents instead: a folder containing another node is a group, and one
+/// containing anything else (i.e., chunks) is an array.  A folder containing
+/// nothing but its `zarr.json` cannot be classified.
+fn v3_node_type(folder: &ManifestFolder) -> Option<ZarrNodeType> {
+    let mut has_other = false;
+    for (name, child) in folder {
+        match child {
+            FolderEntry::Folder(f) if has_entry(f, "zarr.json") => {
+                return Some(ZarrNodeType::Group)
+            }
+            FolderEntry::Entry(_) if name.as_ref() == "zarr.json" => (),
+            _ => has_other = true,
+        }
+    }
+    has_other.then_some(ZarrNodeType::Array)
+}
+
+/// Returns true if `folder` contains a non-folder entry named `name`
+fn has_entry(folder: &ManifestFolder, name: &str) -> bool {
+    name.parse::<Component>()
+        .ok()
+        .and_then(|c| folder.get(&c))
+        .is_some_and(|child| matches!(child, FolderEntry::Entry(_)))
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, GetSize, PartialEq, Serialize)]
 #[serde(untagged)]
 pub(super) enum FolderEntry {
     Folder(ManifestFolder),
@@ -68,6 +187,23 @@ pub(super) struct ManifestEntry {
     pub(super) etag: String,
 }
 
+// Serialized as an array, the same as in manifest files, so that the result
+// can be deserialized again
+impl Serialize for ManifestEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let modified = self
+            .modified
+            .format(&time::format_description::well_known::Rfc3339)
+            .map_err(serde::ser::Error::custom)?;
+        let mut tup = serializer.serialize_tuple(4)?;
+        tup.serialize_element(&self.version_id)?;
+        tup.serialize_element(&modified)?;
+        tup.serialize_element(&self.size)?;
+        tup.serialize_element(&self.etag)?;
+        tup.end()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -75,6 +211,41 @@ mod tests {
     use indoc::indoc;
     use time::macros::datetime;
 
+    fn v3_folder(children: &[(&str, FolderEntry)]) -> ManifestFolder {
+        children
+            .iter()
+            .map(|(name, child)| (name.parse().unwrap(), child.clone()))
+            .collect()
+    }
+
+    fn v3_entry() -> FolderEntry {
+        FolderEntry::Entry(ManifestEntry {
+            version_id: "VwOSu7IVLAQcQHcqOesmlrEDm2sL_Tfs".into(),
+            modified: datetime!(2024-06-27 23:07:47 UTC),
+            size: 512,
+            etag: "cb32b88f6488d55818aba94746bcc19a".into(),
+        })
+    }
+
+    #[test]
+    fn test_v3_node_type() {
+        let array = v3_folder(&[
+            ("zarr.json", v3_entry()),
+            ("c", FolderEntry::Folder(v3_folder(&[("0", v3_entry())]))),
+        ]);
+        assert_eq!(node_type(&array), Some(ZarrNodeType::Array));
+        let group = v3_folder(&[
+            ("zarr.json", v3_entry()),
+            ("0", FolderEntry::Folder(array.clone())),
+            ("labels", FolderEntry::Folder(array)),
+        ]);
+        assert_eq!(node_type(&group), Some(ZarrNodeType::Group));
+        let bare = v3_folder(&[("zarr.json", v3_entry())]);
+        assert_eq!(node_type(&bare), None);
+        let plain = v3_folder(&[("0", v3_entry())]);
+        assert_eq!(node_type(&plain), None);
+    }
+
     #[test]
     fn test_manifest() {
         let s = indoc! {r#"
@@ -194,7 +365,12 @@ mod tests {
                             )
                         ]))
                     )
-                ])
+                ]),
+                statistics: Some(ManifestStatistics {
+                    entry_count: 509,
+                    total_size: 710206390,
+                    last_modified: Some(datetime!(2022-06-27 23:09:39 UTC)),
+                }),
             }
         );
 
@@ -228,5 +404,68 @@ mod tests {
                 assert_eq!(folder.keys().collect::<Vec<_>>(), ["100", "101"]);
             }
         );
+
+        assert_eq!(
+            manifest.find_by_etag("7B5AF4C6C28047C83DD86E4814BC0272"),
+            Some(("0/0/0/13/8/100".parse::<PurePath>().unwrap(), &entry_100))
+        );
+        assert_eq!(
+            manifest.find_by_etag("cb32b88f6488d55818aba94746bcc19a"),
+            Some((".zattrs".parse::<PurePath>().unwrap(), &zattrs))
+        );
+        assert_eq!(
+            manifest.find_by_etag("00000000000000000000000000000000"),
+            None
+        );
+
+        assert_eq!(node_type(&manifest.entries), Some(ZarrNodeType::Group));
+        assert_matches!(
+            manifest.get(&"0".parse::<PurePath>().unwrap()),
+            Some(EntryRef::Folder(folder)) => {
+                assert_eq!(node_type(folder), Some(ZarrNodeType::Array));
+            }
+        );
+        assert_matches!(
+            manifest.get(&"0/0".parse::<PurePath>().unwrap()),
+            Some(EntryRef::Folder(folder)) => {
+                assert_eq!(node_type(folder), None);
+            }
+        );
+
+        assert_eq!(
+            manifest.statistics(),
+            ManifestStatistics {
+                entry_count: 509,
+                total_size: 710206390,
+                last_modified: Some(datetime!(2022-06-27 23:09:39 UTC)),
+            }
+        );
+        let unsummarized = Manifest {
+            statistics: None,
+            ..manifest.clone()
+        };
+        assert_eq!(
+            unsummarized.statistics(),
+            ManifestStatistics {
+                entry_count: 6,
+                total_size: 8312 + 24 + 15191 + 446 + 1793451 + 1799564,
+                last_modified: Some(datetime!(2022-06-27 23:09:28 UTC)),
+            }
+        );
+
+        let reserialized = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(
+            reserialized["entries"][".zattrs"],
+            serde_json::json!([
+                "VwOSu7IVLAQcQHcqOesmlrEDm2sL_Tfs",
+                "2022-06-27T23:07:47Z",
+                8312,
+                "cb32b88f6488d55818aba94746bcc19a"
+            ])
+        );
+        assert_eq!(
+            serde_json::from_value::<Manifest>(reserialized).unwrap(),
+            manifest
+        );
     }
 }
@@ -0,0 +1,205 @@
+use super::consts::{MANIFEST_MIRROR_COOLDOWN, MANIFEST_ROOT_URL};
+use crate::httputil::{Client, HttpError, HttpUrl};
+use serde::de::DeserializeOwned;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+/// A list of equivalent manifest roots, tried in order of preference
+///
+/// When a request to a manifest root fails for a reason other than the
+/// resource not existing (e.g., due to throttling or an outage), the root is
+/// marked unhealthy for [`MANIFEST_MIRROR_COOLDOWN`] and the request is
+/// retried against the next root.  Unhealthy roots are only tried after all
+/// healthy roots have failed.
+#[derive(Debug)]
+pub(super) struct ManifestMirrors {
+    mirrors: Vec<Mirror>,
+}
+
+impl ManifestMirrors {
+    /// Construct a `ManifestMirrors` for the given manifest root URLs, in
+    /// order of preference.  If `roots` is empty, [`MANIFEST_ROOT_URL`] is
+    /// used.
+    pub(super) fn new(roots: Vec<HttpUrl>) -> ManifestMirrors {
+        let roots = if roots.is_empty() {
+            vec![MANIFEST_ROOT_URL
+                .parse::<HttpUrl>()
+                .expect("MANIFEST_ROOT_URL should be a valid HTTP URL")]
+        } else {
+            roots
+        };
+        ManifestMirrors {
+            mirrors: roots.into_iter().map(Mirror::new).collect(),
+        }
+    }
+
+    /// Return the most preferred manifest root that is currently healthy, or
+    /// the most preferred manifest root overall if none are healthy
+    pub(super) fn current_root(&self) -> &HttpUrl {
+        let now = Instant::now();
+        self.mirrors
+            .iter()
+            .find(|m| m.is_healthy(now))
+            .unwrap_or(&self.mirrors[0])
+            .root_url()
+    }
+
+    /// Use `client` to perform a `GET` request for the URL returned by
+    /// `mkurl` for each manifest root in turn, failing over to the next root
+    /// on any error other than a 404, and deserialize the first successful
+    /// response as JSON.
+    ///
+    /// If all manifest roots fail, the error from the last one is returned.
+    pub(super) async fn get_json<T, F>(&self, client: &Client, mkurl: F) -> Result<T, HttpError>
+    where
+        T: DeserializeOwned,
+        F: Fn(&HttpUrl) -> HttpUrl,
+    {
+        let now = Instant::now();
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) =
+            self.mirrors.iter().partition(|m| m.is_healthy(now));
+        let mut last_err = None;
+        for mirror in healthy.into_iter().chain(unhealthy) {
+            match client.get_json::<T>(mkurl(mirror.root_url())).await {
+                Ok(value) => {
+                    mirror.mark_healthy();
+                    return Ok(value);
+                }
+                Err(e @ (HttpError::NotFound { .. } | HttpError::Budget { .. })) => return Err(e),
+                Err(e) => {
+                    tracing::warn!(
+                        error = %e,
+                        manifest_root = %mirror.root_url(),
+                        "Request to manifest root failed; trying next mirror",
+                    );
+                    mirror.mark_unhealthy();
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.expect("there should be at least one manifest root"))
+    }
+}
+
+/// A single manifest root and its health status
+#[derive(Debug)]
+struct Mirror {
+    root_url: HttpUrl,
+
+    /// If the most recent request to this root failed, the time until which
+    /// the root should be considered unhealthy
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Mirror {
+    fn new(mut root_url: HttpUrl) -> Mirror {
+        root_url.ensure_dirpath();
+        Mirror {
+            root_url,
+            unhealthy_until: Mutex::new(None),
+        }
+    }
+
+    fn root_url(&self) -> &HttpUrl {
+        &self.root_url
+    }
+
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .map_or(true, |until| until <= now)
+    }
+
+    fn mark_healthy(&self) {
+        *self
+            .unhealthy_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    fn mark_unhealthy(&self) {
+        *self
+            .unhealthy_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) =
+            Some(Instant::now() + MANIFEST_MIRROR_COOLDOWN);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[tokio::test]
+    async fn fail_over_to_next_mirror() {
+        let throttled = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&throttled)
+            .await;
+        let mirror = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/root/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!(42)))
+            .mount(&mirror)
+            .await;
+        let mirrors = ManifestMirrors::new(vec![
+            format!("{}/root/", throttled.uri()).parse().unwrap(),
+            format!("{}/root/", mirror.uri()).parse().unwrap(),
+        ]);
+        let client = Client::new(None).unwrap();
+        let mkurl = |root: &HttpUrl| {
+            let mut url = root.clone();
+            url.push("foo.json");
+            url
+        };
+        assert_eq!(
+            mirrors.get_json::<u32, _>(&client, mkurl).await.unwrap(),
+            42
+        );
+        assert_eq!(
+            mirrors.current_root().as_str(),
+            format!("{}/root/", mirror.uri())
+        );
+        // The throttled mirror is not tried again while unhealthy:
+        assert_eq!(
+            mirrors.get_json::<u32, _>(&client, mkurl).await.unwrap(),
+            42
+        );
+        assert_eq!(throttled.received_requests().await.unwrap().len(), 1);
+        assert_eq!(mirror.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn not_found_is_not_failed_over() {
+        let primary = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&primary)
+            .await;
+        let mirror = MockServer::start().await;
+        let mirrors = ManifestMirrors::new(vec![
+            primary.uri().parse().unwrap(),
+            mirror.uri().parse().unwrap(),
+        ]);
+        let client = Client::new(None).unwrap();
+        let r = mirrors
+            .get_json::<u32, _>(&client, |root| {
+                let mut url = root.clone();
+                url.push("foo.json");
+                url
+            })
+            .await;
+        assert!(matches!(r, Err(HttpError::NotFound { .. })));
+        assert!(mirror.received_requests().await.unwrap().is_empty());
+        assert_eq!(
+            mirrors.current_root().as_str(),
+            format!("{}/", primary.uri())
+        );
+    }
+}
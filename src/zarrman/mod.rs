@@ -15,11 +15,12 @@
 mod consts;
 mod fetcher;
 mod manifest;
+mod mirrors;
 mod path;
 mod resources;
 mod util;
-use self::consts::ENTRY_DOWNLOAD_PREFIX;
-pub(crate) use self::fetcher::ManifestFetcher;
+use self::consts::{ENTRY_DOWNLOAD_PREFIX, ZARR_INFO_NAME};
+pub(crate) use self::fetcher::{ManifestFetcher, ManifestSnapshot};
 use self::path::ReqPath;
 pub(crate) use self::resources::*;
 pub(crate) use self::util::ZarrManError;
@@ -56,6 +57,13 @@ impl ZarrManClient {
         }
     }
 
+    /// Return the URL of the Zarr manifest for the Zarr with the given Zarr ID
+    /// & checksum.  Returns `None` if the values cannot be used to form a
+    /// manifest path.
+    pub(crate) fn manifest_url(&self, zarr_id: &str, checksum: &str) -> Option<HttpUrl> {
+        ManifestPath::for_zarr(zarr_id, checksum).map(|path| self.fetcher.manifest_url(&path))
+    }
+
     /// Retrieve the resources at the top level of `/zarrs/`, i.e., those
     /// matching the resources at the top level of the manifest tree
     pub(crate) async fn get_top_level_dirs(&self) -> Result<Vec<ZarrManResource>, ZarrManError> {
@@ -83,9 +91,9 @@ impl ZarrManClient {
                 }))
             }
             ReqPath::Manifest(path) => {
-                // Make a request to confirm that manifest exists
-                let _ = self.fetcher.fetch_manifest(&path).await?;
-                Ok(ZarrManResource::Manifest(Manifest { path }))
+                let man = self.fetcher.fetch_manifest(&path).await?;
+                let node_type = manifest::node_type(&man.entries);
+                Ok(ZarrManResource::Manifest(Manifest { path, node_type }))
             }
             ReqPath::InManifest {
                 manifest_path,
@@ -93,15 +101,21 @@ impl ZarrManClient {
             } => {
                 let man = self.fetcher.fetch_manifest(&manifest_path).await?;
                 match man.get(&entry_path) {
-                    Some(manifest::EntryRef::Folder(_)) => {
+                    Some(manifest::EntryRef::Folder(folref)) => {
                         let web_path = manifest_path
                             .to_web_path()
                             .join_dir(&entry_path.to_dir_path());
-                        Ok(ZarrManResource::ManFolder(ManifestFolder { web_path }))
+                        Ok(ZarrManResource::ManFolder(ManifestFolder {
+                            web_path,
+                            node_type: manifest::node_type(folref),
+                        }))
                     }
                     Some(manifest::EntryRef::Entry(entry)) => Ok(ZarrManResource::ManEntry(
                         self.convert_manifest_entry(&manifest_path, &entry_path, entry),
                     )),
+                    None if entry_path == ZARR_INFO_NAME => Ok(ZarrManResource::ZarrInfo(
+                        Self::zarr_info(&manifest_path, &man),
+                    )),
                     None => Err(ZarrManError::ManifestPathNotFound {
                         manifest_path,
                         entry_path,
@@ -134,8 +148,14 @@ impl ZarrManClient {
             }
             ReqPath::Manifest(path) => {
                 let man = self.fetcher.fetch_manifest(&path).await?;
-                let children = self.convert_manifest_folder_children(&path, None, &man.entries);
-                let folder = Manifest { path };
+                let mut children = self.convert_manifest_folder_children(&path, None, &man.entries);
+                if !man.entries.keys().any(|name| *name == ZARR_INFO_NAME) {
+                    children.push(ZarrManResource::ZarrInfo(Self::zarr_info(&path, &man)));
+                }
+                let folder = Manifest {
+                    path,
+                    node_type: manifest::node_type(&man.entries),
+                };
                 Ok(ZarrManResourceWithChildren::Manifest { folder, children })
             }
             ReqPath::InManifest {
@@ -153,7 +173,10 @@ impl ZarrManClient {
                             Some(&entry_path),
                             folref,
                         );
-                        let folder = ManifestFolder { web_path };
+                        let folder = ManifestFolder {
+                            web_path,
+                            node_type: manifest::node_type(folref),
+                        };
                         Ok(ZarrManResourceWithChildren::ManFolder { folder, children })
                     }
                     Some(manifest::EntryRef::Entry(entry)) => {
@@ -161,6 +184,12 @@ impl ZarrManClient {
                             self.convert_manifest_entry(&manifest_path, &entry_path, entry),
                         ))
                     }
+                    None if entry_path == ZARR_INFO_NAME => {
+                        Ok(ZarrManResourceWithChildren::ZarrInfo(Self::zarr_info(
+                            &manifest_path,
+                            &man,
+                        )))
+                    }
                     None => Err(ZarrManError::ManifestPathNotFound {
                         manifest_path,
                         entry_path,
@@ -170,6 +199,23 @@ impl ZarrManClient {
         }
     }
 
+    /// Find a Zarr entry whose S3 object has the given digest (an MD5 or
+    /// ETag) in any of the currently-cached Zarr manifests
+    pub(crate) async fn get_entry_by_checksum(
+        &self,
+        digest: &str,
+    ) -> Result<ManifestEntry, ZarrManError> {
+        let etag = digest.trim_matches('"');
+        match self.fetcher.find_cached_by_etag(etag).await {
+            Some((manifest_path, entry_path, entry)) => {
+                Ok(self.convert_manifest_entry(&manifest_path, &entry_path, &entry))
+            }
+            None => Err(ZarrManError::ChecksumNotFound {
+                digest: digest.to_owned(),
+            }),
+        }
+    }
+
     /// Retrieve the resources in the given directory of the manifest tree.
     ///
     /// `path` must be relative to the manifest root.  A `path` of `None`
@@ -198,6 +244,7 @@ impl ZarrManClient {
                                 zarr_id: path.name(),
                                 checksum,
                             },
+                            node_type: None,
                         }));
                     }
                     // else: Ignore
@@ -237,10 +284,29 @@ impl ZarrManClient {
             size: entry.size,
             modified: entry.modified,
             etag: entry.etag.clone(),
+            version_id: entry.version_id.clone(),
             url,
         }
     }
 
+    /// Construct the virtual `.zarr-info` file for the Zarr whose manifest
+    /// `man` is at `manifest_path`
+    fn zarr_info(manifest_path: &ManifestPath, man: &manifest::Manifest) -> ZarrInfo {
+        let stats = man.statistics();
+        ZarrInfo {
+            web_path: manifest_path.to_web_path().join(
+                &ZARR_INFO_NAME
+                    .parse::<PurePath>()
+                    .expect("ZARR_INFO_NAME should be a valid path"),
+            ),
+            zarr_id: manifest_path.zarr_id.to_string(),
+            checksum: manifest_path.checksum.to_string(),
+            entry_count: stats.entry_count,
+            size: stats.total_size,
+            last_modified: stats.last_modified,
+        }
+    }
+
     /// Convert the entries in `folder` (a folder at path `folder_path` in the
     /// manifest at `manifest_path`) to [`ZarrManResource`]s
     fn convert_manifest_folder_children(
@@ -256,9 +322,10 @@ impl ZarrManClient {
         };
         for (name, child) in folder {
             match child {
-                manifest::FolderEntry::Folder(_) => {
+                manifest::FolderEntry::Folder(f) => {
                     children.push(ZarrManResource::ManFolder(ManifestFolder {
                         web_path: web_path_prefix.join_one_dir(name),
+                        node_type: manifest::node_type(f),
                     }));
                 }
                 manifest::FolderEntry::Entry(entry) => {
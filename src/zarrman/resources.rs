@@ -1,8 +1,14 @@
 use crate::httputil::HttpUrl;
 use crate::paths::{Component, PureDirPath, PurePath};
+use crate::zarrchecksum::ZarrChecksumStats;
+use serde::Serialize;
 use std::fmt;
 use time::OffsetDateTime;
 
+/// The number of leading characters of a Zarr ID to display for its folder in
+/// the `/zarrs/` hierarchy
+const ZARR_ID_DISPLAY_LEN: usize = 8;
+
 /// A resource served under `dandidav`'s `/zarrs/` hierarchy, not including
 /// information on child resources
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -11,6 +17,7 @@ pub(crate) enum ZarrManResource {
     Manifest(Manifest),
     ManFolder(ManifestFolder),
     ManEntry(ManifestEntry),
+    ZarrInfo(ZarrInfo),
 }
 
 /// A collection between the root of the `/zarrs/` hierarchy and the Zarr
@@ -26,10 +33,28 @@ pub(crate) struct WebFolder {
     pub(crate) web_path: PureDirPath,
 }
 
+impl WebFolder {
+    /// If this is a `zarrs/{prefix1}/{prefix2}/{zarr_id}/` folder, return a
+    /// shortened form of the Zarr ID for display in place of the full ID
+    pub(crate) fn display_name(&self) -> Option<String> {
+        let mut components = self.web_path.component_strs();
+        let zarr_id = components.nth(3)?;
+        if components.next().is_some() {
+            return None;
+        }
+        let short = zarr_id.get(..ZARR_ID_DISPLAY_LEN)?;
+        (short.len() < zarr_id.len()).then(|| format!("{short}\u{2026}"))
+    }
+}
+
 /// A Zarr manifest, served as a virtual collection of the Zarr's entries
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct Manifest {
     pub(crate) path: ManifestPath,
+
+    /// Whether the root of the Zarr is a group or an array, if known.  This
+    /// is only determined when the manifest has been fetched.
+    pub(crate) node_type: Option<ZarrNodeType>,
 }
 
 /// A path to a Zarr manifest in the manifest tree or a Zarr collection in the
@@ -48,11 +73,31 @@ pub(crate) struct ManifestPath {
 }
 
 impl ManifestPath {
+    /// Construct the path to the manifest for the Zarr with the given Zarr ID
+    /// & checksum.  Returns `None` if either value is not suitable for use in
+    /// a path.
+    pub(crate) fn for_zarr(zarr_id: &str, checksum: &str) -> Option<ManifestPath> {
+        let prefix = format!("{}/{}/", zarr_id.get(..3)?, zarr_id.get(3..6)?)
+            .parse::<PureDirPath>()
+            .ok()?;
+        Some(ManifestPath {
+            prefix,
+            zarr_id: zarr_id.parse().ok()?,
+            checksum: checksum.parse().ok()?,
+        })
+    }
+
     /// Returns the Zarr ID
     pub(super) fn zarr_id(&self) -> &str {
         self.zarr_id.as_ref()
     }
 
+    /// Returns the entry count & total size of the Zarr as encoded in its
+    /// checksum, if the checksum is in the expected format
+    pub(crate) fn checksum_stats(&self) -> Option<ZarrChecksumStats> {
+        ZarrChecksumStats::parse(&self.checksum)
+    }
+
     /// Returns the path to the Zarr as served by `dandidav`, in the form
     /// `zarrs/{prefix1}/{prefix2}/{zarr_id}/{checksum}.zarr/`.
     pub(crate) fn to_web_path(&self) -> PureDirPath {
@@ -102,12 +147,40 @@ pub(crate) enum ZarrManResourceWithChildren {
         children: Vec<ZarrManResource>,
     },
     ManEntry(ManifestEntry),
+    ZarrInfo(ZarrInfo),
 }
 
 /// A folder within a Zarr
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct ManifestFolder {
     pub(crate) web_path: PureDirPath,
+
+    /// Whether the folder is a Zarr group or array, if either
+    pub(crate) node_type: Option<ZarrNodeType>,
+}
+
+/// The kind of Zarr hierarchy node that a folder within a Zarr is, as
+/// indicated by the Zarr metadata file (`.zgroup`, `.zarray`, or `zarr.json`)
+/// it contains
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) enum ZarrNodeType {
+    Group,
+    Array,
+}
+
+impl ZarrNodeType {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            ZarrNodeType::Group => "group",
+            ZarrNodeType::Array => "array",
+        }
+    }
+}
+
+impl fmt::Display for ZarrNodeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// An entry within a Zarr
@@ -126,13 +199,45 @@ pub(crate) struct ManifestEntry {
     /// The ETag of the entry's S3 object
     pub(crate) etag: String,
 
-    /// The download URL for the entry
+    /// The S3 version ID of the entry's object as recorded in the manifest
+    pub(crate) version_id: String,
+
+    /// The download URL for the entry, pinned to the object version given by
+    /// `version_id`
     pub(crate) url: HttpUrl,
 }
 
+/// The virtual `.zarr-info` file at the root of each Zarr, a JSON document
+/// summarizing the Zarr's contents
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct ZarrInfo {
+    /// The path to the file as served by `dandidav`, i.e., a path of the form
+    /// `zarrs/{p1}/{p2}/{zarr_id}/{checksum}.zarr/.zarr-info`
+    #[serde(skip)]
+    pub(crate) web_path: PurePath,
+
+    /// The Zarr ID
+    pub(crate) zarr_id: String,
+
+    /// The Zarr's checksum
+    pub(crate) checksum: String,
+
+    /// The number of entries in the Zarr
+    pub(crate) entry_count: u64,
+
+    /// The total size in bytes of the Zarr's entries
+    pub(crate) size: i64,
+
+    /// The latest modification time of any entry in the Zarr, or `None` if
+    /// the Zarr is empty
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub(crate) last_modified: Option<OffsetDateTime>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rstest::rstest;
 
     #[test]
     fn manifest_path_to_urls() {
@@ -146,4 +251,37 @@ mod tests {
         assert_eq!(mp.to_web_path(), "zarrs/128/4a1/1284a14f-fe4f-4dc3-b10d-48e5db8bf18d/6ddc4625befef8d6f9796835648162be-509--710206390.zarr/");
         assert_eq!(mp.under_manifest_root(&"https://datasets.datalad.org/dandi/zarr-manifests/zarr-manifests-v2-sorted/".parse().unwrap()).as_str(), "https://datasets.datalad.org/dandi/zarr-manifests/zarr-manifests-v2-sorted/128/4a1/1284a14f-fe4f-4dc3-b10d-48e5db8bf18d/6ddc4625befef8d6f9796835648162be-509--710206390.json");
     }
+
+    #[test]
+    fn manifest_path_for_zarr() {
+        let mp = ManifestPath::for_zarr(
+            "1284a14f-fe4f-4dc3-b10d-48e5db8bf18d",
+            "6ddc4625befef8d6f9796835648162be-509--710206390",
+        )
+        .unwrap();
+        assert_eq!(mp.to_string(), "128/4a1/1284a14f-fe4f-4dc3-b10d-48e5db8bf18d/6ddc4625befef8d6f9796835648162be-509--710206390.json");
+    }
+
+    #[rstest]
+    #[case("zarrs/128/", None)]
+    #[case("zarrs/128/4a1/", None)]
+    #[case(
+        "zarrs/128/4a1/1284a14f-fe4f-4dc3-b10d-48e5db8bf18d/",
+        Some("1284a14f\u{2026}")
+    )]
+    #[case("zarrs/128/4a1/1284a14f/", None)]
+    fn web_folder_display_name(#[case] web_path: &str, #[case] display_name: Option<&str>) {
+        let folder = WebFolder {
+            web_path: web_path.parse().unwrap(),
+        };
+        assert_eq!(folder.display_name().as_deref(), display_name);
+    }
+
+    #[test]
+    fn manifest_path_for_short_zarr_id() {
+        assert_eq!(
+            ManifestPath::for_zarr("1284", "6ddc4625befef8d6f9796835648162be-509--710206390"),
+            None
+        );
+    }
 }
@@ -21,6 +21,10 @@ pub(crate) enum ZarrManError {
         manifest_path: ManifestPath,
         entry_path: PurePath,
     },
+
+    /// No cached manifest contains an entry with the requested checksum
+    #[error("no entry with checksum {digest:?} found in cached manifests")]
+    ChecksumNotFound { digest: String },
 }
 
 impl ZarrManError {
@@ -28,9 +32,9 @@ impl ZarrManError {
     pub(crate) fn class(&self) -> ErrorClass {
         match self {
             ZarrManError::Http(source) => source.class(),
-            ZarrManError::InvalidPath { .. } | ZarrManError::ManifestPathNotFound { .. } => {
-                ErrorClass::NotFound
-            }
+            ZarrManError::InvalidPath { .. }
+            | ZarrManError::ManifestPathNotFound { .. }
+            | ZarrManError::ChecksumNotFound { .. } => ErrorClass::NotFound,
         }
     }
 }